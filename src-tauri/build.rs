@@ -1,4 +1,17 @@
 fn main() {
+    // 注入 git 提交短哈希 (源码包等无 git 环境时跳过)
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs");
+    if let Ok(output) = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+    {
+        let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() && !hash.is_empty() {
+            println!("cargo:rustc-env=AG_GIT_HASH={}", hash);
+        }
+    }
+
     #[cfg(feature = "ui")]
     tauri_build::build();
 }
@@ -1,6 +1,7 @@
 use clap::{Parser, Subcommand};
 use antigravity_tools_lib::{
-    modules::{account, config},
+    modules::{account, bench, build_info, config, config_diff, probe, proxy_db},
+    proxy::{admin_token, common::{model_mapping, schema_lint::lint_json_schema}},
     services::proxy::ProxyService,
 };
 
@@ -28,6 +29,57 @@ enum Commands {
         #[command(subcommand)]
         action: ConfigCommands,
     },
+    /// Print what every built-in model, custom mapping key and sample family input resolves to
+    Routes {
+        /// Apply Claude family mapping (as for CLI clients)
+        #[arg(long)]
+        apply_claude_family: bool,
+    },
+    /// Fire test requests at the running proxy and report throughput, latency and error rate
+    Bench {
+        /// Number of requests in flight at once
+        #[arg(long, default_value_t = 4)]
+        concurrency: usize,
+        /// Total number of requests to send
+        #[arg(long, default_value_t = 20)]
+        requests: usize,
+        /// Model to request
+        #[arg(long, default_value = "gemini-3-flash")]
+        model: String,
+    },
+    /// Check that this host can reach the upstream Gemini endpoint (no account needed) and measure latency
+    Probe {
+        /// Endpoint to probe (defaults to the primary upstream endpoint)
+        #[arg(long)]
+        url: Option<String>,
+        /// Timeout in seconds
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+    },
+    /// Check a tool JSON schema against Gemini rules and show what cleaning changes
+    LintSchema {
+        /// Path to the JSON schema file
+        file: std::path::PathBuf,
+        /// Output the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Compare two config files field by field (mappings, strategies, flags)
+    ConfigDiff {
+        /// Path to the first (old) config file
+        a: std::path::PathBuf,
+        /// Path to the second (new) config file
+        b: std::path::PathBuf,
+        /// Output the diff as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show build version, git commit and compiled feature flags
+    Version {
+        /// Also show key runtime toggles from the effective configuration
+        #[arg(long)]
+        verbose: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -40,6 +92,17 @@ enum ServerCommands {
     },
     /// Stop the proxy server (if running via background service - note: CLI usually runs foreground)
     Stop,
+    /// Show estimated spend (total and per model) from the request log
+    Cost,
+    /// Print retained request logs (newest first)
+    Logs {
+        /// Output format: json or csv
+        #[arg(long, default_value = "json")]
+        format: String,
+        /// Maximum number of log entries
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+    },
 }
 
 #[derive(Subcommand)]
@@ -54,13 +117,58 @@ enum AccountCommands {
     /// Delete an account
     Delete {
         id: String,
-    }
+    },
+    /// Stop routing new requests to an account and wait for its in-flight requests (run before `delete`)
+    Drain {
+        /// Account ID or partial email
+        id: String,
+        /// Seconds to wait for in-flight requests to finish
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+    },
+    /// Set scheduling priority (higher is preferred by the proxy)
+    Priority {
+        /// Account ID or partial email
+        id: String,
+        priority: i32,
+    },
+    /// Mark an account as canary (receives only a small share of traffic until promoted)
+    Canary {
+        /// Account ID or partial email
+        id: String,
+        /// Clear the canary flag and route full traffic to the account
+        #[arg(long)]
+        promote: bool,
+    },
+    /// Set per-account concurrency / RPM limits (overrides the global defaults, 0 = unlimited)
+    Limits {
+        /// Account ID or partial email
+        id: String,
+        /// Max in-flight requests for this account
+        #[arg(long)]
+        concurrency: Option<u32>,
+        /// Max requests per minute for this account
+        #[arg(long)]
+        rpm: Option<u32>,
+        /// Max requests per day for this account (resets at the scheduling `daily_reset_time`)
+        #[arg(long)]
+        daily: Option<u64>,
+    },
 }
 
 #[derive(Subcommand)]
 enum ConfigCommands {
     /// Show current configuration
-    Show,
+    Show {
+        /// Show the effective configuration (file + migrations + env overrides)
+        #[arg(long)]
+        effective: bool,
+        /// Output format for machine consumption
+        #[arg(long, value_parser = ["json", "toml"])]
+        format: Option<String>,
+    },
+    /// Validate configuration (errors are fatal, warnings are advisory)
+    Validate,
 }
 
 #[tokio::main]
@@ -68,6 +176,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize logging (simple stdout for CLI for now, or reuse modules::logger if adapted)
     antigravity_tools_lib::modules::logger::init_logger(); 
     // tracing_subscriber::fmt::init(); // Use simple stdout
+    antigravity_tools_lib::modules::audit::set_default_actor(match std::env::var("USER") {
+        Ok(user) if !user.is_empty() => format!("cli:{}", user),
+        _ => "cli".to_string(),
+    });
 
     let cli = Cli::parse();
 
@@ -75,7 +187,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::Server { action } => match action {
             ServerCommands::Start { port } => {
                 println!("Starting server...");
-                let mut app_config = config::load_app_config()?;
+                let mut app_config = config::load_effective_app_config()?;
                 
                 if let Some(p) = port {
                     app_config.proxy.port = p;
@@ -95,14 +207,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             ServerCommands::Stop => {
                 println!("If the server is running as a daemon, use system tools to stop it. CLI 'start' runs in foreground.");
             }
+            ServerCommands::Cost => {
+                proxy_db::init_db()?;
+                let stats = proxy_db::get_stats()?;
+                print!("{}", stats.render_cost_report());
+            }
+            ServerCommands::Logs { format, limit } => {
+                proxy_db::init_db()?;
+                let logs = proxy_db::get_logs(limit)?;
+                match format.as_str() {
+                    "json" => println!("{}", serde_json::to_string_pretty(&logs)?),
+                    "csv" => print!("{}", antigravity_tools_lib::proxy::monitor::render_logs_csv(&logs)),
+                    other => return Err(format!("Unsupported format: {} (expected json or csv)", other).into()),
+                }
+            }
         },
         Commands::Account { action } => match action {
             AccountCommands::List => {
                 let accounts = account::list_accounts()?;
                 let current_id = account::get_current_account_id()?;
                 
-                println!("{:<40} {:<30} {:<10} {:<10}", "ID", "Email", "Tier", "Active");
-                println!("{}", "-".repeat(95));
+                println!("{:<40} {:<30} {:<10} {:<8} {:<20} {:<10}", "ID", "Email", "Tier", "Canary", "Protocols", "Active");
+                println!("{}", "-".repeat(125));
                 
                 for account in accounts {
                     let active = if Some(&account.id) == current_id.as_ref() { "*" } else { "" };
@@ -111,10 +237,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .map(|s| s.as_str())
                         .unwrap_or("Free");
                         
-                    println!("{:<40} {:<30} {:<10} {:<10}", 
+                    let canary = if account.canary { "yes" } else { "" };
+                    let protocols = if account.supported_protocols.is_empty() {
+                        "all".to_string()
+                    } else {
+                        account.supported_protocols.iter().map(|p| p.as_str()).collect::<Vec<_>>().join(",")
+                    };
+
+                    println!("{:<40} {:<30} {:<10} {:<8} {:<20} {:<10}", 
                         account.id, 
                         account.email, 
                         tier,
+                        canary,
+                        protocols,
                         active
                     );
                 }
@@ -135,11 +270,196 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 account::delete_account(&id)?;
                 println!("Deleted account {}", id);
             }
+            AccountCommands::Drain { id, timeout } => {
+                let accounts = account::list_accounts()?;
+                let target = accounts.iter().find(|a| a.id == id || a.email.contains(&id));
+
+                if let Some(acc) = target {
+                    // 通过运行中的代理服务排空 (服务未运行时不存在进行中的请求)
+                    // 管理令牌由服务启动时写入数据目录，API Key 本身不足以执行排空
+                    let config = config::load_effective_app_config()?;
+                    let url = format!(
+                        "http://127.0.0.1:{}/internal/accounts/{}/drain?timeout_secs={}",
+                        config.proxy.port, acc.id, timeout
+                    );
+                    let client = reqwest::Client::builder()
+                        .no_proxy()
+                        .timeout(std::time::Duration::from_secs(timeout + 10))
+                        .build()?;
+                    let mut request = client.post(&url).bearer_auth(&config.proxy.api_key);
+                    if let Ok(token) = admin_token::read(&account::get_data_dir()?) {
+                        request = request.header(admin_token::ADMIN_TOKEN_HEADER, token);
+                    }
+
+                    match request.send().await {
+                        Err(e) if e.is_connect() => {
+                            println!("Proxy is not running; {} has no in-flight requests", acc.email);
+                        }
+                        Err(e) => return Err(e.into()),
+                        Ok(resp) => {
+                            let status = resp.status();
+                            let body: serde_json::Value = resp.json().await.unwrap_or_default();
+                            match status.as_u16() {
+                                200 => println!("Drained {}; it can now be deleted safely", acc.email),
+                                403 => {
+                                    return Err("Admin token rejected; run the CLI as the user that started the proxy"
+                                        .into())
+                                }
+                                404 => println!("{} is not loaded by the running proxy; nothing to drain", acc.email),
+                                504 => {
+                                    return Err(format!(
+                                        "Timed out draining {}: {} request(s) still in flight; the account is back in rotation",
+                                        acc.email, body["in_flight"]
+                                    )
+                                    .into())
+                                }
+                                _ => return Err(format!("Drain failed: HTTP {}", status).into()),
+                            }
+                        }
+                    }
+                } else {
+                    println!("Account not found");
+                }
+            }
+            AccountCommands::Priority { id, priority } => {
+                let accounts = account::list_accounts()?;
+                let target = accounts.iter().find(|a| a.id == id || a.email.contains(&id));
+
+                if let Some(acc) = target {
+                    account::set_account_priority(&acc.id, priority)?;
+                    println!("Set priority of {} to {}", acc.email, priority);
+                } else {
+                    println!("Account not found");
+                }
+            }
+            AccountCommands::Canary { id, promote } => {
+                let accounts = account::list_accounts()?;
+                let target = accounts.iter().find(|a| a.id == id || a.email.contains(&id));
+
+                if let Some(acc) = target {
+                    account::set_account_canary(&acc.id, !promote)?;
+                    if promote {
+                        println!("Promoted {} out of canary", acc.email);
+                    } else {
+                        println!("Marked {} as canary", acc.email);
+                    }
+                } else {
+                    println!("Account not found");
+                }
+            }
+            AccountCommands::Limits { id, concurrency, rpm, daily } => {
+                let accounts = account::list_accounts()?;
+                let target = accounts.iter().find(|a| a.id == id || a.email.contains(&id));
+
+                if let Some(acc) = target {
+                    let updated = account::set_account_limits(
+                        &acc.id,
+                        concurrency.or(acc.max_concurrency),
+                        rpm.or(acc.max_rpm),
+                        daily.or(acc.daily_request_cap),
+                    )?;
+                    let fmt = |v: Option<u64>| v.map(|n| n.to_string()).unwrap_or_else(|| "default".to_string());
+                    println!(
+                        "Set limits of {}: concurrency={}, rpm={}, daily={}",
+                        acc.email,
+                        fmt(updated.max_concurrency.map(u64::from)),
+                        fmt(updated.max_rpm.map(u64::from)),
+                        fmt(updated.daily_request_cap)
+                    );
+                } else {
+                    println!("Account not found");
+                }
+            }
         },
         Commands::Config { action } => match action {
-            ConfigCommands::Show => {
-                let config = config::load_app_config()?;
-                println!("{:#?}", config);
+            ConfigCommands::Show { effective, format } => {
+                let config = if effective {
+                    config::load_effective_app_config()?
+                } else {
+                    config::load_app_config()?
+                };
+                match format {
+                    Some(fmt) => println!("{}", config::render_app_config(&config, &fmt)?),
+                    None => println!("{:#?}", config),
+                }
+            }
+            ConfigCommands::Validate => {
+                let config = config::load_effective_app_config()?;
+                let warnings = config.proxy.advisory_warnings();
+                for warning in &warnings {
+                    println!("warning: {}", warning);
+                }
+                println!("Configuration is valid ({} warning(s))", warnings.len());
+            }
+        },
+        Commands::Routes { apply_claude_family } => {
+            let config = config::load_effective_app_config()?;
+            let rows = model_mapping::audit_routes(&config.proxy, apply_claude_family);
+            print!("{}", model_mapping::render_route_table(&rows));
+        }
+        Commands::Bench { concurrency, requests, model } => {
+            let config = config::load_effective_app_config()?;
+            let options = bench::BenchOptions {
+                base_url: format!("http://127.0.0.1:{}", config.proxy.port),
+                api_key: config.proxy.api_key.clone(),
+                model,
+                concurrency,
+                requests,
+            };
+            println!(
+                "Sending {} request(s) to {} with concurrency {}...",
+                options.requests, options.base_url, options.concurrency
+            );
+            let report = bench::run_bench(&options).await?;
+            print!("{}", report.render_text());
+        }
+        Commands::Probe { url, timeout } => {
+            let config = config::load_effective_app_config()?;
+            let options = probe::ProbeOptions {
+                url: url.unwrap_or_else(|| {
+                    antigravity_tools_lib::proxy::upstream::client::primary_base_url().to_string()
+                }),
+                upstream_proxy: config.proxy.upstream_proxy.clone(),
+                timeout: std::time::Duration::from_secs(timeout),
+            };
+            let report = probe::run_probe(&options).await?;
+            print!("{}", report.render_text());
+            if !report.reachable {
+                return Err("Upstream endpoint is unreachable".into());
+            }
+        }
+        Commands::LintSchema { file, json } => {
+            let content = std::fs::read_to_string(&file)?;
+            let schema: serde_json::Value = serde_json::from_str(&content)?;
+            let report = lint_json_schema(&schema);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print!("{}", report.render_text());
+            }
+        }
+        Commands::ConfigDiff { a, b, json } => {
+            let diff = config_diff::diff_config_files(&a, &b)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else {
+                print!("{}", diff.render_text());
+            }
+        }
+        Commands::Version { verbose } => {
+            let info = build_info::build_info();
+            println!("Version:  {}", info.version);
+            println!("Git hash: {}", info.git_hash.unwrap_or("unknown"));
+            println!(
+                "Features: {}",
+                if info.features.is_empty() { "(none)".to_string() } else { info.features.join(", ") }
+            );
+            if verbose {
+                let app_config = config::load_effective_app_config()?;
+                println!(
+                    "Runtime:\n{}",
+                    serde_json::to_string_pretty(&build_info::runtime_toggles(&app_config.proxy))?
+                );
             }
         }
     }
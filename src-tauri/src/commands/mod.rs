@@ -315,27 +315,14 @@ pub async fn save_config(
     proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
     config: AppConfig,
 ) -> Result<(), String> {
+    config.proxy.zai.validate()?;
     modules::save_app_config(&config)?;
 
     // 通知托盘配置已更新
     let _ = app.emit("config://updated", ());
 
     // 热更新正在运行的服务
-    let instance_lock = proxy_state.instance.read().await;
-    if let Some(instance) = instance_lock.as_ref() {
-        // 更新模型映射
-        instance.axum_server.update_mapping(&config.proxy).await;
-        // 更新上游代理
-        instance
-            .axum_server
-            .update_proxy(config.proxy.upstream_proxy.clone())
-            .await;
-        // 更新安全策略 (auth)
-        instance.axum_server.update_security(&config.proxy).await;
-        // 更新 z.ai 配置
-        instance.axum_server.update_zai(&config.proxy).await;
-        tracing::debug!("已同步热更新反代服务配置");
-    }
+    proxy_state.reload(&config.proxy).await?;
 
     Ok(())
 }
@@ -552,7 +539,7 @@ pub async fn sync_account_from_db(app: tauri::AppHandle) -> Result<Option<Accoun
 
     // 3. 对比：如果 Refresh Token 相同，说明账号没变，无需导入
     if let Some(acc) = curr_account {
-        if acc.token.refresh_token == db_refresh_token {
+        if acc.token.as_ref().is_some_and(|t| t.refresh_token == db_refresh_token) {
             // 账号未变，由于已经是周期性任务，我们可以选择性刷新一下配额，或者直接返回
             // 这里为了节省 API 流量，直接返回
             return Ok(None);
@@ -749,6 +736,12 @@ pub async fn toggle_proxy_status(
         if enable { "已启用" } else { "已禁用" }
     ));
 
+    modules::audit::record(
+        None,
+        if enable { "account.proxy_enable" } else { "account.proxy_disable" },
+        serde_json::json!({ "account_id": account_id, "reason": account_json["proxy_disabled_reason"] }),
+    );
+
     // 4. 如果反代服务正在运行,重新加载账号池
     let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
 
@@ -1,12 +1,15 @@
 use tauri::State;
 use crate::proxy::ProxyConfig;
 use crate::proxy::monitor::{ProxyRequestLog, ProxyStats};
-use crate::services::proxy::{ProxyService, ProxyStatus};
+use crate::services::proxy::{DashboardSnapshot, DrainResult, ProxyService, ProxyStatus};
 use tokio::time::Duration;
 use serde_json::Value;
 
 pub type ProxyServiceState = ProxyService;
 
+/// 排空账号的默认最长等待时间 (秒)
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 60;
+
 #[tauri::command]
 pub async fn start_proxy_service(
     config: ProxyConfig,
@@ -37,6 +40,13 @@ pub async fn get_proxy_stats(
     Ok(state.get_stats().await)
 }
 
+#[tauri::command]
+pub async fn get_proxy_dashboard(
+    state: State<'_, ProxyServiceState>,
+) -> Result<DashboardSnapshot, String> {
+    Ok(state.dashboard_snapshot().await)
+}
+
 #[tauri::command]
 pub async fn get_proxy_logs(
     state: State<'_, ProxyServiceState>,
@@ -114,13 +124,17 @@ pub async fn update_model_mapping(
     let instance_lock = state.instance.read().await;
     if let Some(instance) = instance_lock.as_ref() {
         instance.axum_server.update_mapping(&config).await;
+        instance.axum_server.update_model_strategies(&config).await;
     }
     
     let mut app_config = crate::modules::config::load_app_config().map_err(|e| e)?;
     app_config.proxy.anthropic_mapping = config.anthropic_mapping;
     app_config.proxy.openai_mapping = config.openai_mapping;
+    app_config.proxy.openai_family_rules = config.openai_family_rules;
     app_config.proxy.custom_mapping = config.custom_mapping;
     app_config.proxy.model_strategies = config.model_strategies;
+    app_config.proxy.strategies_dir = config.strategies_dir;
+    app_config.proxy.model_output_limits = config.model_output_limits;
     crate::modules::config::save_app_config(&app_config).map_err(|e| e)?;
     
     Ok(())
@@ -165,6 +179,26 @@ pub async fn clear_proxy_session_bindings(
     }
 }
 
+/// 排空账号 (删除前调用)，超时后账号恢复调度
+#[tauri::command]
+pub async fn drain_proxy_account(
+    account_id: String,
+    timeout_secs: Option<u64>,
+    state: State<'_, ProxyServiceState>,
+) -> Result<DrainResult, String> {
+    let timeout = Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS));
+    state.drain_account(&account_id, timeout).await
+}
+
+/// 撤销排空，账号重新参与调度
+#[tauri::command]
+pub async fn undrain_proxy_account(
+    account_id: String,
+    state: State<'_, ProxyServiceState>,
+) -> Result<bool, String> {
+    state.undrain_account(&account_id).await
+}
+
 // Helpers for z.ai
 fn join_base_url(base: &str, path: &str) -> String {
     let base = base.trim_end_matches('/');
@@ -25,6 +25,7 @@ fn greet(name: &str) -> String {
 pub fn run() {
     // 初始化日志
     logger::init_logger();
+    modules::audit::set_default_actor("ui");
     
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
@@ -140,6 +141,7 @@ pub fn run() {
             commands::proxy::stop_proxy_service,
             commands::proxy::get_proxy_status,
             commands::proxy::get_proxy_stats,
+            commands::proxy::get_proxy_dashboard,
             commands::proxy::get_proxy_logs,
             commands::proxy::get_proxy_logs_paginated,
             commands::proxy::get_proxy_log_detail,
@@ -152,6 +154,8 @@ pub fn run() {
             commands::proxy::get_proxy_scheduling_config,
             commands::proxy::update_proxy_scheduling_config,
             commands::proxy::clear_proxy_session_bindings,
+            commands::proxy::drain_proxy_account,
+            commands::proxy::undrain_proxy_account,
             // Autostart 命令
             commands::autostart::toggle_auto_launch,
             commands::autostart::is_auto_launch_enabled,
@@ -7,7 +7,12 @@ pub struct Account {
     pub id: String,
     pub email: String,
     pub name: Option<String>,
-    pub token: TokenData,
+    /// OAuth token; only refresh_token accounts require it (API key / service account accounts omit it).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<TokenData>,
+    /// Upstream credential type; defaults to the OAuth refresh_token flow backed by `token`.
+    #[serde(default, skip_serializing_if = "Credential::is_refresh_token")]
+    pub credential: Credential,
     /// 可选的设备指纹，用于切换账号时固定机器信息
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub device_profile: Option<DeviceProfile>,
@@ -33,10 +38,130 @@ pub struct Account {
     /// Unix timestamp when the proxy was disabled.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub proxy_disabled_at: Option<i64>,
+    /// Manual scheduling preference; higher-priority healthy accounts are used first.
+    #[serde(default)]
+    pub priority: i32,
+    /// Per-account upstream proxy URL; overrides the global `upstream_proxy` when this account is selected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_proxy: Option<String>,
+    /// Per-account max in-flight requests; overrides the global scheduling default (0 = unlimited).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<u32>,
+    /// Per-account requests-per-minute cap; overrides the global scheduling default (0 = unlimited).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rpm: Option<u32>,
+    /// Per-account requests-per-day cap; overrides the global scheduling default (0 = unlimited).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_request_cap: Option<u64>,
+    /// Canary accounts only receive a small share of proxy traffic until promoted after enough successful requests.
+    #[serde(default)]
+    pub canary: bool,
+    /// Client protocols this account may serve; empty means all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub supported_protocols: Vec<AccountProtocol>,
     pub created_at: i64,
     pub last_used: i64,
 }
 
+/// 客户端协议 (按请求路径区分)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountProtocol {
+    Openai,
+    Anthropic,
+    Gemini,
+}
+
+impl AccountProtocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Openai => "openai",
+            Self::Anthropic => "anthropic",
+            Self::Gemini => "gemini",
+        }
+    }
+}
+
+/// 账号凭据类型
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Credential {
+    /// OAuth refresh_token (默认)，访问令牌过期后通过 Google OAuth 刷新
+    #[default]
+    RefreshToken,
+    /// Gemini API Key，出站使用 `x-goog-api-key` 头，无需刷新
+    ApiKey {
+        api_key: String,
+        /// API Key 无法通过 loadCodeAssist 解析项目，须显式配置
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        project_id: Option<String>,
+    },
+    /// GCP 服务账号，访问令牌从元数据服务获取 (GCE/GKE/Cloud Run 等环境)
+    ServiceAccount {
+        /// 服务账号邮箱，缺省为实例默认服务账号
+        #[serde(default = "default_service_account")]
+        service_account: String,
+        /// 元数据服务地址覆盖 (缺省为 http://metadata.google.internal)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        metadata_url: Option<String>,
+        /// 缺省时通过 loadCodeAssist 解析
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        project_id: Option<String>,
+    },
+}
+
+/// 出站请求的鉴权凭据 (由账号凭据解析得到)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpstreamAuth {
+    /// `Authorization: Bearer <access_token>` (OAuth / 服务账号)
+    Bearer(String),
+    /// `x-goog-api-key: <api_key>`
+    ApiKey(String),
+}
+
+impl UpstreamAuth {
+    /// 出站鉴权头
+    pub fn header(&self) -> Result<(reqwest::header::HeaderName, reqwest::header::HeaderValue), String> {
+        use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION};
+        match self {
+            UpstreamAuth::Bearer(token) => Ok((
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|e| e.to_string())?,
+            )),
+            UpstreamAuth::ApiKey(api_key) => Ok((
+                HeaderName::from_static("x-goog-api-key"),
+                HeaderValue::from_str(api_key).map_err(|e| e.to_string())?,
+            )),
+        }
+    }
+
+    /// 为请求附加鉴权头
+    pub fn apply(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self {
+            UpstreamAuth::Bearer(token) => builder.bearer_auth(token),
+            UpstreamAuth::ApiKey(api_key) => builder.header("x-goog-api-key", api_key),
+        }
+    }
+}
+
+fn default_service_account() -> String {
+    "default".to_string()
+}
+
+impl Credential {
+    pub fn is_refresh_token(&self) -> bool {
+        matches!(self, Credential::RefreshToken)
+    }
+
+    /// 凭据中显式配置的项目 ID
+    pub fn project_id(&self) -> Option<&str> {
+        match self {
+            Credential::RefreshToken => None,
+            Credential::ApiKey { project_id, .. } | Credential::ServiceAccount { project_id, .. } => project_id.as_deref(),
+        }
+    }
+}
+
 impl Account {
     pub fn new(id: String, email: String, token: TokenData) -> Self {
         let now = chrono::Utc::now().timestamp();
@@ -44,7 +169,8 @@ impl Account {
             id,
             email,
             name: None,
-            token,
+            token: Some(token),
+            credential: Credential::default(),
             device_profile: None,
             device_history: Vec::new(),
             quota: None,
@@ -54,11 +180,25 @@ impl Account {
             proxy_disabled: false,
             proxy_disabled_reason: None,
             proxy_disabled_at: None,
+            priority: 0,
+            upstream_proxy: None,
+            max_concurrency: None,
+            max_rpm: None,
+            daily_request_cap: None,
+            canary: false,
+            supported_protocols: Vec::new(),
             created_at: now,
             last_used: now,
         }
     }
 
+    /// OAuth 令牌 (仅 refresh_token 账号持有)
+    pub fn oauth_token(&self) -> Result<&TokenData, String> {
+        self.token
+            .as_ref()
+            .ok_or_else(|| format!("账号 {} 没有 OAuth token，当前凭据类型不支持此操作", self.email))
+    }
+
     pub fn update_last_used(&mut self) {
         self.last_used = chrono::Utc::now().timestamp();
     }
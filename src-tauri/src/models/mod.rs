@@ -3,7 +3,7 @@ pub mod token;
 pub mod quota;
 pub mod config;
 
-pub use account::{Account, AccountIndex, AccountSummary, DeviceProfile, DeviceProfileVersion};
+pub use account::{Account, AccountIndex, AccountProtocol, AccountSummary, Credential, UpstreamAuth, DeviceProfile, DeviceProfileVersion};
 pub use token::TokenData;
 pub use quota::QuotaData;
 pub use config::{AppConfig, QuotaProtectionConfig};
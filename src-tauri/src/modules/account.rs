@@ -1,11 +1,13 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use serde_json;
 use uuid::Uuid;
 use serde::Serialize;
 
-use crate::models::{Account, AccountIndex, AccountSummary, TokenData, QuotaData, DeviceProfile, DeviceProfileVersion,};
+use crate::models::{Account, AccountIndex, AccountSummary, TokenData, QuotaData, DeviceProfile, DeviceProfileVersion, UpstreamAuth};
+pub use crate::models::Credential;
 use crate::modules;
+use crate::modules::audit;
 use once_cell::sync::Lazy;
 use std::sync::Mutex;
 
@@ -47,7 +49,10 @@ pub fn get_accounts_dir() -> Result<PathBuf, String> {
 
 /// 加载账号索引
 pub fn load_account_index() -> Result<AccountIndex, String> {
-    let data_dir = get_data_dir()?;
+    load_account_index_from(&get_data_dir()?)
+}
+
+fn load_account_index_from(data_dir: &Path) -> Result<AccountIndex, String> {
     let index_path = data_dir.join(ACCOUNTS_INDEX);
     // modules::logger::log_info(&format!("正在加载账号索引: {:?}", index_path)); // Optional: reduce noise
     
@@ -68,7 +73,10 @@ pub fn load_account_index() -> Result<AccountIndex, String> {
 
 /// 保存账号索引 (原子化写入)
 pub fn save_account_index(index: &AccountIndex) -> Result<(), String> {
-    let data_dir = get_data_dir()?;
+    save_account_index_to(&get_data_dir()?, index)
+}
+
+fn save_account_index_to(data_dir: &Path, index: &AccountIndex) -> Result<(), String> {
     let index_path = data_dir.join(ACCOUNTS_INDEX);
     let temp_path = data_dir.join(format!("{}.tmp", ACCOUNTS_INDEX));
     
@@ -191,6 +199,7 @@ pub fn add_account(email: String, name: Option<String>, token: TokenData) -> Res
     }
     
     save_account_index(&index)?;
+    audit::record(None, "account.add", serde_json::json!({ "account_id": account.id, "email": email }));
     
     Ok(account)
 }
@@ -209,22 +218,25 @@ pub fn upsert_account(email: String, name: Option<String>, token: TokenData) ->
         // 更新现有账号
         match load_account(&account_id) {
             Ok(mut account) => {
-                let old_access_token = account.token.access_token.clone();
-                let old_refresh_token = account.token.refresh_token.clone();
-                account.token = token;
+                let credentials_changed = account.token.as_ref().is_none_or(|old| {
+                    old.refresh_token != token.refresh_token || old.access_token != token.access_token
+                });
+                account.token = Some(token);
                 account.name = name.clone();
                 // If an account was previously disabled (e.g. invalid_grant), any explicit token upsert
                 // should re-enable it (user manually updated credentials in the UI).
-                if account.disabled
-                    && (account.token.refresh_token != old_refresh_token
-                        || account.token.access_token != old_access_token)
-                {
+                if account.disabled && credentials_changed {
                     account.disabled = false;
                     account.disabled_reason = None;
                     account.disabled_at = None;
                 }
                 account.update_last_used();
                 save_account(&account)?;
+                audit::record(
+                    None,
+                    "account.update",
+                    serde_json::json!({ "account_id": account_id, "email": account.email, "fields": ["token", "name"] }),
+                );
                 
                 // 同步更新索引中的 name
                 if let Some(idx_summary) = index.accounts.iter_mut().find(|s| s.id == account_id) {
@@ -264,31 +276,40 @@ pub fn upsert_account(email: String, name: Option<String>, token: TokenData) ->
 /// 删除账号
 pub fn delete_account(account_id: &str) -> Result<(), String> {
     let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
-    let mut index = load_account_index()?;
+    delete_account_in(&get_data_dir()?, account_id)
+}
+
+/// 在指定数据目录中删除账号并写入审计记录 (调用方负责加锁)
+fn delete_account_in(data_dir: &Path, account_id: &str) -> Result<(), String> {
+    let mut index = load_account_index_from(data_dir)?;
     
     // 从索引中移除
-    let original_len = index.accounts.len();
-    index.accounts.retain(|s| s.id != account_id);
-    
-    if index.accounts.len() == original_len {
+    let Some(pos) = index.accounts.iter().position(|s| s.id == account_id) else {
         return Err(format!("找不到账号 ID: {}", account_id));
-    }
+    };
+    let removed = index.accounts.remove(pos);
     
     // 如果是当前账号，清除当前账号
     if index.current_account_id.as_deref() == Some(account_id) {
         index.current_account_id = index.accounts.first().map(|s| s.id.clone());
     }
     
-    save_account_index(&index)?;
+    save_account_index_to(data_dir, &index)?;
     
     // 删除账号文件
-    let accounts_dir = get_accounts_dir()?;
-    let account_path = accounts_dir.join(format!("{}.json", account_id));
+    let account_path = data_dir.join(ACCOUNTS_DIR).join(format!("{}.json", account_id));
     
     if account_path.exists() {
         fs::remove_file(&account_path)
             .map_err(|e| format!("删除账号文件失败: {}", e))?;
     }
+
+    audit::record_in(
+        data_dir,
+        None,
+        "account.delete",
+        serde_json::json!({ "account_id": account_id, "email": removed.email }),
+    );
     
     Ok(())
 }
@@ -302,7 +323,11 @@ pub fn delete_accounts(account_ids: &[String]) -> Result<(), String> {
     
     for account_id in account_ids {
         // 从索引中移除
+        let email = index.accounts.iter().find(|s| &s.id == account_id).map(|s| s.email.clone());
         index.accounts.retain(|s| &s.id != account_id);
+        if let Some(email) = email {
+            audit::record(None, "account.delete", serde_json::json!({ "account_id": account_id, "email": email }));
+        }
         
         // 如果是当前账号，清除当前账号
         if index.current_account_id.as_deref() == Some(account_id) {
@@ -376,12 +401,14 @@ pub async fn switch_account(account_id: &str) -> Result<(), String> {
     crate::modules::logger::log_info(&format!("正在切换到账号: {} (ID: {})", account.email, account.id));
     
     // 2. 确保 Token 有效（自动刷新）
-    let fresh_token = oauth::ensure_fresh_token(&account.token).await
+    // API Key / 服务账号无法注入客户端数据库
+    let current_token = account.oauth_token()?.clone();
+    let fresh_token = oauth::ensure_fresh_token(&current_token).await
         .map_err(|e| format!("Token 刷新失败: {}", e))?;
         
     // 如果 Token 更新了，保存回账号文件
-    if fresh_token.access_token != account.token.access_token {
-        account.token = fresh_token.clone();
+    if fresh_token.access_token != current_token.access_token {
+        account.token = Some(fresh_token.clone());
         save_account(&account)?;
     }
     
@@ -429,9 +456,9 @@ pub async fn switch_account(account_id: &str) -> Result<(), String> {
     crate::modules::logger::log_info("正在注入 Token 到数据库...");
     db::inject_token(
         &db_path,
-        &account.token.access_token,
-        &account.token.refresh_token,
-        account.token.expiry_timestamp,
+        &fresh_token.access_token,
+        &fresh_token.refresh_token,
+        fresh_token.expiry_timestamp,
     )?;
 
     // 7. 更新工具内部状态
@@ -688,6 +715,49 @@ pub fn update_account_quota(account_id: &str, quota: QuotaData) -> Result<(), St
     save_account(&account)
 }
 
+/// 设置账号调度优先级 (数值越大越优先)
+pub fn set_account_priority(account_id: &str, priority: i32) -> Result<Account, String> {
+    let mut account = load_account(account_id)?;
+    account.priority = priority;
+    save_account(&account)?;
+    audit::record(None, "account.update", serde_json::json!({ "account_id": account_id, "priority": priority }));
+    Ok(account)
+}
+
+/// 设置账号灰度标记 (灰度账号仅承接少量流量，成功请求数达标后由反代自动转正)
+pub fn set_account_canary(account_id: &str, canary: bool) -> Result<Account, String> {
+    let mut account = load_account(account_id)?;
+    account.canary = canary;
+    save_account(&account)?;
+    audit::record(None, "account.update", serde_json::json!({ "account_id": account_id, "canary": canary }));
+    Ok(account)
+}
+
+/// 设置账号级并发/RPM 上限 (None 表示沿用全局默认值)
+pub fn set_account_limits(
+    account_id: &str,
+    max_concurrency: Option<u32>,
+    max_rpm: Option<u32>,
+    daily_request_cap: Option<u64>,
+) -> Result<Account, String> {
+    let mut account = load_account(account_id)?;
+    account.max_concurrency = max_concurrency;
+    account.max_rpm = max_rpm;
+    account.daily_request_cap = daily_request_cap;
+    save_account(&account)?;
+    audit::record(
+        None,
+        "account.update",
+        serde_json::json!({
+            "account_id": account_id,
+            "max_concurrency": max_concurrency,
+            "max_rpm": max_rpm,
+            "daily_request_cap": daily_request_cap,
+        }),
+    );
+    Ok(account)
+}
+
 /// 导出所有账号的 refresh_token
 #[allow(dead_code)]
 pub fn export_accounts() -> Result<Vec<(String, String)>, String> {
@@ -695,7 +765,9 @@ pub fn export_accounts() -> Result<Vec<(String, String)>, String> {
     let mut exports = Vec::new();
     
     for account in accounts {
-        exports.push((account.email, account.token.refresh_token));
+        if let Some(token) = account.token {
+            exports.push((account.email, token.refresh_token));
+        }
     }
     
     Ok(exports)
@@ -706,9 +778,18 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
     use crate::modules::oauth;
     use crate::error::AppError;
     use reqwest::StatusCode;
+
+    // API Key / 服务账号：凭据直接可用，不走 OAuth 刷新，也不会因 invalid_grant 被禁用
+    if let Some(auth) = oauth::credential_upstream_auth(&account.credential).await.map_err(AppError::OAuth)? {
+        let project_id = account.credential.project_id().map(|s| s.to_string());
+        return modules::quota::fetch_quota_with_cache(&auth, &account.email, project_id.as_deref())
+            .await
+            .map(|(q, _)| q);
+    }
+    let mut oauth_token = account.oauth_token().map_err(AppError::Account)?.clone();
     
     // 1. 基于时间的检查 (Time-based check) - 先确保 Token 有效
-    let token = match oauth::ensure_fresh_token(&account.token).await {
+    let token = match oauth::ensure_fresh_token(&oauth_token).await {
         Ok(t) => t,
         Err(e) => {
             if e.contains("invalid_grant") {
@@ -720,14 +801,20 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
                 account.disabled_at = Some(chrono::Utc::now().timestamp());
                 account.disabled_reason = Some(format!("invalid_grant: {}", e));
                 let _ = save_account(account);
+                audit::record(
+                    Some(audit::SYSTEM_ACTOR),
+                    "account.disable",
+                    serde_json::json!({ "account_id": account.id, "reason": "invalid_grant" }),
+                );
             }
             return Err(AppError::OAuth(e));
         }
     };
     
-    if token.access_token != account.token.access_token {
+    if token.access_token != oauth_token.access_token {
         modules::logger::log_info(&format!("基于时间的 Token 刷新: {}", account.email));
-        account.token = token.clone();
+        oauth_token = token.clone();
+        account.token = Some(token.clone());
         
         // 重新获取用户名 (Token 刷新后顺便获取)
         let name = if account.name.is_none() || account.name.as_ref().map_or(false, |n| n.trim().is_empty()) {
@@ -747,13 +834,13 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
     if account.name.is_none() || account.name.as_ref().map_or(false, |n| n.trim().is_empty()) {
         modules::logger::log_info(&format!("账号 {} 缺少用户名，尝试获取...", account.email));
         // 使用更新后的 token
-        match oauth::get_user_info(&account.token.access_token).await {
+        match oauth::get_user_info(&oauth_token.access_token).await {
             Ok(user_info) => {
                 let display_name = user_info.get_display_name();
                 modules::logger::log_info(&format!("成功获取用户名: {:?}", display_name));
                 account.name = display_name.clone();
                 // 立即保存
-                if let Err(e) = upsert_account(account.email.clone(), display_name, account.token.clone()) {
+                if let Err(e) = upsert_account(account.email.clone(), display_name, oauth_token.clone()) {
                      modules::logger::log_warn(&format!("保存用户名失败: {}", e));
                 }
             },
@@ -764,14 +851,15 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
     }
 
     // 2. 尝试查询
-    let result: crate::error::AppResult<(QuotaData, Option<String>)> = modules::fetch_quota(&account.token.access_token, &account.email).await;
+    let result: crate::error::AppResult<(QuotaData, Option<String>)> = modules::fetch_quota(&UpstreamAuth::Bearer(oauth_token.access_token.clone()), &account.email).await;
     
     // 捕获可能更新的 project_id 并保存
     if let Ok((ref _q, ref project_id)) = result {
-        if project_id.is_some() && *project_id != account.token.project_id {
+        if project_id.is_some() && *project_id != oauth_token.project_id {
             modules::logger::log_info(&format!("检测到 project_id 更新 ({}), 正在保存...", account.email));
-            account.token.project_id = project_id.clone();
-            if let Err(e) = upsert_account(account.email.clone(), account.name.clone(), account.token.clone()) {
+            oauth_token.project_id = project_id.clone();
+            account.token = Some(oauth_token.clone());
+            if let Err(e) = upsert_account(account.email.clone(), account.name.clone(), oauth_token.clone()) {
                 modules::logger::log_warn(&format!("同步保存 project_id 失败: {}", e));
             }
         }
@@ -784,7 +872,7 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
                 modules::logger::log_warn(&format!("401 Unauthorized for {}, forcing refresh...", account.email));
                 
                 // 强制刷新
-                let token_res = match oauth::refresh_access_token(&account.token.refresh_token).await {
+                let token_res = match oauth::refresh_access_token(&oauth_token.refresh_token).await {
                     Ok(t) => t,
                     Err(e) => {
                         if e.contains("invalid_grant") {
@@ -796,6 +884,11 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
                             account.disabled_at = Some(chrono::Utc::now().timestamp());
                             account.disabled_reason = Some(format!("invalid_grant: {}", e));
                             let _ = save_account(account);
+                            audit::record(
+                                Some(audit::SYSTEM_ACTOR),
+                                "account.disable",
+                                serde_json::json!({ "account_id": account.id, "reason": "invalid_grant" }),
+                            );
                         }
                         return Err(AppError::OAuth(e));
                     }
@@ -803,10 +896,10 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
                 
                 let new_token = TokenData::new(
                     token_res.access_token.clone(),
-                    account.token.refresh_token.clone(),
+                    oauth_token.refresh_token.clone(),
                     token_res.expires_in,
-                    account.token.email.clone(),
-                    account.token.project_id.clone(), // 保留原有 project_id
+                    oauth_token.email.clone(),
+                    oauth_token.project_id.clone(), // 保留原有 project_id
                     None, // 添加 None 作为 session_id
                 );
                 
@@ -820,19 +913,21 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
                     account.name.clone()
                 };
                 
-                account.token = new_token.clone();
+                oauth_token = new_token.clone();
+                account.token = Some(new_token.clone());
                 account.name = name.clone();
                 upsert_account(account.email.clone(), name, new_token.clone()).map_err(AppError::Account)?;
                 
                 // 重试查询
-                let retry_result: crate::error::AppResult<(QuotaData, Option<String>)> = modules::fetch_quota(&new_token.access_token, &account.email).await;
+                let retry_result: crate::error::AppResult<(QuotaData, Option<String>)> = modules::fetch_quota(&UpstreamAuth::Bearer(new_token.access_token.clone()), &account.email).await;
                 
                 // 同样处理重试时的 project_id 保存
                 if let Ok((ref _q, ref project_id)) = retry_result {
-                    if project_id.is_some() && *project_id != account.token.project_id {
+                    if project_id.is_some() && *project_id != oauth_token.project_id {
                         modules::logger::log_info(&format!("检测到重试后 project_id 更新 ({}), 正在保存...", account.email));
-                        account.token.project_id = project_id.clone();
-                        let _ = upsert_account(account.email.clone(), account.name.clone(), account.token.clone());
+                        oauth_token.project_id = project_id.clone();
+                        account.token = Some(oauth_token.clone());
+                        let _ = upsert_account(account.email.clone(), account.name.clone(), oauth_token.clone());
                     }
                 }
 
@@ -954,3 +1049,42 @@ pub async fn refresh_all_quotas_logic() -> Result<RefreshStats, String> {
         details,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delete_account_writes_audit_entry() {
+        let data_dir = std::env::temp_dir().join(format!("ag-account-audit-{}", Uuid::new_v4()));
+        fs::create_dir_all(data_dir.join(ACCOUNTS_DIR)).unwrap();
+
+        let mut index = AccountIndex::new();
+        index.accounts.push(AccountSummary {
+            id: "acc-1".to_string(),
+            email: "a@example.com".to_string(),
+            name: None,
+            created_at: 0,
+            last_used: 0,
+        });
+        index.current_account_id = Some("acc-1".to_string());
+        save_account_index_to(&data_dir, &index).unwrap();
+        fs::write(data_dir.join(ACCOUNTS_DIR).join("acc-1.json"), "{}").unwrap();
+
+        delete_account_in(&data_dir, "acc-1").unwrap();
+        assert!(!data_dir.join(ACCOUNTS_DIR).join("acc-1.json").exists());
+        assert!(load_account_index_from(&data_dir).unwrap().accounts.is_empty());
+
+        let entries = audit::read_entries(&data_dir).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, "account.delete");
+        assert_eq!(entries[0].details["account_id"], "acc-1");
+        assert_eq!(entries[0].details["email"], "a@example.com");
+
+        // 找不到账号时不写审计记录
+        assert!(delete_account_in(&data_dir, "acc-1").is_err());
+        assert_eq!(audit::read_entries(&data_dir).unwrap().len(), 1);
+
+        let _ = fs::remove_dir_all(&data_dir);
+    }
+}
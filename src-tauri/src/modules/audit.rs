@@ -0,0 +1,156 @@
+// 审计日志
+// 记录管理类操作 (账号增删/禁用、配置变更、API Key 轮换)，与请求日志分开存放于 `<data_dir>/audit/audit.log`
+// - 每条记录为一行 JSON，只追加不改写；每次写入都重新以追加模式打开，兼容外部 logrotate 的重命名轮转
+// - 文件超过上限时重命名为 `audit-<timestamp>.log` 后新建，不删除任何历史文件
+// - 记录中不包含令牌、API Key 等敏感值
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const AUDIT_DIR: &str = "audit";
+const AUDIT_FILE: &str = "audit.log";
+
+/// 单个审计文件的大小上限，超出后轮转
+pub const MAX_AUDIT_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// 串行化同一进程内的写入与轮转
+static AUDIT_WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// 进程级默认操作者 (如 CLI 启动时设置为 `cli:<user>`)
+static DEFAULT_ACTOR: OnceCell<String> = OnceCell::new();
+
+/// 系统自动触发的操作 (如 invalid_grant 自动禁用账号) 使用的操作者
+pub const SYSTEM_ACTOR: &str = "system";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    pub action: String,
+    #[serde(default)]
+    pub details: Value,
+}
+
+/// 设置进程级默认操作者，只生效一次
+pub fn set_default_actor(actor: impl Into<String>) {
+    let _ = DEFAULT_ACTOR.set(actor.into());
+}
+
+pub fn default_actor() -> Option<String> {
+    DEFAULT_ACTOR.get().cloned()
+}
+
+pub fn audit_log_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(AUDIT_DIR).join(AUDIT_FILE)
+}
+
+/// 超过上限时将当前文件重命名归档
+fn rotate_if_needed(path: &Path) -> Result<(), String> {
+    let size = match fs::metadata(path) {
+        Ok(meta) => meta.len(),
+        Err(_) => return Ok(()),
+    };
+    if size < MAX_AUDIT_FILE_BYTES {
+        return Ok(());
+    }
+    let archived = path.with_file_name(format!(
+        "audit-{}.log",
+        chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    ));
+    fs::rename(path, &archived).map_err(|e| format!("轮转审计日志失败: {}", e))
+}
+
+/// 向指定数据目录追加一条审计记录
+pub fn append_in(data_dir: &Path, entry: &AuditEntry) -> Result<(), String> {
+    let path = audit_log_path(data_dir);
+    let _lock = AUDIT_WRITE_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("创建审计目录失败: {}", e))?;
+    }
+    rotate_if_needed(&path)?;
+
+    let mut line = serde_json::to_string(entry).map_err(|e| format!("序列化审计记录失败: {}", e))?;
+    line.push('\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("打开审计日志失败: {}", e))?;
+    file.write_all(line.as_bytes())
+        .map_err(|e| format!("写入审计日志失败: {}", e))
+}
+
+/// 记录一次管理操作 (写入失败只记录警告，不影响操作本身)
+pub fn record_in(data_dir: &Path, actor: Option<&str>, action: &str, details: Value) {
+    let entry = AuditEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        actor: actor.map(str::to_string).or_else(default_actor),
+        action: action.to_string(),
+        details,
+    };
+    if let Err(e) = append_in(data_dir, &entry) {
+        tracing::warn!("写入审计日志失败 ({}): {}", action, e);
+    }
+}
+
+/// 记录到默认数据目录
+pub fn record(actor: Option<&str>, action: &str, details: Value) {
+    match crate::modules::account::get_data_dir() {
+        Ok(data_dir) => record_in(&data_dir, actor, action, details),
+        Err(e) => tracing::warn!("写入审计日志失败 ({}): {}", action, e),
+    }
+}
+
+/// 读取当前审计文件中的全部记录 (忽略无法解析的行)
+pub fn read_entries(data_dir: &Path) -> Result<Vec<AuditEntry>, String> {
+    let path = audit_log_path(data_dir);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("读取审计日志失败: {}", e))?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_entries_appended_and_rotated_without_loss() {
+        let dir = std::env::temp_dir().join(format!("ag-audit-{}", uuid::Uuid::new_v4()));
+        record_in(&dir, Some("cli:alice"), "config.update", json!({ "path": "gui_config.json" }));
+        record_in(&dir, None, "account.disable", json!({ "account_id": "a1" }));
+
+        let entries = read_entries(&dir).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].actor.as_deref(), Some("cli:alice"));
+        assert_eq!(entries[1].action, "account.disable");
+
+        // 超过上限后归档旧文件，新记录写入新文件
+        let path = audit_log_path(&dir);
+        let file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.set_len(MAX_AUDIT_FILE_BYTES).unwrap();
+        record_in(&dir, None, "proxy.api_key.rotate", json!({}));
+
+        let entries = read_entries(&dir).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, "proxy.api_key.rotate");
+        let archived: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with("audit-"))
+            .collect();
+        assert_eq!(archived.len(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
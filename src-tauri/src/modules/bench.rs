@@ -0,0 +1,215 @@
+// 本地压测
+// 按给定并发向已配置的代理发送 N 个测试请求，统计吞吐、延迟分位数与错误率，用于容量评估
+
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// 代理地址 (如 `http://127.0.0.1:8045`)
+    pub base_url: String,
+    pub api_key: String,
+    pub model: String,
+    pub concurrency: usize,
+    pub requests: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub elapsed: Duration,
+    /// 每个请求的耗时 (升序)
+    pub latencies: Vec<Duration>,
+    /// 首个失败原因 (便于排查)
+    pub first_error: Option<String>,
+}
+
+impl BenchReport {
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.total as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.failed as f64 / self.total as f64
+        }
+    }
+
+    /// 最近秩法分位数 (p 取 0-100)
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0) * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    pub fn render_text(&self) -> String {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let mut out = String::new();
+        out.push_str(&format!(
+            "Requests: {} ({} ok, {} failed, error rate {:.1}%)\n",
+            self.total,
+            self.succeeded,
+            self.failed,
+            self.error_rate() * 100.0
+        ));
+        out.push_str(&format!(
+            "Elapsed: {:.2}s, throughput: {:.2} req/s\n",
+            self.elapsed.as_secs_f64(),
+            self.throughput()
+        ));
+        out.push_str(&format!(
+            "Latency (ms): p50={:.1} p90={:.1} p99={:.1} max={:.1}\n",
+            ms(self.percentile(50.0)),
+            ms(self.percentile(90.0)),
+            ms(self.percentile(99.0)),
+            ms(self.latencies.last().copied().unwrap_or_default())
+        ));
+        if let Some(err) = &self.first_error {
+            out.push_str(&format!("First error: {}\n", err));
+        }
+        out
+    }
+}
+
+/// 最小的测试请求 (OpenAI Chat Completions，仅生成 1 个 token)
+pub fn build_test_request(model: &str) -> Value {
+    json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": "ping" }],
+        "max_tokens": 1,
+        "stream": false
+    })
+}
+
+/// 向代理发送一次测试请求
+pub async fn send_test_request(
+    client: &reqwest::Client,
+    base_url: &str,
+    api_key: &str,
+    model: &str,
+) -> Result<(), String> {
+    let url = format!("{}/v1/chat/completions", base_url.trim_end_matches('/'));
+    let resp = client
+        .post(&url)
+        .bearer_auth(api_key)
+        .json(&build_test_request(model))
+        .send()
+        .await
+        .map_err(|e| format!("请求失败: {}", e))?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!("HTTP {}: {}", status, body.chars().take(200).collect::<String>()));
+    }
+    // 读完响应体，耗时才包含完整生成时间
+    resp.bytes().await.map_err(|e| format!("读取响应失败: {}", e))?;
+    Ok(())
+}
+
+pub async fn run_bench(options: &BenchOptions) -> Result<BenchReport, String> {
+    if options.requests == 0 {
+        return Err("requests 必须大于 0".to_string());
+    }
+    let client = reqwest::Client::builder()
+        .no_proxy()
+        .timeout(Duration::from_secs(300))
+        .build()
+        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
+
+    let started = Instant::now();
+    let results: Vec<(Duration, Result<(), String>)> = futures::stream::iter(0..options.requests)
+        .map(|_| {
+            let client = &client;
+            async move {
+                let start = Instant::now();
+                let result =
+                    send_test_request(client, &options.base_url, &options.api_key, &options.model).await;
+                (start.elapsed(), result)
+            }
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .collect()
+        .await;
+    let elapsed = started.elapsed();
+
+    let mut latencies: Vec<Duration> = results.iter().map(|(d, _)| *d).collect();
+    latencies.sort();
+    let failed = results.iter().filter(|(_, r)| r.is_err()).count();
+    let first_error = results.into_iter().find_map(|(_, r)| r.err());
+
+    Ok(BenchReport {
+        total: options.requests,
+        succeeded: options.requests - failed,
+        failed,
+        elapsed,
+        latencies,
+        first_error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    async fn spawn_mock_proxy(calls: Arc<AtomicUsize>) -> String {
+        let app = axum::Router::new().route(
+            "/v1/chat/completions",
+            axum::routing::post(move || {
+                let calls = calls.clone();
+                async move {
+                    // 每 5 个请求失败一次
+                    let n = calls.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    if n % 5 == 4 {
+                        return (axum::http::StatusCode::TOO_MANY_REQUESTS, "rate limited").into_response();
+                    }
+                    axum::Json(json!({ "id": format!("resp-{}", n), "choices": [] })).into_response()
+                }
+            }),
+        );
+        format!("http://{}", crate::proxy::tests::support::spawn_router(app).await)
+    }
+
+    #[tokio::test]
+    async fn test_bench_reports_stats_against_mock() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let base_url = spawn_mock_proxy(calls.clone()).await;
+
+        let report = run_bench(&BenchOptions {
+            base_url,
+            api_key: "sk-test".to_string(),
+            model: "gemini-3-flash".to_string(),
+            concurrency: 4,
+            requests: 10,
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 10);
+        assert_eq!(report.total, 10);
+        assert_eq!(report.failed, 2);
+        assert_eq!(report.succeeded, 8);
+        assert_eq!(report.latencies.len(), 10);
+        assert!(report.percentile(50.0) >= Duration::from_millis(5));
+        assert!(report.percentile(99.0) >= report.percentile(50.0));
+        assert!(report.throughput() > 0.0);
+        assert!((report.error_rate() - 0.2).abs() < f64::EPSILON);
+        assert!(report.first_error.as_deref().unwrap().contains("429"));
+        assert!(report.render_text().contains("p50="));
+    }
+}
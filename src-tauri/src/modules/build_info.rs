@@ -0,0 +1,93 @@
+// 构建信息
+// 版本号、git 提交 (由 build.rs 注入，源码包构建时可能缺失) 与编译期 feature，供 `/version` 与 CLI `version` 使用
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::proxy::ProxyConfig;
+
+/// crate 版本号
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// 构建时的 git 提交短哈希
+pub const GIT_HASH: Option<&str> = option_env!("AG_GIT_HASH");
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: Option<&'static str>,
+    pub features: Vec<&'static str>,
+}
+
+/// 编译进当前二进制的 feature 列表
+pub fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "ui") {
+        features.push("ui");
+    }
+    if cfg!(feature = "otel") {
+        features.push("otel");
+    }
+    features
+}
+
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: VERSION,
+        git_hash: GIT_HASH,
+        features: enabled_features(),
+    }
+}
+
+/// 排查问题时关心的运行时开关 (不含密钥等敏感字段)
+pub fn runtime_toggles(config: &ProxyConfig) -> Value {
+    json!({
+        "auth_mode": config.auth_mode,
+        "allow_lan_access": config.allow_lan_access,
+        "enable_logging": config.enable_logging,
+        "monitor_mode": config.monitor_mode,
+        "upstream_proxy": config.upstream_proxy.enabled,
+        "zai_enabled": config.zai.enabled,
+        "zai_dispatch_mode": config.zai.dispatch_mode,
+        "reasoning_output": config.reasoning_output,
+        "readiness_requires_upstream": config.readiness_requires_upstream,
+        "readiness_min_healthy_accounts": config.readiness_threshold.min_healthy_accounts,
+        "readiness_min_healthy_fraction": config.readiness_threshold.min_healthy_fraction,
+        "otlp_export": config.otlp_endpoint.is_some(),
+        "webhook": config.webhook_url.is_some(),
+        "truncation_warning_header": config.truncation_warning_header,
+        "response_compression": config.response_compression,
+        "auto_model": config.auto_model.target,
+        "max_tools": config.tool_limit.max_tools,
+        "remote_media": config.remote_media.enabled,
+        "schema_retry": config.schema_retry.enabled,
+        "logprobs_unsupported_action": config.logprobs.unsupported_action,
+        "max_total_attempts": config.max_total_attempts,
+        "safety_fallback": config.safety_fallback.enabled,
+        "auto_trim_history": config.auto_trim_history.enabled,
+        "advertise_servable_only": config.advertise_servable_only,
+        "strip_variant_suffix_on_unavailable": config.strip_variant_suffix_on_unavailable,
+        "family_overrides_builtin": config.family_overrides_builtin,
+        "default_fallback_policy": config.default_fallback_policy,
+        "expose_debug_headers": config.expose_debug_headers,
+        "maintenance_mode": config.maintenance_mode.enabled,
+        "enabled_endpoints": config.enabled_endpoints,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtime_toggles_exclude_secrets() {
+        let mut config = ProxyConfig {
+            api_key: "sk-secret".to_string(),
+            ..ProxyConfig::default()
+        };
+        config.zai.api_key = "zai-secret".to_string();
+        let toggles = runtime_toggles(&config).to_string();
+        assert!(!toggles.contains("sk-secret"));
+        assert!(!toggles.contains("zai-secret"));
+        assert!(toggles.contains("\"response_compression\":true"));
+    }
+}
@@ -1,26 +1,169 @@
 use std::fs;
+use std::path::{Path, PathBuf};
 use serde_json;
 
 use crate::models::AppConfig;
 use super::account::get_data_dir;
 
 const CONFIG_FILE: &str = "gui_config.json";
+/// 同目录下的可选格式 (允许注释)，仅在 `gui_config.json` 不存在时生效
+const CONFIG_FILE_ALTERNATES: [&str; 2] = ["gui_config.json5", "gui_config.toml"];
+
+/// 配置文件格式
+/// - `Json`: 标准 JSON
+/// - `Json5`: 扩展名为 `.json5`，或 `.json` 文件内容不是合法 JSON 但是合法 JSON5 (含注释、尾随逗号等)
+/// - `Toml`: 扩展名为 `.toml`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Json5,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// 按扩展名判断格式，`.json` 及其他扩展名按 JSON 处理
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("json5") => ConfigFormat::Json5,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+}
+
+/// 解析配置文件内容，返回原始值与实际识别出的格式
+/// JSON 解析失败时再按 JSON5 尝试，以兼容在 `gui_config.json` 中直接加注释的情况
+pub fn parse_config_content(content: &str, path: &Path) -> Result<(serde_json::Value, ConfigFormat), String> {
+    match ConfigFormat::from_path(path) {
+        ConfigFormat::Toml => toml::from_str::<serde_json::Value>(content)
+            .map(|v| (v, ConfigFormat::Toml))
+            .map_err(|e| format!("解析配置文件失败: {}", e)),
+        ConfigFormat::Json5 => json5::from_str::<serde_json::Value>(content)
+            .map(|v| (v, ConfigFormat::Json5))
+            .map_err(|e| format!("解析配置文件失败: {}", e)),
+        ConfigFormat::Json => match serde_json::from_str::<serde_json::Value>(content) {
+            Ok(v) => Ok((v, ConfigFormat::Json)),
+            Err(json_err) => json5::from_str::<serde_json::Value>(content)
+                .map(|v| (v, ConfigFormat::Json5))
+                .map_err(|_| format!("解析配置文件失败: {}", json_err)),
+        },
+    }
+}
+
+// 环境变量覆盖 (优先级高于配置文件)
+pub const ENV_PROXY_PORT: &str = "ANTIGRAVITY_PROXY_PORT";
+pub const ENV_PROXY_API_KEY: &str = "ANTIGRAVITY_PROXY_API_KEY";
+pub const ENV_PROXY_ALLOW_LAN: &str = "ANTIGRAVITY_PROXY_ALLOW_LAN";
+pub const ENV_PROXY_REQUEST_TIMEOUT: &str = "ANTIGRAVITY_PROXY_REQUEST_TIMEOUT";
+pub const ENV_UPSTREAM_PROXY_URL: &str = "ANTIGRAVITY_UPSTREAM_PROXY_URL";
+pub const ENV_SCHEDULING_SEED: &str = "ANTIGRAVITY_SCHEDULING_SEED";
+/// 配置文件损坏时直接报错，而不是备份后回退到默认配置
+pub const ENV_STRICT_CONFIG: &str = "ANTIGRAVITY_STRICT_CONFIG";
+
+/// 配置文件候选路径 (按优先级排序)
+/// 1. 数据目录: `~/.antigravity_tools/gui_config.json` (主路径)
+/// 2. 可执行文件同目录: `<exe_dir>/gui_config.json` (便携模式)
+/// 3. `$XDG_CONFIG_HOME/antigravity_tools/gui_config.json` (设置了该变量时)
+///
+/// 每个目录内依次查找 `gui_config.json`、`gui_config.json5`、`gui_config.toml`
+pub fn config_candidate_paths() -> Result<Vec<PathBuf>, String> {
+    let mut dirs = vec![get_data_dir()?];
+
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|d| d.to_path_buf()))
+    {
+        dirs.push(exe_dir);
+    }
+
+    if let Some(xdg) = std::env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        dirs.push(PathBuf::from(xdg).join("antigravity_tools"));
+    }
+
+    Ok(dirs
+        .iter()
+        .flat_map(|dir| {
+            std::iter::once(CONFIG_FILE)
+                .chain(CONFIG_FILE_ALTERNATES)
+                .map(move |name| dir.join(name))
+        })
+        .collect())
+}
+
+/// 选择实际使用的配置路径：第一个已存在的候选路径；都不存在时使用主路径
+/// 加载与保存均通过此函数定位，保证写回到读取时的同一文件
+pub fn resolve_config_path(candidates: &[PathBuf]) -> Option<PathBuf> {
+    candidates
+        .iter()
+        .find(|p| p.is_file())
+        .or_else(|| candidates.first())
+        .cloned()
+}
+
+fn active_config_path() -> Result<PathBuf, String> {
+    let candidates = config_candidate_paths()?;
+    resolve_config_path(&candidates).ok_or_else(|| "无法确定配置文件路径".to_string())
+}
 
 /// 加载应用配置
+/// 按 `config_candidate_paths` 的顺序查找，第一个存在的文件生效；均不存在时使用默认配置
 pub fn load_app_config() -> Result<AppConfig, String> {
-    let data_dir = get_data_dir()?;
-    let config_path = data_dir.join(CONFIG_FILE);
-    
+    let strict = std::env::var(ENV_STRICT_CONFIG)
+        .ok()
+        .and_then(|v| parse_env_bool(&v))
+        .unwrap_or(false);
+    let config_path = active_config_path()?;
+    load_app_config_from(&config_path, strict)
+}
+
+/// 从指定路径加载配置
+/// - `strict = false`: 文件损坏时备份为 `gui_config.bak.<timestamp>` 并回退到默认配置
+/// - `strict = true`: 文件损坏时直接返回错误
+pub fn load_app_config_from(config_path: &Path, strict: bool) -> Result<AppConfig, String> {
     if !config_path.exists() {
         return Ok(AppConfig::new());
     }
     
-    let content = fs::read_to_string(&config_path)
+    let content = fs::read_to_string(config_path)
         .map_err(|e| format!("读取配置文件失败: {}", e))?;
     
-    let mut v: serde_json::Value = serde_json::from_str(&content)
-        .map_err(|e| format!("解析配置文件失败: {}", e))?;
-    
+    let parsed = parse_config_content(&content, config_path)
+        .and_then(|(v, _)| migrate_config_value(v, config_path));
+
+    match parsed {
+        Ok(config) => {
+            // 语义校验失败属于用户配置错误而非文件损坏，不做回退
+            config.proxy.validate()?;
+            for warning in config.proxy.advisory_warnings() {
+                tracing::warn!("[Config] {}", warning);
+            }
+            Ok(config)
+        }
+        Err(e) if strict => Err(e),
+        Err(e) => {
+            let backup = backup_corrupt_config(config_path)?;
+            tracing::error!(
+                "[Config] 配置文件已损坏 ({}), 已备份至 {:?} 并使用默认配置启动。设置 {}=1 可改为直接报错。",
+                e,
+                backup,
+                ENV_STRICT_CONFIG
+            );
+            Ok(AppConfig::new())
+        }
+    }
+}
+
+/// 将损坏的配置文件移动到同目录下的 `gui_config.bak.<timestamp>`
+fn backup_corrupt_config(config_path: &Path) -> Result<PathBuf, String> {
+    let stamp = chrono::Local::now().format("%Y%m%d%H%M%S");
+    let backup = config_path.with_file_name(format!("gui_config.bak.{}", stamp));
+    fs::rename(config_path, &backup)
+        .map_err(|e| format!("备份损坏的配置文件失败: {}", e))?;
+    Ok(backup)
+}
+
+/// 对原始 JSON 执行迁移并反序列化
+fn migrate_config_value(mut v: serde_json::Value, config_path: &Path) -> Result<AppConfig, String> {
     let mut modified = false;
 
     // 迁移逻辑
@@ -66,23 +209,493 @@ pub fn load_app_config() -> Result<AppConfig, String> {
 
     let config: AppConfig = serde_json::from_value(v)
         .map_err(|e| format!("迁移后转换配置失败: {}", e))?;
-    
+
     // 如果发生了迁移，自动保存一次以清理文件
     if modified {
-        let _ = save_app_config(&config);
+        let _ = save_app_config_to(config_path, &config);
     }
 
     Ok(config)
 }
 
-/// 保存应用配置
+/// 加载生效配置: 文件配置 (含迁移) + 环境变量覆盖
+/// 这是代理服务实际运行时使用的配置
+pub fn load_effective_app_config() -> Result<AppConfig, String> {
+    let mut config = load_app_config()?;
+    let applied = apply_env_overrides(&mut config, |key| std::env::var(key).ok());
+    for key in applied {
+        tracing::info!("[Config] Applied env override: {}", key);
+    }
+    Ok(config)
+}
+
+/// 应用环境变量覆盖，返回实际生效的变量名
+/// 无法解析的值会被忽略并记录警告
+pub fn apply_env_overrides<F>(config: &mut AppConfig, lookup: F) -> Vec<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut applied = Vec::new();
+
+    if let Some(v) = lookup(ENV_PROXY_PORT) {
+        match v.trim().parse::<u16>() {
+            Ok(port) => {
+                config.proxy.port = port;
+                applied.push(ENV_PROXY_PORT.to_string());
+            }
+            Err(_) => tracing::warn!("[Config] Invalid {}: {}", ENV_PROXY_PORT, v),
+        }
+    }
+
+    if let Some(v) = lookup(ENV_PROXY_API_KEY) {
+        config.proxy.api_key = v;
+        applied.push(ENV_PROXY_API_KEY.to_string());
+    }
+
+    if let Some(v) = lookup(ENV_PROXY_ALLOW_LAN) {
+        match parse_env_bool(&v) {
+            Some(allow) => {
+                config.proxy.allow_lan_access = allow;
+                applied.push(ENV_PROXY_ALLOW_LAN.to_string());
+            }
+            None => tracing::warn!("[Config] Invalid {}: {}", ENV_PROXY_ALLOW_LAN, v),
+        }
+    }
+
+    if let Some(v) = lookup(ENV_PROXY_REQUEST_TIMEOUT) {
+        match v.trim().parse::<u64>() {
+            Ok(timeout) => {
+                config.proxy.request_timeout = timeout;
+                applied.push(ENV_PROXY_REQUEST_TIMEOUT.to_string());
+            }
+            Err(_) => tracing::warn!("[Config] Invalid {}: {}", ENV_PROXY_REQUEST_TIMEOUT, v),
+        }
+    }
+
+    if let Some(v) = lookup(ENV_UPSTREAM_PROXY_URL) {
+        let url = v.trim().to_string();
+        config.proxy.upstream_proxy.enabled = !url.is_empty();
+        config.proxy.upstream_proxy.url = url;
+        applied.push(ENV_UPSTREAM_PROXY_URL.to_string());
+    }
+
+    if let Some(v) = lookup(ENV_SCHEDULING_SEED) {
+        match v.trim().parse::<u64>() {
+            Ok(seed) => {
+                config.proxy.scheduling_seed = Some(seed);
+                applied.push(ENV_SCHEDULING_SEED.to_string());
+            }
+            Err(_) => tracing::warn!("[Config] Invalid {}: {}", ENV_SCHEDULING_SEED, v),
+        }
+    }
+
+    applied
+}
+
+fn parse_env_bool(v: &str) -> Option<bool> {
+    match v.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// 将配置序列化为指定格式 (`json` | `toml`)
+pub fn render_app_config(config: &AppConfig, format: &str) -> Result<String, String> {
+    match format {
+        "json" => serde_json::to_string_pretty(config)
+            .map_err(|e| format!("序列化配置失败: {}", e)),
+        "toml" => toml::to_string_pretty(config)
+            .map_err(|e| format!("序列化配置失败: {}", e)),
+        other => Err(format!("不支持的输出格式: {}", other)),
+    }
+}
+
+/// 保存应用配置 (写回到加载时选中的配置文件，并保持其格式)
 pub fn save_app_config(config: &AppConfig) -> Result<(), String> {
-    let data_dir = get_data_dir()?;
-    let config_path = data_dir.join(CONFIG_FILE);
-    
-    let content = serde_json::to_string_pretty(config)
-        .map_err(|e| format!("序列化配置失败: {}", e))?;
+    let config_path = active_config_path()?;
+    let previous_key = load_app_config_from(&config_path, true).ok().map(|c| c.proxy.api_key);
+    let written = save_app_config_to(&config_path, config)?;
+    record_config_update(&written, previous_key, &config.proxy.api_key);
+    Ok(())
+}
+
+/// 记录本次运行的反代配置 (服务启动后调用，写回当前配置文件)
+/// 存在环境变量覆盖时跳过：运行配置中含仅来自环境变量的值 (如 API Key)，写回会让临时覆盖与密钥落盘
+pub fn save_running_proxy_config(proxy: &crate::proxy::ProxyConfig) -> Result<(), String> {
+    let config_path = active_config_path()?;
+    let stored = load_app_config()?;
+    let previous_key = stored.proxy.api_key.clone();
+    if let Some(written) = save_running_proxy_config_to(&config_path, stored, proxy, |key| std::env::var(key).ok())? {
+        record_config_update(&written, Some(previous_key), &proxy.api_key);
+    }
+    Ok(())
+}
+
+/// 返回实际写入的配置文件路径，未写入时为 None
+fn save_running_proxy_config_to<F>(
+    config_path: &Path,
+    mut stored: AppConfig,
+    proxy: &crate::proxy::ProxyConfig,
+    lookup: F,
+) -> Result<Option<PathBuf>, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let applied = apply_env_overrides(&mut AppConfig::new(), lookup);
+    if !applied.is_empty() {
+        tracing::info!(
+            "[Config] Env overrides active ({}), running proxy config not saved",
+            applied.join(", ")
+        );
+        return Ok(None);
+    }
+    stored.proxy = proxy.clone();
+    save_app_config_to(config_path, &stored).map(Some)
+}
+
+/// 审计: 只记录发生了变更，不记录配置内容与密钥
+fn record_config_update(config_path: &Path, previous_key: Option<String>, api_key: &str) {
+    crate::modules::audit::record(
+        None,
+        "config.update",
+        serde_json::json!({ "path": config_path.display().to_string() }),
+    );
+    if previous_key.is_some_and(|key| key != api_key) {
+        crate::modules::audit::record(None, "proxy.api_key.rotate", serde_json::json!({}));
+    }
+}
+
+/// `.toml` 文件写回 TOML；JSON 与 JSON5 文件写回格式化的 JSON (同为合法 JSON5)
+/// 序列化无法保留注释：原文件含注释 (或其他 JSON5 扩展语法) 时不覆盖原文件，
+/// 改为写入同目录的 `<名称>.saved.<扩展名>` 并记录警告，由用户自行合并
+/// 返回实际写入的文件路径
+fn save_app_config_to(config_path: &Path, config: &AppConfig) -> Result<PathBuf, String> {
+    config.proxy.validate()?;
+
+    let format = match ConfigFormat::from_path(config_path) {
+        ConfigFormat::Toml => "toml",
+        ConfigFormat::Json | ConfigFormat::Json5 => "json",
+    };
+    let has_comments = match format {
+        "toml" => has_toml_comments(config_path),
+        _ => has_json5_only_syntax(config_path),
+    };
+    let target = if has_comments {
+        let saved = saved_config_path(config_path);
+        tracing::warn!(
+            "[Config] {:?} 含注释，为避免丢失注释不覆盖原文件，新配置已写入 {:?}，请手动合并",
+            config_path,
+            saved
+        );
+        saved
+    } else {
+        config_path.to_path_buf()
+    };
+    let content = render_app_config(config, format)?;
     
-    fs::write(&config_path, content)
-        .map_err(|e| format!("保存配置失败: {}", e))
+    fs::write(&target, content)
+        .map_err(|e| format!("保存配置失败: {}", e))?;
+    Ok(target)
+}
+
+/// `gui_config.json5` -> `gui_config.saved.json5`
+fn saved_config_path(config_path: &Path) -> PathBuf {
+    let stem = config_path.file_stem().and_then(|s| s.to_str()).unwrap_or("gui_config");
+    let name = match config_path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}.saved.{}", stem, ext),
+        None => format!("{}.saved", stem),
+    };
+    config_path.with_file_name(name)
+}
+
+/// 现有 TOML 文件含 `#` 注释 (整行或行尾，字符串内的 `#` 除外) 时返回 true
+fn has_toml_comments(config_path: &Path) -> bool {
+    let Ok(content) = fs::read_to_string(config_path) else {
+        return false;
+    };
+    content.lines().any(|line| {
+        let mut quote: Option<char> = None;
+        let mut escaped = false;
+        for c in line.chars() {
+            match quote {
+                Some('"') if escaped => escaped = false,
+                Some('"') if c == '\\' => escaped = true,
+                Some(q) if c == q => quote = None,
+                Some(_) => {}
+                None if c == '"' || c == '\'' => quote = Some(c),
+                None if c == '#' => return true,
+                None => {}
+            }
+        }
+        false
+    })
+}
+
+/// 现有文件不是标准 JSON (含注释、尾随逗号等 JSON5 语法) 时返回 true
+fn has_json5_only_syntax(config_path: &Path) -> bool {
+    fs::read_to_string(config_path)
+        .map(|content| serde_json::from_str::<serde_json::Value>(&content).is_err())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_env_override_only_in_effective_output() {
+        let raw = AppConfig::new();
+        let raw_port = raw.proxy.port;
+
+        let env: HashMap<&str, &str> = [(ENV_PROXY_PORT, "18080"), ("UNRELATED", "x")]
+            .into_iter()
+            .collect();
+        let mut effective = raw.clone();
+        let applied = apply_env_overrides(&mut effective, |k| env.get(k).map(|v| v.to_string()));
+        assert_eq!(applied, vec![ENV_PROXY_PORT.to_string()]);
+
+        for format in ["json", "toml"] {
+            let raw_out = render_app_config(&raw, format).unwrap();
+            let effective_out = render_app_config(&effective, format).unwrap();
+            assert!(effective_out.contains("18080"), "{} effective output missing override", format);
+            assert!(!raw_out.contains("18080"), "{} raw output should not see env override", format);
+            assert!(raw_out.contains(&raw_port.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_running_config_with_env_override_is_not_saved() {
+        let dir = temp_config_dir("running");
+        let path = dir.join(CONFIG_FILE);
+        let original = r#"{
+            "language": "en", "theme": "system", "auto_refresh": true, "refresh_interval": 15,
+            "auto_sync": false, "sync_interval": 5,
+            "proxy": { "enabled": false, "api_key": "sk-file", "auto_start": false, "port": 18383 }
+        }"#;
+        fs::write(&path, original).unwrap();
+
+        // CLI 以环境变量覆盖后的配置启动
+        let env: HashMap<&str, &str> = [(ENV_PROXY_API_KEY, "sk-env-only")].into_iter().collect();
+        let lookup = |k: &str| env.get(k).map(|v| v.to_string());
+        let mut effective = load_app_config_from(&path, true).unwrap();
+        apply_env_overrides(&mut effective, lookup);
+        assert_eq!(effective.proxy.api_key, "sk-env-only");
+
+        let stored = load_app_config_from(&path, true).unwrap();
+        assert!(save_running_proxy_config_to(&path, stored, &effective.proxy, lookup).unwrap().is_none());
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+
+        // 无环境变量覆盖时照常记录运行配置
+        let stored = load_app_config_from(&path, true).unwrap();
+        let mut proxy = stored.proxy.clone();
+        proxy.port = 18484;
+        assert_eq!(save_running_proxy_config_to(&path, stored, &proxy, |_| None).unwrap(), Some(path.clone()));
+        assert_eq!(load_app_config_from(&path, true).unwrap().proxy.port, 18484);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_invalid_env_override_is_ignored() {
+        let mut config = AppConfig::new();
+        let port = config.proxy.port;
+        let applied = apply_env_overrides(&mut config, |k| match k {
+            ENV_PROXY_PORT => Some("not-a-port".to_string()),
+            ENV_PROXY_ALLOW_LAN => Some("yes".to_string()),
+            _ => None,
+        });
+        assert_eq!(config.proxy.port, port);
+        assert!(config.proxy.allow_lan_access);
+        assert_eq!(applied, vec![ENV_PROXY_ALLOW_LAN.to_string()]);
+    }
+
+    fn temp_config_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "antigravity-config-test-{}-{}",
+            name,
+            uuid::Uuid::new_v4().simple()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_corrupt_config_is_backed_up_and_defaults_used() {
+        let dir = temp_config_dir("corrupt");
+        let path = dir.join(CONFIG_FILE);
+        let corrupt = r#"{"language": "en", "proxy": {"port": 80"#;
+        fs::write(&path, corrupt).unwrap();
+
+        let config = load_app_config_from(&path, false).unwrap();
+        assert_eq!(config.language, AppConfig::new().language);
+        assert!(!path.exists());
+
+        let backups: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("gui_config.bak."))
+            })
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), corrupt);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_corrupt_config_fails_hard_in_strict_mode() {
+        let dir = temp_config_dir("strict");
+        let path = dir.join(CONFIG_FILE);
+        fs::write(&path, "not json").unwrap();
+
+        assert!(load_app_config_from(&path, true).is_err());
+        assert!(path.exists(), "strict mode must not move the file");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_secondary_config_used_when_primary_missing() {
+        let primary_dir = temp_config_dir("primary");
+        let secondary_dir = temp_config_dir("secondary");
+        let primary = primary_dir.join(CONFIG_FILE);
+        let secondary = secondary_dir.join(CONFIG_FILE);
+
+        let mut stored = AppConfig::new();
+        stored.language = "en".to_string();
+        stored.proxy.port = 18181;
+        save_app_config_to(&secondary, &stored).unwrap();
+
+        let candidates = vec![primary.clone(), secondary.clone()];
+        let chosen = resolve_config_path(&candidates).unwrap();
+        assert_eq!(chosen, secondary);
+
+        let mut loaded = load_app_config_from(&chosen, false).unwrap();
+        assert_eq!(loaded.proxy.port, 18181);
+        assert_eq!(loaded.language, "en");
+
+        // 保存写回到同一 (次要) 路径，不会在主路径创建文件
+        loaded.proxy.port = 18282;
+        save_app_config_to(&resolve_config_path(&candidates).unwrap(), &loaded).unwrap();
+        assert!(!primary.exists());
+        assert_eq!(load_app_config_from(&secondary, false).unwrap().proxy.port, 18282);
+
+        // 主路径存在时优先
+        save_app_config_to(&primary, &AppConfig::new()).unwrap();
+        assert_eq!(resolve_config_path(&candidates).unwrap(), primary);
+
+        let _ = fs::remove_dir_all(&primary_dir);
+        let _ = fs::remove_dir_all(&secondary_dir);
+    }
+
+    #[test]
+    fn test_missing_candidates_fall_back_to_primary() {
+        let dir = temp_config_dir("none");
+        let candidates = vec![dir.join("a").join(CONFIG_FILE), dir.join("b").join(CONFIG_FILE)];
+        assert_eq!(resolve_config_path(&candidates), Some(candidates[0].clone()));
+        assert_eq!(resolve_config_path(&[]), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    const COMMENTED_JSON5: &str = r#"{
+        // 界面语言
+        language: 'en',
+        theme: 'system',
+        auto_refresh: true,
+        refresh_interval: 15,
+        auto_sync: false,
+        sync_interval: 5,
+        proxy: {
+            enabled: false,
+            api_key: 'sk-test',
+            auto_start: false,
+            port: 18383, // 自定义端口
+            custom_mapping: {
+                /* 把 GPT-4 请求映射到 Gemini */
+                "gpt-4": "gemini-3-pro-high",
+            },
+        },
+    }"#;
+
+    #[test]
+    fn test_commented_json5_config_is_loaded() {
+        let dir = temp_config_dir("json5");
+        for name in ["gui_config.json5", CONFIG_FILE] {
+            let path = dir.join(name);
+            fs::write(&path, COMMENTED_JSON5).unwrap();
+            let (_, format) = parse_config_content(COMMENTED_JSON5, &path).unwrap();
+            assert_eq!(format, ConfigFormat::Json5);
+
+            // 严格模式下也能加载，且不会被当作损坏文件备份
+            let config = load_app_config_from(&path, true).unwrap();
+            assert_eq!(config.language, "en");
+            assert_eq!(config.proxy.port, 18383);
+            assert_eq!(config.proxy.custom_mapping.get("gpt-4").map(String::as_str), Some("gemini-3-pro-high"));
+            assert!(path.exists());
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_json5_and_toml_configs_round_trip_in_their_format() {
+        let dir = temp_config_dir("roundtrip");
+
+        let json5_path = dir.join("gui_config.json5");
+        fs::write(&json5_path, COMMENTED_JSON5).unwrap();
+        let mut config = load_app_config_from(&json5_path, true).unwrap();
+        config.proxy.port = 18484;
+        // 含注释的原文件不被覆盖，新配置写入同目录的 .saved 文件
+        let saved = save_app_config_to(&json5_path, &config).unwrap();
+        assert_eq!(saved, dir.join("gui_config.saved.json5"));
+        assert_eq!(fs::read_to_string(&json5_path).unwrap(), COMMENTED_JSON5);
+        let reloaded = load_app_config_from(&saved, true).unwrap();
+        assert_eq!(
+            serde_json::to_value(&reloaded).unwrap(),
+            serde_json::to_value(&config).unwrap()
+        );
+        assert_eq!(reloaded.proxy.custom_mapping.get("gpt-4").map(String::as_str), Some("gemini-3-pro-high"));
+        // 不含注释的文件照常原地写回
+        assert_eq!(save_app_config_to(&saved, &config).unwrap(), saved);
+
+        let toml_path = dir.join("gui_config.toml");
+        fs::write(&toml_path, render_app_config(&config, "toml").unwrap()).unwrap();
+        let mut config = load_app_config_from(&toml_path, true).unwrap();
+        assert_eq!(config.proxy.port, 18484);
+        config.language = "zh".to_string();
+        save_app_config_to(&toml_path, &config).unwrap();
+        let content = fs::read_to_string(&toml_path).unwrap();
+        assert!(serde_json::from_str::<serde_json::Value>(&content).is_err(), "TOML file must stay TOML");
+        let reloaded = load_app_config_from(&toml_path, true).unwrap();
+        assert_eq!(
+            serde_json::to_value(&reloaded).unwrap(),
+            serde_json::to_value(&config).unwrap()
+        );
+
+        // TOML 注释同样保留：字符串内的 # 不算注释
+        assert!(!has_toml_comments(&toml_path));
+        let commented = format!("# 本地覆盖\n{}", content);
+        fs::write(&toml_path, &commented).unwrap();
+        assert!(has_toml_comments(&toml_path));
+        assert_eq!(save_app_config_to(&toml_path, &config).unwrap(), dir.join("gui_config.saved.toml"));
+        assert_eq!(fs::read_to_string(&toml_path).unwrap(), commented);
+
+        // 同目录下 gui_config.json 不存在时才会选中其他格式
+        let candidates: Vec<PathBuf> = std::iter::once(CONFIG_FILE)
+            .chain(CONFIG_FILE_ALTERNATES)
+            .map(|n| dir.join(n))
+            .collect();
+        assert_eq!(resolve_config_path(&candidates).unwrap(), json5_path);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_render_rejects_unknown_format() {
+        assert!(render_app_config(&AppConfig::new(), "yaml").is_err());
+    }
 }
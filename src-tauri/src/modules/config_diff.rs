@@ -0,0 +1,214 @@
+// 配置文件差异对比
+// 将两份配置序列化为 JSON 后逐字段比较，输出新增/删除/修改的字段 (映射表按条目、开关按字段)
+
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::models::AppConfig;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ConfigDiffEntry {
+    /// 以 `.` 连接的字段路径，例如 `proxy.custom_mapping.gpt-4o`
+    pub path: String,
+    pub kind: DiffKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfigDiff {
+    pub entries: Vec<ConfigDiffEntry>,
+}
+
+/// 敏感字段只报告是否变化，不输出具体值
+///
+/// 按完整字段名或 `_` 分隔的后缀匹配 (如 `api_key`、`refresh_token`)，
+/// 避免 `max_tokens_cap` 之类的普通字段被误判。
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    ["api_key", "token", "secret", "password"]
+        .iter()
+        .any(|s| key == *s || key.ends_with(&format!("_{}", s)))
+}
+
+fn redact(value: &Value, secret: bool) -> Value {
+    if secret {
+        Value::String("<redacted>".to_string())
+    } else {
+        value.clone()
+    }
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn diff_values(path: &str, a: &Value, b: &Value, secret: bool, out: &mut Vec<ConfigDiffEntry>) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child = join_path(path, key);
+                let secret = secret || is_secret_key(key);
+                match (a.get(key), b.get(key)) {
+                    (Some(old), Some(new)) => diff_values(&child, old, new, secret, out),
+                    (Some(old), None) => out.push(ConfigDiffEntry {
+                        path: child,
+                        kind: DiffKind::Removed,
+                        old: Some(redact(old, secret)),
+                        new: None,
+                    }),
+                    (None, Some(new)) => out.push(ConfigDiffEntry {
+                        path: child,
+                        kind: DiffKind::Added,
+                        old: None,
+                        new: Some(redact(new, secret)),
+                    }),
+                    (None, None) => {}
+                }
+            }
+        }
+        // 数组 (候选列表等) 与标量整体比较
+        _ if a != b => out.push(ConfigDiffEntry {
+            path: path.to_string(),
+            kind: DiffKind::Changed,
+            old: Some(redact(a, secret)),
+            new: Some(redact(b, secret)),
+        }),
+        _ => {}
+    }
+}
+
+/// 比较两份配置，条目按字段路径排序
+pub fn diff_app_configs(a: &AppConfig, b: &AppConfig) -> Result<ConfigDiff, String> {
+    let a = serde_json::to_value(a).map_err(|e| format!("序列化配置失败: {}", e))?;
+    let b = serde_json::to_value(b).map_err(|e| format!("序列化配置失败: {}", e))?;
+    let mut entries = Vec::new();
+    diff_values("", &a, &b, false, &mut entries);
+    Ok(ConfigDiff { entries })
+}
+
+/// 加载两份配置文件 (经过迁移与校验) 并比较；文件不存在时报错而不是回退为默认配置
+pub fn diff_config_files(a: &Path, b: &Path) -> Result<ConfigDiff, String> {
+    let load = |path: &Path| {
+        if !path.exists() {
+            return Err(format!("配置文件不存在: {}", path.display()));
+        }
+        super::config::load_app_config_from(path, true)
+    };
+    diff_app_configs(&load(a)?, &load(b)?)
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn render_text(&self) -> String {
+        if self.entries.is_empty() {
+            return "No differences\n".to_string();
+        }
+        let show = |v: &Option<Value>| v.as_ref().map(Value::to_string).unwrap_or_default();
+        let mut out = String::new();
+        for entry in &self.entries {
+            let line = match entry.kind {
+                DiffKind::Added => format!("+ {}: {}", entry.path, show(&entry.new)),
+                DiffKind::Removed => format!("- {}: {}", entry.path, show(&entry.old)),
+                DiffKind::Changed => {
+                    format!("~ {}: {} -> {}", entry.path, show(&entry.old), show(&entry.new))
+                }
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push_str(&format!("{} difference(s)\n", self.entries.len()));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_lists_changed_mapping_and_flag_only() {
+        let mut a = AppConfig::new();
+        a.proxy.custom_mapping.insert("gpt-4o".to_string(), "gemini-3-flash".to_string());
+        a.proxy.custom_mapping.insert("claude-*".to_string(), "claude-sonnet-4-5".to_string());
+        a.proxy.enable_logging = false;
+
+        let mut b = a.clone();
+        b.proxy.custom_mapping.insert("gpt-4o".to_string(), "gemini-3-pro-high".to_string());
+        b.proxy.enable_logging = true;
+
+        let diff = diff_app_configs(&a, &b).unwrap();
+        assert_eq!(
+            diff.entries,
+            vec![
+                ConfigDiffEntry {
+                    path: "proxy.custom_mapping.gpt-4o".to_string(),
+                    kind: DiffKind::Changed,
+                    old: Some(json!("gemini-3-flash")),
+                    new: Some(json!("gemini-3-pro-high")),
+                },
+                ConfigDiffEntry {
+                    path: "proxy.enable_logging".to_string(),
+                    kind: DiffKind::Changed,
+                    old: Some(json!(false)),
+                    new: Some(json!(true)),
+                },
+            ]
+        );
+        assert!(diff.render_text().contains("~ proxy.enable_logging: false -> true"));
+    }
+
+    #[test]
+    fn test_diff_redacts_secrets() {
+        let a = AppConfig::new();
+        let mut b = a.clone();
+        b.proxy.api_key = "sk-new".to_string();
+
+        let diff = diff_app_configs(&a, &b).unwrap();
+        assert_eq!(diff.entries.len(), 1);
+        assert_eq!(diff.entries[0].path, "proxy.api_key");
+        assert_eq!(diff.entries[0].new, Some(json!("<redacted>")));
+    }
+
+    #[test]
+    fn test_diff_keeps_token_limit_values() {
+        let a = AppConfig::new();
+        let mut b = a.clone();
+        b.proxy.model_output_limits.insert(
+            "gemini-3-pro".to_string(),
+            crate::proxy::config::ModelOutputLimit { default_max_tokens: None, max_tokens_cap: Some(8192) },
+        );
+
+        let diff = diff_app_configs(&a, &b).unwrap();
+        assert_eq!(diff.entries.len(), 1);
+        assert_eq!(diff.entries[0].new, Some(json!({ "default_max_tokens": null, "max_tokens_cap": 8192 })));
+
+        assert!(is_secret_key("refresh_token"));
+        assert!(is_secret_key("client_secret"));
+        assert!(!is_secret_key("max_tokens_cap"));
+        assert!(!is_secret_key("default_max_tokens"));
+    }
+}
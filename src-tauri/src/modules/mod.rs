@@ -1,6 +1,7 @@
 pub mod account;
 pub mod quota;
 pub mod config;
+pub mod config_diff;
 pub mod logger;
 pub mod db;
 pub mod process;
@@ -14,6 +15,10 @@ pub mod i18n;
 pub mod proxy_db;
 pub mod device;
 pub mod update_checker;
+pub mod bench;
+pub mod probe;
+pub mod build_info;
+pub mod audit;
 #[cfg(feature = "ui")]
 pub mod scheduler;
 
@@ -28,6 +33,6 @@ pub use config::*;
 pub use logger::*;
 // pub use device::*;
 
-pub async fn fetch_quota(access_token: &str, email: &str) -> crate::error::AppResult<(models::QuotaData, Option<String>)> {
-    quota::fetch_quota(access_token, email).await
+pub async fn fetch_quota(auth: &models::UpstreamAuth, email: &str) -> crate::error::AppResult<(models::QuotaData, Option<String>)> {
+    quota::fetch_quota(auth, email).await
 }
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use crate::models::{Credential, UpstreamAuth};
 
 // Google OAuth 配置
 const CLIENT_ID: &str = "1071006060591-tmhssin2h21lcre235vtolojh4g403ep.apps.googleusercontent.com";
@@ -154,6 +155,57 @@ pub async fn refresh_access_token(refresh_token: &str) -> Result<TokenResponse,
     }
 }
 
+/// 元数据服务默认地址
+const METADATA_URL: &str = "http://metadata.google.internal";
+
+/// 从 GCP 元数据服务获取服务账号访问令牌
+pub async fn fetch_service_account_token(
+    service_account: &str,
+    metadata_url: Option<&str>,
+) -> Result<TokenResponse, String> {
+    // 元数据服务位于本机链路内，不经过上游代理
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(15))
+        .no_proxy()
+        .build()
+        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
+    let url = format!(
+        "{}/computeMetadata/v1/instance/service-accounts/{}/token",
+        metadata_url.unwrap_or(METADATA_URL).trim_end_matches('/'),
+        service_account
+    );
+
+    let response = client
+        .get(&url)
+        .header("Metadata-Flavor", "Google")
+        .send()
+        .await
+        .map_err(|e| format!("元数据服务请求失败: {}", e))?;
+
+    if response.status().is_success() {
+        response
+            .json::<TokenResponse>()
+            .await
+            .map_err(|e| format!("服务账号令牌解析失败: {}", e))
+    } else {
+        let error_text = response.text().await.unwrap_or_default();
+        Err(format!("获取服务账号令牌失败: {}", error_text))
+    }
+}
+
+/// 非 OAuth 账号的出站凭据：API Key 直接使用，服务账号从元数据服务获取访问令牌；
+/// refresh_token 账号返回 None，由调用方走 OAuth 刷新流程
+pub async fn credential_upstream_auth(credential: &Credential) -> Result<Option<UpstreamAuth>, String> {
+    match credential {
+        Credential::RefreshToken => Ok(None),
+        Credential::ApiKey { api_key, .. } => Ok(Some(UpstreamAuth::ApiKey(api_key.clone()))),
+        Credential::ServiceAccount { service_account, metadata_url, .. } => {
+            let token = fetch_service_account_token(service_account, metadata_url.as_deref()).await?;
+            Ok(Some(UpstreamAuth::Bearer(token.access_token)))
+        }
+    }
+}
+
 /// 获取用户信息
 pub async fn get_user_info(access_token: &str) -> Result<UserInfo, String> {
     let client = crate::utils::http::create_client(15);
@@ -0,0 +1,156 @@
+// 上游连通性探测
+// 不依赖账号，仅检查本机 (经配置的上游代理) 能否连到 Gemini 端点并测量往返延迟，用于区分网络问题与认证问题
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct ProbeOptions {
+    /// 探测地址 (默认为内置的主上游端点)
+    pub url: String,
+    pub upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProbeReport {
+    pub url: String,
+    /// 实际使用的上游代理 (已隐去凭据，未启用时为 None)
+    pub via_proxy: Option<String>,
+    /// 收到任意 HTTP 响应即视为可达 (无凭据时上游返回 401/404 属正常)
+    pub reachable: bool,
+    pub status: Option<u16>,
+    /// 从发出请求到收到响应头的耗时
+    pub latency: Duration,
+    pub error: Option<String>,
+}
+
+impl ProbeReport {
+    pub fn render_text(&self) -> String {
+        let mut out = format!("Target: {}\n", self.url);
+        if let Some(proxy) = &self.via_proxy {
+            out.push_str(&format!("Via proxy: {}\n", proxy));
+        }
+        let latency_ms = self.latency.as_secs_f64() * 1000.0;
+        if self.reachable {
+            out.push_str(&format!(
+                "Reachable: yes (HTTP {}, latency {:.1} ms)\n",
+                self.status.unwrap_or_default(),
+                latency_ms
+            ));
+        } else {
+            out.push_str(&format!("Reachable: no (after {:.1} ms)\n", latency_ms));
+        }
+        if let Some(err) = &self.error {
+            out.push_str(&format!("Error: {}\n", err));
+        }
+        out
+    }
+}
+
+pub async fn run_probe(options: &ProbeOptions) -> Result<ProbeReport, String> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(options.timeout)
+        .connect_timeout(options.timeout);
+    let proxy = &options.upstream_proxy;
+    let via_proxy = if proxy.enabled && !proxy.url.is_empty() {
+        crate::proxy::upstream::client::validate_proxy_url(&proxy.url)?;
+        let redacted = crate::proxy::upstream::client::redact_proxy_url(&proxy.url);
+        let p = reqwest::Proxy::all(&proxy.url).map_err(|e| format!("无效的代理地址 {}: {}", redacted, e))?;
+        builder = builder.proxy(p);
+        Some(redacted)
+    } else {
+        builder = builder.no_proxy();
+        None
+    };
+    let client = builder.build().map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
+
+    let started = Instant::now();
+    let result = client.get(&options.url).send().await;
+    let latency = started.elapsed();
+
+    Ok(match result {
+        Ok(resp) => ProbeReport {
+            url: options.url.clone(),
+            via_proxy,
+            reachable: true,
+            status: Some(resp.status().as_u16()),
+            latency,
+            error: None,
+        },
+        Err(e) => ProbeReport {
+            url: options.url.clone(),
+            via_proxy,
+            reachable: false,
+            status: None,
+            latency,
+            error: Some(e.to_string()),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_probe_reports_reachable_with_latency() {
+        let app = axum::Router::new().route(
+            "/v1internal",
+            axum::routing::get(|| async {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                (axum::http::StatusCode::UNAUTHORIZED, "missing credentials")
+            }),
+        );
+        let addr = crate::proxy::tests::support::spawn_router(app).await;
+
+        let report = run_probe(&ProbeOptions {
+            url: format!("http://{}/v1internal", addr),
+            upstream_proxy: Default::default(),
+            timeout: Duration::from_secs(5),
+        })
+        .await
+        .unwrap();
+
+        // 无账号时的 401 仍说明网络可达
+        assert!(report.reachable);
+        assert_eq!(report.status, Some(401));
+        assert!(report.latency >= Duration::from_millis(5));
+        assert!(report.render_text().contains("Reachable: yes"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_reports_unreachable() {
+        // 绑定后立即释放端口，连接会被拒绝
+        let addr = crate::proxy::tests::support::closed_local_addr();
+        let report = run_probe(&ProbeOptions {
+            url: format!("http://{}/", addr),
+            upstream_proxy: Default::default(),
+            timeout: Duration::from_secs(5),
+        })
+        .await
+        .unwrap();
+
+        assert!(!report.reachable);
+        assert!(report.error.is_some());
+        assert!(report.render_text().contains("Reachable: no"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_output_redacts_proxy_credentials() {
+        let proxy_addr = crate::proxy::tests::support::closed_local_addr();
+        let report = run_probe(&ProbeOptions {
+            url: "http://upstream.invalid/".to_string(),
+            upstream_proxy: crate::proxy::config::UpstreamProxyConfig {
+                enabled: true,
+                url: format!("http://user:s3cret@{}", proxy_addr),
+            },
+            timeout: Duration::from_secs(5),
+        })
+        .await
+        .unwrap();
+
+        let text = report.render_text();
+        assert!(text.contains(&format!("Via proxy: http://***@{}/", proxy_addr)), "{}", text);
+        assert!(!text.contains("s3cret"), "{}", text);
+    }
+}
@@ -32,6 +32,8 @@ pub fn init_db() -> Result<(), String> {
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN output_tokens INTEGER", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN account_email TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN mapped_model TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN estimated_cost REAL", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN experiment_arm TEXT", []);
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_timestamp ON request_logs (timestamp DESC)",
@@ -52,8 +54,8 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
 
     conn.execute(
-        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, estimated_cost, experiment_arm)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
         params![
             log.id,
             log.timestamp,
@@ -69,6 +71,8 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.output_tokens,
             log.account_email,
             log.mapped_model,
+            log.estimated_cost,
+            log.experiment_arm,
         ],
     ).map_err(|e| e.to_string())?;
 
@@ -83,7 +87,7 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model
+                input_tokens, output_tokens, account_email, mapped_model, estimated_cost, experiment_arm
          FROM request_logs 
          ORDER BY timestamp DESC 
          LIMIT ?1 OFFSET ?2"
@@ -105,6 +109,8 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
             response_body: None, // Don't query large fields for list view
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
+            estimated_cost: row.get(14).unwrap_or(None),
+            experiment_arm: row.get(15).unwrap_or(None),
         })
     }).map_err(|e| e.to_string())?;
 
@@ -135,13 +141,41 @@ pub fn get_stats() -> Result<crate::proxy::monitor::ProxyStats, String> {
         |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
     ).map_err(|e| e.to_string())?;
 
+    let (total_cost, cost_by_model) = get_cost_breakdown(&conn)?;
+
     Ok(crate::proxy::monitor::ProxyStats {
         total_requests,
         success_count,
         error_count,
+        total_cost,
+        cost_by_model,
+        ..Default::default()
     })
 }
 
+/// 按模型汇总估算费用 (优先使用路由后的模型名)
+fn get_cost_breakdown(conn: &Connection) -> Result<(f64, std::collections::HashMap<String, f64>), String> {
+    let mut stmt = conn.prepare(
+        "SELECT COALESCE(mapped_model, model, ''), SUM(estimated_cost)
+         FROM request_logs
+         WHERE estimated_cost IS NOT NULL
+         GROUP BY COALESCE(mapped_model, model, '')"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?))
+    }).map_err(|e| e.to_string())?;
+
+    let mut total = 0.0;
+    let mut by_model = std::collections::HashMap::new();
+    for row in rows {
+        let (model, cost) = row.map_err(|e| e.to_string())?;
+        total += cost;
+        by_model.insert(model, cost);
+    }
+    Ok((total, by_model))
+}
+
 /// Get single log detail (with request_body and response_body)
 pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
     let db_path = get_proxy_db_path()?;
@@ -150,7 +184,7 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 request_body, response_body, input_tokens, output_tokens, 
-                account_email, mapped_model
+                account_email, mapped_model, estimated_cost, experiment_arm
          FROM request_logs 
          WHERE id = ?1"
     ).map_err(|e| e.to_string())?;
@@ -171,6 +205,8 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
             response_body: row.get(9).unwrap_or(None),
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
+            estimated_cost: row.get(14).unwrap_or(None),
+            experiment_arm: row.get(15).unwrap_or(None),
         })
     }).map_err(|e| e.to_string())
 }
@@ -1,7 +1,7 @@
 use reqwest;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use crate::models::QuotaData;
+use crate::models::{QuotaData, UpstreamAuth};
 use crate::modules::config;
 
 const QUOTA_API_URL: &str = "https://cloudcode-pa.googleapis.com/v1internal:fetchAvailableModels";
@@ -65,13 +65,12 @@ fn create_warmup_client() -> reqwest::Client {
 const CLOUD_CODE_BASE_URL: &str = "https://cloudcode-pa.googleapis.com";
 
 /// 获取项目 ID 和订阅类型
-async fn fetch_project_id(access_token: &str, email: &str) -> (Option<String>, Option<String>) {
+async fn fetch_project_id(auth: &UpstreamAuth, email: &str) -> (Option<String>, Option<String>) {
     let client = create_client();
     let meta = json!({"metadata": {"ideType": "ANTIGRAVITY"}});
 
-    let res = client
-        .post(format!("{}/v1internal:loadCodeAssist", CLOUD_CODE_BASE_URL))
-        .header(reqwest::header::AUTHORIZATION, format!("Bearer {}", access_token))
+    let res = auth
+        .apply(client.post(format!("{}/v1internal:loadCodeAssist", CLOUD_CODE_BASE_URL)))
         .header(reqwest::header::CONTENT_TYPE, "application/json")
         .header(reqwest::header::USER_AGENT, "antigravity/windows/amd64")
         .json(&meta)
@@ -112,13 +111,13 @@ async fn fetch_project_id(access_token: &str, email: &str) -> (Option<String>, O
 }
 
 /// 查询账号配额的统一入口
-pub async fn fetch_quota(access_token: &str, email: &str) -> crate::error::AppResult<(QuotaData, Option<String>)> {
-    fetch_quota_with_cache(access_token, email, None).await
+pub async fn fetch_quota(auth: &UpstreamAuth, email: &str) -> crate::error::AppResult<(QuotaData, Option<String>)> {
+    fetch_quota_with_cache(auth, email, None).await
 }
 
 /// 带缓存的配额查询
 pub async fn fetch_quota_with_cache(
-    access_token: &str,
+    auth: &UpstreamAuth,
     email: &str,
     cached_project_id: Option<&str>,
 ) -> crate::error::AppResult<(QuotaData, Option<String>)> {
@@ -128,7 +127,7 @@ pub async fn fetch_quota_with_cache(
     let (project_id, subscription_tier) = if let Some(pid) = cached_project_id {
         (Some(pid.to_string()), None)
     } else {
-        fetch_project_id(access_token, email).await
+        fetch_project_id(auth, email).await
     };
     
     let final_project_id = project_id.as_deref().unwrap_or("bamboo-precept-lgxtn");
@@ -143,9 +142,8 @@ pub async fn fetch_quota_with_cache(
     let mut last_error: Option<AppError> = None;
 
     for attempt in 1..=max_retries {
-        match client
-            .post(url)
-            .bearer_auth(access_token)
+        match auth
+            .apply(client.post(url))
             .header("User-Agent", USER_AGENT)
             .json(&json!(payload))
             .send()
@@ -224,8 +222,8 @@ pub async fn fetch_quota_with_cache(
 }
 
 /// 查询账号配额逻辑
-pub async fn fetch_quota_inner(access_token: &str, email: &str) -> crate::error::AppResult<(QuotaData, Option<String>)> {
-    fetch_quota_with_cache(access_token, email, None).await
+pub async fn fetch_quota_inner(auth: &UpstreamAuth, email: &str) -> crate::error::AppResult<(QuotaData, Option<String>)> {
+    fetch_quota_with_cache(auth, email, None).await
 }
 
 /// 批量查询所有账号配额 (备用功能)
@@ -235,7 +233,7 @@ pub async fn fetch_all_quotas(accounts: Vec<(String, String)>) -> Vec<(String, c
     
     for (account_id, access_token) in accounts {
         // 在批量查询中，我们将 account_id 传入以供日志标识
-        let result = fetch_quota(&access_token, &account_id).await.map(|(q, _)| q);
+        let result = fetch_quota(&UpstreamAuth::Bearer(access_token), &account_id).await.map(|(q, _)| q);
         results.push((account_id, result));
     }
     
@@ -243,15 +241,25 @@ pub async fn fetch_all_quotas(accounts: Vec<(String, String)>) -> Vec<(String, c
 }
 
 /// 获取有效 token（自动刷新过期的）
-pub async fn get_valid_token_for_warmup(account: &crate::models::account::Account) -> Result<(String, String), String> {
+pub async fn get_valid_token_for_warmup(account: &crate::models::account::Account) -> Result<(UpstreamAuth, String), String> {
+    // API Key / 服务账号不走 OAuth 刷新
+    if let Some(auth) = crate::modules::oauth::credential_upstream_auth(&account.credential).await? {
+        let project_id = match account.credential.project_id() {
+            Some(pid) => Some(pid.to_string()),
+            None => fetch_project_id(&auth, &account.email).await.0,
+        };
+        return Ok((auth, project_id.unwrap_or_else(|| "bamboo-precept-lgxtn".to_string())));
+    }
+
     let mut account = account.clone();
+    let current_token = account.oauth_token()?.clone();
     
     // 检查并自动刷新 token
-    let new_token = crate::modules::oauth::ensure_fresh_token(&account.token).await?;
+    let new_token = crate::modules::oauth::ensure_fresh_token(&current_token).await?;
     
     // 如果 token 改变了（意味着刷新了），保存它
-    if new_token.access_token != account.token.access_token {
-        account.token = new_token;
+    if new_token.access_token != current_token.access_token {
+        account.token = Some(new_token.clone());
         if let Err(e) = crate::modules::account::save_account(&account) {
             crate::modules::logger::log_warn(&format!("[Warmup] 保存刷新后的 Token 失败: {}", e));
         } else {
@@ -260,15 +268,16 @@ pub async fn get_valid_token_for_warmup(account: &crate::models::account::Accoun
     }
     
     // 获取 project_id
-    let (project_id, _) = fetch_project_id(&account.token.access_token, &account.email).await;
+    let auth = UpstreamAuth::Bearer(new_token.access_token);
+    let (project_id, _) = fetch_project_id(&auth, &account.email).await;
     let final_pid = project_id.unwrap_or_else(|| "bamboo-precept-lgxtn".to_string());
     
-    Ok((account.token.access_token, final_pid))
+    Ok((auth, final_pid))
 }
 
 /// 通过代理内部 API 发送预热请求
 pub async fn warmup_model_directly(
-    access_token: &str,
+    auth: &UpstreamAuth,
     model_name: &str,
     project_id: &str,
     email: &str,
@@ -280,12 +289,15 @@ pub async fn warmup_model_directly(
         .unwrap_or(8045);
 
     let warmup_url = format!("http://127.0.0.1:{}/internal/warmup", port);
-    let body = json!({
+    let mut body = json!({
         "email": email,
         "model": model_name,
-        "access_token": access_token,
         "project_id": project_id
     });
+    // API Key 不经由请求体传递，由反代按邮箱从账号池取凭据
+    if let UpstreamAuth::Bearer(access_token) = auth {
+        body["access_token"] = json!(access_token);
+    }
 
     let client = create_warmup_client();
     let resp = client
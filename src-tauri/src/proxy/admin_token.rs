@@ -0,0 +1,74 @@
+// 管理令牌
+// 反代服务每次启动时生成随机令牌，写入 `<data_dir>/proxy_admin.token` (仅当前用户可读)
+// 管理类内部端点 (如账号排空) 除 API Key 外还要求 `x-admin-token` 与之匹配：
+// 只持有 API Key 的调用方无法执行管理操作，同机的 CLI 读取该文件后即可调用
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ADMIN_TOKEN_FILE: &str = "proxy_admin.token";
+
+/// 携带管理令牌的请求头
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+fn token_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(ADMIN_TOKEN_FILE)
+}
+
+/// 生成新的管理令牌并写入数据目录 (覆盖上次运行的令牌)
+pub fn issue(data_dir: &Path) -> Result<String, String> {
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let path = token_path(data_dir);
+    fs::write(&path, &token).map_err(|e| format!("写入管理令牌失败: {}", e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("设置管理令牌权限失败: {}", e))?;
+    }
+    Ok(token)
+}
+
+/// 读取运行中服务的管理令牌 (供 CLI 使用)
+pub fn read(data_dir: &Path) -> Result<String, String> {
+    fs::read_to_string(token_path(data_dir))
+        .map(|token| token.trim().to_string())
+        .map_err(|e| format!("读取管理令牌失败: {}", e))
+}
+
+/// 请求头中的管理令牌是否与服务持有的令牌一致 (服务未能生成令牌时一律拒绝)
+pub fn verify(expected: Option<&str>, headers: &axum::http::HeaderMap) -> bool {
+    let provided = headers.get(ADMIN_TOKEN_HEADER).and_then(|v| v.to_str().ok());
+    matches!((expected, provided), (Some(expected), Some(provided)) if expected == provided)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issued_token_round_trips_and_verifies() {
+        let dir = std::env::temp_dir().join(format!("ag-admin-token-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let token = issue(&dir).unwrap();
+        assert_eq!(read(&dir).unwrap(), token);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(token_path(&dir)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let mut headers = axum::http::HeaderMap::new();
+        assert!(!verify(Some(&token), &headers));
+        headers.insert(ADMIN_TOKEN_HEADER, "wrong".parse().unwrap());
+        assert!(!verify(Some(&token), &headers));
+        headers.insert(ADMIN_TOKEN_HEADER, token.parse().unwrap());
+        assert!(verify(Some(&token), &headers));
+        assert!(!verify(None, &headers));
+
+        // 重新启动后旧令牌失效
+        assert_ne!(issue(&dir).unwrap(), token);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,109 @@
+// 单请求上游尝试预算
+// 账号轮换重试与候选模型回退共用同一个预算，二者叠加时上游调用总数不超过 `max_total_attempts`，
+// 避免策略候选较多且重试次数较大时单个客户端请求放大为大量上游调用
+
+/// 默认预算，与单个候选的重试上限一致：单模型请求行为不变，多候选时总数受限
+pub const DEFAULT_MAX_TOTAL_ATTEMPTS: usize = 20;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AttemptBudget {
+    /// None 表示不限制
+    limit: Option<usize>,
+    used: usize,
+}
+
+impl AttemptBudget {
+    /// `max_total_attempts` 为 0 时不限制
+    pub fn new(max_total_attempts: usize) -> Self {
+        Self {
+            limit: (max_total_attempts > 0).then_some(max_total_attempts),
+            used: 0,
+        }
+    }
+
+    /// 发起一次上游调用前调用：返回 false 表示预算已用尽，应停止重试与回退
+    pub fn try_acquire(&mut self) -> bool {
+        if self.is_exhausted() {
+            return false;
+        }
+        self.used += 1;
+        if Some(self.used) == self.limit {
+            tracing::warn!("[Attempt-Budget] Request reached max_total_attempts ({}), no further retries or fallbacks", self.used);
+        }
+        true
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.limit.is_some_and(|limit| self.used >= limit)
+    }
+
+    pub fn used(&self) -> usize {
+        self.used
+    }
+}
+
+impl Default for AttemptBudget {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_TOTAL_ATTEMPTS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::common::model_mapping::ModelRoutePlan;
+    use crate::proxy::config::ModelFallbackPolicy;
+
+    /// 按处理器的循环结构模拟：每个候选最多重试 `max_attempts` 次，所有调用均失败
+    fn run_plan(plan: &ModelRoutePlan, max_attempts: usize, budget: &mut AttemptBudget) -> Vec<String> {
+        let mut calls = Vec::new();
+        for model in plan.candidates().iter().take(plan.max_models()) {
+            if budget.is_exhausted() {
+                break;
+            }
+            for _attempt in 0..max_attempts {
+                if !budget.try_acquire() {
+                    break;
+                }
+                calls.push(model.clone());
+            }
+        }
+        calls
+    }
+
+    fn plan() -> ModelRoutePlan {
+        ModelRoutePlan {
+            primary: "gemini-3-pro-high".to_string(),
+            fallbacks: vec![
+                "gemini-3-pro".to_string(),
+                "gemini-3-flash".to_string(),
+                "gemini-2.5-flash".to_string(),
+            ],
+            policy: ModelFallbackPolicy::default(),
+            strategy_id: Some("quality".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_total_attempts_capped_across_candidates_and_retries() {
+        let mut budget = AttemptBudget::new(6);
+        let calls = run_plan(&plan(), 5, &mut budget);
+        assert_eq!(calls.len(), 6);
+        assert_eq!(budget.used(), 6);
+        // 先用尽首个候选的重试，再回退到下一个候选
+        assert_eq!(calls.iter().filter(|m| *m == "gemini-3-pro-high").count(), 5);
+        assert_eq!(calls[5], "gemini-3-pro");
+
+        for limit in 1..=25 {
+            let mut budget = AttemptBudget::new(limit);
+            assert!(run_plan(&plan(), 5, &mut budget).len() <= limit);
+        }
+    }
+
+    #[test]
+    fn test_zero_disables_budget() {
+        let mut budget = AttemptBudget::new(0);
+        assert_eq!(run_plan(&plan(), 5, &mut budget).len(), 20);
+        assert!(!budget.is_exhausted());
+    }
+}
@@ -0,0 +1,140 @@
+// 单请求候选模型覆盖
+// 客户端可通过 `X-Model-Candidates: a,b,c` 为单次请求指定完整的候选链 (按顺序尝试)，
+// 覆盖配置中的映射与策略，使用默认回退策略执行；需开启 `allow_header_overrides`，候选须为已知模型且通过客户端模型允许列表
+use axum::http::HeaderMap;
+use std::collections::HashMap;
+
+use crate::proxy::common::error::ProxyError;
+use crate::proxy::common::model_mapping::{check_client_model_allowed, is_known_upstream_model, ModelRoutePlan};
+use crate::proxy::config::ModelFallbackPolicy;
+
+pub const MODEL_CANDIDATES_HEADER: &str = "x-model-candidates";
+
+/// 单次请求最多接受的候选数，超出部分忽略
+pub const MAX_HEADER_CANDIDATES: usize = 8;
+
+/// 解析逗号分隔的候选列表 (去除空白与重复项，保持顺序)
+pub fn parse_candidates(raw: &str) -> Vec<String> {
+    let mut candidates: Vec<String> = Vec::new();
+    for model in raw.split(',').map(str::trim).filter(|m| !m.is_empty()) {
+        if !candidates.iter().any(|c| c == model) {
+            candidates.push(model.to_string());
+        }
+    }
+    if candidates.len() > MAX_HEADER_CANDIDATES {
+        tracing::warn!(
+            "[Router] X-Model-Candidates lists {} models, only the first {} are used",
+            candidates.len(),
+            MAX_HEADER_CANDIDATES
+        );
+        candidates.truncate(MAX_HEADER_CANDIDATES);
+    }
+    candidates
+}
+
+/// 由请求头构建临时路由计划；未开启或未携带时返回 None，沿用配置解析的结果
+/// 候选按原样发往上游，逐个校验：
+/// - 空项或未知模型名返回 400
+/// - 不在客户端模型允许列表中返回 403 (请求头不能绕过允许列表)
+pub fn header_route_plan(
+    headers: &HeaderMap,
+    allow_header_overrides: bool,
+    allowed_client_models: &[String],
+    custom_mapping: &HashMap<String, String>,
+) -> Result<Option<ModelRoutePlan>, ProxyError> {
+    if !allow_header_overrides {
+        return Ok(None);
+    }
+    let Some(raw) = headers.get(MODEL_CANDIDATES_HEADER) else {
+        return Ok(None);
+    };
+    let raw = raw.to_str().map_err(|_| {
+        ProxyError::InvalidRequest("X-Model-Candidates must be a comma-separated list of model names".to_string())
+    })?;
+    if raw.split(',').any(|m| m.trim().is_empty()) {
+        return Err(ProxyError::InvalidRequest(format!(
+            "X-Model-Candidates contains an empty entry: '{}'",
+            raw
+        )));
+    }
+    let candidates = parse_candidates(raw);
+    for model in &candidates {
+        if !is_known_upstream_model(model, custom_mapping) {
+            return Err(ProxyError::InvalidRequest(format!(
+                "X-Model-Candidates contains unknown model '{}'",
+                model
+            )));
+        }
+        check_client_model_allowed(model, allowed_client_models)?;
+    }
+    let mut candidates = candidates.into_iter();
+    let Some(primary) = candidates.next() else {
+        return Ok(None);
+    };
+    let plan = ModelRoutePlan {
+        primary,
+        fallbacks: candidates.collect(),
+        policy: ModelFallbackPolicy::default(),
+        strategy_id: None,
+    };
+    tracing::info!("[Router] Using header-supplied model candidates: {:?}", plan.candidates());
+    Ok(Some(plan))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers_with(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(MODEL_CANDIDATES_HEADER, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_header_candidates_override_plan_in_order() {
+        let headers = headers_with(" gemini-3-pro-high, gemini-3-flash ,gemini-3-pro-high,claude-sonnet-4-5");
+        let plan = header_route_plan(&headers, true, &[], &HashMap::new()).unwrap().unwrap();
+        assert_eq!(plan.candidates(), vec!["gemini-3-pro-high", "gemini-3-flash", "claude-sonnet-4-5"]);
+        assert_eq!(plan.max_models(), 3);
+        assert!(plan.strategy_id.is_none());
+        // 默认策略按列表顺序依次尝试
+        assert!(!plan.is_capacity_first());
+    }
+
+    #[test]
+    fn test_header_ignored_when_disabled_or_empty() {
+        let custom = HashMap::new();
+        assert!(header_route_plan(&headers_with("gemini-3-flash"), false, &[], &custom).unwrap().is_none());
+        assert!(header_route_plan(&HeaderMap::new(), true, &[], &custom).unwrap().is_none());
+
+        let many: Vec<String> = (0..12).map(|i| format!("m{}", i)).collect();
+        assert_eq!(parse_candidates(&many.join(",")).len(), MAX_HEADER_CANDIDATES);
+    }
+
+    #[test]
+    fn test_header_candidates_respect_client_allowlist() {
+        let allowed = vec!["gemini-3-*".to_string()];
+        let headers = headers_with("gemini-3-flash,claude-opus-4-5-thinking");
+        let custom = HashMap::new();
+        let err = header_route_plan(&headers, true, &allowed, &custom).unwrap_err();
+        assert!(matches!(err, ProxyError::ModelNotAllowed(_)));
+        assert!(header_route_plan(&headers_with("gemini-3-flash,gemini-3-pro-high"), true, &allowed, &custom).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_header_candidates_reject_empty_and_unknown_models() {
+        let custom = HashMap::from([("my-alias".to_string(), "claude-opus-4-6".to_string())]);
+        for raw in [" , ", "gemini-3-flash,,gemini-3-pro-high", "gemini-3-flash,", "gemini-3-flash,gpt-nonexistent"] {
+            let err = header_route_plan(&headers_with(raw), true, &[], &custom).unwrap_err();
+            assert!(matches!(err, ProxyError::InvalidRequest(_)), "{}", raw);
+        }
+        // 内置模型、gemini- 直通模型与自定义映射的目标模型均视为已知
+        let plan = header_route_plan(&headers_with("claude-sonnet-4-5,gemini-2.5-pro,claude-opus-4-6"), true, &[], &custom)
+            .unwrap()
+            .unwrap();
+        assert_eq!(plan.max_models(), 3);
+        // 自定义映射的别名键不是上游模型名
+        assert!(header_route_plan(&headers_with("my-alias"), true, &[], &custom).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+// 策略候选模型的历史成功率统计
+// 供 `SelectionMode::AdaptiveSuccess` 在解析路由时按成功率重排候选；计数随时间指数衰减，
+// 使近期表现占主导，长时间未使用的候选逐渐回到中性评分
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// 计数衰减半衰期
+const DEFAULT_HALF_LIFE: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy)]
+struct Score {
+    success: f64,
+    failure: f64,
+    updated_at: Instant,
+}
+
+impl Score {
+    fn decayed(&self, now: Instant, half_life: Duration) -> (f64, f64) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        let factor = 0.5f64.powf(elapsed / half_life.as_secs_f64().max(f64::EPSILON));
+        (self.success * factor, self.failure * factor)
+    }
+}
+
+pub struct CandidateStats {
+    scores: DashMap<String, Score>,
+    half_life: Duration,
+}
+
+static GLOBAL: Lazy<CandidateStats> = Lazy::new(|| CandidateStats::new(DEFAULT_HALF_LIFE));
+
+impl CandidateStats {
+    pub fn new(half_life: Duration) -> Self {
+        Self {
+            scores: DashMap::new(),
+            half_life,
+        }
+    }
+
+    /// 进程内共享实例 (按候选模型名统计)
+    pub fn global() -> &'static CandidateStats {
+        &GLOBAL
+    }
+
+    /// 记录一次候选模型的最终结果
+    pub fn record(&self, model: &str, success: bool) {
+        let now = Instant::now();
+        let mut entry = self.scores.entry(model.to_string()).or_insert(Score {
+            success: 0.0,
+            failure: 0.0,
+            updated_at: now,
+        });
+        let (mut s, mut f) = entry.decayed(now, self.half_life);
+        if success {
+            s += 1.0;
+        } else {
+            f += 1.0;
+        }
+        *entry = Score {
+            success: s,
+            failure: f,
+            updated_at: now,
+        };
+    }
+
+    /// 平滑后的成功率 (无记录时为 0.5)
+    pub fn success_rate(&self, model: &str) -> f64 {
+        let (s, f) = self
+            .scores
+            .get(model)
+            .map(|score| score.decayed(Instant::now(), self.half_life))
+            .unwrap_or((0.0, 0.0));
+        (s + 1.0) / (s + f + 2.0)
+    }
+
+    /// 按成功率从高到低重排候选 (稳定排序，成功率相同时保持配置顺序)
+    pub fn order_by_success(&self, candidates: &mut [String]) {
+        let rates: HashMap<String, f64> = candidates
+            .iter()
+            .map(|c| (c.clone(), self.success_rate(c)))
+            .collect();
+        candidates.sort_by(|a, b| {
+            rates[b]
+                .partial_cmp(&rates[a])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+}
+
+/// 记录策略路由中候选模型的结果 (非策略路由忽略)
+pub fn record_outcome(strategy_id: Option<&str>, model: &str, success: bool) {
+    if strategy_id.is_some() {
+        CandidateStats::global().record(model, success);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_order_by_success_is_stable_without_history() {
+        let stats = CandidateStats::new(DEFAULT_HALF_LIFE);
+        let mut candidates = names(&["a", "b", "c"]);
+        stats.order_by_success(&mut candidates);
+        assert_eq!(candidates, names(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn test_failures_demote_candidate() {
+        let stats = CandidateStats::new(DEFAULT_HALF_LIFE);
+        stats.record("a", false);
+        stats.record("a", false);
+        stats.record("b", true);
+
+        assert!(stats.success_rate("a") < 0.5);
+        assert!(stats.success_rate("b") > 0.5);
+
+        let mut candidates = names(&["a", "b", "c"]);
+        stats.order_by_success(&mut candidates);
+        assert_eq!(candidates, names(&["b", "c", "a"]));
+    }
+
+    #[test]
+    fn test_counts_decay_over_time() {
+        let stats = CandidateStats::new(Duration::from_millis(20));
+        for _ in 0..10 {
+            stats.record("a", false);
+        }
+        let before = stats.success_rate("a");
+        std::thread::sleep(Duration::from_millis(200));
+        let after = stats.success_rate("a");
+        assert!(after > before);
+        assert!((after - 0.5).abs() < 0.05);
+    }
+}
@@ -1,41 +1,281 @@
 // 错误处理
+// 代理自身产生的错误使用稳定的错误码，便于客户端按 `code`/`type` 做程序化处理
 use thiserror::Error;
-use axum::{http::StatusCode, Json, response::IntoResponse};
+use axum::{http::{header, HeaderValue, StatusCode}, Json, response::{IntoResponse, Response}};
+use serde_json::{json, Value};
+
+use crate::proxy::upstream::errors::ErrorProtocol;
 
 #[derive(Debug, Error)]
 pub enum ProxyError {
-    #[error("Upstream API error: {0}")]
+    /// 没有可用账号 (账号池为空或全部不可用)
+    #[error("[no_accounts] {0}")]
+    NoAccounts(String),
+
+    /// 策略中的所有候选模型均已尝试失败
+    #[error("[all_fallbacks_failed] {0}")]
+    AllFallbacksFailed(String),
+
+    /// 上游不认识请求的模型
+    #[error("[model_unknown] {0}")]
+    ModelUnknown(String),
+
+    /// 单一模型下所有账号均被限流
+    #[error("[rate_limited] {0}")]
+    RateLimited(String),
+
+    #[error("[upstream_error] {0}")]
     UpstreamError(String),
 
-    #[error("Transform error: {0}")]
+    #[error("[transform_error] {0}")]
     TransformError(String),
 
-    #[error("Account error: {0}")]
-    AccountError(String),
+    #[error("[invalid_request] {0}")]
+    InvalidRequest(String),
 
-    #[error("Rate limit exceeded")]
-    RateLimitExceeded,
+    /// 流式响应超出大小/时长限制被中止
+    #[error("[stream_limit_exceeded] {0}")]
+    StreamLimitExceeded(String),
 
-    #[error("Invalid request: {0}")]
-    InvalidRequest(String),
+    /// 上游流在中途出错 (此前已输出的内容保留)
+    #[error("[stream_interrupted] {0}")]
+    StreamInterrupted(String),
+
+    /// 服务处于维护模式
+    #[error("[maintenance] {0}")]
+    Maintenance(String),
+
+    /// 请求的模型不在客户端允许列表中
+    #[error("[model_not_allowed] {0}")]
+    ModelNotAllowed(String),
+
+    /// 代理配置校验失败
+    #[error("[invalid_config] {0}")]
+    InvalidConfig(String),
+
+    /// 代理服务已在运行 / 尚未运行
+    #[error("[service_state] {0}")]
+    ServiceState(String),
+
+    /// 代理服务启动失败 (数据目录、账号加载、端口绑定等)
+    #[error("[startup_failed] {0}")]
+    StartupFailed(String),
 }
 
-impl IntoResponse for ProxyError {
-    fn into_response(self) -> axum::response::Response {
-        let status = match &self {
-            ProxyError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
-            ProxyError::RateLimitExceeded => StatusCode::TOO_MANY_REQUESTS,
-            ProxyError::AccountError(_) => StatusCode::UNAUTHORIZED,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        };
-
-        let body = serde_json::json!({
-            "error": {
-                "message": self.to_string(),
-                "type": format!("{:?}", self)
+impl ProxyError {
+    /// 稳定的错误码 (对外契约，不要随意修改)
+    pub fn code(&self) -> &'static str {
+        match self {
+            ProxyError::NoAccounts(_) => "no_accounts",
+            ProxyError::AllFallbacksFailed(_) => "all_fallbacks_failed",
+            ProxyError::ModelUnknown(_) => "model_unknown",
+            ProxyError::RateLimited(_) => "rate_limited",
+            ProxyError::UpstreamError(_) => "upstream_error",
+            ProxyError::TransformError(_) => "transform_error",
+            ProxyError::InvalidRequest(_) => "invalid_request",
+            ProxyError::StreamLimitExceeded(_) => "stream_limit_exceeded",
+            ProxyError::StreamInterrupted(_) => "stream_interrupted",
+            ProxyError::Maintenance(_) => "maintenance",
+            ProxyError::ModelNotAllowed(_) => "model_not_allowed",
+            ProxyError::InvalidConfig(_) => "invalid_config",
+            ProxyError::ServiceState(_) => "service_state",
+            ProxyError::StartupFailed(_) => "startup_failed",
+        }
+    }
+
+    /// 不带错误码前缀的原始信息
+    pub fn message(&self) -> &str {
+        match self {
+            ProxyError::NoAccounts(m)
+            | ProxyError::AllFallbacksFailed(m)
+            | ProxyError::ModelUnknown(m)
+            | ProxyError::RateLimited(m)
+            | ProxyError::UpstreamError(m)
+            | ProxyError::TransformError(m)
+            | ProxyError::InvalidRequest(m)
+            | ProxyError::StreamLimitExceeded(m)
+            | ProxyError::StreamInterrupted(m)
+            | ProxyError::Maintenance(m)
+            | ProxyError::ModelNotAllowed(m)
+            | ProxyError::InvalidConfig(m)
+            | ProxyError::ServiceState(m)
+            | ProxyError::StartupFailed(m) => m,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ProxyError::NoAccounts(_) | ProxyError::Maintenance(_) => StatusCode::SERVICE_UNAVAILABLE,
+            // 保持 429，客户端据此触发重试
+            ProxyError::AllFallbacksFailed(_) | ProxyError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ProxyError::ModelUnknown(_) => StatusCode::NOT_FOUND,
+            ProxyError::UpstreamError(_)
+            | ProxyError::StreamLimitExceeded(_)
+            | ProxyError::StreamInterrupted(_) => StatusCode::BAD_GATEWAY,
+            ProxyError::TransformError(_) | ProxyError::StartupFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ProxyError::InvalidRequest(_) | ProxyError::InvalidConfig(_) => StatusCode::BAD_REQUEST,
+            ProxyError::ServiceState(_) => StatusCode::CONFLICT,
+            ProxyError::ModelNotAllowed(_) => StatusCode::FORBIDDEN,
+        }
+    }
+
+    /// 重试耗尽时的错误：尝试过多个候选模型为 all_fallbacks_failed，否则为 rate_limited
+    pub fn exhausted(models_tried: usize, last_error: &str) -> Self {
+        if models_tried > 1 {
+            ProxyError::AllFallbacksFailed(format!(
+                "All {} fallback models failed. Last error: {}",
+                models_tried, last_error
+            ))
+        } else {
+            ProxyError::RateLimited(format!("All accounts exhausted. Last error: {}", last_error))
+        }
+    }
+
+    /// Anthropic 客户端依赖标准 `error.type` 判断是否重试，错误码放在 `error.code`
+    fn anthropic_type(&self) -> &'static str {
+        match self {
+            ProxyError::NoAccounts(_) | ProxyError::AllFallbacksFailed(_) | ProxyError::Maintenance(_) => "overloaded_error",
+            ProxyError::RateLimited(_) => "rate_limit_error",
+            ProxyError::ModelUnknown(_) => "not_found_error",
+            ProxyError::InvalidRequest(_) => "invalid_request_error",
+            ProxyError::ModelNotAllowed(_) => "permission_error",
+            _ => "api_error",
+        }
+    }
+
+    /// 渲染为协议对应的错误体
+    pub fn to_body(&self, protocol: ErrorProtocol) -> Value {
+        match protocol {
+            ErrorProtocol::OpenAI => json!({
+                "error": {
+                    "message": self.message(),
+                    "type": self.code(),
+                    "code": self.code()
+                }
+            }),
+            ErrorProtocol::Anthropic => json!({
+                "type": "error",
+                "error": {
+                    "type": self.anthropic_type(),
+                    "code": self.code(),
+                    "message": self.message()
+                }
+            }),
+            ErrorProtocol::Gemini => {
+                let status = self.status();
+                let reason = status
+                    .canonical_reason()
+                    .unwrap_or("UNKNOWN")
+                    .to_ascii_uppercase()
+                    .replace(' ', "_");
+                json!({
+                    "error": {
+                        "code": status.as_u16(),
+                        "message": self.message(),
+                        "status": reason,
+                        "details": [{
+                            "@type": "type.googleapis.com/google.rpc.ErrorInfo",
+                            "reason": self.code(),
+                            "domain": "antigravity-proxy"
+                        }]
+                    }
+                })
             }
-        });
+        }
+    }
+
+    pub fn into_protocol_response(self, protocol: ErrorProtocol) -> Response {
+        (self.status(), Json(self.to_body(protocol))).into_response()
+    }
+
+    /// 渲染错误响应；限流类错误附带 `Retry-After` (秒)，提示客户端何时重试
+    pub fn into_protocol_response_with_retry(self, protocol: ErrorProtocol, retry_after_secs: Option<u64>) -> Response {
+        let retryable = self.status() == StatusCode::TOO_MANY_REQUESTS;
+        let mut response = self.into_protocol_response(protocol);
+        if let (true, Some(secs)) = (retryable, retry_after_secs) {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
+        }
+        response
+    }
+}
+
+/// 上游 404 的响应体是否表明模型不存在
+/// 路径错误、项目/资源不存在等其他 404 不应标记为 model_unknown
+pub fn is_model_not_found(body: &str) -> bool {
+    let lower = body.to_ascii_lowercase();
+    lower.contains("model")
+        && ["not found", "not_found", "does not exist", "is not supported", "unknown model", "not available"]
+            .iter()
+            .any(|marker| lower.contains(marker))
+}
+
+impl IntoResponse for ProxyError {
+    fn into_response(self) -> Response {
+        self.into_protocol_response(ErrorProtocol::OpenAI)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exhaustion_codes() {
+        assert_eq!(ProxyError::exhausted(1, "429").code(), "rate_limited");
+        assert_eq!(ProxyError::exhausted(3, "429").code(), "all_fallbacks_failed");
+        assert_eq!(ProxyError::exhausted(3, "429").status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn test_code_rendered_per_protocol() {
+        let err = ProxyError::NoAccounts("pool empty".to_string());
+
+        let openai = err.to_body(ErrorProtocol::OpenAI);
+        assert_eq!(openai["error"]["code"], "no_accounts");
+        assert_eq!(openai["error"]["type"], "no_accounts");
+        assert_eq!(openai["error"]["message"], "pool empty");
+
+        let anthropic = err.to_body(ErrorProtocol::Anthropic);
+        assert_eq!(anthropic["error"]["type"], "overloaded_error");
+        assert_eq!(anthropic["error"]["code"], "no_accounts");
+
+        let gemini = ProxyError::ModelUnknown("nope".to_string()).to_body(ErrorProtocol::Gemini);
+        assert_eq!(gemini["error"]["code"], 404);
+        assert_eq!(gemini["error"]["status"], "NOT_FOUND");
+        assert_eq!(gemini["error"]["details"][0]["reason"], "model_unknown");
+    }
+
+    #[test]
+    fn test_retry_after_only_on_rate_limit_errors() {
+        let resp = ProxyError::exhausted(1, "429").into_protocol_response_with_retry(ErrorProtocol::OpenAI, Some(30));
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "30");
+
+        let resp = ProxyError::ModelUnknown("nope".to_string())
+            .into_protocol_response_with_retry(ErrorProtocol::OpenAI, Some(30));
+        assert!(resp.headers().get(header::RETRY_AFTER).is_none());
+
+        let resp = ProxyError::exhausted(1, "429").into_protocol_response_with_retry(ErrorProtocol::OpenAI, None);
+        assert!(resp.headers().get(header::RETRY_AFTER).is_none());
+    }
+
+    #[test]
+    fn test_model_not_found_requires_model_in_404_body() {
+        assert!(is_model_not_found(
+            r#"{"error":{"code":404,"message":"models/gemini-9 is not found for API version v1internal","status":"NOT_FOUND"}}"#
+        ));
+        assert!(is_model_not_found("Unknown model: claude-x"));
+        assert!(!is_model_not_found(
+            r#"{"error":{"code":404,"message":"Requested entity was not found.","status":"NOT_FOUND"}}"#
+        ));
+        assert!(!is_model_not_found("404 page not found"));
+    }
 
-        (status, Json(body)).into_response()
+    #[test]
+    fn test_display_carries_code() {
+        let err = ProxyError::NoAccounts("没有可用账号".to_string());
+        assert_eq!(err.to_string(), "[no_accounts] 没有可用账号");
+        assert_eq!(err.status(), StatusCode::SERVICE_UNAVAILABLE);
     }
 }
@@ -0,0 +1,98 @@
+// 透传上游的请求头过滤
+// 按 `HeaderForwardingConfig` 的白名单/黑名单筛选客户端请求头，避免把本地代理密钥或
+// 其他厂商专用请求头 (如 `OpenAI-Organization`) 带到上游
+
+use axum::http::HeaderMap;
+
+use crate::proxy::config::HeaderForwardingConfig;
+
+/// 名称匹配 (不区分大小写，`*` 结尾为前缀匹配)
+fn header_matches(name: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+pub fn is_forwarded(name: &str, config: &HeaderForwardingConfig) -> bool {
+    let name = name.to_ascii_lowercase();
+    if config.deny.iter().any(|p| header_matches(&name, p)) {
+        return false;
+    }
+    config.allow.iter().any(|p| header_matches(&name, p))
+}
+
+/// 返回允许透传的请求头副本
+pub fn filter_forward_headers(incoming: &HeaderMap, config: &HeaderForwardingConfig) -> HeaderMap {
+    let mut out = HeaderMap::new();
+    for (name, value) in incoming.iter() {
+        if is_forwarded(name.as_str(), config) {
+            out.append(name.clone(), value.clone());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    async fn spawn_echo_upstream() -> std::net::SocketAddr {
+        let app = axum::Router::new().route(
+            "/v1/messages",
+            axum::routing::post(|headers: HeaderMap| async move {
+                let names: Vec<String> = headers.keys().map(|k| k.as_str().to_string()).collect();
+                axum::Json(names)
+            }),
+        );
+        crate::proxy::tests::support::spawn_router(app).await
+    }
+
+    #[tokio::test]
+    async fn test_denied_headers_not_forwarded_to_upstream() {
+        let mut incoming = HeaderMap::new();
+        incoming.insert("content-type", HeaderValue::from_static("application/json"));
+        incoming.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
+        incoming.insert("openai-organization", HeaderValue::from_static("org-123"));
+        incoming.insert("x-api-key", HeaderValue::from_static("local-proxy-key"));
+        incoming.insert("x-route-tag", HeaderValue::from_static("blue"));
+        incoming.insert("x-debug", HeaderValue::from_static("1"));
+
+        let mut config = HeaderForwardingConfig::default();
+        config.allow.push("x-route-*".to_string());
+        let headers = filter_forward_headers(&incoming, &config);
+
+        let addr = spawn_echo_upstream().await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let received: Vec<String> = client
+            .post(format!("http://{}/v1/messages", addr))
+            .headers(headers)
+            .body("{}")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert!(received.contains(&"content-type".to_string()));
+        assert!(received.contains(&"anthropic-version".to_string()));
+        assert!(received.contains(&"x-route-tag".to_string()));
+        assert!(!received.contains(&"openai-organization".to_string()));
+        assert!(!received.contains(&"x-api-key".to_string()));
+        assert!(!received.contains(&"x-debug".to_string()));
+    }
+
+    #[test]
+    fn test_deny_takes_precedence_over_allow() {
+        let config = HeaderForwardingConfig {
+            allow: vec!["x-*".to_string()],
+            deny: vec!["X-Api-Key".to_string()],
+        };
+        assert!(is_forwarded("x-trace-id", &config));
+        assert!(!is_forwarded("X-API-KEY", &config));
+        assert!(!is_forwarded("content-type", &config));
+    }
+}
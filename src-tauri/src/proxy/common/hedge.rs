@@ -0,0 +1,143 @@
+// 对冲请求 (Hedged Request)
+// 主请求在延迟阈值内未返回时，再发起一次备用请求 (换号/换候选)，取先成功返回者，落败一方随 future drop 取消
+// 目前仅由 Gemini 原生处理器使用 (见 ProxyConfig::hedge_delay_ms)
+use std::future::Future;
+use std::time::Duration;
+
+/// 最终采用的请求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HedgeWinner {
+    Primary,
+    Hedge,
+}
+
+/// 将 `hedge_delay_ms` 配置转换为对冲延迟，0 或未设置表示关闭
+pub fn hedge_delay(hedge_delay_ms: Option<u64>) -> Option<Duration> {
+    hedge_delay_ms.filter(|ms| *ms > 0).map(Duration::from_millis)
+}
+
+/// 执行主请求，超过 `delay` 仍未返回时发起对冲请求
+///
+/// - 主请求在延迟内返回：直接采用，不会发起对冲 (避免浪费配额)
+/// - 对冲发起后：先返回且 `is_success` 为真的一方胜出，另一方被取消
+/// - 一方失败 (`Err` 或 `is_success` 为假，如上游 5xx) 时继续等待另一方；两方均失败时返回主请求的结果
+pub async fn race_with_hedge<T, E, P, H, HF, S>(
+    primary: P,
+    delay: Option<Duration>,
+    hedge: H,
+    is_success: S,
+) -> (Result<T, E>, HedgeWinner)
+where
+    P: Future<Output = Result<T, E>>,
+    H: FnOnce() -> HF,
+    HF: Future<Output = Result<T, E>>,
+    S: Fn(&T) -> bool,
+{
+    tokio::pin!(primary);
+
+    let Some(delay) = delay else {
+        return (primary.await, HedgeWinner::Primary);
+    };
+
+    tokio::select! {
+        result = &mut primary => return (result, HedgeWinner::Primary),
+        _ = tokio::time::sleep(delay) => {}
+    }
+
+    tracing::debug!("[Hedge] Primary request exceeded {:?}, starting hedged attempt", delay);
+    let hedge_fut = hedge();
+    tokio::pin!(hedge_fut);
+
+    tokio::select! {
+        result = &mut primary => match result {
+            Ok(value) if is_success(&value) => (Ok(value), HedgeWinner::Primary),
+            primary_result => {
+                tracing::debug!("[Hedge] Primary request failed, waiting for hedged attempt");
+                match hedge_fut.await {
+                    Ok(value) if is_success(&value) => (Ok(value), HedgeWinner::Hedge),
+                    _ => (primary_result, HedgeWinner::Primary),
+                }
+            }
+        },
+        result = &mut hedge_fut => match result {
+            Ok(value) if is_success(&value) => (Ok(value), HedgeWinner::Hedge),
+            _ => {
+                tracing::debug!("[Hedge] Hedged attempt failed, waiting for primary");
+                (primary.await, HedgeWinner::Primary)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    async fn respond_after(ms: u64, value: &'static str) -> Result<&'static str, String> {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        Ok(value)
+    }
+
+    #[tokio::test]
+    async fn test_slow_primary_is_beaten_by_hedge() {
+        let cancelled = Arc::new(AtomicBool::new(true));
+        let primary = {
+            let cancelled = cancelled.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                cancelled.store(false, Ordering::SeqCst);
+                Ok::<_, String>("primary")
+            }
+        };
+
+        let (result, winner) = race_with_hedge(primary, hedge_delay(Some(50)), || respond_after(10, "hedge"), |_| true).await;
+        assert_eq!(result.unwrap(), "hedge");
+        assert_eq!(winner, HedgeWinner::Hedge);
+
+        // 落败的主请求被取消，不会继续执行
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert!(cancelled.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_fast_primary_does_not_hedge() {
+        let hedged = Arc::new(AtomicBool::new(false));
+        let flag = hedged.clone();
+        let (result, winner) = race_with_hedge(respond_after(10, "primary"), hedge_delay(Some(200)), move || {
+            flag.store(true, Ordering::SeqCst);
+            respond_after(0, "hedge")
+        }, |_| true)
+        .await;
+
+        assert_eq!(result.unwrap(), "primary");
+        assert_eq!(winner, HedgeWinner::Primary);
+        assert!(!hedged.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_failed_primary_does_not_beat_in_flight_hedge() {
+        // 对冲发起后主请求先返回 5xx：不采用，等待对冲结果
+        let (result, winner) = race_with_hedge(respond_after(50, "503"), hedge_delay(Some(20)), || respond_after(100, "200"), |v| *v == "200").await;
+        assert_eq!(result.unwrap(), "200");
+        assert_eq!(winner, HedgeWinner::Hedge);
+
+        // 两方都失败时返回主请求的结果
+        let (result, winner) = race_with_hedge(respond_after(50, "503"), hedge_delay(Some(20)), || respond_after(100, "500"), |v| *v == "200").await;
+        assert_eq!(result.unwrap(), "503");
+        assert_eq!(winner, HedgeWinner::Primary);
+    }
+
+    #[tokio::test]
+    async fn test_failed_hedge_falls_back_to_primary() {
+        let (result, winner) = race_with_hedge(respond_after(100, "primary"), hedge_delay(Some(20)), || async {
+            Err::<&'static str, String>("no account".to_string())
+        }, |_| true)
+        .await;
+
+        assert_eq!(result.unwrap(), "primary");
+        assert_eq!(winner, HedgeWinner::Primary);
+        assert_eq!(hedge_delay(Some(0)), None);
+    }
+}
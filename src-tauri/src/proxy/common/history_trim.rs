@@ -0,0 +1,219 @@
+// 历史消息自动裁剪
+// 客户端发送的对话历史超出目标模型上下文窗口时上游会直接拒绝；开启后按估算的 token 数从最早的消息开始丢弃直到不超过窗口
+// 在协议转换之后作用于 Gemini 请求体：系统提示位于 systemInstruction 不受影响，最近的若干条消息始终保留
+use serde_json::Value;
+
+use crate::proxy::common::token_estimate::{estimate_gemini_content_tokens, estimate_gemini_request_tokens};
+use crate::proxy::config::AutoTrimHistoryConfig;
+
+/// 一次裁剪的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrimReport {
+    pub dropped_messages: usize,
+    pub tokens_before: u64,
+    pub tokens_after: u64,
+    /// 扣除输出预留后的输入预算
+    pub budget: u64,
+}
+
+/// 查找模型的上下文窗口：精确匹配优先，其次为最长的通配规则
+pub fn context_window_for(model: &str, config: &AutoTrimHistoryConfig) -> Option<u64> {
+    if let Some((_, window)) = config.context_windows.iter().find(|(pattern, _)| pattern.eq_ignore_ascii_case(model)) {
+        return Some(*window);
+    }
+    let model = model.to_lowercase();
+    config
+        .context_windows
+        .iter()
+        .filter(|(pattern, _)| {
+            pattern.contains('*')
+                && crate::proxy::common::model_mapping::wildcard_match(&pattern.to_lowercase(), &model)
+        })
+        .max_by_key(|(pattern, _)| pattern.len())
+        .map(|(_, window)| *window)
+}
+
+/// 裁剪后的对话必须以用户消息开始，且不能以缺少对应 functionCall 的 functionResponse 开始
+fn is_turn_start(content: &Value) -> bool {
+    content.get("role").and_then(Value::as_str) == Some("user")
+        && !content
+            .get("parts")
+            .and_then(Value::as_array)
+            .is_some_and(|parts| parts.iter().any(|p| p.get("functionResponse").is_some()))
+}
+
+/// 对 Gemini 请求 (`request` 层) 应用历史裁剪，发生裁剪时返回裁剪结果
+///
+/// 输入预算为上下文窗口减去 `generationConfig.maxOutputTokens`；即使丢弃所有可丢弃的消息仍超出时，
+/// 按最大程度裁剪并交由上游处理。
+pub fn trim_history(request: &mut Value, model: &str, config: &AutoTrimHistoryConfig) -> Option<TrimReport> {
+    if !config.enabled {
+        return None;
+    }
+    let window = context_window_for(model, config)?;
+    let reserved_output = request
+        .pointer("/generationConfig/maxOutputTokens")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    let budget = window.saturating_sub(reserved_output);
+
+    let tokens_before = estimate_gemini_request_tokens(request);
+    if tokens_before <= budget {
+        return None;
+    }
+
+    let contents = request.get_mut("contents")?.as_array_mut()?;
+    let protected_from = contents.len().saturating_sub(config.keep_recent_messages);
+    let costs: Vec<u64> = contents.iter().map(estimate_gemini_content_tokens).collect();
+
+    // 选择最小的合法切分点；都不满足预算时取最大的合法切分点
+    let mut cut = 0;
+    let mut remaining = tokens_before;
+    for index in 1..=protected_from {
+        remaining -= costs[index - 1];
+        if index < contents.len() && is_turn_start(&contents[index]) {
+            cut = index;
+            if remaining <= budget {
+                break;
+            }
+        }
+    }
+    if cut == 0 {
+        tracing::warn!(
+            "[HistoryTrim] {}: ~{} tokens exceeds budget {} but no message can be dropped",
+            model,
+            tokens_before,
+            budget
+        );
+        return None;
+    }
+
+    contents.drain(..cut);
+    let tokens_after = estimate_gemini_request_tokens(request);
+    if tokens_after > budget {
+        tracing::warn!(
+            "[HistoryTrim] {}: still ~{} tokens after trimming (budget {})",
+            model,
+            tokens_after,
+            budget
+        );
+    }
+    tracing::info!(
+        "[HistoryTrim] {}: dropped {} oldest message(s), ~{} -> ~{} tokens (budget {})",
+        model,
+        cut,
+        tokens_before,
+        tokens_after,
+        budget
+    );
+    Some(TrimReport {
+        dropped_messages: cut,
+        tokens_before,
+        tokens_after,
+        budget,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(role: &str, text: &str) -> Value {
+        json!({ "role": role, "parts": [{ "text": text }] })
+    }
+
+    fn config(window: u64) -> AutoTrimHistoryConfig {
+        AutoTrimHistoryConfig {
+            enabled: true,
+            context_windows: [("gemini-3-flash".to_string(), window)].into_iter().collect(),
+            keep_recent_messages: 2,
+        }
+    }
+
+    #[test]
+    fn test_oversized_history_trimmed_to_window_keeping_system() {
+        // 每条旧消息约 257 token (约 1010 个 ASCII 字符 + 结构开销)
+        let filler = "x".repeat(1000);
+        let mut contents = Vec::new();
+        for i in 0..10 {
+            contents.push(message("user", &format!("{} question {}", filler, i)));
+            contents.push(message("model", &format!("{} answer {}", filler, i)));
+        }
+        contents.push(message("user", "latest question"));
+        let mut request = json!({
+            "systemInstruction": { "parts": [{ "text": "You are a coding agent." }] },
+            "contents": contents,
+            "generationConfig": { "maxOutputTokens": 1000 }
+        });
+        let config = config(3000);
+
+        let report = trim_history(&mut request, "gemini-3-flash", &config).unwrap();
+        assert!(report.tokens_before > 5000);
+        assert_eq!(report.budget, 2000);
+        assert!(report.tokens_after <= report.budget);
+        assert_eq!(report.tokens_after, estimate_gemini_request_tokens(&request));
+
+        let contents = request["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 21 - report.dropped_messages);
+        // 系统提示与最近的消息保留，且裁剪后以用户消息开始
+        assert_eq!(request["systemInstruction"]["parts"][0]["text"], "You are a coding agent.");
+        assert_eq!(contents.last().unwrap()["parts"][0]["text"], "latest question");
+        assert_eq!(contents[0]["role"], "user");
+        // 只丢弃到满足预算为止
+        assert!(report.tokens_after + 2 * 257 > report.budget);
+    }
+
+    #[test]
+    fn test_trim_keeps_tool_response_with_its_call() {
+        let filler = "x".repeat(4000);
+        let mut request = json!({
+            "contents": [
+                message("user", &filler),
+                { "role": "model", "parts": [{ "functionCall": { "name": "read_file", "args": { "path": "a.rs" } } }] },
+                { "role": "user", "parts": [{ "functionResponse": { "name": "read_file", "response": { "content": filler } } }] },
+                message("model", "done"),
+                message("user", "next"),
+                message("model", "ok"),
+                message("user", "last"),
+            ]
+        });
+
+        let report = trim_history(&mut request, "gemini-3-flash", &config(1200)).unwrap();
+        // 不会以孤立的 functionResponse 开始：跳过整个工具调用往返
+        assert_eq!(report.dropped_messages, 4);
+        assert_eq!(request["contents"][0]["parts"][0]["text"], "next");
+    }
+
+    #[test]
+    fn test_disabled_unknown_model_or_within_window_untouched() {
+        let mut request = json!({ "contents": [message("user", &"x".repeat(8000)), message("model", "a"), message("user", "b")] });
+        let original = request.clone();
+
+        let mut disabled = config(100);
+        disabled.enabled = false;
+        assert!(trim_history(&mut request, "gemini-3-flash", &disabled).is_none());
+        assert!(trim_history(&mut request, "claude-sonnet-4-5", &config(100)).is_none());
+        assert!(trim_history(&mut request, "gemini-3-flash", &config(100_000)).is_none());
+        assert_eq!(request, original);
+    }
+
+    #[test]
+    fn test_context_window_prefers_exact_then_longest_pattern() {
+        let config = AutoTrimHistoryConfig {
+            enabled: true,
+            context_windows: [
+                ("gemini-*".to_string(), 1_000_000),
+                ("gemini-3-*".to_string(), 500_000),
+                ("gemini-3-flash".to_string(), 200_000),
+            ]
+            .into_iter()
+            .collect(),
+            keep_recent_messages: 2,
+        };
+        assert_eq!(context_window_for("gemini-3-flash", &config), Some(200_000));
+        assert_eq!(context_window_for("gemini-3-pro-high", &config), Some(500_000));
+        assert_eq!(context_window_for("gemini-2.5-flash", &config), Some(1_000_000));
+        assert_eq!(context_window_for("claude-sonnet-4-5", &config), None);
+    }
+}
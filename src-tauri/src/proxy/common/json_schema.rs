@@ -1,4 +1,53 @@
 use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// 展开 $ref 后 schema 的默认体积上限 (序列化字节数)
+pub const DEFAULT_INLINE_SIZE_LIMIT: usize = 64 * 1024;
+
+/// 待迁移的约束黑名单 (字段名, 描述中的标签)：移除后以 Hint 形式追加到 description
+pub(crate) const VALIDATION_FIELDS: &[(&str, &str)] = &[
+    ("pattern", "pattern"),
+    ("minLength", "minLen"),
+    ("maxLength", "maxLen"),
+    ("minimum", "min"),
+    ("maximum", "max"),
+    ("minItems", "minItems"),
+    ("maxItems", "maxItems"),
+    ("exclusiveMinimum", "exclMin"),
+    ("exclusiveMaximum", "exclMax"),
+    ("multipleOf", "multipleOf"),
+    ("format", "format"),
+];
+
+/// 彻底物理移除的"硬项"黑色名单 (Hard Blacklist)
+pub(crate) const HARD_REMOVE_FIELDS: &[&str] = &[
+    "$schema",
+    "$id", // [NEW] JSON Schema identifier
+    "additionalProperties",
+    "enumCaseInsensitive",
+    "enumNormalizeWhitespace",
+    "uniqueItems",
+    "default",
+    "const",
+    "examples",
+    "propertyNames",
+    "anyOf",
+    "oneOf",
+    "allOf",
+    "not",
+    "if",
+    "then",
+    "else",
+    "dependencies",
+    "dependentSchemas",
+    "dependentRequired",
+    "cache_control",
+    "contentEncoding",  // [NEW] base64 encoding hint
+    "contentMediaType", // [NEW] MIME type hint
+    "deprecated",       // [NEW] Gemini doesn't understand this
+    "readOnly",         // [NEW]
+    "writeOnly",        // [NEW]
+];
 
 /// 递归清理 JSON Schema 以符合 Gemini 接口要求
 ///
@@ -9,6 +58,13 @@ use serde_json::Value;
 /// 5. 将 type 字段的值转换为小写 (Gemini v1internal 要求)
 /// 6. 移除数字校验字段: multipleOf, exclusiveMinimum, exclusiveMaximum 等
 pub fn clean_json_schema(value: &mut Value) {
+    clean_json_schema_with_limit(value, DEFAULT_INLINE_SIZE_LIMIT);
+}
+
+/// 同 `clean_json_schema`，显式指定 $ref 展开后的体积上限 (`ProxyConfig.schema_inline_max_bytes`，0 表示不限制)
+/// 预估完全展开会超限 (或存在循环引用) 时改为去重展开：每个定义只在首次出现处完整展开，
+/// 其余引用降级为仅保留 type 与说明的摘要；仍超限则按展开体积从大到小将定义整体降级为摘要
+pub fn clean_json_schema_with_limit(value: &mut Value, inline_limit: usize) {
     // 0. 预处理：展开 $ref (Schema Flattening)
     if let Value::Object(map) = value {
         let mut defs = serde_json::Map::new();
@@ -21,8 +77,15 @@ pub fn clean_json_schema(value: &mut Value) {
         }
 
         if !defs.is_empty() {
-            // 递归替换引用
-            flatten_refs(map, &defs);
+            let mut def_sizes = HashMap::new();
+            let estimated = estimate_map_size(map, &defs, &mut def_sizes, &mut Vec::new());
+            match estimated {
+                Some(size) if inline_limit == 0 || size <= inline_limit => {
+                    // 递归替换引用
+                    flatten_refs(map, &defs);
+                }
+                _ => flatten_refs_bounded(map, &defs, &def_sizes, inline_limit),
+            }
         }
     }
 
@@ -30,7 +93,72 @@ pub fn clean_json_schema(value: &mut Value) {
     clean_json_schema_recursive(value);
 }
 
+/// 严格模式保留的 Schema 关键字白名单 (Gemini functionDeclarations 确定接受的子集)
+pub(crate) const STRICT_SCHEMA_KEYWORDS: &[&str] = &[
+    "type",
+    "description",
+    "properties",
+    "required",
+    "items",
+    "enum",
+    "nullable",
+];
+
+/// 严格模式清洗：先执行常规清洗，再按白名单移除其余所有关键字
+/// 常规清洗基于黑名单，未知关键字 (如 title、x-*、patternProperties) 会原样保留；
+/// 上游因此返回 Schema 类 400 时以此模式重新清洗后重试
+pub fn clean_json_schema_strict(value: &mut Value) {
+    clean_json_schema(value);
+    retain_strict_keywords(value);
+}
+
+fn retain_strict_keywords(value: &mut Value) {
+    let Value::Object(map) = value else {
+        return;
+    };
+    map.retain(|key, _| STRICT_SCHEMA_KEYWORDS.contains(&key.as_str()));
+    if map.get("description").is_some_and(|d| !d.is_string()) {
+        map.remove("description");
+    }
+    // properties 的 key 是属性名而非关键字，只递归处理其值
+    if let Some(Value::Object(props)) = map.get_mut("properties") {
+        for prop in props.values_mut() {
+            retain_strict_keywords(prop);
+        }
+    }
+    match map.get_mut("items") {
+        Some(Value::Array(items)) => items.iter_mut().for_each(retain_strict_keywords),
+        Some(items) => retain_strict_keywords(items),
+        None => {}
+    }
+}
+
 /// 递归展开 $ref
+/// 将 $ref 定义中的字段合并到当前节点
+/// - `properties`: 深度合并，同名属性以节点内联定义为准
+/// - `required`: 取并集 (保持原有顺序)
+/// - 其他字段: 仅当当前节点没有该 key 时才插入 (避免覆盖)
+fn merge_ref_field(map: &mut serde_json::Map<String, Value>, key: &str, value: &Value) {
+    match (key, map.get_mut(key), value) {
+        ("properties", Some(Value::Object(existing)), Value::Object(incoming)) => {
+            for (name, schema) in incoming {
+                existing.entry(name.clone()).or_insert_with(|| schema.clone());
+            }
+        }
+        ("required", Some(Value::Array(existing)), Value::Array(incoming)) => {
+            for item in incoming {
+                if !existing.contains(item) {
+                    existing.push(item.clone());
+                }
+            }
+        }
+        (_, Some(_), _) => {}
+        (_, None, _) => {
+            map.insert(key.to_string(), value.clone());
+        }
+    }
+}
+
 fn flatten_refs(map: &mut serde_json::Map<String, Value>, defs: &serde_json::Map<String, Value>) {
     // 检查并替换 $ref
     if let Some(Value::String(ref_path)) = map.remove("$ref") {
@@ -41,9 +169,7 @@ fn flatten_refs(map: &mut serde_json::Map<String, Value>, defs: &serde_json::Map
             // 将定义的内容合并到当前 map
             if let Value::Object(def_map) = def_schema {
                 for (k, v) in def_map {
-                    // 仅当当前 map 没有该 key 时才插入 (避免覆盖)
-                    // 但通常 $ref 节点不应该有其他属性
-                    map.entry(k.clone()).or_insert_with(|| v.clone());
+                    merge_ref_field(map, k, v);
                 }
 
                 // 递归处理刚刚合并进来的内容中可能包含的 $ref
@@ -67,6 +193,166 @@ fn flatten_refs(map: &mut serde_json::Map<String, Value>, defs: &serde_json::Map
     }
 }
 
+/// 预估完全展开后的序列化体积，存在循环引用时返回 None
+fn estimate_inlined_size(
+    value: &Value,
+    defs: &serde_json::Map<String, Value>,
+    def_sizes: &mut HashMap<String, Option<usize>>,
+    stack: &mut Vec<String>,
+) -> Option<usize> {
+    match value {
+        Value::Object(map) => estimate_map_size(map, defs, def_sizes, stack),
+        Value::Array(arr) => {
+            let mut size = 2usize;
+            for item in arr {
+                size = size.saturating_add(1).saturating_add(estimate_inlined_size(item, defs, def_sizes, stack)?);
+            }
+            Some(size)
+        }
+        other => Some(other.to_string().len()),
+    }
+}
+
+fn estimate_map_size(
+    map: &serde_json::Map<String, Value>,
+    defs: &serde_json::Map<String, Value>,
+    def_sizes: &mut HashMap<String, Option<usize>>,
+    stack: &mut Vec<String>,
+) -> Option<usize> {
+    let mut size = 2usize;
+    for (k, v) in map {
+        if let ("$ref", Value::String(ref_path)) = (k.as_str(), v) {
+            let ref_name = ref_path.split('/').next_back().unwrap_or(ref_path);
+            if defs.contains_key(ref_name) {
+                size = size.saturating_add(estimate_def_size(ref_name, defs, def_sizes, stack)?);
+                continue;
+            }
+        }
+        size = size
+            .saturating_add(k.len() + 4)
+            .saturating_add(estimate_inlined_size(v, defs, def_sizes, stack)?);
+    }
+    Some(size)
+}
+
+fn estimate_def_size(
+    name: &str,
+    defs: &serde_json::Map<String, Value>,
+    def_sizes: &mut HashMap<String, Option<usize>>,
+    stack: &mut Vec<String>,
+) -> Option<usize> {
+    if let Some(size) = def_sizes.get(name) {
+        return *size;
+    }
+    // 循环引用无法完全展开
+    if stack.iter().any(|n| n == name) {
+        return None;
+    }
+    stack.push(name.to_string());
+    let size = estimate_inlined_size(&defs[name], defs, def_sizes, stack);
+    stack.pop();
+    def_sizes.insert(name.to_string(), size);
+    size
+}
+
+/// 超限时的展开：先去重，仍超限则按展开体积从大到小将定义整体降级为摘要
+fn flatten_refs_bounded(
+    map: &mut serde_json::Map<String, Value>,
+    defs: &serde_json::Map<String, Value>,
+    def_sizes: &HashMap<String, Option<usize>>,
+    inline_limit: usize,
+) {
+    let original = map.clone();
+    let mut ranked: Vec<(&String, usize)> = defs
+        .keys()
+        .map(|name| (name, def_sizes.get(name).copied().flatten().unwrap_or(usize::MAX)))
+        .collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    let mut ranked = ranked.into_iter();
+
+    let mut summarized = HashSet::new();
+    loop {
+        let mut seen = HashSet::new();
+        flatten_refs_deduped(map, defs, &summarized, &mut seen);
+        let size = serde_json::to_vec(&*map).map(|v| v.len()).unwrap_or(0);
+        if inline_limit == 0 || size <= inline_limit {
+            break;
+        }
+        match ranked.next() {
+            Some((name, _)) => {
+                summarized.insert(name.clone());
+                *map = original.clone();
+            }
+            None => break,
+        }
+    }
+    tracing::debug!(
+        "[JsonSchema] $ref 无法在 {} 字节上限内完全展开 (或存在循环引用)，已去重展开 (降级为摘要的定义: {:?})",
+        inline_limit,
+        summarized
+    );
+}
+
+/// 去重展开 $ref：每个定义仅在首次出现处完整展开 (`summarized` 中的定义始终为摘要)
+fn flatten_refs_deduped(
+    map: &mut serde_json::Map<String, Value>,
+    defs: &serde_json::Map<String, Value>,
+    summarized: &HashSet<String>,
+    seen: &mut HashSet<String>,
+) {
+    if let Some(Value::String(ref_path)) = map.remove("$ref") {
+        let ref_name = ref_path.split('/').next_back().unwrap_or(&ref_path).to_string();
+
+        if let Some(Value::Object(def_map)) = defs.get(&ref_name) {
+            // 先登记再展开，定义内部的自引用会落到摘要分支，不会无限递归
+            if summarized.contains(&ref_name) || !seen.insert(ref_name.clone()) {
+                insert_ref_summary(map, &ref_name, def_map);
+            } else {
+                for (k, v) in def_map {
+                    merge_ref_field(map, k, v);
+                }
+                flatten_refs_deduped(map, defs, summarized, seen);
+            }
+        }
+    }
+
+    for (_, v) in map.iter_mut() {
+        if let Value::Object(child_map) = v {
+            flatten_refs_deduped(child_map, defs, summarized, seen);
+        } else if let Value::Array(arr) = v {
+            for item in arr {
+                if let Value::Object(item_map) = item {
+                    flatten_refs_deduped(item_map, defs, summarized, seen);
+                }
+            }
+        }
+    }
+}
+
+/// 引用摘要：仅保留定义的 type，并在描述中注明结构与该定义相同
+fn insert_ref_summary(map: &mut serde_json::Map<String, Value>, name: &str, def_map: &serde_json::Map<String, Value>) {
+    let def_type = def_map
+        .get("type")
+        .cloned()
+        .unwrap_or_else(|| Value::String("object".to_string()));
+    map.entry("type".to_string()).or_insert(def_type);
+
+    let note = format!("[Same structure as '{}']", name);
+    match map.get_mut("description") {
+        Some(Value::String(s)) => {
+            s.push(' ');
+            s.push_str(&note);
+        }
+        _ => {
+            let desc = match def_map.get("description").and_then(Value::as_str) {
+                Some(d) => format!("{} {}", d, note),
+                None => note,
+            };
+            map.insert("description".to_string(), Value::String(desc));
+        }
+    }
+}
+
 fn clean_json_schema_recursive(value: &mut Value) {
     match value {
         Value::Object(map) => {
@@ -79,22 +365,7 @@ fn clean_json_schema_recursive(value: &mut Value) {
             // 2. 收集并处理校验字段 (Migration logic: 将约束降级为描述中的 Hint)
             let mut constraints = Vec::new();
 
-            // 待迁移的约束黑名单
-            let validation_fields = [
-                ("pattern", "pattern"),
-                ("minLength", "minLen"),
-                ("maxLength", "maxLen"),
-                ("minimum", "min"),
-                ("maximum", "max"),
-                ("minItems", "minItems"),
-                ("maxItems", "maxItems"),
-                ("exclusiveMinimum", "exclMin"),
-                ("exclusiveMaximum", "exclMax"),
-                ("multipleOf", "multipleOf"),
-                ("format", "format"),
-            ];
-
-            for (field, label) in validation_fields {
+            for &(field, label) in VALIDATION_FIELDS {
                 if let Some(val) = map.remove(field) {
                     // 仅当值是简单类型时才迁移
                     if val.is_string() || val.is_number() || val.is_boolean() {
@@ -146,35 +417,7 @@ fn clean_json_schema_recursive(value: &mut Value) {
             }
 
             // 5. 彻底物理移除干扰生成的"硬项"黑色名单 (Hard Blacklist)
-            let hard_remove_fields = [
-                "$schema",
-                "$id", // [NEW] JSON Schema identifier
-                "additionalProperties",
-                "enumCaseInsensitive",
-                "enumNormalizeWhitespace",
-                "uniqueItems",
-                "default",
-                "const",
-                "examples",
-                "propertyNames",
-                "anyOf",
-                "oneOf",
-                "allOf",
-                "not",
-                "if",
-                "then",
-                "else",
-                "dependencies",
-                "dependentSchemas",
-                "dependentRequired",
-                "cache_control",
-                "contentEncoding",  // [NEW] base64 encoding hint
-                "contentMediaType", // [NEW] MIME type hint
-                "deprecated",       // [NEW] Gemini doesn't understand this
-                "readOnly",         // [NEW]
-                "writeOnly",        // [NEW]
-            ];
-            for field in hard_remove_fields {
+            for &field in HARD_REMOVE_FIELDS {
                 map.remove(field);
             }
 
@@ -386,6 +629,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_flatten_refs_merges_inline_properties() {
+        let mut schema = json!({
+            "$defs": {
+                "Named": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "age": { "type": "integer", "description": "from ref" }
+                    },
+                    "required": ["name"]
+                }
+            },
+            "properties": {
+                "person": {
+                    "$ref": "#/$defs/Named",
+                    "properties": {
+                        "age": { "type": "number", "description": "inline" }
+                    },
+                    "required": ["age"]
+                }
+            }
+        });
+
+        clean_json_schema(&mut schema);
+
+        let person = &schema["properties"]["person"];
+        // 内联属性与引用属性同时保留，同名时以内联为准
+        assert_eq!(person["properties"]["name"]["type"], "string");
+        assert_eq!(person["properties"]["age"]["type"], "number");
+        assert_eq!(person["properties"]["age"]["description"], "inline");
+        assert_eq!(person["required"], json!(["age", "name"]));
+    }
+
     #[test]
     fn test_clean_json_schema_missing_required() {
         let mut schema = json!({
@@ -489,4 +766,118 @@ mod tests {
         assert_eq!(schema["properties"]["name"]["type"], "string");
         assert!(schema["properties"]["name"].get("anyOf").is_none());
     }
+
+    #[test]
+    fn test_flatten_refs_bounded_for_heavily_referenced_defs() {
+        let mut item_props = serde_json::Map::new();
+        for i in 0..30 {
+            item_props.insert(
+                format!("field_{}", i),
+                json!({"type": "string", "description": "A reasonably long description for this field"}),
+            );
+        }
+        item_props.insert("left".to_string(), json!({"$ref": "#/$defs/Leaf"}));
+        item_props.insert("right".to_string(), json!({"$ref": "#/$defs/Leaf"}));
+
+        let mut root_props = serde_json::Map::new();
+        for i in 0..40 {
+            root_props.insert(format!("item_{}", i), json!({"$ref": "#/$defs/Item"}));
+        }
+        let mut schema = json!({
+            "$defs": {
+                "Item": {"type": "object", "properties": item_props},
+                "Leaf": {"type": "object", "properties": {"value": {"type": "integer"}}}
+            },
+            "type": "object",
+            "properties": root_props
+        });
+
+        let limit = 8 * 1024;
+        clean_json_schema_with_limit(&mut schema, limit);
+
+        // 完全展开约 100KB，去重后应控制在上限内
+        assert!(serde_json::to_vec(&schema).unwrap().len() <= limit);
+        assert!(schema.get("$defs").is_none());
+
+        let items: Vec<&Value> = schema["properties"].as_object().unwrap().values().collect();
+        assert_eq!(items.len(), 40);
+        let expanded: Vec<&&Value> = items.iter().filter(|v| v.get("properties").is_some()).collect();
+        // 仅首次出现处完整展开，其余为摘要
+        assert_eq!(expanded.len(), 1);
+        assert_eq!(expanded[0]["properties"]["field_0"]["type"], "string");
+        for item in items.iter().filter(|v| v.get("properties").is_none()) {
+            assert_eq!(item["type"], "object");
+            assert!(item["description"].as_str().unwrap().contains("Same structure as 'Item'"));
+        }
+    }
+
+    #[test]
+    fn test_flatten_refs_within_limit_fully_inlined() {
+        let mut schema = json!({
+            "$defs": {"Leaf": {"type": "object", "properties": {"value": {"type": "integer"}}}},
+            "properties": {
+                "a": {"$ref": "#/$defs/Leaf"},
+                "b": {"$ref": "#/$defs/Leaf"}
+            }
+        });
+
+        clean_json_schema_with_limit(&mut schema, 8 * 1024);
+
+        assert_eq!(schema["properties"]["a"]["properties"]["value"]["type"], "integer");
+        assert_eq!(schema["properties"]["b"]["properties"]["value"]["type"], "integer");
+    }
+
+    #[test]
+    fn test_flatten_refs_recursive_definition_terminates() {
+        let mut schema = json!({
+            "$defs": {
+                "Node": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string"},
+                        "children": {"type": "array", "items": {"$ref": "#/$defs/Node"}}
+                    }
+                }
+            },
+            "properties": {"root": {"$ref": "#/$defs/Node"}}
+        });
+
+        // 循环引用即使不限制体积也走去重展开
+        clean_json_schema_with_limit(&mut schema, 0);
+
+        let root = &schema["properties"]["root"];
+        assert_eq!(root["properties"]["name"]["type"], "string");
+        let child = &root["properties"]["children"]["items"];
+        assert_eq!(child["type"], "object");
+        assert!(child["description"].as_str().unwrap().contains("Same structure as 'Node'"));
+    }
+
+    #[test]
+    fn test_strict_mode_keeps_only_whitelisted_keywords() {
+        let mut schema = json!({
+            "type": "object",
+            "title": "Args",
+            "x-internal": true,
+            "patternProperties": {"^x-": {"type": "string"}},
+            "properties": {
+                // 属性名与关键字同名时不受影响
+                "title": {"type": "string", "title": "Title", "minLength": 1},
+                "tags": {"type": "array", "items": {"type": "string", "title": "Tag"}}
+            },
+            "required": ["title"]
+        });
+        clean_json_schema_strict(&mut schema);
+
+        assert_eq!(
+            schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "title": {"type": "string", "description": " [Constraint: minLen: 1]"},
+                    "tags": {"type": "array", "items": {"type": "string"}}
+                },
+                "required": ["title"]
+            })
+        );
+    }
 }
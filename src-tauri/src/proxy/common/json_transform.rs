@@ -0,0 +1,347 @@
+// 模型级请求/响应 JSON 变换
+// 按路由后的模型名匹配 `model_transforms` 配置，对转发给上游的 Gemini 请求体
+// 以及上游返回的 Gemini 响应 (含流式 SSE 分片) 执行声明式的 set/remove/default 操作
+use std::collections::HashMap;
+use std::pin::Pin;
+
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+
+use crate::proxy::config::{JsonTransformOp, ModelTransform};
+
+/// 解析点分路径，数字段在数组上表示下标 (如 `contents.0.parts`)
+fn parse_path(path: &str) -> Result<Vec<&str>, String> {
+    let trimmed = path.trim();
+    if trimmed.is_empty() {
+        return Err("路径不能为空".to_string());
+    }
+    let segments: Vec<&str> = trimmed.split('.').collect();
+    if segments.iter().any(|s| s.is_empty()) {
+        return Err(format!("非法路径 '{}': 存在空字段", path));
+    }
+    Ok(segments)
+}
+
+/// 加载时校验变换配置
+pub fn validate_transforms(transforms: &HashMap<String, ModelTransform>) -> Result<(), String> {
+    for (pattern, transform) in transforms {
+        if pattern.trim().is_empty() {
+            return Err("model_transforms 的模型匹配规则不能为空".to_string());
+        }
+        let ops = transform.request.iter().map(|op| ("request", op));
+        let ops = ops.chain(transform.response.iter().map(|op| ("response", op)));
+        for (stage, op) in ops {
+            parse_path(op.path())
+                .map_err(|e| format!("model_transforms['{}'].{}: {}", pattern, stage, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// 查找模型对应的变换配置 (精确匹配优先，其次最具体的通配符)
+pub fn resolve_transform<'a>(
+    model: &str,
+    transforms: &'a HashMap<String, ModelTransform>,
+) -> Option<&'a ModelTransform> {
+    crate::proxy::mappers::common_utils::resolve_model_entry(model, transforms)
+}
+
+/// 定位路径的父节点；`create` 为 true 时按需创建中间对象
+fn parent_mut<'a>(root: &'a mut Value, parents: &[&str], create: bool) -> Option<&'a mut Value> {
+    let mut current = root;
+    for seg in parents {
+        if create && current.is_null() {
+            *current = Value::Object(serde_json::Map::new());
+        }
+        current = match current {
+            Value::Object(map) => {
+                if create {
+                    map.entry(seg.to_string()).or_insert(Value::Null)
+                } else {
+                    map.get_mut(*seg)?
+                }
+            }
+            Value::Array(arr) => arr.get_mut(seg.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    if create && current.is_null() {
+        *current = Value::Object(serde_json::Map::new());
+    }
+    Some(current)
+}
+
+/// 写入字段；`only_if_missing` 为 true 时仅在字段缺失或为 null 时写入
+fn write_value(target: &mut Value, segments: &[&str], value: &Value, only_if_missing: bool) -> bool {
+    let Some((last, parents)) = segments.split_last() else {
+        return false;
+    };
+    let Some(parent) = parent_mut(target, parents, true) else {
+        return false;
+    };
+    match parent {
+        Value::Object(map) => {
+            if only_if_missing && map.get(*last).is_some_and(|v| !v.is_null()) {
+                return false;
+            }
+            map.insert(last.to_string(), value.clone());
+            true
+        }
+        Value::Array(arr) => match last.parse::<usize>().ok().and_then(|i| arr.get_mut(i)) {
+            Some(slot) if !only_if_missing || slot.is_null() => {
+                *slot = value.clone();
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn remove_value(target: &mut Value, segments: &[&str]) -> bool {
+    let Some((last, parents)) = segments.split_last() else {
+        return false;
+    };
+    match parent_mut(target, parents, false) {
+        Some(Value::Object(map)) => map.remove(*last).is_some(),
+        Some(Value::Array(arr)) => match last.parse::<usize>() {
+            Ok(i) if i < arr.len() => {
+                arr.remove(i);
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// 依次执行变换操作；路径无法定位时跳过该操作
+pub fn apply_ops(target: &mut Value, ops: &[JsonTransformOp]) {
+    for op in ops {
+        let Ok(segments) = parse_path(op.path()) else {
+            continue;
+        };
+        let applied = match op {
+            JsonTransformOp::Set { value, .. } => write_value(target, &segments, value, false),
+            JsonTransformOp::Default { value, .. } => write_value(target, &segments, value, true),
+            JsonTransformOp::Remove { .. } => remove_value(target, &segments),
+        };
+        if !applied {
+            tracing::debug!("[Model-Transform] 操作未生效: {:?}", op);
+        }
+    }
+}
+
+/// 对 v1internal 包装后的请求体执行请求变换 (路径相对于内部的 `request` 对象)
+pub fn apply_request_transform(
+    model: &str,
+    transforms: &HashMap<String, ModelTransform>,
+    wrapped_body: &mut Value,
+) {
+    let Some(transform) = resolve_transform(model, transforms) else {
+        return;
+    };
+    if transform.request.is_empty() {
+        return;
+    }
+    match wrapped_body.get_mut("request") {
+        Some(inner) => apply_ops(inner, &transform.request),
+        None => apply_ops(wrapped_body, &transform.request),
+    }
+    tracing::debug!("[Model-Transform] 已对 {} 的请求应用 {} 个操作", model, transform.request.len());
+}
+
+/// 对上游 Gemini 响应执行响应变换 (路径相对于解包后的响应)
+pub fn apply_response_ops(ops: &[JsonTransformOp], gemini_response: &mut Value) {
+    if ops.is_empty() {
+        return;
+    }
+    if gemini_response.get("response").is_some() {
+        apply_ops(&mut gemini_response["response"], ops);
+    } else {
+        apply_ops(gemini_response, ops);
+    }
+}
+
+/// 获取模型的响应变换操作 (无配置时为空)
+pub fn response_ops_for(model: &str, transforms: &HashMap<String, ModelTransform>) -> Vec<JsonTransformOp> {
+    resolve_transform(model, transforms)
+        .map(|t| t.response.clone())
+        .unwrap_or_default()
+}
+
+fn transform_sse_line(line: &[u8], ops: &[JsonTransformOp]) -> Option<Bytes> {
+    let text = std::str::from_utf8(line).ok()?;
+    let payload = text.trim_end_matches(['\r', '\n']).strip_prefix("data:")?.trim_start();
+    let mut value: Value = serde_json::from_str(payload).ok()?;
+    apply_response_ops(ops, &mut value);
+    Some(Bytes::from(format!("data: {}\n", serde_json::to_string(&value).ok()?)))
+}
+
+/// 对上游 Gemini SSE 流的每个 data 分片执行响应变换
+/// 无响应变换时原样返回
+pub fn transform_sse_stream<E: Send + 'static>(
+    inner: Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>,
+    ops: Vec<JsonTransformOp>,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>> {
+    if ops.is_empty() {
+        return inner;
+    }
+    let mut inner = inner;
+    Box::pin(async_stream::stream! {
+        let mut buffer = BytesMut::new();
+        while let Some(item) = inner.next().await {
+            match item {
+                Ok(chunk) => {
+                    buffer.extend_from_slice(&chunk);
+                    let mut out = BytesMut::new();
+                    while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                        let line = buffer.split_to(pos + 1);
+                        match transform_sse_line(&line, &ops) {
+                            Some(rewritten) => out.extend_from_slice(&rewritten),
+                            None => out.extend_from_slice(&line),
+                        }
+                    }
+                    if !out.is_empty() {
+                        yield Ok(out.freeze());
+                    }
+                }
+                Err(e) => yield Err(e),
+            }
+        }
+        if !buffer.is_empty() {
+            let rest = buffer.split();
+            yield Ok(transform_sse_line(&rest, &ops).unwrap_or_else(|| rest.freeze()));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn transforms(pattern: &str, request: Vec<JsonTransformOp>) -> HashMap<String, ModelTransform> {
+        let mut map = HashMap::new();
+        map.insert(pattern.to_string(), ModelTransform { request, response: Vec::new() });
+        map
+    }
+
+    #[test]
+    fn test_set_and_default_applied_to_matching_request() {
+        let transforms = transforms(
+            "gemini-3-pro-image*",
+            vec![
+                JsonTransformOp::Set {
+                    path: "systemInstruction.parts.0.text".to_string(),
+                    value: json!("Always render in 16:9."),
+                },
+                JsonTransformOp::Set {
+                    path: "generationConfig.imageConfig.aspectRatio".to_string(),
+                    value: json!("16:9"),
+                },
+                JsonTransformOp::Default {
+                    path: "generationConfig.temperature".to_string(),
+                    value: json!(0.4),
+                },
+                JsonTransformOp::Default {
+                    path: "generationConfig.topP".to_string(),
+                    value: json!(0.9),
+                },
+            ],
+        );
+
+        let mut body = json!({
+            "project": "p",
+            "model": "gemini-3-pro-image",
+            "request": {
+                "systemInstruction": {"parts": [{"text": "original"}]},
+                "generationConfig": {"topP": 0.5}
+            }
+        });
+        apply_request_transform("gemini-3-pro-image", &transforms, &mut body);
+
+        let inner = &body["request"];
+        assert_eq!(inner["systemInstruction"]["parts"][0]["text"], "Always render in 16:9.");
+        assert_eq!(inner["generationConfig"]["imageConfig"]["aspectRatio"], "16:9");
+        // default 仅填充缺失字段
+        assert_eq!(inner["generationConfig"]["temperature"], 0.4);
+        assert_eq!(inner["generationConfig"]["topP"], 0.5);
+        // 包装层不受影响
+        assert_eq!(body["project"], "p");
+    }
+
+    #[test]
+    fn test_non_matching_model_untouched() {
+        let transforms = transforms(
+            "gemini-3-pro-image",
+            vec![JsonTransformOp::Set { path: "generationConfig.temperature".to_string(), value: json!(0) }],
+        );
+        let mut body = json!({"request": {"generationConfig": {}}});
+        let original = body.clone();
+        apply_request_transform("gemini-2.5-flash", &transforms, &mut body);
+        assert_eq!(body, original);
+    }
+
+    #[test]
+    fn test_overlapping_patterns_resolve_to_most_specific() {
+        let mut transforms = transforms("gemini-*", Vec::new());
+        transforms.extend(self::transforms(
+            "gemini-3-pro-image*",
+            vec![JsonTransformOp::Remove { path: "a".to_string() }],
+        ));
+        transforms.extend(self::transforms("*", Vec::new()));
+        for _ in 0..8 {
+            let resolved = resolve_transform("gemini-3-pro-image-4k", &transforms).unwrap();
+            assert_eq!(resolved.request.len(), 1);
+        }
+        assert!(resolve_transform("gemini-2.5-flash", &transforms).unwrap().request.is_empty());
+    }
+
+    #[test]
+    fn test_remove_op() {
+        let mut value = json!({"a": {"b": 1, "c": 2}, "list": [1, 2, 3]});
+        apply_ops(
+            &mut value,
+            &[
+                JsonTransformOp::Remove { path: "a.b".to_string() },
+                JsonTransformOp::Remove { path: "list.1".to_string() },
+                JsonTransformOp::Remove { path: "missing.path".to_string() },
+            ],
+        );
+        assert_eq!(value, json!({"a": {"c": 2}, "list": [1, 3]}));
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_paths() {
+        let ok = transforms("gemini-*", vec![JsonTransformOp::Remove { path: "a.b".to_string() }]);
+        assert!(validate_transforms(&ok).is_ok());
+
+        for path in ["", "a..b", ".a", "a."] {
+            let bad = transforms("gemini-*", vec![JsonTransformOp::Remove { path: path.to_string() }]);
+            assert!(validate_transforms(&bad).is_err(), "path {:?} should be rejected", path);
+        }
+
+        let empty_pattern = transforms(" ", Vec::new());
+        assert!(validate_transforms(&empty_pattern).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sse_stream_chunks_transformed() {
+        let ops = vec![JsonTransformOp::Remove { path: "usageMetadata".to_string() }];
+        let chunks: Vec<Result<Bytes, String>> = vec![
+            Ok(Bytes::from("data: {\"response\":{\"candidates\":[],\"usage")),
+            Ok(Bytes::from("Metadata\":{\"x\":1}}}\n\n")),
+        ];
+        let inner: Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> =
+            Box::pin(futures::stream::iter(chunks));
+
+        let collected: Vec<u8> = transform_sse_stream(inner, ops)
+            .map(|c| c.unwrap().to_vec())
+            .concat()
+            .await;
+        let text = String::from_utf8(collected).unwrap();
+        assert_eq!(text, "data: {\"response\":{\"candidates\":[]}}\n\n");
+    }
+}
@@ -0,0 +1,121 @@
+// 流式保活 (Keep-Alive)
+// 长时间思考时上游首字节迟迟不到，中间代理/负载均衡可能因空闲超时断开连接
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use tokio::time::{Duration, Instant};
+
+/// SSE 注释帧 (OpenAI / Gemini 客户端均会忽略)
+pub const SSE_COMMENT_PING: &str = ": keep-alive\n\n";
+
+/// Anthropic 协议的 ping 事件
+pub const ANTHROPIC_PING: &str = "event: ping\ndata: {\"type\": \"ping\"}\n\n";
+
+/// 在内层流产出首个数据块之前，每隔 `interval` 发送一次保活帧；
+/// 首个数据块到达后停止保活，后续数据原样透传。
+pub fn with_keepalive<E>(
+    inner: Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>,
+    interval: Duration,
+    ping: &'static str,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>
+where
+    E: Send + 'static,
+{
+    let stream = async_stream::stream! {
+        let mut inner = inner;
+        let mut ticker = tokio::time::interval_at(Instant::now() + interval, interval);
+        let mut finished = false;
+
+        loop {
+            // None 表示保活计时器触发
+            let next = tokio::select! {
+                item = inner.next() => Some(item),
+                _ = ticker.tick() => None,
+            };
+
+            match next {
+                Some(Some(item)) => {
+                    yield item;
+                    break;
+                }
+                Some(None) => {
+                    finished = true;
+                    break;
+                }
+                None => {
+                    tracing::debug!("[KeepAlive] Awaiting upstream first byte, sending ping");
+                    yield Ok(Bytes::from_static(ping.as_bytes()));
+                }
+            }
+        }
+
+        if !finished {
+            while let Some(item) = inner.next().await {
+                yield item;
+            }
+        }
+    };
+
+    Box::pin(stream)
+}
+
+/// 按配置决定是否启用保活 (`interval_secs == 0` 表示关闭)
+pub fn maybe_keepalive<E>(
+    inner: Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>,
+    interval_secs: u64,
+    ping: &'static str,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>
+where
+    E: Send + 'static,
+{
+    if interval_secs == 0 {
+        inner
+    } else {
+        with_keepalive(inner, Duration::from_secs(interval_secs), ping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pings_until_first_byte_then_stops() {
+        let upstream = async_stream::stream! {
+            tokio::time::sleep(Duration::from_millis(350)).await;
+            yield Ok::<Bytes, String>(Bytes::from("data: first\n\n"));
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            yield Ok::<Bytes, String>(Bytes::from("data: second\n\n"));
+        };
+
+        let chunks: Vec<Bytes> = with_keepalive(
+            Box::pin(upstream),
+            Duration::from_millis(100),
+            SSE_COMMENT_PING,
+        )
+        .map(|c| c.unwrap())
+        .collect()
+        .await;
+
+        let first_data = chunks
+            .iter()
+            .position(|c| c.as_ref() == b"data: first\n\n")
+            .unwrap();
+        assert!(first_data >= 2, "expected heartbeats before first byte, got {:?}", chunks);
+        assert!(chunks[..first_data]
+            .iter()
+            .all(|c| c.as_ref() == SSE_COMMENT_PING.as_bytes()));
+        // 首个数据块之后不再发送保活帧
+        assert_eq!(&chunks[first_data + 1..], &[Bytes::from("data: second\n\n")]);
+    }
+
+    #[tokio::test]
+    async fn test_no_pings_when_upstream_is_fast() {
+        let upstream = futures::stream::iter(vec![Ok::<Bytes, String>(Bytes::from("data: x\n\n"))]);
+        let chunks: Vec<_> = with_keepalive(Box::pin(upstream), Duration::from_secs(1), ANTHROPIC_PING)
+            .collect()
+            .await;
+        assert_eq!(chunks.len(), 1);
+    }
+}
@@ -0,0 +1,198 @@
+// OpenAI logprobs 参数转换
+// 请求侧：`logprobs` / `top_logprobs` -> Gemini generationConfig 的 `responseLogprobs` / `logprobs`
+// 响应侧：候选结果中的 `logprobsResult` -> OpenAI `choices[].logprobs.content`
+// 目标模型不支持时按配置忽略 (记录警告) 或返回 400
+use serde_json::{json, Value};
+
+use crate::proxy::common::error::ProxyError;
+use crate::proxy::config::{LogprobsConfig, LogprobsUnsupportedAction};
+
+/// OpenAI 与 Gemini 允许的 top_logprobs 上限
+pub const MAX_TOP_LOGPROBS: u32 = 20;
+
+/// 解析客户端请求的 logprobs，返回每个位置需要的候选数 (0 表示只要所选 token 的 logprob)
+/// Chat API 中 `logprobs` 为布尔值、候选数由 `top_logprobs` 指定；Legacy Completions API 中 `logprobs` 直接为候选数
+pub fn requested_top_logprobs(logprobs: Option<&Value>, top_logprobs: Option<u32>) -> Option<u32> {
+    let top = match logprobs? {
+        Value::Bool(true) => top_logprobs.unwrap_or(0),
+        Value::Number(n) => n.as_u64().map(|n| n.min(u32::MAX as u64) as u32)?,
+        _ => return None,
+    };
+    Some(top.min(MAX_TOP_LOGPROBS))
+}
+
+pub fn model_supports_logprobs(model: &str, config: &LogprobsConfig) -> bool {
+    config
+        .supported_models
+        .iter()
+        .any(|pattern| crate::proxy::common::model_mapping::glob_match(pattern, model))
+}
+
+/// 决定对目标模型实际下发的 logprobs 参数：`Ok(None)` 表示未请求或已忽略，`Err` 为拒绝时返回给客户端的错误
+pub fn resolve_logprobs(
+    requested: Option<u32>,
+    model: &str,
+    config: &LogprobsConfig,
+) -> Result<Option<u32>, ProxyError> {
+    let Some(top) = requested else {
+        return Ok(None);
+    };
+    if model_supports_logprobs(model, config) {
+        return Ok(Some(top));
+    }
+    match config.unsupported_action {
+        LogprobsUnsupportedAction::Strip => {
+            tracing::warn!("[Logprobs] Model {} does not support logprobs, ignoring logprobs/top_logprobs", model);
+            Ok(None)
+        }
+        LogprobsUnsupportedAction::Error => Err(ProxyError::InvalidRequest(format!(
+            "Model '{}' does not support logprobs; remove \"logprobs\" and \"top_logprobs\" from the request",
+            model
+        ))),
+    }
+}
+
+/// 写入 Gemini generationConfig
+pub fn apply_to_generation_config(generation_config: &mut Value, top_logprobs: u32) {
+    generation_config["responseLogprobs"] = json!(true);
+    if top_logprobs > 0 {
+        generation_config["logprobs"] = json!(top_logprobs);
+    }
+}
+
+fn token_entry(candidate: &Value) -> Value {
+    let token = candidate.get("token").and_then(Value::as_str).unwrap_or("");
+    json!({
+        "token": token,
+        "logprob": candidate.get("logProbability").and_then(Value::as_f64).unwrap_or(0.0),
+        "bytes": token.as_bytes(),
+    })
+}
+
+/// 将单个 Gemini 候选结果的 `logprobsResult` 转换为 OpenAI `logprobs` 对象，未返回时为 None
+pub fn candidate_logprobs(candidate: &Value) -> Option<Value> {
+    let result = candidate.get("logprobsResult")?;
+    let chosen = result.get("chosenCandidates").and_then(Value::as_array)?;
+    let top_steps = result.get("topCandidates").and_then(Value::as_array);
+
+    let content: Vec<Value> = chosen
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            let mut entry = token_entry(token);
+            let top: Vec<Value> = top_steps
+                .and_then(|steps| steps.get(i))
+                .and_then(|step| step.get("candidates"))
+                .and_then(Value::as_array)
+                .map(|alternatives| alternatives.iter().map(token_entry).collect())
+                .unwrap_or_default();
+            entry["top_logprobs"] = json!(top);
+            entry
+        })
+        .collect();
+    Some(json!({ "content": content, "refusal": null }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::mappers::openai::{gemini_response_to_openai, transform_openai_request, OpenAIRequest};
+
+    fn chat_request(model: &str) -> OpenAIRequest {
+        serde_json::from_value(json!({
+            "model": model,
+            "messages": [{ "role": "user", "content": "Say hi" }],
+            "logprobs": true,
+            "top_logprobs": 2
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_logprobs_translated_and_mapped_back() {
+        let config = LogprobsConfig::default();
+        let request = chat_request("gpt-4o");
+        let requested = requested_top_logprobs(request.logprobs.as_ref(), request.top_logprobs);
+        assert_eq!(requested, Some(2));
+
+        let top = resolve_logprobs(requested, "gemini-3-flash", &config).unwrap().unwrap();
+        let mut body = transform_openai_request(
+            &request,
+            "project",
+            "gemini-3-flash",
+            crate::proxy::common::json_schema::DEFAULT_INLINE_SIZE_LIMIT,
+        );
+        apply_to_generation_config(&mut body["request"]["generationConfig"], top);
+        assert_eq!(body["request"]["generationConfig"]["responseLogprobs"], true);
+        assert_eq!(body["request"]["generationConfig"]["logprobs"], 2);
+
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": { "parts": [{ "text": "Hi!" }] },
+                "finishReason": "STOP",
+                "logprobsResult": {
+                    "topCandidates": [
+                        { "candidates": [
+                            { "token": "Hi", "logProbability": -0.1 },
+                            { "token": "Hello", "logProbability": -2.5 }
+                        ] },
+                        { "candidates": [{ "token": "!", "logProbability": -0.01 }] }
+                    ],
+                    "chosenCandidates": [
+                        { "token": "Hi", "logProbability": -0.1 },
+                        { "token": "!", "logProbability": -0.01 }
+                    ]
+                }
+            }]
+        });
+        let out = gemini_response_to_openai(&gemini_resp).unwrap();
+        let content = out["choices"][0]["logprobs"]["content"].as_array().unwrap();
+        assert_eq!(content.len(), 2);
+        assert_eq!(content[0]["token"], "Hi");
+        assert_eq!(content[0]["logprob"], -0.1);
+        assert_eq!(content[0]["bytes"], json!([72, 105]));
+        assert_eq!(content[0]["top_logprobs"][1]["token"], "Hello");
+        assert_eq!(content[1]["top_logprobs"].as_array().unwrap().len(), 1);
+
+        // 未返回 logprobsResult 时不输出 logprobs 字段
+        let plain = json!({ "candidates": [{ "content": { "parts": [{ "text": "Hi" }] } }] });
+        let out = gemini_response_to_openai(&plain).unwrap();
+        assert!(out["choices"][0].get("logprobs").is_none());
+    }
+
+    #[test]
+    fn test_unsupported_model_strips_with_warning_or_errors() {
+        let config = LogprobsConfig::default();
+        assert_eq!(resolve_logprobs(Some(2), "claude-sonnet-4-5", &config).unwrap(), None);
+        // 未请求时不受影响
+        assert_eq!(resolve_logprobs(None, "claude-sonnet-4-5", &config).unwrap(), None);
+
+        let strict = LogprobsConfig {
+            unsupported_action: LogprobsUnsupportedAction::Error,
+            ..Default::default()
+        };
+        let err = resolve_logprobs(Some(2), "claude-sonnet-4-5", &strict).unwrap_err();
+        assert!(err.to_string().contains("does not support logprobs"));
+        assert_eq!(resolve_logprobs(Some(0), "gemini-3-flash", &strict).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn test_supported_model_patterns_support_multiple_wildcards() {
+        let config = LogprobsConfig {
+            supported_models: vec!["gemini-*-flash*".to_string()],
+            ..Default::default()
+        };
+        assert!(model_supports_logprobs("gemini-3-flash", &config));
+        assert!(model_supports_logprobs("gemini-2.5-flash-lite", &config));
+        assert!(!model_supports_logprobs("gemini-3-pro-high", &config));
+    }
+
+    #[test]
+    fn test_requested_top_logprobs_accepts_chat_and_legacy_forms() {
+        assert_eq!(requested_top_logprobs(Some(&json!(true)), None), Some(0));
+        assert_eq!(requested_top_logprobs(Some(&json!(false)), Some(3)), None);
+        assert_eq!(requested_top_logprobs(Some(&json!(5)), None), Some(5));
+        assert_eq!(requested_top_logprobs(Some(&json!(true)), Some(50)), Some(MAX_TOP_LOGPROBS));
+        assert_eq!(requested_top_logprobs(None, Some(3)), None);
+    }
+}
@@ -1,7 +1,28 @@
 // Common 模块 - 公共工具
 
-// pub mod error;
+pub mod error;
 // pub mod rate_limiter;
 pub mod model_mapping;
 pub mod utils;
 pub mod json_schema;
+pub mod schema_lint;
+pub mod keepalive;
+pub mod stream_limits;
+pub mod request_timeout;
+pub mod reasoning_output;
+pub mod json_transform;
+pub mod candidate_stats;
+pub mod header_filter;
+pub mod hedge;
+pub mod truncation;
+pub mod tool_limit;
+pub mod schema_retry;
+pub mod stream_emulation;
+pub mod logprobs;
+pub mod candidate_override;
+pub mod system_prompt;
+pub mod attempt_budget;
+pub mod safety_fallback;
+pub mod token_estimate;
+pub mod history_trim;
+pub mod priority_queue;
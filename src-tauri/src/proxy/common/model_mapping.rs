@@ -1,7 +1,11 @@
 // 模型名称映射
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
 use once_cell::sync::Lazy;
-use crate::proxy::config::{ModelFallbackPolicy, ModelPriority, ModelStrategy};
+use crate::proxy::config::{
+    AutoModelConfig, DeprecatedModel, ModelCanonicalizationConfig, ModelDeprecationPolicy, ModelFallbackPolicy, ModelPriority, ModelStrategy, OpenAIFamilyRule,
+    SelectionMode, ServableModelsFilter, StrategyExperiment,
+};
 
 static CLAUDE_TO_GEMINI: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
     let mut m = HashMap::new();
@@ -56,19 +60,176 @@ static CLAUDE_TO_GEMINI: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|
     m
 });
 
-pub fn map_claude_model_to_gemini(input: &str) -> String {
-    // 1. Check exact match in map
-    if let Some(mapped) = CLAUDE_TO_GEMINI.get(input) {
-        return mapped.to_string();
+/// 兜底默认模型 (未命中任何映射时使用)
+pub const DEFAULT_FALLBACK_MODEL: &str = "claude-sonnet-4-5";
+
+/// 允许 thinking 直通的默认模型名前缀
+pub const DEFAULT_THINKING_PASSTHROUGH_PREFIXES: [&str; 3] = ["claude-", "gemini-", "gpt-"];
+
+pub fn default_thinking_passthrough_prefixes() -> Vec<String> {
+    DEFAULT_THINKING_PASSTHROUGH_PREFIXES.iter().map(|p| p.to_string()).collect()
+}
+
+/// 含 `thinking` 且以已知前缀开头的模型名才原样直通，避免 `my-thinking-experiment` 之类的名称绕过兜底
+fn is_thinking_passthrough(input: &str, prefixes: &[String]) -> bool {
+    input.contains("thinking")
+        && prefixes
+            .iter()
+            .any(|prefix| !prefix.is_empty() && input.starts_with(prefix.as_str()))
+}
+
+/// 兜底告警的最小间隔 (秒)
+const DEFAULT_FALLBACK_WARN_INTERVAL_SECS: i64 = 60;
+
+/// 兜底默认映射命中统计
+/// 频繁命中兜底通常意味着缺少映射配置；告警按间隔限流，避免刷屏
+pub struct DefaultFallbackTracker {
+    count: AtomicU64,
+    last_warn_at: AtomicI64,
+    suppressed: AtomicU64,
+}
+
+impl DefaultFallbackTracker {
+    pub const fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            last_warn_at: AtomicI64::new(i64::MIN),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.suppressed.store(0, Ordering::Relaxed);
+    }
+
+    fn record(&self, input: &str) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+
+        let now = chrono::Utc::now().timestamp();
+        let last = self.last_warn_at.load(Ordering::Relaxed);
+        let due = last == i64::MIN || now - last >= DEFAULT_FALLBACK_WARN_INTERVAL_SECS;
+        if due
+            && self
+                .last_warn_at
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            let suppressed = self.suppressed.swap(0, Ordering::Relaxed);
+            crate::modules::logger::log_warn(&format!(
+                "[Router] 模型 '{}' 未命中任何映射，已兜底为 {} (请检查映射配置；期间另有 {} 次兜底未告警)",
+                input, DEFAULT_FALLBACK_MODEL, suppressed
+            ));
+        } else {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for DefaultFallbackTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static DEFAULT_FALLBACK: DefaultFallbackTracker = DefaultFallbackTracker::new();
+
+/// 进程内兜底默认映射的累计命中次数
+pub fn default_fallback_count() -> u64 {
+    DEFAULT_FALLBACK.count()
+}
+
+/// 清零兜底命中计数 (随监控统计一同清除)
+pub fn reset_default_fallback_count() {
+    DEFAULT_FALLBACK.reset();
+}
+
+static DEPRECATED_ROUTE_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// 进程内路由命中弃用模型的累计次数
+pub fn deprecated_route_count() -> u64 {
+    DEPRECATED_ROUTE_COUNT.load(Ordering::Relaxed)
+}
+
+/// 清零弃用模型命中计数 (随监控统计一同清除)
+pub fn reset_deprecated_route_count() {
+    DEPRECATED_ROUTE_COUNT.store(0, Ordering::Relaxed);
+}
+
+/// 查找目标模型对应的弃用条目 (不区分大小写，支持 `*` 通配)
+pub fn find_deprecated_model<'a>(
+    model: &str,
+    policy: &'a ModelDeprecationPolicy,
+) -> Option<&'a DeprecatedModel> {
+    policy.models.iter().find(|entry| {
+        if entry.model.contains('*') {
+            wildcard_match(&entry.model.to_lowercase(), &model.to_lowercase())
+        } else {
+            entry.model.eq_ignore_ascii_case(model)
+        }
+    })
+}
+
+/// 按弃用策略得到最终目标 (启用自动替换且有替代模型时返回替代模型)
+fn deprecation_target(target: String, policy: &ModelDeprecationPolicy) -> String {
+    match find_deprecated_model(&target, policy) {
+        Some(DeprecatedModel { replacement: Some(replacement), .. }) if policy.auto_replace => {
+            replacement.clone()
+        }
+        _ => target,
     }
+}
 
-    // 2. Pass-through known prefixes (gemini-, -thinking) to support dynamic suffixes
-    if input.starts_with("gemini-") || input.contains("thinking") {
-        return input.to_string();
+/// 路由命中弃用模型时告警并计数，按需替换为建议的替代模型
+fn apply_deprecation_policy(original_model: &str, target: String, policy: &ModelDeprecationPolicy) -> String {
+    let Some(entry) = find_deprecated_model(&target, policy) else {
+        return target;
+    };
+    DEPRECATED_ROUTE_COUNT.fetch_add(1, Ordering::Relaxed);
+    match (&entry.replacement, policy.auto_replace) {
+        (Some(replacement), true) => {
+            crate::modules::logger::log_warn(&format!(
+                "[Router] 模型 '{}' 映射到已弃用的 {}，已自动替换为 {}",
+                original_model, target, replacement
+            ));
+            replacement.clone()
+        }
+        (Some(replacement), false) => {
+            crate::modules::logger::log_warn(&format!(
+                "[Router] 模型 '{}' 映射到已弃用的 {} (建议改为 {})",
+                original_model, target, replacement
+            ));
+            target
+        }
+        (None, _) => {
+            crate::modules::logger::log_warn(&format!(
+                "[Router] 模型 '{}' 映射到已弃用的 {}",
+                original_model, target
+            ));
+            target
+        }
     }
+}
 
-    // 3. Fallback to default
-    "claude-sonnet-4-5".to_string()
+pub fn map_claude_model_to_gemini(input: &str, thinking_passthrough_prefixes: &[String]) -> String {
+    map_claude_model_to_gemini_tracked(input, thinking_passthrough_prefixes, &DEFAULT_FALLBACK)
+}
+
+fn map_claude_model_to_gemini_tracked(
+    input: &str,
+    thinking_passthrough_prefixes: &[String],
+    tracker: &DefaultFallbackTracker,
+) -> String {
+    // 别名表 > 已知前缀直通 (gemini-, 已知前缀 + thinking) > 兜底默认模型
+    let (target, rule) = classify_builtin(input, thinking_passthrough_prefixes);
+    if rule == RouteRule::DefaultFallback {
+        tracker.record(input);
+    }
+    target
 }
 
 /// 获取所有内置支持的模型列表关键字
@@ -77,15 +238,24 @@ pub fn get_supported_models() -> Vec<String> {
 }
 
 /// 动态获取所有可用模型列表 (包含内置与用户自定义)
+///
+/// `advertise_all_aliases` 为 true 时同时列出内置别名 (如 `gpt-4o`、`claude-3-5-sonnet-20241022`)，
+/// 便于只认识某一家命名的客户端在下拉列表中选择；为 false 时仅列出别名实际指向的模型 ID。
 pub async fn get_all_dynamic_models(
     custom_mapping: &tokio::sync::RwLock<std::collections::HashMap<String, String>>,
+    advertise_all_aliases: bool,
 ) -> Vec<String> {
     use std::collections::HashSet;
     let mut model_ids = HashSet::new();
 
-    // 1. 获取所有内置映射模型
-    for m in get_supported_models() {
-        model_ids.insert(m);
+    // 1. 获取所有内置映射模型 (别名 key 与目标模型)
+    if advertise_all_aliases {
+        for m in get_supported_models() {
+            model_ids.insert(m);
+        }
+    }
+    for target in CLAUDE_TO_GEMINI.values() {
+        model_ids.insert(target.to_string());
     }
 
     // 2. 获取所有自定义映射模型 (Custom)
@@ -126,6 +296,34 @@ pub async fn get_all_dynamic_models(
     sorted_ids
 }
 
+/// 仅保留至少一个健康账号能服务的模型 (`advertise_servable_only`)
+///
+/// 模型 ID 与其路由目标的等级要求取较高者，`resolve` 将列表中的模型 ID 解析为上游模型，
+/// `can_serve` 判断账号池能否服务给定等级要求的模型。
+pub fn filter_servable_models(
+    model_ids: Vec<String>,
+    filter: &ServableModelsFilter,
+    resolve: impl Fn(&str) -> String,
+    can_serve: impl Fn(Option<&str>) -> bool,
+) -> Vec<String> {
+    use crate::proxy::config::subscription_tier_rank;
+
+    if !filter.enabled {
+        return model_ids;
+    }
+    model_ids
+        .into_iter()
+        .filter(|id| {
+            let target = resolve(id);
+            let required = [filter.required_tier(id), filter.required_tier(&target)]
+                .into_iter()
+                .flatten()
+                .max_by_key(|tier| subscription_tier_rank(tier));
+            can_serve(required)
+        })
+        .collect()
+}
+
 /// 通配符匹配辅助函数
 /// 支持简单的 * 通配符匹配
 /// 
@@ -133,7 +331,7 @@ pub async fn get_all_dynamic_models(
 /// - `gpt-4*` 匹配 `gpt-4`, `gpt-4-turbo`, `gpt-4-0613` 等
 /// - `claude-3-5-sonnet-*` 匹配所有 3.5 sonnet 版本
 /// - `*-thinking` 匹配所有以 `-thinking` 结尾的模型
-fn wildcard_match(pattern: &str, text: &str) -> bool {
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
     if let Some(star_pos) = pattern.find('*') {
         let prefix = &pattern[..star_pos];
         let suffix = &pattern[star_pos + 1..];
@@ -143,84 +341,289 @@ fn wildcard_match(pattern: &str, text: &str) -> bool {
     }
 }
 
-/// 核心模型路由解析引擎
-/// 优先级：Custom Mapping (精确/通配) > Group Mapping (家族) > System Mapping (内置插件)
-/// 
-/// # 参数
-/// - `apply_claude_family_mapping`: 是否对 Claude 模型应用家族映射
-///   - `true`: CLI 请求，应用家族映射（如 claude-sonnet-4-5 -> gemini-3-pro-high）
-///   - `false`: 非 CLI 请求（如 Cherry Studio），跳过家族映射，直接穿透
-pub fn resolve_model_route(
+/// 支持多个 `*` 的通配符匹配 (区分大小写，不含 `*` 时为精确匹配)
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    if !pattern.contains('*') {
+        return pattern == text;
+    }
+
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if text.len() < first.len() + last.len() || !text.starts_with(first) || !text.ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(*part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// 不区分大小写地剥离前缀 (仅在字符边界上切分)
+fn strip_prefix_ci<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    if prefix.is_empty() {
+        return None;
+    }
+    let head = value.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &value[prefix.len()..])
+}
+
+/// 不区分大小写地剥离后缀
+fn strip_suffix_ci<'a>(value: &'a str, suffix: &str) -> Option<&'a str> {
+    if suffix.is_empty() {
+        return None;
+    }
+    let cut = value.len().checked_sub(suffix.len())?;
+    let tail = value.get(cut..)?;
+    tail.eq_ignore_ascii_case(suffix).then(|| &value[..cut])
+}
+
+/// 模型名规范化：按规则剥离修饰，得到路由查找键
+/// 例如 `anthropic/claude-3-5-sonnet-20241022@20241022` -> `claude-3-5-sonnet-20241022`
+/// 剥离后为空时保留原值
+pub fn canonicalize_model_name(model: &str, rules: &ModelCanonicalizationConfig) -> String {
+    let trimmed = model.trim();
+    if !rules.enabled {
+        return trimmed.to_string();
+    }
+
+    let mut current = trimmed;
+    // 修饰可能叠加 (如 `openai/gpt-4o:latest`)，反复剥离直到稳定
+    loop {
+        let before = current;
+
+        for prefix in &rules.strip_prefixes {
+            if let Some(rest) = strip_prefix_ci(current, prefix) {
+                current = rest;
+            }
+        }
+
+        if rules.strip_at_version {
+            if let Some(pos) = current.rfind('@') {
+                current = &current[..pos];
+            }
+        }
+
+        for suffix in &rules.strip_suffixes {
+            if let Some(rest) = strip_suffix_ci(current, suffix) {
+                current = rest;
+            }
+        }
+
+        current = current.trim();
+        if current == before || current.is_empty() {
+            break;
+        }
+    }
+
+    if current.is_empty() {
+        trimmed.to_string()
+    } else {
+        current.to_string()
+    }
+}
+
+/// 计算路由查找使用的模型名
+/// 自定义映射中对原始名称的精确规则优先，此时不做规范化
+pub fn route_lookup_model(
     original_model: &str,
-    custom_mapping: &std::collections::HashMap<String, String>,
-    openai_mapping: &std::collections::HashMap<String, String>,
-    anthropic_mapping: &std::collections::HashMap<String, String>,
-    apply_claude_family_mapping: bool,
+    custom_mapping: &HashMap<String, String>,
+    rules: &ModelCanonicalizationConfig,
+    auto_model: &AutoModelConfig,
 ) -> String {
+    if custom_mapping.contains_key(original_model) {
+        return original_model.to_string();
+    }
+    // 哨兵名 (default / auto) 交由代理选择，直接替换为自动选型目标
+    if let Some(target) = auto_model.resolve(original_model) {
+        crate::modules::logger::log_info(&format!(
+            "[Router] 自动选型: {} -> {}",
+            original_model, target
+        ));
+        return target.to_string();
+    }
+    let canonical = canonicalize_model_name(original_model, rules);
+    if canonical != original_model {
+        crate::modules::logger::log_info(&format!(
+            "[Router] 模型名规范化: {} -> {}",
+            original_model, canonical
+        ));
+    }
+    canonical
+}
+
+/// 校验客户端传入的模型名：去除首尾空白，空值直接报错
+/// 不再静默兜底到默认模型，避免掩盖客户端漏传 model 的问题
+pub fn require_model_field(model: &str) -> Result<String, crate::proxy::common::error::ProxyError> {
+    let trimmed = model.trim();
+    if trimmed.is_empty() {
+        return Err(crate::proxy::common::error::ProxyError::InvalidRequest(
+            "model field is required".to_string(),
+        ));
+    }
+    Ok(trimmed.to_string())
+}
+
+/// 请求体完全省略 model 字段 (缺失或为 null) 时填入协议默认模型
+/// 未配置默认模型时填入空字符串，由 `require_model_field` 按协议格式报错；显式传入的值 (包括空字符串) 不做替换
+pub fn fill_omitted_model(body: &mut serde_json::Value, default_model: Option<&str>) {
+    let omitted = body.get("model").is_none_or(serde_json::Value::is_null);
+    if let (true, Some(obj)) = (omitted, body.as_object_mut()) {
+        obj.insert("model".to_string(), serde_json::json!(default_model.unwrap_or_default()));
+    }
+}
+
+/// Gemini 路径中的模型段为空 (如 `/v1beta/models/:generateContent`) 时使用协议默认模型
+pub fn fill_omitted_path_model(model: String, default_model: Option<&str>) -> String {
+    match default_model {
+        Some(default_model) if model.is_empty() => default_model.to_string(),
+        _ => model,
+    }
+}
+
+/// 是否为已知的上游模型名 (校验客户端通过请求头直接指定的候选模型)：
+/// 内置映射表中的模型、`gemini-` 直通模型或自定义映射的目标模型
+pub fn is_known_upstream_model(model: &str, custom_mapping: &HashMap<String, String>) -> bool {
+    CLAUDE_TO_GEMINI.contains_key(model)
+        || CLAUDE_TO_GEMINI.values().any(|target| *target == model)
+        || model.starts_with("gemini-")
+        || custom_mapping.values().any(|target| target == model)
+}
+
+/// 检查客户端请求的原始模型名是否在允许列表中 (空列表表示不限制)
+/// 不在列表中时返回 403，错误信息列出允许的模型，即使存在对应的映射也不放行
+pub fn check_client_model_allowed(
+    model: &str,
+    allowed: &[String],
+) -> Result<(), crate::proxy::common::error::ProxyError> {
+    if allowed.is_empty() || allowed.iter().any(|pattern| wildcard_match(pattern.trim(), model)) {
+        return Ok(());
+    }
+    Err(crate::proxy::common::error::ProxyError::ModelNotAllowed(format!(
+        "Model '{}' is not allowed. Permitted models: {}",
+        model,
+        allowed.join(", ")
+    )))
+}
+
+/// 路由命中的规则 (用于日志与路由审计)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteRule {
+    /// 自定义映射精确匹配
+    CustomExact,
+    /// 自定义映射通配符匹配 (规则)
+    CustomWildcard(String),
+    /// 显式策略引用 (`strategy:<id>`)，由执行计划展开
+    StrategyReference,
+    /// OpenAI 家族分组映射 (分组键, 规则)
+    OpenAIFamily { mapping_key: String, pattern: String },
+    /// 内置直通模型，跳过 Claude 家族映射
+    ClaudePassthrough,
+    /// Haiku 智能降级 (仅 CLI)
+    HaikuDowngrade,
+    /// Anthropic 系列映射 (分组键)
+    AnthropicFamily(String),
+    /// 旧版 Anthropic 精确映射
+    AnthropicExact,
+    /// 内置别名表
+    BuiltinAlias,
+    /// 已知前缀直通 (gemini-* / 已知前缀 + thinking)
+    Passthrough,
+    /// 未命中任何映射，兜底到默认模型
+    DefaultFallback,
+}
+
+impl std::fmt::Display for RouteRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RouteRule::CustomExact => write!(f, "custom (exact)"),
+            RouteRule::CustomWildcard(pattern) => write!(f, "custom (wildcard {})", pattern),
+            RouteRule::StrategyReference => write!(f, "strategy reference"),
+            RouteRule::OpenAIFamily { mapping_key, pattern } => {
+                write!(f, "openai family {} (rule {})", mapping_key, pattern)
+            }
+            RouteRule::ClaudePassthrough => write!(f, "claude passthrough"),
+            RouteRule::HaikuDowngrade => write!(f, "haiku downgrade"),
+            RouteRule::AnthropicFamily(key) => write!(f, "anthropic family {}", key),
+            RouteRule::AnthropicExact => write!(f, "anthropic (exact)"),
+            RouteRule::BuiltinAlias => write!(f, "builtin alias"),
+            RouteRule::Passthrough => write!(f, "passthrough"),
+            RouteRule::DefaultFallback => write!(f, "default fallback"),
+        }
+    }
+}
+
+/// 内置映射：别名表 > 已知前缀直通 > 兜底默认模型
+fn classify_builtin(input: &str, thinking_passthrough_prefixes: &[String]) -> (String, RouteRule) {
+    if let Some(mapped) = CLAUDE_TO_GEMINI.get(input) {
+        return (mapped.to_string(), RouteRule::BuiltinAlias);
+    }
+    if input.starts_with("gemini-") || is_thinking_passthrough(input, thinking_passthrough_prefixes) {
+        return (input.to_string(), RouteRule::Passthrough);
+    }
+    (DEFAULT_FALLBACK_MODEL.to_string(), RouteRule::DefaultFallback)
+}
+
+/// 解析模型路由并返回命中的规则 (不写日志、不计数)
+pub fn explain_model_route(
+    original_model: &str,
+    config: &ModelRouteConfig,
+    apply_claude_family_mapping: bool,
+) -> (String, RouteRule) {
+    let ModelRouteConfig {
+        custom_mapping,
+        openai_mapping,
+        openai_family_rules,
+        anthropic_mapping,
+        family_overrides_builtin,
+        ..
+    } = *config;
     // 1. 精确匹配 (最高优先级)
     if let Some(target) = custom_mapping.get(original_model) {
-        crate::modules::logger::log_info(&format!("[Router] 精确映射: {} -> {}", original_model, target));
-        return target.clone();
+        return (target.clone(), RouteRule::CustomExact);
     }
 
     // 2. 通配符匹配
     for (pattern, target) in custom_mapping.iter() {
         if pattern.contains('*') && wildcard_match(pattern, original_model) {
-            crate::modules::logger::log_info(&format!("[Router] 通配符映射: {} -> {} (规则: {})", original_model, target, pattern));
-            return target.clone();
+            return (target.clone(), RouteRule::CustomWildcard(pattern.clone()));
         }
     }
 
+    // 显式策略引用 (如自动选型目标) 原样保留，交给 plan_for_target 展开
+    if extract_strategy_id(original_model).is_some() {
+        return (original_model.to_string(), RouteRule::StrategyReference);
+    }
+
     let lower_model = original_model.to_lowercase();
 
     // 3. 检查家族分组映射 (OpenAI 系)
-    // GPT-4 系列 (含 GPT-4 经典, o1, o3 等, 排除 4o/mini/turbo)
-    if (lower_model.starts_with("gpt-4") && !lower_model.contains("o") && !lower_model.contains("mini") && !lower_model.contains("turbo")) ||
-       lower_model.starts_with("o1-") || lower_model.starts_with("o3-") || lower_model == "gpt-4" {
-        if let Some(target) = openai_mapping.get("gpt-4-series") {
-            crate::modules::logger::log_info(&format!("[Router] 使用 GPT-4 系列映射: {} -> {}", original_model, target));
-            return target.clone();
-        }
-    }
-    
-    // GPT-4o / 3.5 系列 (均衡与轻量, 含 4o, mini, turbo)
-    if lower_model.contains("4o") || lower_model.starts_with("gpt-3.5") || (lower_model.contains("mini") && !lower_model.contains("gemini")) || lower_model.contains("turbo") {
-        if let Some(target) = openai_mapping.get("gpt-4o-series") {
-            crate::modules::logger::log_info(&format!("[Router] 使用 GPT-4o/3.5 系列映射: {} -> {}", original_model, target));
-            return target.clone();
-        }
-    }
-
-    // GPT-5 系列 (gpt-5, gpt-5.1, gpt-5.2 等)
-    if lower_model.starts_with("gpt-5") {
-        // 优先使用 gpt-5-series 映射，如果没有则使用 gpt-4-series
-        if let Some(target) = openai_mapping.get("gpt-5-series") {
-            crate::modules::logger::log_info(&format!("[Router] 使用 GPT-5 系列映射: {} -> {}", original_model, target));
-            return target.clone();
-        }
-        if let Some(target) = openai_mapping.get("gpt-4-series") {
-            crate::modules::logger::log_info(&format!("[Router] 使用 GPT-4 系列映射 (GPT-5 fallback): {} -> {}", original_model, target));
-            return target.clone();
+    // Gemini/Claude 模型名可能碰巧包含 "mini" 等片段，不参与 OpenAI 家族分组
+    if !lower_model.starts_with("gemini") && !lower_model.starts_with("claude-") {
+        for rule in openai_family_rules {
+            if !glob_match(&rule.glob_pattern(), &lower_model)
+                || rule.exclude.iter().any(|f| lower_model.contains(&f.to_lowercase()))
+            {
+                continue;
+            }
+            if let Some(target) = openai_mapping.get(&rule.mapping_key) {
+                return (
+                    target.clone(),
+                    RouteRule::OpenAIFamily {
+                        mapping_key: rule.mapping_key.clone(),
+                        pattern: rule.pattern.clone(),
+                    },
+                );
+            }
         }
     }
 
     // 4. 检查家族分组映射 (Anthropic 系)
     // 仅在允许应用 Claude 家族映射时启用；否则直接下沉到默认映射
     if apply_claude_family_mapping && lower_model.starts_with("claude-") {
-        // 对于内置表中已定义为直通的模型，跳过家族映射，直接返回
-        if let Some(mapped) = CLAUDE_TO_GEMINI.get(original_model) {
-            if *mapped == original_model {
-                crate::modules::logger::log_info(&format!("[Router] 内置直通模型，跳过家族映射: {}", original_model));
-                return original_model.to_string();
-            }
-        }
-        
-        // Haiku 智能降级策略（仅 CLI 生效）
-        if apply_claude_family_mapping && lower_model.contains("haiku") {
-            crate::modules::logger::log_info(&format!("[Router] Haiku 智能降级 (CLI): {} -> gemini-2.5-flash-lite", original_model));
-            return "gemini-2.5-flash-lite".to_string();
-        }
-
         let family_key = if lower_model.contains("4-5") || lower_model.contains("4.5") {
             "claude-4.5-series"
         } else if lower_model.contains("3-5") || lower_model.contains("3.5") {
@@ -228,24 +631,259 @@ pub fn resolve_model_route(
         } else {
             "claude-default"
         };
+        // 用户配置的家族映射，兜底兼容旧版精确映射
+        let user_mapping = anthropic_mapping
+            .get(family_key)
+            .map(|target| (target.clone(), RouteRule::AnthropicFamily(family_key.to_string())))
+            .or_else(|| {
+                anthropic_mapping
+                    .get(original_model)
+                    .map(|target| (target.clone(), RouteRule::AnthropicExact))
+            });
 
-        if let Some(target) = anthropic_mapping.get(family_key) {
-            crate::modules::logger::log_warn(&format!("[Router] 使用 Anthropic 系列映射: {} -> {}", original_model, target));
-            return target.clone();
+        // 内置表中定义为直通的模型：默认跳过家族映射直接返回；
+        // 开启 family_overrides_builtin 且存在用户家族映射时以用户映射为准
+        if let Some(mapped) = CLAUDE_TO_GEMINI.get(original_model) {
+            if *mapped == original_model {
+                return match user_mapping {
+                    Some(user_mapping) if family_overrides_builtin => user_mapping,
+                    _ => (original_model.to_string(), RouteRule::ClaudePassthrough),
+                };
+            }
         }
-        
-        // 兜底兼容旧版精确映射
-        if let Some(target) = anthropic_mapping.get(original_model) {
-            return target.clone();
+
+        // Haiku 智能降级策略（仅 CLI 生效）
+        if lower_model.contains("haiku") {
+            return ("gemini-2.5-flash-lite".to_string(), RouteRule::HaikuDowngrade);
+        }
+
+        if let Some(user_mapping) = user_mapping {
+            return user_mapping;
         }
     }
 
     // 5. 下沉到系统默认映射逻辑
-    let result = map_claude_model_to_gemini(original_model);
-    if result != original_model {
-        crate::modules::logger::log_info(&format!("[Router] 系统默认映射: {} -> {}", original_model, result));
+    classify_builtin(original_model, config.thinking_passthrough_prefixes)
+}
+
+/// 核心模型路由解析引擎
+/// 优先级：Custom Mapping (精确/通配) > Group Mapping (家族) > System Mapping (内置插件)
+///
+/// Claude 家族映射内部的顺序：内置直通模型 > Haiku 降级 > 用户家族映射 > 旧版精确映射；
+/// `family_overrides_builtin` 开启时，用户家族映射 (及精确映射) 优先于内置直通模型
+/// 
+/// # 参数
+/// - `apply_claude_family_mapping`: 是否对 Claude 模型应用家族映射
+///   - `true`: CLI 请求，应用家族映射（如 claude-sonnet-4-5 -> gemini-3-pro-high）
+///   - `false`: 非 CLI 请求（如 Cherry Studio），跳过家族映射，直接穿透
+/// - `deprecation`: 目标为弃用模型时告警计数，并按配置替换为建议模型
+pub fn resolve_model_route(
+    original_model: &str,
+    config: &ModelRouteConfig,
+    apply_claude_family_mapping: bool,
+) -> String {
+    let (target, rule) = explain_model_route(original_model, config, apply_claude_family_mapping);
+
+    match &rule {
+        RouteRule::CustomExact => {
+            crate::modules::logger::log_info(&format!("[Router] 精确映射: {} -> {}", original_model, target));
+        }
+        RouteRule::CustomWildcard(pattern) => {
+            crate::modules::logger::log_info(&format!("[Router] 通配符映射: {} -> {} (规则: {})", original_model, target, pattern));
+        }
+        RouteRule::StrategyReference => {}
+        RouteRule::OpenAIFamily { mapping_key, pattern } => {
+            crate::modules::logger::log_info(&format!(
+                "[Router] 使用 OpenAI 家族映射 ({}, 规则: {}): {} -> {}",
+                mapping_key, pattern, original_model, target
+            ));
+        }
+        RouteRule::ClaudePassthrough => {
+            crate::modules::logger::log_info(&format!("[Router] 内置直通模型，跳过家族映射: {}", original_model));
+        }
+        RouteRule::HaikuDowngrade => {
+            crate::modules::logger::log_info(&format!("[Router] Haiku 智能降级 (CLI): {} -> {}", original_model, target));
+        }
+        RouteRule::AnthropicFamily(_) => {
+            crate::modules::logger::log_warn(&format!("[Router] 使用 Anthropic 系列映射: {} -> {}", original_model, target));
+        }
+        RouteRule::AnthropicExact => {}
+        RouteRule::BuiltinAlias | RouteRule::Passthrough | RouteRule::DefaultFallback => {
+            if rule == RouteRule::DefaultFallback {
+                DEFAULT_FALLBACK.record(original_model);
+            }
+            if target != original_model {
+                crate::modules::logger::log_info(&format!("[Router] 系统默认映射: {} -> {}", original_model, target));
+            }
+        }
+    }
+    apply_deprecation_policy(original_model, target, config.deprecation)
+}
+
+/// 路由审计中额外采样的家族输入 (覆盖 OpenAI/Claude 家族规则)
+const ROUTE_AUDIT_SAMPLE_INPUTS: &[&str] = &[
+    "gpt-4.1",
+    "gpt-4o-2024-11-20",
+    "gpt-4.1-mini",
+    "gpt-5",
+    "o1-preview",
+    "o3-mini",
+    "claude-3-5-haiku-20241022",
+    "claude-3-7-sonnet-20250219",
+    "claude-sonnet-4-5-20250929",
+    "claude-opus-4-1",
+];
+
+/// 路由审计的一行结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteAuditRow {
+    pub input: String,
+    pub primary: String,
+    pub rule: String,
+}
+
+/// 预检审计：列出内置模型、自定义映射键与家族采样输入最终解析到的主模型及命中规则
+pub fn audit_routes(
+    config: &crate::proxy::config::ProxyConfig,
+    apply_claude_family_mapping: bool,
+) -> Vec<RouteAuditRow> {
+    let mut inputs: std::collections::BTreeSet<String> = get_supported_models().into_iter().collect();
+    inputs.extend(
+        config
+            .custom_mapping
+            .keys()
+            .filter(|k| !k.contains('*'))
+            .cloned(),
+    );
+    inputs.extend(ROUTE_AUDIT_SAMPLE_INPUTS.iter().map(|s| s.to_string()));
+    let model_strategies = config.effective_model_strategies();
+    let deprecation = config.model_deprecation_policy();
+    let route_config = ModelRouteConfig {
+        custom_mapping: &config.custom_mapping,
+        openai_mapping: &config.openai_mapping,
+        openai_family_rules: &config.openai_family_rules,
+        anthropic_mapping: &config.anthropic_mapping,
+        model_strategies: &model_strategies,
+        deprecation: &deprecation,
+        default_policy: &config.default_fallback_policy,
+        thinking_passthrough_prefixes: &config.thinking_passthrough_prefixes,
+        family_overrides_builtin: config.family_overrides_builtin,
+    };
+
+    inputs
+        .into_iter()
+        .map(|input| {
+            let (target, rule) = explain_model_route(&input, &route_config, apply_claude_family_mapping);
+            let (primary, rule) = match extract_strategy_id(&target) {
+                Some(strategy_id) => match strategy_primary(strategy_id, &model_strategies) {
+                    Some(primary) => (primary, format!("{} -> strategy {}", rule, strategy_id)),
+                    None => (
+                        classify_builtin(&input, &config.thinking_passthrough_prefixes).0,
+                        format!("{} -> invalid strategy {}", rule, strategy_id),
+                    ),
+                },
+                None => (target, rule.to_string()),
+            };
+            RouteAuditRow { input, primary, rule }
+        })
+        .collect()
+}
+
+/// 渲染路由审计表
+pub fn render_route_table(rows: &[RouteAuditRow]) -> String {
+    let mut out = format!("{:<40} {:<32} {}\n", "INPUT", "PRIMARY", "RULE");
+    out.push_str(&"-".repeat(100));
+    out.push('\n');
+    for row in rows {
+        out.push_str(&format!("{:<40} {:<32} {}\n", row.input, row.primary, row.rule));
+    }
+    out
+}
+
+/// 模型能力分类 (用于策略候选一致性校验)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ModelCapability {
+    Text,
+    Thinking,
+    Image,
+}
+
+impl ModelCapability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModelCapability::Text => "text",
+            ModelCapability::Thinking => "thinking",
+            ModelCapability::Image => "image",
+        }
+    }
+}
+
+/// 按模型名推断能力分类，规则与请求转换保持一致：
+/// - `gemini-3-pro-image*` 走图像生成
+/// - 带 `-thinking` 后缀的模型支持思维链
+pub fn model_capability(model: &str) -> ModelCapability {
+    let lower = model.to_lowercase();
+    if lower.starts_with("gemini-3-pro-image") {
+        ModelCapability::Image
+    } else if lower.contains("-thinking") {
+        ModelCapability::Thinking
+    } else {
+        ModelCapability::Text
+    }
+}
+
+/// 思维链变体对应的基础模型名：去掉末尾的 `-thinking` 及其后的预算档位
+/// (`-low` / `-medium` / `-high` / 数字 token 预算)；不是变体时返回 None
+pub fn variant_base_model(model: &str) -> Option<String> {
+    let lower = model.to_ascii_lowercase();
+    let idx = lower.rfind("-thinking")?;
+    let rest = &lower[idx + "-thinking".len()..];
+    let is_budget = |s: &str| {
+        matches!(s, "low" | "medium" | "high") || (!s.is_empty() && s.chars().all(|c| c.is_ascii_digit()))
+    };
+    let is_variant = rest.is_empty() || rest.strip_prefix('-').is_some_and(is_budget);
+    (is_variant && idx > 0).then(|| model[..idx].to_string())
+}
+
+/// 检查策略候选是否跨越不兼容的能力分类 (仅提示，不阻止加载)
+/// 返回按策略 ID 排序的警告信息
+pub fn validate_strategy_capabilities(
+    strategies: &std::collections::HashMap<String, ModelStrategy>,
+) -> Vec<String> {
+    let mut ids: Vec<&String> = strategies.keys().collect();
+    ids.sort();
+
+    let mut warnings = Vec::new();
+    for id in ids {
+        let mut classes: Vec<(ModelCapability, &str)> = strategies[id]
+            .candidates
+            .iter()
+            .map(|c| c.trim())
+            .filter(|c| !c.is_empty() && !c.starts_with("strategy:"))
+            .map(|c| (model_capability(c), c))
+            .collect();
+        classes.sort_by_key(|(cap, _)| *cap);
+        classes.dedup_by_key(|(cap, _)| *cap);
+
+        if classes.len() > 1 {
+            let detail = classes
+                .iter()
+                .map(|(cap, model)| format!("{} ({})", cap.as_str(), model))
+                .collect::<Vec<_>>()
+                .join(", ");
+            warnings.push(format!(
+                "Strategy '{}' mixes incompatible candidate capabilities: {}",
+                id, detail
+            ));
+        }
     }
-    result
+    warnings
+}
+
+/// 结合全局覆盖决定是否应用 Claude 家族映射
+/// `override_flag` 来自 `ProxyConfig::family_mapping_override`，优先于客户端检测结果
+pub fn effective_family_mapping(detected: bool, override_flag: Option<bool>) -> bool {
+    override_flag.unwrap_or(detected)
 }
 
 #[derive(Debug, Clone)]
@@ -283,34 +921,219 @@ impl ModelRoutePlan {
     }
 }
 
+/// 推理档位后缀 (按成本/容量从低到高)，未带后缀的基础模型视为中间档
+/// 例如 `gemini-3-pro-low` < `gemini-3-pro` < `gemini-3-pro-high`
+fn reasoning_tier(model: &str) -> (&str, u8) {
+    if let Some(base) = model.strip_suffix("-low") {
+        (base, 0)
+    } else if let Some(base) = model.strip_suffix("-high") {
+        (base, 2)
+    } else {
+        (model, 1)
+    }
+}
+
+/// 容量优先时的候选重排：同一基础模型的多个推理档位，低档位优先 (容量更充足)
+/// 仅在同组成员原本占据的位置之间调整顺序，不同基础模型之间的相对顺序保持不变
+pub fn reorder_for_capacity(candidates: &mut [String]) {
+    let mut groups: HashMap<String, Vec<usize>> = HashMap::new();
+    for (idx, model) in candidates.iter().enumerate() {
+        let (base, _) = reasoning_tier(model);
+        groups.entry(base.to_string()).or_default().push(idx);
+    }
+
+    for slots in groups.values().filter(|slots| slots.len() > 1) {
+        let mut members: Vec<String> = slots.iter().map(|&i| candidates[i].clone()).collect();
+        members.sort_by_key(|m| reasoning_tier(m).1);
+        for (&slot, model) in slots.iter().zip(members) {
+            candidates[slot] = model;
+        }
+    }
+}
+
 fn extract_strategy_id(value: &str) -> Option<&str> {
     value.strip_prefix("strategy:")
 }
 
-pub fn resolve_model_route_plan(
-    original_model: &str,
-    custom_mapping: &std::collections::HashMap<String, String>,
-    openai_mapping: &std::collections::HashMap<String, String>,
-    anthropic_mapping: &std::collections::HashMap<String, String>,
+/// 策略的有效候选列表 (已按模型优先级排序)
+fn strategy_candidates(strategy: &ModelStrategy) -> Vec<String> {
+    let mut candidates: Vec<String> = strategy
+        .candidates
+        .iter()
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty() && !c.starts_with("strategy:"))
+        .collect();
+    // 精度优先保持配置顺序；容量优先时低推理档位优先
+    if strategy.policy.model_priority == ModelPriority::CapacityFirst {
+        reorder_for_capacity(&mut candidates);
+    }
+    // 自适应模式按历史成功率重排 (成功率相同时保持上面的顺序)
+    if strategy.policy.selection_mode == SelectionMode::AdaptiveSuccess {
+        crate::proxy::common::candidate_stats::CandidateStats::global().order_by_success(&mut candidates);
+    }
+    candidates
+}
+
+fn strategy_primary(
+    strategy_id: &str,
     model_strategies: &std::collections::HashMap<String, ModelStrategy>,
-    apply_claude_family_mapping: bool,
-) -> ModelRoutePlan {
-    let target = resolve_model_route(
-        original_model,
-        custom_mapping,
-        openai_mapping,
-        anthropic_mapping,
-        apply_claude_family_mapping,
+) -> Option<String> {
+    model_strategies
+        .get(strategy_id)
+        .and_then(|s| strategy_candidates(s).into_iter().next())
+}
+
+/// 策略 A/B 实验的分组结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExperimentAssignment {
+    /// 命中的实验 (模型模式)
+    pub experiment: String,
+    /// `a` 或 `b`
+    pub arm: &'static str,
+    pub strategy_id: String,
+}
+
+impl ExperimentAssignment {
+    /// 写入请求日志的分组标签
+    pub fn label(&self) -> String {
+        format!("{}:{}", self.experiment, self.arm)
+    }
+}
+
+/// 按原始请求模型名匹配实验 (精确匹配优先，其次最具体的通配符)，`roll` 取值 0-99，小于 `a_percent` 时分到 A 组
+pub fn assign_experiment_arm(
+    original_model: &str,
+    experiments: &HashMap<String, StrategyExperiment>,
+    roll: u8,
+) -> Option<ExperimentAssignment> {
+    let (pattern, experiment) =
+        crate::proxy::mappers::common_utils::resolve_model_key_value(original_model, experiments)?;
+    let (arm, strategy_id) = if roll < experiment.a_percent {
+        ("a", &experiment.arm_a)
+    } else {
+        ("b", &experiment.arm_b)
+    };
+    Some(ExperimentAssignment {
+        experiment: pattern.clone(),
+        arm,
+        strategy_id: strategy_id.clone(),
+    })
+}
+
+/// 命中 A/B 实验时随机分组并返回所选策略的路由计划，分组同时记入请求日志
+/// 未命中或所选策略不可用时返回 None，由调用方走常规路由
+pub fn resolve_experiment_plan(
+    original_model: &str,
+    experiments: &HashMap<String, StrategyExperiment>,
+    model_strategies: &HashMap<String, ModelStrategy>,
+) -> Option<ModelRoutePlan> {
+    if experiments.is_empty() {
+        return None;
+    }
+    let roll = rand::Rng::gen_range(&mut rand::thread_rng(), 0..100);
+    let assignment = assign_experiment_arm(original_model, experiments, roll)?;
+    let plan = plan_for_target(
+        format!("strategy:{}", assignment.strategy_id),
+        model_strategies,
+        &ModelFallbackPolicy::default(),
+        || original_model.to_string(),
     );
+    plan.strategy_id.as_ref()?;
+
+    crate::proxy::middleware::monitor::record_experiment_arm(assignment.label());
+    crate::proxy::middleware::request_trace::record(format!(
+        "experiment {} arm {} -> strategy {} {:?}",
+        assignment.experiment,
+        assignment.arm,
+        assignment.strategy_id,
+        plan.candidates()
+    ));
+    Some(plan)
+}
+
+/// 模型路由所需的映射表与策略配置 (由调用方从热更新状态中借用)
+pub struct ModelRouteConfig<'a> {
+    pub custom_mapping: &'a std::collections::HashMap<String, String>,
+    pub openai_mapping: &'a std::collections::HashMap<String, String>,
+    pub openai_family_rules: &'a [OpenAIFamilyRule],
+    pub anthropic_mapping: &'a std::collections::HashMap<String, String>,
+    pub model_strategies: &'a std::collections::HashMap<String, ModelStrategy>,
+    pub deprecation: &'a ModelDeprecationPolicy,
+    pub default_policy: &'a ModelFallbackPolicy,
+    /// 允许 thinking 直通的模型名前缀
+    pub thinking_passthrough_prefixes: &'a [String],
+    /// 用户配置的 Anthropic 家族映射是否优先于内置直通模型
+    pub family_overrides_builtin: bool,
+}
+
+pub fn resolve_model_route_plan(
+    original_model: &str,
+    config: &ModelRouteConfig,
+    apply_claude_family_mapping: bool,
+) -> ModelRoutePlan {
+    let target = resolve_model_route(original_model, config, apply_claude_family_mapping);
+    let plan = plan_for_target(target, config.model_strategies, config.default_policy, || {
+        strategy_fallback_target(original_model, config, apply_claude_family_mapping)
+    });
+    crate::proxy::middleware::request_trace::record(format!(
+        "route {} -> {:?}{}",
+        original_model,
+        plan.candidates(),
+        plan.strategy_id.as_deref().map(|id| format!(" (strategy {})", id)).unwrap_or_default()
+    ));
+    plan
+}
 
+/// 与 `resolve_model_route_plan` 相同的解析结果，同时返回命中的规则
+/// 不记录路由日志与兜底计数，供模型详情等只读查询使用
+pub fn explain_model_route_plan(
+    original_model: &str,
+    config: &ModelRouteConfig,
+    apply_claude_family_mapping: bool,
+) -> (ModelRoutePlan, RouteRule) {
+    let (target, rule) = explain_model_route(original_model, config, apply_claude_family_mapping);
+    let target = deprecation_target(target, config.deprecation);
+    let plan = plan_for_target(target, config.model_strategies, config.default_policy, || {
+        strategy_fallback_target(original_model, config, apply_claude_family_mapping)
+    });
+    (plan, rule)
+}
+
+/// 策略不存在或无可用候选时的兜底目标
+/// 跳过 (指向该策略的) 自定义映射，重新走 OpenAI/Anthropic 家族映射，最后才是系统默认映射
+fn strategy_fallback_target(
+    original_model: &str,
+    config: &ModelRouteConfig,
+    apply_claude_family_mapping: bool,
+) -> String {
+    let without_custom = ModelRouteConfig {
+        custom_mapping: &HashMap::new(),
+        ..*config
+    };
+    let (target, rule) = explain_model_route(original_model, &without_custom, apply_claude_family_mapping);
+    // 家族映射本身指向策略时不再展开，避免循环
+    if extract_strategy_id(&target).is_some() {
+        return map_claude_model_to_gemini(original_model, config.thinking_passthrough_prefixes);
+    }
+    crate::modules::logger::log_info(&format!(
+        "[Router] 策略兜底 ({}): {} -> {}",
+        rule, original_model, target
+    ));
+    target
+}
+
+/// 将路由目标展开为执行计划 (`strategy:<id>` 展开为策略候选)
+/// 策略无法展开时由 `fallback` 给出兜底目标
+/// 非策略目标 (以及策略不可用时的兜底) 使用 `default_policy`
+fn plan_for_target(
+    target: String,
+    model_strategies: &std::collections::HashMap<String, ModelStrategy>,
+    default_policy: &ModelFallbackPolicy,
+    fallback: impl FnOnce() -> String,
+) -> ModelRoutePlan {
     if let Some(strategy_id) = extract_strategy_id(&target) {
         if let Some(strategy) = model_strategies.get(strategy_id) {
-            let mut candidates: Vec<String> = strategy
-                .candidates
-                .iter()
-                .map(|c| c.trim().to_string())
-                .filter(|c| !c.is_empty() && !c.starts_with("strategy:"))
-                .collect();
+            let mut candidates = strategy_candidates(strategy);
             if !candidates.is_empty() {
                 let primary = candidates.remove(0);
                 return ModelRoutePlan {
@@ -321,12 +1144,12 @@ pub fn resolve_model_route_plan(
                 };
             }
             crate::modules::logger::log_warn(&format!(
-                "[Router] Strategy '{}' has no valid candidates, falling back to default mapping.",
+                "[Router] Strategy '{}' has no valid candidates, falling back to family/default mapping.",
                 strategy_id
             ));
         } else {
             crate::modules::logger::log_warn(&format!(
-                "[Router] Strategy '{}' not found, falling back to default mapping.",
+                "[Router] Strategy '{}' not found, falling back to family/default mapping.",
                 strategy_id
             ));
         }
@@ -334,12 +1157,12 @@ pub fn resolve_model_route_plan(
 
     ModelRoutePlan {
         primary: if target.starts_with("strategy:") {
-            map_claude_model_to_gemini(original_model)
+            fallback()
         } else {
             target
         },
         fallbacks: Vec::new(),
-        policy: ModelFallbackPolicy::default(),
+        policy: default_policy.clone(),
         strategy_id: None,
     }
 }
@@ -347,28 +1170,187 @@ pub fn resolve_model_route_plan(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::proxy::config::default_openai_family_rules;
 
     #[test]
     fn test_model_mapping() {
         assert_eq!(
-            map_claude_model_to_gemini("claude-3-5-sonnet-20241022"),
+            map_claude_model_to_gemini("claude-3-5-sonnet-20241022", &default_thinking_passthrough_prefixes()),
             "claude-sonnet-4-5"
         );
         assert_eq!(
-            map_claude_model_to_gemini("claude-opus-4"),
+            map_claude_model_to_gemini("claude-opus-4", &default_thinking_passthrough_prefixes()),
             "claude-opus-4-5-thinking"
         );
         // Test gemini pass-through (should not be caught by "mini" rule)
         assert_eq!(
-            map_claude_model_to_gemini("gemini-2.5-flash-mini-test"),
+            map_claude_model_to_gemini("gemini-2.5-flash-mini-test", &default_thinking_passthrough_prefixes()),
             "gemini-2.5-flash-mini-test"
         );
         assert_eq!(
-            map_claude_model_to_gemini("unknown-model"),
+            map_claude_model_to_gemini("unknown-model", &default_thinking_passthrough_prefixes()),
             "claude-sonnet-4-5"
         );
     }
 
+    #[test]
+    fn test_thinking_passthrough_requires_known_prefix() {
+        assert_eq!(map_claude_model_to_gemini("claude-opus-5-thinking", &default_thinking_passthrough_prefixes()), "claude-opus-5-thinking");
+        assert_eq!(map_claude_model_to_gemini("gpt-5-thinking", &default_thinking_passthrough_prefixes()), "gpt-5-thinking");
+        // 未知前缀的 *-thinking 名称走兜底而非直通
+        assert_eq!(map_claude_model_to_gemini("my-thinking-experiment", &default_thinking_passthrough_prefixes()), DEFAULT_FALLBACK_MODEL);
+        assert_eq!(map_claude_model_to_gemini("foo-thinking", &default_thinking_passthrough_prefixes()), DEFAULT_FALLBACK_MODEL);
+
+        let custom = vec!["acme-".to_string()];
+        assert!(is_thinking_passthrough("acme-r1-thinking", &custom));
+        assert!(!is_thinking_passthrough("claude-opus-5-thinking", &custom));
+        assert!(!is_thinking_passthrough("acme-r1", &custom));
+        assert!(!is_thinking_passthrough("x-thinking", &["".to_string()]));
+    }
+
+    #[test]
+    fn test_audit_routes_rows() {
+        let mut config = crate::proxy::config::ProxyConfig::default();
+        config.custom_mapping.insert("my-model".to_string(), "gemini-3-flash".to_string());
+        config.custom_mapping.insert("team-*".to_string(), "gemini-2.5-pro".to_string());
+        config.custom_mapping.insert("gpt-4".to_string(), "strategy:smart".to_string());
+        config.model_strategies.insert(
+            "smart".to_string(),
+            ModelStrategy {
+                candidates: vec!["gemini-3-pro-high".to_string(), "gemini-2.5-flash".to_string()],
+                policy: ModelFallbackPolicy::default(),
+            },
+        );
+        config.openai_mapping.insert("gpt-4o-series".to_string(), "gemini-3-flash".to_string());
+        config.anthropic_mapping.insert("claude-4.5-series".to_string(), "gemini-3-pro-high".to_string());
+
+        let rows = audit_routes(&config, false);
+        let row = |input: &str| rows.iter().find(|r| r.input == input).cloned().unwrap();
+
+        // 自定义精确映射
+        assert_eq!(row("my-model").primary, "gemini-3-flash");
+        assert_eq!(row("my-model").rule, "custom (exact)");
+        // 通配符键本身不作为输入
+        assert!(rows.iter().all(|r| r.input != "team-*"));
+        // 策略展开为首个候选
+        assert_eq!(row("gpt-4").primary, "gemini-3-pro-high");
+        assert_eq!(row("gpt-4").rule, "custom (exact) -> strategy smart");
+        // OpenAI 家族采样
+        assert_eq!(row("gpt-4o-2024-11-20").primary, "gemini-3-flash");
+        assert_eq!(row("gpt-4o-2024-11-20").rule, "openai family gpt-4o-series (rule *4o*)");
+        // 内置别名与兜底
+        assert_eq!(row("claude-3-5-sonnet-20241022").primary, "claude-sonnet-4-5");
+        assert_eq!(row("claude-3-5-sonnet-20241022").rule, "builtin alias");
+        assert_eq!(row("claude-opus-4-1").rule, "default fallback");
+
+        // 开启 Claude 家族映射
+        let rows = audit_routes(&config, true);
+        let row = |input: &str| rows.iter().find(|r| r.input == input).cloned().unwrap();
+        assert_eq!(row("claude-sonnet-4-5-20250929").primary, "gemini-3-pro-high");
+        assert_eq!(row("claude-sonnet-4-5-20250929").rule, "anthropic family claude-4.5-series");
+        assert_eq!(row("claude-3-5-haiku-20241022").rule, "haiku downgrade");
+        assert_eq!(row("claude-sonnet-4-5").rule, "claude passthrough");
+
+        let table = render_route_table(&rows);
+        assert!(table.starts_with("INPUT"));
+        assert!(table.lines().any(|l| l.starts_with("my-model") && l.contains("gemini-3-flash")));
+    }
+
+    #[test]
+    fn test_default_fallback_counts_only_unmapped_models() {
+        let tracker = DefaultFallbackTracker::new();
+
+        assert_eq!(map_claude_model_to_gemini_tracked("claude-3-5-sonnet-20241022", &default_thinking_passthrough_prefixes(), &tracker), "claude-sonnet-4-5");
+        assert_eq!(map_claude_model_to_gemini_tracked("gemini-2.5-flash", &default_thinking_passthrough_prefixes(), &tracker), "gemini-2.5-flash");
+        assert_eq!(map_claude_model_to_gemini_tracked("claude-sonnet-4-6-thinking", &default_thinking_passthrough_prefixes(), &tracker), "claude-sonnet-4-6-thinking");
+        assert_eq!(tracker.count(), 0);
+
+        assert_eq!(map_claude_model_to_gemini_tracked("unknown-model", &default_thinking_passthrough_prefixes(), &tracker), DEFAULT_FALLBACK_MODEL);
+        assert_eq!(tracker.count(), 1);
+        // 告警限流不影响计数
+        map_claude_model_to_gemini_tracked("another-unknown", &default_thinking_passthrough_prefixes(), &tracker);
+        assert_eq!(tracker.count(), 2);
+
+        tracker.reset();
+        assert_eq!(tracker.count(), 0);
+    }
+
+    fn deprecation_policy(auto_replace: bool) -> ModelDeprecationPolicy {
+        ModelDeprecationPolicy {
+            models: vec![
+                DeprecatedModel {
+                    model: "gemini-2.0-flash-exp".to_string(),
+                    replacement: Some("gemini-2.5-flash".to_string()),
+                },
+                DeprecatedModel { model: "gemini-1.5-*".to_string(), replacement: None },
+            ],
+            auto_replace,
+        }
+    }
+
+    fn route_custom(model: &str, target: &str, policy: &ModelDeprecationPolicy) -> String {
+        let mut custom = HashMap::new();
+        custom.insert(model.to_string(), target.to_string());
+        resolve_model_route(
+            model,
+            &ModelRouteConfig {
+                custom_mapping: &custom,
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &[],
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &HashMap::new(),
+                deprecation: policy,
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
+            false,
+        )
+    }
+
+    #[test]
+    fn test_deprecated_target_warns_and_counts() {
+        let policy = deprecation_policy(false);
+        let before = deprecated_route_count();
+
+        assert_eq!(route_custom("my-flash", "gemini-2.0-flash-exp", &policy), "gemini-2.0-flash-exp");
+        assert_eq!(route_custom("my-pro", "Gemini-1.5-Pro", &policy), "Gemini-1.5-Pro");
+        assert!(deprecated_route_count() >= before + 2);
+
+        assert_eq!(route_custom("my-new", "gemini-2.5-flash", &policy), "gemini-2.5-flash");
+        assert!(find_deprecated_model("gemini-2.5-flash", &policy).is_none());
+    }
+
+    #[test]
+    fn test_deprecated_target_auto_replaced() {
+        let policy = deprecation_policy(true);
+
+        assert_eq!(route_custom("my-flash", "gemini-2.0-flash-exp", &policy), "gemini-2.5-flash");
+        // 无替代模型时仅告警
+        assert_eq!(route_custom("my-pro", "gemini-1.5-pro", &policy), "gemini-1.5-pro");
+
+        // 只读解析与实际路由保持一致
+        let mut custom = HashMap::new();
+        custom.insert("my-flash".to_string(), "gemini-2.0-flash-exp".to_string());
+        let (plan, rule) = explain_model_route_plan(
+            "my-flash",
+            &ModelRouteConfig {
+                custom_mapping: &custom,
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &[],
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &HashMap::new(),
+                deprecation: &policy,
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
+            false,
+        );
+        assert_eq!(plan.primary, "gemini-2.5-flash");
+        assert_eq!(rule, RouteRule::CustomExact);
+    }
+
     #[test]
     fn test_strategy_route_plan_resolves_candidates_and_policy() {
         let mut custom_mapping = HashMap::new();
@@ -386,16 +1368,24 @@ mod tests {
                     model_priority: ModelPriority::CapacityFirst,
                     stickiness: crate::proxy::config::ModelStickiness::Weak,
                     max_model_hops: Some(1),
+                    ..ModelFallbackPolicy::default()
                 },
             },
         );
 
         let plan = resolve_model_route_plan(
             "gpt-4",
-            &custom_mapping,
-            &HashMap::new(),
-            &HashMap::new(),
-            &strategies,
+            &ModelRouteConfig {
+                custom_mapping: &custom_mapping,
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &default_openai_family_rules(),
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &strategies,
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
             false,
         );
 
@@ -406,6 +1396,119 @@ mod tests {
         assert_eq!(plan.max_models(), 1);
     }
 
+    fn tiered_strategy(priority: ModelPriority) -> HashMap<String, ModelStrategy> {
+        let mut strategies = HashMap::new();
+        strategies.insert(
+            "tiers".to_string(),
+            ModelStrategy {
+                candidates: vec![
+                    "gemini-3-pro-high".to_string(),
+                    "gemini-3-flash".to_string(),
+                    "gemini-3-pro-low".to_string(),
+                ],
+                policy: ModelFallbackPolicy {
+                    model_priority: priority,
+                    ..ModelFallbackPolicy::default()
+                },
+            },
+        );
+        strategies
+    }
+
+    #[test]
+    fn test_capacity_first_prefers_low_reasoning_tier() {
+        let mut custom_mapping = HashMap::new();
+        custom_mapping.insert("gpt-4".to_string(), "strategy:tiers".to_string());
+
+        let plan = resolve_model_route_plan(
+            "gpt-4",
+            &ModelRouteConfig {
+                custom_mapping: &custom_mapping,
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &default_openai_family_rules(),
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &tiered_strategy(ModelPriority::CapacityFirst),
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
+            false,
+        );
+        assert_eq!(
+            plan.candidates(),
+            vec!["gemini-3-pro-low", "gemini-3-flash", "gemini-3-pro-high"]
+        );
+
+        let plan = resolve_model_route_plan(
+            "gpt-4",
+            &ModelRouteConfig {
+                custom_mapping: &custom_mapping,
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &default_openai_family_rules(),
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &tiered_strategy(ModelPriority::AccuracyFirst),
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
+            false,
+        );
+        assert_eq!(
+            plan.candidates(),
+            vec!["gemini-3-pro-high", "gemini-3-flash", "gemini-3-pro-low"]
+        );
+    }
+
+    #[test]
+    fn test_empty_strategy_falls_back_to_family_mapping() {
+        let mut custom_mapping = HashMap::new();
+        custom_mapping.insert("claude-sonnet-4-5-20250929".to_string(), "strategy:empty".to_string());
+        let mut anthropic_mapping = HashMap::new();
+        anthropic_mapping.insert("claude-4.5-series".to_string(), "gemini-3-pro-high".to_string());
+        let mut strategies = HashMap::new();
+        strategies.insert(
+            "empty".to_string(),
+            ModelStrategy { candidates: Vec::new(), policy: ModelFallbackPolicy::default() },
+        );
+
+        let plan = resolve_model_route_plan(
+            "claude-sonnet-4-5-20250929",
+            &ModelRouteConfig {
+                custom_mapping: &custom_mapping,
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &default_openai_family_rules(),
+                anthropic_mapping: &anthropic_mapping,
+                model_strategies: &strategies,
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
+            true,
+        );
+
+        assert_eq!(plan.primary, "gemini-3-pro-high");
+        assert!(plan.fallbacks.is_empty());
+        assert!(plan.strategy_id.is_none());
+    }
+
+    #[test]
+    fn test_reorder_for_capacity_handles_base_variant() {
+        let mut candidates = vec![
+            "gemini-3-pro-high".to_string(),
+            "gemini-3-pro".to_string(),
+            "gemini-3-pro-low".to_string(),
+            "gemini-3-pro-image".to_string(),
+        ];
+        reorder_for_capacity(&mut candidates);
+        assert_eq!(
+            candidates,
+            vec!["gemini-3-pro-low", "gemini-3-pro", "gemini-3-pro-high", "gemini-3-pro-image"]
+        );
+    }
+
     #[test]
     fn test_strategy_route_plan_missing_strategy_falls_back() {
         let mut custom_mapping = HashMap::new();
@@ -413,10 +1516,17 @@ mod tests {
 
         let plan = resolve_model_route_plan(
             "claude-3-5-sonnet-20241022",
-            &custom_mapping,
-            &HashMap::new(),
-            &HashMap::new(),
-            &HashMap::new(),
+            &ModelRouteConfig {
+                custom_mapping: &custom_mapping,
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &default_openai_family_rules(),
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &HashMap::new(),
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
             false,
         );
 
@@ -424,4 +1534,652 @@ mod tests {
         assert!(plan.fallbacks.is_empty());
         assert!(plan.strategy_id.is_none());
     }
+
+    fn route_openai(model: &str, openai_mapping: &HashMap<String, String>, rules: &[OpenAIFamilyRule]) -> String {
+        resolve_model_route(
+            model,
+            &ModelRouteConfig {
+                custom_mapping: &HashMap::new(),
+                openai_mapping,
+                openai_family_rules: rules,
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &HashMap::new(),
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
+            false,
+        )
+    }
+
+    #[test]
+    fn test_default_openai_family_rules_keep_existing_grouping() {
+        let mut openai_mapping = HashMap::new();
+        openai_mapping.insert("gpt-4-series".to_string(), "gemini-3-pro-high".to_string());
+        openai_mapping.insert("gpt-4o-series".to_string(), "gemini-3-flash".to_string());
+        let rules = default_openai_family_rules();
+
+        assert_eq!(route_openai("gpt-4", &openai_mapping, &rules), "gemini-3-pro-high");
+        assert_eq!(route_openai("o1-preview", &openai_mapping, &rules), "gemini-3-pro-high");
+        assert_eq!(route_openai("gpt-4o-2024-08-06", &openai_mapping, &rules), "gemini-3-flash");
+        assert_eq!(route_openai("gpt-4-turbo", &openai_mapping, &rules), "gemini-3-flash");
+        assert_eq!(route_openai("gpt-3.5-turbo", &openai_mapping, &rules), "gemini-3-flash");
+        // 未配置 gpt-5-series 时回退到 gpt-4-series
+        assert_eq!(route_openai("gpt-5.1", &openai_mapping, &rules), "gemini-3-pro-high");
+        // Gemini 模型名包含 "mini" 也不应被归入 gpt-4o-series
+        assert_eq!(route_openai("gemini-2.5-flash", &openai_mapping, &rules), "gemini-2.5-flash");
+    }
+
+    #[test]
+    fn test_gpt4_family_rule_excludes_4o_mini_turbo() {
+        // 只配置 gpt-4-series 时，4o/mini/turbo 不应被归入 GPT-4 系列
+        let mut openai_mapping = HashMap::new();
+        openai_mapping.insert("gpt-4-series".to_string(), "gemini-3-pro-high".to_string());
+        let rules = default_openai_family_rules();
+
+        assert_eq!(route_openai("gpt-4", &openai_mapping, &rules), "gemini-3-pro-high");
+        assert_eq!(route_openai("gpt-4-0613", &openai_mapping, &rules), "gemini-3-pro-high");
+        for model in ["gpt-4o", "gpt-4o-mini", "gpt-4-turbo"] {
+            let unmapped = route_openai(model, &HashMap::new(), &rules);
+            assert_eq!(route_openai(model, &openai_mapping, &rules), unmapped, "{}", model);
+            assert_ne!(unmapped, "gemini-3-pro-high");
+        }
+    }
+
+    #[test]
+    fn test_custom_family_rule_routes_new_model_family() {
+        let mut openai_mapping = HashMap::new();
+        openai_mapping.insert("gpt-6-series".to_string(), "gemini-3-pro-high".to_string());
+        openai_mapping.insert("gpt-4o-series".to_string(), "gemini-3-flash".to_string());
+
+        // 未添加规则时，gpt-6-turbo 只会命中 *turbo*
+        let mut rules = default_openai_family_rules();
+        assert_eq!(route_openai("gpt-6-turbo", &openai_mapping, &rules), "gemini-3-flash");
+
+        rules.insert(0, OpenAIFamilyRule::new("gpt-6", "gpt-6-series"));
+        assert_eq!(route_openai("gpt-6-turbo", &openai_mapping, &rules), "gemini-3-pro-high");
+        assert_eq!(route_openai("GPT-6", &openai_mapping, &rules), "gemini-3-pro-high");
+    }
+
+    #[test]
+    fn test_family_rule_matches() {
+        let matches = |pattern: &str, model: &str| glob_match(&OpenAIFamilyRule::new(pattern, "k").glob_pattern(), model);
+        assert!(matches("gpt-6", "gpt-6-turbo"));
+        assert!(matches("GPT-6", "gpt-6"));
+        assert!(matches("*4o*", "chatgpt-4o-latest"));
+        assert!(matches("o*-mini", "o4-mini"));
+        assert!(!matches("*mini*", "gpt-4"));
+        assert!(!matches("a*b*c", "ac"));
+    }
+
+    fn family_anthropic_mapping() -> HashMap<String, String> {
+        let mut anthropic_mapping = HashMap::new();
+        anthropic_mapping.insert("claude-4.5-series".to_string(), "gemini-3-pro-high".to_string());
+        anthropic_mapping
+    }
+
+    fn route_with_override(detected_cli: bool, override_flag: Option<bool>) -> String {
+        resolve_model_route(
+            "claude-opus-4-5-20251101",
+            &ModelRouteConfig {
+                custom_mapping: &HashMap::new(),
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &default_openai_family_rules(),
+                anthropic_mapping: &family_anthropic_mapping(),
+                model_strategies: &HashMap::new(),
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
+            effective_family_mapping(detected_cli, override_flag),
+        )
+    }
+
+    #[test]
+    fn test_disable_family_mapping_overrides_cli_detection() {
+        let config = crate::proxy::config::ProxyConfig {
+            disable_family_mapping: true,
+            ..Default::default()
+        };
+        assert_eq!(config.family_mapping_override(), Some(false));
+
+        // CLI 请求原本会走家族映射
+        assert_eq!(route_with_override(true, None), "gemini-3-pro-high");
+        // 全局禁用后直接穿透到系统默认映射
+        assert_eq!(
+            route_with_override(true, config.family_mapping_override()),
+            "claude-opus-4-5-thinking"
+        );
+    }
+
+    #[test]
+    fn test_force_family_mapping_overrides_non_cli_detection() {
+        let config = crate::proxy::config::ProxyConfig {
+            force_family_mapping: true,
+            ..Default::default()
+        };
+        assert_eq!(config.family_mapping_override(), Some(true));
+
+        assert_eq!(route_with_override(false, None), "claude-opus-4-5-thinking");
+        assert_eq!(
+            route_with_override(false, config.family_mapping_override()),
+            "gemini-3-pro-high"
+        );
+    }
+
+    #[test]
+    fn test_family_overrides_builtin_controls_passthrough_ordering() {
+        // claude-sonnet-4-5 既是内置直通模型，又命中 claude-4.5-series 家族映射
+        let route = |family_overrides_builtin| {
+            explain_model_route(
+                "claude-sonnet-4-5",
+                &ModelRouteConfig {
+                    custom_mapping: &HashMap::new(),
+                    openai_mapping: &HashMap::new(),
+                    openai_family_rules: &default_openai_family_rules(),
+                    anthropic_mapping: &family_anthropic_mapping(),
+                    model_strategies: &HashMap::new(),
+                    deprecation: &ModelDeprecationPolicy::default(),
+                    default_policy: &ModelFallbackPolicy::default(),
+                    thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                    family_overrides_builtin,
+                },
+                true,
+            )
+        };
+
+        // 默认：内置直通优先
+        assert_eq!(
+            route(false),
+            ("claude-sonnet-4-5".to_string(), RouteRule::ClaudePassthrough)
+        );
+        // 开启后：用户家族映射优先
+        assert_eq!(
+            route(true),
+            (
+                "gemini-3-pro-high".to_string(),
+                RouteRule::AnthropicFamily("claude-4.5-series".to_string())
+            )
+        );
+
+        // 未配置家族映射时，开启后仍保持直通
+        let (target, rule) = explain_model_route(
+            "claude-sonnet-4-5",
+            &ModelRouteConfig {
+                custom_mapping: &HashMap::new(),
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &default_openai_family_rules(),
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &HashMap::new(),
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: true,
+            },
+            true,
+        );
+        assert_eq!(target, "claude-sonnet-4-5");
+        assert_eq!(rule, RouteRule::ClaudePassthrough);
+    }
+
+    #[test]
+    fn test_family_mapping_flags_are_mutually_exclusive() {
+        let config = crate::proxy::config::ProxyConfig {
+            disable_family_mapping: true,
+            force_family_mapping: true,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        assert!(crate::proxy::config::ProxyConfig::default().validate().is_ok());
+    }
+
+    fn strategy(candidates: &[&str]) -> ModelStrategy {
+        ModelStrategy {
+            candidates: candidates.iter().map(|c| c.to_string()).collect(),
+            policy: ModelFallbackPolicy::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strategy_experiment_split_and_arm_logged() {
+        let mut strategies = HashMap::new();
+        strategies.insert("fast".to_string(), strategy(&["gemini-3-flash"]));
+        strategies.insert("deep".to_string(), strategy(&["gemini-3-pro-high", "gemini-3-flash"]));
+        let mut experiments = HashMap::new();
+        experiments.insert(
+            "gpt-4*".to_string(),
+            StrategyExperiment { arm_a: "fast".to_string(), arm_b: "deep".to_string(), a_percent: 30 },
+        );
+
+        let total = 10_000;
+        let mut arm_a = 0;
+        for _ in 0..total {
+            let (plan, arm) = crate::proxy::middleware::monitor::scope_experiment_arm(async {
+                resolve_experiment_plan("gpt-4o", &experiments, &strategies)
+            })
+            .await;
+            let plan = plan.unwrap();
+            match arm.as_deref() {
+                Some("gpt-4*:a") => {
+                    assert_eq!(plan.strategy_id.as_deref(), Some("fast"));
+                    arm_a += 1;
+                }
+                Some("gpt-4*:b") => assert_eq!(plan.strategy_id.as_deref(), Some("deep")),
+                other => panic!("unexpected arm: {:?}", other),
+            }
+        }
+        // 30% ± 3%
+        assert!((2_700..=3_300).contains(&arm_a), "arm a share: {}", arm_a);
+
+        // 未命中实验的模型走常规路由，不记录分组
+        let (plan, arm) = crate::proxy::middleware::monitor::scope_experiment_arm(async {
+            resolve_experiment_plan("claude-sonnet-4-5", &experiments, &strategies)
+        })
+        .await;
+        assert!(plan.is_none());
+        assert!(arm.is_none());
+    }
+
+    #[test]
+    fn test_experiment_overlapping_patterns_pick_most_specific() {
+        let experiment = |arm_a: &str| StrategyExperiment {
+            arm_a: arm_a.to_string(),
+            arm_b: "deep".to_string(),
+            a_percent: 100,
+        };
+        let mut experiments = HashMap::new();
+        experiments.insert("gpt-*".to_string(), experiment("generic"));
+        experiments.insert("gpt-4o*".to_string(), experiment("fast"));
+        experiments.insert("*-mini".to_string(), experiment("mini"));
+
+        for _ in 0..8 {
+            let assignment = assign_experiment_arm("gpt-4o-mini", &experiments, 0).unwrap();
+            assert_eq!(assignment.experiment, "gpt-4o*");
+            assert_eq!(assignment.strategy_id, "fast");
+        }
+        let assignment = assign_experiment_arm("gpt-3.5-turbo", &experiments, 0).unwrap();
+        assert_eq!(assignment.label(), "gpt-*:a");
+        assert_eq!(assignment.strategy_id, "generic");
+    }
+
+    #[test]
+    fn test_mixed_text_image_strategy_warns() {
+        let mut strategies = HashMap::new();
+        strategies.insert(
+            "mixed".to_string(),
+            strategy(&["gemini-3-flash", "gemini-3-pro-image"]),
+        );
+
+        let warnings = validate_strategy_capabilities(&strategies);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("'mixed'"));
+        assert!(warnings[0].contains("text (gemini-3-flash)"));
+        assert!(warnings[0].contains("image (gemini-3-pro-image)"));
+    }
+
+    #[test]
+    fn test_homogeneous_strategy_has_no_warning() {
+        let mut strategies = HashMap::new();
+        strategies.insert(
+            "text-only".to_string(),
+            strategy(&["gemini-3-pro-high", "gemini-3-flash", "gemini-2.5-flash"]),
+        );
+        strategies.insert(
+            "thinking-only".to_string(),
+            strategy(&["claude-opus-4-5-thinking", "claude-sonnet-4-5-thinking"]),
+        );
+
+        assert!(validate_strategy_capabilities(&strategies).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_advertise_all_aliases_lists_both_families() {
+        let mut custom = HashMap::new();
+        custom.insert("my-model".to_string(), "gemini-3-flash".to_string());
+        let custom = tokio::sync::RwLock::new(custom);
+
+        let all = get_all_dynamic_models(&custom, true).await;
+        for id in ["gpt-4o", "gpt-3.5-turbo", "claude-3-5-sonnet-20241022", "gemini-2.5-pro", "gemini-3-flash", "my-model"] {
+            assert!(all.contains(&id.to_string()), "missing {}", id);
+        }
+        // 去重且有序
+        let mut expected = all.clone();
+        expected.sort();
+        expected.dedup();
+        assert_eq!(all, expected);
+
+        let canonical = get_all_dynamic_models(&custom, false).await;
+        assert!(!canonical.contains(&"gpt-4o".to_string()));
+        assert!(!canonical.contains(&"claude-3-5-sonnet-20241022".to_string()));
+        assert!(canonical.contains(&"gemini-2.5-pro".to_string()));
+        assert!(canonical.contains(&"claude-sonnet-4-5".to_string()));
+        assert!(canonical.contains(&"my-model".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_servable_only_hides_premium_models_from_free_accounts() {
+        use crate::models::AccountProtocol;
+        use crate::proxy::config::ProxyConfig;
+        use crate::proxy::token_manager::TokenManager;
+
+        let data_dir = crate::proxy::tests::support::temp_data_dir("ag-servable");
+        for id in ["free-a", "free-b"] {
+            crate::proxy::tests::support::write_account(
+                &data_dir,
+                id,
+                serde_json::json!({ "quota": { "models": [], "last_updated": 0, "subscription_tier": "FREE" } }),
+            );
+        }
+        let manager = TokenManager::new(data_dir.clone());
+        manager.load_accounts().await.unwrap();
+
+        let config = ProxyConfig {
+            advertise_servable_only: true,
+            ..Default::default()
+        };
+        let filter = config.servable_models_filter();
+        let custom = tokio::sync::RwLock::new(HashMap::new());
+        let all = get_all_dynamic_models(&custom, true).await;
+        let resolve = |id: &str| classify_builtin(id, &default_thinking_passthrough_prefixes()).0;
+        let can_serve = |tier: Option<&str>| manager.can_serve_model(tier, AccountProtocol::Openai);
+
+        let servable = filter_servable_models(all.clone(), &filter, resolve, can_serve);
+        // 高级模型 (含指向高级模型的别名) 不再出现在列表中
+        for id in ["gemini-3-pro-high", "gemini-3-pro-image-4k", "claude-opus-4-5-thinking", "claude-opus-4"] {
+            assert!(all.contains(&id.to_string()), "missing {}", id);
+            assert!(!servable.contains(&id.to_string()), "premium model advertised: {}", id);
+        }
+        for id in ["gemini-3-flash", "gemini-3-pro-low", "claude-sonnet-4-5"] {
+            assert!(servable.contains(&id.to_string()), "missing {}", id);
+        }
+
+        // 未启用时列表不变
+        let disabled = ServableModelsFilter { enabled: false, ..filter };
+        assert_eq!(filter_servable_models(all.clone(), &disabled, resolve, can_serve), all);
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[test]
+    fn test_require_model_field_rejects_empty_and_whitespace() {
+        use crate::proxy::upstream::errors::ErrorProtocol;
+
+        for input in ["", " ", "\t\n  "] {
+            let err = require_model_field(input).unwrap_err();
+            assert_eq!(err.code(), "invalid_request");
+            assert_eq!(err.status(), axum::http::StatusCode::BAD_REQUEST);
+            assert_eq!(err.message(), "model field is required");
+
+            let anthropic = err.to_body(ErrorProtocol::Anthropic);
+            assert_eq!(anthropic["error"]["type"], "invalid_request_error");
+            let gemini = err.to_body(ErrorProtocol::Gemini);
+            assert_eq!(gemini["error"]["status"], "BAD_REQUEST");
+        }
+
+        assert_eq!(require_model_field("  gemini-3-flash ").unwrap(), "gemini-3-flash");
+    }
+
+    #[test]
+    fn test_omitted_model_uses_protocol_default() {
+        use crate::proxy::config::DefaultModelPerProtocol;
+        use crate::proxy::upstream::errors::ErrorProtocol;
+        use serde_json::json;
+
+        let defaults = DefaultModelPerProtocol {
+            openai: Some("gemini-3-flash".to_string()),
+            anthropic: Some("claude-sonnet-4-5".to_string()),
+            gemini: Some("gemini-2.5-flash".to_string()),
+        };
+
+        let mut body = json!({ "messages": [{ "role": "user", "content": "hi" }] });
+        fill_omitted_model(&mut body, defaults.for_protocol(ErrorProtocol::OpenAI));
+        let req: crate::proxy::mappers::openai::OpenAIRequest = serde_json::from_value(body).unwrap();
+        assert_eq!(require_model_field(&req.model).unwrap(), "gemini-3-flash");
+
+        let mut body = json!({ "model": null, "max_tokens": 16, "messages": [{ "role": "user", "content": "hi" }] });
+        fill_omitted_model(&mut body, defaults.for_protocol(ErrorProtocol::Anthropic));
+        let req: crate::proxy::mappers::claude::models::ClaudeRequest = serde_json::from_value(body).unwrap();
+        assert_eq!(require_model_field(&req.model).unwrap(), "claude-sonnet-4-5");
+
+        let model = fill_omitted_path_model(String::new(), defaults.for_protocol(ErrorProtocol::Gemini));
+        assert_eq!(require_model_field(&model).unwrap(), "gemini-2.5-flash");
+
+        // 显式传入的空字符串仍报错
+        let mut body = json!({ "model": "", "messages": [] });
+        fill_omitted_model(&mut body, defaults.for_protocol(ErrorProtocol::OpenAI));
+        assert_eq!(body["model"], "");
+        assert!(require_model_field(" ").is_err());
+        assert!(require_model_field(&fill_omitted_path_model(" ".to_string(), Some("gemini-2.5-flash"))).is_err());
+
+        // 未配置默认模型时省略字段同样报错
+        let mut body = json!({ "messages": [] });
+        fill_omitted_model(&mut body, DefaultModelPerProtocol::default().for_protocol(ErrorProtocol::OpenAI));
+        assert!(require_model_field(body["model"].as_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_client_model_allowlist() {
+        let allowed = vec!["gpt-4o".to_string(), "claude-sonnet-*".to_string()];
+
+        // 允许列表内的模型 (含通配符) 继续路由
+        assert!(check_client_model_allowed("gpt-4o", &allowed).is_ok());
+        assert!(check_client_model_allowed("claude-sonnet-4-5", &allowed).is_ok());
+
+        // 即使存在映射，列表外的模型也以 403 拒绝并列出允许的模型
+        let err = check_client_model_allowed("gemini-3-pro-high", &allowed).unwrap_err();
+        assert_eq!(err.code(), "model_not_allowed");
+        assert_eq!(err.status(), axum::http::StatusCode::FORBIDDEN);
+        assert!(err.message().contains("gpt-4o, claude-sonnet-*"));
+
+        // 空列表不限制
+        assert!(check_client_model_allowed("anything", &[]).is_ok());
+    }
+
+    #[test]
+    fn test_canonicalize_model_name_strips_decorations() {
+        let rules = ModelCanonicalizationConfig::default();
+        let cases = [
+            ("claude-3-5-sonnet-20241022@20241022", "claude-3-5-sonnet-20241022"),
+            ("anthropic/claude-3-5-sonnet", "claude-3-5-sonnet"),
+            ("Anthropic/claude-3-5-sonnet", "claude-3-5-sonnet"),
+            ("gpt-4o:latest", "gpt-4o"),
+            ("openai/gpt-4o:latest", "gpt-4o"),
+            ("anthropic/claude-opus-4@20251101:latest", "claude-opus-4"),
+            ("  gemini-2.5-pro  ", "gemini-2.5-pro"),
+            ("gemini-2.5-pro", "gemini-2.5-pro"),
+            // 全部被剥离时保留原值
+            ("anthropic/", "anthropic/"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(canonicalize_model_name(input, &rules), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_model_name_respects_ruleset() {
+        let disabled = ModelCanonicalizationConfig { enabled: false, ..Default::default() };
+        assert_eq!(canonicalize_model_name("anthropic/claude-3-5-sonnet", &disabled), "anthropic/claude-3-5-sonnet");
+
+        let custom = ModelCanonicalizationConfig {
+            strip_prefixes: vec!["vendor:".to_string()],
+            strip_suffixes: vec!["-free".to_string()],
+            strip_at_version: false,
+            ..Default::default()
+        };
+        assert_eq!(canonicalize_model_name("vendor:gpt-4o-free", &custom), "gpt-4o");
+        assert_eq!(canonicalize_model_name("anthropic/claude@1", &custom), "anthropic/claude@1");
+    }
+
+    #[test]
+    fn test_canonical_name_matches_custom_rules() {
+        let rules = ModelCanonicalizationConfig::default();
+        let mut custom = HashMap::new();
+        custom.insert("claude-3-5-sonnet*".to_string(), "gemini-3-pro-high".to_string());
+        custom.insert("openai/gpt-4o".to_string(), "gemini-3-flash".to_string());
+
+        let auto = AutoModelConfig::default();
+        let key = route_lookup_model("anthropic/claude-3-5-sonnet@20241022", &custom, &rules, &auto);
+        assert_eq!(key, "claude-3-5-sonnet");
+        let target = resolve_model_route(
+            &key,
+            &ModelRouteConfig {
+                custom_mapping: &custom,
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &[],
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &HashMap::new(),
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
+            false,
+        );
+        assert_eq!(target, "gemini-3-pro-high");
+
+        // 原始名称的精确规则优先
+        assert_eq!(route_lookup_model("openai/gpt-4o", &custom, &rules, &auto), "openai/gpt-4o");
+    }
+
+    #[test]
+    fn test_auto_model_aliases_resolve_to_target() {
+        let rules = ModelCanonicalizationConfig::default();
+        let custom = HashMap::new();
+        let auto = AutoModelConfig {
+            target: Some("gemini-3-flash".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(route_lookup_model("auto", &custom, &rules, &auto), "gemini-3-flash");
+        assert_eq!(route_lookup_model("Default", &custom, &rules, &auto), "gemini-3-flash");
+        assert_eq!(route_lookup_model("gpt-4o", &custom, &rules, &auto), "gpt-4o");
+
+        // 未配置目标时哨兵名按普通模型名路由
+        let unset = AutoModelConfig::default();
+        assert_eq!(route_lookup_model("auto", &custom, &rules, &unset), "auto");
+    }
+
+    #[test]
+    fn test_auto_model_aliases_are_configurable() {
+        let rules = ModelCanonicalizationConfig::default();
+        let mut custom = HashMap::new();
+        let auto = AutoModelConfig {
+            aliases: vec!["pick-for-me".to_string()],
+            target: Some("gemini-3-pro-high".to_string()),
+        };
+
+        assert_eq!(route_lookup_model("pick-for-me", &custom, &rules, &auto), "gemini-3-pro-high");
+        assert_eq!(route_lookup_model("auto", &custom, &rules, &auto), "auto");
+
+        // 自定义精确映射优先于自动选型
+        custom.insert("pick-for-me".to_string(), "gemini-3-flash".to_string());
+        assert_eq!(route_lookup_model("pick-for-me", &custom, &rules, &auto), "pick-for-me");
+    }
+
+    #[test]
+    fn test_auto_model_strategy_target_expands_to_plan() {
+        let auto = AutoModelConfig {
+            target: Some("strategy:smart".to_string()),
+            ..Default::default()
+        };
+        let mut strategies = HashMap::new();
+        strategies.insert(
+            "smart".to_string(),
+            ModelStrategy {
+                candidates: vec!["gemini-3-pro-high".to_string(), "gemini-3-flash".to_string()],
+                policy: ModelFallbackPolicy::default(),
+            },
+        );
+
+        let key = route_lookup_model("default", &HashMap::new(), &ModelCanonicalizationConfig::default(), &auto);
+        let (plan, rule) = explain_model_route_plan(
+            &key,
+            &ModelRouteConfig {
+                custom_mapping: &HashMap::new(),
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &default_openai_family_rules(),
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &strategies,
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
+            false,
+        );
+
+        assert_eq!(rule, RouteRule::StrategyReference);
+        assert_eq!(plan.primary, "gemini-3-pro-high");
+        assert_eq!(plan.fallbacks, vec!["gemini-3-flash".to_string()]);
+        assert_eq!(plan.strategy_id.as_deref(), Some("smart"));
+    }
+
+    #[test]
+    fn test_strategies_loaded_from_directory() {
+        let dir = std::env::temp_dir().join(format!("ag-strategies-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("smart.json"),
+            r#"{"candidates": ["gemini-3-pro-high", "gemini-3-flash"], "policy": {"model_priority": "capacity_first"}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("cheap.toml"),
+            "candidates = [\"gemini-2.5-flash\", \"gemini-2.5-flash-lite\"]\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let mut config = crate::proxy::config::ProxyConfig {
+            strategies_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        // 内联策略与文件同名时优先
+        config.model_strategies.insert(
+            "cheap".to_string(),
+            ModelStrategy {
+                candidates: vec!["gemini-3-flash".to_string()],
+                policy: ModelFallbackPolicy::default(),
+            },
+        );
+        let strategies = config.effective_model_strategies();
+        assert_eq!(strategies.len(), 2);
+        assert_eq!(strategies["cheap"].candidates, vec!["gemini-3-flash".to_string()]);
+
+        let mut custom_mapping = HashMap::new();
+        custom_mapping.insert("gpt-4".to_string(), "strategy:smart".to_string());
+        let plan = resolve_model_route_plan(
+            "gpt-4",
+            &ModelRouteConfig {
+                custom_mapping: &custom_mapping,
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &default_openai_family_rules(),
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &strategies,
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
+            false,
+        );
+        assert_eq!(plan.primary, "gemini-3-pro-high");
+        assert_eq!(plan.fallbacks, vec!["gemini-3-flash".to_string()]);
+        assert_eq!(plan.strategy_id.as_deref(), Some("smart"));
+        assert!(plan.is_capacity_first());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_variant_base_model_strips_thinking_and_budget_suffix() {
+        assert_eq!(variant_base_model("claude-sonnet-4-5-thinking").as_deref(), Some("claude-sonnet-4-5"));
+        assert_eq!(variant_base_model("gemini-2.5-flash-thinking-high").as_deref(), Some("gemini-2.5-flash"));
+        assert_eq!(variant_base_model("gemini-2.5-flash-Thinking-8192").as_deref(), Some("gemini-2.5-flash"));
+        assert_eq!(variant_base_model("gemini-3-pro-high"), None);
+        assert_eq!(variant_base_model("claude-thinking-lab-v2"), None);
+        assert_eq!(variant_base_model("-thinking"), None);
+    }
 }
@@ -0,0 +1,284 @@
+// 请求优先级等待队列
+// 所有账号的并发/RPM/每日槽位均被占满时，请求不再直接失败，而是按优先级排队等待槽位释放：
+// 交互式 (high) 请求总是先于已排队的批量 (low) 请求被唤醒，同一优先级内先到先得。
+// 队列有长度上限，超出上限或等待超时的请求按原有方式报错。
+// 账号池可能混合了只服务部分协议/配额组的账号：被唤醒者用不上释放的槽位时，
+// 把这次唤醒转交给尚未尝试过的下一个等待者，避免唤醒丢失。
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// 请求优先级 (通过 `X-Priority` 请求头或 API Key 默认值指定)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestPriority {
+    /// 批量任务
+    Low,
+    #[default]
+    Normal,
+    /// 交互式请求
+    High,
+}
+
+impl RequestPriority {
+    /// 解析 `X-Priority` 请求头，兼容 interactive / batch 别名
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "high" | "interactive" => Some(Self::High),
+            "normal" | "default" => Some(Self::Normal),
+            "low" | "batch" => Some(Self::Low),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::High => "high",
+            Self::Normal => "normal",
+            Self::Low => "low",
+        }
+    }
+}
+
+/// 请求携带的优先级线索 (由 account_slot 中间件从请求头提取)
+#[derive(Debug, Clone, Default)]
+pub struct RequestPriorityHint {
+    /// `X-Priority` 请求头 (无法识别的取值视为未指定)
+    pub header: Option<RequestPriority>,
+    /// 客户端使用的 API Key，用于查找该 Key 的默认优先级
+    pub api_key: Option<String>,
+}
+
+impl RequestPriorityHint {
+    pub fn from_headers(headers: &axum::http::HeaderMap) -> Self {
+        let header = headers
+            .get("x-priority")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| {
+                let parsed = RequestPriority::parse(v);
+                if parsed.is_none() {
+                    tracing::debug!("[Priority-Queue] Ignoring unknown X-Priority value: {}", v);
+                }
+                parsed
+            });
+        let api_key = headers
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.strip_prefix("Bearer ").unwrap_or(s))
+            .or_else(|| headers.get("x-api-key").and_then(|h| h.to_str().ok()))
+            .map(|s| s.to_string());
+        Self { header, api_key }
+    }
+}
+
+/// 一次槽位释放产生的唤醒，记录已尝试过 (用不上该槽位) 的等待者序号
+#[derive(Debug, Default)]
+pub struct Wakeup {
+    tried: Vec<u64>,
+}
+
+struct Waiter {
+    priority: RequestPriority,
+    seq: u64,
+    tx: oneshot::Sender<Wakeup>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    /// 大顶堆：优先级高者在前，同优先级序号小 (先排队) 者在前
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    next_seq: u64,
+    waiters: BinaryHeap<Waiter>,
+}
+
+/// 等待账号槽位的请求队列
+#[derive(Default)]
+pub struct SlotWaitQueue {
+    state: Mutex<QueueState>,
+}
+
+/// 队列中的一个等待者；drop 后其队列项在下次入队/唤醒时被清理
+pub struct SlotWaiter {
+    priority: RequestPriority,
+    seq: u64,
+    rx: oneshot::Receiver<Wakeup>,
+    /// 最近一次收到、尚未转交的唤醒
+    wakeup: Option<Wakeup>,
+}
+
+impl SlotWaiter {
+    /// 等待被唤醒，超时返回 false；被唤醒后须先 [`SlotWaitQueue::requeue`] 才能再次等待
+    pub async fn wait(&mut self, timeout: Duration) -> bool {
+        match tokio::time::timeout(timeout, &mut self.rx).await {
+            Ok(Ok(wakeup)) => {
+                self.wakeup = Some(wakeup);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl SlotWaitQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 以新的序号入队；队列已满 (`max_len` 个有效等待者) 时返回错误
+    pub fn enqueue(&self, priority: RequestPriority, max_len: usize) -> Result<SlotWaiter, String> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.waiters.retain(|w| !w.tx.is_closed());
+        if state.waiters.len() >= max_len {
+            return Err(format!(
+                "Request queue is full ({} waiting); all accounts are at their concurrency/RPM/daily limit",
+                state.waiters.len()
+            ));
+        }
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        let (tx, rx) = oneshot::channel();
+        state.waiters.push(Waiter { priority, seq, tx });
+        Ok(SlotWaiter { priority, seq, rx, wakeup: None })
+    }
+
+    /// 被唤醒后仍未抢到槽位时重新入队，保留原序号 (不丢失排队位置，也不受长度上限限制)，
+    /// 并把这次唤醒转交给尚未尝试过的下一个等待者 (释放的槽位可能只适合其他协议/配额组的请求)
+    pub fn requeue(&self, waiter: &mut SlotWaiter) {
+        {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let (tx, rx) = oneshot::channel();
+            state.waiters.push(Waiter { priority: waiter.priority, seq: waiter.seq, tx });
+            waiter.rx = rx;
+        }
+        if let Some(wakeup) = waiter.wakeup.take() {
+            self.wake(wakeup);
+        }
+    }
+
+    /// 槽位释放时唤醒优先级最高的等待者 (跳过已放弃等待的项)，返回是否唤醒了请求
+    pub fn wake_next(&self) -> bool {
+        self.wake(Wakeup::default())
+    }
+
+    /// 唤醒优先级最高、且未尝试过这次唤醒的等待者
+    fn wake(&self, mut wakeup: Wakeup) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut tried = Vec::new();
+        let mut woken = false;
+        while let Some(waiter) = state.waiters.pop() {
+            if wakeup.tried.contains(&waiter.seq) {
+                tried.push(waiter);
+                continue;
+            }
+            wakeup.tried.push(waiter.seq);
+            match waiter.tx.send(wakeup) {
+                Ok(()) => {
+                    woken = true;
+                    break;
+                }
+                Err(returned) => wakeup = returned,
+            }
+        }
+        state.waiters.extend(tried);
+        woken
+    }
+
+    /// 当前有效等待者数量
+    pub fn len(&self) -> usize {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.waiters.iter().filter(|w| !w.tx.is_closed()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wake_order_prefers_priority_then_arrival() {
+        let queue = SlotWaitQueue::new();
+        let mut low_first = queue.enqueue(RequestPriority::Low, 8).unwrap();
+        let mut normal = queue.enqueue(RequestPriority::Normal, 8).unwrap();
+        let mut high = queue.enqueue(RequestPriority::High, 8).unwrap();
+        let mut low_second = queue.enqueue(RequestPriority::Low, 8).unwrap();
+        let short = Duration::from_millis(10);
+
+        assert!(queue.wake_next());
+        assert!(high.wait(short).await);
+        assert!(queue.wake_next());
+        assert!(normal.wait(short).await);
+        assert!(queue.wake_next());
+        assert!(low_first.wait(short).await);
+        assert!(!low_second.wait(short).await);
+        assert!(queue.wake_next());
+        assert!(low_second.wait(short).await);
+        assert!(!queue.wake_next());
+    }
+
+    #[test]
+    fn test_queue_is_bounded_and_skips_abandoned_waiters() {
+        let queue = SlotWaitQueue::new();
+        let first = queue.enqueue(RequestPriority::Normal, 2).unwrap();
+        let _second = queue.enqueue(RequestPriority::Normal, 2).unwrap();
+        assert!(queue.enqueue(RequestPriority::High, 2).is_err());
+
+        // 放弃等待的请求不再占用队列容量
+        drop(first);
+        assert_eq!(queue.len(), 1);
+        assert!(queue.enqueue(RequestPriority::High, 2).is_ok());
+
+        assert_eq!(RequestPriority::parse(" Interactive "), Some(RequestPriority::High));
+        assert_eq!(RequestPriority::parse("batch"), Some(RequestPriority::Low));
+        assert_eq!(RequestPriority::parse("urgent"), None);
+    }
+
+    #[tokio::test]
+    async fn test_unusable_wakeup_is_passed_to_next_waiter() {
+        let queue = SlotWaitQueue::new();
+        let mut first = queue.enqueue(RequestPriority::High, 8).unwrap();
+        let mut second = queue.enqueue(RequestPriority::Normal, 8).unwrap();
+        let short = Duration::from_millis(10);
+
+        assert!(queue.wake_next());
+        assert!(first.wait(short).await);
+        assert!(!second.wait(short).await);
+
+        // first 用不上释放的槽位：重新入队并把唤醒转交给 second，而不是再次唤醒自己
+        queue.requeue(&mut first);
+        assert!(second.wait(short).await);
+        assert!(!first.wait(short).await);
+
+        // 所有等待者都尝试过后唤醒结束，不会在等待者之间来回传递
+        queue.requeue(&mut second);
+        assert!(!first.wait(short).await);
+        assert_eq!(queue.len(), 2);
+    }
+}
@@ -0,0 +1,488 @@
+// 推理内容输出转换
+// 部分客户端无法处理 thinking / reasoning 内容，按 `reasoning_output` 配置移除或移出正文
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+use std::pin::Pin;
+
+use crate::proxy::config::ReasoningOutputMode;
+use crate::proxy::upstream::errors::ErrorProtocol;
+
+/// Separate 模式下承载推理内容的顶层字段
+pub const REASONING_FIELD: &str = "reasoning";
+
+/// Separate 模式下 Anthropic 流式推理内容使用的独立事件名
+pub const REASONING_EVENT: &str = "reasoning";
+
+/// 从 OpenAI 的 message / delta 中取出 reasoning_content
+fn take_openai_reasoning(body: &mut Value, key: &str) -> String {
+    let mut out = String::new();
+    if let Some(choices) = body.get_mut("choices").and_then(Value::as_array_mut) {
+        for choice in choices {
+            if let Some(obj) = choice.get_mut(key).and_then(Value::as_object_mut) {
+                if let Some(Value::String(text)) = obj.remove("reasoning_content") {
+                    out.push_str(&text);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// 移除 Anthropic content 中的 thinking / redacted_thinking 块
+fn take_anthropic_reasoning(body: &mut Value) -> String {
+    let mut out = String::new();
+    if let Some(content) = body.get_mut("content").and_then(Value::as_array_mut) {
+        content.retain(|block| {
+            match block.get("type").and_then(Value::as_str) {
+                Some("thinking") => {
+                    if let Some(text) = block.get("thinking").and_then(Value::as_str) {
+                        out.push_str(text);
+                    }
+                    false
+                }
+                Some("redacted_thinking") => false,
+                _ => true,
+            }
+        });
+    }
+    out
+}
+
+/// 移除 Gemini candidates 中 `thought: true` 的 part
+fn take_gemini_reasoning(body: &mut Value) -> String {
+    let mut out = String::new();
+    // 兼容 v1internal 包装格式 ({"response": {...}})
+    let target = if body.get("response").is_some_and(Value::is_object) {
+        &mut body["response"]
+    } else {
+        body
+    };
+    if let Some(candidates) = target.get_mut("candidates").and_then(Value::as_array_mut) {
+        for candidate in candidates {
+            if let Some(parts) = candidate
+                .get_mut("content")
+                .and_then(|c| c.get_mut("parts"))
+                .and_then(Value::as_array_mut)
+            {
+                parts.retain(|part| {
+                    if part.get("thought").and_then(Value::as_bool).unwrap_or(false) {
+                        if let Some(text) = part.get("text").and_then(Value::as_str) {
+                            out.push_str(text);
+                        }
+                        false
+                    } else {
+                        true
+                    }
+                });
+            }
+        }
+    }
+    out
+}
+
+fn attach_reasoning(body: &mut Value, reasoning: String) {
+    if reasoning.is_empty() {
+        return;
+    }
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert(REASONING_FIELD.to_string(), json!(reasoning));
+    }
+}
+
+/// 对非流式响应体就地应用推理内容输出模式
+pub fn apply_to_response(mode: ReasoningOutputMode, protocol: ErrorProtocol, body: &mut Value) {
+    if mode == ReasoningOutputMode::Passthrough {
+        return;
+    }
+    let reasoning = match protocol {
+        ErrorProtocol::OpenAI => take_openai_reasoning(body, "message"),
+        ErrorProtocol::Anthropic => take_anthropic_reasoning(body),
+        ErrorProtocol::Gemini => take_gemini_reasoning(body),
+    };
+    if mode == ReasoningOutputMode::Separate {
+        attach_reasoning(body, reasoning);
+    }
+}
+
+/// 流式 SSE 推理内容过滤器 (逐事件处理，维护 Anthropic 内容块索引的重排)
+pub struct ReasoningStreamFilter {
+    mode: ReasoningOutputMode,
+    protocol: ErrorProtocol,
+    buffer: String,
+    /// 已移除的 Anthropic 内容块原始索引 (升序)
+    dropped_blocks: Vec<u64>,
+}
+
+impl ReasoningStreamFilter {
+    pub fn new(mode: ReasoningOutputMode, protocol: ErrorProtocol) -> Self {
+        Self {
+            mode,
+            protocol,
+            buffer: String::new(),
+            dropped_blocks: Vec::new(),
+        }
+    }
+
+    /// 输入一段字节，返回可立即输出的完整事件
+    pub fn push(&mut self, chunk: &[u8]) -> String {
+        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+        let mut out = String::new();
+        while let Some(pos) = self.buffer.find("\n\n") {
+            let frame: String = self.buffer.drain(..pos + 2).collect();
+            out.push_str(&self.transform_frame(&frame[..pos]));
+        }
+        out
+    }
+
+    /// 流结束时输出残留内容
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.buffer);
+        if rest.trim().is_empty() {
+            rest
+        } else {
+            self.transform_frame(rest.trim_end_matches('\n'))
+        }
+    }
+
+    fn transform_frame(&mut self, frame: &str) -> String {
+        let mut event: Option<&str> = None;
+        let mut data: Option<&str> = None;
+        for line in frame.lines() {
+            if let Some(v) = line.strip_prefix("event:") {
+                event = Some(v.trim());
+            } else if let Some(v) = line.strip_prefix("data:") {
+                data = Some(v.trim_start());
+            }
+        }
+
+        let passthrough = format!("{}\n\n", frame);
+        if self.mode == ReasoningOutputMode::Passthrough {
+            return passthrough;
+        }
+        let Some(data) = data else {
+            return passthrough;
+        };
+        let Ok(mut value) = serde_json::from_str::<Value>(data) else {
+            return passthrough;
+        };
+
+        match self.protocol {
+            ErrorProtocol::Anthropic => self.transform_anthropic_event(event, value),
+            ErrorProtocol::OpenAI => {
+                let reasoning = take_openai_reasoning(&mut value, "delta");
+                if reasoning.is_empty() {
+                    return passthrough;
+                }
+                if self.mode == ReasoningOutputMode::Separate {
+                    attach_reasoning(&mut value, reasoning);
+                } else if openai_chunk_is_empty(&value) {
+                    return String::new();
+                }
+                format!("data: {}\n\n", value)
+            }
+            ErrorProtocol::Gemini => {
+                let reasoning = take_gemini_reasoning(&mut value);
+                if reasoning.is_empty() {
+                    return passthrough;
+                }
+                if self.mode == ReasoningOutputMode::Separate {
+                    attach_reasoning(&mut value, reasoning);
+                } else if gemini_chunk_is_empty(&value) {
+                    return String::new();
+                }
+                format!("data: {}\n\n", value)
+            }
+        }
+    }
+
+    fn transform_anthropic_event(&mut self, event: Option<&str>, mut value: Value) -> String {
+        let event_type = value
+            .get("type")
+            .and_then(Value::as_str)
+            .or(event)
+            .unwrap_or("")
+            .to_string();
+        let index = value.get("index").and_then(Value::as_u64);
+
+        if event_type == "content_block_start" {
+            let block_type = value
+                .get("content_block")
+                .and_then(|b| b.get("type"))
+                .and_then(Value::as_str);
+            if matches!(block_type, Some("thinking") | Some("redacted_thinking")) {
+                if let Some(index) = index {
+                    self.dropped_blocks.push(index);
+                }
+                return String::new();
+            }
+        }
+
+        if let Some(index) = index {
+            if self.dropped_blocks.contains(&index) {
+                // 被移除块的 delta / stop：Separate 模式下将思考文本作为独立事件输出
+                let thinking = value
+                    .get("delta")
+                    .filter(|d| d.get("type").and_then(Value::as_str) == Some("thinking_delta"))
+                    .and_then(|d| d.get("thinking"))
+                    .and_then(Value::as_str)
+                    .filter(|t| !t.is_empty());
+                return match (self.mode, thinking) {
+                    (ReasoningOutputMode::Separate, Some(text)) => format!(
+                        "event: {}\ndata: {}\n\n",
+                        REASONING_EVENT,
+                        json!({ "type": REASONING_EVENT, REASONING_FIELD: text })
+                    ),
+                    _ => String::new(),
+                };
+            }
+
+            // 后续块索引前移，保持客户端看到的索引连续
+            let shift = self.dropped_blocks.iter().filter(|&&d| d < index).count() as u64;
+            if shift > 0 {
+                value["index"] = json!(index - shift);
+            }
+        }
+
+        match event {
+            Some(name) => format!("event: {}\ndata: {}\n\n", name, value),
+            None => format!("data: {}\n\n", value),
+        }
+    }
+}
+
+/// 所有 choice 的 delta 均为空且未结束
+fn openai_chunk_is_empty(value: &Value) -> bool {
+    value
+        .get("choices")
+        .and_then(Value::as_array)
+        .is_some_and(|choices| {
+            choices.iter().all(|c| {
+                c.get("finish_reason").is_none_or(Value::is_null)
+                    && c.get("delta")
+                        .and_then(Value::as_object)
+                        .is_none_or(|d| d.values().all(Value::is_null))
+            })
+        })
+        && value.get("usage").is_none_or(Value::is_null)
+}
+
+/// 所有 candidate 均无剩余 part 且未结束
+fn gemini_chunk_is_empty(value: &Value) -> bool {
+    let target = value.get("response").unwrap_or(value);
+    target.get("usageMetadata").is_none()
+        && target
+            .get("candidates")
+            .and_then(Value::as_array)
+            .is_some_and(|candidates| {
+                candidates.iter().all(|c| {
+                    c.get("finishReason").is_none()
+                        && c.get("content")
+                            .and_then(|c| c.get("parts"))
+                            .and_then(Value::as_array)
+                            .is_none_or(|p| p.is_empty())
+                })
+            })
+}
+
+/// 对流式响应应用推理内容输出模式 (Passthrough 时原样返回)
+pub fn filter_stream<E>(
+    inner: Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>,
+    mode: ReasoningOutputMode,
+    protocol: ErrorProtocol,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>
+where
+    E: Send + 'static,
+{
+    if mode == ReasoningOutputMode::Passthrough {
+        return inner;
+    }
+
+    let stream = async_stream::stream! {
+        let mut inner = inner;
+        let mut filter = ReasoningStreamFilter::new(mode, protocol);
+        while let Some(item) = inner.next().await {
+            match item {
+                Ok(bytes) => {
+                    let out = filter.push(&bytes);
+                    if !out.is_empty() {
+                        yield Ok(Bytes::from(out));
+                    }
+                }
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            }
+        }
+        let rest = filter.finish();
+        if !rest.is_empty() {
+            yield Ok(Bytes::from(rest));
+        }
+    };
+    Box::pin(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn openai_response() -> Value {
+        json!({
+            "id": "chatcmpl-1",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "42", "reasoning_content": "let me think" },
+                "finish_reason": "stop"
+            }]
+        })
+    }
+
+    fn anthropic_response() -> Value {
+        json!({
+            "id": "msg_1",
+            "type": "message",
+            "content": [
+                { "type": "thinking", "thinking": "let me think", "signature": "sig" },
+                { "type": "text", "text": "42" }
+            ]
+        })
+    }
+
+    fn gemini_response() -> Value {
+        json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [
+                    { "text": "let me think", "thought": true },
+                    { "text": "42" }
+                ]},
+                "finishReason": "STOP"
+            }]
+        })
+    }
+
+    #[test]
+    fn test_passthrough_keeps_reasoning() {
+        for (protocol, body) in [
+            (ErrorProtocol::OpenAI, openai_response()),
+            (ErrorProtocol::Anthropic, anthropic_response()),
+            (ErrorProtocol::Gemini, gemini_response()),
+        ] {
+            let mut transformed = body.clone();
+            apply_to_response(ReasoningOutputMode::Passthrough, protocol, &mut transformed);
+            assert_eq!(transformed, body);
+        }
+    }
+
+    #[test]
+    fn test_strip_removes_reasoning() {
+        let mut openai = openai_response();
+        apply_to_response(ReasoningOutputMode::Strip, ErrorProtocol::OpenAI, &mut openai);
+        assert!(openai["choices"][0]["message"].get("reasoning_content").is_none());
+        assert_eq!(openai["choices"][0]["message"]["content"], "42");
+        assert!(openai.get(REASONING_FIELD).is_none());
+
+        let mut anthropic = anthropic_response();
+        apply_to_response(ReasoningOutputMode::Strip, ErrorProtocol::Anthropic, &mut anthropic);
+        assert_eq!(anthropic["content"], json!([{ "type": "text", "text": "42" }]));
+
+        let mut gemini = gemini_response();
+        apply_to_response(ReasoningOutputMode::Strip, ErrorProtocol::Gemini, &mut gemini);
+        assert_eq!(gemini["candidates"][0]["content"]["parts"], json!([{ "text": "42" }]));
+    }
+
+    #[test]
+    fn test_separate_moves_reasoning_to_field() {
+        for (protocol, mut body) in [
+            (ErrorProtocol::OpenAI, openai_response()),
+            (ErrorProtocol::Anthropic, anthropic_response()),
+            (ErrorProtocol::Gemini, gemini_response()),
+        ] {
+            apply_to_response(ReasoningOutputMode::Separate, protocol, &mut body);
+            assert_eq!(body[REASONING_FIELD], "let me think");
+            assert!(!body.to_string().contains("\"thought\":true"));
+            assert!(!body.to_string().contains("reasoning_content"));
+            assert!(!body.to_string().contains("\"thinking\""));
+        }
+    }
+
+    fn run_stream(mode: ReasoningOutputMode, protocol: ErrorProtocol, input: &str) -> String {
+        let mut filter = ReasoningStreamFilter::new(mode, protocol);
+        // 按任意边界切分，验证跨块缓冲
+        let mut out = String::new();
+        for chunk in input.as_bytes().chunks(7) {
+            out.push_str(&filter.push(chunk));
+        }
+        out.push_str(&filter.finish());
+        out
+    }
+
+    const ANTHROPIC_STREAM: &str = concat!(
+        "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"content\":[]}}\n\n",
+        "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"thinking\",\"thinking\":\"\"}}\n\n",
+        "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"thinking_delta\",\"thinking\":\"hmm\"}}\n\n",
+        "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"signature_delta\",\"signature\":\"sig\"}}\n\n",
+        "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+        "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+        "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"text_delta\",\"text\":\"42\"}}\n\n",
+        "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":1}\n\n",
+        "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+    );
+
+    #[test]
+    fn test_stream_passthrough_is_unchanged() {
+        let out = run_stream(ReasoningOutputMode::Passthrough, ErrorProtocol::Anthropic, ANTHROPIC_STREAM);
+        assert_eq!(out, ANTHROPIC_STREAM);
+    }
+
+    #[test]
+    fn test_anthropic_stream_strip_drops_thinking_and_reindexes() {
+        let out = run_stream(ReasoningOutputMode::Strip, ErrorProtocol::Anthropic, ANTHROPIC_STREAM);
+        assert!(!out.contains("thinking"));
+        assert!(!out.contains("signature"));
+        assert!(out.contains("\"index\":0"));
+        assert!(!out.contains("\"index\":1"));
+        assert!(out.contains("text_delta"));
+        assert!(out.contains("message_stop"));
+    }
+
+    #[test]
+    fn test_anthropic_stream_separate_emits_reasoning_event() {
+        let out = run_stream(ReasoningOutputMode::Separate, ErrorProtocol::Anthropic, ANTHROPIC_STREAM);
+        assert!(out.contains("event: reasoning\ndata: "));
+        assert!(out.contains("\"reasoning\":\"hmm\""));
+        assert!(!out.contains("thinking_delta"));
+        assert!(!out.contains("\"index\":1"));
+        assert!(out.contains("text_delta"));
+    }
+
+    #[test]
+    fn test_openai_and_gemini_stream_modes() {
+        let openai = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"reasoning_content\":\"hmm\"},\"finish_reason\":null}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"42\"},\"finish_reason\":null}]}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let stripped = run_stream(ReasoningOutputMode::Strip, ErrorProtocol::OpenAI, openai);
+        assert!(!stripped.contains("hmm"));
+        assert!(stripped.contains("\"content\":\"42\""));
+        assert!(stripped.ends_with("data: [DONE]\n\n"));
+
+        let separated = run_stream(ReasoningOutputMode::Separate, ErrorProtocol::OpenAI, openai);
+        assert!(separated.contains("\"reasoning\":\"hmm\""));
+        assert!(!separated.contains("reasoning_content"));
+
+        let gemini = concat!(
+            "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"hmm\",\"thought\":true}]}}]}\n\n",
+            "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"42\"}]},\"finishReason\":\"STOP\"}]}\n\n",
+        );
+        let stripped = run_stream(ReasoningOutputMode::Strip, ErrorProtocol::Gemini, gemini);
+        assert!(!stripped.contains("hmm"));
+        assert_eq!(stripped.matches("data:").count(), 1);
+
+        let separated = run_stream(ReasoningOutputMode::Separate, ErrorProtocol::Gemini, gemini);
+        assert!(separated.contains("\"reasoning\":\"hmm\""));
+        assert!(!separated.contains("\"thought\""));
+    }
+}
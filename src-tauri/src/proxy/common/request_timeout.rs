@@ -0,0 +1,88 @@
+// 单请求超时覆盖
+// 客户端可通过 `X-Request-Timeout` (秒) 为个别长任务申请更长的超时
+
+use axum::http::HeaderMap;
+use tokio::time::Duration;
+
+pub const REQUEST_TIMEOUT_HEADER: &str = "x-request-timeout";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RequestTimeoutPolicy {
+    /// 是否允许客户端通过请求头覆盖
+    pub allow_header_overrides: bool,
+    /// 请求头可申请的最大超时 (秒)
+    pub max_timeout_secs: u64,
+}
+
+impl RequestTimeoutPolicy {
+    pub fn from_config(config: &crate::proxy::config::ProxyConfig) -> Self {
+        Self {
+            allow_header_overrides: config.allow_header_overrides,
+            max_timeout_secs: config.max_request_timeout_secs,
+        }
+    }
+
+    /// 解析请求头中的超时覆盖
+    /// - 未开启 / 未携带 / 无法解析: 返回 None，沿用默认超时
+    /// - 超出上限: 截断到上限并记录日志
+    pub fn resolve(&self, headers: &HeaderMap) -> Option<Duration> {
+        if !self.allow_header_overrides {
+            return None;
+        }
+
+        let raw = headers.get(REQUEST_TIMEOUT_HEADER)?.to_str().ok()?.trim();
+        let requested = match raw.parse::<u64>() {
+            Ok(secs) if secs > 0 => secs,
+            _ => {
+                tracing::warn!("[Timeout] Ignoring invalid X-Request-Timeout: {}", raw);
+                return None;
+            }
+        };
+
+        let effective = if self.max_timeout_secs > 0 && requested > self.max_timeout_secs {
+            tracing::warn!(
+                "[Timeout] X-Request-Timeout {}s exceeds cap, clamped to {}s",
+                requested,
+                self.max_timeout_secs
+            );
+            self.max_timeout_secs
+        } else {
+            requested
+        };
+
+        tracing::debug!("[Timeout] Using per-request timeout override: {}s", effective);
+        Some(Duration::from_secs(effective))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers_with(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_TIMEOUT_HEADER, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_header_extends_timeout_up_to_cap() {
+        let policy = RequestTimeoutPolicy {
+            allow_header_overrides: true,
+            max_timeout_secs: 1800,
+        };
+        assert_eq!(policy.resolve(&headers_with("900")), Some(Duration::from_secs(900)));
+        assert_eq!(policy.resolve(&headers_with("7200")), Some(Duration::from_secs(1800)));
+        assert_eq!(policy.resolve(&headers_with("abc")), None);
+        assert_eq!(policy.resolve(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_header_ignored_when_flag_off() {
+        let policy = RequestTimeoutPolicy {
+            allow_header_overrides: false,
+            max_timeout_secs: 1800,
+        };
+        assert_eq!(policy.resolve(&headers_with("900")), None);
+    }
+}
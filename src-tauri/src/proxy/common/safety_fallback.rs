@@ -0,0 +1,164 @@
+// 安全拦截回退
+// 上游以 SAFETY / RECITATION 等原因拦截响应 (无任何输出内容) 时，按配置将其视为失败并切换到下一个候选；
+// 在交给协议转换之前检查上游 Gemini 响应：非流式检查完整响应体，流式预读到首个内容事件为止
+use bytes::Bytes;
+use futures::StreamExt;
+use serde_json::Value;
+
+/// 流式预读的字节上限，超出后不再判断直接放行
+const MAX_PEEK_BYTES: usize = 256 * 1024;
+
+fn is_block_reason(reason: &str, reasons: &[String]) -> bool {
+    reasons.iter().any(|r| r.trim().eq_ignore_ascii_case(reason))
+}
+
+/// 候选是否包含实际输出 (文本、函数调用或内联数据)
+fn has_content(candidate: &Value) -> bool {
+    candidate
+        .pointer("/content/parts")
+        .and_then(Value::as_array)
+        .is_some_and(|parts| {
+            parts.iter().any(|part| {
+                part.get("text").and_then(Value::as_str).is_some_and(|t| !t.is_empty())
+                    || part.get("functionCall").is_some()
+                    || part.get("inlineData").is_some()
+            })
+        })
+}
+
+/// 响应 (或单个流事件) 被拦截时返回拦截原因；已有输出内容的候选不视为拦截
+pub fn block_reason(response: &Value, reasons: &[String]) -> Option<String> {
+    let response = response.get("response").unwrap_or(response);
+    if let Some(reason) = response.pointer("/promptFeedback/blockReason").and_then(Value::as_str) {
+        if is_block_reason(reason, reasons) {
+            return Some(reason.to_string());
+        }
+    }
+    let candidate = response.pointer("/candidates/0")?;
+    let reason = candidate.get("finishReason").and_then(Value::as_str)?;
+    (is_block_reason(reason, reasons) && !has_content(candidate)).then(|| reason.to_string())
+}
+
+/// 流事件是否已包含输出内容 (此后不再可能回退)
+fn event_has_content(event: &Value) -> bool {
+    let event = event.get("response").unwrap_or(event);
+    event.pointer("/candidates/0").is_some_and(has_content)
+}
+
+/// 预读结果
+enum Peek {
+    Blocked(String),
+    Pass,
+}
+
+/// 检查完整的 SSE 行，返回可判定的结果
+fn scan_lines(pending: &mut Vec<u8>, reasons: &[String]) -> Option<Peek> {
+    while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+        let line: Vec<u8> = pending.drain(..=pos).collect();
+        let Ok(line) = std::str::from_utf8(&line) else {
+            continue;
+        };
+        let Some(data) = line.trim().strip_prefix("data:") else {
+            continue;
+        };
+        let Ok(event) = serde_json::from_str::<Value>(data.trim()) else {
+            continue;
+        };
+        if let Some(reason) = block_reason(&event, reasons) {
+            return Some(Peek::Blocked(reason));
+        }
+        if event_has_content(&event) {
+            return Some(Peek::Pass);
+        }
+    }
+    None
+}
+
+/// 检查上游成功响应是否被安全拦截
+/// - 被拦截：返回 `Err(原因)`，调用方应切换到下一个候选
+/// - 未拦截：返回与原响应等价的 Response (已读取的部分原样放回)
+pub async fn screen_response(
+    response: reqwest::Response,
+    streaming: bool,
+    reasons: &[String],
+) -> Result<reqwest::Response, String> {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let rebuild = |body: reqwest::Body| {
+        let mut builder = axum::http::Response::builder().status(status);
+        if let Some(h) = builder.headers_mut() {
+            *h = headers.clone();
+            // 响应体已被读取/重新包装，原长度不再可靠
+            h.remove(axum::http::header::CONTENT_LENGTH);
+        }
+        builder
+            .body(body)
+            .map(reqwest::Response::from)
+            .map_err(|e| format!("Failed to rebuild upstream response: {}", e))
+    };
+
+    if !streaming {
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| format!("Failed to read upstream response: {}", e))?;
+        if let Ok(json) = serde_json::from_slice::<Value>(&bytes) {
+            if let Some(reason) = block_reason(&json, reasons) {
+                return Err(reason);
+            }
+        }
+        return rebuild(reqwest::Body::from(bytes));
+    }
+
+    let mut stream = response.bytes_stream();
+    let mut buffered: Vec<Result<Bytes, reqwest::Error>> = Vec::new();
+    let mut pending = Vec::new();
+    let mut peeked_bytes = 0;
+    while let Some(item) = stream.next().await {
+        let chunk = match item {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                // 读取出错时原样放回，由后续处理报告
+                buffered.push(Err(e));
+                break;
+            }
+        };
+        peeked_bytes += chunk.len();
+        pending.extend_from_slice(&chunk);
+        buffered.push(Ok(chunk));
+        match scan_lines(&mut pending, reasons) {
+            Some(Peek::Blocked(reason)) => return Err(reason),
+            Some(Peek::Pass) => break,
+            None if peeked_bytes >= MAX_PEEK_BYTES => break,
+            None => {}
+        }
+    }
+
+    let replay = futures::stream::iter(buffered).chain(stream);
+    rebuild(reqwest::Body::wrap_stream(replay))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn reasons() -> Vec<String> {
+        vec!["SAFETY".to_string(), "RECITATION".to_string()]
+    }
+
+    #[test]
+    fn test_block_reason_requires_empty_output() {
+        let blocked = json!({ "candidates": [{ "finishReason": "RECITATION" }] });
+        assert_eq!(block_reason(&blocked, &reasons()).as_deref(), Some("RECITATION"));
+
+        let prompt_blocked = json!({ "promptFeedback": { "blockReason": "SAFETY" } });
+        assert_eq!(block_reason(&prompt_blocked, &reasons()).as_deref(), Some("SAFETY"));
+
+        // 已有输出内容、或原因未配置时不回退
+        let partial = json!({ "candidates": [{ "content": { "parts": [{ "text": "Once" }] }, "finishReason": "SAFETY" }] });
+        assert_eq!(block_reason(&partial, &reasons()), None);
+        let other = json!({ "candidates": [{ "finishReason": "BLOCKLIST" }] });
+        assert_eq!(block_reason(&other, &reasons()), None);
+    }
+}
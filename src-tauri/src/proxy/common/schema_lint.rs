@@ -0,0 +1,293 @@
+// 工具 Schema 预检
+// 在不发起请求的情况下，报告 `clean_json_schema` 会删除/展开/降级哪些内容
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::proxy::common::json_schema::{clean_json_schema, HARD_REMOVE_FIELDS, VALIDATION_FIELDS};
+
+/// 被移除的关键字
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RemovedKeyword {
+    /// Schema 节点路径 (JSON Pointer 风格，如 `#/properties/city`)
+    pub path: String,
+    pub keyword: String,
+    /// 是否以 `[Constraint: ...]` 形式保留在 description 中
+    pub moved_to_description: bool,
+}
+
+/// 被展开的 $ref
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct InlinedRef {
+    pub path: String,
+    pub reference: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaLintReport {
+    pub removed_keywords: Vec<RemovedKeyword>,
+    pub inlined_refs: Vec<InlinedRef>,
+    pub warnings: Vec<String>,
+    /// 清理后实际发送给 Gemini 的 Schema
+    pub cleaned: Value,
+}
+
+impl SchemaLintReport {
+    /// 人类可读的报告
+    pub fn render_text(&self) -> String {
+        let mut out = String::new();
+        out.push_str("Cleaned schema:\n");
+        out.push_str(&serde_json::to_string_pretty(&self.cleaned).unwrap_or_default());
+        out.push('\n');
+
+        out.push_str(&format!("\nRemoved keywords ({}):\n", self.removed_keywords.len()));
+        for removed in &self.removed_keywords {
+            let note = if removed.moved_to_description { " (moved to description)" } else { "" };
+            out.push_str(&format!("  - {} at {}{}\n", removed.keyword, removed.path, note));
+        }
+
+        out.push_str(&format!("\nInlined refs ({}):\n", self.inlined_refs.len()));
+        for inlined in &self.inlined_refs {
+            out.push_str(&format!("  - {} at {}\n", inlined.reference, inlined.path));
+        }
+
+        out.push_str(&format!("\nWarnings ({}):\n", self.warnings.len()));
+        for warning in &self.warnings {
+            out.push_str(&format!("  - {}\n", warning));
+        }
+        out
+    }
+}
+
+/// 对 Schema 运行清理并生成差异报告 (不修改入参)
+pub fn lint_json_schema(schema: &Value) -> SchemaLintReport {
+    let mut cleaned = schema.clone();
+    clean_json_schema(&mut cleaned);
+
+    let mut report = SchemaLintReport {
+        removed_keywords: Vec::new(),
+        inlined_refs: Vec::new(),
+        warnings: Vec::new(),
+        cleaned,
+    };
+
+    if let Value::Object(root) = schema {
+        // 与 clean_json_schema 一致：仅顶层的 $defs / definitions 参与展开
+        let mut defs = Map::new();
+        for key in ["$defs", "definitions"] {
+            if let Some(Value::Object(d)) = root.get(key) {
+                defs.extend(d.clone());
+                report.removed_keywords.push(RemovedKeyword {
+                    path: "#".to_string(),
+                    keyword: key.to_string(),
+                    moved_to_description: false,
+                });
+            }
+        }
+        lint_node(root, "#", &defs, &mut report);
+    }
+
+    report
+}
+
+fn lint_node(map: &Map<String, Value>, path: &str, defs: &Map<String, Value>, report: &mut SchemaLintReport) {
+    // 1. $ref
+    if let Some(Value::String(reference)) = map.get("$ref") {
+        let ref_name = reference.split('/').next_back().unwrap_or(reference);
+        if defs.get(ref_name).is_some_and(Value::is_object) {
+            report.inlined_refs.push(InlinedRef {
+                path: path.to_string(),
+                reference: reference.clone(),
+            });
+        } else {
+            report.warnings.push(format!("unresolved $ref '{}' at {}", reference, path));
+        }
+    }
+
+    // 2. 被移除的关键字
+    for &(field, _) in VALIDATION_FIELDS {
+        if let Some(val) = map.get(field) {
+            // 与清理逻辑一致：非简单类型不会被迁移 (可能是同名属性)
+            if val.is_string() || val.is_number() || val.is_boolean() {
+                report.removed_keywords.push(RemovedKeyword {
+                    path: path.to_string(),
+                    keyword: field.to_string(),
+                    moved_to_description: true,
+                });
+            }
+        }
+    }
+    for &field in HARD_REMOVE_FIELDS {
+        if map.contains_key(field) {
+            report.removed_keywords.push(RemovedKeyword {
+                path: path.to_string(),
+                keyword: field.to_string(),
+                moved_to_description: false,
+            });
+        }
+    }
+
+    // 3. 警告
+    let properties = map.get("properties").and_then(Value::as_object);
+    if let Some(Value::Array(required)) = map.get("required") {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !properties.is_some_and(|p| p.contains_key(name)) {
+                report.warnings.push(format!(
+                    "required property '{}' at {} is not defined in properties and will be dropped",
+                    name, path
+                ));
+            }
+        }
+    }
+    if properties.is_some_and(|p| p.is_empty()) {
+        report.warnings.push(format!("empty properties at {}", path));
+    }
+    for key in ["anyOf", "oneOf"] {
+        if let Some(Value::Array(branches)) = map.get(key) {
+            let mut types: Vec<&str> = branches
+                .iter()
+                .filter_map(|b| b.get("type").and_then(Value::as_str))
+                .filter(|t| *t != "null")
+                .collect();
+            types.dedup();
+            let non_null = branches
+                .iter()
+                .filter(|b| b.get("type").and_then(Value::as_str) != Some("null"))
+                .count();
+            if non_null > 1 {
+                let kept = map
+                    .get("type")
+                    .and_then(Value::as_str)
+                    .or_else(|| types.first().copied())
+                    .unwrap_or("none");
+                report.warnings.push(format!(
+                    "{} with {} non-null branches at {} collapsed to type '{}'",
+                    key, non_null, path, kept
+                ));
+            }
+        }
+    }
+    if let Some(Value::Array(types)) = map.get("type") {
+        let non_null: Vec<&str> = types
+            .iter()
+            .filter_map(Value::as_str)
+            .filter(|t| *t != "null")
+            .collect();
+        if non_null.len() > 1 {
+            report.warnings.push(format!(
+                "type union {:?} at {} collapsed to '{}'",
+                non_null, path, non_null[0]
+            ));
+        }
+    }
+
+    // 4. 递归子 Schema
+    if let Some(props) = properties {
+        for (name, child) in props {
+            if let Value::Object(child) = child {
+                lint_node(child, &format!("{}/properties/{}", path, name), defs, report);
+            }
+        }
+    }
+    for key in ["$defs", "definitions"] {
+        if let Some(Value::Object(entries)) = map.get(key) {
+            for (name, child) in entries {
+                if let Value::Object(child) = child {
+                    lint_node(child, &format!("{}/{}/{}", path, key, name), defs, report);
+                }
+            }
+        }
+    }
+    for key in ["items", "additionalProperties", "not", "if", "then", "else"] {
+        match map.get(key) {
+            Some(Value::Object(child)) => lint_node(child, &format!("{}/{}", path, key), defs, report),
+            Some(Value::Array(children)) => {
+                for (i, child) in children.iter().enumerate() {
+                    if let Value::Object(child) = child {
+                        lint_node(child, &format!("{}/{}/{}", path, key, i), defs, report);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    for key in ["anyOf", "oneOf", "allOf"] {
+        if let Some(Value::Array(children)) = map.get(key) {
+            for (i, child) in children.iter().enumerate() {
+                if let Value::Object(child) = child {
+                    lint_node(child, &format!("{}/{}/{}", path, key, i), defs, report);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_lint_reports_removed_validation_fields_and_inlined_ref() {
+        let schema = json!({
+            "type": "object",
+            "$defs": {
+                "Address": {
+                    "type": "object",
+                    "properties": { "city": { "type": "string" } }
+                }
+            },
+            "properties": {
+                "email": { "type": "string", "format": "email" },
+                "zip": { "type": "string", "pattern": "^[0-9]{5}$" },
+                "home": { "$ref": "#/$defs/Address" }
+            },
+            "required": ["email", "phone"]
+        });
+
+        let report = lint_json_schema(&schema);
+
+        assert!(report.removed_keywords.contains(&RemovedKeyword {
+            path: "#/properties/email".to_string(),
+            keyword: "format".to_string(),
+            moved_to_description: true,
+        }));
+        assert!(report.removed_keywords.contains(&RemovedKeyword {
+            path: "#/properties/zip".to_string(),
+            keyword: "pattern".to_string(),
+            moved_to_description: true,
+        }));
+        assert_eq!(
+            report.inlined_refs,
+            vec![InlinedRef {
+                path: "#/properties/home".to_string(),
+                reference: "#/$defs/Address".to_string(),
+            }]
+        );
+        assert!(report.warnings.iter().any(|w| w.contains("'phone'")));
+
+        // 清理结果与 clean_json_schema 一致
+        assert_eq!(report.cleaned["properties"]["home"]["properties"]["city"]["type"], "string");
+        assert!(report.cleaned.get("$defs").is_none());
+    }
+
+    #[test]
+    fn test_lint_warns_on_collapsed_union_and_empty_properties() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "value": { "anyOf": [{ "type": "string" }, { "type": "integer" }, { "type": "null" }] },
+                "opts": { "type": "object", "properties": {} }
+            }
+        });
+
+        let report = lint_json_schema(&schema);
+        assert!(report.warnings.iter().any(|w| w.contains("anyOf") && w.contains("'string'")));
+        assert!(report.warnings.iter().any(|w| w.contains("empty properties at #/properties/opts")));
+        assert!(report.removed_keywords.iter().any(|r| r.keyword == "anyOf"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json["warnings"].as_array().unwrap().len() >= 2);
+        assert!(report.render_text().contains("Warnings ("));
+    }
+}
@@ -0,0 +1,184 @@
+// 上游 Schema 类 400 的重试
+// 部分 400 并非请求本身有误，而是协议转换后的工具 Schema 仍含上游不接受的关键字 (边缘情况)；
+// 错误信息命中配置的特征时，以严格模式 (白名单) 重新清洗 functionDeclarations 后重试一次，其余 400 直接失败
+use serde_json::Value;
+
+use crate::proxy::common::json_schema::clean_json_schema_strict;
+use crate::proxy::config::SchemaRetryConfig;
+
+/// 错误是否属于可通过严格清洗恢复的 Schema 错误
+pub fn is_schema_error(status_code: u16, error_text: &str, config: &SchemaRetryConfig) -> bool {
+    if status_code != 400 || !config.enabled {
+        return false;
+    }
+    let text = error_text.to_lowercase();
+    config
+        .error_signatures
+        .iter()
+        .filter(|s| !s.is_empty())
+        .any(|s| text.contains(&s.to_lowercase()))
+}
+
+/// Gemini 请求 (`request` 层) 是否声明了函数工具
+pub fn has_function_declarations(request: &Value) -> bool {
+    request
+        .get("tools")
+        .and_then(Value::as_array)
+        .is_some_and(|tools| {
+            tools.iter().any(|t| {
+                t.get("functionDeclarations")
+                    .and_then(Value::as_array)
+                    .is_some_and(|decls| !decls.is_empty())
+            })
+        })
+}
+
+/// 以严格模式重新清洗所有 functionDeclarations 的参数 Schema
+pub fn strict_reclean_tools(request: &mut Value) {
+    let Some(tools) = request.get_mut("tools").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for tool in tools.iter_mut() {
+        let Some(decls) = tool.get_mut("functionDeclarations").and_then(Value::as_array_mut) else {
+            continue;
+        };
+        for decl in decls.iter_mut() {
+            for key in ["parameters", "parametersJsonSchema"] {
+                if let Some(schema) = decl.get_mut(key) {
+                    clean_json_schema_strict(schema);
+                }
+            }
+        }
+    }
+}
+
+/// 单个请求的 Schema 重试状态 (每个请求最多严格重试一次，并为此额外保留一次尝试机会)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchemaRetry {
+    strict: bool,
+    pending: bool,
+}
+
+impl SchemaRetry {
+    /// 为严格重试额外保留的尝试次数 (账号池只有一个账号时也能重试)
+    pub fn extra_attempts(&self) -> usize {
+        usize::from(!self.strict || self.pending)
+    }
+
+    /// 是否有待执行的严格重试
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// 每次尝试开始时调用，待执行的严格重试随本次尝试发出
+    pub fn begin_attempt(&mut self) {
+        self.pending = false;
+    }
+
+    /// 已进入严格模式时，在发送前对请求重新清洗
+    pub fn prepare(&self, request: &mut Value) {
+        if self.strict {
+            strict_reclean_tools(request);
+        }
+    }
+
+    /// 上游返回错误时调用：返回 true 表示应切换到严格模式重试
+    pub fn should_retry(
+        &mut self,
+        status_code: u16,
+        error_text: &str,
+        had_tools: bool,
+        config: &SchemaRetryConfig,
+    ) -> bool {
+        if self.strict || !had_tools || !is_schema_error(status_code, error_text, config) {
+            return false;
+        }
+        self.strict = true;
+        self.pending = true;
+        tracing::warn!("[Schema-Retry] Upstream rejected tool schema (400), retrying once with strict schema cleaning");
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request_with_tool_schema() -> Value {
+        let mut parameters = json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "title": "Path" }
+            },
+            "required": ["path"]
+        });
+        // 常规清洗 (协议转换阶段) 不移除未知关键字 title
+        crate::proxy::common::json_schema::clean_json_schema(&mut parameters);
+        json!({
+            "contents": [{ "role": "user", "parts": [{ "text": "read it" }] }],
+            "tools": [{ "functionDeclarations": [{ "name": "read_file", "parameters": parameters }] }]
+        })
+    }
+
+    /// 模拟上游：参数 Schema 含未知关键字时返回 400
+    fn fake_upstream(request: &Value) -> (u16, String) {
+        let schema = &request["tools"][0]["functionDeclarations"][0]["parameters"];
+        if schema["properties"]["path"].get("title").is_some() {
+            return (
+                400,
+                r#"{"error":{"code":400,"message":"Invalid JSON payload received. Unknown name \"title\" at 'request.tools[0].function_declarations[0].parameters.properties[0].value': Cannot find field.","status":"INVALID_ARGUMENT"}}"#.to_string(),
+            );
+        }
+        (200, "ok".to_string())
+    }
+
+    #[test]
+    fn test_schema_error_400_retries_with_strict_cleaning() {
+        let config = SchemaRetryConfig::default();
+        let mut retry = SchemaRetry::default();
+        let mut outcomes = Vec::new();
+
+        for _attempt in 0..3 {
+            let mut request = request_with_tool_schema();
+            retry.prepare(&mut request);
+            let had_tools = has_function_declarations(&request);
+            let (status, body) = fake_upstream(&request);
+            outcomes.push(status);
+            if status == 200 {
+                break;
+            }
+            if !retry.should_retry(status, &body, had_tools, &config) {
+                break;
+            }
+        }
+
+        assert_eq!(outcomes, vec![400, 200]);
+    }
+
+    #[test]
+    fn test_generic_400_fails_fast() {
+        let config = SchemaRetryConfig::default();
+        let mut retry = SchemaRetry::default();
+        let generic = r#"{"error":{"code":400,"message":"Request contains an invalid argument.","status":"INVALID_ARGUMENT"}}"#;
+        assert!(!retry.should_retry(400, generic, true, &config));
+
+        // 严格重试占用额外保留的一次尝试，发出后不再保留
+        assert_eq!(retry.extra_attempts(), 1);
+        let schema_error = "Unknown name \"title\" at 'request.tools[0].function_declarations[0]'";
+        assert!(retry.should_retry(400, schema_error, true, &config));
+        assert!(retry.is_pending());
+        retry.begin_attempt();
+        assert!(!retry.is_pending());
+        assert_eq!(retry.extra_attempts(), 0);
+        // 仅重试一次
+        assert!(!retry.should_retry(400, schema_error, true, &config));
+
+        // 未声明工具、非 400 或关闭时不重试
+        let mut retry = SchemaRetry::default();
+        assert!(!retry.should_retry(400, schema_error, false, &config));
+        assert!(!retry.should_retry(500, schema_error, true, &config));
+        let disabled = SchemaRetryConfig { enabled: false, ..Default::default() };
+        assert!(!retry.should_retry(400, schema_error, true, &disabled));
+    }
+}
@@ -0,0 +1,195 @@
+// 不支持流式输出的模型 (如图片生成) 的流式请求处理
+// 按配置拒绝并提示改用非流式，或以非流式调用上游后把完整响应切成 SSE 事件返回 (模拟流式)
+use axum::body::Body;
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use serde_json::{json, Value};
+
+use crate::proxy::common::error::ProxyError;
+use crate::proxy::config::{NonStreamingAction, StreamingConfig};
+
+/// 模型是否支持流式输出
+pub fn supports_streaming(model: &str, config: &StreamingConfig) -> bool {
+    !config
+        .non_streaming_models
+        .iter()
+        .any(|pattern| crate::proxy::common::model_mapping::glob_match(pattern, model))
+}
+
+/// 客户端请求流式输出时的处理：`Ok(true)` 表示需模拟流式，`Err` 为拒绝时返回给客户端的错误
+pub fn check_stream_request(
+    client_wants_stream: bool,
+    model: &str,
+    config: &StreamingConfig,
+) -> Result<bool, ProxyError> {
+    if !client_wants_stream || supports_streaming(model, config) {
+        return Ok(false);
+    }
+    match config.non_streaming_action {
+        NonStreamingAction::Emulate => {
+            tracing::info!("[Stream] Model {} does not support streaming, emulating SSE from the full response", model);
+            Ok(true)
+        }
+        NonStreamingAction::Reject => Err(ProxyError::InvalidRequest(format!(
+            "Model '{}' does not support streaming; retry the request with \"stream\": false",
+            model
+        ))),
+    }
+}
+
+fn sse_event(data: &Value) -> Bytes {
+    Bytes::from(format!("data: {}\n\n", data))
+}
+
+/// 将完整的 OpenAI chat.completion 响应切分为 chat.completion.chunk 事件
+pub fn openai_events(response: &Value) -> Vec<Bytes> {
+    let id = response.get("id").cloned().unwrap_or(Value::Null);
+    let created = response.get("created").cloned().unwrap_or(Value::Null);
+    let model = response.get("model").cloned().unwrap_or(Value::Null);
+    let chunk = |choices: Value| {
+        json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": model,
+            "choices": choices,
+        })
+    };
+
+    let mut events = Vec::new();
+    let choices = response.get("choices").and_then(Value::as_array).cloned().unwrap_or_default();
+    for (i, choice) in choices.iter().enumerate() {
+        let index = choice.get("index").cloned().unwrap_or(json!(i));
+        let message = choice.get("message").cloned().unwrap_or_else(|| json!({}));
+        let mut delta = json!({ "role": "assistant" });
+        for key in ["content", "reasoning_content"] {
+            if let Some(value) = message.get(key).filter(|v| !v.is_null()) {
+                delta[key] = value.clone();
+            }
+        }
+        if let Some(tool_calls) = message.get("tool_calls").and_then(Value::as_array) {
+            let indexed: Vec<Value> = tool_calls
+                .iter()
+                .enumerate()
+                .map(|(n, call)| {
+                    let mut call = call.clone();
+                    call["index"] = json!(n);
+                    call
+                })
+                .collect();
+            delta["tool_calls"] = json!(indexed);
+        }
+        events.push(sse_event(&chunk(json!([{ "index": index, "delta": delta, "finish_reason": null }]))));
+        events.push(sse_event(&chunk(json!([{
+            "index": index,
+            "delta": {},
+            "finish_reason": choice.get("finish_reason").cloned().unwrap_or(json!("stop")),
+        }]))));
+    }
+    if let Some(usage) = response.get("usage").filter(|u| !u.is_null()) {
+        let mut last = chunk(json!([]));
+        last["usage"] = usage.clone();
+        events.push(sse_event(&last));
+    }
+    events.push(Bytes::from("data: [DONE]\n\n"));
+    events
+}
+
+/// Gemini 原生流式响应的每个事件就是一个完整的 GenerateContentResponse
+pub fn gemini_events(response: &Value) -> Vec<Bytes> {
+    vec![sse_event(response)]
+}
+
+/// 以 SSE 响应返回模拟的事件序列
+pub fn sse_response(events: Vec<Bytes>, email: &str, mapped_model: &str) -> Response {
+    let stream = futures::stream::iter(events.into_iter().map(Ok::<Bytes, std::io::Error>));
+    Response::builder()
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("X-Account-Email", email)
+        .header("X-Mapped-Model", mapped_model)
+        .body(Body::from_stream(stream))
+        .unwrap_or_else(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::upstream::errors::ErrorProtocol;
+
+    fn image_response() -> Value {
+        json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1700000000,
+            "model": "gemini-3-pro-image",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "![image](data:image/png;base64,AAAA)" },
+                "finish_reason": "stop"
+            }],
+            "usage": { "prompt_tokens": 5, "completion_tokens": 7, "total_tokens": 12 }
+        })
+    }
+
+    #[test]
+    fn test_image_models_do_not_stream_by_default() {
+        let config = StreamingConfig::default();
+        assert!(!supports_streaming("gemini-3-pro-image", &config));
+        assert!(!supports_streaming("gemini-3-pro-image-16x9", &config));
+        assert!(supports_streaming("gemini-3-flash", &config));
+        assert!(!check_stream_request(false, "gemini-3-pro-image", &config).unwrap());
+    }
+
+    #[test]
+    fn test_non_streaming_patterns_support_multiple_wildcards() {
+        let config = StreamingConfig {
+            non_streaming_models: vec!["gemini-*-image-*".to_string()],
+            ..Default::default()
+        };
+        assert!(!supports_streaming("gemini-3-pro-image-16x9", &config));
+        assert!(supports_streaming("gemini-3-pro-image", &config));
+    }
+
+    #[tokio::test]
+    async fn test_stream_request_for_non_streaming_model_is_emulated() {
+        let config = StreamingConfig::default();
+        assert!(check_stream_request(true, "gemini-3-pro-image", &config).unwrap());
+
+        let events = openai_events(&image_response());
+        let response = sse_response(events, "a@example.com", "gemini-3-pro-image");
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+
+        let payloads: Vec<&str> = text
+            .split("\n\n")
+            .filter_map(|event| event.strip_prefix("data: "))
+            .collect();
+        assert_eq!(payloads.last(), Some(&"[DONE]"));
+        let chunks: Vec<Value> = payloads[..payloads.len() - 1]
+            .iter()
+            .map(|p| serde_json::from_str(p).unwrap())
+            .collect();
+        assert!(chunks.iter().all(|c| c["object"] == "chat.completion.chunk"));
+        assert_eq!(chunks[0]["choices"][0]["delta"]["content"], "![image](data:image/png;base64,AAAA)");
+        assert_eq!(chunks[1]["choices"][0]["finish_reason"], "stop");
+        assert_eq!(chunks[2]["usage"]["total_tokens"], 12);
+    }
+
+    #[test]
+    fn test_stream_request_for_non_streaming_model_is_rejected() {
+        let config = StreamingConfig {
+            non_streaming_action: NonStreamingAction::Reject,
+            ..Default::default()
+        };
+        let err = check_stream_request(true, "gemini-3-pro-image", &config).unwrap_err();
+        assert!(err.to_string().contains("does not support streaming"));
+
+        let response = err.into_protocol_response(ErrorProtocol::OpenAI);
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+
+        // 支持流式的模型不受影响
+        assert!(!check_stream_request(true, "gemini-3-flash", &config).unwrap());
+    }
+}
@@ -0,0 +1,220 @@
+// 流式响应大小/时长限制
+// 防止失控 (或恶意) 的上游无限期地向客户端推送数据
+// 同时兜底上游中途出错：保留已输出的内容，以协议对应的错误事件正常结束流，而不是直接断开连接
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use tokio::time::{Duration, Instant};
+
+use crate::proxy::common::error::ProxyError;
+use crate::proxy::config::StreamingConfig;
+use crate::proxy::upstream::errors::ErrorProtocol;
+
+#[derive(Debug, Clone, Copy)]
+pub struct StreamLimits {
+    /// 最大字节数，0 表示不限制
+    pub max_bytes: u64,
+    /// 最长持续时间，None 表示不限制
+    pub max_duration: Option<Duration>,
+}
+
+impl StreamLimits {
+    pub fn from_config(config: &StreamingConfig) -> Self {
+        Self {
+            max_bytes: config.max_stream_bytes,
+            max_duration: (config.max_stream_duration_secs > 0)
+                .then(|| Duration::from_secs(config.max_stream_duration_secs)),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_bytes == 0 && self.max_duration.is_none()
+    }
+}
+
+/// 生成协议对应的中止事件
+pub fn truncation_event(protocol: ErrorProtocol, message: &str) -> Bytes {
+    terminal_event(protocol, &ProxyError::StreamLimitExceeded(message.to_string()))
+}
+
+/// 生成上游中途出错时的终止事件
+pub fn interrupted_event(protocol: ErrorProtocol, message: &str) -> Bytes {
+    terminal_event(protocol, &ProxyError::StreamInterrupted(message.to_string()))
+}
+
+fn terminal_event(protocol: ErrorProtocol, error: &ProxyError) -> Bytes {
+    let body = error.to_body(protocol);
+    let frame = match protocol {
+        ErrorProtocol::OpenAI => format!("data: {}\n\ndata: [DONE]\n\n", body),
+        ErrorProtocol::Anthropic => format!("event: error\ndata: {}\n\n", body),
+        ErrorProtocol::Gemini => format!("data: {}\n\n", body),
+    };
+    Bytes::from(frame)
+}
+
+/// 对流式响应施加大小/时长限制，超出任一限制时发送中止事件并结束流
+/// 上游中途出错时同样发送错误事件并正常结束流 (不限制时也生效)
+pub fn limit_stream<E>(
+    inner: Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>,
+    limits: StreamLimits,
+    protocol: ErrorProtocol,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>
+where
+    E: std::fmt::Display + Send + 'static,
+{
+    let stream = async_stream::stream! {
+        let mut inner = inner;
+        let mut sent: u64 = 0;
+        let deadline = limits.max_duration.map(|d| Instant::now() + d);
+
+        loop {
+            let next = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, inner.next()).await {
+                    Ok(item) => item,
+                    Err(_) => {
+                        let message = format!(
+                            "Stream aborted: exceeded max duration of {}s",
+                            limits.max_duration.unwrap_or_default().as_secs_f64()
+                        );
+                        tracing::warn!("[StreamLimit] {} ({} bytes sent)", message, sent);
+                        yield Ok(truncation_event(protocol, &message));
+                        break;
+                    }
+                },
+                None => inner.next().await,
+            };
+
+            match next {
+                Some(Ok(bytes)) => {
+                    sent += bytes.len() as u64;
+                    if limits.max_bytes > 0 && sent > limits.max_bytes {
+                        let message = format!(
+                            "Stream aborted: exceeded max size of {} bytes",
+                            limits.max_bytes
+                        );
+                        tracing::warn!("[StreamLimit] {}", message);
+                        yield Ok(truncation_event(protocol, &message));
+                        break;
+                    }
+                    yield Ok(bytes);
+                }
+                Some(Err(e)) => {
+                    let message = format!("Upstream stream interrupted: {}", e);
+                    tracing::warn!("[StreamLimit] {} ({} bytes already sent)", message, sent);
+                    yield Ok(interrupted_event(protocol, &message));
+                    break;
+                }
+                None => break,
+            }
+        }
+    };
+
+    Box::pin(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect_text(chunks: Vec<Result<Bytes, String>>) -> Vec<String> {
+        chunks
+            .into_iter()
+            .map(|c| String::from_utf8(c.unwrap().to_vec()).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_byte_limit_aborts_stream() {
+        let upstream = futures::stream::iter(
+            (0..10).map(|_| Ok::<Bytes, String>(Bytes::from(vec![b'x'; 100]))),
+        );
+        let limits = StreamLimits { max_bytes: 350, max_duration: None };
+
+        let chunks = collect_text(
+            limit_stream(Box::pin(upstream), limits, ErrorProtocol::OpenAI)
+                .collect()
+                .await,
+        );
+
+        assert_eq!(chunks.len(), 4);
+        assert!(chunks[..3].iter().all(|c| c.len() == 100));
+        assert!(chunks[3].contains("stream_limit_exceeded"));
+        assert!(chunks[3].ends_with("data: [DONE]\n\n"));
+    }
+
+    #[tokio::test]
+    async fn test_duration_limit_aborts_stream() {
+        let upstream = async_stream::stream! {
+            yield Ok::<Bytes, String>(Bytes::from("event: message_start\n\n"));
+            loop {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                yield Ok::<Bytes, String>(Bytes::from("event: ping\n\n"));
+            }
+        };
+        let limits = StreamLimits {
+            max_bytes: 0,
+            max_duration: Some(Duration::from_millis(300)),
+        };
+
+        let chunks = collect_text(
+            limit_stream(Box::pin(upstream), limits, ErrorProtocol::Anthropic)
+                .collect()
+                .await,
+        );
+
+        let last = chunks.last().unwrap();
+        assert!(last.starts_with("event: error\n"));
+        assert!(last.contains("stream_limit_exceeded"));
+        assert!(chunks.len() > 1);
+    }
+
+    #[test]
+    fn test_zero_disables_limits() {
+        let config = StreamingConfig {
+            keepalive_interval_secs: 0,
+            max_stream_bytes: 0,
+            max_stream_duration_secs: 0,
+            ..Default::default()
+        };
+        assert!(StreamLimits::from_config(&config).is_unlimited());
+        assert!(!StreamLimits::from_config(&StreamingConfig::default()).is_unlimited());
+    }
+
+    fn partial_then_error() -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
+        Box::pin(futures::stream::iter(vec![
+            Ok(Bytes::from("data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n")),
+            Ok(Bytes::from("data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n")),
+            Err("connection reset".to_string()),
+            Ok(Bytes::from("data: [DONE]\n\n")),
+        ]))
+    }
+
+    #[tokio::test]
+    async fn test_mid_stream_error_keeps_partial_output() {
+        let unlimited = StreamLimits { max_bytes: 0, max_duration: None };
+        let chunks: Vec<Result<Bytes, String>> =
+            limit_stream(partial_then_error(), unlimited, ErrorProtocol::OpenAI).collect().await;
+
+        // 不再向客户端传递 Err (否则连接被直接断开)
+        let chunks = collect_text(chunks);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[0].contains("Hel") && chunks[1].contains("lo"));
+        assert!(chunks[2].contains("\"code\":\"stream_interrupted\""));
+        assert!(chunks[2].contains("connection reset"));
+        assert!(chunks[2].ends_with("data: [DONE]\n\n"));
+    }
+
+    #[tokio::test]
+    async fn test_mid_stream_error_anthropic_event() {
+        let unlimited = StreamLimits { max_bytes: 0, max_duration: None };
+        let chunks = collect_text(
+            limit_stream(partial_then_error(), unlimited, ErrorProtocol::Anthropic)
+                .collect()
+                .await,
+        );
+        let last = chunks.last().unwrap();
+        assert!(last.starts_with("event: error\ndata: "));
+        assert!(last.contains("stream_interrupted"));
+    }
+}
@@ -0,0 +1,215 @@
+// 模型级系统提示词注入
+// 按路由后的模型名匹配 `model_system_prompts` 配置，在转发前将固定的系统提示词置于客户端系统提示之前；
+// 客户端已携带相同内容时不重复注入
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use crate::proxy::mappers::claude::models::{ClaudeRequest, SystemBlock, SystemPrompt};
+use crate::proxy::mappers::openai::{OpenAIContent, OpenAIContentBlock, OpenAIMessage, OpenAIRequest};
+
+/// 查找模型对应的系统提示词 (精确匹配优先，其次最具体的通配符；空白内容视为未配置)
+pub fn resolve_system_prompt<'a>(model: &str, prompts: &'a HashMap<String, String>) -> Option<&'a str> {
+    let prompt = crate::proxy::mappers::common_utils::resolve_model_entry(model, prompts)?;
+    let prompt = prompt.trim();
+    (!prompt.is_empty()).then_some(prompt)
+}
+
+fn openai_message_text(message: &OpenAIMessage) -> String {
+    match &message.content {
+        Some(OpenAIContent::String(s)) => s.clone(),
+        Some(OpenAIContent::Array(blocks)) => blocks
+            .iter()
+            .filter_map(|b| match b {
+                OpenAIContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => String::new(),
+    }
+}
+
+/// OpenAI 请求：在消息列表最前插入 system 消息；无需注入时返回原请求
+pub fn inject_openai<'a>(
+    request: &'a OpenAIRequest,
+    model: &str,
+    prompts: &HashMap<String, String>,
+) -> Cow<'a, OpenAIRequest> {
+    let Some(prompt) = resolve_system_prompt(model, prompts) else {
+        return Cow::Borrowed(request);
+    };
+    let already_present = request
+        .messages
+        .iter()
+        .filter(|m| m.role == "system" || m.role == "developer")
+        .any(|m| openai_message_text(m).contains(prompt));
+    if already_present {
+        return Cow::Borrowed(request);
+    }
+
+    let mut request = request.clone();
+    request.messages.insert(
+        0,
+        OpenAIMessage {
+            role: "system".to_string(),
+            content: Some(OpenAIContent::String(prompt.to_string())),
+            reasoning_content: None,
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        },
+    );
+    Cow::Owned(request)
+}
+
+/// Anthropic 请求：合并到 `system` 字段 (字符串或文本块数组) 的最前面
+pub fn inject_claude(request: &mut ClaudeRequest, prompts: &HashMap<String, String>) {
+    let Some(prompt) = resolve_system_prompt(&request.model, prompts) else {
+        return;
+    };
+    request.system = Some(match request.system.take() {
+        None => SystemPrompt::String(prompt.to_string()),
+        Some(SystemPrompt::String(existing)) if existing.contains(prompt) => SystemPrompt::String(existing),
+        Some(SystemPrompt::String(existing)) if existing.trim().is_empty() => SystemPrompt::String(prompt.to_string()),
+        Some(SystemPrompt::String(existing)) => SystemPrompt::String(format!("{}\n\n{}", prompt, existing)),
+        Some(SystemPrompt::Array(mut blocks)) => {
+            if !blocks.iter().any(|b| b.text.contains(prompt)) {
+                blocks.insert(
+                    0,
+                    SystemBlock {
+                        block_type: "text".to_string(),
+                        text: prompt.to_string(),
+                    },
+                );
+            }
+            SystemPrompt::Array(blocks)
+        }
+    });
+}
+
+/// Gemini 请求 (`request` 层)：在 `systemInstruction.parts` 最前插入文本
+pub fn inject_gemini(request: &mut Value, model: &str, prompts: &HashMap<String, String>) {
+    let Some(prompt) = resolve_system_prompt(model, prompts) else {
+        return;
+    };
+    if !request.is_object() {
+        return;
+    }
+    let instruction = &mut request["systemInstruction"];
+    if !instruction.is_object() {
+        *instruction = json!({});
+    }
+    let parts = &mut instruction["parts"];
+    if !parts.is_array() {
+        *parts = json!([]);
+    }
+    let Some(parts) = parts.as_array_mut() else {
+        return;
+    };
+    let already_present = parts
+        .iter()
+        .filter_map(|p| p.get("text").and_then(Value::as_str))
+        .any(|text| text.contains(prompt));
+    if !already_present {
+        parts.insert(0, json!({ "text": prompt }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREAMBLE: &str = "Do not generate images of real people.";
+
+    fn prompts() -> HashMap<String, String> {
+        HashMap::from([("gemini-3-pro-image*".to_string(), PREAMBLE.to_string())])
+    }
+
+    #[test]
+    fn test_openai_system_message_injected_once() {
+        let request: OpenAIRequest = serde_json::from_value(json!({
+            "model": "draw",
+            "messages": [
+                { "role": "system", "content": "You are an illustrator." },
+                { "role": "user", "content": "Draw a cat" }
+            ]
+        }))
+        .unwrap();
+
+        let injected = inject_openai(&request, "gemini-3-pro-image", &prompts());
+        assert_eq!(injected.messages.len(), 3);
+        assert_eq!(injected.messages[0].role, "system");
+        assert_eq!(openai_message_text(&injected.messages[0]), PREAMBLE);
+        assert_eq!(openai_message_text(&injected.messages[1]), "You are an illustrator.");
+
+        // 已包含相同前言时不重复注入
+        let again = inject_openai(&injected, "gemini-3-pro-image", &prompts());
+        assert!(matches!(again, Cow::Borrowed(_)));
+
+        // 未匹配的模型不受影响
+        assert!(matches!(inject_openai(&request, "gemini-3-flash", &prompts()), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_anthropic_system_field_injected_for_both_shapes() {
+        let mut request: ClaudeRequest = serde_json::from_value(json!({
+            "model": "gemini-3-pro-image",
+            "max_tokens": 64,
+            "system": "You are an illustrator.",
+            "messages": [{ "role": "user", "content": "Draw a cat" }]
+        }))
+        .unwrap();
+        inject_claude(&mut request, &prompts());
+        match &request.system {
+            Some(SystemPrompt::String(s)) => assert_eq!(s, &format!("{}\n\nYou are an illustrator.", PREAMBLE)),
+            other => panic!("unexpected system: {:?}", other),
+        }
+        inject_claude(&mut request, &prompts());
+        match &request.system {
+            Some(SystemPrompt::String(s)) => assert_eq!(s.matches(PREAMBLE).count(), 1),
+            other => panic!("unexpected system: {:?}", other),
+        }
+
+        let mut request: ClaudeRequest = serde_json::from_value(json!({
+            "model": "gemini-3-pro-image",
+            "max_tokens": 64,
+            "system": [{ "type": "text", "text": "You are an illustrator." }],
+            "messages": [{ "role": "user", "content": "Draw a cat" }]
+        }))
+        .unwrap();
+        inject_claude(&mut request, &prompts());
+        inject_claude(&mut request, &prompts());
+        match &request.system {
+            Some(SystemPrompt::Array(blocks)) => {
+                let texts: Vec<_> = blocks.iter().map(|b| b.text.as_str()).collect();
+                assert_eq!(texts, vec![PREAMBLE, "You are an illustrator."]);
+            }
+            other => panic!("unexpected system: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_gemini_system_instruction_injected() {
+        let mut request = json!({ "contents": [{ "role": "user", "parts": [{ "text": "Draw a cat" }] }] });
+        inject_gemini(&mut request, "gemini-3-pro-image", &prompts());
+        inject_gemini(&mut request, "gemini-3-pro-image", &prompts());
+        assert_eq!(request["systemInstruction"]["parts"], json!([{ "text": PREAMBLE }]));
+    }
+
+    #[test]
+    fn test_most_specific_pattern_wins() {
+        let prompts = HashMap::from([
+            ("gemini-*".to_string(), "generic".to_string()),
+            ("gemini-3-pro-image*".to_string(), PREAMBLE.to_string()),
+            ("gemini-3-pro-image-fast".to_string(), "  ".to_string()),
+        ]);
+        for _ in 0..8 {
+            assert_eq!(resolve_system_prompt("gemini-3-pro-image-4k", &prompts), Some(PREAMBLE));
+            assert_eq!(resolve_system_prompt("gemini-2.5-flash", &prompts), Some("generic"));
+        }
+        // 精确匹配的空白提示词视为未配置，不回退到通配符
+        assert_eq!(resolve_system_prompt("gemini-3-pro-image-fast", &prompts), None);
+    }
+}
@@ -0,0 +1,166 @@
+// 本地 token 估算
+// 上游无对应的计数接口时，用近似分词规则估算 Anthropic count_tokens 请求与 Gemini 请求体的输入 token 数：
+// ASCII 文本约 4 字符 1 token，CJK 等非 ASCII 字符约 1 字符 1 token
+use serde_json::{json, Value};
+
+/// 每条消息的结构开销 (角色标记、分隔符)
+const MESSAGE_OVERHEAD: u64 = 4;
+/// 每个工具定义的结构开销
+const TOOL_OVERHEAD: u64 = 8;
+/// 图片/文档无法获知尺寸，按固定值计入
+const MEDIA_TOKENS: u64 = 1600;
+
+/// 估算一段文本的 token 数
+pub fn estimate_text_tokens(text: &str) -> u64 {
+    let mut ascii = 0u64;
+    let mut other = 0u64;
+    for c in text.chars() {
+        if c.is_ascii() {
+            ascii += 1;
+        } else {
+            other += 1;
+        }
+    }
+    ascii.div_ceil(4) + other
+}
+
+fn estimate_json_tokens(value: &Value) -> u64 {
+    estimate_text_tokens(&value.to_string())
+}
+
+/// 估算内容 (字符串或内容块数组) 的 token 数
+fn estimate_content_tokens(content: &Value) -> u64 {
+    match content {
+        Value::String(text) => estimate_text_tokens(text),
+        Value::Array(blocks) => blocks.iter().map(estimate_block_tokens).sum(),
+        Value::Null => 0,
+        other => estimate_json_tokens(other),
+    }
+}
+
+fn estimate_block_tokens(block: &Value) -> u64 {
+    match block.get("type").and_then(Value::as_str) {
+        Some("text") => block.get("text").and_then(Value::as_str).map_or(0, estimate_text_tokens),
+        Some("thinking") => block.get("thinking").and_then(Value::as_str).map_or(0, estimate_text_tokens),
+        Some("image") | Some("document") => MEDIA_TOKENS,
+        Some("tool_use") => {
+            block.get("name").and_then(Value::as_str).map_or(0, estimate_text_tokens)
+                + block.get("input").map_or(0, estimate_json_tokens)
+        }
+        Some("tool_result") => block.get("content").map_or(0, estimate_content_tokens),
+        // 其余块类型 (redacted_thinking 等) 按原始 JSON 估算
+        _ => estimate_json_tokens(block),
+    }
+}
+
+/// 估算 Anthropic Messages 请求的输入 token 数 (system + messages + tools)
+pub fn estimate_claude_input_tokens(body: &Value) -> u64 {
+    let system = body.get("system").map_or(0, estimate_content_tokens);
+    let messages: u64 = body
+        .get("messages")
+        .and_then(Value::as_array)
+        .map_or(0, |messages| {
+            messages
+                .iter()
+                .map(|m| MESSAGE_OVERHEAD + m.get("content").map_or(0, estimate_content_tokens))
+                .sum()
+        });
+    let tools: u64 = body
+        .get("tools")
+        .and_then(Value::as_array)
+        .map_or(0, |tools| tools.iter().map(|t| TOOL_OVERHEAD + estimate_json_tokens(t)).sum());
+    system + messages + tools
+}
+
+/// 估算单个 Gemini content (`{role, parts}`) 的 token 数，含消息结构开销
+/// thoughtSignature 等元数据不计入
+pub fn estimate_gemini_content_tokens(content: &Value) -> u64 {
+    let parts: u64 = content
+        .get("parts")
+        .and_then(Value::as_array)
+        .map_or(0, |parts| {
+            parts
+                .iter()
+                .map(|part| {
+                    if let Some(text) = part.get("text").and_then(Value::as_str) {
+                        estimate_text_tokens(text)
+                    } else if part.get("inlineData").is_some() || part.get("fileData").is_some() {
+                        MEDIA_TOKENS
+                    } else if let Some(call) = part.get("functionCall") {
+                        estimate_json_tokens(call)
+                    } else if let Some(response) = part.get("functionResponse") {
+                        estimate_json_tokens(response)
+                    } else {
+                        0
+                    }
+                })
+                .sum()
+        });
+    MESSAGE_OVERHEAD + parts
+}
+
+/// 估算 Gemini 请求 (`request` 层) 的输入 token 数 (systemInstruction + contents + tools)
+pub fn estimate_gemini_request_tokens(request: &Value) -> u64 {
+    let system = request.get("systemInstruction").map_or(0, estimate_gemini_content_tokens);
+    let contents: u64 = request
+        .get("contents")
+        .and_then(Value::as_array)
+        .map_or(0, |contents| contents.iter().map(estimate_gemini_content_tokens).sum());
+    let tools: u64 = request
+        .get("tools")
+        .and_then(Value::as_array)
+        .map_or(0, |tools| {
+            tools
+                .iter()
+                .flat_map(|t| t.get("functionDeclarations").and_then(Value::as_array).into_iter().flatten())
+                .map(|decl| TOOL_OVERHEAD + estimate_json_tokens(decl))
+                .sum()
+        });
+    system + contents + tools
+}
+
+/// 构造 Anthropic count_tokens 响应
+pub fn claude_count_tokens_response(body: &Value) -> Value {
+    json!({ "input_tokens": estimate_claude_input_tokens(body) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_tokens_returns_plausible_input_tokens() {
+        let body = json!({
+            "model": "claude-sonnet-4-5",
+            "system": "You are a helpful assistant.",
+            "messages": [
+                { "role": "user", "content": "Write a haiku about the ocean, please." },
+                { "role": "assistant", "content": [{ "type": "text", "text": "Waves fold into foam" }] },
+                { "role": "user", "content": [{ "type": "text", "text": "Another one about mountains." }] }
+            ]
+        });
+
+        let response = claude_count_tokens_response(&body);
+        let object = response.as_object().unwrap();
+        assert_eq!(object.len(), 1);
+        let tokens = object["input_tokens"].as_u64().unwrap();
+        // 约 130 个 ASCII 字符 + 3 条消息开销
+        assert!((30..=60).contains(&tokens), "unexpected estimate: {}", tokens);
+
+        // 工具定义与更长的内容会增加估算值
+        let mut with_tools = body.clone();
+        with_tools["tools"] = json!([{
+            "name": "get_weather",
+            "description": "Get the weather for a city",
+            "input_schema": { "type": "object", "properties": { "city": { "type": "string" } } }
+        }]);
+        assert!(estimate_claude_input_tokens(&with_tools) > tokens);
+    }
+
+    #[test]
+    fn test_non_ascii_text_counts_per_char() {
+        assert_eq!(estimate_text_tokens("abcdefgh"), 2);
+        assert_eq!(estimate_text_tokens("你好世界"), 4);
+        assert_eq!(estimate_text_tokens(""), 0);
+    }
+}
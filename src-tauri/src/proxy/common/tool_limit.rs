@@ -0,0 +1,189 @@
+// 单请求工具数量上限
+// 部分 Agent 注册的工具数超过 Gemini 接受的上限，上游会直接拒绝请求；按配置的策略报错或裁剪 functionDeclarations
+// 在协议转换 (含 Schema 清洗) 之后作用于 Gemini 请求体，只统计 functionDeclarations，googleSearch 等内置工具不计入
+use serde_json::Value;
+use std::collections::HashSet;
+
+use crate::proxy::config::{ToolLimitConfig, ToolPruneStrategy};
+
+/// 收集对话中已调用过的工具名 (历史 functionCall 与 toolConfig 允许列表)
+fn used_tool_names(request: &Value) -> HashSet<String> {
+    let mut used = HashSet::new();
+    if let Some(contents) = request.get("contents").and_then(Value::as_array) {
+        for content in contents {
+            let Some(parts) = content.get("parts").and_then(Value::as_array) else {
+                continue;
+            };
+            for part in parts {
+                if let Some(name) = part.pointer("/functionCall/name").and_then(Value::as_str) {
+                    used.insert(name.to_string());
+                }
+            }
+        }
+    }
+    if let Some(allowed) = request
+        .pointer("/toolConfig/functionCallingConfig/allowedFunctionNames")
+        .and_then(Value::as_array)
+    {
+        used.extend(allowed.iter().filter_map(Value::as_str).map(str::to_string));
+    }
+    used
+}
+
+fn declaration_name(decl: &Value) -> &str {
+    decl.get("name").and_then(Value::as_str).unwrap_or("")
+}
+
+/// 对 Gemini 请求 (`request` 层，含 `tools` 与 `contents`) 应用工具数量上限
+/// 超限且策略为 `Error` 时返回错误信息；其余策略裁剪后返回被丢弃的工具名
+pub fn apply_tool_limit(request: &mut Value, config: &ToolLimitConfig) -> Result<Vec<String>, String> {
+    let Some(max_tools) = config.max_tools else {
+        return Ok(Vec::new());
+    };
+    let total: usize = request
+        .get("tools")
+        .and_then(Value::as_array)
+        .map_or(0, |tools| {
+            tools
+                .iter()
+                .filter_map(|t| t.get("functionDeclarations").and_then(Value::as_array))
+                .map(Vec::len)
+                .sum()
+        });
+    if total <= max_tools {
+        return Ok(Vec::new());
+    }
+
+    // 按声明顺序决定保留哪些工具 (索引为所有 functionDeclarations 展平后的位置)
+    let keep: HashSet<usize> = match config.strategy {
+        ToolPruneStrategy::Error => {
+            return Err(format!(
+                "Request declares {} tools, exceeding the configured max_tools ({})",
+                total, max_tools
+            ));
+        }
+        ToolPruneStrategy::TruncateTail => (0..max_tools).collect(),
+        ToolPruneStrategy::DropUnused => {
+            let used = used_tool_names(request);
+            let names: Vec<String> = request["tools"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|t| t.get("functionDeclarations").and_then(Value::as_array))
+                .flatten()
+                .map(|d| declaration_name(d).to_string())
+                .collect();
+            // 先保留对话中用过的工具，余量按原顺序补齐未使用的工具
+            let (used_idx, unused_idx): (Vec<usize>, Vec<usize>) =
+                (0..names.len()).partition(|&i| used.contains(&names[i]));
+            used_idx.into_iter().chain(unused_idx).take(max_tools).collect()
+        }
+    };
+
+    let mut dropped = Vec::new();
+    let mut index = 0;
+    if let Some(tools) = request.get_mut("tools").and_then(Value::as_array_mut) {
+        for tool in tools.iter_mut() {
+            let Some(decls) = tool.get_mut("functionDeclarations").and_then(Value::as_array_mut) else {
+                continue;
+            };
+            decls.retain(|decl| {
+                let kept = keep.contains(&index);
+                if !kept {
+                    dropped.push(declaration_name(decl).to_string());
+                }
+                index += 1;
+                kept
+            });
+        }
+        // 裁空的 functionDeclarations 条目整体移除
+        tools.retain(|tool| {
+            tool.get("functionDeclarations")
+                .and_then(Value::as_array)
+                .is_none_or(|decls| !decls.is_empty())
+        });
+    }
+
+    tracing::warn!(
+        "[Tool-Limit] {} tools exceed max_tools {}, pruned ({:?}): {}",
+        total,
+        max_tools,
+        config.strategy,
+        dropped.join(", ")
+    );
+    Ok(dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request_with_tools(names: &[&str]) -> Value {
+        let decls: Vec<Value> = names
+            .iter()
+            .map(|n| json!({ "name": n, "parameters": { "type": "object" } }))
+            .collect();
+        json!({
+            "contents": [{ "role": "user", "parts": [{ "text": "hi" }] }],
+            "tools": [{ "functionDeclarations": decls }, { "googleSearch": {} }]
+        })
+    }
+
+    fn tool_names(request: &Value) -> Vec<String> {
+        request["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|t| t.get("functionDeclarations").and_then(Value::as_array))
+            .flatten()
+            .map(|d| declaration_name(d).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let mut request = request_with_tools(&["a", "b", "c"]);
+        let dropped = apply_tool_limit(&mut request, &ToolLimitConfig::default()).unwrap();
+        assert!(dropped.is_empty());
+        assert_eq!(tool_names(&request), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_error_strategy_rejects_excess_tools() {
+        let config = ToolLimitConfig { max_tools: Some(2), strategy: ToolPruneStrategy::Error };
+        let mut request = request_with_tools(&["a", "b", "c"]);
+        let err = apply_tool_limit(&mut request, &config).unwrap_err();
+        assert!(err.contains("3 tools"));
+        assert!(err.contains("max_tools (2)"));
+        assert_eq!(tool_names(&request), vec!["a", "b", "c"]);
+
+        // 未超限时不报错
+        let mut request = request_with_tools(&["a", "b"]);
+        assert!(apply_tool_limit(&mut request, &config).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_truncate_tail_keeps_leading_tools() {
+        let config = ToolLimitConfig { max_tools: Some(2), strategy: ToolPruneStrategy::TruncateTail };
+        let mut request = request_with_tools(&["a", "b", "c", "d"]);
+        let dropped = apply_tool_limit(&mut request, &config).unwrap();
+        assert_eq!(dropped, vec!["c", "d"]);
+        assert_eq!(tool_names(&request), vec!["a", "b"]);
+        // 内置工具不计入上限，保持不变
+        assert!(request["tools"].as_array().unwrap().iter().any(|t| t.get("googleSearch").is_some()));
+    }
+
+    #[test]
+    fn test_drop_unused_prefers_called_tools() {
+        let config = ToolLimitConfig { max_tools: Some(2), strategy: ToolPruneStrategy::DropUnused };
+        let mut request = request_with_tools(&["a", "b", "c", "d"]);
+        request["contents"] = json!([
+            { "role": "user", "parts": [{ "text": "hi" }] },
+            { "role": "model", "parts": [{ "functionCall": { "name": "d", "args": {} } }] }
+        ]);
+        let dropped = apply_tool_limit(&mut request, &config).unwrap();
+        assert_eq!(dropped, vec!["b", "c"]);
+        assert_eq!(tool_names(&request), vec!["a", "d"]);
+    }
+}
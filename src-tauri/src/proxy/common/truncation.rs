@@ -0,0 +1,71 @@
+// 截断响应告警
+// 上游因 MAX_TOKENS 停止时仍返回 200，部分客户端会把截断内容当作完整回复；开启后在非流式响应上附加告警头
+// 流式响应的响应头先于 finish_reason 发出，客户端需自行检查 finish_reason / stop_reason
+use axum::http::HeaderValue;
+use axum::response::Response;
+use serde_json::Value;
+
+use crate::proxy::upstream::errors::ErrorProtocol;
+
+/// 截断告警响应头 (值为截断原因)
+pub const TRUNCATED_HEADER: &str = "x-response-truncated";
+
+/// 判断 (已转换为客户端协议的) 响应是否因输出 token 上限被截断
+pub fn is_truncated(protocol: ErrorProtocol, body: &Value) -> bool {
+    match protocol {
+        ErrorProtocol::OpenAI => body
+            .get("choices")
+            .and_then(Value::as_array)
+            .is_some_and(|choices| {
+                choices
+                    .iter()
+                    .any(|c| c.get("finish_reason").and_then(Value::as_str) == Some("length"))
+            }),
+        ErrorProtocol::Anthropic => body.get("stop_reason").and_then(Value::as_str) == Some("max_tokens"),
+        ErrorProtocol::Gemini => body
+            .get("candidates")
+            .and_then(Value::as_array)
+            .is_some_and(|candidates| {
+                candidates
+                    .iter()
+                    .any(|c| c.get("finishReason").and_then(Value::as_str) == Some("MAX_TOKENS"))
+            }),
+    }
+}
+
+/// 开启告警且响应被截断时附加告警头
+pub fn apply_warning_header(enabled: bool, protocol: ErrorProtocol, body: &Value, response: &mut Response) {
+    if enabled && is_truncated(protocol, body) {
+        response
+            .headers_mut()
+            .insert(TRUNCATED_HEADER, HeaderValue::from_static("max_tokens"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+    use serde_json::json;
+
+    #[test]
+    fn test_truncation_detected_per_protocol() {
+        assert!(is_truncated(ErrorProtocol::OpenAI, &json!({"choices": [{"finish_reason": "length"}]})));
+        assert!(!is_truncated(ErrorProtocol::OpenAI, &json!({"choices": [{"finish_reason": "stop"}]})));
+        assert!(is_truncated(ErrorProtocol::Anthropic, &json!({"stop_reason": "max_tokens"})));
+        assert!(!is_truncated(ErrorProtocol::Anthropic, &json!({"stop_reason": "end_turn"})));
+        assert!(is_truncated(ErrorProtocol::Gemini, &json!({"candidates": [{"finishReason": "MAX_TOKENS"}]})));
+    }
+
+    #[test]
+    fn test_warning_header_only_when_enabled() {
+        let body = json!({"stop_reason": "max_tokens"});
+
+        let mut response = "ok".into_response();
+        apply_warning_header(false, ErrorProtocol::Anthropic, &body, &mut response);
+        assert!(response.headers().get(TRUNCATED_HEADER).is_none());
+
+        apply_warning_header(true, ErrorProtocol::Anthropic, &body, &mut response);
+        assert_eq!(response.headers()[TRUNCATED_HEADER], "max_tokens");
+    }
+}
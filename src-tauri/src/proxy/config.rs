@@ -108,6 +108,17 @@ impl Default for ModelStickiness {
     }
 }
 
+/// 候选模型的选择方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionMode {
+    /// 按配置 (及 model_priority) 的顺序
+    #[default]
+    Ordered,
+    /// 按历史成功率 (随时间衰减) 从高到低排序
+    AdaptiveSuccess,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelFallbackPolicy {
     #[serde(default)]
@@ -116,6 +127,8 @@ pub struct ModelFallbackPolicy {
     pub stickiness: ModelStickiness,
     #[serde(default)]
     pub max_model_hops: Option<usize>,
+    #[serde(default)]
+    pub selection_mode: SelectionMode,
 }
 
 impl Default for ModelFallbackPolicy {
@@ -124,10 +137,67 @@ impl Default for ModelFallbackPolicy {
             model_priority: ModelPriority::AccuracyFirst,
             stickiness: ModelStickiness::Strong,
             max_model_hops: None,
+            selection_mode: SelectionMode::Ordered,
         }
     }
 }
 
+/// OpenAI 系模型家族分组规则
+/// 模型名 (小写) 匹配 `pattern` 时使用 `openai_mapping[mapping_key]`；
+/// `pattern` 不含 `*` 时按前缀匹配，含 `*` 时按通配符匹配
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OpenAIFamilyRule {
+    pub pattern: String,
+    pub mapping_key: String,
+    /// 模型名 (小写) 包含其中任一片段时不匹配本规则
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl OpenAIFamilyRule {
+    pub fn new(pattern: &str, mapping_key: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            mapping_key: mapping_key.to_string(),
+            exclude: Vec::new(),
+        }
+    }
+
+    pub fn excluding(mut self, fragments: &[&str]) -> Self {
+        self.exclude = fragments.iter().map(|f| f.to_string()).collect();
+        self
+    }
+
+    /// 规则对应的通配符模式 (小写)：不含 `*` 的前缀规则等价于 `pattern*`
+    pub fn glob_pattern(&self) -> String {
+        let pattern = self.pattern.to_lowercase();
+        if pattern.contains('*') {
+            pattern
+        } else {
+            format!("{}*", pattern)
+        }
+    }
+}
+
+/// 内置家族规则 (按顺序匹配，首个在 openai_mapping 中存在映射的规则生效)
+pub fn default_openai_family_rules() -> Vec<OpenAIFamilyRule> {
+    vec![
+        // GPT-4 系列 (推理模型)
+        OpenAIFamilyRule::new("o1-", "gpt-4-series"),
+        OpenAIFamilyRule::new("o3-", "gpt-4-series"),
+        // GPT-4o / 3.5 系列 (均衡与轻量, 含 4o, mini, turbo)
+        OpenAIFamilyRule::new("*4o*", "gpt-4o-series"),
+        OpenAIFamilyRule::new("gpt-3.5", "gpt-4o-series"),
+        OpenAIFamilyRule::new("*mini*", "gpt-4o-series"),
+        OpenAIFamilyRule::new("*turbo*", "gpt-4o-series"),
+        // GPT-4 经典 (排除 4o/mini/turbo，避免 gpt-4o-series 未配置时被归入 GPT-4 系列)
+        OpenAIFamilyRule::new("gpt-4", "gpt-4-series").excluding(&["o", "mini", "turbo"]),
+        // GPT-5 系列，未配置时回退到 gpt-4-series
+        OpenAIFamilyRule::new("gpt-5", "gpt-5-series"),
+        OpenAIFamilyRule::new("gpt-5", "gpt-4-series"),
+    ]
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelStrategy {
     pub candidates: Vec<String>,
@@ -135,6 +205,786 @@ pub struct ModelStrategy {
     pub policy: ModelFallbackPolicy,
 }
 
+/// 策略 A/B 实验：匹配的请求按比例分流到两个策略，分组记录在请求日志中
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StrategyExperiment {
+    /// A 组使用的策略 ID
+    pub arm_a: String,
+    /// B 组使用的策略 ID
+    pub arm_b: String,
+    /// 分到 A 组的请求百分比 (0-100)，其余走 B 组
+    pub a_percent: u8,
+}
+
+/// 流式响应配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamingConfig {
+    /// 等待上游首字节期间发送保活帧的间隔 (秒)，0 表示关闭
+    #[serde(default)]
+    pub keepalive_interval_secs: u64,
+
+    /// 单个流式响应的最大字节数，超出后中止，0 表示不限制
+    #[serde(default = "default_max_stream_bytes")]
+    pub max_stream_bytes: u64,
+
+    /// 单个流式响应的最长持续时间 (秒)，超出后中止，0 表示不限制
+    #[serde(default = "default_max_stream_duration_secs")]
+    pub max_stream_duration_secs: u64,
+
+    /// 不支持流式输出的模型 (支持 `*` 通配)
+    #[serde(default = "default_non_streaming_models")]
+    pub non_streaming_models: Vec<String>,
+
+    /// 客户端对上述模型请求流式输出时的处理方式
+    #[serde(default)]
+    pub non_streaming_action: NonStreamingAction,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            keepalive_interval_secs: 0,
+            max_stream_bytes: default_max_stream_bytes(),
+            max_stream_duration_secs: default_max_stream_duration_secs(),
+            non_streaming_models: default_non_streaming_models(),
+            non_streaming_action: NonStreamingAction::default(),
+        }
+    }
+}
+
+/// 不支持流式输出的模型收到流式请求时的处理方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NonStreamingAction {
+    /// 以非流式调用上游，再把完整响应切成 SSE 事件返回
+    #[default]
+    Emulate,
+    /// 返回 400 并提示改用非流式请求
+    Reject,
+}
+
+fn default_non_streaming_models() -> Vec<String> {
+    vec!["gemini-3-pro-image*".to_string()]
+}
+
+fn default_max_stream_bytes() -> u64 {
+    64 * 1024 * 1024 // 64 MiB
+}
+
+fn default_max_stream_duration_secs() -> u64 {
+    1800 // 30 分钟
+}
+
+/// Gemini 上下文缓存 (cachedContents) 配置
+/// 对稳定的 systemInstruction + tools 前缀创建缓存句柄，后续相同前缀的请求直接引用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextCacheConfig {
+    /// 是否启用 (默认关闭，属于面向 Agent 场景的高级优化)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 缓存句柄有效期 (秒)
+    #[serde(default = "default_context_cache_ttl_secs")]
+    pub ttl_secs: u64,
+
+    /// 前缀最小字节数，过小的前缀缓存收益不足 (上游也有最小 token 要求)
+    #[serde(default = "default_context_cache_min_bytes")]
+    pub min_prefix_bytes: usize,
+
+    /// 前缀最大字节数，超出则不缓存
+    #[serde(default = "default_context_cache_max_bytes")]
+    pub max_prefix_bytes: usize,
+
+    /// 本地最多保留的缓存句柄数
+    #[serde(default = "default_context_cache_max_entries")]
+    pub max_entries: usize,
+
+    /// 定时预热：在句柄到期前重新创建，避免请求遇到冷缓存
+    #[serde(default)]
+    pub warm: ContextCacheWarmConfig,
+}
+
+/// 上下文缓存定时预热配置 (仅在上下文缓存启用时生效)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextCacheWarmConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 检查间隔 (秒)
+    #[serde(default = "default_context_cache_warm_interval_secs")]
+    pub interval_secs: u64,
+
+    /// 句柄剩余有效期不足该值 (秒) 时重新创建
+    #[serde(default = "default_context_cache_warm_refresh_before_secs")]
+    pub refresh_before_secs: u64,
+
+    /// 句柄闲置超过该值 (秒) 后不再预热，0 表示使用 `ttl_secs`
+    #[serde(default)]
+    pub max_idle_secs: u64,
+
+    /// 需要预热的模型 (支持 `*` 通配符)，为空表示预热所有缓存句柄
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+impl Default for ContextCacheWarmConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: default_context_cache_warm_interval_secs(),
+            refresh_before_secs: default_context_cache_warm_refresh_before_secs(),
+            max_idle_secs: 0,
+            models: Vec::new(),
+        }
+    }
+}
+
+fn default_context_cache_warm_interval_secs() -> u64 {
+    60
+}
+
+fn default_context_cache_warm_refresh_before_secs() -> u64 {
+    300
+}
+
+impl Default for ContextCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: default_context_cache_ttl_secs(),
+            min_prefix_bytes: default_context_cache_min_bytes(),
+            max_prefix_bytes: default_context_cache_max_bytes(),
+            max_entries: default_context_cache_max_entries(),
+            warm: ContextCacheWarmConfig::default(),
+        }
+    }
+}
+
+fn default_context_cache_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_context_cache_min_bytes() -> usize {
+    16 * 1024
+}
+
+fn default_context_cache_max_bytes() -> usize {
+    4 * 1024 * 1024
+}
+
+fn default_context_cache_max_entries() -> usize {
+    64
+}
+
+/// 透传上游 (z.ai Anthropic 兼容接口) 时的客户端请求头过滤
+/// Gemini 上游的请求头由代理自行构造，不透传客户端请求头
+/// 名称不区分大小写，以 `*` 结尾表示前缀匹配 (如 `x-trace-*`)；deny 优先于 allow
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HeaderForwardingConfig {
+    /// 允许透传的请求头，其余一律剥离
+    #[serde(default = "default_forward_allow_headers")]
+    pub allow: Vec<String>,
+    /// 始终剥离的请求头 (鉴权由代理重新设置)
+    #[serde(default = "default_forward_deny_headers")]
+    pub deny: Vec<String>,
+}
+
+impl Default for HeaderForwardingConfig {
+    fn default() -> Self {
+        Self {
+            allow: default_forward_allow_headers(),
+            deny: default_forward_deny_headers(),
+        }
+    }
+}
+
+fn default_forward_allow_headers() -> Vec<String> {
+    [
+        "content-type",
+        "accept",
+        "accept-encoding",
+        "cache-control",
+        "user-agent",
+        "anthropic-version",
+        "anthropic-beta",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+fn default_forward_deny_headers() -> Vec<String> {
+    [
+        "authorization",
+        "x-api-key",
+        "proxy-authorization",
+        "cookie",
+        "host",
+        "content-length",
+        "openai-organization",
+        "openai-project",
+        "x-goog-api-key",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+/// 维护模式：开启后除健康检查外的所有端点直接返回 503 (按客户端协议格式)，不发起任何上游调用
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct MaintenanceModeConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 返回给客户端的提示信息，为空时使用默认文案
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl MaintenanceModeConfig {
+    pub fn effective_message(&self) -> &str {
+        self.message
+            .as_deref()
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+            .unwrap_or("Service is under maintenance, please try again later")
+    }
+}
+
+/// 各协议端点的启用开关 (默认全部启用)，禁用的端点返回 404
+/// 模型列表、健康检查与内部端点始终可用
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EnabledEndpointsConfig {
+    /// `/v1/chat/completions`
+    #[serde(default = "default_true")]
+    pub openai_chat: bool,
+    /// `/v1/completions`、`/v1/responses`
+    #[serde(default = "default_true")]
+    pub openai_completions: bool,
+    /// `/v1/images/*`
+    #[serde(default = "default_true")]
+    pub openai_images: bool,
+    /// `/v1/audio/transcriptions`
+    #[serde(default = "default_true")]
+    pub audio_transcriptions: bool,
+    /// `/v1/messages`、`/v1/messages/count_tokens`
+    #[serde(default = "default_true")]
+    pub anthropic_messages: bool,
+    /// `/v1beta/*` (Gemini 原生协议)
+    #[serde(default = "default_true")]
+    pub gemini: bool,
+    /// `/mcp/*` (z.ai MCP 反代)
+    #[serde(default = "default_true")]
+    pub mcp: bool,
+}
+
+impl Default for EnabledEndpointsConfig {
+    fn default() -> Self {
+        Self {
+            openai_chat: true,
+            openai_completions: true,
+            openai_images: true,
+            audio_transcriptions: true,
+            anthropic_messages: true,
+            gemini: true,
+            mcp: true,
+        }
+    }
+}
+
+/// 模型名规范化规则
+/// 在路由查找前剥离客户端附加的修饰 (供应商前缀、`@版本`、`:latest` 等)，原始模型名仍用于日志与回显
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelCanonicalizationConfig {
+    /// 是否启用
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 需要剥离的供应商前缀 (不区分大小写)，如 `anthropic/`
+    #[serde(default = "default_canonical_provider_prefixes")]
+    pub strip_prefixes: Vec<String>,
+
+    /// 需要剥离的后缀 (不区分大小写)，如 `:latest`
+    #[serde(default = "default_canonical_suffixes")]
+    pub strip_suffixes: Vec<String>,
+
+    /// 是否剥离末尾的 `@...` 版本标记
+    #[serde(default = "default_true")]
+    pub strip_at_version: bool,
+}
+
+impl Default for ModelCanonicalizationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            strip_prefixes: default_canonical_provider_prefixes(),
+            strip_suffixes: default_canonical_suffixes(),
+            strip_at_version: true,
+        }
+    }
+}
+
+/// 自动选型别名
+/// 客户端传入 `default` / `auto` 等哨兵模型名时，表示交由代理选择模型
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoModelConfig {
+    /// 视为"由代理选择"的哨兵模型名 (不区分大小写)
+    #[serde(default = "default_auto_model_aliases")]
+    pub aliases: Vec<String>,
+
+    /// 哨兵名路由到的目标模型，支持 `strategy:<id>`；为空时哨兵名按普通模型名路由
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+impl Default for AutoModelConfig {
+    fn default() -> Self {
+        Self {
+            aliases: default_auto_model_aliases(),
+            target: None,
+        }
+    }
+}
+
+impl AutoModelConfig {
+    /// 模型名命中哨兵名且配置了目标时返回目标
+    pub fn resolve(&self, model: &str) -> Option<&str> {
+        let target = self.target.as_deref().map(str::trim).filter(|t| !t.is_empty())?;
+        let model = model.trim();
+        self.aliases
+            .iter()
+            .any(|alias| alias.trim().eq_ignore_ascii_case(model))
+            .then_some(target)
+    }
+}
+
+fn default_auto_model_aliases() -> Vec<String> {
+    vec!["default".to_string(), "auto".to_string()]
+}
+
+/// 客户端省略模型名时按协议使用的默认模型
+/// 仅在完全省略时生效 (请求体缺少 `model` 字段，或 Gemini 路径中模型段为空)，显式传入空字符串仍报错
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct DefaultModelPerProtocol {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub openai: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anthropic: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gemini: Option<String>,
+}
+
+impl DefaultModelPerProtocol {
+    /// 指定协议的默认模型 (空白视为未配置)
+    pub fn for_protocol(&self, protocol: crate::proxy::upstream::errors::ErrorProtocol) -> Option<&str> {
+        use crate::proxy::upstream::errors::ErrorProtocol;
+        let model = match protocol {
+            ErrorProtocol::OpenAI => &self.openai,
+            ErrorProtocol::Anthropic => &self.anthropic,
+            ErrorProtocol::Gemini => &self.gemini,
+        };
+        model.as_deref().map(str::trim).filter(|m| !m.is_empty())
+    }
+}
+
+fn default_canonical_provider_prefixes() -> Vec<String> {
+    ["anthropic/", "openai/", "google/", "gemini/"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_canonical_suffixes() -> Vec<String> {
+    vec![":latest".to_string()]
+}
+
+/// 已弃用的上游模型
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DeprecatedModel {
+    /// 弃用的模型 ID (支持 `*` 通配)
+    pub model: String,
+    /// 建议替换的模型 ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+}
+
+fn default_deprecated_models() -> Vec<DeprecatedModel> {
+    vec![DeprecatedModel {
+        model: "gemini-2.0-flash-exp".to_string(),
+        replacement: Some("gemini-2.5-flash".to_string()),
+    }]
+}
+
+fn default_model_min_tiers() -> HashMap<String, String> {
+    [
+        ("claude-opus-*", "PRO"),
+        ("gemini-3-pro-high", "PRO"),
+        ("gemini-3-pro-image*", "PRO"),
+    ]
+    .into_iter()
+    .map(|(model, tier)| (model.to_string(), tier.to_string()))
+    .collect()
+}
+
+/// 订阅等级的高低 (FREE < PRO < ULTRA)，未知等级返回 None
+pub fn subscription_tier_rank(tier: &str) -> Option<u8> {
+    match tier.trim().to_ascii_uppercase().as_str() {
+        "FREE" => Some(0),
+        "PRO" => Some(1),
+        "ULTRA" => Some(2),
+        _ => None,
+    }
+}
+
+/// 模型列表的可服务性过滤 (由 `advertise_servable_only` 与 `model_min_tiers` 组合而来)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServableModelsFilter {
+    pub enabled: bool,
+    pub min_tiers: HashMap<String, String>,
+}
+
+impl ServableModelsFilter {
+    /// 模型所需的最低订阅等级：精确匹配优先，其次取最具体的通配规则
+    pub fn required_tier(&self, model: &str) -> Option<&str> {
+        crate::proxy::mappers::common_utils::resolve_model_entry(model, &self.min_tiers).map(String::as_str)
+    }
+}
+
+/// 路由命中弃用模型时的处理策略 (由 `deprecated_models` 与 `auto_replace_deprecated` 组合而来)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelDeprecationPolicy {
+    pub models: Vec<DeprecatedModel>,
+    pub auto_replace: bool,
+}
+
+/// 监控模式
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MonitorMode {
+    /// 保留完整请求日志并更新统计
+    #[default]
+    Full,
+    /// 仅更新统计计数，不保留单条日志 (适用于资源受限设备)
+    CountersOnly,
+}
+
+/// 推理内容输出模式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasoningOutputMode {
+    /// 保持原样返回
+    #[default]
+    Passthrough,
+    /// 移除推理内容 (适用于无法处理 thinking 块的客户端)
+    Strip,
+    /// 从正文中移出，放入独立的 `reasoning` 字段
+    Separate,
+}
+
+/// 工具数量超限时的处理策略
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolPruneStrategy {
+    /// 直接返回 400
+    #[default]
+    Error,
+    /// 按声明顺序保留前 max_tools 个工具
+    TruncateTail,
+    /// 优先保留对话中已调用过的工具，其余按声明顺序补齐
+    DropUnused,
+}
+
+/// 单请求工具数量上限
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ToolLimitConfig {
+    /// 最多允许的 functionDeclarations 数量，为空时不限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tools: Option<usize>,
+    /// 超限处理策略
+    #[serde(default)]
+    pub strategy: ToolPruneStrategy,
+}
+
+/// 远程媒体 (http/https 图片、文档 URL) 的下载策略
+/// 关闭时 URL 以 fileData 原样交给上游；开启后由代理下载为 inlineData，且拒绝解析到内网地址的主机
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct RemoteMediaConfig {
+    /// 是否由代理下载远程媒体 (默认关闭)
+    #[serde(default)]
+    pub enabled: bool,
+    /// 允许下载的主机 (精确匹配或 `*.example.com`)，为空时允许任意公网主机
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+}
+
+/// OpenAI `logprobs` / `top_logprobs` 参数的处理
+/// 目标模型支持时转换为 Gemini 的 `responseLogprobs` / `logprobs`，并把返回的 `logprobsResult` 映射回 OpenAI 格式
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LogprobsConfig {
+    /// 支持返回 logprobs 的上游模型 (支持 `*` 通配)
+    #[serde(default = "default_logprobs_models")]
+    pub supported_models: Vec<String>,
+    /// 目标模型不支持时的处理方式
+    #[serde(default)]
+    pub unsupported_action: LogprobsUnsupportedAction,
+}
+
+fn default_logprobs_models() -> Vec<String> {
+    vec!["gemini-*".to_string()]
+}
+
+impl Default for LogprobsConfig {
+    fn default() -> Self {
+        Self {
+            supported_models: default_logprobs_models(),
+            unsupported_action: LogprobsUnsupportedAction::default(),
+        }
+    }
+}
+
+/// 目标模型不支持 logprobs 时的处理方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogprobsUnsupportedAction {
+    /// 忽略该参数并记录警告，响应中不含 logprobs
+    #[default]
+    Strip,
+    /// 返回 400 并说明该模型不支持 logprobs
+    Error,
+}
+
+/// `/readyz` 要求的最少健康账号数
+/// 健康账号指未被限流且未处于排空状态的账号；数量与比例同时配置时取两者中较大的要求
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReadinessThresholdConfig {
+    /// 最少健康账号数 (默认 1，与旧行为一致)
+    #[serde(default = "default_min_healthy_accounts")]
+    pub min_healthy_accounts: usize,
+    /// 最少健康账号比例 (0, 1]，按账号总数向上取整，为空时不限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_healthy_fraction: Option<f64>,
+}
+
+fn default_min_healthy_accounts() -> usize {
+    1
+}
+
+impl Default for ReadinessThresholdConfig {
+    fn default() -> Self {
+        Self {
+            min_healthy_accounts: default_min_healthy_accounts(),
+            min_healthy_fraction: None,
+        }
+    }
+}
+
+impl ReadinessThresholdConfig {
+    /// 账号总数为 `total` 时就绪所需的健康账号数
+    pub fn required_healthy(&self, total: usize) -> usize {
+        let by_fraction = self
+            .min_healthy_fraction
+            .map_or(0, |f| (f * total as f64).ceil() as usize);
+        self.min_healthy_accounts.max(by_fraction)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(f) = self.min_healthy_fraction {
+            if !(f > 0.0 && f <= 1.0) {
+                return Err(format!("readiness_threshold.min_healthy_fraction 必须位于 (0, 1] 区间: {}", f));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 上游 400 的重试分类
+/// 错误信息命中 Schema 错误特征时，以严格模式重新清洗工具 Schema 并重试一次；其余 400 直接失败
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SchemaRetryConfig {
+    /// 是否启用 Schema 错误重试
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 视为 Schema 错误的错误信息特征 (子串匹配，不区分大小写)
+    #[serde(default = "default_schema_error_signatures")]
+    pub error_signatures: Vec<String>,
+}
+
+fn default_schema_error_signatures() -> Vec<String> {
+    [
+        "Unknown name",
+        "function_declarations",
+        "functionDeclarations",
+        "parameters.properties",
+        "schema",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+impl Default for SchemaRetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            error_signatures: default_schema_error_signatures(),
+        }
+    }
+}
+
+/// 上游以安全审核 / 背诵检测 (SAFETY / RECITATION 等) 拦截响应时，是否视为回退触发条件
+/// 开启后拦截的响应不返回给客户端，而是切换到下一个候选模型；最后一个候选仍返回拦截结果
+/// 流式响应仅在首个内容事件之前被拦截时可以回退
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SafetyFallbackConfig {
+    /// 默认关闭，保留上游的拦截结果
+    #[serde(default)]
+    pub enabled: bool,
+    /// 触发回退的 finishReason / blockReason
+    #[serde(default = "default_safety_block_reasons")]
+    pub block_reasons: Vec<String>,
+}
+
+fn default_safety_block_reasons() -> Vec<String> {
+    vec!["SAFETY".to_string(), "RECITATION".to_string()]
+}
+
+impl Default for SafetyFallbackConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            block_reasons: default_safety_block_reasons(),
+        }
+    }
+}
+
+/// 对话历史超出目标模型上下文窗口时的自动裁剪
+/// 按估算的 token 数从最早的非系统消息开始丢弃，始终保留系统提示与最近的若干条消息
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AutoTrimHistoryConfig {
+    /// 默认关闭，超长请求原样发送 (由上游拒绝)
+    #[serde(default)]
+    pub enabled: bool,
+    /// 上游模型 (支持 `*` 通配) -> 上下文窗口 (token)；未匹配的模型不裁剪
+    #[serde(default = "default_context_windows")]
+    pub context_windows: std::collections::HashMap<String, u64>,
+    /// 始终保留的最近消息条数
+    #[serde(default = "default_trim_keep_recent_messages")]
+    pub keep_recent_messages: usize,
+}
+
+fn default_context_windows() -> std::collections::HashMap<String, u64> {
+    [("gemini-*", 1_048_576), ("claude-*", 200_000)]
+        .into_iter()
+        .map(|(model, window)| (model.to_string(), window))
+        .collect()
+}
+
+fn default_trim_keep_recent_messages() -> usize {
+    4
+}
+
+impl Default for AutoTrimHistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            context_windows: default_context_windows(),
+            keep_recent_messages: default_trim_keep_recent_messages(),
+        }
+    }
+}
+
+/// 账号无权访问所请求模型 (上游 404) 时的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelUnavailableAction {
+    /// 直接返回 404 (默认)
+    #[default]
+    Fail,
+    /// 换号重试同一模型，账号耗尽后再切换下一个候选
+    RotateAccount,
+    /// 直接切换到下一个候选模型
+    NextCandidate,
+}
+
+/// 模型单价 (每 1K token)，未配置的模型按 0 计
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ModelPrice {
+    #[serde(default)]
+    pub input_per_1k: f64,
+    #[serde(default)]
+    pub output_per_1k: f64,
+}
+
+impl ModelPrice {
+    /// 按 token 用量估算费用
+    pub fn cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        input_tokens as f64 / 1000.0 * self.input_per_1k + output_tokens as f64 / 1000.0 * self.output_per_1k
+    }
+}
+
+/// 模型输出 token 限制
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ModelOutputLimit {
+    /// 客户端未指定 max_tokens 时注入的默认值
+    #[serde(default)]
+    pub default_max_tokens: Option<u32>,
+    /// 输出 token 上限，超出时截断
+    #[serde(default)]
+    pub max_tokens_cap: Option<u32>,
+}
+
+/// 推理强度 (`reasoning_effort`) 到 Gemini thinkingBudget 的换算表
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReasoningEffortBudgets {
+    /// 强度名 (如 `low`/`medium`/`high`) -> thinkingBudget
+    #[serde(default)]
+    pub efforts: std::collections::HashMap<String, u32>,
+    /// 表中没有的强度使用的预算
+    pub default_budget: u32,
+}
+
+fn default_reasoning_effort_budgets() -> std::collections::HashMap<String, ReasoningEffortBudgets> {
+    let efforts = [("minimal", 512), ("low", 1024), ("medium", 8192), ("high", 24576)]
+        .iter()
+        .map(|(k, v)| (k.to_string(), *v))
+        .collect();
+    std::collections::HashMap::from([(
+        "*".to_string(),
+        ReasoningEffortBudgets { efforts, default_budget: 8192 },
+    )])
+}
+
+/// 声明式 JSON 变换操作
+/// `path` 为点分路径，数字段在数组上表示下标 (如 `systemInstruction.parts.0.text`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JsonTransformOp {
+    /// 设置字段 (覆盖已有值，按需创建中间对象)
+    Set { path: String, value: serde_json::Value },
+    /// 删除字段
+    Remove { path: String },
+    /// 仅在字段缺失 (或为 null) 时设置
+    Default { path: String, value: serde_json::Value },
+}
+
+impl JsonTransformOp {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Set { path, .. } | Self::Remove { path } | Self::Default { path, .. } => path,
+        }
+    }
+}
+
+/// 模型级请求/响应变换
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ModelTransform {
+    /// 转发前作用于 Gemini 请求 (v1internal 包装内的 `request` 对象)
+    #[serde(default)]
+    pub request: Vec<JsonTransformOp>,
+    /// 返回前作用于上游 Gemini 响应 (流式时作用于每个分片)
+    #[serde(default)]
+    pub response: Vec<JsonTransformOp>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZaiConfig {
     #[serde(default)]
@@ -169,6 +1019,24 @@ impl Default for ZaiConfig {
     }
 }
 
+impl ZaiConfig {
+    /// 热切换前的校验：启用且分发模式非 Off 时必须有可用的上游地址与 API Key
+    /// 不在 ProxyConfig::validate 中调用，避免已保存的不完整配置导致启动失败
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled || self.dispatch_mode == ZaiDispatchMode::Off {
+            return Ok(());
+        }
+        let base_url = self.base_url.trim();
+        if !(base_url.starts_with("http://") || base_url.starts_with("https://")) {
+            return Err(format!("z.ai base_url 必须以 http:// 或 https:// 开头: {}", self.base_url));
+        }
+        if self.api_key.trim().is_empty() {
+            return Err("z.ai 已启用但未配置 api_key".to_string());
+        }
+        Ok(())
+    }
+}
+
 /// 实验性功能配置 (Feature Flags)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExperimentalConfig {
@@ -197,6 +1065,10 @@ impl Default for ExperimentalConfig {
 
 fn default_true() -> bool { true }
 
+fn default_schema_inline_max_bytes() -> usize {
+    crate::proxy::common::json_schema::DEFAULT_INLINE_SIZE_LIMIT
+}
+
 /// 反代服务配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyConfig {
@@ -216,6 +1088,11 @@ pub struct ProxyConfig {
     /// - auto: recommended defaults (currently: allow_lan_access => all_except_health, else off)
     #[serde(default)]
     pub auth_mode: ProxyAuthMode,
+
+    /// `/version` 是否与 `/healthz` 一样免鉴权 (默认关闭，按 auth_mode 要求鉴权；
+    /// 响应包含鉴权模式、局域网访问等运行时开关，公开前需确认可接受)
+    #[serde(default)]
+    pub public_version_endpoint: bool,
     
     /// 监听端口
     pub port: u16,
@@ -235,22 +1112,131 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub openai_mapping: std::collections::HashMap<String, String>,
 
+    /// OpenAI 系家族分组规则 (决定模型名归入 openai_mapping 的哪个分组)
+    #[serde(default = "default_openai_family_rules")]
+    pub openai_family_rules: Vec<OpenAIFamilyRule>,
+
     /// 自定义精确模型映射表 (key: 原始模型名, value: 目标模型名)
     #[serde(default)]
     pub custom_mapping: std::collections::HashMap<String, String>,
 
-    /// API 请求超时时间(秒)
+    /// API 请求超时时间(秒)，作为上游请求的总超时 (含响应体流式读取)
     #[serde(default = "default_request_timeout")]
     pub request_timeout: u64,
 
+    /// 上游建立连接的超时 (秒)，连接无法建立时尽快失败 (需重启服务生效)
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+
+    /// 上游响应两次读取之间的最长空闲时间 (秒)，0 表示不限制 (需重启服务生效)
+    #[serde(default)]
+    pub idle_read_timeout: u64,
+
+    /// 是否允许客户端通过请求头 (如 `X-Request-Timeout`) 覆盖单次请求的参数
+    #[serde(default)]
+    pub allow_header_overrides: bool,
+
+    /// `X-Request-Timeout` 可申请的最大超时 (秒)
+    #[serde(default = "default_max_request_timeout_secs")]
+    pub max_request_timeout_secs: u64,
+
     /// 可复用的模型策略池 (strategy_id -> strategy)
     #[serde(default)]
     pub model_strategies: std::collections::HashMap<String, ModelStrategy>,
 
+    /// 非策略路由目标 (直接映射到具体模型) 使用的全局默认回退策略
+    #[serde(default)]
+    pub default_fallback_policy: ModelFallbackPolicy,
+
+    /// 策略文件目录：每个 `*.json` / `*.toml` 文件为一个策略 (文件名即 strategy_id)，与 `model_strategies` 合并
+    /// 同名时以 `model_strategies` 中的内联策略为准；保存配置时重新读取
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategies_dir: Option<std::path::PathBuf>,
+
+    /// 策略 A/B 实验 (模型模式 -> 实验配置，支持 `*` 通配)，按规范化后的请求模型名匹配，优先于常规路由
+    #[serde(default)]
+    pub strategy_experiments: std::collections::HashMap<String, StrategyExperiment>,
+
+    /// 模型输出 token 限制 (key: 路由后的模型名，支持 * 通配符)
+    #[serde(default)]
+    pub model_output_limits: std::collections::HashMap<String, ModelOutputLimit>,
+
+    /// 推理强度到 thinkingBudget 的换算 (key: 路由后的模型名，支持 * 通配符)，仅作用于支持思维链的模型
+    #[serde(default = "default_reasoning_effort_budgets")]
+    pub reasoning_effort_budgets: std::collections::HashMap<String, ReasoningEffortBudgets>,
+
+    /// 模型级请求/响应 JSON 变换 (key: 路由后的模型名，支持 * 通配符)
+    #[serde(default)]
+    pub model_transforms: std::collections::HashMap<String, ModelTransform>,
+
+    /// 模型级系统提示词 (key: 路由后的模型名，支持 * 通配符)，转发前置于客户端系统提示之前
+    #[serde(default)]
+    pub model_system_prompts: std::collections::HashMap<String, String>,
+
+    /// 模型单价表 (key: 路由后的模型名，支持 * 通配符)，用于估算每个请求的费用
+    #[serde(default)]
+    pub model_prices: std::collections::HashMap<String, ModelPrice>,
+
+    /// 工具 schema 展开 $ref 后的体积上限 (字节，0 表示不限制)，超出时对重复引用的定义去重展开
+    #[serde(default = "default_schema_inline_max_bytes")]
+    pub schema_inline_max_bytes: usize,
+
+    /// 全局禁用 Claude 家族映射 (忽略客户端检测，Claude 模型名直接穿透)
+    #[serde(default)]
+    pub disable_family_mapping: bool,
+
+    /// 全局强制启用 Claude 家族映射 (忽略客户端检测)
+    /// 与 `disable_family_mapping` 互斥
+    #[serde(default)]
+    pub force_family_mapping: bool,
+
+    /// 模型列表 (/v1/models 等) 是否同时列出内置别名 (gpt-*/claude-* 等)
+    /// 关闭时仅列出实际路由到的上游模型 ID
+    #[serde(default = "default_true")]
+    pub advertise_all_aliases: bool,
+
+    /// 模型列表是否只列出至少一个健康账号能服务的模型
+    /// (按 `model_min_tiers` 的订阅等级要求与账号支持的协议过滤)
+    #[serde(default)]
+    pub advertise_servable_only: bool,
+
+    /// 模型所需的最低订阅等级 (模型名，支持 `*` 通配 -> FREE/PRO/ULTRA)
+    /// 仅用于 `advertise_servable_only` 的列表过滤，不影响请求路由
+    #[serde(default = "default_model_min_tiers")]
+    pub model_min_tiers: HashMap<String, String>,
+
+    /// 路由查找前的模型名规范化规则
+    #[serde(default)]
+    pub model_canonicalization: ModelCanonicalizationConfig,
+
+    /// 哨兵模型名 (`default` / `auto`) 的自动选型目标
+    #[serde(default)]
+    pub auto_model: AutoModelConfig,
+
+    /// 客户端省略模型名时按协议 (openai / anthropic / gemini) 使用的默认模型
+    #[serde(default)]
+    pub default_model_per_protocol: DefaultModelPerProtocol,
+
+    /// 客户端允许请求的模型 (支持 `*` 通配符)，按原始请求模型名在路由前检查；为空表示不限制
+    #[serde(default)]
+    pub allowed_client_models: Vec<String>,
+
+    /// 已弃用的上游模型列表；路由命中时告警并计数
+    #[serde(default = "default_deprecated_models")]
+    pub deprecated_models: Vec<DeprecatedModel>,
+
+    /// 路由命中弃用模型时自动替换为建议的替代模型
+    #[serde(default)]
+    pub auto_replace_deprecated: bool,
+
     /// 是否开启请求日志记录 (监控)
     #[serde(default)]
     pub enable_logging: bool,
 
+    /// 监控模式 (full / counters_only)
+    #[serde(default)]
+    pub monitor_mode: MonitorMode,
+
     /// 上游代理配置
     #[serde(default)]
     pub upstream_proxy: UpstreamProxyConfig,
@@ -266,6 +1252,127 @@ pub struct ProxyConfig {
     /// 实验性功能配置
     #[serde(default)]
     pub experimental: ExperimentalConfig,
+
+    /// 流式响应配置 (保活等)
+    #[serde(default)]
+    pub streaming: StreamingConfig,
+
+    /// Gemini 上下文缓存配置
+    #[serde(default)]
+    pub context_cache: ContextCacheConfig,
+
+    /// 合并同时进行中的完全相同的非流式请求 (共享首个请求的响应，避免重复消耗配额)
+    #[serde(default)]
+    pub dedupe_in_flight: bool,
+
+    /// 响应中推理 (thinking) 内容的输出方式
+    #[serde(default)]
+    pub reasoning_output: ReasoningOutputMode,
+
+    /// `/readyz` 深度就绪检查：首次上游调用成功前保持 503 (默认关闭，避免拖慢启动)
+    #[serde(default)]
+    pub readiness_requires_upstream: bool,
+
+    /// `/readyz` 要求的最少健康账号数/比例
+    #[serde(default)]
+    pub readiness_threshold: ReadinessThresholdConfig,
+
+    /// 账号健康 Webhook：账号冷却/禁用及账号池健康状态变化时 POST JSON 事件 (未设置时关闭)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+
+    /// 调度随机种子 (测试/排查用)：设置后账号轮询起点等随机选择可复现，未设置时使用系统随机源
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduling_seed: Option<u64>,
+
+    /// OTLP/HTTP 采集端地址 (如 `http://localhost:4318`)，设置后每个请求导出一个 span (需启用 `otel` feature)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub otlp_endpoint: Option<String>,
+
+    /// 透传上游时的请求头白名单/黑名单
+    #[serde(default)]
+    pub header_forwarding: HeaderForwardingConfig,
+
+    /// 对冲请求延迟 (毫秒)：主请求超过该时间仍未返回响应头时，换号再发起一次请求并取先返回者
+    /// 未设置或为 0 时关闭。仅作用于 Gemini 原生接口：Claude/OpenAI 处理器在重试循环内按账号转换请求体
+    /// (project、思维链签名与会话绑定随账号变化)，对冲需要为另一账号重新转换，暂不支持
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hedge_delay_ms: Option<u64>,
+
+    /// 账号无权访问所请求模型 (上游 404) 时的处理方式
+    #[serde(default)]
+    pub model_unavailable_action: ModelUnavailableAction,
+
+    /// 带思维链/预算后缀的模型 (如 `-thinking`、`-thinking-high`) 在账号上不可用 (404) 时，
+    /// 先用去掉后缀的基础模型在同一账号重试一次，再按 `model_unavailable_action` 处理
+    #[serde(default)]
+    pub strip_variant_suffix_on_unavailable: bool,
+
+    /// 响应因输出 token 上限被截断时附加 `X-Response-Truncated` 响应头 (仅非流式响应)
+    #[serde(default)]
+    pub truncation_warning_header: bool,
+
+    /// 各协议端点的启用开关
+    #[serde(default)]
+    pub enabled_endpoints: EnabledEndpointsConfig,
+
+    /// 按客户端 Accept-Encoding 压缩响应体 (gzip/deflate，SSE 流除外)
+    #[serde(default = "default_true")]
+    pub response_compression: bool,
+
+    /// 单请求工具数量上限及超限处理策略 (默认不限制)
+    #[serde(default)]
+    pub tool_limit: ToolLimitConfig,
+
+    /// 远程媒体 URL 的下载策略 (默认不下载，原样交给上游)
+    #[serde(default)]
+    pub remote_media: RemoteMediaConfig,
+
+    /// 上游 Schema 类 400 的严格清洗重试 (其余 400 直接失败)
+    #[serde(default)]
+    pub schema_retry: SchemaRetryConfig,
+
+    /// 安全拦截 (SAFETY / RECITATION) 时回退到下一个候选 (默认关闭)
+    #[serde(default)]
+    pub safety_fallback: SafetyFallbackConfig,
+
+    /// 对话历史超出模型上下文窗口时自动丢弃最早的消息 (默认关闭)
+    #[serde(default)]
+    pub auto_trim_history: AutoTrimHistoryConfig,
+
+    /// OpenAI logprobs 参数的转换与不支持时的处理
+    #[serde(default)]
+    pub logprobs: LogprobsConfig,
+
+    /// 在成功响应上附加 X-Antigravity-* 调试头 (实际模型、账号、候选回退)，便于客户端排查；
+    /// 流式响应末尾另追加一个元数据事件 (实际模型、账号、token 用量、上下文缓存命中情况)
+    #[serde(default)]
+    pub expose_debug_headers: bool,
+
+    /// 维护模式 (可通过重载配置热切换)
+    #[serde(default)]
+    pub maintenance_mode: MaintenanceModeConfig,
+
+    /// 含 `thinking` 的模型名只有以这些前缀开头时才原样直通，其余未映射名称走兜底默认模型
+    #[serde(default = "default_thinking_passthrough_prefixes")]
+    pub thinking_passthrough_prefixes: Vec<String>,
+
+    /// 用户配置的 Anthropic 家族映射是否优先于内置直通模型 (如 `claude-sonnet-4-5`)
+    /// 默认关闭：内置直通模型不受家族映射影响
+    #[serde(default)]
+    pub family_overrides_builtin: bool,
+
+    /// 单个客户端请求的上游调用总数上限 (账号重试与候选模型回退合计，0 表示不限制)
+    #[serde(default = "default_max_total_attempts")]
+    pub max_total_attempts: usize,
+}
+
+fn default_thinking_passthrough_prefixes() -> Vec<String> {
+    crate::proxy::common::model_mapping::default_thinking_passthrough_prefixes()
+}
+
+fn default_max_total_attempts() -> usize {
+    crate::proxy::common::attempt_budget::DEFAULT_MAX_TOTAL_ATTEMPTS
 }
 
 /// 上游代理配置
@@ -283,27 +1390,129 @@ impl Default for ProxyConfig {
             enabled: false,
             allow_lan_access: false, // 默认仅本机访问，隐私优先
             auth_mode: ProxyAuthMode::default(),
+            public_version_endpoint: false,
             port: 8045,
             api_key: format!("sk-{}", uuid::Uuid::new_v4().simple()),
             auto_start: false,
             anthropic_mapping: std::collections::HashMap::new(),
             openai_mapping: std::collections::HashMap::new(),
+            openai_family_rules: default_openai_family_rules(),
             custom_mapping: std::collections::HashMap::new(),
             request_timeout: default_request_timeout(),
+            connect_timeout: default_connect_timeout(),
+            idle_read_timeout: 0,
+            allow_header_overrides: false,
+            max_request_timeout_secs: default_max_request_timeout_secs(),
             model_strategies: std::collections::HashMap::new(),
+            strategies_dir: None,
+            strategy_experiments: std::collections::HashMap::new(),
+            model_output_limits: std::collections::HashMap::new(),
+            reasoning_effort_budgets: default_reasoning_effort_budgets(),
+            model_transforms: std::collections::HashMap::new(),
+            model_system_prompts: std::collections::HashMap::new(),
+            model_prices: std::collections::HashMap::new(),
+            schema_inline_max_bytes: default_schema_inline_max_bytes(),
+            disable_family_mapping: false,
+            force_family_mapping: false,
+            advertise_all_aliases: true,
+            advertise_servable_only: false,
+            model_min_tiers: default_model_min_tiers(),
+            model_canonicalization: ModelCanonicalizationConfig::default(),
+            auto_model: AutoModelConfig::default(),
+            default_model_per_protocol: DefaultModelPerProtocol::default(),
+            allowed_client_models: Vec::new(),
+            deprecated_models: default_deprecated_models(),
+            auto_replace_deprecated: false,
             enable_logging: false, // 默认关闭，节省性能
+            monitor_mode: MonitorMode::default(),
             upstream_proxy: UpstreamProxyConfig::default(),
             zai: ZaiConfig::default(),
             scheduling: crate::proxy::sticky_config::StickySessionConfig::default(),
             experimental: ExperimentalConfig::default(),
+            streaming: StreamingConfig::default(),
+            context_cache: ContextCacheConfig::default(),
+            dedupe_in_flight: false,
+            reasoning_output: ReasoningOutputMode::default(),
+            readiness_requires_upstream: false,
+            readiness_threshold: ReadinessThresholdConfig::default(),
+            webhook_url: None,
+            scheduling_seed: None,
+            otlp_endpoint: None,
+            header_forwarding: HeaderForwardingConfig::default(),
+            hedge_delay_ms: None,
+            model_unavailable_action: ModelUnavailableAction::default(),
+            strip_variant_suffix_on_unavailable: false,
+            truncation_warning_header: false,
+            enabled_endpoints: EnabledEndpointsConfig::default(),
+            response_compression: true,
+            tool_limit: ToolLimitConfig::default(),
+            remote_media: RemoteMediaConfig::default(),
+            schema_retry: SchemaRetryConfig::default(),
+            safety_fallback: SafetyFallbackConfig::default(),
+            auto_trim_history: AutoTrimHistoryConfig::default(),
+            logprobs: LogprobsConfig::default(),
+            thinking_passthrough_prefixes: default_thinking_passthrough_prefixes(),
+            family_overrides_builtin: false,
+            default_fallback_policy: ModelFallbackPolicy::default(),
+            max_total_attempts: default_max_total_attempts(),
+            expose_debug_headers: false,
+            maintenance_mode: MaintenanceModeConfig::default(),
+        }
+    }
+}
+
+/// 读取策略目录中的 `*.json` / `*.toml` 文件 (文件名即 strategy_id)
+/// 单个文件解析失败时记录告警并跳过，不影响其他策略
+pub fn load_strategies_dir(dir: &std::path::Path) -> Result<HashMap<String, ModelStrategy>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| e.to_string())?;
+    let mut paths: Vec<std::path::PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+
+    let mut strategies = HashMap::new();
+    for path in paths {
+        let ext = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+        let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let content = match ext.as_deref() {
+            Some("json") | Some("toml") => match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) => {
+                    tracing::warn!("读取策略文件 {} 失败: {}", path.display(), e);
+                    continue;
+                }
+            },
+            _ => continue,
+        };
+        let parsed = if ext.as_deref() == Some("json") {
+            serde_json::from_str::<ModelStrategy>(&content).map_err(|e| e.to_string())
+        } else {
+            toml::from_str::<ModelStrategy>(&content).map_err(|e| e.to_string())
+        };
+        match parsed {
+            Ok(strategy) => {
+                if strategies.insert(id.to_string(), strategy).is_some() {
+                    tracing::warn!("策略目录中存在重名策略 {}，使用 {}", id, path.display());
+                }
+            }
+            Err(e) => tracing::warn!("解析策略文件 {} 失败，已跳过: {}", path.display(), e),
         }
     }
+    Ok(strategies)
 }
 
 fn default_request_timeout() -> u64 {
     120  // 默认 120 秒,原来 60 秒太短
 }
 
+fn default_connect_timeout() -> u64 {
+    20
+}
+
+fn default_max_request_timeout_secs() -> u64 {
+    1800 // 30 分钟
+}
+
 fn default_zai_base_url() -> String {
     "https://api.z.ai/api/anthropic".to_string()
 }
@@ -331,4 +1540,116 @@ impl ProxyConfig {
             "127.0.0.1"
         }
     }
+
+    /// 家族映射的全局覆盖
+    /// - `Some(false)`: disable_family_mapping
+    /// - `Some(true)`: force_family_mapping
+    /// - `None`: 按客户端检测决定
+    pub fn family_mapping_override(&self) -> Option<bool> {
+        if self.disable_family_mapping {
+            Some(false)
+        } else if self.force_family_mapping {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    /// 合并策略目录与内联配置后的策略池 (内联策略优先)
+    pub fn effective_model_strategies(&self) -> HashMap<String, ModelStrategy> {
+        let Some(dir) = self.strategies_dir.as_deref() else {
+            return self.model_strategies.clone();
+        };
+        let mut strategies = match load_strategies_dir(dir) {
+            Ok(strategies) => strategies,
+            Err(e) => {
+                tracing::warn!("读取策略目录 {} 失败，仅使用内联策略: {}", dir.display(), e);
+                return self.model_strategies.clone();
+            }
+        };
+        for (id, strategy) in &self.model_strategies {
+            if strategies.insert(id.clone(), strategy.clone()).is_some() {
+                tracing::warn!("策略 {} 同时存在于策略目录与内联配置中，使用内联配置", id);
+            }
+        }
+        strategies
+    }
+
+    /// 弃用模型处理策略
+    pub fn model_deprecation_policy(&self) -> ModelDeprecationPolicy {
+        ModelDeprecationPolicy {
+            models: self.deprecated_models.clone(),
+            auto_replace: self.auto_replace_deprecated,
+        }
+    }
+
+    /// 模型列表的可服务性过滤
+    pub fn servable_models_filter(&self) -> ServableModelsFilter {
+        ServableModelsFilter {
+            enabled: self.advertise_servable_only,
+            min_tiers: self.model_min_tiers.clone(),
+        }
+    }
+
+    /// 校验配置中互斥/非法的组合
+    pub fn validate(&self) -> Result<(), String> {
+        if self.disable_family_mapping && self.force_family_mapping {
+            return Err(
+                "disable_family_mapping 与 force_family_mapping 不能同时启用".to_string(),
+            );
+        }
+        crate::proxy::common::json_transform::validate_transforms(&self.model_transforms)?;
+        crate::proxy::sticky_config::parse_daily_reset_time(&self.scheduling.daily_reset_time)?;
+        self.readiness_threshold.validate()?;
+        if let Some(endpoint) = self.otlp_endpoint.as_deref().filter(|e| !e.trim().is_empty()) {
+            if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+                return Err(format!("otlp_endpoint 必须以 http:// 或 https:// 开头: {}", endpoint));
+            }
+        }
+        if let Some(url) = self.webhook_url.as_deref().filter(|u| !u.trim().is_empty()) {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err("webhook_url 必须以 http:// 或 https:// 开头".to_string());
+            }
+        }
+        for (pattern, experiment) in &self.strategy_experiments {
+            if experiment.a_percent > 100 {
+                return Err(format!(
+                    "strategy_experiments.{}.a_percent 必须位于 0-100 之间: {}",
+                    pattern, experiment.a_percent
+                ));
+            }
+        }
+        for (model, tier) in &self.model_min_tiers {
+            if subscription_tier_rank(tier).is_none() {
+                return Err(format!(
+                    "model_min_tiers.{} 的订阅等级无效 (应为 FREE/PRO/ULTRA): {}",
+                    model, tier
+                ));
+            }
+        }
+        if self.context_cache.warm.enabled && self.context_cache.warm.interval_secs == 0 {
+            return Err("context_cache.warm.interval_secs 必须大于 0".to_string());
+        }
+        Ok(())
+    }
+
+    /// 非致命的配置提示 (不影响加载)
+    pub fn advisory_warnings(&self) -> Vec<String> {
+        let strategies = self.effective_model_strategies();
+        let mut warnings = crate::proxy::common::model_mapping::validate_strategy_capabilities(&strategies);
+        for (pattern, experiment) in &self.strategy_experiments {
+            for arm in [&experiment.arm_a, &experiment.arm_b] {
+                if !strategies.contains_key(arm) {
+                    warnings.push(format!("实验 {} 引用的策略 {} 不存在，命中时按常规路由处理", pattern, arm));
+                }
+            }
+        }
+        if self.context_cache.warm.enabled && !self.context_cache.enabled {
+            warnings.push("已启用 context_cache.warm，但上下文缓存未启用，预热不会执行".to_string());
+        }
+        if cfg!(not(feature = "otel")) && self.otlp_endpoint.is_some() {
+            warnings.push("已设置 otlp_endpoint，但当前构建未启用 otel feature，span 不会导出".to_string());
+        }
+        warnings
+    }
 }
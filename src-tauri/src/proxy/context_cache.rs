@@ -0,0 +1,671 @@
+// Gemini 上下文缓存 (Context Caching)
+// Agent 场景下 systemInstruction + tools 往往是稳定且很大的前缀，
+// 为其创建 cachedContents 句柄后，后续相同前缀的请求只需引用句柄即可降低成本。
+// 可选的定时预热任务在句柄到期前重新创建，使请求始终命中热缓存。
+
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::proxy::config::ContextCacheConfig;
+
+/// 可被缓存的前缀字段 (位于 v1internal 请求体的 `request` 内)
+const PREFIX_FIELDS: [&str; 3] = ["systemInstruction", "tools", "toolConfig"];
+
+/// 句柄到期前提前失效的余量上限，避免引用一个即将过期的句柄
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+struct CacheHandle {
+    name: String,
+    expires_at: Instant,
+    last_used: Instant,
+    /// 预热时重新创建句柄所需的信息
+    source: Option<CacheSource>,
+}
+
+/// 句柄的创建来源
+#[derive(Clone, Debug)]
+pub struct CacheSource {
+    /// 创建句柄的账号 (句柄与该账号的 project 绑定)
+    pub account: String,
+    pub model: String,
+    /// createCachedContent 提交的内容
+    pub payload: Value,
+}
+
+/// 从请求体中提取出的可缓存前缀
+#[derive(Debug, Clone)]
+pub struct CachePrefix {
+    /// project + model + 前缀内容的 SHA256
+    pub key: String,
+    pub model: String,
+    /// 仅包含 PREFIX_FIELDS 的对象
+    pub content: Map<String, Value>,
+    pub size: usize,
+}
+
+impl CachePrefix {
+    /// 从 v1internal 包装后的请求体 (`{project, model, request}`) 中提取前缀
+    pub fn extract(wrapped_body: &Value) -> Option<Self> {
+        let project = wrapped_body.get("project").and_then(Value::as_str).unwrap_or("");
+        let model = wrapped_body.get("model").and_then(Value::as_str)?;
+        let inner = wrapped_body.get("request")?.as_object()?;
+
+        let mut content = Map::new();
+        for field in PREFIX_FIELDS {
+            if let Some(v) = inner.get(field) {
+                content.insert(field.to_string(), v.clone());
+            }
+        }
+        if content.is_empty() {
+            return None;
+        }
+
+        let serialized = serde_json::to_string(&content).ok()?;
+        let mut hasher = Sha256::new();
+        hasher.update(project.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(model.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(serialized.as_bytes());
+        let key = format!("{:x}", hasher.finalize());
+
+        Some(Self {
+            key,
+            model: model.to_string(),
+            size: serialized.len(),
+            content,
+        })
+    }
+
+    /// 创建 cachedContents 时提交的内容
+    pub fn create_payload(&self, ttl: Duration) -> Value {
+        let mut payload = self.content.clone();
+        payload.insert("model".to_string(), json!(format!("models/{}", self.model)));
+        payload.insert("ttl".to_string(), json!(format!("{}s", ttl.as_secs())));
+        Value::Object(payload)
+    }
+}
+
+/// 用缓存句柄替换请求体中的前缀字段
+pub fn apply_cache_reference(wrapped_body: &mut Value, name: &str) {
+    if let Some(inner) = wrapped_body.get_mut("request").and_then(Value::as_object_mut) {
+        for field in PREFIX_FIELDS {
+            inner.remove(field);
+        }
+        inner.insert("cachedContent".to_string(), json!(name));
+    }
+}
+
+/// 上游错误是否表示缓存句柄失效 (400/404 且报文指向 cachedContent)
+///
+/// 限流、鉴权、Schema 等与缓存无关的错误不应丢弃仍然有效的句柄。
+pub fn is_cache_rejection(status: u16, error_text: &str) -> bool {
+    if status != 400 && status != 404 {
+        return false;
+    }
+    let lower = error_text.to_ascii_lowercase();
+    lower.contains("cachedcontent") || lower.contains("cached content")
+}
+
+/// 单个请求的缓存句柄重试状态
+/// 上游拒绝句柄 (已过期/被删除) 时丢弃句柄，并额外保留一次不带句柄的重试机会；
+/// 此后本请求不再挂载缓存，避免新建的句柄再次被拒
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheRetry {
+    bypass: bool,
+    pending: bool,
+}
+
+impl CacheRetry {
+    /// 为不带句柄的重试额外保留的尝试次数
+    pub fn extra_attempts(&self) -> usize {
+        usize::from(!self.bypass || self.pending)
+    }
+
+    /// 是否有待执行的重试
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// 每次尝试开始时调用，待执行的重试随本次尝试发出
+    pub fn begin_attempt(&mut self) {
+        self.pending = false;
+    }
+
+    /// 本请求是否已停止使用缓存
+    pub fn bypasses_cache(&self) -> bool {
+        self.bypass
+    }
+
+    /// 上游返回错误时调用：句柄被拒时从缓存中移除，返回 true 表示应不带句柄重试
+    pub fn should_retry(
+        &mut self,
+        cache: &ContextCache,
+        cached_content: Option<&str>,
+        status_code: u16,
+        error_text: &str,
+    ) -> bool {
+        let Some(name) = cached_content else {
+            return false;
+        };
+        if !is_cache_rejection(status_code, error_text) {
+            return false;
+        }
+        cache.invalidate(name);
+        if self.bypass {
+            return false;
+        }
+        self.bypass = true;
+        self.pending = true;
+        tracing::warn!("[ContextCache] Upstream rejected {} ({}), retrying once without it", name, status_code);
+        true
+    }
+}
+
+/// 缓存句柄表 (由 AppState 持有，随反代服务创建与销毁)
+#[derive(Default)]
+pub struct ContextCache {
+    /// 前缀 key -> 缓存句柄
+    handles: Mutex<HashMap<String, CacheHandle>>,
+}
+
+impl ContextCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 查找未过期的句柄
+    pub fn lookup(&self, key: &str) -> Option<String> {
+        let mut handles = self.handles.lock().ok()?;
+        let now = Instant::now();
+        match handles.get_mut(key) {
+            Some(handle) if handle.expires_at > now => {
+                handle.last_used = now;
+                Some(handle.name.clone())
+            }
+            Some(_) => {
+                handles.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// 记录新建的句柄，超出容量时淘汰最久未使用的条目
+    pub fn insert(&self, key: String, name: String, ttl: Duration, max_entries: usize) {
+        self.insert_handle(key, name, ttl, max_entries, None);
+    }
+
+    fn insert_handle(
+        &self,
+        key: String,
+        name: String,
+        ttl: Duration,
+        max_entries: usize,
+        source: Option<CacheSource>,
+    ) {
+        let Ok(mut handles) = self.handles.lock() else {
+            return;
+        };
+        let now = Instant::now();
+        handles.retain(|_, h| h.expires_at > now);
+
+        while max_entries > 0 && handles.len() >= max_entries {
+            let oldest = handles
+                .iter()
+                .min_by_key(|(_, h)| h.last_used)
+                .map(|(k, _)| k.clone());
+            match oldest {
+                Some(k) => {
+                    handles.remove(&k);
+                }
+                None => break,
+            }
+        }
+
+        handles.insert(
+            key,
+            CacheHandle {
+                name,
+                expires_at: now + local_lifetime(ttl),
+                last_used: now,
+                source,
+            },
+        );
+    }
+
+    /// 上游拒绝句柄 (已过期/被删除) 时移除
+    pub fn invalidate(&self, name: &str) {
+        if let Ok(mut handles) = self.handles.lock() {
+            handles.retain(|_, h| h.name != name);
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut handles) = self.handles.lock() {
+            handles.clear();
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.handles.lock().map(|h| h.len()).unwrap_or(0)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 为请求挂载缓存句柄 (命中则复用，未命中则调用 `create` 创建)
+    ///
+    /// 仅处理 Gemini 模型；创建失败时保持请求体不变。返回实际使用的句柄名。
+    /// `account` 为当前请求使用的账号，预热时用它重新创建句柄。
+    pub async fn attach<F, Fut>(
+        &self,
+        config: &ContextCacheConfig,
+        account: &str,
+        wrapped_body: &mut Value,
+        create: F,
+    ) -> Option<String>
+    where
+        F: FnOnce(Value) -> Fut,
+        Fut: Future<Output = Result<String, String>>,
+    {
+        if !config.enabled {
+            return None;
+        }
+        let prefix = CachePrefix::extract(wrapped_body)?;
+        if !prefix.model.starts_with("gemini-") {
+            return None;
+        }
+        if prefix.size < config.min_prefix_bytes || prefix.size > config.max_prefix_bytes {
+            return None;
+        }
+
+        let name = match self.lookup(&prefix.key) {
+            Some(name) => {
+                tracing::debug!("[ContextCache] Hit: {} ({} bytes)", name, prefix.size);
+                crate::proxy::middleware::debug_headers::record_cache_status("hit");
+                name
+            }
+            None => {
+                let ttl = Duration::from_secs(config.ttl_secs);
+                let payload = prefix.create_payload(ttl);
+                match create(payload.clone()).await {
+                    Ok(name) => {
+                        tracing::info!(
+                            "[ContextCache] Created {} for {} ({} bytes, ttl {}s)",
+                            name,
+                            prefix.model,
+                            prefix.size,
+                            config.ttl_secs
+                        );
+                        let source = CacheSource {
+                            account: account.to_string(),
+                            model: prefix.model.clone(),
+                            payload,
+                        };
+                        self.insert_handle(prefix.key.clone(), name.clone(), ttl, config.max_entries, Some(source));
+                        crate::proxy::middleware::debug_headers::record_cache_status("miss");
+                        name
+                    }
+                    Err(e) => {
+                        tracing::warn!("[ContextCache] Failed to create cached content: {}", e);
+                        return None;
+                    }
+                }
+            }
+        };
+
+        apply_cache_reference(wrapped_body, &name);
+        Some(name)
+    }
+
+    /// 重新创建剩余有效期不足 `warm.refresh_before_secs` 的句柄，返回成功刷新的数量
+    ///
+    /// 只处理由请求创建 (带来源信息) 且模型匹配 `warm.models` 的句柄；刷新失败时保留原句柄。
+    /// 闲置超过 `warm.max_idle_secs` (未配置时为 `ttl_secs`) 的句柄任其自然过期，不再为其付费续期。
+    pub async fn refresh_expiring<F, Fut>(&self, config: &ContextCacheConfig, refresh: F) -> usize
+    where
+        F: Fn(CacheSource) -> Fut,
+        Fut: Future<Output = Result<String, String>>,
+    {
+        let lead = Duration::from_secs(config.warm.refresh_before_secs);
+        let max_idle = Duration::from_secs(match config.warm.max_idle_secs {
+            0 => config.ttl_secs,
+            secs => secs,
+        });
+        let due: Vec<(String, String, CacheSource)> = {
+            let Ok(handles) = self.handles.lock() else {
+                return 0;
+            };
+            let now = Instant::now();
+            handles
+                .iter()
+                .filter(|(_, h)| h.expires_at > now && h.expires_at <= now + lead)
+                .filter(|(_, h)| now.duration_since(h.last_used) <= max_idle)
+                .filter_map(|(key, h)| {
+                    let source = h.source.as_ref()?;
+                    warm_model_matches(&config.warm.models, &source.model)
+                        .then(|| (key.clone(), h.name.clone(), source.clone()))
+                })
+                .collect()
+        };
+
+        let ttl = Duration::from_secs(config.ttl_secs);
+        let mut refreshed = 0;
+        for (key, old_name, source) in due {
+            match refresh(source).await {
+                Ok(name) => {
+                    let Ok(mut handles) = self.handles.lock() else {
+                        break;
+                    };
+                    // 刷新期间句柄可能已被失效或替换
+                    if let Some(handle) = handles.get_mut(&key).filter(|h| h.name == old_name) {
+                        tracing::debug!("[ContextCache] Refreshed {} -> {}", old_name, name);
+                        handle.name = name;
+                        handle.expires_at = Instant::now() + local_lifetime(ttl);
+                        refreshed += 1;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("[ContextCache] Failed to refresh {}: {}", old_name, e);
+                }
+            }
+        }
+        refreshed
+    }
+}
+
+/// 本地记录的句柄有效期 (比上游 TTL 提前一定余量)
+fn local_lifetime(ttl: Duration) -> Duration {
+    ttl.saturating_sub(EXPIRY_MARGIN.min(ttl / 10))
+}
+
+fn warm_model_matches(models: &[String], model: &str) -> bool {
+    models.is_empty()
+        || models.iter().any(|pattern| {
+            pattern.eq_ignore_ascii_case(model) || crate::proxy::common::model_mapping::glob_match(pattern, model)
+        })
+}
+
+/// 定时预热循环：每隔 `warm.interval_secs` 刷新即将到期的句柄
+/// 每轮重新读取配置；上下文缓存或预热未启用时跳过本轮
+pub async fn run_warmer<F, Fut>(
+    cache: &ContextCache,
+    config: Arc<tokio::sync::RwLock<ContextCacheConfig>>,
+    refresh: F,
+) where
+    F: Fn(CacheSource) -> Fut,
+    Fut: Future<Output = Result<String, String>>,
+{
+    loop {
+        let interval = config.read().await.warm.interval_secs.max(1);
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        let snapshot = config.read().await.clone();
+        if !snapshot.enabled || !snapshot.warm.enabled {
+            continue;
+        }
+        let refreshed = cache.refresh_expiring(&snapshot, &refresh).await;
+        if refreshed > 0 {
+            tracing::info!("[ContextCache] Warmed {} cached content handle(s)", refreshed);
+        }
+    }
+}
+
+/// 启动上下文缓存的预热任务 (随反代服务停止而取消)
+pub fn spawn_warmer(
+    cache: Arc<ContextCache>,
+    config: Arc<tokio::sync::RwLock<ContextCacheConfig>>,
+    token_manager: Arc<crate::proxy::token_manager::TokenManager>,
+    upstream: Arc<crate::proxy::upstream::client::UpstreamClient>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        run_warmer(&cache, config, |source: CacheSource| {
+            let (token_manager, upstream) = (token_manager.clone(), upstream.clone());
+            async move {
+                let (access_token, project_id, email) = token_manager.get_token_by_email(&source.account).await?;
+                let upstream = upstream.for_account_proxy(token_manager.upstream_proxy_for(&email).as_deref())?;
+                upstream.create_cached_content(&access_token, &project_id, source.payload).await
+            }
+        })
+        .await
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn wrapped(system: &str, user: &str) -> Value {
+        json!({
+            "project": "proj-1",
+            "model": "gemini-2.5-pro",
+            "request": {
+                "systemInstruction": { "parts": [{ "text": system }] },
+                "tools": [{ "functionDeclarations": [{ "name": "read_file" }] }],
+                "contents": [{ "role": "user", "parts": [{ "text": user }] }]
+            }
+        })
+    }
+
+    fn test_config() -> ContextCacheConfig {
+        ContextCacheConfig {
+            enabled: true,
+            min_prefix_bytes: 0,
+            ..ContextCacheConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_identical_prefixes_reuse_cache_handle() {
+        let cache = ContextCache::new();
+        let config = test_config();
+        let created = AtomicUsize::new(0);
+        let create = |payload: Value| {
+            let n = created.fetch_add(1, Ordering::SeqCst);
+            async move {
+                assert_eq!(payload["model"], "models/gemini-2.5-pro");
+                assert!(payload.get("systemInstruction").is_some());
+                Ok(format!("cachedContents/handle-{}", n))
+            }
+        };
+
+        let mut first = wrapped("You are a coding agent.", "hello");
+        let mut second = wrapped("You are a coding agent.", "a different question");
+        let h1 = cache.attach(&config, "a@example.com", &mut first, create).await;
+        let h2 = cache.attach(&config, "a@example.com", &mut second, create).await;
+
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+        assert_eq!(h1.as_deref(), Some("cachedContents/handle-0"));
+        assert_eq!(h1, h2);
+        for body in [&first, &second] {
+            assert_eq!(body["request"]["cachedContent"], "cachedContents/handle-0");
+            assert!(body["request"].get("systemInstruction").is_none());
+            assert!(body["request"].get("tools").is_none());
+            assert!(body["request"].get("contents").is_some());
+        }
+
+        // 前缀不同则创建新句柄
+        let mut third = wrapped("You are a reviewer.", "hello");
+        let h3 = cache.attach(&config, "a@example.com", &mut third, create).await;
+        assert_eq!(h3.as_deref(), Some("cachedContents/handle-1"));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_or_small_prefix_is_untouched() {
+        let cache = ContextCache::new();
+        let create = |_: Value| async { Ok::<_, String>("cachedContents/x".to_string()) };
+
+        let mut body = wrapped("sys", "hi");
+        let disabled = ContextCacheConfig::default();
+        assert!(cache.attach(&disabled, "a@example.com", &mut body, create).await.is_none());
+
+        let small = ContextCacheConfig { enabled: true, ..ContextCacheConfig::default() };
+        assert!(cache.attach(&small, "a@example.com", &mut body, create).await.is_none());
+        assert!(body["request"].get("cachedContent").is_none());
+    }
+
+    #[test]
+    fn test_eviction_and_invalidate() {
+        let cache = ContextCache::new();
+        let ttl = Duration::from_secs(600);
+        cache.insert("a".into(), "cachedContents/a".into(), ttl, 2);
+        std::thread::sleep(Duration::from_millis(5));
+        cache.insert("b".into(), "cachedContents/b".into(), ttl, 2);
+        std::thread::sleep(Duration::from_millis(5));
+        cache.insert("c".into(), "cachedContents/c".into(), ttl, 2);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.lookup("a").is_none());
+
+        cache.invalidate("cachedContents/c");
+        assert!(cache.lookup("c").is_none());
+        assert_eq!(cache.lookup("b").as_deref(), Some("cachedContents/b"));
+    }
+
+    #[test]
+    fn test_only_cache_errors_invalidate_handle() {
+        assert!(is_cache_rejection(404, r#"{"error":{"message":"CachedContent not found (or permission denied)"}}"#));
+        assert!(is_cache_rejection(400, "Invalid cachedContent: cachedContents/abc has expired"));
+        // 与缓存无关的 400/404 以及其他状态码保留句柄
+        assert!(!is_cache_rejection(400, "Invalid JSON payload received. Unknown name \"foo\""));
+        assert!(!is_cache_rejection(404, "Requested entity was not found."));
+        assert!(!is_cache_rejection(429, "Resource exhausted for cachedContents/abc"));
+        assert!(!is_cache_rejection(500, "Internal error"));
+    }
+
+    #[test]
+    fn test_rejected_handle_is_dropped_and_retried_once_without_cache() {
+        let cache = ContextCache::new();
+        let ttl = Duration::from_secs(600);
+        cache.insert("a".into(), "cachedContents/a".into(), ttl, 8);
+        let rejection = "CachedContent not found (or permission denied)";
+
+        let mut retry = CacheRetry::default();
+        assert_eq!(retry.extra_attempts(), 1);
+        assert!(!retry.bypasses_cache());
+
+        // 无句柄或非缓存错误不触发重试
+        assert!(!retry.should_retry(&cache, None, 404, rejection));
+        assert!(!retry.should_retry(&cache, Some("cachedContents/a"), 500, "Internal error"));
+        assert_eq!(cache.lookup("a").as_deref(), Some("cachedContents/a"));
+
+        assert!(retry.should_retry(&cache, Some("cachedContents/a"), 404, rejection));
+        assert!(cache.lookup("a").is_none());
+        assert!(retry.is_pending());
+        assert!(retry.bypasses_cache());
+        assert_eq!(retry.extra_attempts(), 1);
+
+        retry.begin_attempt();
+        assert!(!retry.is_pending());
+        assert_eq!(retry.extra_attempts(), 0);
+        // 只重试一次
+        assert!(!retry.should_retry(&cache, Some("cachedContents/b"), 404, rejection));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_warmer_refreshes_handle_before_ttl() {
+        let cache: &'static ContextCache = Box::leak(Box::new(ContextCache::new()));
+        let mut config = test_config();
+        config.ttl_secs = 600;
+        config.warm.enabled = true;
+        config.warm.interval_secs = 60;
+        config.warm.refresh_before_secs = 120;
+
+        let create = |_: Value| async { Ok::<_, String>("cachedContents/cold".to_string()) };
+        let mut body = wrapped("You are a coding agent.", "hello");
+        let key = CachePrefix::extract(&body).unwrap().key;
+        cache.attach(&config, "a@example.com", &mut body, create).await;
+
+        let refreshed = Arc::new(AtomicUsize::new(0));
+        let counter = refreshed.clone();
+        let warmer = tokio::spawn(run_warmer(
+            cache,
+            Arc::new(tokio::sync::RwLock::new(config)),
+            move |source: CacheSource| {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    assert_eq!(source.account, "a@example.com");
+                    assert_eq!(source.payload["ttl"], "600s");
+                    Ok(format!("cachedContents/warm-{}", n))
+                }
+            },
+        ));
+
+        // 本地有效期 540s：t=420 起进入刷新窗口，此前不刷新
+        tokio::time::sleep(Duration::from_secs(410)).await;
+        assert_eq!(refreshed.load(Ordering::SeqCst), 0);
+        assert_eq!(cache.lookup(&key).as_deref(), Some("cachedContents/cold"));
+
+        // 在原句柄到期 (t=540) 之前完成刷新
+        tokio::time::sleep(Duration::from_secs(120)).await;
+        assert_eq!(refreshed.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.lookup(&key).as_deref(), Some("cachedContents/warm-0"));
+
+        // 刷新后的句柄在原 TTL 过后仍然有效，且不会被重复刷新
+        tokio::time::sleep(Duration::from_secs(200)).await;
+        assert_eq!(cache.lookup(&key).as_deref(), Some("cachedContents/warm-0"));
+        assert_eq!(refreshed.load(Ordering::SeqCst), 1);
+        warmer.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_warmer_skips_idle_handle() {
+        let cache: &'static ContextCache = Box::leak(Box::new(ContextCache::new()));
+        let mut config = test_config();
+        config.ttl_secs = 600;
+        config.warm.enabled = true;
+        config.warm.interval_secs = 60;
+        config.warm.refresh_before_secs = 120;
+        config.warm.max_idle_secs = 300;
+
+        let create = |_: Value| async { Ok::<_, String>("cachedContents/cold".to_string()) };
+        let mut body = wrapped("You are a coding agent.", "hello");
+        let key = CachePrefix::extract(&body).unwrap().key;
+        cache.attach(&config, "a@example.com", &mut body, create).await;
+
+        let refreshed = Arc::new(AtomicUsize::new(0));
+        let counter = refreshed.clone();
+        let warmer = tokio::spawn(run_warmer(cache, Arc::new(tokio::sync::RwLock::new(config)), move |_: CacheSource| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok::<_, String>("cachedContents/warm".to_string()) }
+        }));
+
+        // 创建后再无请求使用：进入刷新窗口时已闲置超过 300s，任其过期
+        tokio::time::sleep(Duration::from_secs(600)).await;
+        assert_eq!(refreshed.load(Ordering::SeqCst), 0);
+        assert_eq!(cache.lookup(&key), None);
+        warmer.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_warmer_skips_when_caching_disabled() {
+        let cache: &'static ContextCache = Box::leak(Box::new(ContextCache::new()));
+        let mut config = test_config();
+        config.ttl_secs = 600;
+        config.warm.enabled = true;
+        let create = |_: Value| async { Ok::<_, String>("cachedContents/cold".to_string()) };
+        let mut body = wrapped("You are a coding agent.", "hello");
+        cache.attach(&config, "a@example.com", &mut body, create).await;
+
+        config.enabled = false;
+        let refreshed = Arc::new(AtomicUsize::new(0));
+        let counter = refreshed.clone();
+        let warmer = tokio::spawn(run_warmer(cache, Arc::new(tokio::sync::RwLock::new(config)), move |_: CacheSource| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok::<_, String>("cachedContents/warm".to_string()) }
+        }));
+        tokio::time::sleep(Duration::from_secs(600)).await;
+        assert_eq!(refreshed.load(Ordering::SeqCst), 0);
+        warmer.abort();
+    }
+}
@@ -0,0 +1,70 @@
+// 账号管理内部端点
+// 提供 /internal/accounts/:id/drain，供 CLI 在删除账号前排空运行中服务上该账号的进行中请求
+// 除 API Key 外还要求管理令牌 (见 `proxy::admin_token`)，只持有 API Key 的客户端无法调用
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::proxy::server::AppState;
+
+/// 默认最长等待时间 (秒)
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 60;
+
+#[derive(Debug, Deserialize)]
+pub struct DrainQuery {
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// 排空账号：停止分配新请求并等待进行中的请求结束
+/// - 200: 已排空，可以安全删除
+/// - 403: 缺少或错误的管理令牌
+/// - 404: 账号不在当前账号池中
+/// - 504: 超时，账号恢复调度，返回仍在进行中的请求数
+pub async fn handle_drain_account(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DrainQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if !crate::proxy::admin_token::verify(state.admin_token.as_deref().map(String::as_str), &headers) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({ "error": "Admin token required" })),
+        )
+            .into_response();
+    }
+
+    let Some(account_id) = state.token_manager.find_account_id(&id) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Account {} not found in the proxy pool", id) })),
+        )
+            .into_response();
+    };
+
+    let timeout = Duration::from_secs(query.timeout_secs.unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS));
+    match state.token_manager.drain_account(&account_id, timeout).await {
+        Ok(()) => Json(json!({
+            "account_id": account_id,
+            "drained": true,
+            "in_flight": 0
+        }))
+        .into_response(),
+        Err(remaining) => (
+            StatusCode::GATEWAY_TIMEOUT,
+            Json(json!({
+                "account_id": account_id,
+                "drained": false,
+                "in_flight": remaining
+            })),
+        )
+            .into_response(),
+    }
+}
@@ -56,6 +56,9 @@ pub async fn handle_audio_transcription(
         "无法获取文件名".to_string(),
     ))?;
 
+    crate::proxy::common::model_mapping::check_client_model_allowed(&model, &state.allowed_client_models.read().await)
+        .map_err(|e| (e.status(), e.to_body(crate::proxy::upstream::errors::ErrorProtocol::OpenAI).to_string()))?;
+
     info!(
         "收到音频转录请求: 文件={}, 大小={} bytes, 模型={}",
         file_name,
@@ -118,7 +121,10 @@ pub async fn handle_audio_transcription(
     });
 
     // 8. 发送请求到 Gemini
-    let upstream = state.upstream.clone();
+    let upstream = state
+        .upstream
+        .for_account_proxy(token_manager.upstream_proxy_for(&email).as_deref())
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
     let response = upstream
         .call_v1_internal("generateContent", &access_token, wrapped_body, None)
         .await
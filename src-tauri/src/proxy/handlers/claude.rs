@@ -18,8 +18,10 @@ use crate::proxy::mappers::claude::{
     StreamingState, BlockType,
 };
 use crate::proxy::server::AppState;
+use crate::proxy::common::error::ProxyError;
+use crate::proxy::upstream::errors::ErrorProtocol;
+use crate::proxy::upstream::retry::{model_unavailable_decision, ModelUnavailableDecision, VariantFallback};
 use axum::http::HeaderMap;
-use std::sync::atomic::Ordering;
 
 // Increase to allow rotation across larger account pools.
 const MAX_RETRY_ATTEMPTS: usize = 20;
@@ -351,7 +353,7 @@ fn should_rotate_account(status_code: u16) -> bool {
 pub async fn handle_messages(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(body): Json<Value>,
+    Json(mut body): Json<Value>,
 ) -> Response {
     tracing::debug!("handle_messages called. Body JSON len: {}", body.to_string().len());
     
@@ -362,26 +364,18 @@ pub async fn handle_messages(
         .collect::<String>().to_lowercase();
         
     // Decide whether this request should be handled by z.ai (Anthropic passthrough) or the existing Google flow.
+    // 每次请求读取一次快照，热切换期间进行中的请求不受影响
     let zai = state.zai.read().await.clone();
-    let zai_enabled = zai.enabled && !matches!(zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
-    let google_accounts = state.token_manager.len();
+    let use_zai = crate::proxy::providers::zai_anthropic::should_dispatch_to_zai(
+        &zai,
+        state.token_manager.len(),
+        state.token_manager.available_count(),
+        &state.provider_rr,
+    );
 
-    let use_zai = if !zai_enabled {
-        false
-    } else {
-        match zai.dispatch_mode {
-            crate::proxy::ZaiDispatchMode::Off => false,
-            crate::proxy::ZaiDispatchMode::Exclusive => true,
-            crate::proxy::ZaiDispatchMode::Fallback => google_accounts == 0,
-            crate::proxy::ZaiDispatchMode::Pooled => {
-                // Treat z.ai as exactly one extra slot in the pool.
-                // No strict guarantees: it may get 0 requests if selection never hits.
-                let total = google_accounts.saturating_add(1).max(1);
-                let slot = state.provider_rr.fetch_add(1, Ordering::Relaxed) % total;
-                slot == 0
-            }
-        }
-    };
+    // 省略 model 字段时使用 Anthropic 协议的默认模型
+    let default_model = state.default_model_per_protocol.read().await.for_protocol(ErrorProtocol::Anthropic).map(str::to_string);
+    crate::proxy::common::model_mapping::fill_omitted_model(&mut body, default_model.as_deref());
 
     // [CRITICAL REFACTOR] 优先解析并过滤 Thinking 块，确保 z.ai 也是用修复后的 Body
     let mut request: crate::proxy::mappers::claude::models::ClaudeRequest = match serde_json::from_value(body) {
@@ -399,6 +393,13 @@ pub async fn handle_messages(
             ).into_response();
         }
     };
+    request.model = match crate::proxy::common::model_mapping::require_model_field(&request.model) {
+        Ok(model) => model,
+        Err(e) => return e.into_protocol_response(ErrorProtocol::Anthropic),
+    };
+    if let Err(e) = crate::proxy::common::model_mapping::check_client_model_allowed(&request.model, &state.allowed_client_models.read().await) {
+        return e.into_protocol_response(ErrorProtocol::Anthropic);
+    }
 
     // Warmup short-circuit: return deterministic test response at service layer
     if is_warmup_request(&request) {
@@ -582,6 +583,8 @@ pub async fn handle_messages(
     
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    let model_unavailable_action = *state.model_unavailable_action.read().await;
+    let strip_variant_suffix = *state.strip_variant_suffix_on_unavailable.read().await;
 
     let mut last_error = String::new();
     let mut retried_without_thinking = false;
@@ -593,15 +596,52 @@ pub async fn handle_messages(
     });
 
     // 2. 模型路由与配置解析 (提前解析以确定请求类型)
-    // 先不应用家族映射，获取初步的 mapped_model
-    let initial_route_plan = crate::proxy::common::model_mapping::resolve_model_route_plan(
+    // 先不应用家族映射，获取初步的 mapped_model；路由前先规范化模型名，原始名称保留用于回显
+    let lookup_model = crate::proxy::common::model_mapping::route_lookup_model(
         &request_for_body.model,
         &*state.custom_mapping.read().await,
-        &*state.openai_mapping.read().await,
-        &*state.anthropic_mapping.read().await,
-        &*state.model_strategies.read().await,
-        false,  // 先不应用家族映射
+        &*state.model_canonicalization.read().await,
+        &*state.auto_model.read().await,
     );
+    // 请求头指定的候选链优先于配置解析的结果 (不再应用家族映射)
+    let header_plan = match crate::proxy::common::candidate_override::header_route_plan(
+        &headers,
+        state.request_timeout_policy.read().await.allow_header_overrides,
+        &state.allowed_client_models.read().await,
+        &*state.custom_mapping.read().await,
+    ) {
+        Ok(plan) => plan,
+        Err(e) => return e.into_protocol_response(ErrorProtocol::Anthropic),
+    };
+    // 命中策略 A/B 实验时按分组选择策略 (请求头指定的候选链仍然优先)
+    let experiment_plan = match header_plan {
+        Some(_) => None,
+        None => crate::proxy::common::model_mapping::resolve_experiment_plan(
+            &lookup_model,
+            &*state.strategy_experiments.read().await,
+            &*state.model_strategies.read().await,
+        ),
+    };
+    // 请求头候选链与实验分组均为确定的计划，不再按家族映射重新解析
+    let has_fixed_plan = header_plan.is_some() || experiment_plan.is_some();
+    let initial_route_plan = match header_plan.or(experiment_plan) {
+        Some(plan) => plan,
+        None => crate::proxy::common::model_mapping::resolve_model_route_plan(
+            &lookup_model,
+            &crate::proxy::common::model_mapping::ModelRouteConfig {
+                custom_mapping: &*state.custom_mapping.read().await,
+                openai_mapping: &*state.openai_mapping.read().await,
+                openai_family_rules: &state.openai_family_rules.read().await,
+                anthropic_mapping: &*state.anthropic_mapping.read().await,
+                model_strategies: &*state.model_strategies.read().await,
+                deprecation: &*state.model_deprecation.read().await,
+                default_policy: &*state.default_fallback_policy.read().await,
+                thinking_passthrough_prefixes: &state.thinking_passthrough_prefixes.read().await,
+                family_overrides_builtin: *state.family_overrides_builtin.read().await,
+            },
+            false, // 先不应用家族映射
+        ),
+    };
 
     let config_probe = crate::proxy::mappers::common_utils::resolve_request_config(&request_for_body.model, &initial_route_plan.primary, &tools_val);
 
@@ -609,15 +649,27 @@ pub async fn handle_messages(
     // request_type == "agent" 表示 CLI 请求，应该应用家族映射
     // 其他类型（web_search, image_gen）不应用家族映射
     let is_cli_request = config_probe.request_type == "agent";
+    // 全局覆盖 (disable/force_family_mapping) 优先于客户端检测
+    let apply_family_mapping = crate::proxy::common::model_mapping::effective_family_mapping(
+        is_cli_request,
+        *state.family_mapping_override.read().await,
+    );
 
-    let route_plan = if is_cli_request {
+    let route_plan = if apply_family_mapping && !has_fixed_plan {
         crate::proxy::common::model_mapping::resolve_model_route_plan(
-            &request_for_body.model,
-            &*state.custom_mapping.read().await,
-            &*state.openai_mapping.read().await,
-            &*state.anthropic_mapping.read().await,
-            &*state.model_strategies.read().await,
-            true,  // CLI 请求应用家族映射
+            &lookup_model,
+            &crate::proxy::common::model_mapping::ModelRouteConfig {
+                custom_mapping: &*state.custom_mapping.read().await,
+                openai_mapping: &*state.openai_mapping.read().await,
+                openai_family_rules: &state.openai_family_rules.read().await,
+                anthropic_mapping: &*state.anthropic_mapping.read().await,
+                model_strategies: &*state.model_strategies.read().await,
+                deprecation: &*state.model_deprecation.read().await,
+                default_policy: &*state.default_fallback_policy.read().await,
+                thinking_passthrough_prefixes: &state.thinking_passthrough_prefixes.read().await,
+                family_overrides_builtin: *state.family_overrides_builtin.read().await,
+            },
+            true, // CLI 请求 (或强制开启) 应用家族映射
         )
     } else {
         initial_route_plan
@@ -626,7 +678,7 @@ pub async fn handle_messages(
     let mut model_candidates = route_plan.candidates();
     let max_models = route_plan.max_models();
     if model_candidates.is_empty() {
-        model_candidates.push(request_for_body.model.clone());
+        model_candidates.push(lookup_model.clone());
     }
     model_candidates.truncate(max_models);
 
@@ -634,18 +686,66 @@ pub async fn handle_messages(
     // 使用 SessionManager 生成稳定的会话指纹
     let session_id_str = crate::proxy::session_manager::SessionManager::extract_session_id(&request_for_body);
     let session_id = Some(session_id_str.as_str());
+    let output_limits = state.model_output_limits.read().await.clone();
+    let model_transforms = state.model_transforms.read().await.clone();
+    let model_system_prompts = state.model_system_prompts.read().await.clone();
+    let streaming_config = state.streaming.read().await.clone();
+    let keepalive_secs = streaming_config.keepalive_interval_secs;
+    let stream_limits = crate::proxy::common::stream_limits::StreamLimits::from_config(&streaming_config);
+    let reasoning_output = *state.reasoning_output.read().await;
+    let truncation_warning = *state.truncation_warning.read().await;
+    let tool_limit = state.tool_limit.read().await.clone();
+    let auto_trim_history = state.auto_trim_history.read().await.clone();
+    // 图片/文档块不合法时直接拒绝，而不是丢弃后继续请求
+    if let Err(e) = crate::proxy::mappers::claude::request::validate_media_blocks(&request_for_body.messages) {
+        return ProxyError::InvalidRequest(e).into_protocol_response(ErrorProtocol::Anthropic);
+    }
+    let mut media_cache = crate::proxy::mappers::content_parts::MediaCache::new();
+    let media_policy = crate::proxy::mappers::content_parts::MediaFetchPolicy::from_config(&*state.remote_media.read().await);
+    let schema_retry_config = state.schema_retry.read().await.clone();
+    let schema_inline_max_bytes = *state.schema_inline_max_bytes.read().await;
+    let thinking_passthrough_prefixes = state.thinking_passthrough_prefixes.read().await.clone();
+    let safety_fallback = state.safety_fallback.read().await.clone();
+    let mut schema_retry = crate::proxy::common::schema_retry::SchemaRetry::default();
+    let mut cache_retry = crate::proxy::context_cache::CacheRetry::default();
+    let timeout_override = state.request_timeout_policy.read().await.resolve(&headers);
+    let context_cache_config = state.context_cache.read().await.clone();
+    let context_cache = state.context_cache_handles.clone();
+
+    // 账号重试与候选回退共用的上游调用预算
+    let mut attempt_budget = crate::proxy::common::attempt_budget::AttemptBudget::new(*state.max_total_attempts.read().await);
+    // 实际尝试过的候选模型数 (用于错误信息，不含因预算耗尽未尝试的候选)
+    let mut models_tried = 0usize;
 
     for (model_index, candidate_model) in model_candidates.iter().enumerate() {
+        if attempt_budget.is_exhausted() {
+            break;
+        }
+        models_tried = model_index + 1;
         let is_last_model = model_index + 1 >= model_candidates.len();
         let mut switched_model = false;
 
         let config = crate::proxy::mappers::common_utils::resolve_request_config(&request_for_body.model, candidate_model, &tools_val);
+        let mut variant_fallback = VariantFallback::new(strip_variant_suffix, candidate_model);
 
-        for attempt in 0..max_attempts {
-            let mut mapped_model = candidate_model.clone();
+        for attempt in 0..max_attempts + variant_fallback.extra_attempts() + schema_retry.extra_attempts() + cache_retry.extra_attempts() {
+            // 额外的尝试只留给同账号的基础模型重试、Schema 严格重试与缓存句柄重试
+            if attempt >= max_attempts && !variant_fallback.is_pending() && !schema_retry.is_pending() && !cache_retry.is_pending() {
+                break;
+            }
+            if !attempt_budget.try_acquire() {
+                break;
+            }
+            schema_retry.begin_attempt();
+            cache_retry.begin_attempt();
+            // 思维链变体在上一个账号不可用：同账号改用基础模型 (thinking 参数由请求转换按基础模型能力保留)
+            let (pinned_account, base_model) = variant_fallback.take_pinned().unzip();
+            let mut mapped_model = base_model.unwrap_or_else(|| candidate_model.clone());
 
             let force_rotate_token = attempt > 0;
-            let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, force_rotate_token, session_id).await {
+            let (access_token, project_id, email) = match pinned_account {
+            Some(account) => account,
+            None => match token_manager.get_token(&config.request_type, force_rotate_token, session_id).await {
             Ok(t) => t,
             Err(e) => {
                 let safe_message = if e.contains("invalid_grant") {
@@ -653,21 +753,23 @@ pub async fn handle_messages(
                 } else {
                     e
                 };
-                 return (
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    Json(json!({
-                        "type": "error",
-                        "error": {
-                            "type": "overloaded_error",
-                            "message": format!("No available accounts: {}", safe_message)
-                        }
-                    }))
-                ).into_response();
+                 return ProxyError::NoAccounts(format!("No available accounts: {}", safe_message))
+                    .into_protocol_response(ErrorProtocol::Anthropic);
             }
+            },
         };
 
         last_email = Some(email.clone());
         info!("✓ Using account: {} (type: {})", email, config.request_type);
+
+        // 账号级上游代理覆盖全局代理
+        let upstream = match upstream.for_account_proxy(token_manager.upstream_proxy_for(&email).as_deref()) {
+            Ok(u) => u,
+            Err(e) => {
+                last_error = e;
+                continue;
+            }
+        };
         
         
         // ===== 【优化】后台任务智能检测与降级 =====
@@ -728,12 +830,13 @@ pub async fn handle_messages(
         }
 
         
-        request_with_mapped.model = mapped_model;
+        request_with_mapped.model = mapped_model.clone();
+        crate::proxy::common::system_prompt::inject_claude(&mut request_with_mapped, &model_system_prompts);
 
         // 生成 Trace ID (简单用时间戳后缀)
         // let _trace_id = format!("req_{}", chrono::Utc::now().timestamp_subsec_millis());
 
-        let gemini_body = match transform_claude_request_in(&request_with_mapped, &project_id) {
+        let mut gemini_body = match transform_claude_request_in(&request_with_mapped, &project_id, &thinking_passthrough_prefixes, schema_inline_max_bytes) {
             Ok(b) => {
                 debug!("[{}] Transformed Gemini Body: {}", trace_id, serde_json::to_string_pretty(&b).unwrap_or_default());
                 b
@@ -752,6 +855,41 @@ pub async fn handle_messages(
             }
         };
         
+        // 按模型输出上限规范化 max_tokens
+        if let Some(limit) = crate::proxy::mappers::common_utils::resolve_output_limit(&request_with_mapped.model, &output_limits) {
+            crate::proxy::mappers::common_utils::clamp_thinking_budget(&mut gemini_body["request"]["generationConfig"], limit, &request_with_mapped.model);
+            if let Some(v) = crate::proxy::mappers::common_utils::normalize_output_tokens(
+                request_with_mapped.max_tokens,
+                limit,
+                &request_with_mapped.model,
+                crate::proxy::mappers::common_utils::thinking_budget(&gemini_body["request"]["generationConfig"]),
+            ) {
+                gemini_body["request"]["generationConfig"]["maxOutputTokens"] = json!(v);
+            }
+        }
+
+        // 模型级请求变换
+        crate::proxy::common::json_transform::apply_request_transform(&request_with_mapped.model, &model_transforms, &mut gemini_body);
+
+        // 工具数量上限 (Schema 清洗之后)
+        if let Err(e) = crate::proxy::common::tool_limit::apply_tool_limit(&mut gemini_body["request"], &tool_limit) {
+            return ProxyError::InvalidRequest(e).into_protocol_response(ErrorProtocol::Anthropic);
+        }
+        // 超出上下文窗口时裁剪最早的历史消息
+        crate::proxy::common::history_trim::trim_history(&mut gemini_body["request"], &request_with_mapped.model, &auto_trim_history);
+        // 校验媒体类型，按配置下载远程图片/文档
+        if let Err(e) = crate::proxy::mappers::content_parts::resolve_media_parts(
+            &mut gemini_body["request"],
+            &media_policy,
+            &mut media_cache,
+        )
+        .await
+        {
+            return ProxyError::InvalidRequest(e).into_protocol_response(ErrorProtocol::Anthropic);
+        }
+        schema_retry.prepare(&mut gemini_body["request"]);
+        let had_tools = crate::proxy::common::schema_retry::has_function_declarations(&gemini_body["request"]);
+
     // 4. 上游调用 - 自动转换逻辑
     let client_wants_stream = request.stream;
     // [AUTO-CONVERSION] 非 Stream 请求自动转换为 Stream 以享受更宽松的配额
@@ -765,11 +903,24 @@ pub async fn handle_messages(
     let method = if actual_stream { "streamGenerateContent" } else { "generateContent" };
     let query = if actual_stream { Some("alt=sse") } else { None };
 
-    let response = match upstream.call_v1_internal(
+    // Gemini 上下文缓存：复用稳定的 systemInstruction + tools 前缀
+    // 句柄被拒后的重试不再挂载缓存
+    let cached_content = if cache_retry.bypasses_cache() {
+        None
+    } else {
+        context_cache
+            .attach(&context_cache_config, &email, &mut gemini_body, |payload| {
+                upstream.create_cached_content(&access_token, &project_id, payload)
+            })
+            .await
+    };
+
+    let response = match upstream.call_v1_internal_with_timeout(
         method,
         &access_token,
         gemini_body,
-        query
+        query,
+        timeout_override,
     ).await {
             Ok(r) => r,
             Err(e) => {
@@ -780,7 +931,22 @@ pub async fn handle_messages(
         };
         
         let status = response.status();
-        
+
+        // 安全拦截 (SAFETY / RECITATION) 按配置切换到下一个候选
+        let response = if status.is_success() && safety_fallback.enabled && !is_last_model {
+            match crate::proxy::common::safety_fallback::screen_response(response, actual_stream, &safety_fallback.block_reasons).await {
+                Ok(r) => r,
+                Err(reason) => {
+                    tracing::warn!("[{}] Response from {} blocked ({}), advancing to next candidate", trace_id, candidate_model, reason);
+                    last_error = format!("Response blocked by upstream: {}", reason);
+                    switched_model = true;
+                    break;
+                }
+            }
+        } else {
+            response
+        };
+
         // 成功
         if status.is_success() {
             // [智能限流] 请求成功，重置该账号的连续失败计数
@@ -788,8 +954,10 @@ pub async fn handle_messages(
             
             // 处理流式响应
             if actual_stream {
-                let stream = response.bytes_stream();
-                let gemini_stream = Box::pin(stream);
+                let gemini_stream = crate::proxy::common::json_transform::transform_sse_stream(
+                    Box::pin(response.bytes_stream()),
+                    crate::proxy::common::json_transform::response_ops_for(&request_with_mapped.model, &model_transforms),
+                );
                 let warmup = is_warmup_request(&request_with_mapped);
                 let mut claude_stream = create_claude_sse_stream(
                     gemini_stream,
@@ -800,7 +968,53 @@ pub async fn handle_messages(
 
                 // [FIX #530/#529] Peek first chunk to detect empty response and allow retry
                 // If the stream is empty or fails immediately, we should retry instead of sending 200 OK + empty body
-                let first_chunk = claude_stream.next().await;
+                // 开启保活时，首字节超时则提前返回 SSE 并发送 ping (此后不再支持空响应重试)
+                let first_chunk = if client_wants_stream && keepalive_secs > 0 {
+                    let peeked = tokio::time::timeout(Duration::from_secs(keepalive_secs), claude_stream.next()).await;
+                    match peeked {
+                        Ok(chunk) => chunk,
+                        Err(_) => {
+                            info!("[{}] Upstream first byte delayed, streaming with keep-alive pings", trace_id);
+                            state.monitor.record_strategy_serve(route_plan.strategy_id.as_deref(), model_index, candidate_model).await;
+                            crate::proxy::common::candidate_stats::record_outcome(route_plan.strategy_id.as_deref(), candidate_model, true);
+                            use crate::proxy::common::keepalive::{with_keepalive, ANTHROPIC_PING};
+                            let keepalive_stream = futures::stream::once(async {
+                                Ok::<Bytes, String>(Bytes::from_static(ANTHROPIC_PING.as_bytes()))
+                            })
+                            .chain(with_keepalive(
+                                crate::proxy::common::reasoning_output::filter_stream(
+                                    claude_stream,
+                                    reasoning_output,
+                                    ErrorProtocol::Anthropic,
+                                ),
+                                Duration::from_secs(keepalive_secs),
+                                ANTHROPIC_PING,
+                            ))
+                            .map(|result| -> Result<Bytes, std::io::Error> {
+                                match result {
+                                    Ok(b) => Ok(b),
+                                    // 交给 limit_stream 转为 Anthropic error 事件并结束流
+                                    Err(e) => Err(std::io::Error::other(e)),
+                                }
+                            });
+                            return Response::builder()
+                                .status(StatusCode::OK)
+                                .header(header::CONTENT_TYPE, "text/event-stream")
+                                .header(header::CACHE_CONTROL, "no-cache")
+                                .header(header::CONNECTION, "keep-alive")
+                                .header("X-Account-Email", &email)
+                                .header("X-Mapped-Model", &request_with_mapped.model)
+                                .body(Body::from_stream(crate::proxy::common::stream_limits::limit_stream(
+                                    Box::pin(keepalive_stream),
+                                    stream_limits,
+                                    ErrorProtocol::Anthropic,
+                                )))
+                                .unwrap();
+                        }
+                    }
+                } else {
+                    claude_stream.next().await
+                };
 
                 match first_chunk {
                     Some(Ok(bytes)) => {
@@ -811,12 +1025,15 @@ pub async fn handle_messages(
                         }
                         
                         // We have data! Construct the combined stream
+                        state.monitor.record_strategy_serve(route_plan.strategy_id.as_deref(), model_index, candidate_model).await;
+                        crate::proxy::common::candidate_stats::record_outcome(route_plan.strategy_id.as_deref(), candidate_model, true);
                         let stream_rest = claude_stream;
                         let combined_stream = Box::pin(futures::stream::once(async move { Ok(bytes) })
                             .chain(stream_rest.map(|result| -> Result<Bytes, std::io::Error> {
                                 match result {
                                     Ok(b) => Ok(b),
-                                    Err(e) => Ok(Bytes::from(format!("data: {{\"error\":\"{}\"}}\n\n", e))),
+                                    // 交给 limit_stream 转为 Anthropic error 事件并结束流
+                                    Err(e) => Err(std::io::Error::other(e)),
                                 }
                             })));
 
@@ -830,7 +1047,15 @@ pub async fn handle_messages(
                                 .header(header::CONNECTION, "keep-alive")
                                 .header("X-Account-Email", &email)
                                 .header("X-Mapped-Model", &request_with_mapped.model)
-                                .body(Body::from_stream(combined_stream))
+                                .body(Body::from_stream(crate::proxy::common::stream_limits::limit_stream(
+                                    crate::proxy::common::reasoning_output::filter_stream(
+                                        combined_stream,
+                                        reasoning_output,
+                                        ErrorProtocol::Anthropic,
+                                    ),
+                                    stream_limits,
+                                    ErrorProtocol::Anthropic,
+                                )))
                                 .unwrap();
                         } else {
                             // 客户端要非 Stream，需要收集完整响应并转换为 JSON
@@ -839,6 +1064,8 @@ pub async fn handle_messages(
                             match collect_stream_to_json(combined_stream).await {
                                 Ok(full_response) => {
                                     info!("[{}] ✓ Stream collected and converted to JSON", trace_id);
+                                    let mut full_response = serde_json::to_value(&full_response).unwrap_or_default();
+                                    crate::proxy::common::reasoning_output::apply_to_response(reasoning_output, ErrorProtocol::Anthropic, &mut full_response);
                                     return Response::builder()
                                         .status(StatusCode::OK)
                                         .header(header::CONTENT_TYPE, "application/json")
@@ -866,6 +1093,8 @@ pub async fn handle_messages(
                 }
             } else {
                 // 处理非流式响应
+                state.monitor.record_strategy_serve(route_plan.strategy_id.as_deref(), model_index, candidate_model).await;
+                crate::proxy::common::candidate_stats::record_outcome(route_plan.strategy_id.as_deref(), candidate_model, true);
                 let bytes = match response.bytes().await {
                     Ok(b) => b,
                     Err(e) => return (StatusCode::BAD_GATEWAY, format!("Failed to read body: {}", e)).into_response(),
@@ -876,10 +1105,14 @@ pub async fn handle_messages(
                     debug!("Upstream Response for Claude request: {}", text);
                 }
 
-                let gemini_resp: Value = match serde_json::from_slice(&bytes) {
+                let mut gemini_resp: Value = match serde_json::from_slice(&bytes) {
                     Ok(v) => v,
                     Err(e) => return (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)).into_response(),
                 };
+                crate::proxy::common::json_transform::apply_response_ops(
+                    &crate::proxy::common::json_transform::response_ops_for(&request_with_mapped.model, &model_transforms),
+                    &mut gemini_resp,
+                );
 
                 // 解包 response 字段（v1internal 格式）
                 let raw = gemini_resp.get("response").unwrap_or(&gemini_resp);
@@ -912,18 +1145,28 @@ pub async fn handle_messages(
                     cache_info
                 );
 
-                return (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", request_with_mapped.model.as_str())], Json(claude_response)).into_response();
+                let mut claude_response = serde_json::to_value(&claude_response).unwrap_or_default();
+                crate::proxy::common::reasoning_output::apply_to_response(reasoning_output, ErrorProtocol::Anthropic, &mut claude_response);
+                let mut resp = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", request_with_mapped.model.as_str())], Json(&claude_response)).into_response();
+                crate::proxy::common::truncation::apply_warning_header(truncation_warning, ErrorProtocol::Anthropic, &claude_response, &mut resp);
+                return resp;
             }
         }
         
         // 1. 立即提取状态码和 headers（防止 response 被 move）
         let status_code = status.as_u16();
-        let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
+        let retry_after = crate::proxy::rate_limit::extract_retry_after_secs(response.headers()).map(|s| s.to_string());
+        let content_type = response.headers().get("Content-Type").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         
         // 2. 获取错误文本并转移 Response 所有权
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status));
         last_error = format!("HTTP {}: {}", status_code, error_text);
         debug!("[{}] Upstream Error Response: {}", trace_id, error_text);
+
+        // 上游拒绝缓存句柄 (过期/已删除) 时丢弃句柄，并不带句柄重试一次；其他错误保留句柄
+        if cache_retry.should_retry(&context_cache, cached_content.as_deref(), status_code, &error_text) {
+            continue;
+        }
         
         // 3. 标记限流状态(用于 UI 显示) - 使用异步版本以支持实时配额刷新
         // 🆕 传入实际使用的模型,实现模型级别限流,避免不同模型配额互相影响
@@ -931,6 +1174,11 @@ pub async fn handle_messages(
             token_manager.mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, Some(&request_with_mapped.model)).await;
         }
 
+        // Schema 类 400：严格清洗工具 Schema 后重试一次 (先于下方宽泛的 INVALID_ARGUMENT 匹配)
+        if schema_retry.should_retry(status_code, &error_text, had_tools, &schema_retry_config) {
+            continue;
+        }
+
         // 4. 处理 400 错误 (Thinking 签名失效)
         // 由于已经主动过滤,这个错误应该很少发生
         if status_code == 400
@@ -986,6 +1234,29 @@ pub async fn handle_messages(
             }
         }
 
+        // 404: 当前账号无权访问该模型，按配置换号或切换候选
+        if status_code == 404 {
+            // 后台任务降级等已改写模型名时不回退
+            if mapped_model == *candidate_model
+                && variant_fallback.on_unavailable((access_token.clone(), project_id.clone(), email.clone()))
+            {
+                tracing::warn!("[{}] Model {} unavailable (404) on account {}, retrying base model on the same account", trace_id, candidate_model, email);
+                continue;
+            }
+            match model_unavailable_decision(model_unavailable_action, attempt, max_attempts, is_last_model) {
+                ModelUnavailableDecision::RotateAccount => {
+                    tracing::warn!("[{}] Model {} unavailable (404) on account {} attempt {}/{}, rotating account", trace_id, candidate_model, email, attempt + 1, max_attempts);
+                    continue;
+                }
+                ModelUnavailableDecision::NextCandidate => {
+                    tracing::warn!("[{}] Model {} unavailable (404) on account {}, advancing to next candidate", trace_id, candidate_model, email);
+                    switched_model = true;
+                    break;
+                }
+                ModelUnavailableDecision::Fail => {}
+            }
+        }
+
         if route_plan.is_capacity_first() && should_rotate_account(status_code) && !is_last_model {
             switched_model = true;
             break;
@@ -1009,10 +1280,17 @@ pub async fn handle_messages(
         } else {
             // 不可重试的错误，直接返回
             error!("[{}] Non-retryable error {}: {}", trace_id, status_code, error_text);
-            return (status, [("X-Account-Email", email.as_str())], error_text).into_response();
+            if status_code == 404 && crate::proxy::common::error::is_model_not_found(&error_text) {
+                let err = ProxyError::ModelUnknown(format!("Model '{}' is not available upstream", mapped_model));
+                return ([("X-Account-Email", email.as_str())], err.into_protocol_response(ErrorProtocol::Anthropic)).into_response();
+            }
+            return ([("X-Account-Email", email.as_str())], crate::proxy::upstream::errors::upstream_error_response(crate::proxy::upstream::errors::ErrorProtocol::Anthropic, status, content_type.as_deref(), error_text)).into_response();
         }
     }
 
+    // 该候选未能服务请求 (成功时已提前返回)
+    crate::proxy::common::candidate_stats::record_outcome(route_plan.strategy_id.as_deref(), candidate_model, false);
+    crate::proxy::middleware::debug_headers::record_fallback(candidate_model);
     if switched_model {
         if route_plan.policy.stickiness == crate::proxy::config::ModelStickiness::Weak {
             token_manager.clear_session_binding(session_id_str.as_str());
@@ -1040,32 +1318,18 @@ pub async fn handle_messages(
     }
     }
     
+    let err = ProxyError::exhausted(models_tried, &last_error);
+    let retry_after_secs = token_manager.min_rate_limit_wait();
     if let Some(email) = last_email {
-        (StatusCode::TOO_MANY_REQUESTS, [("X-Account-Email", email)], Json(json!({
-            "type": "error",
-            "error": {
-                "type": "overloaded_error",
-                "message": format!("All {} attempts failed. Last error: {}", max_attempts, last_error)
-            }
-        }))).into_response()
+        ([("X-Account-Email", email)], err.into_protocol_response_with_retry(ErrorProtocol::Anthropic, retry_after_secs)).into_response()
     } else {
-        (StatusCode::TOO_MANY_REQUESTS, Json(json!({
-            "type": "error",
-            "error": {
-                "type": "overloaded_error",
-                "message": format!("All {} attempts failed. Last error: {}", max_attempts, last_error)
-            }
-        }))).into_response()
+        err.into_protocol_response_with_retry(ErrorProtocol::Anthropic, retry_after_secs)
     }
 }
 
 /// 列出可用模型
 pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
-    use crate::proxy::common::model_mapping::get_all_dynamic_models;
-
-    let model_ids = get_all_dynamic_models(
-        &state.custom_mapping,
-    ).await;
+    let model_ids = super::common::advertised_model_ids(&state, crate::models::AccountProtocol::Anthropic).await;
 
     let data: Vec<_> = model_ids.into_iter().map(|id| {
         json!({
@@ -1082,7 +1346,7 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
     }))
 }
 
-/// 计算 tokens (占位符)
+/// 计算 tokens (z.ai 启用时转发，否则本地估算)
 pub async fn handle_count_tokens(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -1102,11 +1366,7 @@ pub async fn handle_count_tokens(
         .await;
     }
 
-    Json(json!({
-        "input_tokens": 0,
-        "output_tokens": 0
-    }))
-    .into_response()
+    Json(crate::proxy::common::token_estimate::claude_count_tokens_response(&body)).into_response()
 }
 
 // 移除已失效的简单单元测试，后续将补全完整的集成测试
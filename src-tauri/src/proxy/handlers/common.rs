@@ -1,5 +1,9 @@
-use axum::{extract::State, extract::Json, http::StatusCode, response::IntoResponse};
+use axum::{extract::State, extract::Json, extract::Path, extract::Query, http::StatusCode, response::IntoResponse};
+use serde::Deserialize;
 use serde_json::{json, Value};
+use crate::proxy::common::model_mapping::{ModelRoutePlan, RouteRule};
+use crate::proxy::common::request_timeout::RequestTimeoutPolicy;
+use crate::proxy::config::ModelOutputLimit;
 use crate::proxy::server::AppState;
 
 /// Detects model capabilities and configuration
@@ -8,19 +12,36 @@ pub async fn handle_detect_model(
     State(state): State<AppState>,
     Json(body): Json<Value>,
 ) -> impl IntoResponse {
-    let model_name = body.get("model").and_then(|v| v.as_str()).unwrap_or("");
+    let model_name = body.get("model").and_then(|v| v.as_str()).unwrap_or("").trim();
     
     if model_name.is_empty() {
         return (StatusCode::BAD_REQUEST, "Missing 'model' field").into_response();
     }
 
-    // 1. Resolve mapping
-    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+    // 1. Resolve mapping (canonicalize decorated names first)
+    let lookup_model = crate::proxy::common::model_mapping::route_lookup_model(
         model_name,
         &*state.custom_mapping.read().await,
-        &*state.openai_mapping.read().await,
-        &*state.anthropic_mapping.read().await,
-        false,
+        &*state.model_canonicalization.read().await,
+        &*state.auto_model.read().await,
+    );
+    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        &lookup_model,
+        &crate::proxy::common::model_mapping::ModelRouteConfig {
+            custom_mapping: &*state.custom_mapping.read().await,
+            openai_mapping: &*state.openai_mapping.read().await,
+            openai_family_rules: &state.openai_family_rules.read().await,
+            anthropic_mapping: &*state.anthropic_mapping.read().await,
+            model_strategies: &*state.model_strategies.read().await,
+            deprecation: &*state.model_deprecation.read().await,
+            default_policy: &*state.default_fallback_policy.read().await,
+            thinking_passthrough_prefixes: &state.thinking_passthrough_prefixes.read().await,
+            family_overrides_builtin: *state.family_overrides_builtin.read().await,
+        },
+        crate::proxy::common::model_mapping::effective_family_mapping(
+            false,
+            *state.family_mapping_override.read().await,
+        ),
     );
 
     // 2. Resolve capabilities
@@ -41,6 +62,17 @@ pub async fn handle_detect_model(
         }
     });
 
+    // 4. 输出 token 限制 (与能力信息一并返回)
+    let output_limits = state.model_output_limits.read().await;
+    if let Some(limit) = crate::proxy::mappers::common_utils::resolve_output_limit(&mapped_model, &output_limits) {
+        if let Some(obj) = response.as_object_mut() {
+            obj.insert("limits".to_string(), json!({
+                "default_max_tokens": limit.default_max_tokens,
+                "max_tokens_cap": limit.max_tokens_cap
+            }));
+        }
+    }
+
     if let Some(img_conf) = config.image_config {
         if let Some(obj) = response.as_object_mut() {
             obj.insert("config".to_string(), img_conf);
@@ -49,3 +81,239 @@ pub async fn handle_detect_model(
 
     Json(response).into_response()
 }
+
+/// 模型列表端点 (/v1/models 等) 对外展示的模型 ID
+/// 启用 `advertise_servable_only` 时仅保留当前协议下至少一个健康账号能服务的模型
+pub async fn advertised_model_ids(state: &AppState, protocol: crate::models::AccountProtocol) -> Vec<String> {
+    use crate::proxy::common::model_mapping;
+
+    let model_ids = model_mapping::get_all_dynamic_models(
+        &state.custom_mapping,
+        *state.advertise_all_aliases.read().await,
+    ).await;
+    let filter = state.servable_models.read().await.clone();
+    if !filter.enabled {
+        return model_ids;
+    }
+
+    let custom_mapping = state.custom_mapping.read().await;
+    let model_canonicalization = state.model_canonicalization.read().await;
+    let auto_model = state.auto_model.read().await;
+    let openai_mapping = state.openai_mapping.read().await;
+    let openai_family_rules = state.openai_family_rules.read().await;
+    let anthropic_mapping = state.anthropic_mapping.read().await;
+    let model_strategies = state.model_strategies.read().await;
+    let family_mapping = model_mapping::effective_family_mapping(false, *state.family_mapping_override.read().await);
+    let model_deprecation = state.model_deprecation.read().await;
+    let default_fallback_policy = state.default_fallback_policy.read().await;
+    let thinking_passthrough_prefixes = state.thinking_passthrough_prefixes.read().await;
+    let family_overrides_builtin = *state.family_overrides_builtin.read().await;
+    // 使用 explain 解析路由，避免列表请求计入兜底/弃用命中统计
+    let resolve = |id: &str| {
+        let lookup_model = model_mapping::route_lookup_model(id, &custom_mapping, &model_canonicalization, &auto_model);
+        model_mapping::explain_model_route_plan(
+            &lookup_model,
+            &model_mapping::ModelRouteConfig {
+                custom_mapping: &custom_mapping,
+                openai_mapping: &openai_mapping,
+                openai_family_rules: &openai_family_rules,
+                anthropic_mapping: &anthropic_mapping,
+                model_strategies: &model_strategies,
+                deprecation: &model_deprecation,
+                default_policy: &default_fallback_policy,
+                thinking_passthrough_prefixes: &thinking_passthrough_prefixes,
+                family_overrides_builtin,
+            },
+            family_mapping,
+        )
+        .0
+        .primary
+    };
+    let can_serve = |tier: Option<&str>| state.token_manager.can_serve_model(tier, protocol);
+    model_mapping::filter_servable_models(model_ids, &filter, resolve, can_serve)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModelDetailQuery {
+    /// 默认严格匹配：未命中任何映射的模型返回 404；`strict=false` 时返回兜底解析结果
+    #[serde(default)]
+    pub strict: Option<bool>,
+}
+
+/// 查询单个模型的详细信息 (路由解析、能力、限制与可服务的账号等级)
+/// GET /v1/models/:id
+pub async fn handle_get_model_detail(
+    State(state): State<AppState>,
+    Path(model_id): Path<String>,
+    Query(query): Query<ModelDetailQuery>,
+) -> impl IntoResponse {
+    use crate::proxy::common::model_mapping;
+
+    let lookup_model = model_mapping::route_lookup_model(
+        &model_id,
+        &*state.custom_mapping.read().await,
+        &*state.model_canonicalization.read().await,
+        &*state.auto_model.read().await,
+    );
+    let (plan, rule) = model_mapping::explain_model_route_plan(
+        &lookup_model,
+        &model_mapping::ModelRouteConfig {
+            custom_mapping: &*state.custom_mapping.read().await,
+            openai_mapping: &*state.openai_mapping.read().await,
+            openai_family_rules: &state.openai_family_rules.read().await,
+            anthropic_mapping: &*state.anthropic_mapping.read().await,
+            model_strategies: &*state.model_strategies.read().await,
+            deprecation: &*state.model_deprecation.read().await,
+            default_policy: &*state.default_fallback_policy.read().await,
+            thinking_passthrough_prefixes: &state.thinking_passthrough_prefixes.read().await,
+            family_overrides_builtin: *state.family_overrides_builtin.read().await,
+        },
+        model_mapping::effective_family_mapping(false, *state.family_mapping_override.read().await),
+    );
+
+    if rule == RouteRule::DefaultFallback && query.strict.unwrap_or(true) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": {
+                    "message": format!("The model '{}' does not exist", model_id),
+                    "type": "invalid_request_error",
+                    "code": "model_not_found"
+                }
+            })),
+        )
+            .into_response();
+    }
+
+    let output_limits = state.model_output_limits.read().await;
+    let limit = crate::proxy::mappers::common_utils::resolve_output_limit(&plan.primary, &output_limits);
+    let timeout_policy = *state.request_timeout_policy.read().await;
+    let detail = build_model_detail(
+        &model_id,
+        &lookup_model,
+        &plan,
+        &rule,
+        limit,
+        &timeout_policy,
+        &state.token_manager.subscription_tiers(),
+    );
+    Json(detail).into_response()
+}
+
+/// 组装模型详情 (OpenAI 模型对象 + 扩展字段)
+pub fn build_model_detail(
+    model_id: &str,
+    lookup_model: &str,
+    plan: &ModelRoutePlan,
+    rule: &RouteRule,
+    limit: Option<&ModelOutputLimit>,
+    timeout_policy: &RequestTimeoutPolicy,
+    tiers: &[String],
+) -> Value {
+    let config = crate::proxy::mappers::common_utils::resolve_request_config(
+        model_id,
+        &plan.primary,
+        &None,
+    );
+    let capability = crate::proxy::common::model_mapping::model_capability(&plan.primary);
+
+    json!({
+        "id": model_id,
+        "object": "model",
+        "created": 1706745600,
+        "owned_by": "antigravity",
+        "resolution": {
+            "lookup_model": lookup_model,
+            "rule": rule.to_string(),
+            "primary": plan.primary,
+            "fallbacks": plan.fallbacks,
+            "strategy_id": plan.strategy_id,
+            "policy": serde_json::to_value(&plan.policy).unwrap_or(Value::Null)
+        },
+        "capabilities": {
+            "class": capability.as_str(),
+            "type": config.request_type,
+            "has_web_search": config.inject_google_search,
+            "is_image_gen": config.request_type == "image_gen"
+        },
+        "limits": {
+            "default_max_tokens": limit.and_then(|l| l.default_max_tokens),
+            "max_tokens_cap": limit.and_then(|l| l.max_tokens_cap),
+            "allow_timeout_override": timeout_policy.allow_header_overrides,
+            "max_timeout_secs": timeout_policy.max_timeout_secs
+        },
+        // 目前不按模型限制账号等级，账号池中的所有等级均可服务
+        "tiers": tiers
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::common::model_mapping::{default_thinking_passthrough_prefixes, explain_model_route_plan, ModelRouteConfig};
+    use crate::proxy::config::{ModelDeprecationPolicy, ModelFallbackPolicy};
+    use std::collections::HashMap;
+
+    fn explain(model: &str, custom_mapping: &HashMap<String, String>) -> (ModelRoutePlan, RouteRule) {
+        explain_model_route_plan(
+            model,
+            &ModelRouteConfig {
+                custom_mapping,
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &[],
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &HashMap::new(),
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
+            false,
+        )
+    }
+
+    #[test]
+    fn test_model_detail_for_mapped_model() {
+        let mut custom_mapping = HashMap::new();
+        custom_mapping.insert("my-thinker".to_string(), "claude-sonnet-4-5-thinking".to_string());
+        let (plan, rule) = explain("my-thinker", &custom_mapping);
+        assert_eq!(rule, RouteRule::CustomExact);
+
+        let limit = ModelOutputLimit {
+            default_max_tokens: Some(1024),
+            max_tokens_cap: Some(8192),
+        };
+        let policy = RequestTimeoutPolicy {
+            allow_header_overrides: true,
+            max_timeout_secs: 600,
+        };
+        let detail = build_model_detail(
+            "my-thinker",
+            "my-thinker",
+            &plan,
+            &rule,
+            Some(&limit),
+            &policy,
+            &["PRO".to_string()],
+        );
+
+        assert_eq!(detail["id"], "my-thinker");
+        assert_eq!(detail["object"], "model");
+        assert_eq!(detail["resolution"]["primary"], "claude-sonnet-4-5-thinking");
+        assert_eq!(detail["resolution"]["rule"], "custom (exact)");
+        assert_eq!(detail["resolution"]["fallbacks"], json!([]));
+        assert_eq!(detail["capabilities"]["class"], "thinking");
+        assert_eq!(detail["capabilities"]["is_image_gen"], false);
+        assert_eq!(detail["limits"]["default_max_tokens"], 1024);
+        assert_eq!(detail["limits"]["max_tokens_cap"], 8192);
+        assert_eq!(detail["limits"]["max_timeout_secs"], 600);
+        assert_eq!(detail["tiers"], json!(["PRO"]));
+    }
+
+    #[test]
+    fn test_unknown_model_resolves_to_default_fallback() {
+        let (plan, rule) = explain("totally-unknown-model", &HashMap::new());
+        assert_eq!(rule, RouteRule::DefaultFallback);
+        assert!(!plan.primary.is_empty());
+    }
+}
@@ -1,11 +1,15 @@
 // Gemini Handler
-use axum::{extract::State, extract::{Json, Path}, http::StatusCode, response::IntoResponse};
+use axum::{extract::State, extract::{Json, Path}, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use serde_json::{json, Value};
 use tracing::{debug, error, info};
 
+use crate::proxy::common::error::ProxyError;
+use crate::proxy::common::hedge::{race_with_hedge, HedgeWinner};
 use crate::proxy::mappers::gemini::{wrap_request, unwrap_response};
 use crate::proxy::server::AppState;
 use crate::proxy::session_manager::SessionManager;
+use crate::proxy::upstream::errors::ErrorProtocol;
+use crate::proxy::upstream::retry::{model_unavailable_decision, ModelUnavailableDecision, VariantFallback};
  
 // Increase to allow rotation across larger account pools.
 const MAX_RETRY_ATTEMPTS: usize = 20;
@@ -15,6 +19,7 @@ const MAX_RETRY_ATTEMPTS: usize = 20;
 pub async fn handle_generate(
     State(state): State<AppState>,
     Path(model_action): Path<String>,
+    headers: HeaderMap,
     Json(body): Json<Value>
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // 解析 model:method
@@ -23,6 +28,18 @@ pub async fn handle_generate(
     } else {
         (model_action, "generateContent".to_string())
     };
+    // 路径中省略模型名时使用 Gemini 协议的默认模型
+    let model_name = crate::proxy::common::model_mapping::fill_omitted_path_model(
+        model_name,
+        state.default_model_per_protocol.read().await.for_protocol(ErrorProtocol::Gemini),
+    );
+    let model_name = match crate::proxy::common::model_mapping::require_model_field(&model_name) {
+        Ok(model) => model,
+        Err(e) => return Ok(e.into_protocol_response(ErrorProtocol::Gemini)),
+    };
+    if let Err(e) = crate::proxy::common::model_mapping::check_client_model_allowed(&model_name, &state.allowed_client_models.read().await) {
+        return Ok(e.into_protocol_response(ErrorProtocol::Gemini));
+    }
 
     crate::modules::logger::log_info(&format!("Received Gemini request: {}/{}", model_name, method));
 
@@ -37,6 +54,22 @@ pub async fn handle_generate(
     let token_manager = state.token_manager;
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    let model_unavailable_action = *state.model_unavailable_action.read().await;
+    let strip_variant_suffix = *state.strip_variant_suffix_on_unavailable.read().await;
+
+    // 工具数量上限 (原生请求体的 Schema 清洗在包装时进行，不影响计数)
+    let mut body = body;
+    let tool_limit = state.tool_limit.read().await.clone();
+    let auto_trim_history = state.auto_trim_history.read().await.clone();
+    if let Err(e) = crate::proxy::common::tool_limit::apply_tool_limit(&mut body, &tool_limit) {
+        return Ok(ProxyError::InvalidRequest(e).into_protocol_response(ErrorProtocol::Gemini));
+    }
+    let schema_retry_config = state.schema_retry.read().await.clone();
+    let schema_inline_max_bytes = *state.schema_inline_max_bytes.read().await;
+    let safety_fallback = state.safety_fallback.read().await.clone();
+    let mut schema_retry = crate::proxy::common::schema_retry::SchemaRetry::default();
+    let mut cache_retry = crate::proxy::context_cache::CacheRetry::default();
+    let had_tools = crate::proxy::common::schema_retry::has_function_declarations(&body);
 
     // 提取 tools 列表以进行联网探测 (Gemini 风格可能是嵌套的)
     let tools_val: Option<Vec<Value>> = body.get("tools").and_then(|t| t.as_array()).map(|arr| {
@@ -51,76 +84,277 @@ pub async fn handle_generate(
         flattened
     });
 
-    // 解析模型策略（支持 strategy:<id>）
-    let route_plan = crate::proxy::common::model_mapping::resolve_model_route_plan(
+    // 解析模型策略（支持 strategy:<id>）；路由前先规范化模型名
+    let lookup_model = crate::proxy::common::model_mapping::route_lookup_model(
         &model_name,
         &*state.custom_mapping.read().await,
-        &*state.openai_mapping.read().await,
-        &*state.anthropic_mapping.read().await,
-        &*state.model_strategies.read().await,
-        false, // Gemini 请求不应用 Claude 家族映射
+        &*state.model_canonicalization.read().await,
+        &*state.auto_model.read().await,
     );
+    // 请求头指定的候选链优先于配置解析的结果
+    let header_plan = match crate::proxy::common::candidate_override::header_route_plan(
+        &headers,
+        state.request_timeout_policy.read().await.allow_header_overrides,
+        &state.allowed_client_models.read().await,
+        &*state.custom_mapping.read().await,
+    ) {
+        Ok(plan) => plan,
+        Err(e) => return Ok(e.into_protocol_response(ErrorProtocol::Gemini)),
+    };
+    // 命中策略 A/B 实验时按分组选择策略 (请求头指定的候选链仍然优先)
+    let experiment_plan = match header_plan {
+        Some(_) => None,
+        None => crate::proxy::common::model_mapping::resolve_experiment_plan(
+            &lookup_model,
+            &*state.strategy_experiments.read().await,
+            &*state.model_strategies.read().await,
+        ),
+    };
+    let route_plan = match header_plan.or(experiment_plan) {
+        Some(plan) => plan,
+        None => crate::proxy::common::model_mapping::resolve_model_route_plan(
+            &lookup_model,
+            &crate::proxy::common::model_mapping::ModelRouteConfig {
+                custom_mapping: &*state.custom_mapping.read().await,
+                openai_mapping: &*state.openai_mapping.read().await,
+                openai_family_rules: &state.openai_family_rules.read().await,
+                anthropic_mapping: &*state.anthropic_mapping.read().await,
+                model_strategies: &*state.model_strategies.read().await,
+                deprecation: &*state.model_deprecation.read().await,
+                default_policy: &*state.default_fallback_policy.read().await,
+                thinking_passthrough_prefixes: &state.thinking_passthrough_prefixes.read().await,
+                family_overrides_builtin: *state.family_overrides_builtin.read().await,
+            },
+            crate::proxy::common::model_mapping::effective_family_mapping(
+                false, // Gemini 请求不应用 Claude 家族映射
+                *state.family_mapping_override.read().await,
+            ),
+        ),
+    };
     let mut model_candidates = route_plan.candidates();
     let max_models = route_plan.max_models();
     if model_candidates.is_empty() {
-        model_candidates.push(model_name.clone());
+        model_candidates.push(lookup_model.clone());
     }
     model_candidates.truncate(max_models);
 
     // 提取 SessionId (粘性指纹)
     let session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
+    let output_limits = state.model_output_limits.read().await.clone();
+    let model_transforms = state.model_transforms.read().await.clone();
+    let model_system_prompts = state.model_system_prompts.read().await.clone();
+    let streaming_config = state.streaming.read().await.clone();
+    let keepalive_secs = streaming_config.keepalive_interval_secs;
+    let stream_limits = crate::proxy::common::stream_limits::StreamLimits::from_config(&streaming_config);
+    let reasoning_output = *state.reasoning_output.read().await;
+    let truncation_warning = *state.truncation_warning.read().await;
+    let timeout_override = state.request_timeout_policy.read().await.resolve(&headers);
+    let context_cache_config = state.context_cache.read().await.clone();
+    let context_cache = state.context_cache_handles.clone();
+    let hedge_delay = crate::proxy::common::hedge::hedge_delay(*state.hedge_delay_ms.read().await);
 
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
 
+    // 账号重试与候选回退共用的上游调用预算
+    let mut attempt_budget = crate::proxy::common::attempt_budget::AttemptBudget::new(*state.max_total_attempts.read().await);
+    // 实际尝试过的候选模型数 (用于错误信息，不含因预算耗尽未尝试的候选)
+    let mut models_tried = 0usize;
+
     for (model_index, mapped_model) in model_candidates.iter().enumerate() {
+        if attempt_budget.is_exhausted() {
+            break;
+        }
+        models_tried = model_index + 1;
         let is_last_model = model_index + 1 >= model_candidates.len();
         let mut switched_model = false;
+        // 不支持流式输出的模型：按配置拒绝，或以非流式调用上游后模拟 SSE
+        let emulate_stream = match crate::proxy::common::stream_emulation::check_stream_request(
+            is_stream,
+            mapped_model,
+            &streaming_config,
+        ) {
+            Ok(emulate) => emulate,
+            // 拒绝流式的候选直接跳过，最后一个候选仍拒绝时才返回给客户端
+            Err(e) if !is_last_model => {
+                tracing::info!("[Stream] Skipping candidate {}: {}", mapped_model, e);
+                last_error = e.to_string();
+                continue;
+            }
+            Err(e) => return Ok(e.into_protocol_response(ErrorProtocol::Gemini)),
+        };
+        let upstream_stream = is_stream && !emulate_stream;
 
         // 3. 模型路由与配置解析
         let config = crate::proxy::mappers::common_utils::resolve_request_config(&model_name, mapped_model, &tools_val);
+        let mut variant_fallback = VariantFallback::new(strip_variant_suffix, mapped_model);
+
+        for attempt in 0..max_attempts + variant_fallback.extra_attempts() + schema_retry.extra_attempts() + cache_retry.extra_attempts() {
+            // 额外的尝试只留给同账号的基础模型重试、Schema 严格重试与缓存句柄重试
+            if attempt >= max_attempts && !variant_fallback.is_pending() && !schema_retry.is_pending() && !cache_retry.is_pending() {
+                break;
+            }
+            if !attempt_budget.try_acquire() {
+                break;
+            }
+            schema_retry.begin_attempt();
+            cache_retry.begin_attempt();
+            // 思维链变体在上一个账号不可用：同账号改用基础模型
+            let (pinned_account, base_model) = variant_fallback.take_pinned().unzip();
+            let mapped_model = base_model.as_ref().unwrap_or(mapped_model);
 
-        for attempt in 0..max_attempts {
             // 4. 获取 Token (使用准确的 request_type)
             // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
-            let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id)).await {
-                Ok(t) => t,
-                Err(e) => {
-                    return Err((StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)));
-                }
+            let (access_token, project_id, mut email) = match pinned_account {
+                Some(account) => account,
+                None => match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id)).await {
+                    Ok(t) => t,
+                    Err(e) => {
+                        return Ok(ProxyError::NoAccounts(format!("Token error: {}", e))
+                            .into_protocol_response(ErrorProtocol::Gemini));
+                    }
+                },
             };
 
             last_email = Some(email.clone());
             info!("✓ Using account: {} (type: {})", email, config.request_type);
 
-            // 5. 包装请求 (project injection)
-            let wrapped_body = wrap_request(&body, &project_id, mapped_model);
+            // 账号级上游代理覆盖全局代理
+            let upstream = match upstream.for_account_proxy(token_manager.upstream_proxy_for(&email).as_deref()) {
+                Ok(u) => u,
+                Err(e) => {
+                    last_error = e;
+                    continue;
+                }
+            };
+
+            // 5. 包装请求 (project injection)，对冲请求换号后按新账号的 project 重新包装
+            let schema_mode = schema_retry;
+            let prepare_body = |project_id: &str| {
+                let mut wrapped_body = wrap_request(&body, project_id, mapped_model, schema_inline_max_bytes);
+
+                // 按模型输出上限规范化 maxOutputTokens
+                if let Some(limit) = crate::proxy::mappers::common_utils::resolve_output_limit(mapped_model, &output_limits) {
+                    if let Some(inner) = wrapped_body.get_mut("request").and_then(|v| v.as_object_mut()) {
+                        let gen_config = inner.entry("generationConfig").or_insert_with(|| json!({}));
+                        crate::proxy::mappers::common_utils::apply_output_token_limit(gen_config, "maxOutputTokens", Some(limit), mapped_model);
+                    }
+                }
+
+                // 模型级系统提示词与请求变换
+                crate::proxy::common::system_prompt::inject_gemini(&mut wrapped_body["request"], mapped_model, &model_system_prompts);
+                crate::proxy::common::json_transform::apply_request_transform(mapped_model, &model_transforms, &mut wrapped_body);
+                // 超出上下文窗口时裁剪最早的历史消息
+                crate::proxy::common::history_trim::trim_history(&mut wrapped_body["request"], mapped_model, &auto_trim_history);
+                schema_mode.prepare(&mut wrapped_body["request"]);
+                wrapped_body
+            };
+            let mut wrapped_body = prepare_body(&project_id);
 
         // 5. 上游调用
-        let query_string = if is_stream { Some("alt=sse") } else { None };
-        let upstream_method = if is_stream { "streamGenerateContent" } else { "generateContent" };
+        let query_string = if upstream_stream { Some("alt=sse") } else { None };
+        let upstream_method = if upstream_stream { "streamGenerateContent" } else { "generateContent" };
 
-            let response = match upstream
-                .call_v1_internal(upstream_method, &access_token, wrapped_body, query_string)
-                .await {
-                    Ok(r) => r,
-                    Err(e) => {
-                        last_error = e.clone();
-                        debug!("Gemini Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
-                        continue;
+            // Gemini 上下文缓存：复用稳定的 systemInstruction + tools 前缀
+            // 句柄被拒后的重试不再挂载缓存
+            let cached_content = if cache_retry.bypasses_cache() {
+                None
+            } else {
+                context_cache
+                    .attach(&context_cache_config, &email, &mut wrapped_body, |payload| {
+                        upstream.create_cached_content(&access_token, &project_id, payload)
+                    })
+                    .await
+            };
+
+            let primary = futures::FutureExt::map(
+                upstream.call_v1_internal_with_timeout(upstream_method, &access_token, wrapped_body, query_string, timeout_override),
+                |r| r.map(|resp| (resp, None)),
+            );
+            // 对冲请求：换用其他账号 (不复用上下文缓存，缓存与 project 绑定)
+            let (token_manager_ref, upstream_ref, primary_email, request_type) =
+                (&token_manager, &upstream, email.as_str(), config.request_type.as_str());
+            let prepare_body = &prepare_body;
+            let budget = &mut attempt_budget;
+            let hedge = move || {
+                // 对冲请求同样占用尝试预算，预算已用尽时不再发起
+                let reserved = budget.try_acquire();
+                async move {
+                    if !reserved {
+                        return Err("Attempt budget exhausted, skipping hedged request".to_string());
                     }
-                };
+                    // 在独立的槽位作用域中选号，避免替换主请求占用的槽位
+                    let holder: crate::proxy::token_manager::RequestSlotHolder = Default::default();
+                    let (hedge_token, hedge_project, hedge_email) = crate::proxy::token_manager::REQUEST_ACCOUNT_SLOT
+                        .scope(holder.clone(), token_manager_ref.get_token(request_type, true, None))
+                        .await?;
+                    if hedge_email == primary_email {
+                        return Err("No alternative account for hedged request".to_string());
+                    }
+                    let hedge_upstream = upstream_ref.for_account_proxy(token_manager_ref.upstream_proxy_for(&hedge_email).as_deref())?;
+                    let resp = hedge_upstream
+                        .call_v1_internal_with_timeout(upstream_method, &hedge_token, prepare_body(&hedge_project), query_string, timeout_override)
+                        .await?;
+                    Ok::<_, String>((resp, Some((hedge_email, holder))))
+                }
+            };
+
+            // 只有 2xx 响应才能胜出，失败的一方继续等待另一方
+            let response = match race_with_hedge(primary, hedge_delay, hedge, |(resp, _)| resp.status().is_success()).await {
+                (Ok((r, hedged)), winner) => {
+                    if let (HedgeWinner::Hedge, Some((hedge_email, holder))) = (winner, hedged) {
+                        info!("Hedged request on {} beat primary account {}", hedge_email, email);
+                        // 胜出账号的槽位移入请求作用域，主请求槽位随之释放
+                        let slot = holder.lock().ok().and_then(|mut s| s.take());
+                        let _ = crate::proxy::token_manager::REQUEST_ACCOUNT_SLOT.try_with(|current| {
+                            if let Ok(mut current) = current.lock() {
+                                *current = slot;
+                            }
+                        });
+                        email = hedge_email;
+                        last_email = Some(email.clone());
+                    }
+                    r
+                }
+                (Err(e), _) => {
+                    last_error = e.clone();
+                    debug!("Gemini Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                    continue;
+                }
+            };
 
         let status = response.status();
+
+        // 安全拦截 (SAFETY / RECITATION) 按配置切换到下一个候选
+        let response = if status.is_success() && safety_fallback.enabled && !is_last_model {
+            match crate::proxy::common::safety_fallback::screen_response(response, upstream_stream, &safety_fallback.block_reasons).await {
+                Ok(r) => r,
+                Err(reason) => {
+                    tracing::warn!("Gemini response from {} blocked ({}), advancing to next candidate", mapped_model, reason);
+                    last_error = format!("Response blocked by upstream: {}", reason);
+                    switched_model = true;
+                    break;
+                }
+            }
+        } else {
+            response
+        };
+
             if status.is_success() {
+            state.monitor.record_strategy_serve(route_plan.strategy_id.as_deref(), model_index, mapped_model).await;
+            token_manager.record_canary_success(&email);
+            crate::proxy::common::candidate_stats::record_outcome(route_plan.strategy_id.as_deref(), mapped_model, true);
             // 6. 响应处理
-            if is_stream {
+            if upstream_stream {
                 use axum::body::Body;
                 use axum::response::Response;
                 use bytes::{Bytes, BytesMut};
                 use futures::StreamExt;
                 
-                let mut response_stream = response.bytes_stream();
+                let mut response_stream = crate::proxy::common::json_transform::transform_sse_stream(
+                    Box::pin(response.bytes_stream()),
+                    crate::proxy::common::json_transform::response_ops_for(mapped_model, &model_transforms),
+                );
                 let mut buffer = BytesMut::new();
 
                 let stream = async_stream::stream! {
@@ -176,6 +410,19 @@ pub async fn handle_generate(
                     }
                 };
                 
+                let stream = crate::proxy::common::stream_limits::limit_stream(
+                    crate::proxy::common::keepalive::maybe_keepalive(
+                        crate::proxy::common::reasoning_output::filter_stream(
+                            Box::pin(stream),
+                            reasoning_output,
+                            ErrorProtocol::Gemini,
+                        ),
+                        keepalive_secs,
+                        crate::proxy::common::keepalive::SSE_COMMENT_PING,
+                    ),
+                    stream_limits,
+                    ErrorProtocol::Gemini,
+                );
                 let body = Body::from_stream(stream);
                 return Ok(Response::builder()
                     .header("Content-Type", "text/event-stream")
@@ -188,20 +435,42 @@ pub async fn handle_generate(
                     .into_response());
             }
 
-            let gemini_resp: Value = response
+            let mut gemini_resp: Value = response
                 .json()
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+            crate::proxy::common::json_transform::apply_response_ops(
+                &crate::proxy::common::json_transform::response_ops_for(mapped_model, &model_transforms),
+                &mut gemini_resp,
+            );
 
-            let unwrapped = unwrap_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(unwrapped)).into_response());
+            let mut unwrapped = unwrap_response(&gemini_resp);
+            crate::proxy::common::reasoning_output::apply_to_response(reasoning_output, ErrorProtocol::Gemini, &mut unwrapped);
+            if emulate_stream {
+                let events = crate::proxy::common::stream_emulation::gemini_events(&unwrapped);
+                return Ok(crate::proxy::common::stream_emulation::sse_response(events, &email, mapped_model).into_response());
+            }
+            let mut resp = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(&unwrapped)).into_response();
+            crate::proxy::common::truncation::apply_warning_header(truncation_warning, ErrorProtocol::Gemini, &unwrapped, &mut resp);
+            return Ok(resp);
         }
 
         // 处理错误并重试
         let status_code = status.as_u16();
-        let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
+        let retry_after = crate::proxy::rate_limit::extract_retry_after_secs(response.headers()).map(|s| s.to_string());
+        let content_type = response.headers().get("Content-Type").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
         last_error = format!("HTTP {}: {}", status_code, error_text);
+
+        // 上游拒绝缓存句柄 (过期/已删除) 时丢弃句柄，并不带句柄重试一次；其他错误保留句柄
+        if cache_retry.should_retry(&context_cache, cached_content.as_deref(), status_code, &error_text) {
+            continue;
+        }
+
+        // Schema 类 400：严格清洗工具 Schema 后重试一次，其余 400 直接失败
+        if schema_retry.should_retry(status_code, &error_text, had_tools, &schema_retry_config) {
+            continue;
+        }
  
         // 只有 429 (限流), 529 (过载), 503, 403 (权限) 和 401 (认证失效) 触发账号轮换
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 || status_code == 403 || status_code == 401 {
@@ -216,17 +485,44 @@ pub async fn handle_generate(
             // 只有明确包含 "QUOTA_EXHAUSTED" 才停止，避免误判上游的频率限制提示 (如 "check quota")
             if status_code == 429 && error_text.contains("QUOTA_EXHAUSTED") {
                 error!("Gemini Quota exhausted (429) on account {} attempt {}/{}, stopping to protect pool.", email, attempt + 1, max_attempts);
-                return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+                return Ok(([("X-Account-Email", email.as_str())], crate::proxy::upstream::errors::upstream_error_response(crate::proxy::upstream::errors::ErrorProtocol::Gemini, status, content_type.as_deref(), error_text)).into_response());
             }
 
             tracing::warn!("Gemini Upstream {} on account {} attempt {}/{}, rotating account", status_code, email, attempt + 1, max_attempts);
             continue;
         }
  
-        // 404 等由于模型配置或路径错误的 HTTP 异常，直接报错，不进行无效轮换
+        // 404: 当前账号无权访问该模型，按配置换号或切换候选
+        if status_code == 404 {
+            if variant_fallback.on_unavailable((access_token.clone(), project_id.clone(), email.clone())) {
+                tracing::warn!("Gemini model {} unavailable (404) on account {}, retrying base model on the same account", mapped_model, email);
+                continue;
+            }
+            match model_unavailable_decision(model_unavailable_action, attempt, max_attempts, is_last_model) {
+                ModelUnavailableDecision::RotateAccount => {
+                    tracing::warn!("Gemini model {} unavailable (404) on account {} attempt {}/{}, rotating account", mapped_model, email, attempt + 1, max_attempts);
+                    continue;
+                }
+                ModelUnavailableDecision::NextCandidate => {
+                    tracing::warn!("Gemini model {} unavailable (404) on account {}, advancing to next candidate", mapped_model, email);
+                    switched_model = true;
+                    break;
+                }
+                ModelUnavailableDecision::Fail => {}
+            }
+        }
+
+        // 其他由于模型配置或路径错误的 HTTP 异常，直接报错，不进行无效轮换
         error!("Gemini Upstream non-retryable error {}: {}", status_code, error_text);
-        return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+        if status_code == 404 && crate::proxy::common::error::is_model_not_found(&error_text) {
+            let err = ProxyError::ModelUnknown(format!("Model '{}' is not available upstream", mapped_model));
+            return Ok(([("X-Account-Email", email.as_str())], err.into_protocol_response(ErrorProtocol::Gemini)).into_response());
         }
+        return Ok(([("X-Account-Email", email.as_str())], crate::proxy::upstream::errors::upstream_error_response(crate::proxy::upstream::errors::ErrorProtocol::Gemini, status, content_type.as_deref(), error_text)).into_response());
+        }
+        // 该候选未能服务请求 (成功时已提前返回)
+        crate::proxy::common::candidate_stats::record_outcome(route_plan.strategy_id.as_deref(), mapped_model, false);
+        crate::proxy::middleware::debug_headers::record_fallback(mapped_model);
         if switched_model {
             if route_plan.policy.stickiness == crate::proxy::config::ModelStickiness::Weak {
                 token_manager.clear_session_binding(&session_id);
@@ -250,20 +546,18 @@ pub async fn handle_generate(
         }
     }
 
+    let err = ProxyError::exhausted(models_tried, &last_error);
+    let retry_after_secs = token_manager.min_rate_limit_wait();
     if let Some(email) = last_email {
-        Ok((StatusCode::TOO_MANY_REQUESTS, [("X-Account-Email", email)], format!("All accounts exhausted. Last error: {}", last_error)).into_response())
+        Ok(([("X-Account-Email", email)], err.into_protocol_response_with_retry(ErrorProtocol::Gemini, retry_after_secs)).into_response())
     } else {
-        Ok((StatusCode::TOO_MANY_REQUESTS, format!("All accounts exhausted. Last error: {}", last_error)).into_response())
+        Ok(err.into_protocol_response_with_retry(ErrorProtocol::Gemini, retry_after_secs))
     }
 }
 
 pub async fn handle_list_models(State(state): State<AppState>) -> Result<impl IntoResponse, (StatusCode, String)> {
-    use crate::proxy::common::model_mapping::get_all_dynamic_models;
-
     // 获取所有动态模型列表（与 /v1/models 一致）
-    let model_ids = get_all_dynamic_models(
-        &state.custom_mapping,
-    ).await;
+    let model_ids = super::common::advertised_model_ids(&state, crate::models::AccountProtocol::Gemini).await;
 
     // 转换为 Gemini API 格式
     let models: Vec<_> = model_ids.into_iter().map(|id| {
@@ -1,15 +1,18 @@
 // OpenAI Handler
-use axum::{extract::Json, extract::State, http::StatusCode, response::IntoResponse};
+use axum::{extract::Json, extract::State, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use base64::Engine as _; 
 use bytes::Bytes;
 use serde_json::{json, Value};
 use tracing::{debug, error, info}; // Import Engine trait for encode method
 
 use crate::proxy::mappers::openai::{
-    transform_openai_request, transform_openai_response, OpenAIRequest,
+    gemini_response_to_openai, transform_openai_request, transform_openai_response, OpenAIRequest,
 };
 // use crate::proxy::upstream::client::UpstreamClient; // 通过 state 获取
 use crate::proxy::server::AppState;
+use crate::proxy::common::error::ProxyError;
+use crate::proxy::upstream::errors::ErrorProtocol;
+use crate::proxy::upstream::retry::{model_unavailable_decision, ModelUnavailableDecision, VariantFallback};
 
 // Increase to allow rotation across larger account pools.
 const MAX_RETRY_ATTEMPTS: usize = 20;
@@ -17,10 +20,21 @@ use crate::proxy::session_manager::SessionManager;
 
 pub async fn handle_chat_completions(
     State(state): State<AppState>,
-    Json(body): Json<Value>,
+    headers: HeaderMap,
+    Json(mut body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    // 省略 model 字段时使用 OpenAI 协议的默认模型
+    let default_model = state.default_model_per_protocol.read().await.for_protocol(ErrorProtocol::OpenAI).map(str::to_string);
+    crate::proxy::common::model_mapping::fill_omitted_model(&mut body, default_model.as_deref());
     let mut openai_req: OpenAIRequest = serde_json::from_value(body)
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+    openai_req.model = match crate::proxy::common::model_mapping::require_model_field(&openai_req.model) {
+        Ok(model) => model,
+        Err(e) => return Ok(e.into_protocol_response(ErrorProtocol::OpenAI)),
+    };
+    if let Err(e) = crate::proxy::common::model_mapping::check_client_model_allowed(&openai_req.model, &state.allowed_client_models.read().await) {
+        return Ok(e.into_protocol_response(ErrorProtocol::OpenAI));
+    }
 
     // Safety: Ensure messages is not empty
     if openai_req.messages.is_empty() {
@@ -46,6 +60,8 @@ pub async fn handle_chat_completions(
     let token_manager = state.token_manager;
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    let model_unavailable_action = *state.model_unavailable_action.read().await;
+    let strip_variant_suffix = *state.strip_variant_suffix_on_unavailable.read().await;
 
     // 将 OpenAI 工具转为 Value 数组以便探测联网
     let tools_val: Option<Vec<Value>> = openai_req
@@ -53,58 +69,234 @@ pub async fn handle_chat_completions(
         .as_ref()
         .map(|list| list.iter().cloned().collect());
 
-    // 解析模型策略（支持 strategy:<id>）
-    let route_plan = crate::proxy::common::model_mapping::resolve_model_route_plan(
+    // 解析模型策略（支持 strategy:<id>）；路由前先规范化模型名，原始名称保留用于回显
+    let lookup_model = crate::proxy::common::model_mapping::route_lookup_model(
         &openai_req.model,
         &*state.custom_mapping.read().await,
-        &*state.openai_mapping.read().await,
-        &*state.anthropic_mapping.read().await,
-        &*state.model_strategies.read().await,
-        false, // OpenAI 请求不应用 Claude 家族映射
+        &*state.model_canonicalization.read().await,
+        &*state.auto_model.read().await,
     );
+    // 请求头指定的候选链优先于配置解析的结果
+    let header_plan = match crate::proxy::common::candidate_override::header_route_plan(
+        &headers,
+        state.request_timeout_policy.read().await.allow_header_overrides,
+        &state.allowed_client_models.read().await,
+        &*state.custom_mapping.read().await,
+    ) {
+        Ok(plan) => plan,
+        Err(e) => return Ok(e.into_protocol_response(ErrorProtocol::OpenAI)),
+    };
+    // 命中策略 A/B 实验时按分组选择策略 (请求头指定的候选链仍然优先)
+    let experiment_plan = match header_plan {
+        Some(_) => None,
+        None => crate::proxy::common::model_mapping::resolve_experiment_plan(
+            &lookup_model,
+            &*state.strategy_experiments.read().await,
+            &*state.model_strategies.read().await,
+        ),
+    };
+    let route_plan = match header_plan.or(experiment_plan) {
+        Some(plan) => plan,
+        None => crate::proxy::common::model_mapping::resolve_model_route_plan(
+            &lookup_model,
+            &crate::proxy::common::model_mapping::ModelRouteConfig {
+                custom_mapping: &*state.custom_mapping.read().await,
+                openai_mapping: &*state.openai_mapping.read().await,
+                openai_family_rules: &state.openai_family_rules.read().await,
+                anthropic_mapping: &*state.anthropic_mapping.read().await,
+                model_strategies: &*state.model_strategies.read().await,
+                deprecation: &*state.model_deprecation.read().await,
+                default_policy: &*state.default_fallback_policy.read().await,
+                thinking_passthrough_prefixes: &state.thinking_passthrough_prefixes.read().await,
+                family_overrides_builtin: *state.family_overrides_builtin.read().await,
+            },
+            crate::proxy::common::model_mapping::effective_family_mapping(
+                false, // OpenAI 请求不应用 Claude 家族映射
+                *state.family_mapping_override.read().await,
+            ),
+        ),
+    };
     let mut model_candidates = route_plan.candidates();
     let max_models = route_plan.max_models();
     if model_candidates.is_empty() {
-        model_candidates.push(openai_req.model.clone());
+        model_candidates.push(lookup_model.clone());
     }
     model_candidates.truncate(max_models);
 
     // 提取 SessionId (粘性指纹)
     let session_id = SessionManager::extract_openai_session_id(&openai_req);
+    let output_limits = state.model_output_limits.read().await.clone();
+    let reasoning_effort_budgets = state.reasoning_effort_budgets.read().await.clone();
+    let model_transforms = state.model_transforms.read().await.clone();
+    let model_system_prompts = state.model_system_prompts.read().await.clone();
+    let streaming_config = state.streaming.read().await.clone();
+    let keepalive_secs = streaming_config.keepalive_interval_secs;
+    let stream_limits = crate::proxy::common::stream_limits::StreamLimits::from_config(&streaming_config);
+    let reasoning_output = *state.reasoning_output.read().await;
+    let truncation_warning = *state.truncation_warning.read().await;
+    let tool_limit = state.tool_limit.read().await.clone();
+    let auto_trim_history = state.auto_trim_history.read().await.clone();
+    // 图片 URL 不合法时直接拒绝，而不是丢弃后继续请求
+    if let Err(e) = crate::proxy::mappers::openai::validate_image_urls(&openai_req.messages) {
+        return Ok(ProxyError::InvalidRequest(e).into_protocol_response(ErrorProtocol::OpenAI));
+    }
+    let mut media_cache = crate::proxy::mappers::content_parts::MediaCache::new();
+    let media_policy = crate::proxy::mappers::content_parts::MediaFetchPolicy::from_config(&*state.remote_media.read().await);
+    let schema_retry_config = state.schema_retry.read().await.clone();
+    let schema_inline_max_bytes = *state.schema_inline_max_bytes.read().await;
+    let safety_fallback = state.safety_fallback.read().await.clone();
+    let mut schema_retry = crate::proxy::common::schema_retry::SchemaRetry::default();
+    let mut cache_retry = crate::proxy::context_cache::CacheRetry::default();
+    let timeout_override = state.request_timeout_policy.read().await.resolve(&headers);
+    let context_cache_config = state.context_cache.read().await.clone();
+    let context_cache = state.context_cache_handles.clone();
+    let logprobs_config = state.logprobs.read().await.clone();
+    let requested_logprobs = crate::proxy::common::logprobs::requested_top_logprobs(
+        openai_req.logprobs.as_ref(),
+        openai_req.top_logprobs,
+    );
 
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
 
+    // 账号重试与候选回退共用的上游调用预算
+    let mut attempt_budget = crate::proxy::common::attempt_budget::AttemptBudget::new(*state.max_total_attempts.read().await);
+    // 实际尝试过的候选模型数 (用于错误信息，不含因预算耗尽未尝试的候选)
+    let mut models_tried = 0usize;
+
     for (model_index, mapped_model) in model_candidates.iter().enumerate() {
+        if attempt_budget.is_exhausted() {
+            break;
+        }
+        models_tried = model_index + 1;
         let is_last_model = model_index + 1 >= model_candidates.len();
         let mut switched_model = false;
+        // 不支持流式输出的模型：按配置拒绝，或以非流式调用上游后模拟 SSE
+        let emulate_stream = match crate::proxy::common::stream_emulation::check_stream_request(
+            openai_req.stream,
+            mapped_model,
+            &streaming_config,
+        ) {
+            Ok(emulate) => emulate,
+            // 拒绝流式的候选直接跳过，最后一个候选仍拒绝时才返回给客户端
+            Err(e) if !is_last_model => {
+                tracing::info!("[Stream] Skipping candidate {}: {}", mapped_model, e);
+                last_error = e.to_string();
+                continue;
+            }
+            Err(e) => return Ok(e.into_protocol_response(ErrorProtocol::OpenAI)),
+        };
+        // logprobs：目标模型支持时转换，否则按配置忽略或拒绝
+        let logprobs = match crate::proxy::common::logprobs::resolve_logprobs(
+            requested_logprobs,
+            mapped_model,
+            &logprobs_config,
+        ) {
+            Ok(logprobs) => logprobs,
+            Err(e) => return Ok(e.into_protocol_response(ErrorProtocol::OpenAI)),
+        };
         let config = crate::proxy::mappers::common_utils::resolve_request_config(
             &openai_req.model,
             mapped_model,
             &tools_val,
         );
+        let mut variant_fallback = VariantFallback::new(strip_variant_suffix, mapped_model);
+
+        for attempt in 0..max_attempts + variant_fallback.extra_attempts() + schema_retry.extra_attempts() + cache_retry.extra_attempts() {
+            // 额外的尝试只留给同账号的基础模型重试、Schema 严格重试与缓存句柄重试
+            if attempt >= max_attempts && !variant_fallback.is_pending() && !schema_retry.is_pending() && !cache_retry.is_pending() {
+                break;
+            }
+            if !attempt_budget.try_acquire() {
+                break;
+            }
+            schema_retry.begin_attempt();
+            cache_retry.begin_attempt();
+            // 思维链变体在上一个账号不可用：同账号改用基础模型
+            let (pinned_account, base_model) = variant_fallback.take_pinned().unzip();
+            let mapped_model = base_model.as_ref().unwrap_or(mapped_model);
 
-        for attempt in 0..max_attempts {
             // 4. 获取 Token (使用准确的 request_type)
             // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
-            let (access_token, project_id, email) = match token_manager
-                .get_token(&config.request_type, attempt > 0, Some(&session_id))
-                .await
-            {
-                Ok(t) => t,
-                Err(e) => {
-                    return Err((
-                        StatusCode::SERVICE_UNAVAILABLE,
-                        format!("Token error: {}", e),
-                    ));
-                }
+            let (access_token, project_id, email) = match pinned_account {
+                Some(account) => account,
+                None => match token_manager
+                    .get_token(&config.request_type, attempt > 0, Some(&session_id))
+                    .await
+                {
+                    Ok(t) => t,
+                    Err(e) => {
+                        return Ok(ProxyError::NoAccounts(format!("Token error: {}", e))
+                            .into_protocol_response(ErrorProtocol::OpenAI));
+                    }
+                },
             };
 
             last_email = Some(email.clone());
             info!("✓ Using account: {} (type: {})", email, config.request_type);
 
-            // 5. 转换请求
-            let gemini_body = transform_openai_request(&openai_req, &project_id, mapped_model);
+            // 账号级上游代理覆盖全局代理
+            let upstream = match upstream.for_account_proxy(token_manager.upstream_proxy_for(&email).as_deref()) {
+                Ok(u) => u,
+                Err(e) => {
+                    last_error = e;
+                    continue;
+                }
+            };
+
+            // 5. 转换请求 (先注入模型级系统提示词)
+            let model_req = crate::proxy::common::system_prompt::inject_openai(&openai_req, mapped_model, &model_system_prompts);
+            let mut gemini_body = transform_openai_request(&model_req, &project_id, mapped_model, schema_inline_max_bytes);
+
+            // 推理强度换算为 thinkingBudget
+            crate::proxy::mappers::common_utils::apply_reasoning_effort(
+                &mut gemini_body["request"]["generationConfig"],
+                openai_req.reasoning_effort.as_deref(),
+                mapped_model,
+                &reasoning_effort_budgets,
+            );
+
+            // 按模型输出上限规范化 max_tokens (在推理强度之后，保证不低于 thinkingBudget)
+            if let Some(limit) = crate::proxy::mappers::common_utils::resolve_output_limit(mapped_model, &output_limits) {
+                crate::proxy::mappers::common_utils::clamp_thinking_budget(&mut gemini_body["request"]["generationConfig"], limit, mapped_model);
+                if let Some(v) = crate::proxy::mappers::common_utils::normalize_output_tokens(
+                    openai_req.max_tokens,
+                    limit,
+                    mapped_model,
+                    crate::proxy::mappers::common_utils::thinking_budget(&gemini_body["request"]["generationConfig"]),
+                ) {
+                    gemini_body["request"]["generationConfig"]["maxOutputTokens"] = json!(v);
+                }
+            }
+
+            if let Some(top_logprobs) = logprobs {
+                crate::proxy::common::logprobs::apply_to_generation_config(
+                    &mut gemini_body["request"]["generationConfig"],
+                    top_logprobs,
+                );
+            }
+
+            // 模型级请求变换
+            crate::proxy::common::json_transform::apply_request_transform(mapped_model, &model_transforms, &mut gemini_body);
+
+            // 工具数量上限 (Schema 清洗之后)
+            if let Err(e) = crate::proxy::common::tool_limit::apply_tool_limit(&mut gemini_body["request"], &tool_limit) {
+                return Ok(ProxyError::InvalidRequest(e).into_protocol_response(ErrorProtocol::OpenAI));
+            }
+            // 超出上下文窗口时裁剪最早的历史消息
+            crate::proxy::common::history_trim::trim_history(&mut gemini_body["request"], mapped_model, &auto_trim_history);
+            // 校验媒体类型，按配置下载远程图片
+            if let Err(e) = crate::proxy::mappers::content_parts::resolve_media_parts(
+                &mut gemini_body["request"],
+                &media_policy,
+                &mut media_cache,
+            )
+            .await
+            {
+                return Ok(ProxyError::InvalidRequest(e).into_protocol_response(ErrorProtocol::OpenAI));
+            }
+            schema_retry.prepare(&mut gemini_body["request"]);
+            let had_tools = crate::proxy::common::schema_retry::has_function_declarations(&gemini_body["request"]);
 
             // [New] 打印转换后的报文 (Gemini Body) 供调试
             if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
@@ -113,9 +305,12 @@ pub async fn handle_chat_completions(
 
         // 5. 发送请求 - 自动转换逻辑
         let client_wants_stream = openai_req.stream;
-        // [AUTO-CONVERSION] 非 Stream 请求自动转换为 Stream 以享受更宽松的配额
-        let force_stream_internally = !client_wants_stream;
-        let actual_stream = client_wants_stream || force_stream_internally;
+        // [AUTO-CONVERSION] 非 Stream 请求自动转换为 Stream 以享受更宽松的配额 (不支持流式的模型除外)
+        // 请求了 logprobs 时保持非流式，流式收集器不保留 logprobs
+        let force_stream_internally = !client_wants_stream
+            && logprobs.is_none()
+            && crate::proxy::common::stream_emulation::supports_streaming(mapped_model, &streaming_config);
+        let actual_stream = (client_wants_stream && !emulate_stream) || force_stream_internally;
         
         if force_stream_internally {
             info!("[OpenAI] 🔄 Auto-converting non-stream request to stream for better quota");
@@ -128,8 +323,20 @@ pub async fn handle_chat_completions(
         };
         let query_string = if actual_stream { Some("alt=sse") } else { None };
 
+            // Gemini 上下文缓存：复用稳定的 systemInstruction + tools 前缀
+            // 句柄被拒后的重试不再挂载缓存
+            let cached_content = if cache_retry.bypasses_cache() {
+                None
+            } else {
+                context_cache
+                    .attach(&context_cache_config, &email, &mut gemini_body, |payload| {
+                        upstream.create_cached_content(&access_token, &project_id, payload)
+                    })
+                    .await
+            };
+
             let response = match upstream
-                .call_v1_internal(method, &access_token, gemini_body, query_string)
+                .call_v1_internal_with_timeout(method, &access_token, gemini_body, query_string, timeout_override)
                 .await
             {
                 Ok(r) => r,
@@ -146,20 +353,55 @@ pub async fn handle_chat_completions(
             };
 
         let status = response.status();
+
+        // 安全拦截 (SAFETY / RECITATION) 按配置切换到下一个候选
+        let response = if status.is_success() && safety_fallback.enabled && !is_last_model {
+            match crate::proxy::common::safety_fallback::screen_response(response, actual_stream, &safety_fallback.block_reasons).await {
+                Ok(r) => r,
+                Err(reason) => {
+                    tracing::warn!("OpenAI response from {} blocked ({}), advancing to next candidate", mapped_model, reason);
+                    last_error = format!("Response blocked by upstream: {}", reason);
+                    switched_model = true;
+                    break;
+                }
+            }
+        } else {
+            response
+        };
+
             if status.is_success() {
+            state.monitor.record_strategy_serve(route_plan.strategy_id.as_deref(), model_index, mapped_model).await;
+            token_manager.record_canary_success(&email);
+            crate::proxy::common::candidate_stats::record_outcome(route_plan.strategy_id.as_deref(), mapped_model, true);
             // 5. 处理流式 vs 非流式
             if actual_stream {
                 use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
                 use axum::body::Body;
                 use axum::response::Response;
 
-                let gemini_stream = response.bytes_stream();
+                let gemini_stream = crate::proxy::common::json_transform::transform_sse_stream(
+                    Box::pin(response.bytes_stream()),
+                    crate::proxy::common::json_transform::response_ops_for(mapped_model, &model_transforms),
+                );
                 let openai_stream =
-                    create_openai_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
+                    create_openai_sse_stream(gemini_stream, openai_req.model.clone());
 
                 // 判断客户端期望的格式
                 if client_wants_stream {
                     // 客户端本就要 Stream，直接返回 SSE
+                    let openai_stream = crate::proxy::common::stream_limits::limit_stream(
+                        crate::proxy::common::keepalive::maybe_keepalive(
+                            crate::proxy::common::reasoning_output::filter_stream(
+                                openai_stream,
+                                reasoning_output,
+                                ErrorProtocol::OpenAI,
+                            ),
+                            keepalive_secs,
+                            crate::proxy::common::keepalive::SSE_COMMENT_PING,
+                        ),
+                        stream_limits,
+                        ErrorProtocol::OpenAI,
+                    );
                     let body = Body::from_stream(openai_stream);
                     return Ok(Response::builder()
                         .header("Content-Type", "text/event-stream")
@@ -186,7 +428,11 @@ pub async fn handle_chat_completions(
                     match collect_openai_stream_to_json(sse_stream).await {
                         Ok(full_response) => {
                             info!("[OpenAI] ✓ Stream collected and converted to JSON");
-                            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(full_response)).into_response());
+                            let mut full_response = serde_json::to_value(&full_response).unwrap_or_default();
+                            crate::proxy::common::reasoning_output::apply_to_response(reasoning_output, ErrorProtocol::OpenAI, &mut full_response);
+                            let mut resp = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(&full_response)).into_response();
+                            crate::proxy::common::truncation::apply_warning_header(truncation_warning, ErrorProtocol::OpenAI, &full_response, &mut resp);
+                            return Ok(resp);
                         }
                         Err(e) => {
                             return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)));
@@ -195,21 +441,39 @@ pub async fn handle_chat_completions(
                 }
             }
 
-            let gemini_resp: Value = response
+            let mut gemini_resp: Value = response
                 .json()
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+            crate::proxy::common::json_transform::apply_response_ops(
+                &crate::proxy::common::json_transform::response_ops_for(mapped_model, &model_transforms),
+                &mut gemini_resp,
+            );
 
-            let openai_response = transform_openai_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response());
+            let mut openai_response = gemini_response_to_openai(&gemini_resp)
+                .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+            crate::proxy::common::reasoning_output::apply_to_response(reasoning_output, ErrorProtocol::OpenAI, &mut openai_response);
+            if emulate_stream {
+                let events = crate::proxy::common::stream_emulation::openai_events(&openai_response);
+                return Ok(crate::proxy::common::stream_emulation::sse_response(events, &email, mapped_model));
+            }
+            let mut resp = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(&openai_response)).into_response();
+            crate::proxy::common::truncation::apply_warning_header(truncation_warning, ErrorProtocol::OpenAI, &openai_response, &mut resp);
+            return Ok(resp);
         }
 
         // 处理特定错误并重试
         let status_code = status.as_u16();
-        let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
+        let retry_after = crate::proxy::rate_limit::extract_retry_after_secs(response.headers()).map(|s| s.to_string());
+        let content_type = response.headers().get("Content-Type").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
         last_error = format!("HTTP {}: {}", status_code, error_text);
 
+        // 上游拒绝缓存句柄 (过期/已删除) 时丢弃句柄，并不带句柄重试一次；其他错误保留句柄
+        if cache_retry.should_retry(&context_cache, cached_content.as_deref(), status_code, &error_text) {
+            continue;
+        }
+
         // [New] 打印错误报文日志
         tracing::error!(
             "[OpenAI-Upstream] Error Response {}: {}",
@@ -217,6 +481,11 @@ pub async fn handle_chat_completions(
             error_text
         );
 
+        // Schema 类 400：严格清洗工具 Schema 后重试一次，其余 400 直接失败
+        if schema_retry.should_retry(status_code, &error_text, had_tools, &schema_retry_config) {
+            continue;
+        }
+
         // 429/529/503 智能处理
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
             // 记录限流信息 (全局同步)
@@ -250,7 +519,7 @@ pub async fn handle_chat_completions(
                     attempt + 1,
                     max_attempts
                 );
-                return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+                return Ok(([("X-Account-Email", email.as_str())], crate::proxy::upstream::errors::upstream_error_response(crate::proxy::upstream::errors::ErrorProtocol::OpenAI, status, content_type.as_deref(), error_text)).into_response());
             }
 
             // 3. 其他限流或服务器过载情况，轮换账号
@@ -280,13 +549,52 @@ pub async fn handle_chat_completions(
             continue;
         }
 
-        // 404 等由于模型配置或路径错误的 HTTP 异常，直接报错，不进行无效轮换
+        // 404: 当前账号无权访问该模型，按配置换号或切换候选
+        if status_code == 404 {
+            if variant_fallback.on_unavailable((access_token.clone(), project_id.clone(), email.clone())) {
+                tracing::warn!(
+                    "OpenAI model {} unavailable (404) on account {}, retrying base model on the same account",
+                    mapped_model, email
+                );
+                continue;
+            }
+            match model_unavailable_decision(model_unavailable_action, attempt, max_attempts, is_last_model) {
+                ModelUnavailableDecision::RotateAccount => {
+                    tracing::warn!(
+                        "OpenAI model {} unavailable (404) on account {} attempt {}/{}, rotating account",
+                        mapped_model,
+                        email,
+                        attempt + 1,
+                        max_attempts
+                    );
+                    continue;
+                }
+                ModelUnavailableDecision::NextCandidate => {
+                    tracing::warn!(
+                        "OpenAI model {} unavailable (404) on account {}, advancing to next candidate",
+                        mapped_model, email
+                    );
+                    switched_model = true;
+                    break;
+                }
+                ModelUnavailableDecision::Fail => {}
+            }
+        }
+
+        // 其他由于模型配置或路径错误的 HTTP 异常，直接报错，不进行无效轮换
         error!(
             "OpenAI Upstream non-retryable error {} on account {}: {}",
             status_code, email, error_text
         );
-        return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+        if status_code == 404 && crate::proxy::common::error::is_model_not_found(&error_text) {
+            let err = ProxyError::ModelUnknown(format!("Model '{}' is not available upstream", mapped_model));
+            return Ok(([("X-Account-Email", email.as_str())], err.into_protocol_response(ErrorProtocol::OpenAI)).into_response());
+        }
+        return Ok(([("X-Account-Email", email.as_str())], crate::proxy::upstream::errors::upstream_error_response(crate::proxy::upstream::errors::ErrorProtocol::OpenAI, status, content_type.as_deref(), error_text)).into_response());
         }
+        // 该候选未能服务请求 (成功时已提前返回)
+        crate::proxy::common::candidate_stats::record_outcome(route_plan.strategy_id.as_deref(), mapped_model, false);
+        crate::proxy::middleware::debug_headers::record_fallback(mapped_model);
         if switched_model {
             if route_plan.policy.stickiness == crate::proxy::config::ModelStickiness::Weak {
                 token_manager.clear_session_binding(&session_id);
@@ -311,17 +619,12 @@ pub async fn handle_chat_completions(
     }
 
     // 所有尝试均失败
+    let err = ProxyError::exhausted(models_tried, &last_error);
+    let retry_after_secs = token_manager.min_rate_limit_wait();
     if let Some(email) = last_email {
-        Ok((
-            StatusCode::TOO_MANY_REQUESTS,
-            [("X-Account-Email", email)],
-            format!("All accounts exhausted. Last error: {}", last_error),
-        ).into_response())
+        Ok(([("X-Account-Email", email)], err.into_protocol_response_with_retry(ErrorProtocol::OpenAI, retry_after_secs)).into_response())
     } else {
-        Ok((
-            StatusCode::TOO_MANY_REQUESTS,
-            format!("All accounts exhausted. Last error: {}", last_error),
-        ).into_response())
+        Ok(err.into_protocol_response_with_retry(ErrorProtocol::OpenAI, retry_after_secs))
     }
 }
 
@@ -329,6 +632,7 @@ pub async fn handle_chat_completions(
 /// 将 Prompt 转换为 Chat Message 格式，复用 handle_chat_completions
 pub async fn handle_completions(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(mut body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     info!(
@@ -585,8 +889,17 @@ pub async fn handle_completions(
     // Actually, due to SSE handling differences (Codex uses different event format), we replicate the loop here or abstract it.
     // For now, let's replicate the core loop but with Codex specific SSE mapping.
 
+    let default_model = state.default_model_per_protocol.read().await.for_protocol(ErrorProtocol::OpenAI).map(str::to_string);
+    crate::proxy::common::model_mapping::fill_omitted_model(&mut body, default_model.as_deref());
     let mut openai_req: OpenAIRequest = serde_json::from_value(body.clone())
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+    openai_req.model = match crate::proxy::common::model_mapping::require_model_field(&openai_req.model) {
+        Ok(model) => model,
+        Err(e) => return Ok(e.into_protocol_response(ErrorProtocol::OpenAI)),
+    };
+    if let Err(e) = crate::proxy::common::model_mapping::check_client_model_allowed(&openai_req.model, &state.allowed_client_models.read().await) {
+        return Ok(e.into_protocol_response(ErrorProtocol::OpenAI));
+    }
 
     // Safety: Inject empty message if needed
     if openai_req.messages.is_empty() {
@@ -608,6 +921,21 @@ pub async fn handle_completions(
     let token_manager = state.token_manager;
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    let model_unavailable_action = *state.model_unavailable_action.read().await;
+    let strip_variant_suffix = *state.strip_variant_suffix_on_unavailable.read().await;
+    let truncation_warning = *state.truncation_warning.read().await;
+    let tool_limit = state.tool_limit.read().await.clone();
+    let auto_trim_history = state.auto_trim_history.read().await.clone();
+    // 图片 URL 不合法时直接拒绝，而不是丢弃后继续请求
+    if let Err(e) = crate::proxy::mappers::openai::validate_image_urls(&openai_req.messages) {
+        return Ok(ProxyError::InvalidRequest(e).into_protocol_response(ErrorProtocol::OpenAI));
+    }
+    let mut media_cache = crate::proxy::mappers::content_parts::MediaCache::new();
+    let media_policy = crate::proxy::mappers::content_parts::MediaFetchPolicy::from_config(&*state.remote_media.read().await);
+    let schema_retry_config = state.schema_retry.read().await.clone();
+    let schema_inline_max_bytes = *state.schema_inline_max_bytes.read().await;
+    let safety_fallback = state.safety_fallback.read().await.clone();
+    let mut schema_retry = crate::proxy::common::schema_retry::SchemaRetry::default();
 
     // 将 OpenAI 工具转为 Value 数组以便探测联网
     let tools_val: Option<Vec<Value>> = openai_req
@@ -615,27 +943,82 @@ pub async fn handle_completions(
         .as_ref()
         .map(|list| list.iter().cloned().collect());
 
-    // 解析模型策略（支持 strategy:<id>）
-    let route_plan = crate::proxy::common::model_mapping::resolve_model_route_plan(
+    // 解析模型策略（支持 strategy:<id>）；路由前先规范化模型名，原始名称保留用于回显
+    let lookup_model = crate::proxy::common::model_mapping::route_lookup_model(
         &openai_req.model,
         &*state.custom_mapping.read().await,
-        &*state.openai_mapping.read().await,
-        &*state.anthropic_mapping.read().await,
-        &*state.model_strategies.read().await,
-        false, // OpenAI 请求不应用 Claude 家族映射
+        &*state.model_canonicalization.read().await,
+        &*state.auto_model.read().await,
     );
+    // 请求头指定的候选链优先于配置解析的结果
+    let header_plan = match crate::proxy::common::candidate_override::header_route_plan(
+        &headers,
+        state.request_timeout_policy.read().await.allow_header_overrides,
+        &state.allowed_client_models.read().await,
+        &*state.custom_mapping.read().await,
+    ) {
+        Ok(plan) => plan,
+        Err(e) => return Ok(e.into_protocol_response(ErrorProtocol::OpenAI)),
+    };
+    // 命中策略 A/B 实验时按分组选择策略 (请求头指定的候选链仍然优先)
+    let experiment_plan = match header_plan {
+        Some(_) => None,
+        None => crate::proxy::common::model_mapping::resolve_experiment_plan(
+            &lookup_model,
+            &*state.strategy_experiments.read().await,
+            &*state.model_strategies.read().await,
+        ),
+    };
+    let route_plan = match header_plan.or(experiment_plan) {
+        Some(plan) => plan,
+        None => crate::proxy::common::model_mapping::resolve_model_route_plan(
+            &lookup_model,
+            &crate::proxy::common::model_mapping::ModelRouteConfig {
+                custom_mapping: &*state.custom_mapping.read().await,
+                openai_mapping: &*state.openai_mapping.read().await,
+                openai_family_rules: &state.openai_family_rules.read().await,
+                anthropic_mapping: &*state.anthropic_mapping.read().await,
+                model_strategies: &*state.model_strategies.read().await,
+                deprecation: &*state.model_deprecation.read().await,
+                default_policy: &*state.default_fallback_policy.read().await,
+                thinking_passthrough_prefixes: &state.thinking_passthrough_prefixes.read().await,
+                family_overrides_builtin: *state.family_overrides_builtin.read().await,
+            },
+            crate::proxy::common::model_mapping::effective_family_mapping(
+                false, // OpenAI 请求不应用 Claude 家族映射
+                *state.family_mapping_override.read().await,
+            ),
+        ),
+    };
     let mut model_candidates = route_plan.candidates();
     let max_models = route_plan.max_models();
     if model_candidates.is_empty() {
-        model_candidates.push(openai_req.model.clone());
+        model_candidates.push(lookup_model.clone());
     }
     model_candidates.truncate(max_models);
 
     let session_id = SessionManager::extract_openai_session_id(&openai_req);
+    let output_limits = state.model_output_limits.read().await.clone();
+    let reasoning_effort_budgets = state.reasoning_effort_budgets.read().await.clone();
+    let model_transforms = state.model_transforms.read().await.clone();
+    let model_system_prompts = state.model_system_prompts.read().await.clone();
+    let streaming_config = state.streaming.read().await.clone();
+    let keepalive_secs = streaming_config.keepalive_interval_secs;
+    let stream_limits = crate::proxy::common::stream_limits::StreamLimits::from_config(&streaming_config);
+    let timeout_override = state.request_timeout_policy.read().await.resolve(&headers);
 
     let mut last_error = String::new();
 
+    // 账号重试与候选回退共用的上游调用预算
+    let mut attempt_budget = crate::proxy::common::attempt_budget::AttemptBudget::new(*state.max_total_attempts.read().await);
+    // 实际尝试过的候选模型数 (用于错误信息，不含因预算耗尽未尝试的候选)
+    let mut models_tried = 0usize;
+
     for (model_index, mapped_model) in model_candidates.iter().enumerate() {
+        if attempt_budget.is_exhausted() {
+            break;
+        }
+        models_tried = model_index + 1;
         let is_last_model = model_index + 1 >= model_candidates.len();
         let mut switched_model = false;
         let config = crate::proxy::mappers::common_utils::resolve_request_config(
@@ -643,22 +1026,86 @@ pub async fn handle_completions(
             mapped_model,
             &tools_val,
         );
+        let mut variant_fallback = VariantFallback::new(strip_variant_suffix, mapped_model);
 
-        for attempt in 0..max_attempts {
-            let (access_token, project_id, email) =
-                match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id)).await {
+        for attempt in 0..max_attempts + variant_fallback.extra_attempts() + schema_retry.extra_attempts() {
+            // 额外的尝试只留给同账号的基础模型重试与 Schema 严格重试
+            if attempt >= max_attempts && !variant_fallback.is_pending() && !schema_retry.is_pending() {
+                break;
+            }
+            if !attempt_budget.try_acquire() {
+                break;
+            }
+            schema_retry.begin_attempt();
+            let (pinned_account, base_model) = variant_fallback.take_pinned().unzip();
+            let mapped_model = base_model.as_ref().unwrap_or(mapped_model);
+            let (access_token, project_id, email) = match pinned_account {
+                Some(account) => account,
+                None => match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id)).await {
                     Ok(t) => t,
                     Err(e) => {
-                        return Err((
-                            StatusCode::SERVICE_UNAVAILABLE,
-                            format!("Token error: {}", e),
-                        ))
+                        return Ok(ProxyError::NoAccounts(format!("Token error: {}", e))
+                            .into_protocol_response(ErrorProtocol::OpenAI))
                     }
-                };
+                },
+            };
 
             info!("✓ Using account: {} (type: {})", email, config.request_type);
 
-            let gemini_body = transform_openai_request(&openai_req, &project_id, mapped_model);
+            // 账号级上游代理覆盖全局代理
+            let upstream = match upstream.for_account_proxy(token_manager.upstream_proxy_for(&email).as_deref()) {
+                Ok(u) => u,
+                Err(e) => {
+                    last_error = e;
+                    continue;
+                }
+            };
+
+            let model_req = crate::proxy::common::system_prompt::inject_openai(&openai_req, mapped_model, &model_system_prompts);
+            let mut gemini_body = transform_openai_request(&model_req, &project_id, mapped_model, schema_inline_max_bytes);
+
+            // 推理强度换算为 thinkingBudget
+            crate::proxy::mappers::common_utils::apply_reasoning_effort(
+                &mut gemini_body["request"]["generationConfig"],
+                openai_req.reasoning_effort.as_deref(),
+                mapped_model,
+                &reasoning_effort_budgets,
+            );
+
+            // 按模型输出上限规范化 max_tokens (在推理强度之后，保证不低于 thinkingBudget)
+            if let Some(limit) = crate::proxy::mappers::common_utils::resolve_output_limit(mapped_model, &output_limits) {
+                crate::proxy::mappers::common_utils::clamp_thinking_budget(&mut gemini_body["request"]["generationConfig"], limit, mapped_model);
+                if let Some(v) = crate::proxy::mappers::common_utils::normalize_output_tokens(
+                    openai_req.max_tokens,
+                    limit,
+                    mapped_model,
+                    crate::proxy::mappers::common_utils::thinking_budget(&gemini_body["request"]["generationConfig"]),
+                ) {
+                    gemini_body["request"]["generationConfig"]["maxOutputTokens"] = json!(v);
+                }
+            }
+
+            // 模型级请求变换
+            crate::proxy::common::json_transform::apply_request_transform(mapped_model, &model_transforms, &mut gemini_body);
+
+            // 工具数量上限 (Schema 清洗之后)
+            if let Err(e) = crate::proxy::common::tool_limit::apply_tool_limit(&mut gemini_body["request"], &tool_limit) {
+                return Ok(ProxyError::InvalidRequest(e).into_protocol_response(ErrorProtocol::OpenAI));
+            }
+            // 超出上下文窗口时裁剪最早的历史消息
+            crate::proxy::common::history_trim::trim_history(&mut gemini_body["request"], mapped_model, &auto_trim_history);
+            // 校验媒体类型，按配置下载远程图片
+            if let Err(e) = crate::proxy::mappers::content_parts::resolve_media_parts(
+                &mut gemini_body["request"],
+                &media_policy,
+                &mut media_cache,
+            )
+            .await
+            {
+                return Ok(ProxyError::InvalidRequest(e).into_protocol_response(ErrorProtocol::OpenAI));
+            }
+            schema_retry.prepare(&mut gemini_body["request"]);
+            let had_tools = crate::proxy::common::schema_retry::has_function_declarations(&gemini_body["request"]);
 
         // [New] 打印转换后的报文 (Gemini Body) 供调试 (Codex 路径)
         if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
@@ -674,7 +1121,7 @@ pub async fn handle_completions(
         let query_string = if list_response { Some("alt=sse") } else { None };
 
             let response = match upstream
-                .call_v1_internal(method, &access_token, gemini_body, query_string)
+                .call_v1_internal_with_timeout(method, &access_token, gemini_body, query_string, timeout_override)
                 .await
             {
                 Ok(r) => r,
@@ -685,22 +1132,60 @@ pub async fn handle_completions(
             };
 
         let status = response.status();
+
+        // 安全拦截 (SAFETY / RECITATION) 按配置切换到下一个候选
+        let response = if status.is_success() && safety_fallback.enabled && !is_last_model {
+            match crate::proxy::common::safety_fallback::screen_response(response, list_response, &safety_fallback.block_reasons).await {
+                Ok(r) => r,
+                Err(reason) => {
+                    tracing::warn!("Legacy completions response from {} blocked ({}), advancing to next candidate", mapped_model, reason);
+                    last_error = format!("Response blocked by upstream: {}", reason);
+                    switched_model = true;
+                    break;
+                }
+            }
+        } else {
+            response
+        };
+
             if status.is_success() {
+            state.monitor.record_strategy_serve(route_plan.strategy_id.as_deref(), model_index, mapped_model).await;
+            token_manager.record_canary_success(&email);
+            crate::proxy::common::candidate_stats::record_outcome(route_plan.strategy_id.as_deref(), mapped_model, true);
             if list_response {
                 use axum::body::Body;
                 use axum::response::Response;
 
-                let gemini_stream = response.bytes_stream();
+                let gemini_stream = crate::proxy::common::json_transform::transform_sse_stream(
+                    Box::pin(response.bytes_stream()),
+                    crate::proxy::common::json_transform::response_ops_for(mapped_model, &model_transforms),
+                );
                 let body = if is_codex_style {
                     use crate::proxy::mappers::openai::streaming::create_codex_sse_stream;
                     let s =
-                        create_codex_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
-                    Body::from_stream(s)
+                        create_codex_sse_stream(gemini_stream, openai_req.model.clone());
+                    Body::from_stream(crate::proxy::common::stream_limits::limit_stream(
+                        crate::proxy::common::keepalive::maybe_keepalive(
+                            s,
+                            keepalive_secs,
+                            crate::proxy::common::keepalive::SSE_COMMENT_PING,
+                        ),
+                        stream_limits,
+                        ErrorProtocol::OpenAI,
+                    ))
                 } else {
                     use crate::proxy::mappers::openai::streaming::create_legacy_sse_stream;
                     let s =
-                        create_legacy_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
-                    Body::from_stream(s)
+                        create_legacy_sse_stream(gemini_stream, openai_req.model.clone());
+                    Body::from_stream(crate::proxy::common::stream_limits::limit_stream(
+                        crate::proxy::common::keepalive::maybe_keepalive(
+                            s,
+                            keepalive_secs,
+                            crate::proxy::common::keepalive::SSE_COMMENT_PING,
+                        ),
+                        stream_limits,
+                        ErrorProtocol::OpenAI,
+                    ))
                 };
 
                 return Ok(Response::builder()
@@ -714,10 +1199,14 @@ pub async fn handle_completions(
                     .into_response());
             }
 
-            let gemini_resp: Value = response
+            let mut gemini_resp: Value = response
                 .json()
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+            crate::proxy::common::json_transform::apply_response_ops(
+                &crate::proxy::common::json_transform::response_ops_for(mapped_model, &model_transforms),
+                &mut gemini_resp,
+            );
 
             let chat_resp = transform_openai_response(&gemini_resp);
 
@@ -742,7 +1231,9 @@ pub async fn handle_completions(
                 "choices": choices
             });
 
-            return Ok(axum::Json(legacy_resp).into_response());
+            let mut resp = axum::Json(&legacy_resp).into_response();
+            crate::proxy::common::truncation::apply_warning_header(truncation_warning, ErrorProtocol::OpenAI, &legacy_resp, &mut resp);
+            return Ok(resp);
         }
 
         // Handle errors and retry
@@ -750,6 +1241,10 @@ pub async fn handle_completions(
         let error_text = response.text().await.unwrap_or_default();
         last_error = format!("HTTP {}: {}", status_code, error_text);
 
+        if schema_retry.should_retry(status_code, &error_text, had_tools, &schema_retry_config) {
+            continue;
+        }
+
         if status_code == 429 || status_code == 403 || status_code == 401 {
             if route_plan.is_capacity_first() && !is_last_model {
                 switched_model = true;
@@ -757,8 +1252,33 @@ pub async fn handle_completions(
             }
             continue;
         }
+        if status_code == 404 {
+            if variant_fallback.on_unavailable((access_token.clone(), project_id.clone(), email.clone())) {
+                tracing::warn!("Legacy completions model {} unavailable (404), retrying base model on the same account", mapped_model);
+                continue;
+            }
+            match model_unavailable_decision(model_unavailable_action, attempt, max_attempts, is_last_model) {
+                ModelUnavailableDecision::RotateAccount => {
+                    tracing::warn!("Legacy completions model {} unavailable (404), rotating account", mapped_model);
+                    continue;
+                }
+                ModelUnavailableDecision::NextCandidate => {
+                    tracing::warn!("Legacy completions model {} unavailable (404), advancing to next candidate", mapped_model);
+                    switched_model = true;
+                    break;
+                }
+                ModelUnavailableDecision::Fail => {}
+            }
+            if crate::proxy::common::error::is_model_not_found(&error_text) {
+                return Ok(ProxyError::ModelUnknown(format!("Model '{}' is not available upstream", mapped_model))
+                    .into_protocol_response(ErrorProtocol::OpenAI));
+            }
+        }
         return Err((status, error_text));
         }
+        // 该候选未能服务请求 (成功时已提前返回)
+        crate::proxy::common::candidate_stats::record_outcome(route_plan.strategy_id.as_deref(), mapped_model, false);
+        crate::proxy::middleware::debug_headers::record_fallback(mapped_model);
         if switched_model {
             if route_plan.policy.stickiness == crate::proxy::config::ModelStickiness::Weak {
                 token_manager.clear_session_binding(&session_id);
@@ -782,18 +1302,12 @@ pub async fn handle_completions(
         }
     }
 
-    Err((
-        StatusCode::TOO_MANY_REQUESTS,
-        format!("All attempts failed. Last error: {}", last_error),
-    ))
+    Ok(ProxyError::exhausted(models_tried, &last_error)
+        .into_protocol_response_with_retry(ErrorProtocol::OpenAI, token_manager.min_rate_limit_wait()))
 }
 
 pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
-    use crate::proxy::common::model_mapping::get_all_dynamic_models;
-
-    let model_ids = get_all_dynamic_models(
-        &state.custom_mapping,
-    ).await;
+    let model_ids = super::common::advertised_model_ids(&state, crate::models::AccountProtocol::Openai).await;
 
     let data: Vec<_> = model_ids.into_iter().map(|id| {
         json!({
@@ -826,6 +1340,8 @@ pub async fn handle_images_generations(
         .get("model")
         .and_then(|v| v.as_str())
         .unwrap_or("gemini-3-pro-image");
+    crate::proxy::common::model_mapping::check_client_model_allowed(model, &state.allowed_client_models.read().await)
+        .map_err(|e| (e.status(), e.to_body(ErrorProtocol::OpenAI).to_string()))?;
 
     let n = body.get("n").and_then(|v| v.as_u64()).unwrap_or(1) as usize;
 
@@ -896,6 +1412,10 @@ pub async fn handle_images_generations(
 
     info!("✓ Using account: {} for image generation", email);
 
+    let upstream = upstream
+        .for_account_proxy(token_manager.upstream_proxy_for(&email).as_deref())
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
     // 4. 并发发送请求 (解决 candidateCount > 1 不支持的问题)
     let mut tasks = Vec::new();
 
@@ -1104,6 +1624,8 @@ pub async fn handle_images_edits(
     if image_data.is_none() {
         return Err((StatusCode::BAD_REQUEST, "Missing image".to_string()));
     }
+    crate::proxy::common::model_mapping::check_client_model_allowed(&model, &state.allowed_client_models.read().await)
+        .map_err(|e| (e.status(), e.to_body(ErrorProtocol::OpenAI).to_string()))?;
     if prompt.is_empty() {
         return Err((StatusCode::BAD_REQUEST, "Missing prompt".to_string()));
     }
@@ -1133,7 +1655,7 @@ pub async fn handle_images_edits(
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
     // Fix: Proper get_token call with correct signature and unwrap (using image_gen quota)
-    let (access_token, project_id, _email) = match token_manager.get_token("image_gen", false, None).await
+    let (access_token, project_id, email) = match token_manager.get_token("image_gen", false, None).await
     {
         Ok(t) => t,
         Err(e) => {
@@ -1143,6 +1665,9 @@ pub async fn handle_images_edits(
             ))
         }
     };
+    let upstream = upstream
+        .for_account_proxy(token_manager.upstream_proxy_for(&email).as_deref())
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
 
     // 2. 映射配置
     let mut contents_parts = Vec::new();
@@ -51,7 +51,7 @@ pub async fn handle_warmup(
 
     // ===== 步骤 1: 获取 Token =====
     let (access_token, project_id) = if let (Some(at), Some(pid)) = (&req.access_token, &req.project_id) {
-        (at.clone(), pid.clone())
+        (crate::models::UpstreamAuth::Bearer(at.clone()), pid.clone())
     } else {
         match state.token_manager.get_token_by_email(&req.email).await {
             Ok((at, pid, _)) => (at, pid),
@@ -76,6 +76,7 @@ pub async fn handle_warmup(
     // ===== 步骤 2: 根据模型类型构建请求体 =====
     let is_claude = req.model.to_lowercase().contains("claude");
     let is_image = req.model.to_lowercase().contains("image");
+    let schema_inline_max_bytes = *state.schema_inline_max_bytes.read().await;
 
     let body: Value = if is_claude {
         // Claude 模型：使用 transform_claude_request_in 转换
@@ -99,9 +100,12 @@ pub async fn handle_warmup(
             output_config: None,
         };
 
+        let thinking_passthrough_prefixes = state.thinking_passthrough_prefixes.read().await.clone();
         match crate::proxy::mappers::claude::transform_claude_request_in(
             &claude_request,
             &project_id,
+            &thinking_passthrough_prefixes,
+            schema_inline_max_bytes,
         ) {
             Ok(transformed) => transformed,
             Err(e) => {
@@ -135,7 +139,7 @@ pub async fn handle_warmup(
             })
         };
 
-        wrap_request(&base_request, &project_id, &req.model)
+        wrap_request(&base_request, &project_id, &req.model, schema_inline_max_bytes)
     };
 
     // ===== 步骤 3: 调用 UpstreamClient =====
@@ -148,15 +152,33 @@ pub async fn handle_warmup(
         ("streamGenerateContent", Some("alt=sse"))
     };
 
-    let mut result = state
+    // 账号级上游代理覆盖全局代理
+    let upstream = match state
         .upstream
+        .for_account_proxy(state.token_manager.upstream_proxy_for(&req.email).as_deref())
+    {
+        Ok(u) => u,
+        Err(e) => {
+            warn!("[Warmup-API] Step 3 FAILED: proxy error for {}: {}", req.email, e);
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(WarmupResponse {
+                    success: false,
+                    message: format!("Invalid upstream proxy for {}", req.email),
+                    error: Some(e),
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    let mut result = upstream
         .call_v1_internal(method, &access_token, body.clone(), query)
         .await;
 
     // 如果流式请求失败，尝试非流式请求
     if result.is_err() && !prefer_non_stream {
-        result = state
-            .upstream
+        result = upstream
             .call_v1_internal("generateContent", &access_token, body, None)
             .await;
     }
@@ -188,6 +210,8 @@ pub async fn handle_warmup(
                 response_body: None,
                 input_tokens: None,
                 output_tokens: None,
+                estimated_cost: None,
+                experiment_arm: None,
             };
             state.monitor.log_request(log).await;
             
@@ -238,6 +262,8 @@ pub async fn handle_warmup(
                 response_body: None,
                 input_tokens: None,
                 output_tokens: None,
+                estimated_cost: None,
+                experiment_arm: None,
             };
             state.monitor.log_request(log).await;
             
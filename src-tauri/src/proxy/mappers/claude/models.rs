@@ -140,17 +140,25 @@ pub enum ContentBlock {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageSource {
     #[serde(rename = "type")]
-    pub source_type: String,
+    pub source_type: String, // "base64" | "url"
+    #[serde(default)]
     pub media_type: String,
+    #[serde(default)]
     pub data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DocumentSource {
     #[serde(rename = "type")]
-    pub source_type: String, // "base64"
+    pub source_type: String, // "base64" | "url"
+    #[serde(default)]
     pub media_type: String,  // e.g. "application/pdf"
+    #[serde(default)]
     pub data: String,        // base64 data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
 }
 
 /// Tool - supports both client tools (with input_schema) and server tools (like web_search)
@@ -106,10 +106,32 @@ fn clean_cache_control_from_messages(messages: &mut [Message]) {
     }
 }
 
+/// 校验请求中的图片/文档块 (来源类型、MIME 类型)，不合法时返回错误 (调用方应以 400 拒绝请求)
+pub fn validate_media_blocks(messages: &[Message]) -> Result<(), String> {
+    for msg in messages {
+        let MessageContent::Array(blocks) = &msg.content else {
+            continue;
+        };
+        for block in blocks {
+            let (source_type, media_type, data, url) = match block {
+                ContentBlock::Image { source, .. } => (&source.source_type, &source.media_type, &source.data, &source.url),
+                ContentBlock::Document { source, .. } => (&source.source_type, &source.media_type, &source.data, &source.url),
+                _ => continue,
+            };
+            crate::proxy::mappers::content_parts::anthropic_source_part(source_type, media_type, data, url.as_deref())?;
+        }
+    }
+    Ok(())
+}
+
 /// 转换 Claude 请求为 Gemini v1internal 格式
+/// `thinking_passthrough_prefixes` 为允许 thinking 直通的模型名前缀 (与路由配置一致)，
+/// `schema_inline_limit` 为工具 Schema 展开 $ref 后的体积上限
 pub fn transform_claude_request_in(
     claude_req: &ClaudeRequest,
     project_id: &str,
+    thinking_passthrough_prefixes: &[String],
+    schema_inline_limit: usize,
 ) -> Result<Value, String> {
     // [CRITICAL FIX] 预先清理所有消息中的 cache_control 字段
     // 这解决了 VS Code 插件等客户端在多轮对话中将历史消息的 cache_control 字段
@@ -148,7 +170,7 @@ pub fn transform_claude_request_in(
         );
         WEB_SEARCH_FALLBACK_MODEL.to_string()
     } else {
-        crate::proxy::common::model_mapping::map_claude_model_to_gemini(&claude_req.model)
+        crate::proxy::common::model_mapping::map_claude_model_to_gemini(&claude_req.model, thinking_passthrough_prefixes)
     };
     
     // 将 Claude 工具转为 Value 数组以便探测联网
@@ -280,7 +302,7 @@ pub fn transform_claude_request_in(
     )?;
 
     // 3. Tools
-    let tools = build_tools(&claude_req.tools, has_web_search_tool)?;
+    let tools = build_tools(&claude_req.tools, has_web_search_tool, schema_inline_limit)?;
 
     // 5. Safety Settings (configurable via GEMINI_SAFETY_THRESHOLD env var)
     let safety_settings = build_safety_settings();
@@ -666,23 +688,25 @@ fn build_contents(
                             continue;
                         }
                         ContentBlock::Image { source, .. } => {
-                            if source.source_type == "base64" {
-                                parts.push(json!({
-                                    "inlineData": {
-                                        "mimeType": source.media_type,
-                                        "data": source.data
-                                    }
-                                }));
+                            match crate::proxy::mappers::content_parts::anthropic_source_part(
+                                &source.source_type,
+                                &source.media_type,
+                                &source.data,
+                                source.url.as_deref(),
+                            ) {
+                                Ok(part) => parts.push(part),
+                                Err(e) => tracing::warn!("[Claude-Request] Skipping image block: {}", e),
                             }
                         }
                         ContentBlock::Document { source, .. } => {
-                            if source.source_type == "base64" {
-                                parts.push(json!({
-                                    "inlineData": {
-                                        "mimeType": source.media_type,
-                                        "data": source.data
-                                    }
-                                }));
+                            match crate::proxy::mappers::content_parts::anthropic_source_part(
+                                &source.source_type,
+                                &source.media_type,
+                                &source.data,
+                                source.url.as_deref(),
+                            ) {
+                                Ok(part) => parts.push(part),
+                                Err(e) => tracing::warn!("[Claude-Request] Skipping document block: {}", e),
                             }
                         }
                         ContentBlock::ToolUse { id, name, input, signature, .. } => {
@@ -966,7 +990,11 @@ fn merge_adjacent_roles(mut contents: Vec<Value>) -> Vec<Value> {
 }
 
 /// 构建 Tools
-fn build_tools(tools: &Option<Vec<Tool>>, has_web_search: bool) -> Result<Option<Value>, String> {
+fn build_tools(
+    tools: &Option<Vec<Tool>>,
+    has_web_search: bool,
+    schema_inline_limit: usize,
+) -> Result<Option<Value>, String> {
     if let Some(tools_list) = tools {
         let mut function_declarations: Vec<Value> = Vec::new();
         let mut has_google_search = has_web_search;
@@ -997,7 +1025,7 @@ fn build_tools(tools: &Option<Vec<Tool>>, has_web_search: bool) -> Result<Option
                     "type": "object",
                     "properties": {}
                 }));
-                crate::proxy::common::json_schema::clean_json_schema(&mut input_schema);
+                crate::proxy::common::json_schema::clean_json_schema_with_limit(&mut input_schema, schema_inline_limit);
 
                 function_declarations.push(json!({
                     "name": name,
@@ -1162,6 +1190,8 @@ fn is_model_compatible(cached: &str, target: &str) -> bool {
 mod tests {
     use super::*;
     use crate::proxy::common::json_schema::clean_json_schema;
+    use crate::proxy::common::json_schema::DEFAULT_INLINE_SIZE_LIMIT;
+    use crate::proxy::common::model_mapping::default_thinking_passthrough_prefixes;
 
     #[test]
     fn test_simple_request() {
@@ -1183,7 +1213,12 @@ mod tests {
             output_config: None,
         };
 
-        let result = transform_claude_request_in(&req, "test-project");
+        let result = transform_claude_request_in(
+            &req,
+            "test-project",
+            &default_thinking_passthrough_prefixes(),
+            DEFAULT_INLINE_SIZE_LIMIT,
+        );
         assert!(result.is_ok());
 
         let body = result.unwrap();
@@ -1280,7 +1315,12 @@ mod tests {
             output_config: None,
         };
 
-        let result = transform_claude_request_in(&req, "test-project");
+        let result = transform_claude_request_in(
+            &req,
+            "test-project",
+            &default_thinking_passthrough_prefixes(),
+            DEFAULT_INLINE_SIZE_LIMIT,
+        );
         assert!(result.is_ok());
 
         let body = result.unwrap();
@@ -1332,6 +1372,7 @@ mod tests {
                                 source_type: "base64".to_string(),
                                 media_type: "image/png".to_string(),
                                 data: "iVBORw0KGgo=".to_string(),
+                                url: None,
                             },
                             cache_control: Some(json!({"type": "ephemeral"})), // 这个也应该被清理
                         },
@@ -1350,7 +1391,12 @@ mod tests {
             output_config: None,
         };
 
-        let result = transform_claude_request_in(&req, "test-project");
+        let result = transform_claude_request_in(
+            &req,
+            "test-project",
+            &default_thinking_passthrough_prefixes(),
+            DEFAULT_INLINE_SIZE_LIMIT,
+        );
         assert!(result.is_ok());
 
         // 验证请求成功转换
@@ -1425,7 +1471,12 @@ mod tests {
             output_config: None,
         };
 
-        let result = transform_claude_request_in(&req, "test-project");
+        let result = transform_claude_request_in(
+            &req,
+            "test-project",
+            &default_thinking_passthrough_prefixes(),
+            DEFAULT_INLINE_SIZE_LIMIT,
+        );
         assert!(result.is_ok());
 
         let body = result.unwrap();
@@ -1474,7 +1525,12 @@ mod tests {
             output_config: None,
         };
 
-        let result = transform_claude_request_in(&req, "test-project");
+        let result = transform_claude_request_in(
+            &req,
+            "test-project",
+            &default_thinking_passthrough_prefixes(),
+            DEFAULT_INLINE_SIZE_LIMIT,
+        );
         assert!(result.is_ok());
 
         let body = result.unwrap();
@@ -1527,7 +1583,12 @@ mod tests {
             output_config: None,
         };
 
-        let result = transform_claude_request_in(&req, "test-project");
+        let result = transform_claude_request_in(
+            &req,
+            "test-project",
+            &default_thinking_passthrough_prefixes(),
+            DEFAULT_INLINE_SIZE_LIMIT,
+        );
         assert!(result.is_ok(), "Transformation failed");
         let body = result.unwrap();
         let contents = body["request"]["contents"].as_array().unwrap();
@@ -1567,7 +1628,12 @@ mod tests {
             output_config: None,
         };
 
-        let result = transform_claude_request_in(&req, "test-project");
+        let result = transform_claude_request_in(
+            &req,
+            "test-project",
+            &default_thinking_passthrough_prefixes(),
+            DEFAULT_INLINE_SIZE_LIMIT,
+        );
         assert!(result.is_ok());
         let body = result.unwrap();
         let parts = body["request"]["contents"][0]["parts"].as_array().unwrap();
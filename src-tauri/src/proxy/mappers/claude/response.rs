@@ -3,6 +3,7 @@
 
 use super::models::*;
 use super::utils::to_claude_usage;
+use crate::proxy::mappers::common_utils::anthropic_stop_reason;
 
 /// Known parameter remappings for Gemini → Claude compatibility
 /// [FIX] Gemini sometimes uses different parameter names than specified in tool schema
@@ -367,10 +368,8 @@ impl NonStreamingProcessor {
 
         let stop_reason = if self.has_tool_call {
             "tool_use"
-        } else if finish_reason == Some("MAX_TOKENS") {
-            "max_tokens"
         } else {
-            "end_turn"
+            finish_reason.map_or("end_turn", anthropic_stop_reason)
         };
 
         let usage = gemini_response
@@ -455,6 +454,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_finish_reasons_map_to_stop_reason() {
+        for (gemini, expected) in [
+            ("STOP", "end_turn"),
+            ("MAX_TOKENS", "max_tokens"),
+            ("SAFETY", "refusal"),
+            ("RECITATION", "refusal"),
+        ] {
+            let gemini_resp: GeminiResponse = serde_json::from_value(serde_json::json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": "partial"}]},
+                    "finishReason": gemini
+                }]
+            }))
+            .unwrap();
+            let claude_resp = transform_response(&gemini_resp).unwrap();
+            assert_eq!(claude_resp.stop_reason, expected, "{}", gemini);
+        }
+    }
+
     #[test]
     fn test_thinking_with_signature() {
         let gemini_resp = GeminiResponse {
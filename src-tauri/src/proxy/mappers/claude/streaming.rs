@@ -3,6 +3,7 @@
 
 use super::models::*;
 use super::utils::to_claude_usage;
+use crate::proxy::mappers::common_utils::anthropic_stop_reason;
 use crate::proxy::SignatureCache;
 use crate::proxy::mappers::signature_store::store_thought_signature;
 use bytes::Bytes;
@@ -385,10 +386,8 @@ impl StreamingState {
         // 确定 stop_reason
         let stop_reason = if self.used_tool {
             "tool_use"
-        } else if finish_reason == Some("MAX_TOKENS") {
-            "max_tokens"
         } else {
-            "end_turn"
+            finish_reason.map_or("end_turn", anthropic_stop_reason)
         };
 
         let usage = usage_metadata
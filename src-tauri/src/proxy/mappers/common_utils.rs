@@ -2,6 +2,7 @@
 // Provides unified grounding/networking logic
 
 use serde_json::{json, Value};
+use crate::proxy::config::{ModelOutputLimit, ReasoningEffortBudgets};
 
 /// Request configuration after grounding resolution
 #[derive(Debug, Clone)]
@@ -87,6 +88,199 @@ pub fn resolve_request_config(
     }
 }
 
+/// 查找模型对应的输出 token 限制 (精确匹配优先，其次通配符)
+pub fn resolve_output_limit<'a>(
+    model: &str,
+    limits: &'a std::collections::HashMap<String, ModelOutputLimit>,
+) -> Option<&'a ModelOutputLimit> {
+    resolve_model_entry(model, limits)
+}
+
+/// 按模型名查找配置项：精确匹配优先，其次 * 通配符
+///
+/// 多个通配符同时命中时取最具体的一个：首个 `*` 之前的字面前缀最长者优先，其次 `*` 最少者，
+/// 再其次字面字符总数最多者，仍相同时按键名字典序，保证结果与 HashMap 遍历顺序无关
+pub fn resolve_model_entry<'a, T>(model: &str, entries: &'a std::collections::HashMap<String, T>) -> Option<&'a T> {
+    resolve_model_key_value(model, entries).map(|(_, entry)| entry)
+}
+
+/// 同 [`resolve_model_entry`]，同时返回命中的键 (模型名或通配规则)
+pub fn resolve_model_key_value<'a, T>(
+    model: &str,
+    entries: &'a std::collections::HashMap<String, T>,
+) -> Option<(&'a String, &'a T)> {
+    if let Some(entry) = entries.get_key_value(model) {
+        return Some(entry);
+    }
+    entries
+        .iter()
+        .filter(|(pattern, _)| {
+            pattern.contains('*') && crate::proxy::common::model_mapping::glob_match(pattern, model)
+        })
+        .min_by_key(|(pattern, _)| {
+            let literal_prefix = pattern.find('*').unwrap_or(pattern.len());
+            let stars = pattern.matches('*').count();
+            (
+                std::cmp::Reverse(literal_prefix),
+                stars,
+                std::cmp::Reverse(pattern.len() - stars),
+                pattern.as_str(),
+            )
+        })
+}
+
+/// 将客户端的推理强度 (`reasoning_effort`) 换算为 thinkingBudget 写入 generationConfig
+/// 仅对支持思维链的模型生效 (`-thinking` 模型或已注入 thinkingConfig 的模型)；表中没有的强度使用默认预算
+pub fn apply_reasoning_effort(
+    gen_config: &mut Value,
+    effort: Option<&str>,
+    model: &str,
+    budgets: &std::collections::HashMap<String, ReasoningEffortBudgets>,
+) {
+    let Some(effort) = effort.map(|e| e.trim().to_lowercase()).filter(|e| !e.is_empty()) else {
+        return;
+    };
+    let supports_thinking = crate::proxy::common::model_mapping::model_capability(model)
+        == crate::proxy::common::model_mapping::ModelCapability::Thinking
+        || gen_config.get("thinkingConfig").is_some();
+    if !supports_thinking {
+        tracing::debug!("[Reasoning-Effort] Model {} does not support thinking, ignoring effort '{}'", model, effort);
+        return;
+    }
+    let Some(table) = resolve_model_entry(model, budgets) else {
+        return;
+    };
+
+    let budget = table.efforts.get(&effort).copied().unwrap_or(table.default_budget);
+    if gen_config.is_null() {
+        *gen_config = json!({});
+    }
+    let Some(obj) = gen_config.as_object_mut() else {
+        return;
+    };
+    let thinking = obj
+        .entry("thinkingConfig")
+        .or_insert_with(|| json!({ "includeThoughts": true }));
+    if !thinking.is_object() {
+        *thinking = json!({ "includeThoughts": true });
+    }
+    thinking["thinkingBudget"] = json!(budget);
+    tracing::debug!("[Reasoning-Effort] {} -> thinkingBudget={} (model: {})", effort, budget, model);
+}
+
+/// 思维链预算之外至少为正文输出保留的 token 数
+pub const THINKING_OUTPUT_HEADROOM: u32 = 1024;
+
+/// 读取 generationConfig 中的 thinkingBudget
+pub fn thinking_budget(gen_config: &Value) -> Option<u32> {
+    gen_config
+        .get("thinkingConfig")
+        .and_then(|t| t.get("thinkingBudget"))
+        .and_then(|v| v.as_u64())
+        .map(|v| v.min(u32::MAX as u64) as u32)
+}
+
+/// 上限容纳不下 thinkingBudget + [`THINKING_OUTPUT_HEADROOM`] 时，把 thinkingBudget 下调到
+/// `max_tokens_cap - 余量`，使随后规范化的输出 token 数不会突破上限
+pub fn clamp_thinking_budget(gen_config: &mut Value, limit: &ModelOutputLimit, model: &str) {
+    let (Some(budget), Some(cap)) = (thinking_budget(gen_config), limit.max_tokens_cap) else {
+        return;
+    };
+    let max_budget = cap.saturating_sub(THINKING_OUTPUT_HEADROOM);
+    if budget > max_budget {
+        tracing::warn!(
+            "[Output-Limit] Clamping thinkingBudget {} -> {} to fit max_tokens cap {} for {}",
+            budget,
+            max_budget,
+            cap,
+            model
+        );
+        gen_config["thinkingConfig"]["thinkingBudget"] = json!(max_budget);
+    }
+}
+
+/// 按模型限制规范化输出 token 数
+/// - 客户端未指定: 注入配置的默认值
+/// - 超过上限: 截断到上限
+/// - 开启思维链时: 结果不低于 thinkingBudget + [`THINKING_OUTPUT_HEADROOM`]，否则上游拒绝请求或没有正文输出；
+///   但始终不超过上限 (预算过大时先用 [`clamp_thinking_budget`] 下调预算)
+pub fn normalize_output_tokens(
+    requested: Option<u32>,
+    limit: &ModelOutputLimit,
+    model: &str,
+    thinking_budget: Option<u32>,
+) -> Option<u32> {
+    let value = match requested {
+        Some(v) => Some(v),
+        None => {
+            if let Some(default) = limit.default_max_tokens {
+                tracing::debug!(
+                    "[Output-Limit] Injecting default max_tokens {} for {}",
+                    default,
+                    model
+                );
+            }
+            limit.default_max_tokens
+        }
+    };
+
+    let value = match (value, limit.max_tokens_cap) {
+        (Some(v), Some(cap)) if v > cap => {
+            tracing::warn!(
+                "[Output-Limit] Clamping max_tokens {} -> {} for {}",
+                v,
+                cap,
+                model
+            );
+            Some(cap)
+        }
+        (v, _) => v,
+    };
+
+    match (value, thinking_budget) {
+        (Some(v), Some(budget)) if v < budget.saturating_add(THINKING_OUTPUT_HEADROOM) => {
+            let floor = budget.saturating_add(THINKING_OUTPUT_HEADROOM);
+            tracing::warn!(
+                "[Output-Limit] Raising max_tokens {} -> {} to fit thinkingBudget {} for {}",
+                v,
+                floor,
+                budget,
+                model
+            );
+            Some(limit.max_tokens_cap.map_or(floor, |cap| floor.min(cap)))
+        }
+        (v, _) => v,
+    }
+}
+
+/// 对 JSON 对象中的输出 token 字段应用限制
+/// `key` 为 `max_tokens` (OpenAI/Anthropic) 或 `maxOutputTokens` (Gemini)
+pub fn apply_output_token_limit(
+    container: &mut Value,
+    key: &str,
+    limit: Option<&ModelOutputLimit>,
+    model: &str,
+) {
+    let Some(limit) = limit else {
+        return;
+    };
+
+    let requested = container
+        .get(key)
+        .and_then(|v| v.as_u64())
+        .map(|v| v.min(u32::MAX as u64) as u32);
+    clamp_thinking_budget(container, limit, model);
+
+    if let Some(value) = normalize_output_tokens(requested, limit, model, thinking_budget(container)) {
+        if container.is_null() {
+            *container = json!({});
+        }
+        if let Some(obj) = container.as_object_mut() {
+            obj.insert(key.to_string(), json!(value));
+        }
+    }
+}
+
 /// Parse image configuration from model name suffixes
 /// Returns (image_config, clean_model_name)
 fn parse_image_config(model_name: &str) -> (Value, String) {
@@ -263,10 +457,53 @@ pub fn contains_non_networking_tool(tools: &Option<Vec<Value>>) -> bool {
     false
 }
 
+/// 因内容审核被上游中止的 finishReason
+const CONTENT_FILTER_REASONS: [&str; 6] = [
+    "SAFETY",
+    "RECITATION",
+    "BLOCKLIST",
+    "PROHIBITED_CONTENT",
+    "SPII",
+    "IMAGE_SAFETY",
+];
+
+/// Gemini finishReason -> OpenAI finish_reason
+pub fn openai_finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "MAX_TOKENS" => "length",
+        r if CONTENT_FILTER_REASONS.contains(&r) => "content_filter",
+        _ => "stop",
+    }
+}
+
+/// Gemini finishReason -> Anthropic stop_reason
+pub fn anthropic_stop_reason(reason: &str) -> &'static str {
+    match reason {
+        "MAX_TOKENS" => "max_tokens",
+        r if CONTENT_FILTER_REASONS.contains(&r) => "refusal",
+        _ => "end_turn",
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_finish_reason_mapping() {
+        let cases = [
+            ("STOP", "stop", "end_turn"),
+            ("MAX_TOKENS", "length", "max_tokens"),
+            ("SAFETY", "content_filter", "refusal"),
+            ("RECITATION", "content_filter", "refusal"),
+            ("FINISH_REASON_UNSPECIFIED", "stop", "end_turn"),
+        ];
+        for (gemini, openai, anthropic) in cases {
+            assert_eq!(openai_finish_reason(gemini), openai, "{}", gemini);
+            assert_eq!(anthropic_stop_reason(gemini), anthropic, "{}", gemini);
+        }
+    }
+
     #[test]
     fn test_high_quality_model_auto_grounding() {
         // Auto-grounding is currently disabled by default due to conflict with image gen
@@ -307,6 +544,160 @@ mod tests {
         assert!(!config.inject_google_search);
     }
 
+    #[test]
+    fn test_output_limit_injects_default_on_omit() {
+        let limit = ModelOutputLimit {
+            default_max_tokens: Some(8192),
+            max_tokens_cap: Some(16384),
+        };
+        assert_eq!(normalize_output_tokens(None, &limit, "gemini-3-flash", None), Some(8192));
+
+        let mut body = json!({ "model": "gpt-4o" });
+        apply_output_token_limit(&mut body, "max_tokens", Some(&limit), "gemini-3-flash");
+        assert_eq!(body["max_tokens"], 8192);
+
+        // Gemini: generationConfig 缺失时同样注入
+        let mut gen_config = Value::Null;
+        apply_output_token_limit(&mut gen_config, "maxOutputTokens", Some(&limit), "gemini-3-flash");
+        assert_eq!(gen_config["maxOutputTokens"], 8192);
+    }
+
+    #[test]
+    fn test_output_limit_clamps_on_exceed() {
+        let limit = ModelOutputLimit {
+            default_max_tokens: None,
+            max_tokens_cap: Some(8192),
+        };
+        assert_eq!(normalize_output_tokens(Some(64000), &limit, "gemini-2.5-flash", None), Some(8192));
+        assert_eq!(normalize_output_tokens(Some(1024), &limit, "gemini-2.5-flash", None), Some(1024));
+        assert_eq!(normalize_output_tokens(None, &limit, "gemini-2.5-flash", None), None);
+
+        let mut gen_config = json!({ "maxOutputTokens": 64000, "temperature": 0.5 });
+        apply_output_token_limit(&mut gen_config, "maxOutputTokens", Some(&limit), "gemini-2.5-flash");
+        assert_eq!(gen_config["maxOutputTokens"], 8192);
+        assert_eq!(gen_config["temperature"], 0.5);
+    }
+
+    #[test]
+    fn test_output_limit_keeps_room_for_thinking_budget() {
+        let limit = ModelOutputLimit {
+            default_max_tokens: Some(4096),
+            max_tokens_cap: Some(8192),
+        };
+        // 请求/注入的值低于思维链预算时抬高到预算 + 余量
+        assert_eq!(
+            normalize_output_tokens(Some(2048), &limit, "claude-opus-4-5-thinking", Some(4096)),
+            Some(4096 + THINKING_OUTPUT_HEADROOM)
+        );
+        assert_eq!(
+            normalize_output_tokens(None, &limit, "claude-opus-4-5-thinking", Some(6000)),
+            Some(6000 + THINKING_OUTPUT_HEADROOM)
+        );
+        // 余量充足时保持截断结果
+        assert_eq!(normalize_output_tokens(Some(64000), &limit, "claude-opus-4-5-thinking", Some(1024)), Some(8192));
+
+        let limit = ModelOutputLimit { max_tokens_cap: None, ..limit };
+        let mut gen_config = json!({ "maxOutputTokens": 4096, "thinkingConfig": { "includeThoughts": true, "thinkingBudget": 16000 } });
+        apply_output_token_limit(&mut gen_config, "maxOutputTokens", Some(&limit), "gemini-2.5-pro");
+        assert_eq!(gen_config["maxOutputTokens"], 16000 + THINKING_OUTPUT_HEADROOM);
+    }
+
+    #[test]
+    fn test_thinking_budget_above_cap_is_clamped() {
+        let limit = ModelOutputLimit {
+            default_max_tokens: None,
+            max_tokens_cap: Some(8192),
+        };
+        for budget in [8192, 7600, 32000] {
+            let mut gen_config = json!({ "maxOutputTokens": 64000, "thinkingConfig": { "includeThoughts": true, "thinkingBudget": budget } });
+            apply_output_token_limit(&mut gen_config, "maxOutputTokens", Some(&limit), "gemini-2.5-pro");
+            // 预算下调到上限减余量，输出上限恰好等于上限
+            assert_eq!(gen_config["thinkingConfig"]["thinkingBudget"], 8192 - THINKING_OUTPUT_HEADROOM);
+            assert_eq!(gen_config["maxOutputTokens"], 8192);
+        }
+        // 未下调预算时规范化结果也不超过上限
+        assert_eq!(normalize_output_tokens(Some(64000), &limit, "gemini-2.5-pro", Some(16000)), Some(8192));
+
+        // 预算本身在上限内时不做改动
+        let mut gen_config = json!({ "thinkingConfig": { "thinkingBudget": 4096 } });
+        clamp_thinking_budget(&mut gen_config, &limit, "gemini-2.5-pro");
+        assert_eq!(gen_config["thinkingConfig"]["thinkingBudget"], 4096);
+    }
+
+    fn effort_budgets() -> std::collections::HashMap<String, ReasoningEffortBudgets> {
+        let efforts = [("low".to_string(), 1024), ("high".to_string(), 32000)].into_iter().collect();
+        std::collections::HashMap::from([(
+            "*".to_string(),
+            ReasoningEffortBudgets { efforts, default_budget: 4096 },
+        )])
+    }
+
+    #[test]
+    fn test_reasoning_effort_maps_to_budget_for_thinking_model() {
+        let mut gen_config = json!({ "maxOutputTokens": 8192 });
+        apply_reasoning_effort(&mut gen_config, Some("high"), "claude-opus-4-5-thinking", &effort_budgets());
+        assert_eq!(gen_config["thinkingConfig"]["thinkingBudget"], 32000);
+        assert_eq!(gen_config["thinkingConfig"]["includeThoughts"], true);
+
+        // 已注入的 thinkingConfig 只覆盖预算；未知强度回落到默认值
+        let mut gen_config = json!({ "thinkingConfig": { "includeThoughts": true, "thinkingBudget": 16000 } });
+        apply_reasoning_effort(&mut gen_config, Some("xhigh"), "gemini-3-pro-high", &effort_budgets());
+        assert_eq!(gen_config["thinkingConfig"]["thinkingBudget"], 4096);
+    }
+
+    #[test]
+    fn test_reasoning_effort_ignored_for_non_thinking_model() {
+        let mut gen_config = json!({ "maxOutputTokens": 8192 });
+        apply_reasoning_effort(&mut gen_config, Some("high"), "gemini-2.5-flash", &effort_budgets());
+        assert!(gen_config.get("thinkingConfig").is_none());
+    }
+
+    #[test]
+    fn test_resolve_output_limit_wildcard() {
+        let mut limits = std::collections::HashMap::new();
+        limits.insert(
+            "gemini-2.5-*".to_string(),
+            ModelOutputLimit { default_max_tokens: Some(4096), max_tokens_cap: None },
+        );
+        limits.insert(
+            "gemini-2.5-flash".to_string(),
+            ModelOutputLimit { default_max_tokens: Some(8192), max_tokens_cap: None },
+        );
+        assert_eq!(resolve_output_limit("gemini-2.5-flash", &limits).unwrap().default_max_tokens, Some(8192));
+        assert_eq!(resolve_output_limit("gemini-2.5-pro", &limits).unwrap().default_max_tokens, Some(4096));
+        assert!(resolve_output_limit("gemini-3-flash", &limits).is_none());
+    }
+
+    #[test]
+    fn test_resolve_model_entry_prefers_most_specific_wildcard() {
+        let entries: std::collections::HashMap<String, &str> = [
+            ("*", "any"),
+            ("gemini-*", "gemini"),
+            ("gemini-2.5-*", "gemini-2.5"),
+            ("gemini-2.5-*-*", "gemini-2.5-two-stars"),
+            ("*-flash", "flash-a"),
+            ("*flash", "flash-b"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        // 字面前缀最长者优先，其次 * 最少者
+        assert_eq!(resolve_model_entry("gemini-2.5-flash-lite", &entries), Some(&"gemini-2.5"));
+        assert_eq!(resolve_model_entry("gemini-3-flash", &entries), Some(&"gemini"));
+        // 前缀与 * 数量相同时字面字符多者优先，再按键名字典序
+        assert_eq!(resolve_model_entry("gpt-4o-flash", &entries), Some(&"flash-a"));
+        assert_eq!(resolve_model_entry("claude-opus-4-5", &entries), Some(&"any"));
+
+        let entries: std::collections::HashMap<String, &str> =
+            [("gpt-*-mini", "a"), ("gpt-*-min*", "b"), ("gpt-4*", "c")]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect();
+        assert_eq!(resolve_model_entry("gpt-4o-mini", &entries), Some(&"c"));
+        assert_eq!(resolve_model_entry("gpt-5-mini", &entries), Some(&"a"));
+    }
+
     #[test]
     fn test_image_2k_and_ultrawide_config() {
         // Test 2K
@@ -0,0 +1,469 @@
+// 多模态内容块转换
+// 将 OpenAI `image_url` (base64 data URL / 远程 URL) 与 Anthropic 图片/文档 `source` 转为 Gemini 的 inlineData / fileData 部件。
+// 远程 URL 在协议转换时先以 fileData 占位；启用 `remote_media` 后，发送上游前由 `resolve_media_parts`
+// 下载为 inlineData (带大小上限，拒绝内网地址与重定向，防止客户端借代理访问内网)，未启用时原样交给上游。
+// 同时统一校验 MIME 类型，不支持的类型直接拒绝而不是交给上游返回含糊的 400。
+use base64::Engine as _;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::proxy::config::RemoteMediaConfig;
+
+/// 远程媒体下载大小上限 (与 Gemini 单请求内联数据上限一致)
+pub const MAX_REMOTE_MEDIA_BYTES: usize = 20 * 1024 * 1024;
+
+/// Gemini 接受的图片与文档类型；音频/视频按前缀放行
+const SUPPORTED_MIME_TYPES: [&str; 8] = [
+    "image/png",
+    "image/jpeg",
+    "image/webp",
+    "image/heic",
+    "image/heif",
+    "image/gif",
+    "application/pdf",
+    "text/plain",
+];
+
+/// 规范化 MIME 类型 (去掉参数并转小写，`image/jpg` 视为 `image/jpeg`)
+fn normalize_mime(mime: &str) -> String {
+    let mime = mime.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    if mime == "image/jpg" {
+        "image/jpeg".to_string()
+    } else {
+        mime
+    }
+}
+
+/// 校验 MIME 类型是否可作为 Gemini 内联数据
+pub fn validate_mime_type(mime: &str) -> Result<(), String> {
+    let mime = normalize_mime(mime);
+    if SUPPORTED_MIME_TYPES.contains(&mime.as_str())
+        || mime.starts_with("audio/")
+        || mime.starts_with("video/")
+    {
+        return Ok(());
+    }
+    Err(format!(
+        "Unsupported media type '{}' (supported: {}, audio/*, video/*)",
+        mime,
+        SUPPORTED_MIME_TYPES.join(", ")
+    ))
+}
+
+/// 按 URL 路径的扩展名推断 MIME 类型
+fn guess_mime_from_path(path: &str) -> Option<&'static str> {
+    let path = path.split(['?', '#']).next().unwrap_or(path).to_ascii_lowercase();
+    let ext = path.rsplit('.').next()?;
+    Some(match ext {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        "heic" => "image/heic",
+        "heif" => "image/heif",
+        "pdf" => "application/pdf",
+        _ => return None,
+    })
+}
+
+fn inline_part(mime: &str, data: &str) -> Value {
+    json!({ "inlineData": { "mimeType": normalize_mime(mime), "data": data } })
+}
+
+/// 远程 URL 的占位部件，`resolve_media_parts` 会将其下载为 inlineData
+fn remote_part(url: &str, mime: Option<&str>) -> Value {
+    let mime = mime
+        .map(normalize_mime)
+        .or_else(|| guess_mime_from_path(url).map(str::to_string))
+        .unwrap_or_else(|| "image/jpeg".to_string());
+    json!({ "fileData": { "fileUri": url, "mimeType": mime } })
+}
+
+fn is_remote_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// 解析 base64 data URL (`data:<mime>;base64,<data>`)
+pub fn data_url_part(url: &str) -> Result<Value, String> {
+    let rest = url.strip_prefix("data:").ok_or_else(|| "Not a data URL".to_string())?;
+    let (meta, data) = rest.split_once(',').ok_or_else(|| "Malformed data URL: missing ','".to_string())?;
+    let mut params = meta.split(';');
+    let mime = params.next().unwrap_or("").trim();
+    if mime.is_empty() {
+        return Err("Malformed data URL: missing media type".to_string());
+    }
+    if !params.any(|p| p.trim().eq_ignore_ascii_case("base64")) {
+        return Err("Only base64-encoded data URLs are supported".to_string());
+    }
+    if data.is_empty() {
+        return Err("Malformed data URL: empty data".to_string());
+    }
+    Ok(inline_part(mime, data))
+}
+
+/// OpenAI `image_url.url` -> Gemini 部件 (data URL 或远程 URL)
+pub fn image_url_part(url: &str) -> Result<Value, String> {
+    if url.starts_with("data:") {
+        data_url_part(url)
+    } else if is_remote_url(url) {
+        Ok(remote_part(url, None))
+    } else {
+        Err(format!("Unsupported image URL scheme: {}", url.split(':').next().unwrap_or(url)))
+    }
+}
+
+/// Anthropic 图片/文档 `source` -> Gemini 部件
+/// - `base64`: `{media_type, data}`
+/// - `url`: `{url}`，与 OpenAI 远程 URL 同样处理
+pub fn anthropic_source_part(
+    source_type: &str,
+    media_type: &str,
+    data: &str,
+    url: Option<&str>,
+) -> Result<Value, String> {
+    match source_type {
+        "base64" => {
+            if media_type.is_empty() || data.is_empty() {
+                return Err("base64 source requires media_type and data".to_string());
+            }
+            Ok(inline_part(media_type, data))
+        }
+        "url" => match url {
+            Some(url) if is_remote_url(url) => {
+                Ok(remote_part(url, Some(media_type).filter(|m| !m.is_empty())))
+            }
+            _ => Err("url source requires an http(s) url".to_string()),
+        },
+        other => Err(format!("Unsupported content source type: {}", other)),
+    }
+}
+
+/// 远程媒体下载策略
+#[derive(Debug, Clone)]
+pub struct MediaFetchPolicy {
+    /// 为 false 时远程 URL 保持 fileData 原样交给上游
+    pub enabled: bool,
+    /// 允许下载的主机 (精确匹配或 `*.example.com`)；为空时允许任意公网主机
+    pub allowed_hosts: Vec<String>,
+    /// 拒绝解析到回环/内网/链路本地等地址的主机
+    pub block_private_networks: bool,
+    pub max_bytes: usize,
+}
+
+impl MediaFetchPolicy {
+    pub fn from_config(config: &RemoteMediaConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            allowed_hosts: config.allowed_hosts.clone(),
+            block_private_networks: true,
+            max_bytes: MAX_REMOTE_MEDIA_BYTES,
+        }
+    }
+
+    fn host_allowed(&self, host: &str) -> bool {
+        if self.allowed_hosts.is_empty() {
+            return true;
+        }
+        let host = host.to_ascii_lowercase();
+        self.allowed_hosts.iter().any(|pattern| {
+            let pattern = pattern.trim().to_ascii_lowercase();
+            match pattern.strip_prefix("*.") {
+                Some(suffix) => host.ends_with(&format!(".{}", suffix)),
+                None => host == pattern,
+            }
+        })
+    }
+}
+
+/// 回环、私有、链路本地、CGNAT、组播等不应由代理代为访问的地址
+fn is_private_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_private_address(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+/// 下载前校验 URL：主机须在允许列表内，且 (启用时) 不得解析到内网地址
+/// 返回校验通过的解析结果，下载时固定连接这些地址，避免二次解析被 DNS rebinding 绕过
+async fn check_fetch_target(
+    url: &str,
+    policy: &MediaFetchPolicy,
+) -> Result<Option<(String, Vec<SocketAddr>)>, String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid media URL {}: {}", url, e))?;
+    let host = parsed.host_str().ok_or_else(|| format!("Media URL has no host: {}", url))?;
+    if !policy.host_allowed(host) {
+        return Err(format!("Media host '{}' is not in remote_media.allowed_hosts", host));
+    }
+    if !policy.block_private_networks {
+        return Ok(None);
+    }
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    let (pinned_host, addrs) = match host.parse::<IpAddr>() {
+        Ok(ip) => (None, vec![SocketAddr::new(ip, port)]),
+        Err(_) => (
+            Some(host.to_string()),
+            tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|e| format!("Failed to resolve media host '{}': {}", host, e))?
+                .collect::<Vec<_>>(),
+        ),
+    };
+    if addrs.is_empty() || addrs.iter().any(|addr| is_private_address(addr.ip())) {
+        return Err(format!("Media host '{}' resolves to a private or local address", host));
+    }
+    Ok(pinned_host.map(|host| (host, addrs)))
+}
+
+/// 下载远程媒体为 inlineData，超过 `max_bytes` 时中止
+async fn fetch_inline_part(
+    url: &str,
+    fallback_mime: &str,
+    policy: &MediaFetchPolicy,
+) -> Result<Value, String> {
+    let pinned = check_fetch_target(url, policy).await?;
+    let max_bytes = policy.max_bytes;
+    // 直连 (不走系统代理，否则地址校验失去意义) 且不跟随重定向 (重定向目标无法预先校验)
+    let mut builder = reqwest::Client::builder()
+        .no_proxy()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(std::time::Duration::from_secs(30));
+    if let Some((host, addrs)) = pinned {
+        builder = builder.resolve_to_addrs(&host, &addrs);
+    }
+    let client = builder
+        .build()
+        .map_err(|e| format!("Failed to create media HTTP client: {}", e))?;
+    let resp = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch media {}: {}", url, e))?;
+    if !resp.status().is_success() {
+        return Err(format!("Failed to fetch media {}: HTTP {}", url, resp.status()));
+    }
+    if resp.content_length().is_some_and(|len| len as usize > max_bytes) {
+        return Err(format!("Media {} exceeds the {} byte limit", url, max_bytes));
+    }
+    // 以响应头为准；通用二进制类型时退回到占位部件上的类型
+    let mime = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(normalize_mime)
+        .filter(|m| !m.is_empty() && m != "application/octet-stream")
+        .unwrap_or_else(|| fallback_mime.to_string());
+    validate_mime_type(&mime)?;
+
+    let mut bytes = Vec::new();
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| format!("Failed to read media {}: {}", url, e))?;
+        if bytes.len() + chunk.len() > max_bytes {
+            return Err(format!("Media {} exceeds the {} byte limit", url, max_bytes));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let data = base64::engine::general_purpose::STANDARD.encode(&bytes);
+    Ok(inline_part(&mime, &data))
+}
+
+/// 已下载的远程媒体 (URL -> inlineData 部件)，同一请求换号/回退重试时复用
+pub type MediaCache = std::collections::HashMap<String, Value>;
+
+/// 处理 Gemini 请求 (`request` 层) 中的媒体部件：校验 inlineData 类型，(启用时) 将 http(s) fileData 下载为 inlineData
+/// 返回下载的远程媒体数量；任一部件不合法时返回错误 (调用方应以 400 拒绝请求)
+pub async fn resolve_media_parts(
+    request: &mut Value,
+    policy: &MediaFetchPolicy,
+    cache: &mut MediaCache,
+) -> Result<usize, String> {
+    // 先同步收集待下载部件的位置，避免跨 await 持有对请求体的可变迭代器
+    let mut remote = Vec::new();
+    let Some(contents) = request.get_mut("contents").and_then(Value::as_array_mut) else {
+        return Ok(0);
+    };
+    for (content_idx, content) in contents.iter().enumerate() {
+        let Some(parts) = content.get("parts").and_then(Value::as_array) else {
+            continue;
+        };
+        for (part_idx, part) in parts.iter().enumerate() {
+            if let Some(mime) = part.pointer("/inlineData/mimeType").and_then(Value::as_str) {
+                validate_mime_type(mime)?;
+                continue;
+            }
+            let Some(uri) = part.pointer("/fileData/fileUri").and_then(Value::as_str) else {
+                continue;
+            };
+            // gs:// 等上游可直接访问的 URI 保持不变
+            if !is_remote_url(uri) {
+                continue;
+            }
+            let fallback_mime = part
+                .pointer("/fileData/mimeType")
+                .and_then(Value::as_str)
+                .unwrap_or("image/jpeg")
+                .to_string();
+            remote.push((content_idx, part_idx, uri.to_string(), fallback_mime));
+        }
+    }
+    if !policy.enabled {
+        return Ok(0);
+    }
+
+    let mut fetched = 0;
+    for (content_idx, part_idx, uri, fallback_mime) in remote {
+        let resolved = match cache.get(&uri) {
+            Some(cached) => cached.clone(),
+            None => {
+                let part = fetch_inline_part(&uri, &fallback_mime, policy).await?;
+                cache.insert(uri, part.clone());
+                fetched += 1;
+                part
+            }
+        };
+        request["contents"][content_idx]["parts"][part_idx] = resolved;
+    }
+    if fetched > 0 {
+        tracing::debug!("[ContentParts] Inlined {} remote media part(s)", fetched);
+    }
+    Ok(fetched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_data_url_to_inline_data() {
+        let part = image_url_part("data:image/PNG;base64,iVBORw0KGgo=").unwrap();
+        assert_eq!(part, json!({ "inlineData": { "mimeType": "image/png", "data": "iVBORw0KGgo=" } }));
+
+        assert!(data_url_part("data:image/png,rawbytes").unwrap_err().contains("base64"));
+        assert!(data_url_part("data:;base64,AAAA").is_err());
+        assert!(image_url_part("ftp://example.com/cat.png").is_err());
+    }
+
+    #[test]
+    fn test_anthropic_image_source_to_inline_data() {
+        let part = anthropic_source_part("base64", "image/jpeg", "/9j/4AAQ", None).unwrap();
+        assert_eq!(part, json!({ "inlineData": { "mimeType": "image/jpeg", "data": "/9j/4AAQ" } }));
+
+        let remote = anthropic_source_part("url", "", "", Some("https://example.com/cat.webp?size=2")).unwrap();
+        assert_eq!(
+            remote,
+            json!({ "fileData": { "fileUri": "https://example.com/cat.webp?size=2", "mimeType": "image/webp" } })
+        );
+        assert!(anthropic_source_part("file", "", "", None).unwrap_err().contains("file"));
+    }
+
+    fn test_policy(max_bytes: usize) -> MediaFetchPolicy {
+        MediaFetchPolicy {
+            enabled: true,
+            allowed_hosts: Vec::new(),
+            block_private_networks: false,
+            max_bytes,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_mime_type_rejected() {
+        let mut request = json!({
+            "contents": [{ "role": "user", "parts": [
+                { "text": "what is this?" },
+                image_url_part("data:image/svg+xml;base64,PHN2Zz4=").unwrap()
+            ] }]
+        });
+        let err = resolve_media_parts(&mut request, &test_policy(MAX_REMOTE_MEDIA_BYTES), &mut MediaCache::new())
+            .await
+            .unwrap_err();
+        assert!(err.contains("image/svg+xml"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_remote_image_fetched_with_size_limit() {
+        let app = axum::Router::new()
+            .route(
+                "/cat.png",
+                axum::routing::get(|| async { ([(axum::http::header::CONTENT_TYPE, "image/png")], vec![1u8, 2, 3, 4]) }),
+            )
+            .route(
+                "/big.png",
+                axum::routing::get(|| async { ([(axum::http::header::CONTENT_TYPE, "image/png")], vec![0u8; 64]) }),
+            );
+        let addr = crate::proxy::tests::support::spawn_router(app).await;
+        let policy = test_policy(32);
+
+        let mut request = json!({
+            "contents": [{ "role": "user", "parts": [image_url_part(&format!("http://{}/cat.png", addr)).unwrap()] }]
+        });
+        let mut cache = MediaCache::new();
+        let placeholder = request.clone();
+        assert_eq!(resolve_media_parts(&mut request, &policy, &mut cache).await.unwrap(), 1);
+        assert_eq!(
+            request["contents"][0]["parts"][0],
+            json!({ "inlineData": { "mimeType": "image/png", "data": "AQIDBA==" } })
+        );
+        // 重试时复用已下载的内容
+        let mut retry = placeholder;
+        assert_eq!(resolve_media_parts(&mut retry, &policy, &mut cache).await.unwrap(), 0);
+        assert_eq!(retry, request);
+
+        let mut request = json!({
+            "contents": [{ "role": "user", "parts": [image_url_part(&format!("http://{}/big.png", addr)).unwrap()] }]
+        });
+        let err = resolve_media_parts(&mut request, &policy, &mut cache).await.unwrap_err();
+        assert!(err.contains("byte limit"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_remote_fetch_is_opt_in_and_blocks_private_hosts() {
+        let placeholder = json!({
+            "contents": [{ "role": "user", "parts": [image_url_part("http://127.0.0.1:9/cat.png").unwrap()] }]
+        });
+
+        // 默认关闭：fileData 原样交给上游，不发起任何请求
+        let disabled = MediaFetchPolicy::from_config(&RemoteMediaConfig::default());
+        let mut request = placeholder.clone();
+        assert_eq!(resolve_media_parts(&mut request, &disabled, &mut MediaCache::new()).await.unwrap(), 0);
+        assert_eq!(request, placeholder);
+
+        let enabled = MediaFetchPolicy::from_config(&RemoteMediaConfig { enabled: true, allowed_hosts: Vec::new() });
+        for url in ["http://127.0.0.1:9/a.png", "http://169.254.169.254/latest", "http://[::1]/a.png", "http://10.1.2.3/a.png"] {
+            let mut request = json!({ "contents": [{ "parts": [image_url_part(url).unwrap()] }] });
+            let err = resolve_media_parts(&mut request, &enabled, &mut MediaCache::new()).await.unwrap_err();
+            assert!(err.contains("private or local"), "{}: {}", url, err);
+        }
+
+        let allowlisted = MediaFetchPolicy::from_config(&RemoteMediaConfig {
+            enabled: true,
+            allowed_hosts: vec!["*.example.com".to_string()],
+        });
+        assert!(allowlisted.host_allowed("cdn.example.com"));
+        assert!(!allowlisted.host_allowed("example.com.evil.net"));
+        let mut request = json!({ "contents": [{ "parts": [image_url_part("https://evil.net/a.png").unwrap()] }] });
+        let err = resolve_media_parts(&mut request, &allowlisted, &mut MediaCache::new()).await.unwrap_err();
+        assert!(err.contains("allowed_hosts"), "{}", err);
+    }
+}
@@ -2,7 +2,8 @@
 use serde_json::{json, Value};
 
 /// 包装请求体为 v1internal 格式
-pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value {
+/// `schema_inline_limit` 为工具/结构化输出 Schema 展开 $ref 后的体积上限
+pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str, schema_inline_limit: usize) -> Value {
     // 优先使用传入的 mapped_model，其次尝试从 body 获取
     let original_model = body.get("model").and_then(|v| v.as_str()).unwrap_or(mapped_model);
     
@@ -50,7 +51,7 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value
                         // 2. 清洗剩余 Schema
                         for decl in decls_arr {
                             if let Some(params) = decl.get_mut("parameters") {
-                                crate::proxy::common::json_schema::clean_json_schema(params);
+                                crate::proxy::common::json_schema::clean_json_schema_with_limit(params, schema_inline_limit);
                             }
                         }
                     }
@@ -59,6 +60,15 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value
         }
     }
 
+    // 清洗结构化输出的 responseSchema (与工具 Schema 同样不支持 $ref/format 等字段)
+    if let Some(gen_config) = inner_request.get_mut("generationConfig").and_then(|v| v.as_object_mut()) {
+        for key in ["responseSchema", "response_schema"] {
+            if let Some(schema) = gen_config.get_mut(key) {
+                crate::proxy::common::json_schema::clean_json_schema_with_limit(schema, schema_inline_limit);
+            }
+        }
+    }
+
     tracing::debug!("[Debug] Gemini Wrap: original='{}', mapped='{}', final='{}', type='{}'", 
         original_model, final_model_name, config.final_model, config.request_type);
     
@@ -145,6 +155,7 @@ pub fn unwrap_response(response: &Value) -> Value {
 #[cfg(test)]
 mod tests_identity {
     use super::*;
+    use crate::proxy::common::json_schema::DEFAULT_INLINE_SIZE_LIMIT;
     use serde_json::json;
 
     #[test]
@@ -154,12 +165,49 @@ mod tests_identity {
             "contents": [{"role": "user", "parts": [{"text": "Hi"}]}]
         });
 
-        let result = wrap_request(&body, "test-project", "gemini-2.5-flash");
+        let result = wrap_request(&body, "test-project", "gemini-2.5-flash", DEFAULT_INLINE_SIZE_LIMIT);
         assert_eq!(result["project"], "test-project");
         assert_eq!(result["model"], "gemini-2.5-flash");
         assert!(result["requestId"].as_str().unwrap().starts_with("agent-"));
     }
 
+    #[test]
+    fn test_response_schema_is_cleaned() {
+        let body = json!({
+            "model": "gemini-2.5-flash",
+            "contents": [{"role": "user", "parts": [{"text": "Hi"}]}],
+            "generationConfig": {
+                "responseMimeType": "application/json",
+                "responseSchema": {
+                    "$schema": "http://json-schema.org/draft-07/schema#",
+                    "type": "object",
+                    "properties": {
+                        "created": {"type": "string", "format": "date-time"},
+                        "owner": {"$ref": "#/$defs/User"}
+                    },
+                    "$defs": {
+                        "User": {
+                            "type": "object",
+                            "additionalProperties": false,
+                            "properties": {"name": {"type": ["string", "null"]}}
+                        }
+                    }
+                }
+            }
+        });
+
+        let result = wrap_request(&body, "test-proj", "gemini-2.5-flash", DEFAULT_INLINE_SIZE_LIMIT);
+        let schema = &result["request"]["generationConfig"]["responseSchema"];
+
+        assert!(schema.get("$schema").is_none());
+        assert!(schema.get("$defs").is_none());
+        assert!(schema["properties"]["created"].get("format").is_none());
+        let owner = &schema["properties"]["owner"];
+        assert!(owner.get("$ref").is_none());
+        assert!(owner.get("additionalProperties").is_none());
+        assert_eq!(owner["properties"]["name"]["type"], "string");
+    }
+
     #[test]
     fn test_unwrap_response() {
         let wrapped = json!({
@@ -180,7 +228,7 @@ mod tests_identity {
             "messages": []
         });
         
-        let result = wrap_request(&body, "test-proj", "gemini-pro");
+        let result = wrap_request(&body, "test-proj", "gemini-pro", DEFAULT_INLINE_SIZE_LIMIT);
         
         // 验证 systemInstruction
         let sys = result.get("request").unwrap().get("systemInstruction").unwrap();
@@ -205,7 +253,7 @@ mod tests_identity {
             }
         });
 
-        let result = wrap_request(&body, "test-proj", "gemini-pro");
+        let result = wrap_request(&body, "test-proj", "gemini-pro", DEFAULT_INLINE_SIZE_LIMIT);
         let sys = result.get("request").unwrap().get("systemInstruction").unwrap();
         let parts = sys.get("parts").unwrap().as_array().unwrap();
 
@@ -224,7 +272,7 @@ mod tests_identity {
             }
         });
 
-        let result = wrap_request(&body, "test-proj", "gemini-pro");
+        let result = wrap_request(&body, "test-proj", "gemini-pro", DEFAULT_INLINE_SIZE_LIMIT);
         let sys = result.get("request").unwrap().get("systemInstruction").unwrap();
         let parts = sys.get("parts").unwrap().as_array().unwrap();
 
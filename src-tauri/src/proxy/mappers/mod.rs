@@ -3,6 +3,7 @@
 
 pub mod claude;
 pub mod common_utils;
+pub mod content_parts;
 pub mod gemini;
 pub mod openai;
 pub mod signature_store;
@@ -159,6 +159,7 @@ where
     response.choices.push(Choice {
         index: 0,
         message,
+        logprobs: None,
         finish_reason,
     });
 
@@ -27,6 +27,14 @@ pub struct OpenAIRequest {
     pub tool_choice: Option<Value>,
     #[serde(rename = "parallel_tool_calls")]
     pub parallel_tool_calls: Option<bool>,
+    /// 推理强度 (low/medium/high)，按配置换算为 thinkingBudget
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+    /// Chat API 为布尔值；Legacy Completions API 为整数 (即返回的候选数)
+    #[serde(default)]
+    pub logprobs: Option<Value>,
+    #[serde(default)]
+    pub top_logprobs: Option<u32>,
     // Codex proprietary fields
     pub instructions: Option<String>,
     pub input: Option<Value>,
@@ -114,5 +122,7 @@ pub struct OpenAIResponse {
 pub struct Choice {
     pub index: u32,
     pub message: OpenAIMessage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Value>,
     pub finish_reason: Option<String>,
 }
@@ -3,7 +3,30 @@ use super::models::*;
 use serde_json::{json, Value};
 use super::streaming::get_thought_signature;
 
-pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mapped_model: &str) -> Value {
+/// 校验请求中的 data URL / 远程图片 URL，不合法时返回错误 (调用方应以 400 拒绝请求)
+pub fn validate_image_urls(messages: &[OpenAIMessage]) -> Result<(), String> {
+    for msg in messages {
+        let Some(OpenAIContent::Array(blocks)) = &msg.content else {
+            continue;
+        };
+        for block in blocks {
+            if let OpenAIContentBlock::ImageUrl { image_url } = block {
+                if image_url.url.starts_with("data:") || image_url.url.starts_with("http") {
+                    crate::proxy::mappers::content_parts::image_url_part(&image_url.url)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `schema_inline_limit` 为工具 Schema 展开 $ref 后的体积上限
+pub fn transform_openai_request(
+    request: &OpenAIRequest,
+    project_id: &str,
+    mapped_model: &str,
+    schema_inline_limit: usize,
+) -> Value {
     // 将 OpenAI 工具转为 Value 数组以便探测
     let tools_val = request.tools.as_ref().map(|list| {
         list.iter().map(|v| v.clone()).collect::<Vec<_>>()
@@ -83,20 +106,12 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
                                     parts.push(json!({"text": text}));
                                 }
                                 OpenAIContentBlock::ImageUrl { image_url } => {
-                                    if image_url.url.starts_with("data:") {
-                                        if let Some(pos) = image_url.url.find(",") {
-                                            let mime_part = &image_url.url[5..pos];
-                                            let mime_type = mime_part.split(';').next().unwrap_or("image/jpeg");
-                                            let data = &image_url.url[pos + 1..];
-                                            
-                                            parts.push(json!({
-                                                "inlineData": { "mimeType": mime_type, "data": data }
-                                            }));
+                                    if image_url.url.starts_with("data:") || image_url.url.starts_with("http") {
+                                        // data URL 直接内联；远程 URL 以 fileData 占位，发送前下载 (见 content_parts)
+                                        match crate::proxy::mappers::content_parts::image_url_part(&image_url.url) {
+                                            Ok(part) => parts.push(part),
+                                            Err(e) => tracing::warn!("[OpenAI-Request] Skipping image_url: {}", e),
                                         }
-                                    } else if image_url.url.starts_with("http") {
-                                        parts.push(json!({
-                                            "fileData": { "fileUri": &image_url.url, "mimeType": "image/jpeg" }
-                                        }));
                                     } else {
                                         // [NEW] 处理本地文件路径 (file:// 或 Windows/Unix 路径)
                                         let file_path = if image_url.url.starts_with("file://") {
@@ -308,7 +323,7 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
 
             if let Some(params) = gemini_func.get_mut("parameters") {
                 // [DEEP FIX] 统一调用公共库清洗：展开 $ref 并剔除所有层级的 format/definitions
-                crate::proxy::common::json_schema::clean_json_schema(params);
+                crate::proxy::common::json_schema::clean_json_schema_with_limit(params, schema_inline_limit);
 
                 // Gemini v1internal 要求：
                 // 1. type 必须是大写 (OBJECT, STRING 等)
@@ -412,6 +427,7 @@ fn enforce_uppercase_types(value: &mut Value) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::proxy::common::json_schema::DEFAULT_INLINE_SIZE_LIMIT;
 
     #[test]
     fn test_transform_openai_request_multimodal() {
@@ -441,12 +457,15 @@ mod tests {
             tools: None,
             tool_choice: None,
             parallel_tool_calls: None,
+            reasoning_effort: None,
+            logprobs: None,
+            top_logprobs: None,
             instructions: None,
             input: None,
             prompt: None,
         };
 
-        let result = transform_openai_request(&req, "test-v", "gemini-1.5-flash");
+        let result = transform_openai_request(&req, "test-v", "gemini-1.5-flash", DEFAULT_INLINE_SIZE_LIMIT);
         let parts = &result["request"]["contents"][0]["parts"];
         assert_eq!(parts.as_array().unwrap().len(), 2);
         assert_eq!(parts[0]["text"].as_str().unwrap(), "What is in this image?");
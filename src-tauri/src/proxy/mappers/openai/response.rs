@@ -1,6 +1,7 @@
 // OpenAI 协议响应转换模块
 use super::models::*;
 use serde_json::Value;
+use crate::proxy::mappers::common_utils::openai_finish_reason;
 
 pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
     // 解包 response 字段
@@ -123,17 +124,12 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
             }
 
             // 提取该候选结果的 finish_reason
-            let finish_reason = candidate
-                .get("finishReason")
-                .and_then(|f| f.as_str())
-                .map(|f| match f {
-                    "STOP" => "stop",
-                    "MAX_TOKENS" => "length",
-                    "SAFETY" => "content_filter",
-                    "RECITATION" => "content_filter",
-                    _ => "stop",
-                })
-                .unwrap_or("stop");
+            // Gemini 在工具调用时同样返回 STOP，OpenAI 客户端需要 tool_calls 才会执行工具
+            let finish_reason = match candidate.get("finishReason").and_then(|f| f.as_str()).map(openai_finish_reason) {
+                Some(reason) if reason != "stop" => reason,
+                _ if !tool_calls.is_empty() => "tool_calls",
+                _ => "stop",
+            };
 
             choices.push(Choice {
                 index: idx as u32,
@@ -157,6 +153,7 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
                     tool_call_id: None,
                     name: None,
                 },
+                logprobs: crate::proxy::common::logprobs::candidate_logprobs(candidate),
                 finish_reason: Some(finish_reason.to_string()),
             });
         }
@@ -179,10 +176,69 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
     }
 }
 
+/// Gemini 响应 -> OpenAI Chat Completion JSON (含 usage)
+/// 纯函数，不依赖网络层；上游返回错误或没有任何候选结果时返回 Err
+pub fn gemini_response_to_openai(gemini_response: &Value) -> Result<Value, String> {
+    let raw = gemini_response.get("response").unwrap_or(gemini_response);
+
+    if let Some(err) = raw.get("error") {
+        let message = err
+            .get("message")
+            .and_then(|m| m.as_str())
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| err.to_string());
+        return Err(format!("Upstream error: {}", message));
+    }
+
+    let has_candidates = raw
+        .get("candidates")
+        .and_then(|c| c.as_array())
+        .is_some_and(|c| !c.is_empty());
+    if !has_candidates {
+        let reason = raw
+            .get("promptFeedback")
+            .and_then(|f| f.get("blockReason"))
+            .and_then(|r| r.as_str())
+            .unwrap_or("no candidates");
+        return Err(format!("Empty upstream response: {}", reason));
+    }
+
+    let mut out = serde_json::to_value(transform_openai_response(gemini_response))
+        .map_err(|e| format!("Serialize error: {}", e))?;
+
+    if let Some(usage) = raw.get("usageMetadata") {
+        out["usage"] = usage_to_openai(usage);
+    }
+
+    Ok(out)
+}
+
+/// Gemini usageMetadata -> OpenAI usage
+fn usage_to_openai(usage: &Value) -> Value {
+    let count = |key: &str| usage.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+    let prompt_tokens = count("promptTokenCount");
+    let reasoning_tokens = count("thoughtsTokenCount");
+    // OpenAI 的 completion_tokens 包含推理 token
+    let completion_tokens = count("candidatesTokenCount") + reasoning_tokens;
+    let total_tokens = match count("totalTokenCount") {
+        0 => prompt_tokens + completion_tokens,
+        n => n,
+    };
+
+    serde_json::json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": total_tokens,
+        "prompt_tokens_details": { "cached_tokens": count("cachedContentTokenCount") },
+        "completion_tokens_details": { "reasoning_tokens": reasoning_tokens }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::json;
+    use std::collections::HashSet;
 
     #[test]
     fn test_transform_openai_response() {
@@ -206,4 +262,162 @@ mod tests {
         assert_eq!(content, "Hello!");
         assert_eq!(result.choices[0].finish_reason, Some("stop".to_string()));
     }
+
+    #[test]
+    fn test_gemini_to_openai_text_only() {
+        let gemini_resp = json!({
+            "response": {
+                "candidates": [{
+                    "content": {
+                        "parts": [
+                            {"text": "Let me think", "thought": true},
+                            {"text": "Hello "},
+                            {"text": "world"}
+                        ]
+                    },
+                    "finishReason": "STOP"
+                }],
+                "usageMetadata": {
+                    "promptTokenCount": 10,
+                    "candidatesTokenCount": 5,
+                    "thoughtsTokenCount": 3,
+                    "totalTokenCount": 18
+                },
+                "modelVersion": "gemini-2.5-pro",
+                "responseId": "resp_text"
+            }
+        });
+
+        let out = gemini_response_to_openai(&gemini_resp).unwrap();
+        assert_eq!(out["id"], "resp_text");
+        assert_eq!(out["object"], "chat.completion");
+        let choice = &out["choices"][0];
+        assert_eq!(choice["message"]["role"], "assistant");
+        assert_eq!(choice["message"]["content"], "Hello world");
+        assert_eq!(choice["message"]["reasoning_content"], "Let me think");
+        assert!(choice["message"].get("tool_calls").is_none_or(|v| v.is_null()));
+        assert_eq!(choice["finish_reason"], "stop");
+
+        assert_eq!(out["usage"]["prompt_tokens"], 10);
+        assert_eq!(out["usage"]["completion_tokens"], 8);
+        assert_eq!(out["usage"]["total_tokens"], 18);
+        assert_eq!(out["usage"]["completion_tokens_details"]["reasoning_tokens"], 3);
+    }
+
+    #[test]
+    fn test_gemini_to_openai_single_function_call() {
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": {
+                    "parts": [{
+                        "functionCall": {
+                            "id": "call_weather_1",
+                            "name": "get_weather",
+                            "args": {"city": "Paris"}
+                        }
+                    }]
+                },
+                "finishReason": "STOP"
+            }]
+        });
+
+        let out = gemini_response_to_openai(&gemini_resp).unwrap();
+        let choice = &out["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert!(choice["message"]["content"].is_null());
+
+        let calls = choice["message"]["tool_calls"].as_array().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0]["id"], "call_weather_1");
+        assert_eq!(calls[0]["type"], "function");
+        assert_eq!(calls[0]["function"]["name"], "get_weather");
+        let args: Value =
+            serde_json::from_str(calls[0]["function"]["arguments"].as_str().unwrap()).unwrap();
+        assert_eq!(args, json!({"city": "Paris"}));
+        // 上游未返回 usageMetadata 时不输出 usage
+        assert!(out.get("usage").is_none());
+    }
+
+    #[test]
+    fn test_gemini_to_openai_multiple_function_calls() {
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": {
+                    "parts": [
+                        {"text": "Checking both."},
+                        {"functionCall": {"name": "get_weather", "args": {"city": "Paris"}}},
+                        {"functionCall": {"name": "get_weather", "args": {"city": "Tokyo"}}},
+                        {"functionCall": {"name": "get_time"}}
+                    ]
+                }
+            }],
+            "usageMetadata": {"promptTokenCount": 7, "candidatesTokenCount": 4}
+        });
+
+        let out = gemini_response_to_openai(&gemini_resp).unwrap();
+        let choice = &out["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert_eq!(choice["message"]["content"], "Checking both.");
+
+        let calls = choice["message"]["tool_calls"].as_array().unwrap();
+        let names: Vec<&str> = calls.iter().map(|c| c["function"]["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["get_weather", "get_weather", "get_time"]);
+
+        // 缺失 id 时生成唯一 id
+        let ids: HashSet<&str> = calls.iter().map(|c| c["id"].as_str().unwrap()).collect();
+        assert_eq!(ids.len(), 3);
+        assert!(ids.iter().all(|id| !id.is_empty()));
+
+        // 缺失 args 时补为空对象
+        assert_eq!(calls[2]["function"]["arguments"], "{}");
+
+        // totalTokenCount 缺失时自行求和
+        assert_eq!(out["usage"]["total_tokens"], 11);
+    }
+
+    #[test]
+    fn test_gemini_to_openai_max_tokens_keeps_length() {
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": {"parts": [{"functionCall": {"name": "f", "args": {}}}]},
+                "finishReason": "MAX_TOKENS"
+            }]
+        });
+        let out = gemini_response_to_openai(&gemini_resp).unwrap();
+        assert_eq!(out["choices"][0]["finish_reason"], "length");
+    }
+
+    #[test]
+    fn test_gemini_to_openai_finish_reasons() {
+        for (gemini, expected) in [
+            ("STOP", "stop"),
+            ("MAX_TOKENS", "length"),
+            ("SAFETY", "content_filter"),
+            ("RECITATION", "content_filter"),
+        ] {
+            let gemini_resp = json!({
+                "candidates": [{
+                    "content": {"parts": [{"text": "partial"}]},
+                    "finishReason": gemini
+                }]
+            });
+            let out = gemini_response_to_openai(&gemini_resp).unwrap();
+            assert_eq!(out["choices"][0]["finish_reason"], expected, "{}", gemini);
+        }
+    }
+
+    #[test]
+    fn test_gemini_to_openai_errors() {
+        let err = gemini_response_to_openai(&json!({
+            "error": {"code": 400, "message": "bad request"}
+        }))
+        .unwrap_err();
+        assert!(err.contains("bad request"));
+
+        let err = gemini_response_to_openai(&json!({
+            "promptFeedback": {"blockReason": "SAFETY"}
+        }))
+        .unwrap_err();
+        assert!(err.contains("SAFETY"));
+    }
 }
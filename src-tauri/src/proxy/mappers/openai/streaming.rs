@@ -9,6 +9,8 @@ use uuid::Uuid;
 use tracing::debug;
 use rand::Rng;
 
+use crate::proxy::mappers::common_utils::openai_finish_reason;
+
 // === 全局 ThoughtSignature 存储 ===
 // 用于在流式响应和后续请求之间传递签名，避免嵌入到用户可见的文本中
 static GLOBAL_THOUGHT_SIG: OnceLock<Mutex<Option<String>>> = OnceLock::new();
@@ -174,13 +176,7 @@ pub fn create_openai_sse_stream(
                                             // Extract finish reason
                                             let finish_reason = candidate.get("finishReason")
                                                 .and_then(|f| f.as_str())
-                                                .map(|f| match f {
-                                                    "STOP" => "stop",
-                                                    "MAX_TOKENS" => "length",
-                                                    "SAFETY" => "content_filter",
-                                                    "RECITATION" => "content_filter",
-                                                    _ => f,
-                                                });
+                                                .map(openai_finish_reason);
 
                                             // Construct OpenAI SSE chunk
                                             // 如果有思考内容，先发送 reasoning_content chunk
@@ -208,7 +204,7 @@ pub fn create_openai_sse_stream(
 
                                             // 发送正常 content chunk
                                             if !content_out.is_empty() || finish_reason.is_some() {
-                                                let openai_chunk = json!({
+                                                let mut openai_chunk = json!({
                                                     "id": &stream_id,
                                                     "object": "chat.completion.chunk",
                                                     "created": created_ts,
@@ -223,6 +219,9 @@ pub fn create_openai_sse_stream(
                                                         }
                                                     ]
                                                 });
+                                                if let Some(logprobs) = crate::proxy::common::logprobs::candidate_logprobs(candidate) {
+                                                    openai_chunk["choices"][0]["logprobs"] = logprobs;
+                                                }
 
                                                 let sse_out = format!("data: {}\n\n", serde_json::to_string(&openai_chunk).unwrap_or_default());
                                                 yield Ok::<Bytes, String>(Bytes::from(sse_out));
@@ -308,12 +307,7 @@ pub fn create_legacy_sse_stream(
                                         .and_then(|c| c.get(0))
                                         .and_then(|c| c.get("finishReason"))
                                         .and_then(|f| f.as_str())
-                                        .map(|f| match f {
-                                            "STOP" => "stop",
-                                            "MAX_TOKENS" => "length",
-                                            "SAFETY" => "content_filter",
-                                            _ => f,
-                                        });
+                                        .map(openai_finish_reason);
 
                                     // Construct LEGACY completion chunk - STRICT VERSION
                                     let legacy_chunk = json!({
@@ -404,11 +398,7 @@ pub fn create_codex_sse_stream(
                                 if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
                                     if let Some(candidate) = candidates.get(0) {
                                         if let Some(reason) = candidate.get("finishReason").and_then(|r| r.as_str()) {
-                                            last_finish_reason = match reason {
-                                                "STOP" => "stop".to_string(),
-                                                "MAX_TOKENS" => "length".to_string(),
-                                                _ => "stop".to_string(),
-                                            };
+                                            last_finish_reason = openai_finish_reason(reason).to_string();
                                         }
                                     }
                                 }
@@ -0,0 +1,172 @@
+// 账号并发槽位中间件
+// 为每个请求建立槽位作用域：TokenManager 选号时占用的槽位在请求结束 (流式响应传输完毕) 后释放
+// 客户端中途断开时 hyper 会 drop 请求 future (或流式响应体)，进行中的上游调用随之取消，槽位立即归还
+// 同时按请求路径设置客户端协议，选号时跳过不支持该协议的账号；
+// 并提取请求优先级线索 (`X-Priority` / API Key)，槽位占满排队时高优先级请求先被服务
+use axum::{body::Body, extract::Request, middleware::Next, response::Response};
+use futures::StreamExt;
+use std::sync::{Arc, Mutex};
+
+use crate::models::AccountProtocol;
+use crate::proxy::common::priority_queue::RequestPriorityHint;
+use crate::proxy::token_manager::{RequestSlotHolder, REQUEST_ACCOUNT_SLOT, REQUEST_PRIORITY, REQUEST_PROTOCOL};
+use crate::proxy::upstream::errors::ErrorProtocol;
+
+fn request_protocol(path: &str) -> AccountProtocol {
+    match super::maintenance::protocol_for_path(path) {
+        ErrorProtocol::OpenAI => AccountProtocol::Openai,
+        ErrorProtocol::Anthropic => AccountProtocol::Anthropic,
+        ErrorProtocol::Gemini => AccountProtocol::Gemini,
+    }
+}
+
+/// 客户端断开检测：请求在完成前被 drop 时记录取消日志
+struct DisconnectGuard {
+    request: String,
+    holder: RequestSlotHolder,
+    completed: bool,
+}
+
+impl DisconnectGuard {
+    fn new(request: String, holder: RequestSlotHolder) -> Self {
+        Self { request, holder, completed: false }
+    }
+
+    fn complete(&mut self) {
+        self.completed = true;
+    }
+}
+
+impl Drop for DisconnectGuard {
+    fn drop(&mut self) {
+        if self.completed {
+            return;
+        }
+        let had_slot = self
+            .holder
+            .lock()
+            .map(|mut slot| slot.take().is_some())
+            .unwrap_or(false);
+        tracing::info!(
+            "Client disconnected, cancelled upstream call for {} (account slot released: {})",
+            self.request,
+            had_slot
+        );
+    }
+}
+
+pub async fn account_slot_middleware(request: Request, next: Next) -> Response {
+    let holder: RequestSlotHolder = Arc::new(Mutex::new(None));
+    let mut guard = DisconnectGuard::new(
+        format!("{} {}", request.method(), request.uri().path()),
+        holder.clone(),
+    );
+    let protocol = request_protocol(request.uri().path());
+    let priority = RequestPriorityHint::from_headers(request.headers());
+    let response = REQUEST_ACCOUNT_SLOT
+        .scope(
+            holder.clone(),
+            REQUEST_PROTOCOL.scope(protocol, REQUEST_PRIORITY.scope(priority, next.run(request))),
+        )
+        .await;
+
+    let has_slot = holder.lock().map(|slot| slot.is_some()).unwrap_or(false);
+    let is_stream = response
+        .headers()
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("text/event-stream"));
+
+    if !has_slot || !is_stream {
+        // 非流式响应已在 handler 内完成上游调用，槽位随 holder 立即释放
+        guard.complete();
+        return response;
+    }
+
+    // 流式响应：槽位随响应体一同存活，传输完毕或客户端断开时释放
+    let (parts, body) = response.into_parts();
+    let stream = async_stream::stream! {
+        let mut guard = guard;
+        let mut body = body.into_data_stream();
+        while let Some(chunk) = body.next().await {
+            yield chunk;
+        }
+        guard.complete();
+    };
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::token_manager::TokenManager;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+
+    /// 模拟上游调用：drop 时标记已取消
+    struct UpstreamCall(Arc<AtomicBool>);
+
+    impl Drop for UpstreamCall {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_disconnect_cancels_upstream_and_releases_slot() {
+        let data_dir = crate::proxy::tests::support::temp_data_dir("ag-disconnect");
+        crate::proxy::tests::support::write_account(&data_dir, "a", serde_json::json!({}));
+        let token_manager = Arc::new(TokenManager::new(data_dir.clone()));
+        token_manager.load_accounts().await.unwrap();
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let app = {
+            let token_manager = token_manager.clone();
+            let cancelled = cancelled.clone();
+            axum::Router::new()
+                .route(
+                    "/v1/chat/completions",
+                    axum::routing::post(move || {
+                        let token_manager = token_manager.clone();
+                        let cancelled = cancelled.clone();
+                        async move {
+                            token_manager.get_token("agent", false, None).await.unwrap();
+                            let _call = UpstreamCall(cancelled);
+                            std::future::pending::<()>().await;
+                            "unreachable"
+                        }
+                    }),
+                )
+                .layer(axum::middleware::from_fn(account_slot_middleware))
+        };
+        let addr = crate::proxy::tests::support::spawn_router(app).await;
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(b"POST /v1/chat/completions HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        for _ in 0..100 {
+            if token_manager.in_flight("a") == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(token_manager.in_flight("a"), 1);
+        assert!(!cancelled.load(Ordering::SeqCst));
+
+        // 客户端断开
+        drop(client);
+        for _ in 0..100 {
+            if cancelled.load(Ordering::SeqCst) && token_manager.in_flight("a") == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(cancelled.load(Ordering::SeqCst));
+        assert_eq!(token_manager.in_flight("a"), 0);
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+}
@@ -21,7 +21,7 @@ pub async fn auth_middleware(
     let path = request.uri().path().to_string();
 
     // 过滤心跳和健康检查请求,避免日志噪音
-    if !path.contains("event_logging") && path != "/healthz" {
+    if !path.contains("event_logging") && path != "/healthz" && path != "/readyz" {
         tracing::info!("Request: {} {}", method, path);
     } else {
         tracing::trace!("Heartbeat: {} {}", method, path);
@@ -39,7 +39,11 @@ pub async fn auth_middleware(
         return Ok(next.run(request).await);
     }
 
-    if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && path == "/healthz" {
+    if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && (path == "/healthz" || path == "/readyz") {
+        return Ok(next.run(request).await);
+    }
+
+    if security.public_version_endpoint && path == "/version" {
         return Ok(next.run(request).await);
     }
     
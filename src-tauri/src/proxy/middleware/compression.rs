@@ -0,0 +1,175 @@
+// 请求/响应体压缩中间件
+// 入站：按 Content-Encoding 透明解压 gzip/deflate 请求体，解析与 Schema 清洗始终看到明文 JSON
+// 出站：按客户端 Accept-Encoding 重新压缩响应 (SSE 流、图片与过小的响应除外)
+// 上游返回的压缩响应由 reqwest (gzip/deflate feature) 自动解压
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tower_http::compression::predicate::{And, DefaultPredicate, Predicate};
+use tower_http::compression::CompressionLayer;
+use tower_http::decompression::RequestDecompressionLayer;
+
+/// 响应压缩开关 (压缩判定在同步回调中执行，故使用原子量而非 RwLock)
+#[derive(Debug, Clone)]
+pub struct CompressionToggle(Arc<AtomicBool>);
+
+impl CompressionToggle {
+    pub fn new(enabled: bool) -> Self {
+        Self(Arc::new(AtomicBool::new(enabled)))
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Predicate for CompressionToggle {
+    fn should_compress<B>(&self, _response: &axum::http::Response<B>) -> bool
+    where
+        B: axum::body::HttpBody,
+    {
+        self.is_enabled()
+    }
+}
+
+/// 入站请求体解压层 (不支持的 Content-Encoding 返回 415)
+pub fn request_decompression_layer() -> RequestDecompressionLayer {
+    RequestDecompressionLayer::new().gzip(true).deflate(true)
+}
+
+/// 出站响应压缩层：默认判定 (跳过 SSE/图片/小响应) 且开关开启时压缩
+pub fn response_compression_layer(
+    toggle: CompressionToggle,
+) -> CompressionLayer<And<DefaultPredicate, CompressionToggle>> {
+    CompressionLayer::new()
+        .gzip(true)
+        .deflate(true)
+        .compress_when(DefaultPredicate::new().and(toggle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::header, routing::post, Json, Router};
+    use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+    use serde_json::{json, Value};
+    use std::io::{Read, Write};
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn gunzip(data: &[u8]) -> String {
+        let mut out = String::new();
+        GzDecoder::new(data).read_to_string(&mut out).unwrap();
+        out
+    }
+
+    async fn serve(app: Router) -> String {
+        format!("http://{}", crate::proxy::tests::support::spawn_router(app).await)
+    }
+
+    #[tokio::test]
+    async fn test_gzip_request_body_is_decompressed_before_parsing() {
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                post(|Json(body): Json<Value>| async move { Json(json!({ "model": body["model"] })) }),
+            )
+            .layer(request_decompression_layer());
+        let base = serve(app).await;
+
+        let payload = json!({ "model": "gemini-3-flash", "messages": [{ "role": "user", "content": "hi" }] });
+        let resp = reqwest::Client::builder()
+            .no_proxy()
+            .build()
+            .unwrap()
+            .post(format!("{}/v1/chat/completions", base))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(gzip(payload.to_string().as_bytes()))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["model"], "gemini-3-flash");
+    }
+
+    #[tokio::test]
+    async fn test_compressed_upstream_response_is_recompressed_for_client() {
+        let payload = json!({ "candidates": [{ "content": { "parts": [{ "text": "hello ".repeat(64) }] } }] });
+        let upstream_body = payload.to_string();
+
+        // 上游返回 gzip 压缩的 JSON
+        let upstream = {
+            let compressed = gzip(upstream_body.as_bytes());
+            Router::new().route(
+                "/generate",
+                post(move || {
+                    let compressed = compressed.clone();
+                    async move {
+                        (
+                            [(header::CONTENT_TYPE, "application/json"), (header::CONTENT_ENCODING, "gzip")],
+                            compressed,
+                        )
+                    }
+                }),
+            )
+        };
+        let upstream_base = serve(upstream).await;
+
+        // 代理：经 reqwest 取回上游响应并原样返回给客户端
+        let toggle = CompressionToggle::new(true);
+        let proxy = Router::new()
+            .route(
+                "/v1/generate",
+                post(move || {
+                    let url = format!("{}/generate", upstream_base);
+                    async move {
+                        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+                        let text = client.post(url).send().await.unwrap().text().await.unwrap();
+                        ([(header::CONTENT_TYPE, "application/json")], text)
+                    }
+                }),
+            )
+            .layer(response_compression_layer(toggle.clone()));
+        let proxy_base = serve(proxy).await;
+
+        // 客户端关闭自动解压，以便检查线上字节
+        let client = reqwest::Client::builder().no_proxy().no_gzip().no_deflate().build().unwrap();
+
+        let resp = client
+            .post(format!("{}/v1/generate", proxy_base))
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+        let bytes = resp.bytes().await.unwrap();
+        assert_eq!(gunzip(&bytes), upstream_body);
+
+        // 客户端未声明 Accept-Encoding 时返回明文
+        let resp = client.post(format!("{}/v1/generate", proxy_base)).send().await.unwrap();
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(resp.text().await.unwrap(), upstream_body);
+
+        // 关闭开关后不再压缩
+        toggle.set(false);
+        let resp = client
+            .post(format!("{}/v1/generate", proxy_base))
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .send()
+            .await
+            .unwrap();
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(resp.text().await.unwrap(), upstream_body);
+    }
+}
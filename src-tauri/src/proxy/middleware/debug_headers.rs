@@ -0,0 +1,269 @@
+// 调试响应头
+// 开启 expose_debug_headers 后，在成功响应上附加实际使用的模型、账号与候选模型回退情况，
+// 便于客户端在无法访问服务端日志时对照排查；只输出模型名与账号邮箱，不包含任何令牌或 API Key
+// 流式响应开始后无法再补充响应头，改为在流末尾追加一个元数据事件 (含 token 用量与上下文缓存命中情况)
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use bytes::Bytes;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use tokio::sync::RwLock;
+
+pub const RESOLVED_MODEL_HEADER: &str = "x-antigravity-resolved-model";
+pub const ACCOUNT_HEADER: &str = "x-antigravity-account";
+pub const FALLBACKS_USED_HEADER: &str = "x-antigravity-fallbacks-used";
+pub const FALLBACK_CHAIN_HEADER: &str = "x-antigravity-fallback-chain";
+/// 流式响应末尾的元数据事件名
+pub const METADATA_EVENT: &str = "antigravity.metadata";
+
+/// 请求处理过程中收集的调试信息
+#[derive(Debug, Default)]
+struct DebugInfo {
+    /// 被放弃的候选模型
+    fallbacks: Vec<String>,
+    /// 最后一次上游调用的上下文缓存状态 (`hit` / `miss`，未使用缓存时为空)
+    cache_status: Option<&'static str>,
+}
+
+type DebugInfoHolder = Arc<Mutex<DebugInfo>>;
+
+tokio::task_local! {
+    /// 当前请求的调试信息 (由 debug_headers 中间件建立作用域)
+    static DEBUG_INFO: DebugInfoHolder;
+}
+
+/// 记录一个未能服务请求、已放弃的候选模型；未开启调试响应头时为空操作
+pub fn record_fallback(abandoned_model: &str) {
+    let _ = DEBUG_INFO.try_with(|info| {
+        if let Ok(mut info) = info.lock() {
+            info.fallbacks.push(abandoned_model.to_string());
+        }
+    });
+}
+
+/// 记录上下文缓存状态 (每次上游调用覆盖上一次)；未开启调试响应头时为空操作
+pub fn record_cache_status(status: &'static str) {
+    let _ = DEBUG_INFO.try_with(|info| {
+        if let Ok(mut info) = info.lock() {
+            info.cache_status = Some(status);
+        }
+    });
+}
+
+/// 从流式事件中累计 token 用量 (兼容 OpenAI / Anthropic / Gemini 格式)
+#[derive(Debug, Default)]
+struct StreamUsage {
+    pending: Vec<u8>,
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+}
+
+impl StreamUsage {
+    fn feed(&mut self, chunk: &[u8]) {
+        self.pending.extend_from_slice(chunk);
+        while let Some(pos) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            let Some(data) = std::str::from_utf8(&line).ok().and_then(|l| l.trim().strip_prefix("data:")) else {
+                continue;
+            };
+            let Ok(event) = serde_json::from_str::<Value>(data.trim()) else {
+                continue;
+            };
+            let Some(usage) = event
+                .get("usage")
+                .or_else(|| event.pointer("/message/usage"))
+                .or_else(|| event.get("usageMetadata"))
+                .filter(|u| u.is_object())
+            else {
+                continue;
+            };
+            let count = |keys: [&str; 3]| keys.iter().find_map(|k| usage.get(*k).and_then(Value::as_u64));
+            if let Some(n) = count(["prompt_tokens", "input_tokens", "promptTokenCount"]) {
+                self.input_tokens = Some(n);
+            }
+            if let Some(n) = count(["completion_tokens", "output_tokens", "candidatesTokenCount"]) {
+                self.output_tokens = Some(n);
+            }
+        }
+    }
+}
+
+/// 在流式响应末尾追加元数据事件
+fn append_metadata_event(response: Response, mut metadata: Value) -> Response {
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+
+    let usage = Arc::new(Mutex::new(StreamUsage::default()));
+    let tracker = usage.clone();
+    let stream = body.into_data_stream().inspect(move |chunk| {
+        if let (Ok(chunk), Ok(mut usage)) = (chunk, tracker.lock()) {
+            usage.feed(chunk);
+        }
+    });
+    let trailer = futures::stream::once(async move {
+        if let Ok(usage) = usage.lock() {
+            metadata["input_tokens"] = json!(usage.input_tokens);
+            metadata["output_tokens"] = json!(usage.output_tokens);
+        }
+        Ok::<_, axum::Error>(Bytes::from(format!("event: {}\ndata: {}\n\n", METADATA_EVENT, metadata)))
+    });
+    Response::from_parts(parts, Body::from_stream(stream.chain(trailer)))
+}
+
+pub async fn debug_headers_middleware(
+    State(enabled): State<Arc<RwLock<bool>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !*enabled.read().await {
+        return next.run(request).await;
+    }
+
+    let info: DebugInfoHolder = Arc::new(Mutex::new(DebugInfo::default()));
+    let mut response = DEBUG_INFO.scope(info.clone(), next.run(request)).await;
+    if !response.status().is_success() {
+        return response;
+    }
+
+    // 仅模型请求 (handler 已写入 X-Mapped-Model) 附加调试头
+    let headers = response.headers_mut();
+    let Some(model) = headers.get("x-mapped-model").cloned() else {
+        return response;
+    };
+    headers.insert(RESOLVED_MODEL_HEADER, model.clone());
+    let account = headers.get("x-account-email").cloned();
+    if let Some(account) = &account {
+        headers.insert(ACCOUNT_HEADER, account.clone());
+    }
+    let (fallbacks, cache_status) = info
+        .lock()
+        .map(|info| (info.fallbacks.clone(), info.cache_status))
+        .unwrap_or_default();
+    headers.insert(FALLBACKS_USED_HEADER, HeaderValue::from(fallbacks.len()));
+    if !fallbacks.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&fallbacks.join(", ")) {
+            headers.insert(FALLBACK_CHAIN_HEADER, value);
+        }
+    }
+
+    let is_stream = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("text/event-stream"));
+    if !is_stream {
+        return response;
+    }
+    let metadata = json!({
+        "resolved_model": model.to_str().ok(),
+        "account": account.as_ref().and_then(|a| a.to_str().ok()),
+        "fallbacks_used": fallbacks.len(),
+        "context_cache": cache_status,
+    });
+    append_metadata_event(response, metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+
+    /// 模拟策略回退：首个候选失败后由第二个候选成功响应
+    async fn fallback_handler() -> Response {
+        record_fallback("gemini-3-pro-high");
+        (
+            [("X-Account-Email", "a@example.com"), ("X-Mapped-Model", "gemini-3-flash")],
+            "ok",
+        )
+            .into_response()
+    }
+
+    /// 模拟命中上下文缓存的 Anthropic 流式响应
+    async fn stream_handler() -> Response {
+        record_cache_status("hit");
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":42,\"output_tokens\":0}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"hi\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":7}}\n\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        );
+        (
+            [
+                ("Content-Type", "text/event-stream"),
+                ("X-Account-Email", "a@example.com"),
+                ("X-Mapped-Model", "gemini-3-flash"),
+            ],
+            body,
+        )
+            .into_response()
+    }
+
+    async fn spawn_app(enabled: bool) -> String {
+        let app = axum::Router::new()
+            .route("/v1/chat/completions", axum::routing::post(fallback_handler))
+            .route("/v1/messages", axum::routing::post(stream_handler))
+            .route("/healthz", axum::routing::get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(RwLock::new(enabled)),
+                debug_headers_middleware,
+            ));
+        format!("http://{}", crate::proxy::tests::support::spawn_router(app).await)
+    }
+
+    #[tokio::test]
+    async fn test_debug_headers_reflect_resolution_when_enabled() {
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+
+        let base = spawn_app(true).await;
+        let resp = client.post(format!("{}/v1/chat/completions", base)).send().await.unwrap();
+        let headers = resp.headers();
+        assert_eq!(headers[RESOLVED_MODEL_HEADER], "gemini-3-flash");
+        assert_eq!(headers[ACCOUNT_HEADER], "a@example.com");
+        assert_eq!(headers[FALLBACKS_USED_HEADER], "1");
+        assert_eq!(headers[FALLBACK_CHAIN_HEADER], "gemini-3-pro-high");
+
+        // 非模型请求不附加
+        let resp = client.get(format!("{}/healthz", base)).send().await.unwrap();
+        assert!(resp.headers().get(FALLBACKS_USED_HEADER).is_none());
+
+        let base = spawn_app(false).await;
+        let resp = client.post(format!("{}/v1/chat/completions", base)).send().await.unwrap();
+        for name in [RESOLVED_MODEL_HEADER, ACCOUNT_HEADER, FALLBACKS_USED_HEADER, FALLBACK_CHAIN_HEADER] {
+            assert!(resp.headers().get(name).is_none(), "{} should be absent", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_ends_with_metadata_event() {
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+
+        let base = spawn_app(true).await;
+        let body = client.post(format!("{}/v1/messages", base)).send().await.unwrap().text().await.unwrap();
+        let last = body.trim_end().rsplit("\n\n").next().unwrap();
+        let data = last
+            .strip_prefix(&format!("event: {}\ndata: ", METADATA_EVENT))
+            .expect("stream should end with the metadata event");
+        let metadata: Value = serde_json::from_str(data).unwrap();
+        assert_eq!(metadata["resolved_model"], "gemini-3-flash");
+        assert_eq!(metadata["account"], "a@example.com");
+        assert_eq!(metadata["input_tokens"], 42);
+        assert_eq!(metadata["output_tokens"], 7);
+        assert_eq!(metadata["context_cache"], "hit");
+        assert_eq!(metadata["fallbacks_used"], 0);
+        // 原有事件保持不变
+        assert!(body.contains("event: message_stop"));
+
+        let base = spawn_app(false).await;
+        let body = client.post(format!("{}/v1/messages", base)).send().await.unwrap().text().await.unwrap();
+        assert!(!body.contains(METADATA_EVENT));
+    }
+}
@@ -0,0 +1,291 @@
+// 并发重复请求合并 (Single-Flight)
+// Agent 框架的重试竞争可能同时发出完全相同的请求，合并后只消耗一次配额
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OnceCell, RwLock};
+
+const MAX_DEDUPE_BODY_SIZE: usize = 100 * 1024 * 1024; // 100MB
+
+/// 参与合并的生成类端点 (Gemini 原生端点仅限非流式的 generateContent)
+fn is_dedupable_path(path: &str) -> bool {
+    matches!(
+        path,
+        "/v1/chat/completions" | "/v1/completions" | "/v1/responses" | "/v1/messages"
+    ) || (path.starts_with("/v1beta/models/") && path.ends_with(":generateContent"))
+}
+
+/// 可在多个等待者之间共享的已缓冲响应
+#[derive(Clone, Debug)]
+pub struct SharedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl SharedResponse {
+    pub async fn from_response(response: Response) -> Self {
+        let (parts, body) = response.into_parts();
+        match axum::body::to_bytes(body, MAX_DEDUPE_BODY_SIZE).await {
+            Ok(body) => Self {
+                status: parts.status,
+                headers: parts.headers,
+                body,
+            },
+            Err(e) => Self {
+                status: StatusCode::BAD_GATEWAY,
+                headers: HeaderMap::new(),
+                body: Bytes::from(format!("Failed to buffer response: {}", e)),
+            },
+        }
+    }
+
+    pub fn to_response(&self) -> Response {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+/// 按 key 合并同时进行中的相同任务
+#[derive(Default)]
+pub struct SingleFlight {
+    flights: Mutex<HashMap<String, Arc<OnceCell<SharedResponse>>>>,
+}
+
+impl SingleFlight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 若相同 key 的任务正在进行，等待并共享其结果；否则执行 `f`。
+    /// 返回 (结果, 是否为共享结果)。
+    /// 执行者被取消 (客户端断开) 时，由下一个等待者接手执行。
+    pub async fn run<F, Fut>(&self, key: String, f: F) -> (SharedResponse, bool)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = SharedResponse>,
+    {
+        let cell = {
+            let mut flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+            flights.entry(key.clone()).or_default().clone()
+        };
+
+        let mut executed = false;
+        let result = cell
+            .get_or_init(|| {
+                executed = true;
+                f()
+            })
+            .await
+            .clone();
+
+        if executed {
+            let mut flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+            if flights.get(&key).is_some_and(|c| Arc::ptr_eq(c, &cell)) {
+                flights.remove(&key);
+            }
+        }
+
+        (result, !executed)
+    }
+
+    #[allow(dead_code)]
+    pub fn in_flight(&self) -> usize {
+        self.flights.lock().map(|f| f.len()).unwrap_or(0)
+    }
+}
+
+/// 合并中间件状态
+#[derive(Clone)]
+pub struct DedupeState {
+    pub enabled: Arc<RwLock<bool>>,
+    pub flights: Arc<SingleFlight>,
+}
+
+impl DedupeState {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: Arc::new(RwLock::new(enabled)),
+            flights: Arc::new(SingleFlight::new()),
+        }
+    }
+}
+
+/// 会改变路由或上游响应的请求头：取值不同的请求不能共享响应
+const KEYED_HEADERS: [&str; 8] = [
+    "authorization",
+    "x-api-key",
+    "x-goog-api-key",
+    crate::proxy::common::candidate_override::MODEL_CANDIDATES_HEADER,
+    crate::proxy::common::request_timeout::REQUEST_TIMEOUT_HEADER,
+    "x-priority",
+    "anthropic-beta",
+    "anthropic-version",
+];
+
+/// 请求指纹：方法 + 路径 + 凭证与影响响应的请求头 + 请求体
+fn request_key(request_parts: &axum::http::request::Parts, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(request_parts.method.as_str().as_bytes());
+    hasher.update([0u8]);
+    hasher.update(request_parts.uri.to_string().as_bytes());
+    hasher.update([0u8]);
+    for name in KEYED_HEADERS {
+        if let Some(v) = request_parts.headers.get(name) {
+            hasher.update(v.as_bytes());
+        }
+        hasher.update([0u8]);
+    }
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
+
+/// 并发重复请求合并中间件 (仅非流式请求，需开启 `dedupe_in_flight`)
+pub async fn dedupe_middleware(
+    State(state): State<DedupeState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != axum::http::Method::POST
+        || !is_dedupable_path(request.uri().path())
+        || !*state.enabled.read().await
+    {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_DEDUPE_BODY_SIZE).await {
+        Ok(b) => b,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, format!("Failed to read request body: {}", e)).into_response();
+        }
+    };
+
+    // 流式请求永不合并
+    let is_stream = serde_json::from_slice::<Value>(&bytes)
+        .ok()
+        .and_then(|v| v.get("stream").and_then(Value::as_bool))
+        .unwrap_or(false);
+    if is_stream {
+        return next.run(Request::from_parts(parts, Body::from(bytes))).await;
+    }
+
+    let key = request_key(&parts, &bytes);
+    let request = Request::from_parts(parts, Body::from(bytes));
+    let (shared, was_shared) = state
+        .flights
+        .run(key, || async move { SharedResponse::from_response(next.run(request).await).await })
+        .await;
+
+    if was_shared {
+        tracing::info!("[Dedupe] Identical request in flight, shared its response");
+    }
+    shared.to_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    async fn spawn_app(enabled: bool, calls: Arc<AtomicUsize>) -> std::net::SocketAddr {
+        let app = axum::Router::new()
+            .route(
+                "/v1/chat/completions",
+                axum::routing::post(move || {
+                    let calls = calls.clone();
+                    async move {
+                        let n = calls.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                        axum::Json(serde_json::json!({ "id": format!("resp-{}", n) }))
+                    }
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                DedupeState::new(enabled),
+                dedupe_middleware,
+            ));
+        crate::proxy::tests::support::spawn_router(app).await
+    }
+
+    async fn fire_pair(addr: std::net::SocketAddr, body: Value) -> (String, String) {
+        fire_pair_with_headers(addr, body, &[], &[]).await
+    }
+
+    /// 并发发出两个请求体相同、请求头分别为 `a_headers` / `b_headers` 的请求
+    async fn fire_pair_with_headers(
+        addr: std::net::SocketAddr,
+        body: Value,
+        a_headers: &[(&str, &str)],
+        b_headers: &[(&str, &str)],
+    ) -> (String, String) {
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let url = format!("http://{}/v1/chat/completions", addr);
+        let build = |headers: &[(&str, &str)]| {
+            headers
+                .iter()
+                .fold(client.post(&url).json(&body), |req, (k, v)| req.header(*k, *v))
+        };
+        let (a, b) = tokio::join!(build(a_headers).send(), build(b_headers).send());
+        (a.unwrap().text().await.unwrap(), b.unwrap().text().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_identical_concurrent_requests_share_one_upstream_call() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_app(true, calls.clone()).await;
+
+        let body = serde_json::json!({ "model": "gemini-3-flash", "messages": [{ "role": "user", "content": "hi" }] });
+        let (a, b) = fire_pair(addr, body).await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(a, b);
+        assert!(a.contains("resp-0"));
+    }
+
+    #[tokio::test]
+    async fn test_streaming_and_disabled_requests_are_not_deduped() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_app(true, calls.clone()).await;
+        let body = serde_json::json!({ "model": "gemini-3-flash", "stream": true, "messages": [] });
+        fire_pair(addr, body).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_app(false, calls.clone()).await;
+        let body = serde_json::json!({ "model": "gemini-3-flash", "messages": [] });
+        fire_pair(addr, body).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_requests_differing_in_routing_headers_are_not_deduped() {
+        let body = serde_json::json!({ "model": "gemini-3-flash", "messages": [{ "role": "user", "content": "hi" }] });
+        for name in ["x-model-candidates", "x-request-timeout", "x-priority", "anthropic-beta"] {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let addr = spawn_app(true, calls.clone()).await;
+            let (a, b) = fire_pair_with_headers(addr, body.clone(), &[(name, "1")], &[(name, "2")]).await;
+            assert_eq!(calls.load(Ordering::SeqCst), 2, "{}", name);
+            assert_ne!(a, b);
+        }
+
+        // 取值相同时仍然合并
+        let calls = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_app(true, calls.clone()).await;
+        let headers = [("x-priority", "high")];
+        fire_pair_with_headers(addr, body, &headers, &headers).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
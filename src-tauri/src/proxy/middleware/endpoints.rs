@@ -0,0 +1,99 @@
+// 按协议端点启用/禁用路由
+// 被禁用的端点直接返回 404，与不存在的路由无法区分；模型列表、健康检查与内部端点不受影响
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::proxy::config::EnabledEndpointsConfig;
+
+impl EnabledEndpointsConfig {
+    /// 请求路径是否允许访问
+    pub fn allows(&self, path: &str) -> bool {
+        match path {
+            "/v1/chat/completions" => self.openai_chat,
+            "/v1/completions" | "/v1/responses" => self.openai_completions,
+            "/v1/messages" | "/v1/messages/count_tokens" => self.anthropic_messages,
+            "/v1/audio/transcriptions" => self.audio_transcriptions,
+            _ if path.starts_with("/v1/images/") => self.openai_images,
+            _ if path.starts_with("/v1beta/") => self.gemini,
+            _ if path.starts_with("/mcp/") => self.mcp,
+            _ => true,
+        }
+    }
+}
+
+pub async fn endpoint_toggle_middleware(
+    State(endpoints): State<Arc<RwLock<EnabledEndpointsConfig>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    if !endpoints.read().await.allows(path) {
+        tracing::debug!("端点已禁用，拒绝请求: {} {}", request.method(), path);
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn spawn_app(endpoints: EnabledEndpointsConfig) -> std::net::SocketAddr {
+        let app = axum::Router::new()
+            .route("/v1/chat/completions", axum::routing::post(|| async { "openai" }))
+            .route("/v1/messages", axum::routing::post(|| async { "anthropic" }))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(RwLock::new(endpoints)),
+                endpoint_toggle_middleware,
+            ));
+        crate::proxy::tests::support::spawn_router(app).await
+    }
+
+    #[tokio::test]
+    async fn test_disabled_endpoint_returns_404() {
+        let addr = spawn_app(EnabledEndpointsConfig {
+            openai_chat: false,
+            ..Default::default()
+        })
+        .await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+
+        let resp = client
+            .post(format!("http://{}/v1/chat/completions", addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let resp = client
+            .post(format!("http://{}/v1/messages", addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        assert_eq!(resp.text().await.unwrap(), "anthropic");
+    }
+
+    #[test]
+    fn test_unlisted_paths_always_allowed() {
+        let none = EnabledEndpointsConfig {
+            openai_chat: false,
+            openai_completions: false,
+            openai_images: false,
+            audio_transcriptions: false,
+            anthropic_messages: false,
+            gemini: false,
+            mcp: false,
+        };
+        assert!(!none.allows("/v1beta/models/gemini-2.5-flash:generateContent"));
+        assert!(!none.allows("/v1/images/generations"));
+        assert!(none.allows("/v1/models"));
+        assert!(none.allows("/healthz"));
+    }
+}
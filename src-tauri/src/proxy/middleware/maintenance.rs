@@ -0,0 +1,125 @@
+// 维护模式
+// 开启后除健康检查外的所有请求直接返回 503，错误体按请求路径对应的客户端协议格式渲染，不发起任何上游调用
+// `/readyz` 不经此拦截，由就绪检查自身报告 503 (not_ready)，以便负载均衡摘除实例
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::proxy::common::error::ProxyError;
+use crate::proxy::config::MaintenanceModeConfig;
+use crate::proxy::upstream::errors::ErrorProtocol;
+
+/// 维护期间仍需响应的端点 (供编排系统探测)
+fn is_exempt(path: &str) -> bool {
+    matches!(path, "/healthz" | "/readyz" | "/version")
+}
+
+/// 按请求路径推断客户端协议
+pub(crate) fn protocol_for_path(path: &str) -> ErrorProtocol {
+    if path.starts_with("/v1/messages") {
+        ErrorProtocol::Anthropic
+    } else if path.starts_with("/v1beta/") {
+        ErrorProtocol::Gemini
+    } else {
+        ErrorProtocol::OpenAI
+    }
+}
+
+pub async fn maintenance_middleware(
+    State(maintenance): State<Arc<RwLock<MaintenanceModeConfig>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    if is_exempt(&path) {
+        return next.run(request).await;
+    }
+    let message = {
+        let maintenance = maintenance.read().await;
+        if !maintenance.enabled {
+            drop(maintenance);
+            return next.run(request).await;
+        }
+        maintenance.effective_message().to_string()
+    };
+    tracing::debug!("维护模式，拒绝请求: {} {}", request.method(), path);
+    ProxyError::Maintenance(message).into_protocol_response(protocol_for_path(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn spawn_app(
+        maintenance: Arc<RwLock<MaintenanceModeConfig>>,
+        upstream_calls: Arc<AtomicUsize>,
+    ) -> String {
+        let handler = move || {
+            let upstream_calls = upstream_calls.clone();
+            async move {
+                upstream_calls.fetch_add(1, Ordering::SeqCst);
+                "ok"
+            }
+        };
+        let app = axum::Router::new()
+            .route("/v1/chat/completions", axum::routing::post(handler.clone()))
+            .route("/v1/messages", axum::routing::post(handler.clone()))
+            .route("/v1beta/models/:model", axum::routing::post(handler))
+            .route("/healthz", axum::routing::get(|| async { "healthy" }))
+            .layer(axum::middleware::from_fn_with_state(maintenance, maintenance_middleware));
+        format!("http://{}", crate::proxy::tests::support::spawn_router(app).await)
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_mode_short_circuits_with_configured_message() {
+        let maintenance = Arc::new(RwLock::new(MaintenanceModeConfig {
+            enabled: true,
+            message: Some("Upgrading upstream, back at 18:00".to_string()),
+        }));
+        let upstream_calls = Arc::new(AtomicUsize::new(0));
+        let base = spawn_app(maintenance.clone(), upstream_calls.clone()).await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+
+        let resp = client.post(format!("{}/v1/chat/completions", base)).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "maintenance");
+        assert_eq!(body["error"]["message"], "Upgrading upstream, back at 18:00");
+
+        let resp = client.post(format!("{}/v1/messages", base)).send().await.unwrap();
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "overloaded_error");
+        assert_eq!(body["error"]["message"], "Upgrading upstream, back at 18:00");
+
+        let resp = client
+            .post(format!("{}/v1beta/models/gemini-3-flash:generateContent", base))
+            .send()
+            .await
+            .unwrap();
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["error"]["status"], "SERVICE_UNAVAILABLE");
+
+        // 健康检查不受影响，且未触达任何处理器
+        let resp = client.get(format!("{}/healthz", base)).send().await.unwrap();
+        assert_eq!(resp.text().await.unwrap(), "healthy");
+        assert_eq!(upstream_calls.load(Ordering::SeqCst), 0);
+
+        // 运行时关闭后恢复正常
+        maintenance.write().await.enabled = false;
+        let resp = client.post(format!("{}/v1/chat/completions", base)).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        assert_eq!(upstream_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_blank_message_uses_default() {
+        let config = MaintenanceModeConfig { enabled: true, message: Some("  ".to_string()) };
+        assert!(config.effective_message().contains("maintenance"));
+    }
+}
@@ -1,9 +1,18 @@
 // Middleware 模块 - Axum 中间件
 
+pub mod account_slot;
 pub mod auth;
+pub mod compression;
 pub mod cors;
+pub mod debug_headers;
+pub mod dedupe;
+pub mod endpoints;
 pub mod logging;
+pub mod maintenance;
 pub mod monitor;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod request_trace;
 
 pub use auth::auth_middleware;
 pub use cors::cors_layer;
@@ -4,15 +4,39 @@ use axum::{
     response::Response,
     body::Body,
 };
+use std::future::Future;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use crate::proxy::server::AppState;
-use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::monitor::{MonitorEvent, ProxyRequestLog};
 use serde_json::Value;
 use futures::StreamExt;
 
 const MAX_REQUEST_LOG_SIZE: usize = 100 * 1024 * 1024; // 100MB
 const MAX_RESPONSE_LOG_SIZE: usize = 100 * 1024 * 1024; // 100MB for image responses
 
+tokio::task_local! {
+    /// 当前请求命中的 A/B 实验分组 (由路由解析写入，请求结束后记入日志)
+    static EXPERIMENT_ARM: Arc<Mutex<Option<String>>>;
+}
+
+/// 记录当前请求的实验分组；不在请求作用域内时忽略
+pub fn record_experiment_arm(label: String) {
+    let _ = EXPERIMENT_ARM.try_with(|arm| {
+        if let Ok(mut arm) = arm.lock() {
+            *arm = Some(label);
+        }
+    });
+}
+
+/// 在实验分组作用域内执行 future，返回其结果与期间记录的分组
+pub async fn scope_experiment_arm<F: Future>(fut: F) -> (F::Output, Option<String>) {
+    let holder = Arc::new(Mutex::new(None));
+    let output = EXPERIMENT_ARM.scope(holder.clone(), fut).await;
+    let arm = holder.lock().ok().and_then(|mut arm| arm.take());
+    (output, arm)
+}
+
 pub async fn monitor_middleware(
     State(state): State<AppState>,
     request: Request,
@@ -22,6 +46,16 @@ pub async fn monitor_middleware(
         return next.run(request).await;
     }
 
+    // 仅计数模式: 不缓存请求/响应体，只记录状态码
+    if state.monitor.is_counters_only() {
+        let is_event_logging = request.uri().path().contains("event_logging");
+        let response = next.run(request).await;
+        if !is_event_logging {
+            state.monitor.record_status(response.status().as_u16()).await;
+        }
+        return response;
+    }
+
     let start = Instant::now();
     let method = request.method().to_string();
     let uri = request.uri().to_string();
@@ -65,8 +99,17 @@ pub async fn monitor_middleware(
         request_body_str = None;
         request
     };
-    
-    let response = next.run(request).await;
+
+    let request_id = uuid::Uuid::new_v4().to_string();
+    state.monitor.publish(MonitorEvent::RequestStarted {
+        id: request_id.clone(),
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        method: method.clone(),
+        url: uri.clone(),
+        model: model.clone(),
+    });
+
+    let (response, experiment_arm) = scope_experiment_arm(next.run(request)).await;
     
     let duration = start.elapsed().as_millis() as u64;
     let status = response.status().as_u16();
@@ -92,7 +135,7 @@ pub async fn monitor_middleware(
 
     let monitor = state.monitor.clone();
     let mut log = ProxyRequestLog {
-        id: uuid::Uuid::new_v4().to_string(),
+        id: request_id,
         timestamp: chrono::Utc::now().timestamp_millis(),
         method,
         url: uri,
@@ -106,6 +149,8 @@ pub async fn monitor_middleware(
         response_body: None,
         input_tokens: None,
         output_tokens: None,
+        estimated_cost: None,
+        experiment_arm,
     };
 
     if content_type.contains("text/event-stream") {
@@ -133,6 +178,9 @@ pub async fn monitor_middleware(
             }
             
             if let Ok(full_tail) = std::str::from_utf8(&last_few_bytes) {
+                if let Some(failure) = stream_failure_from_tail(full_tail) {
+                    log.error = Some(failure);
+                }
                 for line in full_tail.lines().rev() {
                     if line.starts_with("data: ") && (line.contains("\"usage\"") || line.contains("\"usageMetadata\"")) {
                         let json_str = line.trim_start_matches("data: ").trim();
@@ -163,7 +211,7 @@ pub async fn monitor_middleware(
                 }
             }
             
-            if log.status >= 400 {
+            if log.status >= 400 && log.error.is_none() {
                 log.error = Some("Stream Error or Failed".to_string());
             }
             monitor.log_request(log).await;
@@ -220,3 +268,33 @@ pub async fn monitor_middleware(
         response
     }
 }
+
+/// 流以终止错误事件结束时 (上游中途出错 / 超出流限制)，返回记录到日志的失败原因
+fn stream_failure_from_tail(tail: &str) -> Option<String> {
+    if tail.contains("\"stream_interrupted\"") {
+        Some("Partial response: upstream stream interrupted".to_string())
+    } else if tail.contains("\"stream_limit_exceeded\"") {
+        Some("Partial response: stream limit exceeded".to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::common::stream_limits::interrupted_event;
+    use crate::proxy::upstream::errors::ErrorProtocol;
+
+    #[test]
+    fn test_partial_stream_failure_recorded() {
+        let event = interrupted_event(ErrorProtocol::Anthropic, "Upstream stream interrupted: reset");
+        let tail = format!("event: content_block_delta\ndata: {{}}\n\n{}", String::from_utf8_lossy(&event));
+        assert_eq!(
+            stream_failure_from_tail(&tail).as_deref(),
+            Some("Partial response: upstream stream interrupted")
+        );
+        assert_eq!(stream_failure_from_tail("data: [DONE]\n\n"), None);
+    }
+}
+
@@ -0,0 +1,391 @@
+// OpenTelemetry 请求 Span 导出 (OTLP/HTTP JSON，需启用 `otel` feature)
+// 每个 API 请求生成一个 SERVER span (接收请求 -> 路由解析 -> 上游调用 -> 响应结束)，
+// 记录请求模型/实际模型/账号/状态码，结束后异步上报到 `otlp_endpoint`
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use rand::Rng;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+const MAX_SPAN_BODY_SIZE: usize = 100 * 1024 * 1024; // 100MB
+const SERVICE_NAME: &str = "antigravity-proxy";
+const SCOPE_NAME: &str = "antigravity_tools";
+
+/// OTLP span kind: SERVER
+const SPAN_KIND_SERVER: u8 = 2;
+/// OTLP status code: UNSET / ERROR
+const STATUS_UNSET: u8 = 0;
+const STATUS_ERROR: u8 = 2;
+
+#[derive(Clone)]
+pub struct OtlpState {
+    pub endpoint: Arc<RwLock<Option<String>>>,
+    client: reqwest::Client,
+}
+
+impl OtlpState {
+    pub fn new(endpoint: Arc<RwLock<Option<String>>>) -> Self {
+        // 采集端通常位于本机或内网，不经过上游代理
+        let client = reqwest::Client::builder()
+            .no_proxy()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_or_default();
+        Self { endpoint, client }
+    }
+}
+
+/// 单个请求的 span
+#[derive(Debug, Clone)]
+pub struct RequestSpan {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub start_unix_nano: u64,
+    pub end_unix_nano: u64,
+    pub attributes: Vec<(&'static str, Value)>,
+    pub status: u16,
+}
+
+impl RequestSpan {
+    /// 编码为 OTLP/HTTP JSON 的 ExportTraceServiceRequest
+    pub fn to_otlp_json(&self) -> Value {
+        let attributes: Vec<Value> = self
+            .attributes
+            .iter()
+            .map(|(key, value)| json!({ "key": key, "value": attribute_value(value) }))
+            .collect();
+        let status = if self.status >= 500 {
+            json!({ "code": STATUS_ERROR, "message": format!("HTTP {}", self.status) })
+        } else {
+            json!({ "code": STATUS_UNSET })
+        };
+
+        let mut span = json!({
+            "traceId": self.trace_id,
+            "spanId": self.span_id,
+            "name": self.name,
+            "kind": SPAN_KIND_SERVER,
+            "startTimeUnixNano": self.start_unix_nano.to_string(),
+            "endTimeUnixNano": self.end_unix_nano.to_string(),
+            "attributes": attributes,
+            "status": status,
+        });
+        if let Some(parent) = &self.parent_span_id {
+            span["parentSpanId"] = json!(parent);
+        }
+
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        { "key": "service.name", "value": { "stringValue": SERVICE_NAME } }
+                    ]
+                },
+                "scopeSpans": [{
+                    "scope": { "name": SCOPE_NAME },
+                    "spans": [span]
+                }]
+            }]
+        })
+    }
+}
+
+fn attribute_value(value: &Value) -> Value {
+    match value {
+        Value::Bool(b) => json!({ "boolValue": b }),
+        // OTLP JSON 中 int64 以字符串表示
+        Value::Number(n) if n.is_i64() || n.is_u64() => json!({ "intValue": n.to_string() }),
+        Value::Number(n) => json!({ "doubleValue": n.as_f64() }),
+        Value::String(s) => json!({ "stringValue": s }),
+        other => json!({ "stringValue": other.to_string() }),
+    }
+}
+
+/// 补全 OTLP/HTTP traces 路径 (`http://collector:4318` -> `http://collector:4318/v1/traces`)
+pub fn traces_url(endpoint: &str) -> String {
+    let trimmed = endpoint.trim().trim_end_matches('/');
+    if trimmed.ends_with("/v1/traces") {
+        trimmed.to_string()
+    } else {
+        format!("{}/v1/traces", trimmed)
+    }
+}
+
+/// 解析 W3C `traceparent` (`00-<trace_id>-<parent_id>-<flags>`)，用于接入调用方的链路
+pub fn parse_traceparent(header: &str) -> Option<(String, String)> {
+    let parts: Vec<&str> = header.trim().split('-').collect();
+    if parts.len() != 4 || parts[0] == "ff" {
+        return None;
+    }
+    let valid = |s: &str, len: usize| {
+        s.len() == len
+            && s.chars().all(|c| c.is_ascii_hexdigit())
+            && s.chars().any(|c| c != '0')
+    };
+    if !valid(parts[1], 32) || !valid(parts[2], 16) {
+        return None;
+    }
+    Some((parts[1].to_ascii_lowercase(), parts[2].to_ascii_lowercase()))
+}
+
+pub async fn export_span(
+    client: &reqwest::Client,
+    endpoint: &str,
+    span: &RequestSpan,
+) -> Result<(), String> {
+    let resp = client
+        .post(traces_url(endpoint))
+        .json(&span.to_otlp_json())
+        .send()
+        .await
+        .map_err(|e| format!("OTLP 上报失败: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("OTLP 采集端返回 {}", resp.status()));
+    }
+    Ok(())
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..bytes).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+fn now_unix_nano() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// 仅追踪 API 请求 (健康检查、内部端点与埋点上报除外)
+fn is_traced_path(path: &str) -> bool {
+    (path.starts_with("/v1") || path.starts_with("/mcp")) && !path.contains("event_logging")
+}
+
+/// 响应结束 (流式传输完毕或客户端断开) 时补齐结束时间并上报
+struct PendingSpan {
+    span: Option<RequestSpan>,
+    client: reqwest::Client,
+    endpoint: String,
+}
+
+impl Drop for PendingSpan {
+    fn drop(&mut self) {
+        let Some(mut span) = self.span.take() else {
+            return;
+        };
+        span.end_unix_nano = now_unix_nano();
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let client = self.client.clone();
+        let endpoint = std::mem::take(&mut self.endpoint);
+        handle.spawn(async move {
+            if let Err(e) = export_span(&client, &endpoint, &span).await {
+                tracing::debug!("[OTel] {}", e);
+            }
+        });
+    }
+}
+
+pub async fn otel_middleware(
+    State(state): State<OtlpState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let endpoint = match state.endpoint.read().await.clone() {
+        Some(e) if !e.trim().is_empty() => e,
+        _ => return next.run(request).await,
+    };
+    let path = request.uri().path().to_string();
+    if !is_traced_path(&path) {
+        return next.run(request).await;
+    }
+
+    let start_unix_nano = now_unix_nano();
+    let method = request.method().to_string();
+    let (trace_id, parent_span_id) = match request
+        .headers()
+        .get("traceparent")
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_traceparent)
+    {
+        Some((trace_id, parent)) => (trace_id, Some(parent)),
+        None => (random_hex(16), None),
+    };
+
+    let mut requested_model = path
+        .strip_prefix("/v1beta/models/")
+        .and_then(|s| s.split(':').next())
+        .map(|s| s.to_string());
+    let request = if requested_model.is_none() && method == "POST" {
+        let (parts, body) = request.into_parts();
+        let bytes = match axum::body::to_bytes(body, MAX_SPAN_BODY_SIZE).await {
+            Ok(b) => b,
+            Err(e) => {
+                return (StatusCode::BAD_REQUEST, format!("Failed to read request body: {}", e))
+                    .into_response();
+            }
+        };
+        requested_model = serde_json::from_slice::<Value>(&bytes)
+            .ok()
+            .and_then(|v| v.get("model").and_then(Value::as_str).map(|s| s.to_string()));
+        Request::from_parts(parts, Body::from(bytes))
+    } else {
+        request
+    };
+
+    let response = next.run(request).await;
+
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string())
+    };
+    let status = response.status().as_u16();
+    let mut attributes: Vec<(&'static str, Value)> = vec![
+        ("http.request.method", json!(method)),
+        ("url.path", json!(path)),
+        ("http.response.status_code", json!(status)),
+    ];
+    if let Some(model) = requested_model {
+        attributes.push(("gen_ai.request.model", json!(model)));
+    }
+    if let Some(mapped) = header("X-Mapped-Model") {
+        attributes.push(("gen_ai.response.model", json!(mapped)));
+    }
+    if let Some(account) = header("X-Account-Email") {
+        attributes.push(("antigravity.account", json!(account)));
+    }
+
+    let pending = PendingSpan {
+        span: Some(RequestSpan {
+            trace_id,
+            span_id: random_hex(8),
+            parent_span_id,
+            name: format!("{} {}", method, path),
+            start_unix_nano,
+            end_unix_nano: start_unix_nano,
+            attributes,
+            status,
+        }),
+        client: state.client.clone(),
+        endpoint,
+    };
+
+    let is_stream = header("content-type")
+        .map_or(false, |ct| ct.starts_with("text/event-stream"));
+    if !is_stream {
+        drop(pending);
+        return response;
+    }
+
+    // 流式响应：span 随响应体一同存活，传输完毕后结束
+    let (parts, body) = response.into_parts();
+    let stream = body.into_data_stream().map(move |chunk| {
+        let _ = &pending;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    async fn spawn_collector() -> (String, mpsc::UnboundedReceiver<Value>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let app = axum::Router::new().route(
+            "/v1/traces",
+            axum::routing::post(move |axum::Json(body): axum::Json<Value>| {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send(body);
+                    StatusCode::OK
+                }
+            }),
+        );
+        let addr = crate::proxy::tests::support::spawn_router(app).await;
+        (format!("http://{}", addr), rx)
+    }
+
+    async fn spawn_app(endpoint: String) -> std::net::SocketAddr {
+        let app = axum::Router::new()
+            .route(
+                "/v1/chat/completions",
+                axum::routing::post(|| async {
+                    (
+                        [
+                            ("X-Mapped-Model", "gemini-3-flash"),
+                            ("X-Account-Email", "a@example.com"),
+                        ],
+                        axum::Json(json!({ "id": "resp-1" })),
+                    )
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                OtlpState::new(Arc::new(RwLock::new(Some(endpoint)))),
+                otel_middleware,
+            ));
+        crate::proxy::tests::support::spawn_router(app).await
+    }
+
+    fn attribute<'a>(span: &'a Value, key: &str) -> Option<&'a Value> {
+        span["attributes"]
+            .as_array()?
+            .iter()
+            .find(|a| a["key"] == key)
+            .map(|a| &a["value"])
+    }
+
+    #[tokio::test]
+    async fn test_request_span_exported_to_collector() {
+        let (endpoint, mut rx) = spawn_collector().await;
+        let addr = spawn_app(endpoint).await;
+
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        client
+            .post(format!("http://{}/v1/chat/completions", addr))
+            .header("traceparent", "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+            .json(&json!({ "model": "gpt-4o", "messages": [] }))
+            .send()
+            .await
+            .unwrap();
+
+        let payload = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("collector did not receive a span")
+            .unwrap();
+        let span = &payload["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+
+        assert_eq!(span["name"], "POST /v1/chat/completions");
+        assert_eq!(span["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(span["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(attribute(span, "gen_ai.request.model").unwrap()["stringValue"], "gpt-4o");
+        assert_eq!(attribute(span, "gen_ai.response.model").unwrap()["stringValue"], "gemini-3-flash");
+        assert_eq!(attribute(span, "antigravity.account").unwrap()["stringValue"], "a@example.com");
+        assert_eq!(attribute(span, "http.response.status_code").unwrap()["intValue"], "200");
+    }
+
+    #[test]
+    fn test_traces_url_and_traceparent() {
+        assert_eq!(traces_url("http://collector:4318"), "http://collector:4318/v1/traces");
+        assert_eq!(traces_url("http://collector:4318/v1/traces/"), "http://collector:4318/v1/traces");
+
+        assert!(parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none());
+        assert!(parse_traceparent("garbage").is_none());
+    }
+}
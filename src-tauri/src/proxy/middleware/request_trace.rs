@@ -0,0 +1,159 @@
+// 请求级诊断日志中间件
+// 路由解析、选号、上游状态等细节先缓存在请求作用域内：请求成功时丢弃，失败时以 error 级别一次性输出
+// 这样常规运行保持安静，失败请求无需开启 debug 也能完整排查
+use axum::{extract::Request, middleware::Next, response::Response};
+use std::sync::{Arc, Mutex};
+
+/// 单个请求最多缓存的诊断条目数，超出后丢弃最早的条目
+const MAX_TRACE_ENTRIES: usize = 64;
+
+/// 请求级诊断缓冲区
+#[derive(Debug, Default)]
+pub struct RequestTrace {
+    entries: Vec<String>,
+    dropped: usize,
+}
+
+impl RequestTrace {
+    fn push(&mut self, entry: String) {
+        if self.entries.len() >= MAX_TRACE_ENTRIES {
+            self.entries.remove(0);
+            self.dropped += 1;
+        }
+        self.entries.push(entry);
+    }
+
+    /// 生成失败请求的诊断报告
+    fn report(&self, request: &str, status: u16) -> String {
+        let mut report = format!("[Request Failed] {} -> {}", request, status);
+        if self.dropped > 0 {
+            report.push_str(&format!("\n  ... {} earlier entries dropped", self.dropped));
+        }
+        for entry in &self.entries {
+            report.push_str("\n  ");
+            report.push_str(entry);
+        }
+        report
+    }
+}
+
+pub type RequestTraceHolder = Arc<Mutex<RequestTrace>>;
+
+tokio::task_local! {
+    /// 当前请求的诊断缓冲区 (由 request_trace 中间件建立作用域)
+    static REQUEST_TRACE: RequestTraceHolder;
+}
+
+/// 记录一条请求级诊断信息；不在请求作用域内 (如后台任务) 时仅输出 debug 日志
+pub fn record(entry: impl Into<String>) {
+    let entry = entry.into();
+    tracing::debug!("{}", entry);
+    let _ = REQUEST_TRACE.try_with(|trace| {
+        if let Ok(mut trace) = trace.lock() {
+            trace.push(entry);
+        }
+    });
+}
+
+pub async fn request_trace_middleware(request: Request, next: Next) -> Response {
+    let label = format!("{} {}", request.method(), request.uri().path());
+    let holder: RequestTraceHolder = Arc::new(Mutex::new(RequestTrace::default()));
+    let response = REQUEST_TRACE.scope(holder.clone(), next.run(request)).await;
+
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        if let Ok(trace) = holder.lock() {
+            tracing::error!("{}", trace.report(&label, status.as_u16()));
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    /// 捕获 tracing 输出的 writer
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for CapturedLogs {
+        type Writer = CapturedLogs;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    impl CapturedLogs {
+        fn text(&self) -> String {
+            String::from_utf8_lossy(&self.0.lock().unwrap()).to_string()
+        }
+    }
+
+    async fn traced_handler(ok: bool) -> StatusCode {
+        record("route gpt-4o -> [gemini-3-flash]");
+        record("account a@example.com (group=agent)");
+        record("upstream generateContent model=gemini-3-flash -> 503 Service Unavailable");
+        if ok {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_request_flushes_trace_at_error_level() {
+        let logs = CapturedLogs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(logs.clone())
+            .with_ansi(false)
+            .with_max_level(tracing::Level::ERROR)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = axum::Router::new()
+            .route("/ok", axum::routing::post(|| traced_handler(true)))
+            .route("/fail", axum::routing::post(|| traced_handler(false)))
+            .layer(axum::middleware::from_fn(request_trace_middleware));
+        let addr = crate::proxy::tests::support::spawn_router(app).await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+
+        let ok = client.post(format!("http://{}/ok", addr)).send().await.unwrap();
+        assert_eq!(ok.status().as_u16(), 200);
+        assert!(logs.text().is_empty(), "successful request should stay quiet: {}", logs.text());
+
+        let failed = client.post(format!("http://{}/fail", addr)).send().await.unwrap();
+        assert_eq!(failed.status().as_u16(), 503);
+        let text = logs.text();
+        assert!(text.contains("ERROR"));
+        assert!(text.contains("[Request Failed] POST /fail -> 503"));
+        assert!(text.contains("route gpt-4o -> [gemini-3-flash]"));
+        assert!(text.contains("account a@example.com"));
+        assert!(text.contains("upstream generateContent model=gemini-3-flash -> 503"));
+    }
+
+    #[test]
+    fn test_trace_buffer_is_bounded() {
+        let mut trace = RequestTrace::default();
+        for i in 0..MAX_TRACE_ENTRIES + 3 {
+            trace.push(format!("entry {}", i));
+        }
+        let report = trace.report("POST /v1/messages", 500);
+        assert!(report.contains("3 earlier entries dropped"));
+        assert!(!report.contains("entry 2\n"));
+        assert!(report.contains(&format!("entry {}", MAX_TRACE_ENTRIES + 2)));
+    }
+}
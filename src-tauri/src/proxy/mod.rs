@@ -20,8 +20,12 @@ pub mod monitor;           // 监控
 pub mod rate_limit;        // 限流跟踪
 pub mod sticky_config;     // 粘性调度配置
 pub mod session_manager;   // 会话指纹管理
+pub mod session_cache;     // 粘性会话缓存 (LRU + TTL)
 pub mod audio;             // 音频处理模块 (PR #311)
 pub mod signature_cache;   // Signature Cache (v3.3.16)
+pub mod context_cache;     // Gemini 上下文缓存 (cachedContents)
+pub mod webhook;           // 账号健康 Webhook
+pub mod admin_token;       // 管理类内部端点的令牌
 
 
 pub use config::ProxyConfig;
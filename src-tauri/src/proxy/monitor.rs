@@ -1,9 +1,10 @@
 use serde::{Serialize, Deserialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use tokio::sync::RwLock;
 #[cfg(feature = "ui")]
 use tauri::Emitter;
 use std::sync::atomic::{AtomicBool, Ordering};
+use crate::proxy::config::{ModelPrice, MonitorMode};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyRequestLog {
@@ -22,6 +23,12 @@ pub struct ProxyRequestLog {
     pub response_body: Option<String>,
     pub input_tokens: Option<u32>,
     pub output_tokens: Option<u32>,
+    /// 按 `model_prices` 估算的费用 (无 token 用量时为空)
+    #[serde(default)]
+    pub estimated_cost: Option<f64>,
+    /// 命中策略 A/B 实验时的分组 (`<模型模式>:a|b`)
+    #[serde(default)]
+    pub experiment_arm: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -29,6 +36,126 @@ pub struct ProxyStats {
     pub total_requests: u64,
     pub success_count: u64,
     pub error_count: u64,
+    /// 按策略统计最终服务请求的候选位置 (strategy_id -> breakdown)
+    #[serde(default)]
+    pub strategy_stats: HashMap<String, StrategyServeStats>,
+    /// 命中兜底默认映射的请求数 (通常意味着缺少映射配置)
+    #[serde(default)]
+    pub default_fallback_count: u64,
+    /// 路由命中弃用模型的请求数
+    #[serde(default)]
+    pub deprecated_route_count: u64,
+    /// 估算总费用
+    #[serde(default)]
+    pub total_cost: f64,
+    /// 按模型统计的估算费用
+    #[serde(default)]
+    pub cost_by_model: HashMap<String, f64>,
+    /// 监控是否开启；关闭时以上计数不反映实际流量，UI 应显示“监控已关闭”而非 0
+    #[serde(default)]
+    pub monitoring_enabled: bool,
+}
+
+impl ProxyStats {
+    /// 费用报表 (按费用降序)
+    pub fn render_cost_report(&self) -> String {
+        let mut rows: Vec<(&String, &f64)> = self.cost_by_model.iter().collect();
+        rows.sort_by(|a, b| b.1.partial_cmp(a.1).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(b.0)));
+
+        let mut out = format!("{:<40} {:>12}\n", "Model", "Cost");
+        out.push_str(&format!("{}\n", "-".repeat(53)));
+        for (model, cost) in rows {
+            let model = if model.is_empty() { "(unknown)" } else { model.as_str() };
+            out.push_str(&format!("{:<40} {:>12.4}\n", model, cost));
+        }
+        out.push_str(&format!("{}\n", "-".repeat(53)));
+        out.push_str(&format!("{:<40} {:>12.4}\n", "Total", self.total_cost));
+        out
+    }
+}
+
+/// 监控状态 (供 UI 区分“监控关闭”与“暂无流量”)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MonitoringStatus {
+    /// 未创建监控或 enable_logging 关闭
+    Disabled,
+    /// 仅内存计数，不保存请求日志
+    CountersOnly,
+    /// 计数与请求日志
+    Full,
+}
+
+/// 实时事件广播通道容量 (订阅方处理过慢时丢弃最旧的事件)
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// 监控实时事件 (轮询接口之外的增量推送，UI 通过 Tauri 事件 `proxy://monitor-event` 接收)
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum MonitorEvent {
+    /// 请求进入代理 (仅完整监控模式)
+    RequestStarted {
+        id: String,
+        timestamp: i64,
+        method: String,
+        url: String,
+        model: Option<String>,
+    },
+    /// 请求处理完成 (流式响应在流结束后发送)
+    RequestCompleted {
+        id: String,
+        timestamp: i64,
+        status: u16,
+        duration: u64,
+        model: Option<String>,
+        mapped_model: Option<String>,
+        account_email: Option<String>,
+        input_tokens: Option<u32>,
+        output_tokens: Option<u32>,
+        estimated_cost: Option<f64>,
+        experiment_arm: Option<String>,
+    },
+    /// 账号进入冷却或被禁用
+    AccountStateChanged {
+        account: String,
+        state: AccountEventState,
+        reason: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountEventState {
+    Cooldown,
+    Disabled,
+}
+
+impl MonitorEvent {
+    /// 由请求日志生成完成事件 (不含请求/响应体)
+    pub fn completed(log: &ProxyRequestLog) -> Self {
+        MonitorEvent::RequestCompleted {
+            id: log.id.clone(),
+            timestamp: log.timestamp,
+            status: log.status,
+            duration: log.duration,
+            model: log.model.clone(),
+            mapped_model: log.mapped_model.clone(),
+            account_email: log.account_email.clone(),
+            input_tokens: log.input_tokens,
+            output_tokens: log.output_tokens,
+            estimated_cost: log.estimated_cost,
+            experiment_arm: log.experiment_arm.clone(),
+        }
+    }
+}
+
+/// 单个策略的候选命中分布
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct StrategyServeStats {
+    /// 下标为候选位置 (0 = primary)，值为该位置最终服务的请求数
+    pub served_by_position: Vec<u64>,
+    /// 按实际模型名统计的服务次数
+    pub served_by_model: HashMap<String, u64>,
 }
 
 pub struct ProxyMonitor {
@@ -36,13 +163,53 @@ pub struct ProxyMonitor {
     pub stats: RwLock<ProxyStats>,
     pub max_logs: usize,
     pub enabled: AtomicBool,
+    counters_only: AtomicBool,
+    model_prices: std::sync::RwLock<HashMap<String, ModelPrice>>,
+    events: tokio::sync::broadcast::Sender<MonitorEvent>,
     #[cfg(feature = "ui")]
     app_handle: Option<tauri::AppHandle>,
 }
 
 impl ProxyMonitor {
     #[cfg(feature = "ui")]
-    pub fn new(max_logs: usize, app_handle: Option<tauri::AppHandle>) -> Self {
+    pub fn new(max_logs: usize, mode: MonitorMode, app_handle: Option<tauri::AppHandle>) -> Self {
+        let counters_only = mode == MonitorMode::CountersOnly;
+        if !counters_only {
+            Self::init_storage();
+        }
+
+        Self {
+            logs: RwLock::new(VecDeque::with_capacity(if counters_only { 0 } else { max_logs })),
+            stats: RwLock::new(ProxyStats::default()),
+            max_logs,
+            enabled: AtomicBool::new(false),
+            counters_only: AtomicBool::new(counters_only),
+            model_prices: std::sync::RwLock::new(HashMap::new()),
+            events: tokio::sync::broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            app_handle,
+        }
+    }
+    
+    #[cfg(not(feature = "ui"))]
+    pub fn new(max_logs: usize, mode: MonitorMode, _app_handle: Option<()>) -> Self {
+        let counters_only = mode == MonitorMode::CountersOnly;
+        if !counters_only {
+            Self::init_storage();
+        }
+
+        Self {
+            logs: RwLock::new(VecDeque::with_capacity(if counters_only { 0 } else { max_logs })),
+            stats: RwLock::new(ProxyStats::default()),
+            max_logs,
+            enabled: AtomicBool::new(false),
+            counters_only: AtomicBool::new(counters_only),
+            model_prices: std::sync::RwLock::new(HashMap::new()),
+            events: tokio::sync::broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+        }
+    }
+
+    /// 初始化日志数据库并清理过期日志
+    fn init_storage() {
         if let Err(e) = crate::modules::proxy_db::init_db() {
             tracing::error!("Failed to initialize proxy DB: {}", e);
         }
@@ -60,53 +227,124 @@ impl ProxyMonitor {
                 }
             }
         });
+    }
 
-        Self {
-            logs: RwLock::new(VecDeque::with_capacity(max_logs)),
-            stats: RwLock::new(ProxyStats::default()),
-            max_logs,
-            enabled: AtomicBool::new(false),
-            app_handle,
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// 切换监控模式 (从仅计数切回完整模式时需初始化数据库)
+    pub fn set_mode(&self, mode: MonitorMode) {
+        let counters_only = mode == MonitorMode::CountersOnly;
+        let was_counters_only = self.counters_only.swap(counters_only, Ordering::Relaxed);
+        if was_counters_only && !counters_only {
+            Self::init_storage();
         }
     }
-    
-    #[cfg(not(feature = "ui"))]
-    pub fn new(max_logs: usize, _app_handle: Option<()>) -> Self {
-         if let Err(e) = crate::modules::proxy_db::init_db() {
-            tracing::error!("Failed to initialize proxy DB: {}", e);
+
+    pub fn is_counters_only(&self) -> bool {
+        self.counters_only.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> MonitoringStatus {
+        if !self.is_enabled() {
+            MonitoringStatus::Disabled
+        } else if self.is_counters_only() {
+            MonitoringStatus::CountersOnly
+        } else {
+            MonitoringStatus::Full
         }
+    }
 
-        Self {
-            logs: RwLock::new(VecDeque::with_capacity(max_logs)),
-            stats: RwLock::new(ProxyStats::default()),
-            max_logs,
-            enabled: AtomicBool::new(false),
+    /// 订阅实时事件
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<MonitorEvent> {
+        self.events.subscribe()
+    }
+
+    /// 发布实时事件 (监控关闭时丢弃；无订阅者时为空操作)
+    pub fn publish(&self, event: MonitorEvent) {
+        if !self.is_enabled() {
+            return;
+        }
+        #[cfg(feature = "ui")]
+        if let Some(app) = &self.app_handle {
+            let _ = app.emit("proxy://monitor-event", &event);
         }
+        let _ = self.events.send(event);
     }
 
-    pub fn set_enabled(&self, enabled: bool) {
-        self.enabled.store(enabled, Ordering::Relaxed);
+    /// 更新模型单价表
+    pub fn set_model_prices(&self, prices: HashMap<String, ModelPrice>) {
+        if let Ok(mut current) = self.model_prices.write() {
+            *current = prices;
+        }
     }
 
-    pub fn is_enabled(&self) -> bool {
-        self.enabled.load(Ordering::Relaxed)
+    /// 按请求的 token 用量估算费用 (优先使用路由后的模型名)
+    pub fn estimate_cost(&self, log: &ProxyRequestLog) -> Option<f64> {
+        if log.input_tokens.is_none() && log.output_tokens.is_none() {
+            return None;
+        }
+        let model = log.mapped_model.as_deref().or(log.model.as_deref()).unwrap_or_default();
+        let prices = self.model_prices.read().ok()?;
+        let price = crate::proxy::mappers::common_utils::resolve_model_entry(model, &*prices)
+            .cloned()
+            .unwrap_or_default();
+        Some(price.cost(log.input_tokens.unwrap_or(0), log.output_tokens.unwrap_or(0)))
     }
 
-    pub async fn log_request(&self, log: ProxyRequestLog) {
+    /// 仅更新统计计数
+    pub async fn record_status(&self, status: u16) {
         if !self.is_enabled() {
             return;
         }
-        tracing::info!("[Monitor] Logging request: {} {}", log.method, log.url);
-        
-        {
+        let mut stats = self.stats.write().await;
+        stats.total_requests += 1;
+        if (200..400).contains(&status) {
+            stats.success_count += 1;
+        } else {
+            stats.error_count += 1;
+        }
+    }
+
+    /// 记录策略中实际服务请求的候选位置 (非策略路由忽略)
+    pub async fn record_strategy_serve(&self, strategy_id: Option<&str>, position: usize, model: &str) {
+        let Some(strategy_id) = strategy_id else {
+            return;
+        };
+        let mut stats = self.stats.write().await;
+        let entry = stats.strategy_stats.entry(strategy_id.to_string()).or_default();
+        if entry.served_by_position.len() <= position {
+            entry.served_by_position.resize(position + 1, 0);
+        }
+        entry.served_by_position[position] += 1;
+        *entry.served_by_model.entry(model.to_string()).or_insert(0) += 1;
+    }
+
+    pub async fn log_request(&self, mut log: ProxyRequestLog) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        self.record_status(log.status).await;
+        if log.estimated_cost.is_none() {
+            log.estimated_cost = self.estimate_cost(&log);
+        }
+        if let Some(cost) = log.estimated_cost {
+            let model = log.mapped_model.clone().or_else(|| log.model.clone()).unwrap_or_default();
             let mut stats = self.stats.write().await;
-            stats.total_requests += 1;
-            if log.status >= 200 && log.status < 400 {
-                stats.success_count += 1;
-            } else {
-                stats.error_count += 1;
-            }
+            stats.total_cost += cost;
+            *stats.cost_by_model.entry(model).or_insert(0.0) += cost;
         }
+        self.publish(MonitorEvent::completed(&log));
+        if self.is_counters_only() {
+            return;
+        }
+        tracing::info!("[Monitor] Logging request: {} {}", log.method, log.url);
 
         {
             let mut logs = self.logs.write().await;
@@ -130,6 +368,9 @@ impl ProxyMonitor {
     }
 
     pub async fn get_logs(&self, limit: usize) -> Vec<ProxyRequestLog> {
+        if self.is_counters_only() {
+            return Vec::new();
+        }
         match crate::modules::proxy_db::get_logs(limit) {
             Ok(logs) => logs,
             Err(e) => {
@@ -141,13 +382,25 @@ impl ProxyMonitor {
     }
 
     pub async fn get_stats(&self) -> ProxyStats {
-        match crate::modules::proxy_db::get_stats() {
-            Ok(stats) => stats,
-            Err(e) => {
-                tracing::error!("Failed to get stats from DB: {}", e);
-                self.stats.read().await.clone()
+        let mut stats = if self.is_counters_only() {
+            self.stats.read().await.clone()
+        } else {
+            match crate::modules::proxy_db::get_stats() {
+                Ok(mut stats) => {
+                    // 策略分布仅保存在内存中
+                    stats.strategy_stats = self.stats.read().await.strategy_stats.clone();
+                    stats
+                }
+                Err(e) => {
+                    tracing::error!("Failed to get stats from DB: {}", e);
+                    self.stats.read().await.clone()
+                }
             }
-        }
+        };
+        stats.default_fallback_count = crate::proxy::common::model_mapping::default_fallback_count();
+        stats.deprecated_route_count = crate::proxy::common::model_mapping::deprecated_route_count();
+        stats.monitoring_enabled = self.is_enabled();
+        stats
     }
     
     pub async fn clear(&self) {
@@ -155,9 +408,195 @@ impl ProxyMonitor {
         logs.clear();
         let mut stats = self.stats.write().await;
         *stats = ProxyStats::default();
+        crate::proxy::common::model_mapping::reset_default_fallback_count();
+        crate::proxy::common::model_mapping::reset_deprecated_route_count();
 
+        if self.is_counters_only() {
+            return;
+        }
         if let Err(e) = crate::modules::proxy_db::clear_logs() {
             tracing::error!("Failed to clear logs in DB: {}", e);
         }
     }
-}
\ No newline at end of file
+
+    /// 将保留的请求日志导出为 CSV
+    pub async fn export_csv(&self, limit: usize) -> String {
+        render_logs_csv(&self.get_logs(limit).await)
+    }
+}
+
+/// CSV 导出的列
+pub const LOG_CSV_HEADER: &str =
+    "timestamp,model,resolved_model,account,status,latency_ms,input_tokens,output_tokens,error";
+
+/// 按 RFC 4180 转义字段：包含逗号、引号或换行时整体加引号，内部引号加倍
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// 将请求日志渲染为 CSV (含表头，时间为 UTC RFC 3339)
+pub fn render_logs_csv(logs: &[ProxyRequestLog]) -> String {
+    let opt = |v: &Option<String>| csv_field(v.as_deref().unwrap_or(""));
+    let num = |v: Option<u32>| v.map(|n| n.to_string()).unwrap_or_default();
+
+    let mut out = String::from(LOG_CSV_HEADER);
+    out.push('\n');
+    for log in logs {
+        let timestamp = chrono::DateTime::from_timestamp_millis(log.timestamp)
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+            .unwrap_or_default();
+        let row = [
+            timestamp,
+            opt(&log.model),
+            opt(&log.mapped_model),
+            opt(&log.account_email),
+            log.status.to_string(),
+            log.duration.to_string(),
+            num(log.input_tokens),
+            num(log.output_tokens),
+            opt(&log.error),
+        ];
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_log(status: u16) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status,
+            duration: 10,
+            model: Some("claude-sonnet-4-5".to_string()),
+            mapped_model: None,
+            account_email: None,
+            error: None,
+            request_body: Some("{}".to_string()),
+            response_body: None,
+            input_tokens: None,
+            output_tokens: None,
+            estimated_cost: None,
+            experiment_arm: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_counters_only_updates_stats_without_logs() {
+        let monitor = ProxyMonitor::new(1000, MonitorMode::CountersOnly, None);
+        monitor.set_enabled(true);
+
+        monitor.log_request(sample_log(200)).await;
+        monitor.log_request(sample_log(200)).await;
+        monitor.log_request(sample_log(429)).await;
+
+        let stats = monitor.get_stats().await;
+        assert_eq!(stats.total_requests, 3);
+        assert_eq!(stats.success_count, 2);
+        assert_eq!(stats.error_count, 1);
+
+        assert!(monitor.logs.read().await.is_empty());
+        assert!(monitor.get_logs(100).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_completed_request_emits_event_to_subscribers() {
+        let monitor = ProxyMonitor::new(1000, MonitorMode::CountersOnly, None);
+        let mut events = monitor.subscribe();
+
+        // 监控关闭时不推送
+        monitor.publish(MonitorEvent::completed(&sample_log(200)));
+        assert!(events.try_recv().is_err());
+
+        monitor.set_enabled(true);
+        let mut log = sample_log(200);
+        log.mapped_model = Some("gemini-3-flash".to_string());
+        log.account_email = Some("a@example.com".to_string());
+        log.output_tokens = Some(42);
+        let id = log.id.clone();
+        monitor.log_request(log).await;
+
+        match events.recv().await.unwrap() {
+            MonitorEvent::RequestCompleted { id: event_id, status, mapped_model, account_email, output_tokens, .. } => {
+                assert_eq!(event_id, id);
+                assert_eq!(status, 200);
+                assert_eq!(mapped_model.as_deref(), Some("gemini-3-flash"));
+                assert_eq!(account_email.as_deref(), Some("a@example.com"));
+                assert_eq!(output_tokens, Some(42));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        let json = serde_json::to_value(MonitorEvent::AccountStateChanged {
+            account: "a".to_string(),
+            state: AccountEventState::Cooldown,
+            reason: None,
+        })
+        .unwrap();
+        assert_eq!(json["event"], "account_state_changed");
+        assert_eq!(json["state"], "cooldown");
+    }
+
+    #[test]
+    fn test_logs_csv_header_and_escaped_row() {
+        let mut log = sample_log(502);
+        log.timestamp = 1_767_225_600_123; // 2026-01-01T00:00:00.123Z
+        log.duration = 1534;
+        log.mapped_model = Some("gemini-3-pro-high".to_string());
+        log.account_email = Some("a@example.com".to_string());
+        log.input_tokens = Some(12);
+        log.error = Some("upstream said \"bad gateway\", retry later".to_string());
+
+        let csv = render_logs_csv(&[log]);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], LOG_CSV_HEADER);
+        assert_eq!(
+            lines[1],
+            "2026-01-01T00:00:00.123Z,claude-sonnet-4-5,gemini-3-pro-high,a@example.com,502,1534,12,,\"upstream said \"\"bad gateway\"\", retry later\""
+        );
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_estimated_cost_from_usage_and_prices() {
+        let monitor = ProxyMonitor::new(1000, MonitorMode::CountersOnly, None);
+        monitor.set_enabled(true);
+        let mut prices = HashMap::new();
+        prices.insert(
+            "gemini-3-*".to_string(),
+            ModelPrice { input_per_1k: 0.5, output_per_1k: 1.5 },
+        );
+        monitor.set_model_prices(prices);
+
+        let mut log = sample_log(200);
+        log.mapped_model = Some("gemini-3-flash".to_string());
+        log.input_tokens = Some(2000);
+        log.output_tokens = Some(1000);
+        assert_eq!(monitor.estimate_cost(&log), Some(2.5));
+        monitor.log_request(log).await;
+
+        // 未配置单价的模型按 0 计
+        let mut unpriced = sample_log(200);
+        unpriced.input_tokens = Some(500);
+        monitor.log_request(unpriced).await;
+        // 无用量时不估算
+        monitor.log_request(sample_log(200)).await;
+
+        let stats = monitor.get_stats().await;
+        assert!((stats.total_cost - 2.5).abs() < 1e-9);
+        assert_eq!(stats.cost_by_model.get("gemini-3-flash"), Some(&2.5));
+        assert_eq!(stats.cost_by_model.get("claude-sonnet-4-5"), Some(&0.0));
+        assert_eq!(stats.cost_by_model.len(), 2);
+        assert!(stats.render_cost_report().contains("gemini-3-flash"));
+    }
+}
@@ -8,7 +8,9 @@ use futures::StreamExt;
 use serde_json::Value;
 use tokio::time::Duration;
 
+use crate::proxy::config::{ZaiConfig, ZaiDispatchMode};
 use crate::proxy::server::AppState;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 fn map_model_for_zai(original: &str, state: &crate::proxy::ZaiConfig) -> String {
     let m = original.to_lowercase();
@@ -67,27 +69,6 @@ fn build_client(
         .map_err(|e| format!("Failed to build HTTP client: {}", e))
 }
 
-fn copy_passthrough_headers(incoming: &HeaderMap) -> HeaderMap {
-    // Only forward a conservative set of headers to avoid leaking the local proxy key or cookies.
-    let mut out = HeaderMap::new();
-
-    for (k, v) in incoming.iter() {
-        let key = k.as_str().to_ascii_lowercase();
-        match key.as_str() {
-            "content-type" | "accept" | "anthropic-version" | "user-agent" => {
-                out.insert(k.clone(), v.clone());
-            }
-            // Some clients use these for streaming; safe to pass through.
-            "accept-encoding" | "cache-control" => {
-                out.insert(k.clone(), v.clone());
-            }
-            _ => {}
-        }
-    }
-
-    out
-}
-
 fn set_zai_auth(headers: &mut HeaderMap, incoming: &HeaderMap, api_key: &str) {
     // Prefer to keep the same auth scheme as the incoming request:
     // - If the client used x-api-key (Anthropic style), replace it.
@@ -109,6 +90,31 @@ fn set_zai_auth(headers: &mut HeaderMap, incoming: &HeaderMap, api_key: &str) {
     }
 }
 
+/// Decide whether an Anthropic request should be dispatched to z.ai instead of the Google pool.
+/// `Fallback` kicks in once no Google account can take new requests (empty pool or all limited).
+pub fn should_dispatch_to_zai(
+    zai: &ZaiConfig,
+    google_accounts: usize,
+    google_available: usize,
+    provider_rr: &AtomicUsize,
+) -> bool {
+    if !zai.enabled {
+        return false;
+    }
+    match zai.dispatch_mode {
+        ZaiDispatchMode::Off => false,
+        ZaiDispatchMode::Exclusive => true,
+        ZaiDispatchMode::Fallback => google_available == 0,
+        ZaiDispatchMode::Pooled => {
+            // Treat z.ai as exactly one extra slot in the pool.
+            // No strict guarantees: it may get 0 requests if selection never hits.
+            let total = google_accounts.saturating_add(1).max(1);
+            let slot = provider_rr.fetch_add(1, Ordering::Relaxed) % total;
+            slot == 0
+        }
+    }
+}
+
 /// Recursively remove cache_control from all nested objects/arrays
 /// [FIX #290] This is a defensive fix that works regardless of serde annotations
 pub fn deep_remove_cache_control(value: &mut Value) {
@@ -161,7 +167,10 @@ pub async fn forward_anthropic_json(
         Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
     };
 
-    let mut headers = copy_passthrough_headers(incoming_headers);
+    // Only forward configured headers to avoid leaking the local proxy key or cookies.
+    let forwarding = state.header_forwarding.read().await.clone();
+    let mut headers =
+        crate::proxy::common::header_filter::filter_forward_headers(incoming_headers, &forwarding);
     set_zai_auth(&mut headers, incoming_headers, &zai.api_key);
 
     // Ensure JSON content type.
@@ -195,6 +204,15 @@ pub async fn forward_anthropic_json(
         }
     };
 
+    // 非 JSON 错误页 (如网关 HTML) 需包装为 Anthropic 错误体
+    if !resp.status().is_success() {
+        return crate::proxy::upstream::errors::read_upstream_error(
+            crate::proxy::upstream::errors::ErrorProtocol::Anthropic,
+            resp,
+        )
+        .await;
+    }
+
     let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
 
     let mut out = Response::builder().status(status);
@@ -171,9 +171,7 @@ impl RateLimitTracker {
         
         // 2. 从 Retry-After header 提取
         if let Some(retry_after) = retry_after_header {
-            if let Ok(seconds) = retry_after.parse::<u64>() {
-                retry_after_sec = Some(seconds);
-            }
+            retry_after_sec = parse_retry_after_value(retry_after);
         }
         
         // 3. 从错误消息提取 (优先尝试 JSON 解析，再试正则)
@@ -312,46 +310,7 @@ impl RateLimitTracker {
     
     /// 通用时间解析函数：支持 "2h1m1s" 等所有格式组合
     fn parse_duration_string(&self, s: &str) -> Option<u64> {
-        tracing::debug!("[时间解析] 尝试解析: '{}'", s);
-        
-        // 使用正则表达式提取小时、分钟、秒、毫秒
-        // 支持格式："2h1m1s", "1h30m", "5m", "30s", "500ms" 等
-        let re = Regex::new(r"(?:(\d+)h)?(?:(\d+)m)?(?:(\d+(?:\.\d+)?)s)?(?:(\d+)ms)?").ok()?;
-        let caps = match re.captures(s) {
-            Some(c) => c,
-            None => {
-                tracing::warn!("[时间解析] 正则未匹配: '{}'", s);
-                return None;
-            }
-        };
-        
-        let hours = caps.get(1)
-            .and_then(|m| m.as_str().parse::<u64>().ok())
-            .unwrap_or(0);
-        let minutes = caps.get(2)
-            .and_then(|m| m.as_str().parse::<u64>().ok())
-            .unwrap_or(0);
-        let seconds = caps.get(3)
-            .and_then(|m| m.as_str().parse::<f64>().ok())
-            .unwrap_or(0.0);
-        let milliseconds = caps.get(4)
-            .and_then(|m| m.as_str().parse::<u64>().ok())
-            .unwrap_or(0);
-        
-        tracing::debug!("[时间解析] 提取结果: {}h {}m {:.3}s {}ms", hours, minutes, seconds, milliseconds);
-        
-        // 计算总秒数
-        let total_seconds = hours * 3600 + minutes * 60 + seconds.ceil() as u64 + (milliseconds + 999) / 1000;
-        
-        // 如果总秒数为 0，说明解析失败
-        if total_seconds == 0 {
-            tracing::warn!("[时间解析] 失败: '{}' (总秒数为0)", s);
-            None
-        } else {
-            tracing::info!("[时间解析] ✓ 成功: '{}' => {}秒 ({}h {}m {:.1}s)", 
-                s, total_seconds, hours, minutes, seconds);
-            Some(total_seconds)
-        }
+        parse_duration_secs(s)
     }
     
     /// 从错误消息 body 中解析重置时间
@@ -507,6 +466,102 @@ impl Default for RateLimitTracker {
     }
 }
 
+/// 通用时间解析函数：支持 "2h1m1s" 等所有格式组合
+fn parse_duration_secs(s: &str) -> Option<u64> {
+    tracing::debug!("[时间解析] 尝试解析: '{}'", s);
+    
+    // 使用正则表达式提取小时、分钟、秒、毫秒
+    // 支持格式："2h1m1s", "1h30m", "5m", "30s", "500ms" 等
+    let re = Regex::new(r"(?:(\d+)h)?(?:(\d+)m)?(?:(\d+(?:\.\d+)?)s)?(?:(\d+)ms)?").ok()?;
+    let caps = match re.captures(s) {
+        Some(c) => c,
+        None => {
+            tracing::warn!("[时间解析] 正则未匹配: '{}'", s);
+            return None;
+        }
+    };
+    
+    let hours = caps.get(1)
+        .and_then(|m| m.as_str().parse::<u64>().ok())
+        .unwrap_or(0);
+    let minutes = caps.get(2)
+        .and_then(|m| m.as_str().parse::<u64>().ok())
+        .unwrap_or(0);
+    let seconds = caps.get(3)
+        .and_then(|m| m.as_str().parse::<f64>().ok())
+        .unwrap_or(0.0);
+    let milliseconds = caps.get(4)
+        .and_then(|m| m.as_str().parse::<u64>().ok())
+        .unwrap_or(0);
+    
+    tracing::debug!("[时间解析] 提取结果: {}h {}m {:.3}s {}ms", hours, minutes, seconds, milliseconds);
+    
+    // 计算总秒数
+    let total_seconds = hours * 3600 + minutes * 60 + seconds.ceil() as u64 + (milliseconds + 999) / 1000;
+    
+    // 如果总秒数为 0，说明解析失败
+    if total_seconds == 0 {
+        tracing::warn!("[时间解析] 失败: '{}' (总秒数为0)", s);
+        None
+    } else {
+        tracing::info!("[时间解析] ✓ 成功: '{}' => {}秒 ({}h {}m {:.1}s)", 
+            s, total_seconds, hours, minutes, seconds);
+        Some(total_seconds)
+    }
+}
+
+/// 解析单个 Retry-After 值：支持秒数 ("30" / "1.5") 与 HTTP 日期 ("Wed, 21 Oct 2015 07:28:00 GMT")
+pub fn parse_retry_after_value(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(seconds);
+    }
+    if let Ok(seconds) = value.parse::<f64>() {
+        return (seconds.is_finite() && seconds >= 0.0).then(|| seconds.ceil() as u64);
+    }
+    let reset_at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delta = reset_at.timestamp() - chrono::Utc::now().timestamp();
+    Some(delta.max(0) as u64)
+}
+
+/// 上游可能携带的限流重置头 (按出现即采用、取最大值)
+const RATE_LIMIT_RESET_HEADERS: [&str; 3] = [
+    "x-ratelimit-reset",
+    "x-ratelimit-reset-requests",
+    "x-ratelimit-reset-tokens",
+];
+
+/// 从上游 429 响应头中提取冷却秒数
+/// 1. `Retry-After` (秒数或 HTTP 日期)
+/// 2. `x-ratelimit-reset*` (秒数、"1m30s" 形式或 Unix 时间戳)
+///
+/// 均缺失时返回 None，由调用方回退到指数退避
+pub fn extract_retry_after_secs(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    if let Some(seconds) = headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_retry_after_value)
+    {
+        return Some(seconds);
+    }
+
+    RATE_LIMIT_RESET_HEADERS
+        .iter()
+        .filter_map(|name| headers.get(*name).and_then(|v| v.to_str().ok()))
+        .filter_map(|raw| {
+            let raw = raw.trim();
+            match raw.parse::<u64>() {
+                // 超过 10 位数量级视为 Unix 时间戳
+                Ok(ts) if ts > 1_000_000_000 => {
+                    Some(ts.saturating_sub(chrono::Utc::now().timestamp().max(0) as u64))
+                }
+                Ok(seconds) => Some(seconds),
+                Err(_) => parse_retry_after_value(raw).or_else(|| parse_duration_secs(raw)),
+            }
+        })
+        .max()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -572,4 +627,58 @@ mod tests {
         // 应该被识别为 RateLimitExceeded，而不是 QuotaExhausted
         assert_eq!(reason, RateLimitReason::RateLimitExceeded);
     }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> reqwest::header::HeaderMap {
+        let mut map = reqwest::header::HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, value.parse().unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_retry_after_header_sets_precise_cooldown() {
+        let tracker = RateLimitTracker::new();
+        let resp_headers = headers(&[("retry-after", "30")]);
+        let retry_after = extract_retry_after_secs(&resp_headers).map(|s| s.to_string());
+        assert_eq!(retry_after.as_deref(), Some("30"));
+
+        // body 表明是配额耗尽 (默认退避 60s)，但 Retry-After 更精确
+        let body = r#"{"error":{"details":[{"reason":"QUOTA_EXHAUSTED"}]}}"#;
+        let info = tracker
+            .parse_from_error("acc1", 429, retry_after.as_deref(), body, None)
+            .unwrap();
+        assert_eq!(info.retry_after_sec, 30);
+        let wait = tracker.get_remaining_wait("acc1");
+        assert!(wait > 25 && wait <= 30);
+    }
+
+    #[test]
+    fn test_retry_after_http_date() {
+        let reset_at = chrono::Utc::now() + chrono::Duration::seconds(45);
+        let resp_headers = headers(&[("retry-after", &reset_at.to_rfc2822())]);
+        let secs = extract_retry_after_secs(&resp_headers).unwrap();
+        assert!(secs > 40 && secs <= 45);
+    }
+
+    #[test]
+    fn test_ratelimit_reset_headers_fallback() {
+        let resp_headers = headers(&[
+            ("x-ratelimit-reset-requests", "1m30s"),
+            ("x-ratelimit-reset-tokens", "12"),
+        ]);
+        assert_eq!(extract_retry_after_secs(&resp_headers), Some(90));
+        assert_eq!(extract_retry_after_secs(&headers(&[("retry-after", "soon")])), None);
+    }
+
+    #[test]
+    fn test_missing_header_falls_back_to_backoff() {
+        let tracker = RateLimitTracker::new();
+        assert_eq!(extract_retry_after_secs(&headers(&[])), None);
+        let body = r#"{"error":{"details":[{"reason":"QUOTA_EXHAUSTED"}]}}"#;
+        let first = tracker.parse_from_error("acc1", 429, None, body, None).unwrap();
+        let second = tracker.parse_from_error("acc1", 429, None, body, None).unwrap();
+        assert_eq!(first.retry_after_sec, 60);
+        assert_eq!(second.retry_after_sec, 300);
+    }
 }
@@ -5,6 +5,7 @@ pub struct ProxySecurityConfig {
     pub auth_mode: ProxyAuthMode,
     pub api_key: String,
     pub allow_lan_access: bool,
+    pub public_version_endpoint: bool,
 }
 
 impl ProxySecurityConfig {
@@ -13,6 +14,7 @@ impl ProxySecurityConfig {
             auth_mode: config.auth_mode.clone(),
             api_key: config.api_key.clone(),
             allow_lan_access: config.allow_lan_access,
+            public_version_endpoint: config.public_version_endpoint,
         }
     }
 
@@ -40,6 +42,7 @@ mod tests {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
             allow_lan_access: false,
+            public_version_endpoint: true,
         };
         assert!(matches!(s.effective_auth_mode(), ProxyAuthMode::Off));
     }
@@ -50,6 +53,7 @@ mod tests {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
             allow_lan_access: true,
+            public_version_endpoint: true,
         };
         assert!(matches!(
             s.effective_auth_mode(),
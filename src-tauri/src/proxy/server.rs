@@ -19,8 +19,27 @@ pub struct AppState {
     pub token_manager: Arc<TokenManager>,
     pub custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
     pub openai_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    pub openai_family_rules: Arc<tokio::sync::RwLock<Vec<crate::proxy::config::OpenAIFamilyRule>>>,
     pub anthropic_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
     pub model_strategies: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::ModelStrategy>>>,
+    pub model_output_limits: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::ModelOutputLimit>>>,
+    pub reasoning_effort_budgets: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::ReasoningEffortBudgets>>>,
+    pub model_transforms: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::ModelTransform>>>,
+    pub model_system_prompts: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    pub family_mapping_override: Arc<tokio::sync::RwLock<Option<bool>>>, // 家族映射全局覆盖
+    pub advertise_all_aliases: Arc<tokio::sync::RwLock<bool>>, // 模型列表是否包含内置别名
+    pub servable_models: Arc<tokio::sync::RwLock<crate::proxy::config::ServableModelsFilter>>, // 模型列表仅列出账号池可服务的模型
+    pub model_canonicalization: Arc<tokio::sync::RwLock<crate::proxy::config::ModelCanonicalizationConfig>>, // 路由前模型名规范化规则
+    pub auto_model: Arc<tokio::sync::RwLock<crate::proxy::config::AutoModelConfig>>, // 哨兵模型名的自动选型目标
+    pub model_deprecation: Arc<tokio::sync::RwLock<crate::proxy::config::ModelDeprecationPolicy>>, // 弃用模型告警/替换策略
+    pub model_unavailable_action: Arc<tokio::sync::RwLock<crate::proxy::config::ModelUnavailableAction>>, // 账号无权访问模型时的处理方式
+    pub strip_variant_suffix_on_unavailable: Arc<tokio::sync::RwLock<bool>>, // 变体不可用时同账号改用基础模型
+    pub default_model_per_protocol: Arc<tokio::sync::RwLock<crate::proxy::config::DefaultModelPerProtocol>>, // 省略模型名时的协议默认模型
+    pub allowed_client_models: Arc<tokio::sync::RwLock<Vec<String>>>, // 客户端允许请求的模型 (空表示不限制)
+    pub strategy_experiments: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::StrategyExperiment>>>, // 策略 A/B 实验
+    pub default_fallback_policy: Arc<tokio::sync::RwLock<crate::proxy::config::ModelFallbackPolicy>>, // 非策略目标的默认回退策略
+    pub thinking_passthrough_prefixes: Arc<tokio::sync::RwLock<Vec<String>>>, // 允许 thinking 直通的模型名前缀
+    pub family_overrides_builtin: Arc<tokio::sync::RwLock<bool>>, // 家族映射是否优先于内置直通模型
     #[allow(dead_code)]
     pub request_timeout: u64, // API 请求超时(秒)
     #[allow(dead_code)]
@@ -28,23 +47,89 @@ pub struct AppState {
     #[allow(dead_code)]
     pub upstream_proxy: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     pub upstream: Arc<crate::proxy::upstream::client::UpstreamClient>,
+    pub admin_token: Option<Arc<String>>, // 本次运行的管理令牌 (生成失败时为 None，管理端点一律拒绝)
     pub zai: Arc<RwLock<crate::proxy::ZaiConfig>>,
     pub provider_rr: Arc<AtomicUsize>,
     pub zai_vision_mcp: Arc<crate::proxy::zai_vision_mcp::ZaiVisionMcpState>,
     pub monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
     pub experimental: Arc<RwLock<crate::proxy::config::ExperimentalConfig>>,
+    pub streaming: Arc<RwLock<crate::proxy::config::StreamingConfig>>,
+    pub request_timeout_policy: Arc<RwLock<crate::proxy::common::request_timeout::RequestTimeoutPolicy>>,
+    pub context_cache: Arc<RwLock<crate::proxy::config::ContextCacheConfig>>,
+    pub context_cache_handles: Arc<crate::proxy::context_cache::ContextCache>, // 上下文缓存句柄表
+    pub reasoning_output: Arc<RwLock<crate::proxy::config::ReasoningOutputMode>>,
+    pub readiness_requires_upstream: Arc<RwLock<bool>>, // /readyz 是否要求上游调用成功
+    pub readiness_threshold: Arc<RwLock<crate::proxy::config::ReadinessThresholdConfig>>, // /readyz 最少健康账号数
+    pub maintenance: Arc<RwLock<crate::proxy::config::MaintenanceModeConfig>>, // 维护模式 (/readyz 期间报告未就绪)
+    pub header_forwarding: Arc<RwLock<crate::proxy::config::HeaderForwardingConfig>>, // 透传上游的请求头过滤
+    pub hedge_delay_ms: Arc<RwLock<Option<u64>>>, // 对冲请求延迟
+    pub truncation_warning: Arc<RwLock<bool>>, // 截断响应是否附加告警头
+    pub tool_limit: Arc<RwLock<crate::proxy::config::ToolLimitConfig>>, // 单请求工具数量上限
+    pub remote_media: Arc<RwLock<crate::proxy::config::RemoteMediaConfig>>, // 远程媒体下载策略
+    pub schema_retry: Arc<RwLock<crate::proxy::config::SchemaRetryConfig>>, // Schema 类 400 的严格清洗重试
+    pub logprobs: Arc<RwLock<crate::proxy::config::LogprobsConfig>>, // OpenAI logprobs 参数转换
+    pub max_total_attempts: Arc<RwLock<usize>>, // 单请求上游调用总数上限 (0 = 不限制)
+    pub schema_inline_max_bytes: Arc<RwLock<usize>>, // 工具 schema 展开 $ref 后的体积上限 (0 = 不限制)
+    pub safety_fallback: Arc<RwLock<crate::proxy::config::SafetyFallbackConfig>>, // 安全拦截时回退到下一个候选
+    pub auto_trim_history: Arc<RwLock<crate::proxy::config::AutoTrimHistoryConfig>>, // 超出上下文窗口时裁剪历史消息
+    pub runtime_toggles: Arc<RwLock<serde_json::Value>>, // `/version` 展示的运行时开关
 }
 
 /// Axum 服务器实例
 pub struct AxumServer {
     shutdown_tx: Option<oneshot::Sender<()>>,
+    cache_warmer: tokio::task::JoinHandle<()>,
     custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
     openai_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    openai_family_rules: Arc<tokio::sync::RwLock<Vec<crate::proxy::config::OpenAIFamilyRule>>>,
     anthropic_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
     model_strategies: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::ModelStrategy>>>,
+    model_output_limits: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::ModelOutputLimit>>>,
+    reasoning_effort_budgets: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::ReasoningEffortBudgets>>>,
+    model_transforms: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::ModelTransform>>>,
+    model_system_prompts: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    family_mapping_override: Arc<tokio::sync::RwLock<Option<bool>>>,
+    advertise_all_aliases: Arc<tokio::sync::RwLock<bool>>,
+    servable_models: Arc<tokio::sync::RwLock<crate::proxy::config::ServableModelsFilter>>,
+    model_canonicalization: Arc<tokio::sync::RwLock<crate::proxy::config::ModelCanonicalizationConfig>>,
+    auto_model: Arc<tokio::sync::RwLock<crate::proxy::config::AutoModelConfig>>,
+    model_deprecation: Arc<tokio::sync::RwLock<crate::proxy::config::ModelDeprecationPolicy>>,
+    model_unavailable_action: Arc<tokio::sync::RwLock<crate::proxy::config::ModelUnavailableAction>>,
+    strip_variant_suffix_state: Arc<tokio::sync::RwLock<bool>>,
+    default_model_state: Arc<tokio::sync::RwLock<crate::proxy::config::DefaultModelPerProtocol>>,
+    allowed_client_models_state: Arc<tokio::sync::RwLock<Vec<String>>>,
+    strategy_experiments_state: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::StrategyExperiment>>>,
+    default_fallback_policy_state: Arc<tokio::sync::RwLock<crate::proxy::config::ModelFallbackPolicy>>,
+    thinking_passthrough_prefixes_state: Arc<tokio::sync::RwLock<Vec<String>>>,
+    family_overrides_builtin_state: Arc<tokio::sync::RwLock<bool>>,
     proxy_state: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
     zai_state: Arc<RwLock<crate::proxy::ZaiConfig>>,
+    streaming_state: Arc<RwLock<crate::proxy::config::StreamingConfig>>,
+    request_timeout_policy_state: Arc<RwLock<crate::proxy::common::request_timeout::RequestTimeoutPolicy>>,
+    context_cache_state: Arc<RwLock<crate::proxy::config::ContextCacheConfig>>,
+    context_cache_handles: Arc<crate::proxy::context_cache::ContextCache>,
+    dedupe_enabled_state: Arc<RwLock<bool>>,
+    reasoning_output_state: Arc<RwLock<crate::proxy::config::ReasoningOutputMode>>,
+    readiness_requires_upstream_state: Arc<RwLock<bool>>,
+    readiness_threshold_state: Arc<RwLock<crate::proxy::config::ReadinessThresholdConfig>>,
+    otlp_endpoint_state: Arc<RwLock<Option<String>>>,
+    header_forwarding_state: Arc<RwLock<crate::proxy::config::HeaderForwardingConfig>>,
+    hedge_delay_state: Arc<RwLock<Option<u64>>>,
+    truncation_warning_state: Arc<RwLock<bool>>,
+    tool_limit_state: Arc<RwLock<crate::proxy::config::ToolLimitConfig>>,
+    remote_media_state: Arc<RwLock<crate::proxy::config::RemoteMediaConfig>>,
+    schema_retry_state: Arc<RwLock<crate::proxy::config::SchemaRetryConfig>>,
+    logprobs_state: Arc<RwLock<crate::proxy::config::LogprobsConfig>>,
+    max_total_attempts_state: Arc<RwLock<usize>>,
+    schema_inline_max_bytes_state: Arc<RwLock<usize>>,
+    safety_fallback_state: Arc<RwLock<crate::proxy::config::SafetyFallbackConfig>>,
+    auto_trim_history_state: Arc<RwLock<crate::proxy::config::AutoTrimHistoryConfig>>,
+    runtime_toggles_state: Arc<RwLock<serde_json::Value>>,
+    enabled_endpoints_state: Arc<RwLock<crate::proxy::config::EnabledEndpointsConfig>>,
+    debug_headers_state: Arc<RwLock<bool>>,
+    maintenance_state: Arc<RwLock<crate::proxy::config::MaintenanceModeConfig>>,
+    response_compression: crate::proxy::middleware::compression::CompressionToggle,
 }
 
 impl AxumServer {
@@ -57,15 +142,98 @@ impl AxumServer {
             let mut m = self.openai_mapping.write().await;
             *m = config.openai_mapping.clone();
         }
+        {
+            let mut m = self.openai_family_rules.write().await;
+            *m = config.openai_family_rules.clone();
+        }
         {
             let mut m = self.anthropic_mapping.write().await;
             *m = config.anthropic_mapping.clone();
         }
         {
-            let mut m = self.model_strategies.write().await;
-            *m = config.model_strategies.clone();
+            let mut m = self.model_output_limits.write().await;
+            *m = config.model_output_limits.clone();
+        }
+        {
+            let mut m = self.reasoning_effort_budgets.write().await;
+            *m = config.reasoning_effort_budgets.clone();
+        }
+        {
+            let mut m = self.model_transforms.write().await;
+            *m = config.model_transforms.clone();
+        }
+        {
+            let mut m = self.model_system_prompts.write().await;
+            *m = config.model_system_prompts.clone();
+        }
+        {
+            let mut m = self.advertise_all_aliases.write().await;
+            *m = config.advertise_all_aliases;
+        }
+        {
+            let mut m = self.servable_models.write().await;
+            *m = config.servable_models_filter();
+        }
+        {
+            let mut m = self.model_canonicalization.write().await;
+            *m = config.model_canonicalization.clone();
+        }
+        {
+            let mut m = self.auto_model.write().await;
+            *m = config.auto_model.clone();
+        }
+        {
+            let mut m = self.model_deprecation.write().await;
+            *m = config.model_deprecation_policy();
+        }
+        {
+            let mut m = self.model_unavailable_action.write().await;
+            *m = config.model_unavailable_action;
         }
-        tracing::debug!("模型映射 (Anthropic/OpenAI/Custom/Strategy) 已全量热更新");
+        {
+            let mut s = self.strip_variant_suffix_state.write().await;
+            *s = config.strip_variant_suffix_on_unavailable;
+        }
+        {
+            let mut d = self.default_model_state.write().await;
+            *d = config.default_model_per_protocol.clone();
+        }
+        {
+            let mut a = self.allowed_client_models_state.write().await;
+            *a = config.allowed_client_models.clone();
+        }
+        {
+            let mut e = self.strategy_experiments_state.write().await;
+            *e = config.strategy_experiments.clone();
+        }
+        {
+            let mut p = self.default_fallback_policy_state.write().await;
+            *p = config.default_fallback_policy.clone();
+        }
+        {
+            let mut p = self.thinking_passthrough_prefixes_state.write().await;
+            *p = config.thinking_passthrough_prefixes.clone();
+        }
+        {
+            let mut f = self.family_overrides_builtin_state.write().await;
+            *f = config.family_overrides_builtin;
+        }
+        tracing::debug!("模型映射 (Anthropic/OpenAI/Custom/OutputLimit/ReasoningEffort/Transform/Deprecation/Unavailable) 已全量热更新");
+    }
+
+    /// 更新模型策略池 (重新读取策略目录并与内联策略合并，请求路径只读取内存中的策略池)
+    pub async fn update_model_strategies(&self, config: &crate::proxy::config::ProxyConfig) {
+        let strategies = config.effective_model_strategies();
+        let count = strategies.len();
+        *self.model_strategies.write().await = strategies;
+        tracing::info!("模型策略已热更新 ({} 个)", count);
+    }
+
+    /// 更新家族映射全局覆盖
+    pub async fn update_family_mapping(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut m = self.family_mapping_override.write().await;
+        *m = config.family_mapping_override();
+        tracing::debug!("家族映射覆盖已热更新: {:?}", *m);
     }
 
     /// 更新代理配置
@@ -81,62 +249,327 @@ impl AxumServer {
         tracing::info!("反代服务安全配置已热更新");
     }
 
-    pub async fn update_zai(&self, config: &crate::proxy::config::ProxyConfig) {
-        let mut zai = self.zai_state.write().await;
-        *zai = config.zai.clone();
-        tracing::info!("z.ai 配置已热更新");
+    pub async fn update_zai(&self, config: &crate::proxy::config::ProxyConfig) -> Result<(), String> {
+        swap_zai_config(&self.zai_state, &config.zai).await
+    }
+
+    pub async fn update_streaming(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut streaming = self.streaming_state.write().await;
+        *streaming = config.streaming.clone();
+        tracing::info!("流式响应配置已热更新");
+    }
+
+    /// 更新单请求超时覆盖策略
+    pub async fn update_request_timeout_policy(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut policy = self.request_timeout_policy_state.write().await;
+        *policy = crate::proxy::common::request_timeout::RequestTimeoutPolicy::from_config(config);
+        tracing::info!("单请求超时覆盖策略已热更新");
+    }
+
+    /// 更新上下文缓存配置
+    pub async fn update_context_cache(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut cache = self.context_cache_state.write().await;
+        *cache = config.context_cache.clone();
+        if !cache.enabled {
+            self.context_cache_handles.clear();
+        }
+        tracing::info!("上下文缓存配置已热更新");
+    }
+
+    /// 更新并发重复请求合并开关
+    pub async fn update_dedupe(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut enabled = self.dedupe_enabled_state.write().await;
+        *enabled = config.dedupe_in_flight;
+        tracing::info!("重复请求合并开关已热更新: {}", *enabled);
+    }
+
+    /// 更新推理内容输出模式
+    pub async fn update_reasoning_output(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut mode = self.reasoning_output_state.write().await;
+        *mode = config.reasoning_output;
+        tracing::info!("推理内容输出模式已热更新: {:?}", *mode);
+    }
+
+    /// 更新深度就绪检查开关
+    pub async fn update_readiness(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut enabled = self.readiness_requires_upstream_state.write().await;
+        *enabled = config.readiness_requires_upstream;
+        tracing::info!("深度就绪检查开关已热更新: {}", *enabled);
+        let mut threshold = self.readiness_threshold_state.write().await;
+        *threshold = config.readiness_threshold.clone();
+        tracing::info!("就绪健康账号阈值已热更新: {:?}", *threshold);
+    }
+
+    /// 更新 OTLP span 导出地址
+    pub async fn update_otlp(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut endpoint = self.otlp_endpoint_state.write().await;
+        *endpoint = config.otlp_endpoint.clone();
+        tracing::info!("OTLP 导出地址已热更新: {:?}", *endpoint);
+    }
+
+    /// 更新透传请求头过滤规则
+    pub async fn update_header_forwarding(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut forwarding = self.header_forwarding_state.write().await;
+        *forwarding = config.header_forwarding.clone();
+        tracing::info!("透传请求头过滤规则已热更新");
+    }
+
+    /// 更新对冲请求延迟
+    pub async fn update_hedging(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut delay = self.hedge_delay_state.write().await;
+        *delay = config.hedge_delay_ms;
+        tracing::info!("对冲请求延迟已热更新: {:?}", *delay);
+    }
+
+    /// 更新截断告警开关
+    pub async fn update_truncation_warning(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut enabled = self.truncation_warning_state.write().await;
+        *enabled = config.truncation_warning_header;
+        tracing::info!("截断告警开关已热更新: {}", *enabled);
+    }
+
+    /// 更新工具数量上限
+    pub async fn update_tool_limit(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut limit = self.tool_limit_state.write().await;
+        *limit = config.tool_limit.clone();
+        tracing::info!("工具数量上限已热更新: {:?}", *limit);
+    }
+
+    /// 更新远程媒体下载策略
+    pub async fn update_remote_media(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut remote_media = self.remote_media_state.write().await;
+        *remote_media = config.remote_media.clone();
+        tracing::info!("远程媒体下载策略已热更新: {:?}", *remote_media);
+    }
+
+    /// 更新 Schema 类 400 的重试配置
+    pub async fn update_schema_retry(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut retry = self.schema_retry_state.write().await;
+        *retry = config.schema_retry.clone();
+        tracing::info!("Schema 错误重试配置已热更新: enabled={}", retry.enabled);
+    }
+
+    /// 更新工具 schema 展开体积上限
+    pub async fn update_schema_inline_limit(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut limit = self.schema_inline_max_bytes_state.write().await;
+        *limit = config.schema_inline_max_bytes;
+        tracing::info!("Schema 展开体积上限已热更新: {} bytes", *limit);
+    }
+
+    /// 更新 logprobs 参数转换配置
+    pub async fn update_logprobs(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut logprobs = self.logprobs_state.write().await;
+        *logprobs = config.logprobs.clone();
+        tracing::info!("logprobs 转换配置已热更新: {:?}", *logprobs);
+    }
+
+    /// 更新单请求上游调用总数上限
+    pub async fn update_attempt_budget(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut max = self.max_total_attempts_state.write().await;
+        *max = config.max_total_attempts;
+        tracing::info!("单请求尝试预算已热更新: {}", *max);
+    }
+
+    /// 更新安全拦截回退配置
+    pub async fn update_safety_fallback(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut fallback = self.safety_fallback_state.write().await;
+        *fallback = config.safety_fallback.clone();
+        tracing::info!("安全拦截回退配置已热更新: enabled={}", fallback.enabled);
+    }
+
+    /// 更新历史消息自动裁剪配置
+    pub async fn update_auto_trim_history(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut trim = self.auto_trim_history_state.write().await;
+        *trim = config.auto_trim_history.clone();
+        tracing::info!("历史消息自动裁剪配置已热更新: enabled={}", trim.enabled);
+    }
+
+    /// 更新 `/version` 展示的运行时开关
+    pub async fn update_runtime_toggles(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut toggles = self.runtime_toggles_state.write().await;
+        *toggles = crate::modules::build_info::runtime_toggles(config);
+    }
+
+    /// 更新端点启用开关
+    pub async fn update_enabled_endpoints(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut endpoints = self.enabled_endpoints_state.write().await;
+        *endpoints = config.enabled_endpoints.clone();
+        tracing::info!("端点启用开关已热更新: {:?}", *endpoints);
+    }
+
+    /// 更新调试响应头开关
+    pub async fn update_debug_headers(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut enabled = self.debug_headers_state.write().await;
+        *enabled = config.expose_debug_headers;
+        tracing::info!("调试响应头开关已热更新: {}", *enabled);
+    }
+
+    /// 更新维护模式
+    pub async fn update_maintenance_mode(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut maintenance = self.maintenance_state.write().await;
+        *maintenance = config.maintenance_mode.clone();
+        tracing::info!("维护模式已热更新: {}", maintenance.enabled);
     }
-    /// 启动 Axum 服务器
+
+    /// 更新响应压缩开关
+    pub fn update_response_compression(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.response_compression.set(config.response_compression);
+        tracing::info!("响应压缩开关已热更新: {}", config.response_compression);
+    }
+    /// 按配置启动 Axum 服务器
     pub async fn start(
-        host: String,
-        port: u16,
+        config: &crate::proxy::config::ProxyConfig,
         token_manager: Arc<TokenManager>,
-        anthropic_mapping: std::collections::HashMap<String, String>,
-        openai_mapping: std::collections::HashMap<String, String>,
-        custom_mapping: std::collections::HashMap<String, String>,
-        model_strategies: std::collections::HashMap<String, crate::proxy::config::ModelStrategy>,
-        _request_timeout: u64,
-        upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
-        security_config: crate::proxy::ProxySecurityConfig,
-        zai_config: crate::proxy::ZaiConfig,
         monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
-        experimental_config: crate::proxy::config::ExperimentalConfig,
+    ) -> Result<(Self, tokio::task::JoinHandle<()>), String> {
+        let upstream = crate::proxy::upstream::client::UpstreamClient::with_timeouts(
+            Some(config.upstream_proxy.clone()),
+            crate::proxy::upstream::client::UpstreamTimeouts::from_config(config),
+        );
+        Self::start_with_upstream(config, token_manager, monitor, Arc::new(upstream)).await
+    }
 
+    /// 使用给定的上游客户端启动 (测试中指向本地 mock 上游)
+    pub(crate) async fn start_with_upstream(
+        config: &crate::proxy::config::ProxyConfig,
+        token_manager: Arc<TokenManager>,
+        monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
+        upstream: Arc<crate::proxy::upstream::client::UpstreamClient>,
     ) -> Result<(Self, tokio::task::JoinHandle<()>), String> {
-        let custom_mapping_state = Arc::new(tokio::sync::RwLock::new(custom_mapping));
-        let openai_mapping_state = Arc::new(tokio::sync::RwLock::new(openai_mapping));
-        let anthropic_mapping_state = Arc::new(tokio::sync::RwLock::new(anthropic_mapping));
-        let model_strategies_state = Arc::new(tokio::sync::RwLock::new(model_strategies));
-	        let proxy_state = Arc::new(tokio::sync::RwLock::new(upstream_proxy.clone()));
-	        let security_state = Arc::new(RwLock::new(security_config));
-	        let zai_state = Arc::new(RwLock::new(zai_config));
-	        let provider_rr = Arc::new(AtomicUsize::new(0));
+        let custom_mapping_state = Arc::new(tokio::sync::RwLock::new(config.custom_mapping.clone()));
+        let openai_mapping_state = Arc::new(tokio::sync::RwLock::new(config.openai_mapping.clone()));
+        let openai_family_rules_state = Arc::new(tokio::sync::RwLock::new(config.openai_family_rules.clone()));
+        let anthropic_mapping_state = Arc::new(tokio::sync::RwLock::new(config.anthropic_mapping.clone()));
+        let model_strategies_state = Arc::new(tokio::sync::RwLock::new(config.effective_model_strategies()));
+        let model_output_limits_state = Arc::new(tokio::sync::RwLock::new(config.model_output_limits.clone()));
+        let reasoning_effort_budgets_state = Arc::new(tokio::sync::RwLock::new(config.reasoning_effort_budgets.clone()));
+        let model_transforms_state = Arc::new(tokio::sync::RwLock::new(config.model_transforms.clone()));
+        let model_system_prompts_state = Arc::new(tokio::sync::RwLock::new(config.model_system_prompts.clone()));
+        let family_mapping_override_state = Arc::new(tokio::sync::RwLock::new(config.family_mapping_override()));
+        let advertise_all_aliases_state = Arc::new(tokio::sync::RwLock::new(config.advertise_all_aliases));
+        let servable_models_state = Arc::new(tokio::sync::RwLock::new(config.servable_models_filter()));
+        let model_canonicalization_state = Arc::new(tokio::sync::RwLock::new(config.model_canonicalization.clone()));
+        let auto_model_state = Arc::new(tokio::sync::RwLock::new(config.auto_model.clone()));
+        let model_deprecation_state = Arc::new(tokio::sync::RwLock::new(config.model_deprecation_policy()));
+        let model_unavailable_action_state = Arc::new(tokio::sync::RwLock::new(config.model_unavailable_action));
+        let strip_variant_suffix_state = Arc::new(tokio::sync::RwLock::new(config.strip_variant_suffix_on_unavailable));
+        let default_model_state = Arc::new(tokio::sync::RwLock::new(config.default_model_per_protocol.clone()));
+        let allowed_client_models_state = Arc::new(tokio::sync::RwLock::new(config.allowed_client_models.clone()));
+        let strategy_experiments_state = Arc::new(tokio::sync::RwLock::new(config.strategy_experiments.clone()));
+        let default_fallback_policy_state = Arc::new(tokio::sync::RwLock::new(config.default_fallback_policy.clone()));
+        let thinking_passthrough_prefixes_state = Arc::new(tokio::sync::RwLock::new(config.thinking_passthrough_prefixes.clone()));
+        let family_overrides_builtin_state = Arc::new(tokio::sync::RwLock::new(config.family_overrides_builtin));
+	        let proxy_state = Arc::new(tokio::sync::RwLock::new(config.upstream_proxy.clone()));
+	        let security_state = Arc::new(RwLock::new(crate::proxy::ProxySecurityConfig::from_proxy_config(config)));
+	        let zai_state = Arc::new(RwLock::new(config.zai.clone()));
+	        // z.ai 池化分发的轮询起点同样取自调度随机源
+	        let provider_rr = Arc::new(AtomicUsize::new(
+	            token_manager.random_index(token_manager.len().saturating_add(1)),
+	        ));
 	        let zai_vision_mcp_state =
 	            Arc::new(crate::proxy::zai_vision_mcp::ZaiVisionMcpState::new());
-	        let experimental_state = Arc::new(RwLock::new(experimental_config));
+	        let experimental_state = Arc::new(RwLock::new(config.experimental.clone()));
+	        let streaming_state = Arc::new(RwLock::new(config.streaming.clone()));
+	        let request_timeout_policy_state = Arc::new(RwLock::new(crate::proxy::common::request_timeout::RequestTimeoutPolicy::from_config(config)));
+	        let context_cache_state = Arc::new(RwLock::new(config.context_cache.clone()));
+	        let context_cache_handles = Arc::new(crate::proxy::context_cache::ContextCache::new());
+	        let dedupe_state = crate::proxy::middleware::dedupe::DedupeState::new(config.dedupe_in_flight);
+	        let reasoning_output_state = Arc::new(RwLock::new(config.reasoning_output));
+	        let readiness_requires_upstream_state = Arc::new(RwLock::new(config.readiness_requires_upstream));
+	        let readiness_threshold_state = Arc::new(RwLock::new(config.readiness_threshold.clone()));
+	        let otlp_endpoint_state = Arc::new(RwLock::new(config.otlp_endpoint.clone()));
+	        let header_forwarding_state = Arc::new(RwLock::new(config.header_forwarding.clone()));
+	        let hedge_delay_state = Arc::new(RwLock::new(config.hedge_delay_ms));
+	        let truncation_warning_state = Arc::new(RwLock::new(config.truncation_warning_header));
+	        let tool_limit_state = Arc::new(RwLock::new(config.tool_limit.clone()));
+	        let remote_media_state = Arc::new(RwLock::new(config.remote_media.clone()));
+	        let schema_retry_state = Arc::new(RwLock::new(config.schema_retry.clone()));
+	        let logprobs_state = Arc::new(RwLock::new(config.logprobs.clone()));
+	        let max_total_attempts_state = Arc::new(RwLock::new(config.max_total_attempts));
+	        let schema_inline_max_bytes_state = Arc::new(RwLock::new(config.schema_inline_max_bytes));
+	        let safety_fallback_state = Arc::new(RwLock::new(config.safety_fallback.clone()));
+	        let auto_trim_history_state = Arc::new(RwLock::new(config.auto_trim_history.clone()));
+	        let runtime_toggles_state = Arc::new(RwLock::new(crate::modules::build_info::runtime_toggles(config)));
+	        let enabled_endpoints_state = Arc::new(RwLock::new(config.enabled_endpoints.clone()));
+	        let debug_headers_state = Arc::new(RwLock::new(config.expose_debug_headers));
+	        let maintenance_state = Arc::new(RwLock::new(config.maintenance_mode.clone()));
+	        let response_compression =
+	            crate::proxy::middleware::compression::CompressionToggle::new(config.response_compression);
+	        let admin_token = match crate::proxy::admin_token::issue(token_manager.data_dir()) {
+	            Ok(token) => Some(Arc::new(token)),
+	            Err(e) => {
+	                tracing::warn!("{}，账号排空等管理端点不可用", e);
+	                None
+	            }
+	        };
 
 	        let state = AppState {
 	            token_manager: token_manager.clone(),
 	            custom_mapping: custom_mapping_state.clone(),
                 openai_mapping: openai_mapping_state.clone(),
+                openai_family_rules: openai_family_rules_state.clone(),
                 anthropic_mapping: anthropic_mapping_state.clone(),
                 model_strategies: model_strategies_state.clone(),
+                model_output_limits: model_output_limits_state.clone(),
+                reasoning_effort_budgets: reasoning_effort_budgets_state.clone(),
+                model_transforms: model_transforms_state.clone(),
+                model_system_prompts: model_system_prompts_state.clone(),
+                family_mapping_override: family_mapping_override_state.clone(),
+                advertise_all_aliases: advertise_all_aliases_state.clone(),
+                servable_models: servable_models_state.clone(),
+                model_canonicalization: model_canonicalization_state.clone(),
+                auto_model: auto_model_state.clone(),
+                model_deprecation: model_deprecation_state.clone(),
+                model_unavailable_action: model_unavailable_action_state.clone(),
+                strip_variant_suffix_on_unavailable: strip_variant_suffix_state.clone(),
+                default_model_per_protocol: default_model_state.clone(),
+                allowed_client_models: allowed_client_models_state.clone(),
+                strategy_experiments: strategy_experiments_state.clone(),
+                default_fallback_policy: default_fallback_policy_state.clone(),
+                thinking_passthrough_prefixes: thinking_passthrough_prefixes_state.clone(),
+                family_overrides_builtin: family_overrides_builtin_state.clone(),
 	            request_timeout: 300, // 5分钟超时
             thought_signature_map: Arc::new(tokio::sync::Mutex::new(
                 std::collections::HashMap::new(),
             )),
             upstream_proxy: proxy_state.clone(),
-            upstream: Arc::new(crate::proxy::upstream::client::UpstreamClient::new(Some(
-                upstream_proxy.clone(),
-            ))),
+            upstream,
+            admin_token,
             zai: zai_state.clone(),
             provider_rr: provider_rr.clone(),
             zai_vision_mcp: zai_vision_mcp_state,
             monitor: monitor.clone(),
             experimental: experimental_state,
+            streaming: streaming_state.clone(),
+            request_timeout_policy: request_timeout_policy_state.clone(),
+            context_cache: context_cache_state.clone(),
+            context_cache_handles: context_cache_handles.clone(),
+            reasoning_output: reasoning_output_state.clone(),
+            readiness_requires_upstream: readiness_requires_upstream_state.clone(),
+            readiness_threshold: readiness_threshold_state.clone(),
+            maintenance: maintenance_state.clone(),
+            header_forwarding: header_forwarding_state.clone(),
+            hedge_delay_ms: hedge_delay_state.clone(),
+            truncation_warning: truncation_warning_state.clone(),
+            tool_limit: tool_limit_state.clone(),
+            remote_media: remote_media_state.clone(),
+            schema_retry: schema_retry_state.clone(),
+            logprobs: logprobs_state.clone(),
+            max_total_attempts: max_total_attempts_state.clone(),
+            schema_inline_max_bytes: schema_inline_max_bytes_state.clone(),
+            safety_fallback: safety_fallback_state.clone(),
+            auto_trim_history: auto_trim_history_state.clone(),
+            runtime_toggles: runtime_toggles_state.clone(),
         };
 
 
+        let warmer_parts = (
+            state.context_cache_handles.clone(),
+            state.context_cache.clone(),
+            state.token_manager.clone(),
+            state.upstream.clone(),
+        );
+
         // 构建路由 - 使用新架构的 handlers！
         use crate::proxy::handlers;
         // 构建路由
@@ -199,40 +632,127 @@ impl AxumServer {
                 post(handlers::gemini::handle_count_tokens),
             ) // Specific route priority
             .route("/v1/models/detect", post(handlers::common::handle_detect_model))
+            .route("/v1/models/:id", get(handlers::common::handle_get_model_detail))
             .route("/internal/warmup", post(handlers::warmup::handle_warmup)) // 内部预热端点
+            .route("/internal/accounts/:id/drain", post(handlers::accounts::handle_drain_account)) // 删除前排空账号 (需管理令牌)
             .route("/v1/api/event_logging/batch", post(silent_ok_handler))
             .route("/v1/api/event_logging", post(silent_ok_handler))
             .route("/healthz", get(health_check_handler))
+            .route("/readyz", get(readiness_handler))
+            .route("/version", get(version_handler))
             .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
+            .layer(axum::middleware::from_fn_with_state(
+                debug_headers_state.clone(),
+                crate::proxy::middleware::debug_headers::debug_headers_middleware,
+            ))
+            .layer(axum::middleware::from_fn(
+                crate::proxy::middleware::request_trace::request_trace_middleware,
+            ))
+            .layer(axum::middleware::from_fn(
+                crate::proxy::middleware::account_slot::account_slot_middleware,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                dedupe_state.clone(),
+                crate::proxy::middleware::dedupe::dedupe_middleware,
+            ))
             .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::monitor::monitor_middleware))
+            // 解压/压缩位于监控与去重之外，二者始终看到明文请求/响应体
+            .layer(crate::proxy::middleware::compression::response_compression_layer(
+                response_compression.clone(),
+            ))
+            .layer(crate::proxy::middleware::compression::request_decompression_layer())
             .layer(TraceLayer::new_for_http())
+            // 维护模式位于鉴权之内、监控之外：短路的请求不计入监控，也不会触达上游
+            .layer(axum::middleware::from_fn_with_state(
+                maintenance_state.clone(),
+                crate::proxy::middleware::maintenance::maintenance_middleware,
+            ))
             .layer(axum::middleware::from_fn_with_state(
                 security_state.clone(),
                 crate::proxy::middleware::auth_middleware,
             ))
+            .layer(axum::middleware::from_fn_with_state(
+                enabled_endpoints_state.clone(),
+                crate::proxy::middleware::endpoints::endpoint_toggle_middleware,
+            ))
             .layer(crate::proxy::middleware::cors_layer())
             .with_state(state);
 
+        #[cfg(feature = "otel")]
+        let app = app.layer(axum::middleware::from_fn_with_state(
+            crate::proxy::middleware::otel::OtlpState::new(otlp_endpoint_state.clone()),
+            crate::proxy::middleware::otel::otel_middleware,
+        ));
+
         // 绑定地址
-        let addr = format!("{}:{}", host, port);
+        let addr = format!("{}:{}", config.get_bind_address(), config.port);
         let listener = tokio::net::TcpListener::bind(&addr)
             .await
             .map_err(|e| format!("地址 {} 绑定失败: {}", addr, e))?;
 
         tracing::info!("反代服务器启动在 http://{}", addr);
 
+        // 上下文缓存定时预热 (每轮按最新配置决定是否执行)
+        let (warm_cache, warm_config, warm_tokens, warm_upstream) = warmer_parts;
+        let cache_warmer = crate::proxy::context_cache::spawn_warmer(warm_cache, warm_config, warm_tokens, warm_upstream);
+
         // 创建关闭通道
         let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
 
         let server_instance = Self {
             shutdown_tx: Some(shutdown_tx),
+            cache_warmer,
             custom_mapping: custom_mapping_state.clone(),
             openai_mapping: openai_mapping_state.clone(),
+            openai_family_rules: openai_family_rules_state.clone(),
             anthropic_mapping: anthropic_mapping_state.clone(),
             model_strategies: model_strategies_state.clone(),
+            model_output_limits: model_output_limits_state.clone(),
+            reasoning_effort_budgets: reasoning_effort_budgets_state,
+            model_transforms: model_transforms_state,
+            model_system_prompts: model_system_prompts_state,
+            family_mapping_override: family_mapping_override_state.clone(),
+            advertise_all_aliases: advertise_all_aliases_state,
+            servable_models: servable_models_state,
+            model_canonicalization: model_canonicalization_state,
+            auto_model: auto_model_state,
+            model_deprecation: model_deprecation_state,
+            model_unavailable_action: model_unavailable_action_state,
+            strip_variant_suffix_state,
+            default_model_state,
+            allowed_client_models_state,
+            strategy_experiments_state,
+            default_fallback_policy_state,
+            thinking_passthrough_prefixes_state,
+            family_overrides_builtin_state,
             proxy_state,
             security_state,
             zai_state,
+            streaming_state,
+            request_timeout_policy_state,
+            context_cache_state,
+            context_cache_handles,
+            dedupe_enabled_state: dedupe_state.enabled.clone(),
+            reasoning_output_state,
+            readiness_requires_upstream_state,
+            readiness_threshold_state,
+            otlp_endpoint_state,
+            header_forwarding_state,
+            hedge_delay_state,
+            truncation_warning_state,
+            tool_limit_state,
+            remote_media_state,
+            schema_retry_state,
+            logprobs_state,
+            max_total_attempts_state,
+            schema_inline_max_bytes_state,
+            safety_fallback_state,
+            auto_trim_history_state,
+            runtime_toggles_state,
+            enabled_endpoints_state,
+            debug_headers_state,
+            maintenance_state,
+            response_compression,
         };
 
         // 在新任务中启动服务器
@@ -280,9 +800,32 @@ impl AxumServer {
         if let Some(tx) = self.shutdown_tx.take() {
             let _ = tx.send(());
         }
+        self.cache_warmer.abort();
     }
 }
 
+/// 校验后原子替换 z.ai 配置；校验失败时保留旧配置
+/// 请求处理时按请求读取快照，进行中的请求继续使用旧配置
+async fn swap_zai_config(
+    state: &RwLock<crate::proxy::ZaiConfig>,
+    new_config: &crate::proxy::ZaiConfig,
+) -> Result<(), String> {
+    new_config.validate()?;
+    let mut zai = state.write().await;
+    if zai.dispatch_mode != new_config.dispatch_mode || zai.enabled != new_config.enabled {
+        tracing::info!(
+            "z.ai 分发模式切换: {:?}(enabled={}) -> {:?}(enabled={})",
+            zai.dispatch_mode,
+            zai.enabled,
+            new_config.dispatch_mode,
+            new_config.enabled
+        );
+    }
+    *zai = new_config.clone();
+    tracing::info!("z.ai 配置已热更新");
+    Ok(())
+}
+
 // ===== API 处理器 (旧代码已移除，由 src/proxy/handlers/* 接管) =====
 
 /// 健康检查处理器
@@ -293,7 +836,240 @@ async fn health_check_handler() -> Response {
     .into_response()
 }
 
+/// 版本信息：crate 版本、git 提交、编译 feature 与关键运行时开关
+async fn version_handler(axum::extract::State(state): axum::extract::State<AppState>) -> Response {
+    Json(version_report(state.runtime_toggles.read().await.clone())).into_response()
+}
+
+fn version_report(runtime: serde_json::Value) -> serde_json::Value {
+    let build = crate::modules::build_info::build_info();
+    serde_json::json!({
+        "version": build.version,
+        "git_hash": build.git_hash,
+        "features": build.features,
+        "runtime": runtime,
+    })
+}
+
+/// 就绪检查
+/// - 基础模式：存在可用账号 (或启用了 z.ai) 即就绪
+/// - 健康账号阈值 (`readiness_threshold`)：未启用 z.ai 时，健康账号数需达到配置的最小值
+/// - 深度模式 (`readiness_requires_upstream`)：还需至少一次上游调用 (真实请求或预热) 成功
+async fn readiness_handler(axum::extract::State(state): axum::extract::State<AppState>) -> Response {
+    // 维护期间报告未就绪，让负载均衡摘除本实例
+    if state.maintenance.read().await.enabled {
+        let body = serde_json::json!({ "status": "not_ready", "reason": "maintenance mode" });
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response();
+    }
+    let zai = state.zai.read().await;
+    let zai_active = zai.enabled && !matches!(zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
+    drop(zai);
+    let has_backend = state.token_manager.len() > 0 || zai_active;
+    // z.ai 可以独立承接流量，此时不要求 Google 账号池达到阈值
+    let healthy = if zai_active {
+        None
+    } else {
+        let required = state.readiness_threshold.read().await.required_healthy(state.token_manager.len());
+        Some((state.token_manager.available_count(), required))
+    };
+    let requires_upstream = *state.readiness_requires_upstream.read().await;
+    let (status, body) = readiness_report(has_backend, healthy, requires_upstream, state.upstream.has_upstream_success());
+    (status, Json(body)).into_response()
+}
+
+/// `healthy` 为 (健康账号数, 所需健康账号数)，为空时不检查阈值
+fn readiness_report(
+    has_backend: bool,
+    healthy: Option<(usize, usize)>,
+    requires_upstream: bool,
+    upstream_ok: bool,
+) -> (StatusCode, serde_json::Value) {
+    let reason = if !has_backend {
+        Some("no accounts available")
+    } else if healthy.is_some_and(|(count, required)| count < required) {
+        Some("not enough healthy accounts")
+    } else if requires_upstream && !upstream_ok {
+        Some("waiting for first successful upstream call")
+    } else {
+        None
+    };
+    let mut body = match reason {
+        None => serde_json::json!({ "status": "ready", "upstream_ok": upstream_ok }),
+        Some(reason) => serde_json::json!({ "status": "not_ready", "reason": reason, "upstream_ok": upstream_ok }),
+    };
+    if let Some((count, required)) = healthy {
+        body["healthy_accounts"] = serde_json::json!(count);
+        body["required_healthy_accounts"] = serde_json::json!(required);
+    }
+    let status = if reason.is_none() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, body)
+}
+
 /// 静默成功处理器 (用于拦截遥测日志等)
 async fn silent_ok_handler() -> Response {
     StatusCode::OK.into_response()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::upstream::client::UpstreamClient;
+
+    #[tokio::test]
+    async fn test_version_route_requires_auth_by_default() {
+        use crate::proxy::tests::support;
+        let data_dir = support::temp_data_dir("ag-version");
+        let config = crate::proxy::config::ProxyConfig {
+            auth_mode: crate::proxy::ProxyAuthMode::Strict,
+            api_key: "sk-version".to_string(),
+            ..Default::default()
+        };
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, support::closed_local_addr()).await;
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/version", addr);
+
+        // 默认不公开：未带密钥时拒绝，避免泄露鉴权模式等运行时开关
+        let resp = client.get(&url).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        let resp = client.get(&url).bearer_auth("sk-version").send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert!(body["runtime"]["auth_mode"].is_string());
+    }
+
+    #[test]
+    fn test_version_report_contains_crate_version() {
+        let report = version_report(serde_json::json!({ "response_compression": true }));
+        assert_eq!(report["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(report["runtime"]["response_compression"], true);
+        assert!(report["features"].is_array());
+    }
+
+    #[test]
+    fn test_readiness_basic_mode_only_needs_accounts() {
+        assert_eq!(readiness_report(false, None, false, false).0, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(readiness_report(true, None, false, false).0, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_readiness_flips_after_first_upstream_success() {
+        let upstream = UpstreamClient::new(None);
+        // 账号级代理派生的实例与原实例共享上游状态
+        let per_account = upstream.for_account_proxy(None).unwrap();
+
+        let (status, body) = readiness_report(true, None, true, upstream.has_upstream_success());
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["reason"], "waiting for first successful upstream call");
+
+        // 模拟一次成功的上游调用
+        per_account.mark_upstream_success();
+
+        let (status, body) = readiness_report(true, None, true, upstream.has_upstream_success());
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["upstream_ok"], true);
+
+        // 没有账号时仍未就绪
+        assert_eq!(readiness_report(false, None, true, true).0, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_readiness_reports_not_ready_during_maintenance() {
+        use crate::proxy::tests::support;
+        let data_dir = support::temp_data_dir("ag-readiness-maintenance");
+        support::write_account(&data_dir, "a", serde_json::json!({}));
+        let mut config = crate::proxy::config::ProxyConfig {
+            maintenance_mode: crate::proxy::config::MaintenanceModeConfig { enabled: true, message: None },
+            ..Default::default()
+        };
+        let (server, addr) = support::start_proxy(config.clone(), &data_dir, support::closed_local_addr()).await;
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+
+        let resp = client.get(format!("http://{}/readyz", addr)).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["reason"], "maintenance mode");
+
+        // 关闭维护模式后恢复就绪
+        config.maintenance_mode.enabled = false;
+        server.update_maintenance_mode(&config).await;
+        let resp = client.get(format!("http://{}/readyz", addr)).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_readiness_waits_for_min_healthy_accounts() {
+        let data_dir = crate::proxy::tests::support::temp_data_dir("ag-readiness");
+        for id in ["a", "b", "c", "d"] {
+            crate::proxy::tests::support::write_account(&data_dir, id, serde_json::json!({}));
+        }
+        let manager = TokenManager::new(data_dir.clone());
+        assert_eq!(manager.load_accounts().await.unwrap(), 4);
+        // 启动时所有账号均未通过健康检查 (限流中)
+        for id in ["a", "b", "c", "d"] {
+            manager.mark_rate_limited(id, 429, Some("60"), "");
+        }
+        let threshold = crate::proxy::config::ReadinessThresholdConfig {
+            min_healthy_accounts: 2,
+            min_healthy_fraction: None,
+        };
+        let report = |manager: &TokenManager| {
+            let required = threshold.required_healthy(manager.len());
+            readiness_report(manager.len() > 0, Some((manager.available_count(), required)), false, false)
+        };
+
+        let (status, body) = report(&manager);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["reason"], "not enough healthy accounts");
+        assert_eq!(body["required_healthy_accounts"], 2);
+
+        // 账号逐个恢复健康，达到阈值前保持 503
+        manager.clear_rate_limit("a");
+        assert_eq!(report(&manager).0, StatusCode::SERVICE_UNAVAILABLE);
+        manager.clear_rate_limit("b");
+        let (status, body) = report(&manager);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["healthy_accounts"], 2);
+
+        // 比例阈值按账号总数向上取整：4 * 0.6 -> 3
+        let fraction = crate::proxy::config::ReadinessThresholdConfig {
+            min_healthy_accounts: 1,
+            min_healthy_fraction: Some(0.6),
+        };
+        assert_eq!(fraction.required_healthy(4), 3);
+        assert_eq!(crate::proxy::config::ReadinessThresholdConfig::default().required_healthy(4), 1);
+        assert!(crate::proxy::config::ReadinessThresholdConfig { min_healthy_fraction: Some(1.5), ..Default::default() }.validate().is_err());
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[tokio::test]
+    async fn test_zai_hot_swap_off_to_fallback_routes_overflow() {
+        use crate::proxy::providers::zai_anthropic::should_dispatch_to_zai;
+        use crate::proxy::{ZaiConfig, ZaiDispatchMode};
+
+        let state = RwLock::new(ZaiConfig::default());
+        let rr = AtomicUsize::new(0);
+        // Off: Google 账号全部不可用也不走 z.ai
+        assert!(!should_dispatch_to_zai(&state.read().await.clone(), 2, 0, &rr));
+
+        // 缺少 api_key 的配置被拒绝，旧配置保持不变
+        let invalid = ZaiConfig {
+            enabled: true,
+            dispatch_mode: ZaiDispatchMode::Fallback,
+            ..ZaiConfig::default()
+        };
+        assert!(swap_zai_config(&state, &invalid).await.is_err());
+        assert_eq!(state.read().await.dispatch_mode, ZaiDispatchMode::Off);
+
+        let mut fallback = invalid.clone();
+        fallback.api_key = "test-key".to_string();
+        swap_zai_config(&state, &fallback).await.unwrap();
+
+        let snapshot = state.read().await.clone();
+        // 仍有可用账号时继续走 Google，池耗尽后溢出到 z.ai
+        assert!(!should_dispatch_to_zai(&snapshot, 2, 1, &rr));
+        assert!(should_dispatch_to_zai(&snapshot, 2, 0, &rr));
+    }
+}
@@ -0,0 +1,225 @@
+// 粘性会话缓存
+// 会话 -> 绑定值 (账号等) 的 LRU 映射，带空闲过期时间与容量上限，避免长时间运行时会话状态无限增长
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 默认会话空闲过期时间 (秒)
+pub const DEFAULT_SESSION_TTL_SECS: u64 = 3600;
+/// 默认最大会话数
+pub const DEFAULT_MAX_SESSIONS: usize = 10_000;
+
+struct Entry<V> {
+    value: V,
+    last_access: Instant,
+    seq: u64,
+}
+
+struct Inner<V> {
+    entries: HashMap<String, Entry<V>>,
+    /// 访问序号 -> key，序号越小越久未访问
+    order: BTreeMap<u64, String>,
+    next_seq: u64,
+    /// 空闲过期时间 (None 表示不过期)
+    ttl: Option<Duration>,
+    /// 容量上限 (0 表示不限制)
+    max_entries: usize,
+}
+
+impl<V> Inner<V> {
+    fn touch(&mut self, key: &str, now: Instant) {
+        let seq = self.next_seq;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.order.remove(&entry.seq);
+            entry.seq = seq;
+            entry.last_access = now;
+            self.order.insert(seq, key.to_string());
+            self.next_seq += 1;
+        }
+    }
+
+    fn remove(&mut self, key: &str) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.seq);
+        Some(entry.value)
+    }
+
+    fn is_expired(&self, entry: &Entry<V>, now: Instant) -> bool {
+        self.ttl
+            .is_some_and(|ttl| now.saturating_duration_since(entry.last_access) >= ttl)
+    }
+
+    /// 清理过期条目：按访问顺序从最旧的开始，遇到未过期的即停止
+    fn purge_expired(&mut self, now: Instant) {
+        while let Some((_, key)) = self.order.first_key_value() {
+            let expired = self.entries.get(key).is_none_or(|e| self.is_expired(e, now));
+            if !expired {
+                break;
+            }
+            let key = key.clone();
+            self.remove(&key);
+        }
+    }
+
+    fn evict_over_capacity(&mut self) {
+        if self.max_entries == 0 {
+            return;
+        }
+        while self.entries.len() > self.max_entries {
+            let Some((_, key)) = self.order.pop_first() else { break };
+            self.entries.remove(&key);
+        }
+    }
+}
+
+pub struct SessionCache<V> {
+    inner: Mutex<Inner<V>>,
+}
+
+impl<V: Clone> SessionCache<V> {
+    /// `ttl_secs` 为 0 表示不过期，`max_entries` 为 0 表示不限制容量
+    pub fn new(ttl_secs: u64, max_entries: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                next_seq: 0,
+                ttl: (ttl_secs > 0).then(|| Duration::from_secs(ttl_secs)),
+                max_entries,
+            }),
+        }
+    }
+
+    /// 热更新过期时间与容量上限，超出新上限的最旧条目立即淘汰
+    pub fn configure(&self, ttl_secs: u64, max_entries: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.ttl = (ttl_secs > 0).then(|| Duration::from_secs(ttl_secs));
+        inner.max_entries = max_entries;
+        inner.purge_expired(Instant::now());
+        inner.evict_over_capacity();
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        self.get_at(key, Instant::now())
+    }
+
+    fn get_at(&self, key: &str, now: Instant) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap();
+        let expired = inner.is_expired(inner.entries.get(key)?, now);
+        if expired {
+            inner.remove(key);
+            return None;
+        }
+        inner.touch(key, now);
+        inner.entries.get(key).map(|e| e.value.clone())
+    }
+
+    pub fn insert(&self, key: String, value: V) {
+        self.insert_at(key, value, Instant::now());
+    }
+
+    fn insert_at(&self, key: String, value: V, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.purge_expired(now);
+        inner.remove(&key);
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+        inner.order.insert(seq, key.clone());
+        inner.entries.insert(
+            key,
+            Entry {
+                value,
+                last_access: now,
+                seq,
+            },
+        );
+        inner.evict_over_capacity();
+    }
+
+    pub fn remove(&self, key: &str) -> Option<V> {
+        self.inner.lock().unwrap().remove(key)
+    }
+
+    /// 仅保留满足条件的条目
+    pub fn retain(&self, mut keep: impl FnMut(&V) -> bool) {
+        let mut inner = self.inner.lock().unwrap();
+        let dropped: Vec<String> = inner
+            .entries
+            .iter()
+            .filter(|(_, e)| !keep(&e.value))
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in dropped {
+            inner.remove(&key);
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.order.clear();
+    }
+
+    /// 当前会话数 (先清理已过期的条目)
+    pub fn len(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        inner.purge_expired(Instant::now());
+        inner.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_expire_after_ttl() {
+        let cache = SessionCache::new(60, 0);
+        let start = Instant::now();
+        cache.insert_at("s1".to_string(), "a".to_string(), start);
+        cache.insert_at("s2".to_string(), "b".to_string(), start);
+
+        // 访问会刷新空闲计时
+        assert_eq!(cache.get_at("s1", start + Duration::from_secs(50)).as_deref(), Some("a"));
+
+        let later = start + Duration::from_secs(61);
+        assert_eq!(cache.get_at("s2", later), None);
+        assert_eq!(cache.get_at("s1", later).as_deref(), Some("a"));
+        assert_eq!(cache.get_at("s1", later + Duration::from_secs(60)), None);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used_past_limit() {
+        let cache = SessionCache::new(0, 2);
+        cache.insert("s1".to_string(), 1);
+        cache.insert("s2".to_string(), 2);
+        // s1 最近被访问，超限时淘汰 s2
+        assert_eq!(cache.get("s1"), Some(1));
+        cache.insert("s3".to_string(), 3);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("s2"), None);
+        assert_eq!(cache.get("s1"), Some(1));
+        assert_eq!(cache.get("s3"), Some(3));
+
+        // 缩小容量时立即淘汰最旧条目
+        cache.configure(0, 1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get("s3"), Some(3));
+    }
+
+    #[test]
+    fn test_retain_drops_bindings() {
+        let cache = SessionCache::new(0, 0);
+        cache.insert("s1".to_string(), "a".to_string());
+        cache.insert("s2".to_string(), "b".to_string());
+        cache.retain(|account| account != "a");
+        assert_eq!(cache.get("s1"), None);
+        assert_eq!(cache.get("s2").as_deref(), Some("b"));
+    }
+}
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::proxy::common::priority_queue::{RequestPriority, RequestPriorityHint};
 
 /// 调度模式枚举
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -24,6 +27,112 @@ pub struct StickySessionConfig {
     pub mode: SchedulingMode,
     /// 缓存优先模式下的最大等待时间 (秒)
     pub max_wait_seconds: u64,
+    /// 单账号最大并发请求数 (0 表示不限制，可被账号级配置覆盖)
+    #[serde(default)]
+    pub max_concurrency_per_account: u32,
+    /// 单账号每分钟最大请求数 (0 表示不限制，可被账号级配置覆盖)
+    #[serde(default)]
+    pub max_rpm_per_account: u32,
+    /// 单账号每日最大请求数 (0 表示不限制，可被账号级配置覆盖)
+    #[serde(default)]
+    pub daily_request_cap_per_account: u64,
+    /// 每日计数的重置时刻 (本地时间 `HH:MM`)
+    #[serde(default = "default_daily_reset_time")]
+    pub daily_reset_time: String,
+    /// 会话绑定的空闲过期时间 (秒，0 表示不过期)
+    #[serde(default = "default_session_ttl_secs")]
+    pub session_ttl_secs: u64,
+    /// 最多保留的会话绑定数，超出时淘汰最久未使用的会话 (0 表示不限制)
+    #[serde(default = "default_max_sessions")]
+    pub max_sessions: usize,
+    /// 灰度账号可参与调度的请求比例 (百分比，0-100)，未抽中的请求不会分配给灰度账号
+    #[serde(default = "default_canary_traffic_percent")]
+    pub canary_traffic_percent: u32,
+    /// 灰度账号累计成功多少次后自动转正 (0 表示不自动转正)
+    #[serde(default = "default_canary_promotion_successes")]
+    pub canary_promotion_successes: u32,
+    /// 槽位占满时的请求优先级排队
+    #[serde(default)]
+    pub priority_queue: PriorityQueueConfig,
+}
+
+/// 请求优先级队列配置
+/// 所有账号槽位均被占满时按优先级排队等待，而不是立即返回错误
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityQueueConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 队列最多容纳的等待请求数，超出时直接报错
+    #[serde(default = "default_priority_queue_max_len")]
+    pub max_queue_len: usize,
+    /// 单个请求的最长排队时间 (秒)
+    #[serde(default = "default_priority_queue_max_wait_secs")]
+    pub max_wait_secs: u64,
+    /// 未携带 `X-Priority` 且 API Key 未配置默认值时使用的优先级
+    #[serde(default)]
+    pub default_priority: RequestPriority,
+    /// 按 API Key 指定的默认优先级 (`X-Priority` 请求头优先)
+    #[serde(default)]
+    pub api_key_priorities: HashMap<String, RequestPriority>,
+}
+
+fn default_priority_queue_max_len() -> usize {
+    64
+}
+
+fn default_priority_queue_max_wait_secs() -> u64 {
+    30
+}
+
+impl Default for PriorityQueueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_queue_len: default_priority_queue_max_len(),
+            max_wait_secs: default_priority_queue_max_wait_secs(),
+            default_priority: RequestPriority::Normal,
+            api_key_priorities: HashMap::new(),
+        }
+    }
+}
+
+impl PriorityQueueConfig {
+    /// 解析请求优先级：`X-Priority` 请求头 > API Key 默认值 > 全局默认值
+    pub fn resolve(&self, hint: &RequestPriorityHint) -> RequestPriority {
+        hint.header
+            .or_else(|| {
+                hint.api_key
+                    .as_ref()
+                    .and_then(|key| self.api_key_priorities.get(key).copied())
+            })
+            .unwrap_or(self.default_priority)
+    }
+}
+
+fn default_canary_traffic_percent() -> u32 {
+    5
+}
+
+fn default_canary_promotion_successes() -> u32 {
+    20
+}
+
+fn default_session_ttl_secs() -> u64 {
+    crate::proxy::session_cache::DEFAULT_SESSION_TTL_SECS
+}
+
+fn default_max_sessions() -> usize {
+    crate::proxy::session_cache::DEFAULT_MAX_SESSIONS
+}
+
+fn default_daily_reset_time() -> String {
+    "00:00".to_string()
+}
+
+/// 解析 `HH:MM` 格式的每日重置时刻
+pub fn parse_daily_reset_time(value: &str) -> Result<chrono::NaiveTime, String> {
+    chrono::NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map_err(|_| format!("daily_reset_time 格式无效 (应为 HH:MM): {}", value))
 }
 
 impl Default for StickySessionConfig {
@@ -31,6 +140,15 @@ impl Default for StickySessionConfig {
         Self {
             mode: SchedulingMode::Balance,
             max_wait_seconds: 60,
+            max_concurrency_per_account: 0,
+            max_rpm_per_account: 0,
+            daily_request_cap_per_account: 0,
+            daily_reset_time: default_daily_reset_time(),
+            session_ttl_secs: default_session_ttl_secs(),
+            max_sessions: default_max_sessions(),
+            canary_traffic_percent: default_canary_traffic_percent(),
+            canary_promotion_successes: default_canary_promotion_successes(),
+            priority_queue: PriorityQueueConfig::default(),
         }
     }
 }
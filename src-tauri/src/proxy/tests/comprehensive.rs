@@ -3,6 +3,8 @@ mod tests {
     use crate::proxy::mappers::claude::models::{
         ClaudeRequest, Message, MessageContent, ContentBlock, ThinkingConfig, Tool
     };
+    use crate::proxy::common::json_schema::DEFAULT_INLINE_SIZE_LIMIT;
+    use crate::proxy::common::model_mapping::default_thinking_passthrough_prefixes;
     use crate::proxy::mappers::claude::request::transform_claude_request_in;
     use crate::proxy::mappers::claude::thinking_utils::{analyze_conversation_state, close_tool_loop_for_thinking};
     use serde_json::json;
@@ -40,7 +42,12 @@ mod tests {
 
         // 2. 执行转换
         // 如果修复生效，这里应该成功返回，且 thinkingConfig 被保留
-        let result = transform_claude_request_in(&req, "test-project");
+        let result = transform_claude_request_in(
+            &req,
+            "test-project",
+            &default_thinking_passthrough_prefixes(),
+            DEFAULT_INLINE_SIZE_LIMIT,
+        );
         assert!(result.is_ok(), "First thinking request should be allowed");
 
         let body = result.unwrap();
@@ -0,0 +1,727 @@
+// 通过完整的反代服务驱动各协议处理器 (上游为本地 mock)
+#[cfg(test)]
+mod tests {
+    use crate::proxy::config::{ModelUnavailableAction, ProxyConfig};
+    use crate::proxy::tests::support::{self, error_response, text_response};
+    use serde_json::json;
+
+    async fn post_json(url: String, body: serde_json::Value) -> (reqwest::StatusCode, serde_json::Value) {
+        let resp = reqwest::Client::new().post(url).json(&body).send().await.unwrap();
+        let status = resp.status();
+        let text = resp.text().await.unwrap();
+        (status, serde_json::from_str(&text).unwrap_or(json!(text)))
+    }
+
+    fn claude_body(model: &str) -> serde_json::Value {
+        json!({
+            "model": model,
+            "max_tokens": 64,
+            "messages": [{ "role": "user", "content": "hello there, how are you today?" }]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_model_unavailable_rotates_to_account_with_access() {
+        let data_dir = support::temp_data_dir("ag-unavailable");
+        for id in ["a", "b"] {
+            support::write_account(&data_dir, id, json!({}));
+        }
+        // 先被选中的账号无权访问该模型，另一个账号可以
+        let denied = std::sync::Mutex::new(None::<String>);
+        let (upstream, calls) = support::spawn_mock_upstream(move |call| {
+            let mut denied = denied.lock().unwrap();
+            if denied.get_or_insert_with(|| call.token.clone()) == &call.token {
+                error_response(404, "NOT_FOUND", "Requested entity was not found.")
+            } else {
+                text_response(call, "served by other account")
+            }
+        })
+        .await;
+        let config = ProxyConfig {
+            model_unavailable_action: ModelUnavailableAction::RotateAccount,
+            ..ProxyConfig::default()
+        };
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+
+        let (status, body) = post_json(format!("http://{}/v1/messages", addr), claude_body("claude-sonnet-4-5")).await;
+        assert_eq!(status, reqwest::StatusCode::OK, "{}", body);
+        assert_eq!(body["content"][0]["text"], "served by other account");
+        let tokens: Vec<String> = calls.lock().unwrap().iter().map(|c| c.token.clone()).collect();
+        assert_eq!(tokens.len(), 2, "{:?}", tokens);
+        assert_ne!(tokens[0], tokens[1]);
+    }
+
+    #[tokio::test]
+    async fn test_model_unavailable_fails_by_default() {
+        let data_dir = support::temp_data_dir("ag-unavailable-fail");
+        for id in ["a", "b"] {
+            support::write_account(&data_dir, id, json!({}));
+        }
+        let (upstream, calls) = support::spawn_mock_upstream(|_| {
+            error_response(404, "NOT_FOUND", "Requested entity was not found.")
+        })
+        .await;
+        let (_proxy, addr) = support::start_proxy(ProxyConfig::default(), &data_dir, upstream).await;
+
+        let (status, _) = post_json(format!("http://{}/v1/messages", addr), claude_body("claude-sonnet-4-5")).await;
+        assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+        // 默认不换号重试
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_thinking_variant_unavailable_retries_base_model_on_same_account() {
+        let data_dir = support::temp_data_dir("ag-variant");
+        for id in ["a", "b"] {
+            support::write_account(&data_dir, id, json!({}));
+        }
+        // 两个账号都只开通了基础模型，`-thinking` 变体返回 404
+        let (upstream, calls) = support::spawn_mock_upstream(|call| {
+            if call.model.ends_with("-thinking") {
+                error_response(404, "NOT_FOUND", "Requested entity was not found.")
+            } else {
+                text_response(call, "base model reply")
+            }
+        })
+        .await;
+        let config = ProxyConfig {
+            strip_variant_suffix_on_unavailable: true,
+            ..Default::default()
+        };
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+
+        let (status, body) = post_json(
+            format!("http://{}/v1/messages", addr),
+            claude_body("claude-sonnet-4-5-thinking"),
+        )
+        .await;
+        assert_eq!(status, reqwest::StatusCode::OK, "{}", body);
+        assert_eq!(body["content"][0]["text"], "base model reply");
+
+        let tried: Vec<(String, String)> = calls.lock().unwrap().iter().map(|c| (c.token.clone(), c.model.clone())).collect();
+        assert_eq!(tried.len(), 2, "{:?}", tried);
+        assert_eq!(tried[0].1, "claude-sonnet-4-5-thinking");
+        assert_eq!(tried[1].1, "claude-sonnet-4-5");
+        // 基础模型重试固定在同一账号
+        assert_eq!(tried[0].0, tried[1].0);
+    }
+
+    #[tokio::test]
+    async fn test_thinking_variant_unavailable_not_retried_when_disabled() {
+        let data_dir = support::temp_data_dir("ag-variant-off");
+        support::write_account(&data_dir, "a", json!({}));
+        let (upstream, calls) = support::spawn_mock_upstream(|call| {
+            if call.model.ends_with("-thinking") {
+                error_response(404, "NOT_FOUND", "Requested entity was not found.")
+            } else {
+                text_response(call, "base model reply")
+            }
+        })
+        .await;
+        let (_proxy, addr) = support::start_proxy(ProxyConfig::default(), &data_dir, upstream).await;
+
+        let (status, _) = post_json(
+            format!("http://{}/v1/messages", addr),
+            claude_body("claude-sonnet-4-5-thinking"),
+        )
+        .await;
+        assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_output_limit_keeps_room_for_thinking_budget() {
+        use crate::proxy::config::ModelOutputLimit;
+        use crate::proxy::mappers::common_utils::THINKING_OUTPUT_HEADROOM;
+
+        let data_dir = support::temp_data_dir("ag-output-thinking");
+        support::write_account(&data_dir, "a", json!({}));
+        let (upstream, calls) = support::spawn_mock_upstream(|call| text_response(call, "ok")).await;
+        let config = ProxyConfig {
+            model_output_limits: std::collections::HashMap::from([(
+                "claude-sonnet-4-5-thinking".to_string(),
+                ModelOutputLimit { default_max_tokens: None, max_tokens_cap: Some(8192) },
+            )]),
+            ..Default::default()
+        };
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+
+        for (max_tokens, budget) in [(2048, 4000), (32000, 16000)] {
+            let mut body = claude_body("claude-sonnet-4-5-thinking");
+            body["max_tokens"] = json!(max_tokens);
+            body["thinking"] = json!({ "type": "enabled", "budget_tokens": budget });
+            let (status, body) = post_json(format!("http://{}/v1/messages", addr), body).await;
+            assert_eq!(status, reqwest::StatusCode::OK, "{}", body);
+        }
+
+        let calls = calls.lock().unwrap();
+        // 请求值低于思维链预算时抬高到预算 + 余量
+        let gen_config = &calls[0].body["request"]["generationConfig"];
+        assert_eq!(gen_config["thinkingConfig"]["thinkingBudget"], 4000);
+        assert_eq!(gen_config["maxOutputTokens"], 4000 + THINKING_OUTPUT_HEADROOM);
+        // 预算超过上限时下调预算，输出上限不突破 8192
+        let gen_config = &calls[1].body["request"]["generationConfig"];
+        assert_eq!(gen_config["thinkingConfig"]["thinkingBudget"], 8192 - THINKING_OUTPUT_HEADROOM);
+        assert_eq!(gen_config["maxOutputTokens"], 8192);
+    }
+
+    #[tokio::test]
+    async fn test_schema_error_400_retries_with_strictly_cleaned_tools() {
+        let data_dir = support::temp_data_dir("ag-schema-retry");
+        support::write_account(&data_dir, "a", json!({}));
+        // 参数 Schema 仍含未知关键字 title 时上游返回 Schema 类 400
+        let (upstream, calls) = support::spawn_mock_upstream(|call| {
+            let schema = &call.body["request"]["tools"][0]["functionDeclarations"][0]["parameters"];
+            if schema["properties"]["path"].get("title").is_some() {
+                error_response(
+                    400,
+                    "INVALID_ARGUMENT",
+                    "Invalid JSON payload received. Unknown name \"title\" at 'request.tools[0].function_declarations[0].parameters.properties[0].value': Cannot find field.",
+                )
+            } else {
+                text_response(call, "strict schema accepted")
+            }
+        })
+        .await;
+        let (_proxy, addr) = support::start_proxy(ProxyConfig::default(), &data_dir, upstream).await;
+
+        let mut body = claude_body("claude-sonnet-4-5");
+        body["tools"] = json!([{
+            "name": "read_file",
+            "description": "Read a file",
+            "input_schema": {
+                "type": "object",
+                "properties": { "path": { "type": "string", "title": "Path" } },
+                "required": ["path"]
+            }
+        }]);
+        let (status, body) = post_json(format!("http://{}/v1/messages", addr), body).await;
+        assert_eq!(status, reqwest::StatusCode::OK, "{}", body);
+        assert_eq!(body["content"][0]["text"], "strict schema accepted");
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 2);
+        let schema = |i: usize| calls[i].body["request"]["tools"][0]["functionDeclarations"][0]["parameters"].clone();
+        // 首次按常规清洗发送，重试时经严格清洗移除未知关键字，保留白名单内的结构
+        assert_eq!(schema(0)["properties"]["path"]["title"], "Path");
+        let retried = schema(1);
+        assert!(retried["properties"]["path"].get("title").is_none(), "{}", retried);
+        assert_eq!(retried["properties"]["path"]["type"], schema(0)["properties"]["path"]["type"]);
+        assert_eq!(retried["required"], json!(["path"]));
+    }
+
+    #[tokio::test]
+    async fn test_generic_400_is_not_retried() {
+        let data_dir = support::temp_data_dir("ag-schema-generic");
+        support::write_account(&data_dir, "a", json!({}));
+        let (upstream, calls) = support::spawn_mock_upstream(|_| {
+            error_response(400, "INVALID_ARGUMENT", "Request contains an invalid argument.")
+        })
+        .await;
+        let (_proxy, addr) = support::start_proxy(ProxyConfig::default(), &data_dir, upstream).await;
+
+        let mut body = claude_body("claude-sonnet-4-5");
+        body["tools"] = json!([{
+            "name": "read_file",
+            "input_schema": { "type": "object", "properties": { "path": { "type": "string" } } }
+        }]);
+        let (status, _) = post_json(format!("http://{}/v1/messages", addr), body).await;
+        assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_client_model_allowlist_applies_to_every_entry_point() {
+        let data_dir = support::temp_data_dir("ag-allowlist");
+        support::write_account(&data_dir, "a", json!({}));
+        let (upstream, calls) = support::spawn_mock_upstream(|call| text_response(call, "ok")).await;
+        let config = ProxyConfig {
+            allowed_client_models: vec!["gemini-3-flash".to_string()],
+            allow_header_overrides: true,
+            ..Default::default()
+        };
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+        let client = reqwest::Client::new();
+
+        let (status, _) = post_json(
+            format!("http://{}/v1/images/generations", addr),
+            json!({ "model": "gemini-3-pro-image", "prompt": "a cat" }),
+        )
+        .await;
+        assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+        // 请求头候选链中的每个模型同样要通过允许列表
+        let resp = client
+            .post(format!("http://{}/v1/chat/completions", addr))
+            .header("x-model-candidates", "gemini-3-flash,claude-opus-4-5-thinking")
+            .json(&json!({ "model": "gemini-3-flash", "messages": [{ "role": "user", "content": "hi" }] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::FORBIDDEN);
+
+        let boundary = "allowlist-boundary";
+        let multipart = |fields: &[(&str, &str, Option<&str>)]| {
+            let mut body = String::new();
+            for (name, value, file_name) in fields {
+                body.push_str(&format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"", boundary, name));
+                if let Some(file_name) = file_name {
+                    body.push_str(&format!("; filename=\"{}\"", file_name));
+                }
+                body.push_str(&format!("\r\n\r\n{}\r\n", value));
+            }
+            body.push_str(&format!("--{}--\r\n", boundary));
+            body
+        };
+        let content_type = format!("multipart/form-data; boundary={}", boundary);
+        for (path, fields) in [
+            ("/v1/audio/transcriptions", vec![("file", "RIFF", Some("a.mp3")), ("model", "whisper-1", None)]),
+            (
+                "/v1/images/edits",
+                vec![("image", "PNG", Some("a.png")), ("prompt", "a hat", None), ("model", "gemini-3-pro-image", None)],
+            ),
+        ] {
+            let resp = client
+                .post(format!("http://{}{}", addr, path))
+                .header("content-type", &content_type)
+                .body(multipart(&fields))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), reqwest::StatusCode::FORBIDDEN, "{}", path);
+        }
+
+        assert!(calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_header_candidates_change_upstream_model() {
+        let data_dir = support::temp_data_dir("ag-header-candidates");
+        support::write_account(&data_dir, "a", json!({}));
+        let (upstream, calls) = support::spawn_mock_upstream(|call| text_response(call, &call.model)).await;
+        let config = ProxyConfig {
+            allow_header_overrides: true,
+            ..Default::default()
+        };
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+        let send = |candidates: &'static str| {
+            reqwest::Client::new()
+                .post(format!("http://{}/v1/chat/completions", addr))
+                .header("x-model-candidates", candidates)
+                .json(&json!({ "model": "gemini-3-flash", "messages": [{ "role": "user", "content": "hi" }] }))
+                .send()
+        };
+
+        let resp = send("gemini-2.5-flash-lite").await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        assert_eq!(calls.lock().unwrap()[0].model, "gemini-2.5-flash-lite");
+
+        // 未知模型与空项直接拒绝，不调用上游
+        for bad in ["gemini-2.5-flash-lite,not-a-model", "gemini-2.5-flash-lite,,"] {
+            let resp = send(bad).await.unwrap();
+            assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST, "{}", bad);
+        }
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_safety_block_on_primary_falls_back_to_next_candidate() {
+        let data_dir = support::temp_data_dir("ag-safety-fallback");
+        support::write_account(&data_dir, "a", json!({}));
+        // 主候选被 SAFETY 拦截 (无输出内容)，下一个候选正常返回
+        let (upstream, calls) = support::spawn_mock_upstream(|call| {
+            use axum::response::IntoResponse;
+            if call.model != "gemini-3-pro-high" {
+                return text_response(call, "Here is a cat.");
+            }
+            let blocked = json!({
+                "response": { "candidates": [{ "content": { "role": "model", "parts": [] }, "finishReason": "SAFETY" }] }
+            });
+            if call.method == "streamGenerateContent" {
+                ([(axum::http::header::CONTENT_TYPE, "text/event-stream")], format!("data: {}\n\n", blocked)).into_response()
+            } else {
+                axum::Json(blocked).into_response()
+            }
+        })
+        .await;
+        let mut config = ProxyConfig {
+            allow_header_overrides: true,
+            ..Default::default()
+        };
+        config.safety_fallback.enabled = true;
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+
+        let resp = reqwest::Client::new()
+            .post(format!("http://{}/v1/messages", addr))
+            .header("x-model-candidates", "gemini-3-pro-high,gemini-3-flash")
+            .json(&claude_body("claude-sonnet-4-5"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["content"][0]["text"], "Here is a cat.");
+
+        let resp = reqwest::Client::new()
+            .post(format!("http://{}/v1/chat/completions", addr))
+            .header("x-model-candidates", "gemini-3-pro-high,gemini-3-flash")
+            .json(&json!({ "model": "gpt-4o", "messages": [{ "role": "user", "content": "draw a cat" }] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["choices"][0]["message"]["content"], "Here is a cat.");
+
+        let models: Vec<String> = calls.lock().unwrap().iter().map(|c| c.model.clone()).collect();
+        assert_eq!(models, ["gemini-3-pro-high", "gemini-3-flash", "gemini-3-pro-high", "gemini-3-flash"]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_reject_skips_to_next_candidate() {
+        let data_dir = support::temp_data_dir("ag-stream-reject");
+        support::write_account(&data_dir, "a", json!({}));
+        let (upstream, calls) = support::spawn_mock_upstream(|call| text_response(call, "ok")).await;
+        let mut config = ProxyConfig {
+            allow_header_overrides: true,
+            ..Default::default()
+        };
+        config.streaming.non_streaming_action = crate::proxy::config::NonStreamingAction::Reject;
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+
+        let stream_request = |candidates: &'static str| {
+            reqwest::Client::new()
+                .post(format!("http://{}/v1/chat/completions", addr))
+                .header("x-model-candidates", candidates)
+                .json(&json!({ "model": "gpt-4o", "stream": true, "messages": [{ "role": "user", "content": "hi" }] }))
+                .send()
+        };
+        // 首个候选不支持流式，改用下一个候选
+        let resp = stream_request("gemini-3-pro-image,gemini-3-flash").await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let _ = resp.text().await.unwrap();
+        let models: Vec<String> = calls.lock().unwrap().iter().map(|c| c.model.clone()).collect();
+        assert_eq!(models, ["gemini-3-flash"]);
+
+        // 没有可用的候选时才拒绝
+        let resp = stream_request("gemini-3-pro-image").await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_models_list_hides_premium_models_for_free_accounts() {
+        let data_dir = support::temp_data_dir("ag-models-free");
+        for id in ["free-a", "free-b"] {
+            support::write_account(
+                &data_dir,
+                id,
+                json!({ "quota": { "models": [], "last_updated": 0, "subscription_tier": "FREE" } }),
+            );
+        }
+        let (upstream, _calls) = support::spawn_mock_upstream(|call| text_response(call, "ok")).await;
+        let config = ProxyConfig {
+            advertise_servable_only: true,
+            ..Default::default()
+        };
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+
+        let resp = reqwest::get(format!("http://{}/v1/models", addr)).await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        let ids: Vec<&str> = body["data"].as_array().unwrap().iter().filter_map(|m| m["id"].as_str()).collect();
+        for id in ["gemini-3-pro-high", "gemini-3-pro-image-4k", "claude-opus-4-5-thinking"] {
+            assert!(!ids.contains(&id), "premium model advertised: {}", id);
+        }
+        for id in ["gemini-3-flash", "claude-sonnet-4-5"] {
+            assert!(ids.contains(&id), "missing {}", id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drain_endpoint_requires_admin_token() {
+        use crate::proxy::admin_token::{self, ADMIN_TOKEN_HEADER};
+
+        let data_dir = support::temp_data_dir("ag-drain-admin");
+        support::write_account(&data_dir, "a", json!({}));
+        let (upstream, _calls) = support::spawn_mock_upstream(|call| text_response(call, "ok")).await;
+        let (_proxy, addr) = support::start_proxy(ProxyConfig::default(), &data_dir, upstream).await;
+        let url = format!("http://{}/internal/accounts/a/drain?timeout_secs=1", addr);
+        let messages = || post_json(format!("http://{}/v1/messages", addr), claude_body("claude-sonnet-4-5"));
+
+        // 只持有 API Key 的客户端不能排空账号
+        for token in [None, Some("wrong")] {
+            let mut request = reqwest::Client::new().post(&url);
+            if let Some(token) = token {
+                request = request.header(ADMIN_TOKEN_HEADER, token);
+            }
+            assert_eq!(request.send().await.unwrap().status(), reqwest::StatusCode::FORBIDDEN);
+        }
+        assert_eq!(messages().await.0, reqwest::StatusCode::OK);
+
+        let token = admin_token::read(&data_dir).unwrap();
+        let resp = reqwest::Client::new().post(&url).header(ADMIN_TOKEN_HEADER, token).send().await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        // 唯一的账号已排空，不再接收新请求
+        assert_ne!(messages().await.0, reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_thinking_passthrough_prefixes_come_from_proxy_config() {
+        let data_dir = support::temp_data_dir("ag-thinking-prefixes");
+        support::write_account(&data_dir, "a", json!({}));
+        let (upstream, calls) = support::spawn_mock_upstream(|call| text_response(call, "ok")).await;
+        let config = ProxyConfig {
+            thinking_passthrough_prefixes: vec!["acme-".to_string()],
+            ..Default::default()
+        };
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+
+        for model in ["acme-r1-thinking", "gpt-5-thinking"] {
+            let (status, body) = post_json(format!("http://{}/v1/messages", addr), claude_body(model)).await;
+            assert_eq!(status, reqwest::StatusCode::OK, "{}", body);
+        }
+        // 配置的前缀直通，默认前缀不再生效
+        let models: Vec<String> = calls.lock().unwrap().iter().map(|c| c.model.clone()).collect();
+        assert_eq!(models[0], "acme-r1-thinking");
+        assert_ne!(models[1], "gpt-5-thinking");
+    }
+
+    #[tokio::test]
+    async fn test_family_overrides_builtin_follows_mapping_reload() {
+        let data_dir = support::temp_data_dir("ag-family-overrides");
+        support::write_account(&data_dir, "a", json!({}));
+        let (upstream, calls) = support::spawn_mock_upstream(|call| text_response(call, "ok")).await;
+        let mut config = ProxyConfig {
+            force_family_mapping: true,
+            ..Default::default()
+        };
+        config.anthropic_mapping.insert("claude-4.5-series".to_string(), "gemini-3-pro-high".to_string());
+        let (proxy, addr) = support::start_proxy(config.clone(), &data_dir, upstream).await;
+
+        // 默认内置直通优先；热更新开启后改走用户家族映射
+        for family_overrides_builtin in [false, true] {
+            config.family_overrides_builtin = family_overrides_builtin;
+            proxy.update_mapping(&config).await;
+            let (status, body) = post_json(format!("http://{}/v1/messages", addr), claude_body("claude-sonnet-4-5")).await;
+            assert_eq!(status, reqwest::StatusCode::OK, "{}", body);
+        }
+        let models: Vec<String> = calls.lock().unwrap().iter().map(|c| c.model.clone()).collect();
+        assert_eq!(models, ["claude-sonnet-4-5", "gemini-3-pro-high"]);
+    }
+
+    #[tokio::test]
+    async fn test_strategies_dir_is_read_on_reload_not_per_request() {
+        let data_dir = support::temp_data_dir("ag-strategies-reload");
+        support::write_account(&data_dir, "a", json!({}));
+        let strategies_dir = data_dir.join("strategies");
+        std::fs::create_dir_all(&strategies_dir).unwrap();
+        let write_strategy = |model: &str| {
+            std::fs::write(strategies_dir.join("fast.json"), json!({ "candidates": [model] }).to_string()).unwrap();
+        };
+        write_strategy("gemini-2.5-flash");
+        let (upstream, calls) = support::spawn_mock_upstream(|call| text_response(call, "ok")).await;
+        let mut config = ProxyConfig {
+            strategies_dir: Some(strategies_dir.clone()),
+            ..Default::default()
+        };
+        config.custom_mapping.insert("fast-model".to_string(), "strategy:fast".to_string());
+        let (proxy, addr) = support::start_proxy(config.clone(), &data_dir, upstream).await;
+
+        // 请求使用内存中的策略池：改动策略文件后需经热更新才生效
+        write_strategy("gemini-2.5-pro");
+        for reload in [false, true] {
+            if reload {
+                proxy.update_model_strategies(&config).await;
+            }
+            let (status, body) = post_json(format!("http://{}/v1/messages", addr), claude_body("fast-model")).await;
+            assert_eq!(status, reqwest::StatusCode::OK, "{}", body);
+        }
+        let models: Vec<String> = calls.lock().unwrap().iter().map(|c| c.model.clone()).collect();
+        assert_eq!(models, ["gemini-2.5-flash", "gemini-2.5-pro"]);
+    }
+
+    #[tokio::test]
+    async fn test_schema_inline_limit_follows_proxy_config_reload() {
+        let data_dir = support::temp_data_dir("ag-schema-inline");
+        support::write_account(&data_dir, "a", json!({}));
+        let (upstream, calls) = support::spawn_mock_upstream(|call| text_response(call, "ok")).await;
+        let mut config = ProxyConfig {
+            schema_inline_max_bytes: 64,
+            ..Default::default()
+        };
+        let (proxy, addr) = support::start_proxy(config.clone(), &data_dir, upstream).await;
+
+        let mut body = claude_body("claude-sonnet-4-5");
+        body["tools"] = json!([{
+            "name": "pair",
+            "input_schema": {
+                "$defs": {"Leaf": {"type": "object", "properties": {"value": {"type": "integer"}}}},
+                "type": "object",
+                "properties": {
+                    "a": {"$ref": "#/$defs/Leaf"},
+                    "b": {"$ref": "#/$defs/Leaf"}
+                }
+            }
+        }]);
+        // 上限过小时重复引用降级为摘要；热更新为默认值后完整展开
+        for limit in [64, ProxyConfig::default().schema_inline_max_bytes] {
+            config.schema_inline_max_bytes = limit;
+            proxy.update_schema_inline_limit(&config).await;
+            let (status, resp) = post_json(format!("http://{}/v1/messages", addr), body.clone()).await;
+            assert_eq!(status, reqwest::StatusCode::OK, "{}", resp);
+        }
+
+        let calls = calls.lock().unwrap();
+        let props = |i: usize| calls[i].body["request"]["tools"][0]["functionDeclarations"][0]["parameters"]["properties"].clone();
+        let summarized = props(0);
+        assert!(summarized["b"].get("properties").is_none(), "{}", summarized);
+        assert!(summarized["b"]["description"].as_str().unwrap().contains("Same structure as 'Leaf'"));
+        assert_eq!(props(1)["b"]["properties"]["value"]["type"], "integer");
+    }
+
+    async fn post_gemini(addr: std::net::SocketAddr) -> (reqwest::StatusCode, serde_json::Value) {
+        post_json(
+            format!("http://{}/v1beta/models/gemini-2.5-flash:generateContent", addr),
+            json!({ "contents": [{ "role": "user", "parts": [{ "text": "hello there" }] }] }),
+        )
+        .await
+    }
+
+    /// 首个调用所用账号的请求在 `primary_delay` 后返回 `primary_status`，其余账号在 `hedge_delay` 后成功
+    async fn hedged_upstream(
+        primary_delay: u64,
+        primary_status: u16,
+        hedge_delay: u64,
+    ) -> (std::net::SocketAddr, support::UpstreamCalls) {
+        let primary = std::sync::Arc::new(std::sync::Mutex::new(None::<String>));
+        support::spawn_mock_upstream_async(move |call| {
+            let is_primary = primary.lock().unwrap().get_or_insert_with(|| call.token.clone()) == &call.token;
+            async move {
+                let (delay, status) = if is_primary { (primary_delay, primary_status) } else { (hedge_delay, 200) };
+                tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                match status {
+                    200 => text_response(&call, if is_primary { "primary" } else { "hedge" }),
+                    status => error_response(status, "UNAVAILABLE", "The model is overloaded."),
+                }
+            }
+        })
+        .await
+    }
+
+    fn hedged_config() -> ProxyConfig {
+        ProxyConfig {
+            hedge_delay_ms: Some(100),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_primary_is_replaced_by_hedge() {
+        let data_dir = support::temp_data_dir("ag-hedge-slow");
+        for id in ["a", "b"] {
+            support::write_account(&data_dir, id, json!({}));
+        }
+        let (upstream, calls) = hedged_upstream(2000, 200, 0).await;
+        let (_proxy, addr) = support::start_proxy(hedged_config(), &data_dir, upstream).await;
+
+        let started = std::time::Instant::now();
+        let (status, body) = post_gemini(addr).await;
+        assert_eq!(status, reqwest::StatusCode::OK, "{}", body);
+        assert_eq!(body["candidates"][0]["content"]["parts"][0]["text"], "hedge");
+        assert!(started.elapsed() < std::time::Duration::from_millis(1500));
+
+        let tokens: Vec<String> = calls.lock().unwrap().iter().map(|c| c.token.clone()).collect();
+        assert_eq!(tokens.len(), 2, "{:?}", tokens);
+        assert_ne!(tokens[0], tokens[1]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_primary_does_not_beat_in_flight_hedge() {
+        let data_dir = support::temp_data_dir("ag-hedge-failed");
+        for id in ["a", "b"] {
+            support::write_account(&data_dir, id, json!({}));
+        }
+        // 对冲发起后主请求先返回 503，仍采用稍后成功的对冲响应，不再换号重试
+        let (upstream, calls) = hedged_upstream(200, 503, 400).await;
+        let (_proxy, addr) = support::start_proxy(hedged_config(), &data_dir, upstream).await;
+
+        let (status, body) = post_gemini(addr).await;
+        assert_eq!(status, reqwest::StatusCode::OK, "{}", body);
+        assert_eq!(body["candidates"][0]["content"]["parts"][0]["text"], "hedge");
+        assert_eq!(calls.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_cache_handle_is_dropped_and_request_retried_without_it() {
+        let data_dir = support::temp_data_dir("ag-cache-reject");
+        support::write_account(&data_dir, "a", json!({}));
+        // 上游接受缓存创建，但生成时拒绝该句柄 (模拟句柄已过期)
+        let (upstream, calls) = support::spawn_mock_upstream(|call| match call.method.as_str() {
+            "createCachedContent" => axum::response::IntoResponse::into_response(axum::Json(json!({ "name": "cachedContents/stale" }))),
+            _ if call.body["request"].get("cachedContent").is_some() => {
+                error_response(404, "NOT_FOUND", "CachedContent not found (or permission denied)")
+            }
+            _ => text_response(call, "served without cache"),
+        })
+        .await;
+        let config = ProxyConfig {
+            context_cache: crate::proxy::config::ContextCacheConfig {
+                enabled: true,
+                min_prefix_bytes: 0,
+                ..Default::default()
+            },
+            ..ProxyConfig::default()
+        };
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+
+        let (status, body) = post_json(
+            format!("http://{}/v1beta/models/gemini-2.5-flash:generateContent", addr),
+            json!({
+                "systemInstruction": { "parts": [{ "text": "You are a careful assistant." }] },
+                "contents": [{ "role": "user", "parts": [{ "text": "hello there" }] }]
+            }),
+        )
+        .await;
+        assert_eq!(status, reqwest::StatusCode::OK, "{}", body);
+        let generations: Vec<bool> = calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|c| c.method == "generateContent")
+            .map(|c| c.body["request"].get("cachedContent").is_some())
+            .collect();
+        assert_eq!(generations, vec![true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_omitted_model_routes_to_protocol_default() {
+        let data_dir = support::temp_data_dir("ag-default-model");
+        support::write_account(&data_dir, "a", json!({}));
+        let (upstream, calls) = support::spawn_mock_upstream(|call| text_response(call, "ok")).await;
+        let config = ProxyConfig {
+            default_model_per_protocol: crate::proxy::config::DefaultModelPerProtocol {
+                openai: Some("gemini-2.5-flash".to_string()),
+                anthropic: Some("gemini-2.5-pro".to_string()),
+                gemini: Some("gemini-2.5-flash-lite".to_string()),
+            },
+            ..ProxyConfig::default()
+        };
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+
+        let openai = json!({ "messages": [{ "role": "user", "content": "hello there" }] });
+        let mut claude = claude_body("");
+        claude.as_object_mut().unwrap().remove("model");
+        let gemini = json!({ "contents": [{ "role": "user", "parts": [{ "text": "hello there" }] }] });
+        for (path, body) in [
+            ("v1/chat/completions", openai),
+            ("v1/messages", claude),
+            ("v1beta/models/:generateContent", gemini),
+        ] {
+            let (status, body) = post_json(format!("http://{}/{}", addr, path), body).await;
+            assert_eq!(status, reqwest::StatusCode::OK, "{}: {}", path, body);
+        }
+        let models: Vec<String> = calls.lock().unwrap().iter().map(|c| c.model.clone()).collect();
+        assert_eq!(models, ["gemini-2.5-flash", "gemini-2.5-pro", "gemini-2.5-flash-lite"]);
+    }
+}
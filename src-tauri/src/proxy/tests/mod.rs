@@ -1,2 +1,5 @@
 pub mod comprehensive;
 pub mod strategy;
+pub mod handlers;
+#[cfg(test)]
+pub mod support;
@@ -1,8 +1,8 @@
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
-    use crate::proxy::common::model_mapping::resolve_model_route_plan;
-    use crate::proxy::config::{ModelStrategy, ModelFallbackPolicy, ModelPriority, ModelStickiness};
+    use crate::proxy::common::model_mapping::{default_thinking_passthrough_prefixes, resolve_model_route_plan, ModelRouteConfig};
+    use crate::proxy::config::{default_openai_family_rules, ModelDeprecationPolicy, ModelStrategy, ModelFallbackPolicy, ModelPriority, ModelStickiness};
 
     #[test]
     fn test_family_mapping_with_strategy_candidates() {
@@ -23,10 +23,17 @@ mod tests {
 
         let plan = resolve_model_route_plan(
             "claude-opus-4-5-20251101",
-            &HashMap::new(),
-            &HashMap::new(),
-            &anthropic_mapping,
-            &strategies,
+            &ModelRouteConfig {
+                custom_mapping: &HashMap::new(),
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &default_openai_family_rules(),
+                anthropic_mapping: &anthropic_mapping,
+                model_strategies: &strategies,
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
             true,
         );
 
@@ -55,20 +62,278 @@ mod tests {
                     model_priority: ModelPriority::AccuracyFirst,
                     stickiness: ModelStickiness::Strong,
                     max_model_hops: Some(2),
+                    ..ModelFallbackPolicy::default()
                 },
             },
         );
 
         let plan = resolve_model_route_plan(
             "gpt-4",
-            &custom_mapping,
-            &HashMap::new(),
-            &HashMap::new(),
-            &strategies,
+            &ModelRouteConfig {
+                custom_mapping: &custom_mapping,
+                openai_mapping: &HashMap::new(),
+                openai_family_rules: &default_openai_family_rules(),
+                anthropic_mapping: &HashMap::new(),
+                model_strategies: &strategies,
+                deprecation: &ModelDeprecationPolicy::default(),
+                default_policy: &ModelFallbackPolicy::default(),
+                thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                family_overrides_builtin: false,
+            },
             false,
         );
 
         assert_eq!(plan.max_models(), 2);
         assert_eq!(plan.candidates().len(), 3);
     }
+
+    #[test]
+    fn test_non_strategy_route_uses_default_fallback_policy() {
+        let mut custom_mapping = HashMap::new();
+        custom_mapping.insert("gpt-4".to_string(), "gemini-3-flash".to_string());
+        custom_mapping.insert("gpt-4o".to_string(), "strategy:short-list".to_string());
+
+        let mut strategies = HashMap::new();
+        strategies.insert(
+            "short-list".to_string(),
+            ModelStrategy {
+                candidates: vec!["gemini-3-pro-high".to_string(), "gemini-3-flash".to_string()],
+                policy: ModelFallbackPolicy::default(),
+            },
+        );
+
+        let default_policy = ModelFallbackPolicy {
+            model_priority: ModelPriority::CapacityFirst,
+            stickiness: ModelStickiness::Weak,
+            max_model_hops: Some(1),
+            ..ModelFallbackPolicy::default()
+        };
+        let resolve = |model: &str| {
+            resolve_model_route_plan(
+                model,
+                &ModelRouteConfig {
+                    custom_mapping: &custom_mapping,
+                    openai_mapping: &HashMap::new(),
+                    openai_family_rules: &default_openai_family_rules(),
+                    anthropic_mapping: &HashMap::new(),
+                    model_strategies: &strategies,
+                    deprecation: &ModelDeprecationPolicy::default(),
+                    default_policy: &default_policy,
+                    thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                    family_overrides_builtin: false,
+                },
+                false,
+            )
+        };
+
+        let plan = resolve("gpt-4");
+        assert_eq!(plan.primary, "gemini-3-flash");
+        assert_eq!(plan.strategy_id, None);
+        assert_eq!(plan.policy.model_priority, ModelPriority::CapacityFirst);
+        assert_eq!(plan.policy.stickiness, ModelStickiness::Weak);
+        assert_eq!(plan.policy.max_model_hops, Some(1));
+
+        // 策略目标仍使用策略自身的回退策略
+        let plan = resolve("gpt-4o");
+        assert_eq!(plan.strategy_id.as_deref(), Some("short-list"));
+        assert_eq!(plan.policy.model_priority, ModelPriority::AccuracyFirst);
+        assert_eq!(plan.policy.max_model_hops, None);
+    }
+
+    #[tokio::test]
+    async fn test_strategy_stats_track_fallback_candidate() {
+        use crate::proxy::config::{MonitorMode, ProxyConfig};
+        use crate::proxy::monitor::ProxyMonitor;
+        use crate::proxy::tests::support::{self, error_response, text_response};
+
+        let data_dir = support::temp_data_dir("ag-strategy-stats");
+        // 每次 primary 失败都会让当前账号进入冷却，为 3 次请求准备足够的账号
+        for id in ["a", "b", "c", "d", "e", "f"] {
+            support::write_account(&data_dir, id, serde_json::json!({}));
+        }
+        // primary 持续返回 503，capacity-first 切换到下一个候选
+        let (upstream, calls) = support::spawn_mock_upstream(|call| {
+            if call.model == "gemini-3-pro-high" {
+                error_response(503, "UNAVAILABLE", "The model is overloaded.")
+            } else {
+                text_response(call, "served by flash")
+            }
+        })
+        .await;
+
+        let mut config = ProxyConfig::default();
+        config.custom_mapping.insert("gpt-4".to_string(), "strategy:pro-then-flash".to_string());
+        config.model_strategies.insert(
+            "pro-then-flash".to_string(),
+            ModelStrategy {
+                candidates: vec![
+                    "gemini-3-pro-high".to_string(),
+                    "gemini-3-flash".to_string(),
+                ],
+                policy: ModelFallbackPolicy {
+                    model_priority: ModelPriority::CapacityFirst,
+                    stickiness: ModelStickiness::Weak,
+                    max_model_hops: None,
+                    ..ModelFallbackPolicy::default()
+                },
+            },
+        );
+        let monitor = std::sync::Arc::new(ProxyMonitor::new(10, MonitorMode::CountersOnly, None));
+        let (_proxy, addr) = support::start_proxy_with_monitor(config, &data_dir, upstream, monitor.clone()).await;
+
+        let client = reqwest::Client::new();
+        for _ in 0..3 {
+            let resp = client
+                .post(format!("http://{}/v1/chat/completions", addr))
+                .json(&serde_json::json!({
+                    "model": "gpt-4",
+                    "messages": [{ "role": "user", "content": "hello there" }]
+                }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        }
+        assert!(calls.lock().unwrap().iter().any(|c| c.model == "gemini-3-pro-high"));
+
+        let stats = monitor.get_stats().await;
+        let breakdown = stats.strategy_stats.get("pro-then-flash").unwrap();
+        assert_eq!(breakdown.served_by_position, vec![0, 3]);
+        assert_eq!(breakdown.served_by_model.get("gemini-3-flash"), Some(&3));
+        assert!(!breakdown.served_by_model.contains_key("gemini-3-pro-high"));
+    }
+
+    /// 所有候选都持续失败时，按 `max_total_attempts` 启动反代并返回客户端状态码与上游调用记录
+    /// `hedge_delay_ms` 非空时上游响应变慢并开启对冲，请求改走 Gemini 原生协议
+    async fn run_failing_strategy(
+        max_total_attempts: usize,
+        hedge_delay_ms: Option<u64>,
+    ) -> (reqwest::StatusCode, Vec<String>) {
+        use crate::proxy::config::ProxyConfig;
+        use crate::proxy::tests::support::{self, error_response};
+
+        let data_dir = support::temp_data_dir("ag-attempt-budget");
+        for id in ["a", "b", "c", "d", "e", "f"] {
+            support::write_account(&data_dir, id, serde_json::json!({}));
+        }
+        let upstream_delay = hedge_delay_ms.map_or(0, |ms| ms * 3);
+        let (upstream, calls) = support::spawn_mock_upstream_async(move |_| async move {
+            tokio::time::sleep(std::time::Duration::from_millis(upstream_delay)).await;
+            error_response(503, "UNAVAILABLE", "The model is overloaded.")
+        })
+        .await;
+
+        let mut config = ProxyConfig {
+            max_total_attempts,
+            hedge_delay_ms,
+            ..Default::default()
+        };
+        config.custom_mapping.insert("gpt-4".to_string(), "strategy:three-candidates".to_string());
+        config.model_strategies.insert(
+            "three-candidates".to_string(),
+            ModelStrategy {
+                candidates: vec![
+                    "gemini-3-pro-high".to_string(),
+                    "gemini-3-flash".to_string(),
+                    "gemini-2.5-flash".to_string(),
+                ],
+                policy: ModelFallbackPolicy {
+                    model_priority: ModelPriority::CapacityFirst,
+                    max_model_hops: None,
+                    ..ModelFallbackPolicy::default()
+                },
+            },
+        );
+        let (_proxy, addr) = support::start_proxy(config, &data_dir, upstream).await;
+
+        let request = match hedge_delay_ms {
+            Some(_) => reqwest::Client::new()
+                .post(format!("http://{}/v1beta/models/gpt-4:generateContent", addr))
+                .json(&serde_json::json!({ "contents": [{ "role": "user", "parts": [{ "text": "hello there" }] }] })),
+            None => reqwest::Client::new()
+                .post(format!("http://{}/v1/chat/completions", addr))
+                .json(&serde_json::json!({
+                    "model": "gpt-4",
+                    "messages": [{ "role": "user", "content": "hello there" }]
+                })),
+        };
+        let resp = request.send().await.unwrap();
+        let models = calls.lock().unwrap().iter().map(|c| c.model.clone()).collect();
+        (resp.status(), models)
+    }
+
+    #[tokio::test]
+    async fn test_attempt_budget_caps_upstream_calls_across_candidates() {
+        // 不限制时账号重试与候选回退叠加，单个请求产生的上游调用超过预算
+        let (status, unbounded) = run_failing_strategy(0, None).await;
+        assert!(!status.is_success());
+        assert!(unbounded.len() > 4, "{:?}", unbounded);
+
+        let (status, bounded) = run_failing_strategy(4, None).await;
+        assert!(!status.is_success());
+        assert_eq!(bounded.len(), 4, "{:?}", bounded);
+    }
+
+    #[tokio::test]
+    async fn test_attempt_budget_counts_hedged_requests() {
+        // 每次主请求都会触发对冲，对冲请求同样占用预算，预算用尽后不再发起对冲
+        let (status, bounded) = run_failing_strategy(3, Some(50)).await;
+        assert!(!status.is_success());
+        assert_eq!(bounded.len(), 3, "{:?}", bounded);
+    }
+
+    #[test]
+    fn test_adaptive_success_demotes_failing_candidate() {
+        use crate::proxy::common::candidate_stats::record_outcome;
+        use crate::proxy::config::SelectionMode;
+
+        // 使用独立的候选名，避免与其他测试共享的全局统计互相干扰
+        let first = "adaptive-test-primary";
+        let second = "adaptive-test-secondary";
+
+        let mut custom_mapping = HashMap::new();
+        custom_mapping.insert("gpt-4".to_string(), "strategy:adaptive".to_string());
+        let mut strategies = HashMap::new();
+        strategies.insert(
+            "adaptive".to_string(),
+            ModelStrategy {
+                candidates: vec![first.to_string(), second.to_string()],
+                policy: ModelFallbackPolicy {
+                    selection_mode: SelectionMode::AdaptiveSuccess,
+                    max_model_hops: Some(1),
+                    ..ModelFallbackPolicy::default()
+                },
+            },
+        );
+        let resolve = || {
+            resolve_model_route_plan(
+                "gpt-4",
+                &ModelRouteConfig {
+                    custom_mapping: &custom_mapping,
+                    openai_mapping: &HashMap::new(),
+                    openai_family_rules: &default_openai_family_rules(),
+                    anthropic_mapping: &HashMap::new(),
+                    model_strategies: &strategies,
+                    deprecation: &ModelDeprecationPolicy::default(),
+                    default_policy: &ModelFallbackPolicy::default(),
+                    thinking_passthrough_prefixes: &default_thinking_passthrough_prefixes(),
+                    family_overrides_builtin: false,
+                },
+                false,
+            )
+        };
+
+        // 无历史记录时保持配置顺序
+        assert_eq!(resolve().primary, first);
+
+        for _ in 0..3 {
+            record_outcome(Some("adaptive"), first, false);
+            record_outcome(Some("adaptive"), second, true);
+        }
+
+        let plan = resolve();
+        assert_eq!(plan.candidates(), vec![second.to_string(), first.to_string()]);
+        // 仍然遵守 max_model_hops
+        assert_eq!(plan.max_models(), 1);
+    }
 }
@@ -0,0 +1,177 @@
+// 测试共用的辅助函数：本地 mock 服务
+use std::net::SocketAddr;
+
+/// 在随机本地端口上启动 axum 应用 (mock 上游、被测中间件等)，返回监听地址
+pub async fn spawn_router(app: axum::Router) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    addr
+}
+
+/// 返回一个当前无人监听的本地地址 (绑定后立即释放)，连接会被拒绝
+pub fn closed_local_addr() -> SocketAddr {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+/// 创建带 `accounts` 子目录的临时数据目录
+pub fn temp_data_dir(prefix: &str) -> std::path::PathBuf {
+    let data_dir = std::env::temp_dir().join(format!("{}-{}", prefix, uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(data_dir.join("accounts")).unwrap();
+    data_dir
+}
+
+/// 在数据目录的 `accounts` 下写入一个令牌未过期的账号文件 (`<id>@example.com`)
+/// `extra` 中的顶层字段 (如 `quota`) 会合并进账号 JSON
+pub fn write_account(data_dir: &std::path::Path, id: &str, extra: serde_json::Value) {
+    let mut account = serde_json::json!({
+        "id": id,
+        "email": format!("{}@example.com", id),
+        "token": {
+            "access_token": format!("at-{}", id),
+            "refresh_token": format!("rt-{}", id),
+            "expires_in": 3600,
+            "expiry_timestamp": chrono::Utc::now().timestamp() + 3600,
+            "project_id": format!("project-{}", id)
+        }
+    });
+    if let (Some(account), Some(extra)) = (account.as_object_mut(), extra.as_object()) {
+        account.extend(extra.clone());
+    }
+    std::fs::write(data_dir.join("accounts").join(format!("{}.json", id)), account.to_string()).unwrap();
+}
+
+/// 按配置启动完整的反代服务，上游 v1internal 端点指向 `upstream_addr` 上的 mock，
+/// 返回服务实例 (丢弃即停止监听) 与反代地址 (`config.port` 会被替换为空闲端口)
+pub async fn start_proxy(
+    config: crate::proxy::config::ProxyConfig,
+    data_dir: &std::path::Path,
+    upstream_addr: SocketAddr,
+) -> (crate::proxy::AxumServer, SocketAddr) {
+    let monitor = std::sync::Arc::new(crate::proxy::monitor::ProxyMonitor::new(
+        100,
+        crate::proxy::config::MonitorMode::CountersOnly,
+        None,
+    ));
+    start_proxy_with_monitor(config, data_dir, upstream_addr, monitor).await
+}
+
+/// 同 [`start_proxy`]，由调用方提供监控实例以便检查处理器记录的统计
+pub async fn start_proxy_with_monitor(
+    mut config: crate::proxy::config::ProxyConfig,
+    data_dir: &std::path::Path,
+    upstream_addr: SocketAddr,
+    monitor: std::sync::Arc<crate::proxy::monitor::ProxyMonitor>,
+) -> (crate::proxy::AxumServer, SocketAddr) {
+    let token_manager = std::sync::Arc::new(crate::proxy::TokenManager::new(data_dir.to_path_buf()));
+    token_manager.load_accounts().await.unwrap();
+    let upstream = crate::proxy::upstream::client::UpstreamClient::new(None)
+        .with_base_urls(vec![format!("http://{}/v1internal", upstream_addr)]);
+    config.port = closed_local_addr().port();
+    let (server, _handle) = crate::proxy::AxumServer::start_with_upstream(
+        &config,
+        token_manager,
+        monitor,
+        std::sync::Arc::new(upstream),
+    )
+    .await
+    .unwrap();
+    (server, SocketAddr::from(([127, 0, 0, 1], config.port)))
+}
+
+/// mock 上游收到的一次 v1internal 调用
+#[derive(Clone, Debug)]
+pub struct UpstreamCall {
+    /// `generateContent` / `streamGenerateContent` 等
+    pub method: String,
+    /// `Authorization: Bearer` 中的访问令牌
+    pub token: String,
+    /// 请求体中的上游模型名
+    pub model: String,
+    pub body: serde_json::Value,
+}
+
+pub type UpstreamCalls = std::sync::Arc<std::sync::Mutex<Vec<UpstreamCall>>>;
+
+/// 启动记录所有调用的 mock v1internal 上游，由 `respond` 决定每次调用的响应
+pub async fn spawn_mock_upstream<F>(respond: F) -> (SocketAddr, UpstreamCalls)
+where
+    F: Fn(&UpstreamCall) -> axum::response::Response + Send + Sync + 'static,
+{
+    spawn_mock_upstream_async(move |call| std::future::ready(respond(&call))).await
+}
+
+/// 同 [`spawn_mock_upstream`]，`respond` 可以异步返回 (用于模拟慢速上游)
+pub async fn spawn_mock_upstream_async<F, Fut>(respond: F) -> (SocketAddr, UpstreamCalls)
+where
+    F: Fn(UpstreamCall) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = axum::response::Response> + Send + 'static,
+{
+    let calls = UpstreamCalls::default();
+    let recorded = calls.clone();
+    let respond = std::sync::Arc::new(respond);
+    let app = axum::Router::new().fallback(move |request: axum::extract::Request| {
+        let recorded = recorded.clone();
+        let respond = respond.clone();
+        async move {
+            let method = request.uri().path().rsplit(':').next().unwrap_or_default().to_string();
+            let token = request
+                .headers()
+                .get(axum::http::header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+                .unwrap_or_default()
+                .to_string();
+            let bytes = axum::body::to_bytes(request.into_body(), usize::MAX).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap_or_default();
+            let call = UpstreamCall {
+                method,
+                token,
+                model: body["model"].as_str().unwrap_or_default().to_string(),
+                body,
+            };
+            recorded.lock().unwrap().push(call.clone());
+            respond(call).await
+        }
+    });
+    (spawn_router(app).await, calls)
+}
+
+/// 与调用方式匹配的成功响应 (流式为 SSE，否则为 JSON)，回复文本为 `text`
+pub fn text_response(call: &UpstreamCall, text: &str) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    let payload = serde_json::json!({
+        "response": {
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": text }] },
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": { "promptTokenCount": 1, "candidatesTokenCount": 1, "totalTokenCount": 2 },
+            "modelVersion": call.model
+        }
+    });
+    if call.method == "streamGenerateContent" {
+        (
+            [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+            format!("data: {}\n\n", payload),
+        )
+            .into_response()
+    } else {
+        axum::Json(payload).into_response()
+    }
+}
+
+/// Google 风格的错误响应
+pub fn error_response(status: u16, google_status: &str, message: &str) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    let status = axum::http::StatusCode::from_u16(status).unwrap();
+    (
+        status,
+        axum::Json(serde_json::json!({
+            "error": { "code": status.as_u16(), "message": message, "status": google_status }
+        })),
+    )
+        .into_response()
+}
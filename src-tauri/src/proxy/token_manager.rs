@@ -1,12 +1,74 @@
 // 移除冗余的顶层导入，因为这些在代码中已由 full path 或局部导入处理
 use dashmap::DashMap;
-use std::collections::HashSet;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::models::{AccountProtocol, Credential, UpstreamAuth};
+use crate::proxy::common::priority_queue::{RequestPriorityHint, SlotWaitQueue};
 use crate::proxy::rate_limit::RateLimitTracker;
+use crate::proxy::session_cache::SessionCache;
 use crate::proxy::sticky_config::StickySessionConfig;
+use crate::proxy::webhook::{WebhookEvent, WebhookNotifier};
+use crate::proxy::monitor::{AccountEventState, MonitorEvent, ProxyMonitor};
+
+/// RPM 统计窗口
+const RPM_WINDOW: Duration = Duration::from_secs(60);
+/// 排队请求在未被唤醒时重新尝试选号的间隔 (RPM/每日窗口到期不会触发唤醒)
+const SLOT_RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 调度状态持久化文件 (位于数据目录下)
+const SCHEDULING_STATE_FILE: &str = "scheduling_state.json";
+
+/// 60s 全局锁定窗口
+const LAST_USED_WINDOW: Duration = Duration::from_secs(60);
+
+/// 跨重启保留的最小调度状态，避免每次重启后请求集中打到排序第一的账号
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct SchedulingState {
+    /// 轮询游标
+    cursor: usize,
+    /// 60s 锁定中的账号 (AccountID, 最近使用时间 Unix 毫秒)
+    last_used_account: Option<(String, i64)>,
+    /// 各账号最近一次被调度的时间 (AccountID -> Unix 毫秒)
+    last_used_at: HashMap<String, i64>,
+}
+
+/// 单账号的实时负载 (进行中请求数 + 最近一分钟的请求时间)
+#[derive(Default)]
+struct AccountLoad {
+    in_flight: AtomicUsize,
+    recent: std::sync::Mutex<VecDeque<Instant>>,
+}
+
+/// 账号并发槽位，drop 时释放并唤醒排队中优先级最高的请求
+pub struct AccountSlot {
+    load: Arc<AccountLoad>,
+    queue: Arc<SlotWaitQueue>,
+}
+
+impl Drop for AccountSlot {
+    fn drop(&mut self) {
+        self.load.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.queue.wake_next();
+    }
+}
+
+/// 请求级槽位持有者 (由 account_slot 中间件建立作用域)
+pub type RequestSlotHolder = Arc<std::sync::Mutex<Option<AccountSlot>>>;
+
+tokio::task_local! {
+    /// 当前请求占用的账号槽位；同一请求重试换号时替换 (释放) 旧槽位
+    pub static REQUEST_ACCOUNT_SLOT: RequestSlotHolder;
+    /// 当前请求的客户端协议 (由 account_slot 中间件按路径设置)，选号时跳过不支持该协议的账号
+    pub static REQUEST_PROTOCOL: AccountProtocol;
+    /// 当前请求的优先级线索 (由 account_slot 中间件从请求头提取)，槽位占满排队时使用
+    pub static REQUEST_PRIORITY: RequestPriorityHint;
+}
 
 #[derive(Debug, Clone)]
 pub struct ProxyToken {
@@ -19,30 +81,219 @@ pub struct ProxyToken {
     pub account_path: PathBuf,  // 账号文件路径，用于更新
     pub project_id: Option<String>,
     pub subscription_tier: Option<String>, // "FREE" | "PRO" | "ULTRA"
+    pub priority: i32, // 手动优先级，数值越大越优先
+    pub upstream_proxy: Option<String>, // 账号级上游代理，覆盖全局 upstream_proxy
+    pub max_concurrency: Option<u32>, // 账号级并发上限，覆盖全局默认值
+    pub max_rpm: Option<u32>, // 账号级每分钟请求上限，覆盖全局默认值
+    pub daily_request_cap: Option<u64>, // 账号级每日请求上限，覆盖全局默认值
+    pub credential: Credential, // 凭据类型，决定出站鉴权方式与令牌刷新方式
+    pub canary: bool, // 灰度账号，仅承接少量流量，成功次数达标后自动转正
+    pub supported_protocols: Vec<AccountProtocol>, // 可服务的客户端协议，空表示全部
+}
+
+impl ProxyToken {
+    pub fn serves_protocol(&self, protocol: AccountProtocol) -> bool {
+        self.supported_protocols.is_empty() || self.supported_protocols.contains(&protocol)
+    }
+
+    /// 出站鉴权凭据 (API Key 使用 `x-goog-api-key`，其余为 Bearer 访问令牌)
+    pub fn upstream_auth(&self) -> UpstreamAuth {
+        match &self.credential {
+            Credential::ApiKey { api_key, .. } => UpstreamAuth::ApiKey(api_key.clone()),
+            _ => UpstreamAuth::Bearer(self.access_token.clone()),
+        }
+    }
+}
+
+/// 按凭据类型获取新的访问令牌 (API Key 无需刷新)
+async fn fetch_access_token(
+    credential: &Credential,
+    refresh_token: &str,
+) -> Result<crate::modules::oauth::TokenResponse, String> {
+    match credential {
+        Credential::RefreshToken => crate::modules::oauth::refresh_access_token(refresh_token).await,
+        Credential::ServiceAccount { service_account, metadata_url, .. } => {
+            crate::modules::oauth::fetch_service_account_token(service_account, metadata_url.as_deref()).await
+        }
+        Credential::ApiKey { .. } => Err("API Key 凭据无需刷新".to_string()),
+    }
+}
+
+/// 账号健康概况 (供状态展示与排查)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AccountHealth {
+    pub account_id: String,
+    pub email: String,
+    pub in_flight: usize,
+    pub rate_limited: bool,
+    pub draining: bool,
+    pub requests_today: u64,
+    /// 今日剩余请求数 (未设置每日上限时为 None)
+    pub remaining_today: Option<u64>,
+    /// 最近一次被调度的时间 (Unix 毫秒，从未使用时为 None)
+    pub last_used_at: Option<i64>,
+    /// 是否为灰度账号
+    pub canary: bool,
+}
+
+/// 计数所属的"日"：重置时刻之前的请求归入前一天
+fn daily_window(now: chrono::NaiveDateTime, reset: chrono::NaiveTime) -> chrono::NaiveDate {
+    let date = now.date();
+    if now.time() >= reset {
+        date
+    } else {
+        date.pred_opt().unwrap_or(date)
+    }
+}
+
+/// 每日计数打包为单个 u64：高 32 位为计数日 (自公元元年起的天数)，低 32 位为当日请求数，
+/// 使跨日重置、上限检查与计数可以在一次 `fetch_update` 中完成
+fn pack_daily_usage(window: chrono::NaiveDate, count: u64) -> u64 {
+    use chrono::Datelike;
+    ((window.num_days_from_ce() as u32 as u64) << 32) | count.min(u32::MAX as u64)
+}
+
+/// 打包值在 `window` 计数日内的请求数 (其他日的计数视为 0)
+fn unpack_daily_count(packed: u64, window: chrono::NaiveDate) -> u64 {
+    if packed >> 32 == pack_daily_usage(window, 0) >> 32 {
+        packed & u32::MAX as u64
+    } else {
+        0
+    }
 }
 
 pub struct TokenManager {
     tokens: Arc<DashMap<String, ProxyToken>>,  // account_id -> ProxyToken
+    email_index: Arc<DashMap<String, String>>, // email -> account_id (随账号加载维护，账号被移除后的残留项查不到 token 即无效)
     current_index: Arc<AtomicUsize>,
     last_used_account: Arc<tokio::sync::Mutex<Option<(String, std::time::Instant)>>>,
     data_dir: PathBuf,
     rate_limit_tracker: Arc<RateLimitTracker>,  // 新增: 限流跟踪器
     sticky_config: Arc<tokio::sync::RwLock<StickySessionConfig>>, // 新增：调度配置
-    session_accounts: Arc<DashMap<String, String>>, // 新增：会话与账号映射 (SessionID -> AccountID)
+    session_accounts: Arc<SessionCache<String>>, // 会话与账号映射 (SessionID -> AccountID)，LRU + 空闲过期
+    account_load: Arc<DashMap<String, Arc<AccountLoad>>>, // 账号实时负载 (AccountID -> Load)
+    default_max_concurrency: AtomicU32, // 全局单账号并发上限 (0 = 不限制)
+    default_max_rpm: AtomicU32, // 全局单账号 RPM 上限 (0 = 不限制)
+    default_daily_cap: AtomicU64, // 全局单账号每日请求上限 (0 = 不限制)
+    daily_reset_time: std::sync::RwLock<chrono::NaiveTime>, // 每日计数重置时刻 (本地时间)
+    daily_usage: Arc<DashMap<String, Arc<AtomicU64>>>, // 账号当日请求数 (AccountID -> 打包的 (日, 计数))
+    scheduling_rng: std::sync::Mutex<StdRng>, // 调度随机源 (配置 scheduling_seed 时可复现)
+    draining: Arc<dashmap::DashSet<String>>, // 排空中的账号 (不再分配新请求，等待进行中请求结束后删除)
+    last_used_at: Arc<DashMap<String, i64>>, // 账号最近一次被调度的时间 (AccountID -> Unix 毫秒)
+    canary_traffic_percent: AtomicU32, // 灰度账号可参与调度的请求比例 (百分比)
+    canary_promotion_successes: AtomicU32, // 灰度账号转正所需成功次数 (0 = 不自动转正)
+    canary_successes: Arc<DashMap<String, u32>>, // 灰度账号累计成功次数 (AccountID -> 次数)
+    webhook: Arc<WebhookNotifier>, // 账号健康事件通知
+    monitor: std::sync::RwLock<Option<Arc<ProxyMonitor>>>, // 账号状态变化的实时事件推送
+    slot_queue: Arc<SlotWaitQueue>, // 槽位占满时按优先级排队的请求
 }
 
 impl TokenManager {
     /// 创建新的 TokenManager
     pub fn new(data_dir: PathBuf) -> Self {
+        Self::with_scheduling_seed(data_dir, None)
+    }
+
+    /// 使用指定随机种子创建 TokenManager
+    /// - `Some(seed)`: 调度中的随机选择可复现 (测试/问题排查)
+    /// - `None`: 使用系统随机源
+    pub fn with_scheduling_seed(data_dir: PathBuf, seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let webhook = Arc::new(WebhookNotifier::new(&data_dir));
         Self {
             tokens: Arc::new(DashMap::new()),
+            email_index: Arc::new(DashMap::new()),
             current_index: Arc::new(AtomicUsize::new(0)),
             last_used_account: Arc::new(tokio::sync::Mutex::new(None)),
             data_dir,
             rate_limit_tracker: Arc::new(RateLimitTracker::new()),
             sticky_config: Arc::new(tokio::sync::RwLock::new(StickySessionConfig::default())),
-            session_accounts: Arc::new(DashMap::new()),
+            session_accounts: Arc::new(SessionCache::new(
+                crate::proxy::session_cache::DEFAULT_SESSION_TTL_SECS,
+                crate::proxy::session_cache::DEFAULT_MAX_SESSIONS,
+            )),
+            account_load: Arc::new(DashMap::new()),
+            default_max_concurrency: AtomicU32::new(0),
+            default_max_rpm: AtomicU32::new(0),
+            default_daily_cap: AtomicU64::new(0),
+            daily_reset_time: std::sync::RwLock::new(chrono::NaiveTime::MIN),
+            daily_usage: Arc::new(DashMap::new()),
+            scheduling_rng: std::sync::Mutex::new(rng),
+            draining: Arc::new(dashmap::DashSet::new()),
+            last_used_at: Arc::new(DashMap::new()),
+            canary_traffic_percent: AtomicU32::new(StickySessionConfig::default().canary_traffic_percent),
+            canary_promotion_successes: AtomicU32::new(StickySessionConfig::default().canary_promotion_successes),
+            canary_successes: Arc::new(DashMap::new()),
+            webhook,
+            monitor: std::sync::RwLock::new(None),
+            slot_queue: Arc::new(SlotWaitQueue::new()),
+        }
+    }
+
+    /// 关联监控，账号进入冷却/被禁用时推送实时事件
+    pub fn attach_monitor(&self, monitor: Arc<ProxyMonitor>) {
+        if let Ok(mut current) = self.monitor.write() {
+            *current = Some(monitor);
+        }
+    }
+
+    fn publish_account_state(&self, account_id: &str, state: AccountEventState, reason: Option<String>) {
+        if let Some(monitor) = self.monitor.read().ok().and_then(|m| m.clone()) {
+            monitor.publish(MonitorEvent::AccountStateChanged {
+                account: account_id.to_string(),
+                state,
+                reason,
+            });
+        }
+    }
+
+    /// 更新账号健康 Webhook 地址与账号池健康下限
+    pub fn configure_webhook(&self, url: Option<String>, threshold: &crate::proxy::config::ReadinessThresholdConfig) {
+        self.webhook.configure(url, threshold);
+    }
+
+    /// 账号池健康状态跨越下限时发送事件 (未配置 Webhook 时不计算)
+    fn observe_pool_health(&self) {
+        if self.webhook.is_enabled() {
+            self.webhook.observe_pool(self.available_count(), self.len());
+        }
+    }
+
+    /// 账号由可用转为冷却时发送事件
+    fn notify_cooldown(&self, account_id: &str, status: u16, was_limited: bool) {
+        if was_limited || !self.is_rate_limited(account_id) {
+            return;
         }
+        self.publish_account_state(account_id, AccountEventState::Cooldown, Some(format!("HTTP {}", status)));
+        if !self.webhook.is_enabled() {
+            return;
+        }
+        self.webhook.notify(WebhookEvent::AccountCooldown {
+            account: account_id.to_string(),
+            status,
+            retry_after_secs: self.get_rate_limit_reset_seconds(account_id),
+        });
+        self.observe_pool_health();
+    }
+
+    /// 从调度随机源取 [0, n) 内的随机下标 (n = 0 时返回 0)
+    pub fn random_index(&self, n: usize) -> usize {
+        if n == 0 {
+            return 0;
+        }
+        match self.scheduling_rng.lock() {
+            Ok(mut rng) => rng.gen_range(0..n),
+            Err(_) => 0,
+        }
+    }
+
+    /// 随机化轮询起点，避免每次重载后都从同一账号开始
+    pub fn reset_rotation(&self) {
+        let offset = self.random_index(self.tokens.len());
+        self.current_index.store(offset, Ordering::SeqCst);
     }
     
     /// 从主应用账号目录加载所有账号
@@ -55,6 +306,7 @@ impl TokenManager {
 
         // Reload should reflect current on-disk state (accounts can be added/removed/disabled).
         self.tokens.clear();
+        self.email_index.clear();
         self.current_index.store(0, Ordering::SeqCst);
         {
             let mut last_used = self.last_used_account.lock().await;
@@ -78,6 +330,7 @@ impl TokenManager {
             match self.load_single_account(&path).await {
                 Ok(Some(token)) => {
                     let account_id = token.account_id.clone();
+                    self.email_index.insert(token.email.clone(), account_id.clone());
                     self.tokens.insert(account_id, token);
                     count += 1;
                 },
@@ -89,10 +342,81 @@ impl TokenManager {
                 }
             }
         }
+
+        self.reset_rotation();
         
         Ok(count)
     }
 
+    fn scheduling_state_path(&self) -> PathBuf {
+        self.data_dir.join(SCHEDULING_STATE_FILE)
+    }
+
+    /// 将调度状态 (轮询游标、最近使用时间) 写入数据目录，先写临时文件再原子替换
+    pub async fn save_scheduling_state(&self) -> Result<(), String> {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let last_used_account = self
+            .last_used_account
+            .lock()
+            .await
+            .as_ref()
+            .map(|(id, at)| (id.clone(), now_ms - at.elapsed().as_millis() as i64));
+        let state = SchedulingState {
+            cursor: self.current_index.load(Ordering::SeqCst),
+            last_used_account,
+            last_used_at: self
+                .last_used_at
+                .iter()
+                .map(|e| (e.key().clone(), *e.value()))
+                .collect(),
+        };
+        let content = serde_json::to_string_pretty(&state)
+            .map_err(|e| format!("序列化调度状态失败: {}", e))?;
+        let path = self.scheduling_state_path();
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, content).map_err(|e| format!("写入调度状态失败: {}", e))?;
+        std::fs::rename(&tmp, &path).map_err(|e| format!("保存调度状态失败: {}", e))
+    }
+
+    /// 恢复上次保存的调度状态 (尽力而为，需在 load_accounts 之后调用)
+    /// 文件缺失时保持当前状态；文件损坏时删除并从头开始，返回是否成功恢复
+    pub async fn restore_scheduling_state(&self) -> bool {
+        let path = self.scheduling_state_path();
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            return false;
+        };
+        let state: SchedulingState = match serde_json::from_str(&content) {
+            Ok(state) => state,
+            Err(e) => {
+                tracing::warn!("调度状态文件损坏，已重置: {}", e);
+                let _ = std::fs::remove_file(&path);
+                return false;
+            }
+        };
+
+        self.current_index.store(state.cursor, Ordering::SeqCst);
+        self.last_used_at.clear();
+        for (account_id, at) in state.last_used_at {
+            if self.tokens.contains_key(&account_id) {
+                self.last_used_at.insert(account_id, at);
+            }
+        }
+
+        // 仅恢复仍在 60s 锁定窗口内且账号仍存在的锁定
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let restored_lock = state.last_used_account.and_then(|(account_id, at)| {
+            let age = Duration::from_millis(now_ms.saturating_sub(at).max(0) as u64);
+            if age >= LAST_USED_WINDOW || !self.tokens.contains_key(&account_id) {
+                return None;
+            }
+            Instant::now().checked_sub(age).map(|instant| (account_id, instant))
+        });
+        *self.last_used_account.lock().await = restored_lock;
+
+        tracing::debug!("已恢复调度状态 (cursor={})", state.cursor);
+        true
+    }
+
     /// 重新加载指定账号（用于配额更新后的实时同步）
     pub async fn reload_account(&self, account_id: &str) -> Result<(), String> {
         let path = self.data_dir.join("accounts").join(format!("{}.json", account_id));
@@ -102,6 +426,7 @@ impl TokenManager {
 
         match self.load_single_account(&path).await {
             Ok(Some(token)) => {
+                self.email_index.insert(token.email.clone(), account_id.to_string());
                 self.tokens.insert(account_id.to_string(), token);
                 Ok(())
             }
@@ -169,26 +494,39 @@ impl TokenManager {
             .ok_or("缺少 email 字段")?
             .to_string();
         
-        let token_obj = account["token"].as_object()
-            .ok_or("缺少 token 字段")?;
-        
-        let access_token = token_obj["access_token"].as_str()
-            .ok_or("缺少 access_token")?
-            .to_string();
-        
-        let refresh_token = token_obj["refresh_token"].as_str()
-            .ok_or("缺少 refresh_token")?
-            .to_string();
-        
-        let expires_in = token_obj["expires_in"].as_i64()
-            .ok_or("缺少 expires_in")?;
-        
-        let timestamp = token_obj["expiry_timestamp"].as_i64()
-            .ok_or("缺少 expiry_timestamp")?;
-        
-        // project_id 是可选的
-        let project_id = token_obj.get("project_id")
-            .and_then(|v| v.as_str())
+        // 凭据类型 (缺省为 refresh_token)
+        let credential: Credential = match account.get("credential") {
+            Some(v) => serde_json::from_value(v.clone()).map_err(|e| format!("credential 字段无效: {}", e))?,
+            None => Credential::default(),
+        };
+
+        let empty_token = serde_json::Map::new();
+        let token_obj = match account["token"].as_object() {
+            Some(obj) => obj,
+            None if credential.is_refresh_token() => return Err("缺少 token 字段".to_string()),
+            // API Key / 服务账号不依赖 OAuth token，令牌在首次使用时获取
+            None => &empty_token,
+        };
+
+        let (access_token, refresh_token, expires_in, timestamp) = if credential.is_refresh_token() {
+            (
+                token_obj["access_token"].as_str().ok_or("缺少 access_token")?.to_string(),
+                token_obj["refresh_token"].as_str().ok_or("缺少 refresh_token")?.to_string(),
+                token_obj["expires_in"].as_i64().ok_or("缺少 expires_in")?,
+                token_obj["expiry_timestamp"].as_i64().ok_or("缺少 expiry_timestamp")?,
+            )
+        } else {
+            (
+                token_obj.get("access_token").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+                String::new(),
+                token_obj.get("expires_in").and_then(|v| v.as_i64()).unwrap_or(0),
+                token_obj.get("expiry_timestamp").and_then(|v| v.as_i64()).unwrap_or(0),
+            )
+        };
+
+        // project_id 是可选的 (API Key / 服务账号可在凭据中配置)
+        let project_id = credential.project_id()
+            .or_else(|| token_obj.get("project_id").and_then(|v| v.as_str()))
             .map(|s| s.to_string());
         
         // 【新增】提取订阅等级 (subscription_tier 为 "FREE" | "PRO" | "ULTRA")
@@ -197,6 +535,50 @@ impl TokenManager {
             .and_then(|v| v.as_str())
             .map(|s| s.to_string());
         
+        // 手动优先级 (缺省为 0)
+        let priority = account.get("priority")
+            .and_then(|v| v.as_i64())
+            .map(|p| p.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+            .unwrap_or(0);
+
+        // 账号级上游代理 (加载时校验，非法地址直接拒绝该账号，避免流量误走全局出口)
+        let upstream_proxy = account.get("upstream_proxy")
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string());
+        if let Some(url) = &upstream_proxy {
+            if let Err(e) = crate::proxy::upstream::client::validate_proxy_url(url) {
+                tracing::warn!("账号 {} 的上游代理无效，已跳过: {}", email, e);
+                return Err(format!("账号上游代理无效: {}", e));
+            }
+        }
+
+        let max_concurrency = account.get("max_concurrency")
+            .and_then(|v| v.as_u64())
+            .map(|v| v.min(u32::MAX as u64) as u32);
+        let max_rpm = account.get("max_rpm")
+            .and_then(|v| v.as_u64())
+            .map(|v| v.min(u32::MAX as u64) as u32);
+        let daily_request_cap = account.get("daily_request_cap").and_then(|v| v.as_u64());
+        let canary = account.get("canary").and_then(|v| v.as_bool()).unwrap_or(false);
+        let supported_protocols: Vec<AccountProtocol> = account
+            .get("supported_protocols")
+            .and_then(|v| v.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| match serde_json::from_value(item.clone()) {
+                        Ok(protocol) => Some(protocol),
+                        Err(_) => {
+                            tracing::warn!("账号 {} 的 supported_protocols 包含未知协议 {}，已忽略", email, item);
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
         Ok(Some(ProxyToken {
             account_id,
             access_token,
@@ -207,6 +589,14 @@ impl TokenManager {
             account_path: path.clone(),
             project_id,
             subscription_tier,
+            priority,
+            upstream_proxy,
+            max_concurrency,
+            max_rpm,
+            daily_request_cap,
+            credential,
+            canary,
+            supported_protocols,
         }))
     }
     
@@ -384,7 +774,22 @@ impl TokenManager {
     /// 参数 `quota_group` 用于区分 "claude" vs "gemini" 组
     /// 参数 `force_rotate` 为 true 时将忽略锁定，强制切换账号
     /// 参数 `session_id` 用于跨请求维持会话粘性
-    pub async fn get_token(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(String, String, String), String> {
+    pub async fn get_token(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(UpstreamAuth, String, String), String> {
+        let mut result = self.get_token_with_timeout(quota_group, force_rotate, session_id).await;
+        if matches!(&result, Err(e) if Self::is_saturation_error(e)) {
+            if let Some(queued) = self.wait_for_slot(quota_group, force_rotate, session_id).await {
+                result = queued;
+            }
+        }
+        crate::proxy::middleware::request_trace::record(match &result {
+            Ok((_, _, email)) => format!("account {} (group={}, rotate={})", email, quota_group, force_rotate),
+            Err(e) => format!("account selection failed (group={}): {}", quota_group, e),
+        });
+        self.observe_pool_health();
+        result
+    }
+
+    async fn get_token_with_timeout(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(UpstreamAuth, String, String), String> {
         // 【优化 Issue #284】添加 5 秒超时，防止死锁
         let timeout_duration = std::time::Duration::from_secs(5);
         match tokio::time::timeout(timeout_duration, self.get_token_internal(quota_group, force_rotate, session_id)).await {
@@ -393,16 +798,89 @@ impl TokenManager {
         }
     }
 
+    /// 选号失败是否因为所有账号的并发/RPM/每日槽位均被占满
+    fn is_saturation_error(error: &str) -> bool {
+        error.contains("concurrency/RPM/daily limit")
+    }
+
+    /// 槽位占满时按请求优先级排队，槽位释放后重新选号
+    /// 未启用排队时返回 None (沿用原错误)；排队超时后做最后一次尝试并返回其结果
+    async fn wait_for_slot(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Option<Result<(UpstreamAuth, String, String), String>> {
+        let config = self.sticky_config.read().await.priority_queue.clone();
+        if !config.enabled || config.max_queue_len == 0 {
+            return None;
+        }
+        let priority = REQUEST_PRIORITY
+            .try_with(|hint| config.resolve(hint))
+            .unwrap_or(config.default_priority);
+        // 重试换号的请求先归还已占用的槽位，避免排队等待自己持有的槽位
+        let released = REQUEST_ACCOUNT_SLOT.try_with(|holder| holder.lock().ok().and_then(|mut slot| slot.take()));
+        drop(released);
+
+        let mut waiter = match self.slot_queue.enqueue(priority, config.max_queue_len) {
+            Ok(waiter) => waiter,
+            Err(e) => return Some(Err(e)),
+        };
+        tracing::debug!(
+            "[Priority-Queue] All account slots busy, queued {} request ({} waiting)",
+            priority.as_str(),
+            self.slot_queue.len()
+        );
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(config.max_wait_secs);
+        let mut woken = false;
+        loop {
+            // 入队后先重试一次，避免错过选号失败到入队之间释放的槽位
+            let result = self.get_token_with_timeout(quota_group, force_rotate, session_id).await;
+            if !matches!(&result, Err(e) if Self::is_saturation_error(e)) {
+                return Some(result);
+            }
+            // 被唤醒后仍未抢到槽位 (已被抢占，或释放的账号不服务本请求的协议/配额组)：
+            // 保留原排队位置重新等待，并把唤醒转交给下一个等待者
+            if woken {
+                self.slot_queue.requeue(&mut waiter);
+            }
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                tracing::debug!("[Priority-Queue] {} request timed out after {}s in queue", priority.as_str(), config.max_wait_secs);
+                return Some(result);
+            }
+            // RPM/每日窗口到期释放的槽位没有唤醒事件，定期自行重试
+            woken = waiter.wait(remaining.min(SLOT_RECHECK_INTERVAL)).await;
+        }
+    }
+
     /// 内部实现：获取 Token 的核心逻辑
-    async fn get_token_internal(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(String, String, String), String> {
-        let mut tokens_snapshot: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
+    async fn get_token_internal(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(UpstreamAuth, String, String), String> {
+        let mut tokens_snapshot: Vec<ProxyToken> = self
+            .tokens
+            .iter()
+            .filter(|e| !self.draining.contains(e.key()))
+            .map(|e| e.value().clone())
+            .collect();
+        // 账号限定了可服务的协议时，跳过不支持当前请求协议的账号
+        if let Ok(protocol) = REQUEST_PROTOCOL.try_with(|p| *p) {
+            tokens_snapshot.retain(|t| t.serves_protocol(protocol));
+            if tokens_snapshot.is_empty() && !self.tokens.is_empty() {
+                return Err(format!("No account supports the {} protocol", protocol.as_str()));
+            }
+        }
+        // 灰度账号只参与一小部分请求的调度：本次未抽中且有正式账号可用时排除灰度账号
+        if tokens_snapshot.iter().any(|t| t.canary)
+            && tokens_snapshot.iter().any(|t| !t.canary)
+            && !self.admit_canary()
+        {
+            tokens_snapshot.retain(|t| !t.canary);
+        }
         let total = tokens_snapshot.len();
         if total == 0 {
+            if !self.tokens.is_empty() {
+                return Err("All accounts are draining".to_string());
+            }
             return Err("Token pool is empty".to_string());
         }
 
-        // ===== 【优化】根据订阅等级排序 (优先级: ULTRA > PRO > FREE) =====
-        // 理由: ULTRA/PRO 重置快，优先消耗；FREE 重置慢，用于兜底
+        // ===== 【优化】先按手动优先级 (降序)，再按订阅等级排序 (ULTRA > PRO > FREE) =====
+        // 理由: 用户指定的优先级最高；ULTRA/PRO 重置快，优先消耗；FREE 重置慢，用于兜底
         tokens_snapshot.sort_by(|a, b| {
             let tier_priority = |tier: &Option<String>| match tier.as_deref() {
                 Some("ULTRA") => 0,
@@ -410,7 +888,10 @@ impl TokenManager {
                 Some("FREE") => 2,
                 _ => 3,
             };
-            tier_priority(&a.subscription_tier).cmp(&tier_priority(&b.subscription_tier))
+            b.priority.cmp(&a.priority)
+                .then_with(|| tier_priority(&a.subscription_tier).cmp(&tier_priority(&b.subscription_tier)))
+                // 同档账号按 ID 排序，使轮询顺序不依赖 DashMap 的遍历顺序
+                .then_with(|| a.account_id.cmp(&b.account_id))
         });
 
         // 0. 读取当前调度配置
@@ -433,6 +914,9 @@ impl TokenManager {
         for attempt in 0..total {
             let rotate = force_rotate || attempt > 0;
 
+            // 手动优先级: 只在当前可用账号中最高优先级的一档内调度
+            let top_priority = self.top_available_priority(&tokens_snapshot, &attempted);
+
             // ===== 【核心】粘性会话与智能调度逻辑 =====
             let mut target_token: Option<ProxyToken> = None;
             
@@ -441,7 +925,7 @@ impl TokenManager {
                 let sid = session_id.unwrap();
                 
                 // 1. 检查会话是否已绑定账号
-                if let Some(bound_id) = self.session_accounts.get(sid).map(|v| v.clone()) {
+                if let Some(bound_id) = self.session_accounts.get(sid) {
                     // 【修复】先通过 account_id 找到对应的账号，获取其 email
                     // 因为限流记录是以 email 为 key 存储的
                     if let Some(bound_token) = tokens_snapshot.iter().find(|t| t.account_id == bound_id) {
@@ -455,6 +939,19 @@ impl TokenManager {
                                 sid, bound_token.email, reset_sec
                             );
                             self.session_accounts.remove(sid);
+                        } else if self.is_token_saturated(bound_token) {
+                            // 达到并发/RPM/每日上限：保留绑定，本次临时使用其他账号
+                            tracing::debug!(
+                                "Session {} bound account {} is at its concurrency/RPM/daily limit, using another account for this request.",
+                                sid, bound_token.email
+                            );
+                        } else if bound_token.priority < top_priority {
+                            // 有更高优先级的健康账号可用，放弃当前绑定
+                            tracing::debug!(
+                                "Session {} bound account {} has lower priority ({} < {}), rebinding.",
+                                sid, bound_token.email, bound_token.priority, top_priority
+                            );
+                            self.session_accounts.remove(sid);
                         } else if !attempted.contains(&bound_id) {
                             // 3. 账号可用且未被标记为尝试失败，优先复用
                             tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", bound_token.email, sid);
@@ -472,10 +969,12 @@ impl TokenManager {
             if target_token.is_none() && !rotate && quota_group != "image_gen" {
                 // 【优化】使用预先获取的快照，不再在循环内加锁
                 if let Some((account_id, last_time)) = &last_used_account_id {
-                    if last_time.elapsed().as_secs() < 60 && !attempted.contains(account_id) {
+                    if last_time.elapsed() < LAST_USED_WINDOW && !attempted.contains(account_id) {
                         if let Some(found) = tokens_snapshot.iter().find(|t| &t.account_id == account_id) {
                             // 【修复】检查限流状态，避免复用已被锁定的账号
-                            if !self.is_rate_limited(&found.email) {
+                            if found.priority < top_priority {
+                                tracing::debug!("60s Window: Last account {} has lower priority, skipping", found.email);
+                            } else if !self.is_rate_limited(&found.email) && !self.is_token_saturated(found) {
                                 tracing::debug!("60s Window: Force reusing last account: {}", found.email);
                                 target_token = Some(found.clone());
                             } else {
@@ -496,7 +995,12 @@ impl TokenManager {
                         }
 
                         // 【新增】主动避开限流或 5xx 锁定的账号 (来自 PR #28 的高可用思路)
-                        if self.is_rate_limited(&candidate.account_id) {
+                        if self.is_token_rate_limited(candidate) {
+                            continue;
+                        }
+
+                        // 仅在同一优先级内轮询
+                        if candidate.priority < top_priority {
                             continue;
                         }
 
@@ -504,9 +1008,9 @@ impl TokenManager {
                         // 【优化】标记需要更新，稍后统一写回
                         need_update_last_used = Some((candidate.account_id.clone(), std::time::Instant::now()));
                         
-                        // 如果是会话首次分配且需要粘性，在此建立绑定
+                        // 如果是会话首次分配且需要粘性，在此建立绑定 (灰度账号不绑定会话，避免整段会话落在灰度账号上)
                         if let Some(sid) = session_id {
-                            if scheduling.mode != SchedulingMode::PerformanceFirst {
+                            if scheduling.mode != SchedulingMode::PerformanceFirst && !candidate.canary {
                                 self.session_accounts.insert(sid.to_string(), candidate.account_id.clone());
                                 tracing::debug!("Sticky Session: Bound new account {} to session {}", candidate.email, sid);
                             }
@@ -525,7 +1029,12 @@ impl TokenManager {
                     }
 
                     // 【新增】主动避开限流或 5xx 锁定的账号
-                    if self.is_rate_limited(&candidate.account_id) {
+                    if self.is_token_rate_limited(candidate) {
+                        continue;
+                    }
+
+                    // 仅在同一优先级内轮询
+                    if candidate.priority < top_priority {
                         continue;
                     }
 
@@ -541,6 +1050,11 @@ impl TokenManager {
             let mut token = match target_token {
                 Some(t) => t,
                 None => {
+                    // 账号均被并发/RPM/每日上限占满时直接报错，不触发限流的乐观重置
+                    if tokens_snapshot.iter().any(|t| !attempted.contains(&t.account_id) && self.is_token_saturated(t)) {
+                        return Err("All available accounts are at their concurrency/RPM/daily limit".to_string());
+                    }
+
                     // 乐观重置策略: 双层防护机制
                     // 当所有账号都无法选择时,可能是时序竞争导致的状态不同步
                     
@@ -603,13 +1117,14 @@ impl TokenManager {
             };
 
         
-            // 3. 检查 token 是否过期（提前5分钟刷新）
+            // 3. 检查 token 是否过期（提前5分钟刷新，API Key 无需刷新）
             let now = chrono::Utc::now().timestamp();
-            if now >= token.timestamp - 300 {
+            let needs_refresh = !matches!(token.credential, Credential::ApiKey { .. });
+            if needs_refresh && now >= token.timestamp - 300 {
                 tracing::debug!("账号 {} 的 token 即将过期，正在刷新...", token.email);
 
-                // 调用 OAuth 刷新 token
-                match crate::modules::oauth::refresh_access_token(&token.refresh_token).await {
+                // 按凭据类型刷新 (OAuth refresh_token / 服务账号元数据服务)
+                match fetch_access_token(&token.credential, &token.refresh_token).await {
                     Ok(token_response) => {
                         tracing::debug!("Token 刷新成功！");
 
@@ -632,7 +1147,8 @@ impl TokenManager {
                     }
                     Err(e) => {
                         tracing::error!("Token 刷新失败 ({}): {}，尝试下一个账号", token.email, e);
-                        if e.contains("\"invalid_grant\"") || e.contains("invalid_grant") {
+                        // 仅 refresh_token 失效时禁用账号 (服务账号失败多为元数据服务暂时不可用)
+                        if token.credential.is_refresh_token() && e.contains("invalid_grant") {
                             tracing::error!(
                                 "Disabling account due to invalid_grant ({}): refresh_token likely revoked/expired",
                                 token.email
@@ -657,9 +1173,14 @@ impl TokenManager {
                 }
             }
 
-            // 4. 确保有 project_id
+            // 4. 确保有 project_id (API Key 无法通过 loadCodeAssist 解析，须在账号中配置)
             let project_id = if let Some(pid) = &token.project_id {
                 pid.clone()
+            } else if matches!(token.credential, Credential::ApiKey { .. }) {
+                tracing::error!("API Key account {} has no project_id configured", token.email);
+                last_error = Some(format!("API Key account {} requires a project_id", token.email));
+                attempted.insert(token.account_id.clone());
+                continue;
             } else {
                 tracing::debug!("账号 {} 缺少 project_id，尝试获取...", token.email);
                 match crate::proxy::project_resolver::fetch_project_id(&token.access_token).await {
@@ -686,6 +1207,24 @@ impl TokenManager {
                 }
             };
 
+            // 占用并发/RPM/每日槽位 (并发竞争下可能刚好被占满，此时换号)
+            let slot = match self.try_acquire_slot(&token) {
+                Some(slot) => slot,
+                None => {
+                    tracing::debug!("Account {} reached its concurrency/RPM/daily limit or is draining, trying next account", token.email);
+                    last_error = Some("All accounts are at their concurrency/RPM/daily limit".to_string());
+                    attempted.insert(token.account_id.clone());
+                    continue;
+                }
+            };
+            let _ = REQUEST_ACCOUNT_SLOT.try_with(|holder| {
+                if let Ok(mut current) = holder.lock() {
+                    *current = Some(slot);
+                }
+            });
+
+            self.last_used_at.insert(token.account_id.clone(), chrono::Utc::now().timestamp_millis());
+
             // 【优化】在成功返回前，统一更新 last_used_account（如果需要）
             if let Some((new_account_id, new_time)) = need_update_last_used {
                 if quota_group != "image_gen" {
@@ -699,12 +1238,274 @@ impl TokenManager {
                 }
             }
 
-            return Ok((token.access_token, project_id, token.email));
+            return Ok((token.upstream_auth(), project_id, token.email));
         }
 
         Err(last_error.unwrap_or_else(|| "All accounts failed".to_string()))
     }
 
+    /// 限流记录可能以 account_id 或 email 为 key (handler 侧使用 email)，两者都需检查
+    /// 达到账号并发/RPM/每日上限的账号同样视为暂不可用
+    fn is_token_rate_limited(&self, token: &ProxyToken) -> bool {
+        self.is_rate_limited(&token.account_id)
+            || self.is_rate_limited(&token.email)
+            || self.is_token_saturated(token)
+    }
+
+    /// 账号生效的 (并发上限, RPM 上限)，账号级配置优先于全局默认值，0 表示不限制
+    fn effective_limits(&self, token: &ProxyToken) -> (u32, u32) {
+        (
+            token.max_concurrency.unwrap_or_else(|| self.default_max_concurrency.load(Ordering::Relaxed)),
+            token.max_rpm.unwrap_or_else(|| self.default_max_rpm.load(Ordering::Relaxed)),
+        )
+    }
+
+    fn local_now() -> chrono::NaiveDateTime {
+        chrono::Local::now().naive_local()
+    }
+
+    /// 账号生效的每日请求上限，0 表示不限制
+    fn effective_daily_cap(&self, token: &ProxyToken) -> u64 {
+        token
+            .daily_request_cap
+            .unwrap_or_else(|| self.default_daily_cap.load(Ordering::Relaxed))
+    }
+
+    fn current_daily_window(&self, now: chrono::NaiveDateTime) -> chrono::NaiveDate {
+        let reset = self
+            .daily_reset_time
+            .read()
+            .map(|t| *t)
+            .unwrap_or(chrono::NaiveTime::MIN);
+        daily_window(now, reset)
+    }
+
+    /// 账号在 `now` 所属计数日内的请求数
+    fn requests_today_at(&self, account_id: &str, now: chrono::NaiveDateTime) -> u64 {
+        let window = self.current_daily_window(now);
+        self.daily_usage
+            .get(account_id)
+            .map(|usage| unpack_daily_count(usage.load(Ordering::SeqCst), window))
+            .unwrap_or(0)
+    }
+
+    fn is_daily_capped_at(&self, token: &ProxyToken, now: chrono::NaiveDateTime) -> bool {
+        let cap = self.effective_daily_cap(token);
+        cap > 0 && self.requests_today_at(&token.account_id, now) >= cap
+    }
+
+    /// 在未达上限 (`cap`，0 表示不限制) 时计入一次请求；检查与计数为同一次原子更新，
+    /// 并发请求不会超出上限。已达上限时返回 false
+    fn try_record_daily_request_at(&self, account_id: &str, cap: u64, now: chrono::NaiveDateTime) -> bool {
+        let window = self.current_daily_window(now);
+        let usage = self.daily_usage.entry(account_id.to_string()).or_default().value().clone();
+        usage
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |packed| {
+                let count = unpack_daily_count(packed, window);
+                if cap > 0 && count >= cap {
+                    return None;
+                }
+                Some(pack_daily_usage(window, count + 1))
+            })
+            .is_ok()
+    }
+
+    /// 所有账号的健康概况 (按邮箱排序)
+    pub fn account_health(&self) -> Vec<AccountHealth> {
+        let now = Self::local_now();
+        let mut list: Vec<AccountHealth> = self
+            .tokens
+            .iter()
+            .map(|entry| {
+                let token = entry.value();
+                let requests_today = self.requests_today_at(&token.account_id, now);
+                let cap = self.effective_daily_cap(token);
+                AccountHealth {
+                    account_id: token.account_id.clone(),
+                    email: token.email.clone(),
+                    in_flight: self.in_flight(&token.account_id),
+                    rate_limited: self.is_rate_limited(&token.account_id) || self.is_rate_limited(&token.email),
+                    draining: self.is_draining(&token.account_id),
+                    requests_today,
+                    remaining_today: (cap > 0).then(|| cap.saturating_sub(requests_today)),
+                    last_used_at: self.last_used_at.get(&token.account_id).map(|t| *t),
+                    canary: token.canary,
+                }
+            })
+            .collect();
+        list.sort_by(|a, b| a.email.cmp(&b.email));
+        list
+    }
+
+    /// 账号是否已达到并发、RPM 或每日上限
+    fn is_token_saturated(&self, token: &ProxyToken) -> bool {
+        if self.is_daily_capped_at(token, Self::local_now()) {
+            return true;
+        }
+        let (max_concurrency, max_rpm) = self.effective_limits(token);
+        if max_concurrency == 0 && max_rpm == 0 {
+            return false;
+        }
+        let Some(load) = self.account_load.get(&token.account_id).map(|l| l.value().clone()) else {
+            return false;
+        };
+        if max_concurrency > 0 && load.in_flight.load(Ordering::SeqCst) >= max_concurrency as usize {
+            return true;
+        }
+        if max_rpm > 0 {
+            if let Ok(mut recent) = load.recent.lock() {
+                let now = Instant::now();
+                while recent.front().is_some_and(|t| now.duration_since(*t) >= RPM_WINDOW) {
+                    recent.pop_front();
+                }
+                return recent.len() >= max_rpm as usize;
+            }
+        }
+        false
+    }
+
+    /// 尝试为账号占用一个并发槽位并记录一次请求 (RPM)
+    /// 超出上限时返回 None；槽位在返回值 drop 时释放
+    pub fn try_acquire_slot(&self, token: &ProxyToken) -> Option<AccountSlot> {
+        let local_now = Self::local_now();
+        if self.is_daily_capped_at(token, local_now) {
+            return None;
+        }
+        let (max_concurrency, max_rpm) = self.effective_limits(token);
+        let load = self
+            .account_load
+            .entry(token.account_id.clone())
+            .or_default()
+            .value()
+            .clone();
+
+        // 1. 并发 (CAS 避免超额)
+        let mut current = load.in_flight.load(Ordering::SeqCst);
+        loop {
+            if max_concurrency > 0 && current >= max_concurrency as usize {
+                return None;
+            }
+            match load.in_flight.compare_exchange(current, current + 1, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+        let slot = AccountSlot { load: load.clone(), queue: self.slot_queue.clone() };
+
+        // 调用方的账号快照可能早于排空开始：占用计数后再检查一次，
+        // 与 drain_account 的 "先标记再读计数" 配对，二者至少有一方能看到对方
+        std::sync::atomic::fence(Ordering::SeqCst);
+        if self.draining.contains(&token.account_id) {
+            return None;
+        }
+
+        // 2. RPM (失败时 slot drop 自动归还并发计数)
+        let mut recent = load.recent.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        while recent.front().is_some_and(|t| now.duration_since(*t) >= RPM_WINDOW) {
+            recent.pop_front();
+        }
+        if max_rpm > 0 && recent.len() >= max_rpm as usize {
+            return None;
+        }
+
+        // 3. 每日计数 (检查与计数一次完成；失败时不记入 RPM)
+        if !self.try_record_daily_request_at(&token.account_id, self.effective_daily_cap(token), local_now) {
+            return None;
+        }
+        recent.push_back(now);
+        drop(recent);
+
+        Some(slot)
+    }
+
+    /// 账号数据目录
+    pub fn data_dir(&self) -> &std::path::Path {
+        &self.data_dir
+    }
+
+    /// 按账号 ID 或邮箱查找账号池中的账号 ID
+    pub fn find_account_id(&self, id_or_email: &str) -> Option<String> {
+        if self.tokens.contains_key(id_or_email) {
+            return Some(id_or_email.to_string());
+        }
+        self.tokens
+            .iter()
+            .find(|t| t.email == id_or_email)
+            .map(|t| t.account_id.clone())
+    }
+
+    /// 账号当前进行中的请求数
+    pub fn in_flight(&self, account_id: &str) -> usize {
+        self.account_load
+            .get(account_id)
+            .map(|load| load.in_flight.load(Ordering::SeqCst))
+            .unwrap_or(0)
+    }
+
+    pub fn is_draining(&self, account_id: &str) -> bool {
+        self.draining.contains(account_id)
+    }
+
+    /// 排空账号：立即停止为其分配新请求，并等待进行中的请求结束
+    /// 返回 Ok(()) 表示已排空，排空状态保留到账号被删除、撤销或服务重启；
+    /// 超时或被 [`Self::undrain_account`] 撤销时恢复调度并返回仍在进行中的请求数
+    pub async fn drain_account(&self, account_id: &str, timeout: Duration) -> Result<(), usize> {
+        self.draining.insert(account_id.to_string());
+        std::sync::atomic::fence(Ordering::SeqCst);
+        // 会话绑定与 60s 复用窗口都不应再指向该账号
+        self.session_accounts.retain(|bound| bound != account_id);
+        {
+            let mut last_used = self.last_used_account.lock().await;
+            if matches!(&*last_used, Some((id, _)) if id == account_id) {
+                *last_used = None;
+            }
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = self.in_flight(account_id);
+            if remaining == 0 {
+                tracing::info!("Account {} drained", account_id);
+                return Ok(());
+            }
+            if !self.draining.contains(account_id) {
+                tracing::info!("Account {} drain cancelled with {} request(s) in flight", account_id, remaining);
+                return Err(remaining);
+            }
+            if Instant::now() >= deadline {
+                self.draining.remove(account_id);
+                tracing::warn!(
+                    "Account {} drain timed out with {} request(s) in flight, returning it to rotation",
+                    account_id,
+                    remaining
+                );
+                return Err(remaining);
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// 撤销排空，账号重新参与调度；返回该账号此前是否处于排空状态
+    pub fn undrain_account(&self, account_id: &str) -> bool {
+        let was_draining = self.draining.remove(account_id).is_some();
+        if was_draining {
+            tracing::info!("Account {} returned to rotation", account_id);
+        }
+        was_draining
+    }
+
+    /// 当前可选账号 (未尝试且未限流) 中的最高优先级
+    /// 没有可选账号时返回 `i32::MIN`，不限制后续选择
+    fn top_available_priority(&self, tokens: &[ProxyToken], attempted: &HashSet<String>) -> i32 {
+        tokens
+            .iter()
+            .filter(|t| !attempted.contains(&t.account_id) && !self.is_token_rate_limited(t))
+            .map(|t| t.priority)
+            .max()
+            .unwrap_or(i32::MIN)
+    }
+
     async fn disable_account(&self, account_id: &str, reason: &str) -> Result<(), String> {
         let path = if let Some(entry) = self.tokens.get(account_id) {
             entry.account_path.clone()
@@ -728,6 +1529,17 @@ impl TokenManager {
             .map_err(|e| format!("写入文件失败: {}", e))?;
 
         tracing::warn!("Account disabled: {} ({:?})", account_id, path);
+        self.publish_account_state(account_id, AccountEventState::Disabled, Some(truncate_reason(reason, 200)));
+        self.webhook.notify(WebhookEvent::AccountDisabled {
+            account: account_id.to_string(),
+            reason: truncate_reason(reason, 200),
+        });
+        crate::modules::audit::record_in(
+            &self.data_dir,
+            Some(crate::modules::audit::SYSTEM_ACTOR),
+            "account.disable",
+            serde_json::json!({ "account_id": account_id, "reason": truncate_reason(reason, 200) }),
+        );
         Ok(())
     }
 
@@ -742,7 +1554,9 @@ impl TokenManager {
             &std::fs::read_to_string(path).map_err(|e| format!("读取文件失败: {}", e))?
         ).map_err(|e| format!("解析 JSON 失败: {}", e))?;
         
-        content["token"]["project_id"] = serde_json::Value::String(project_id.to_string());
+        // 非 refresh_token 账号没有 OAuth token，项目写入凭据
+        let section = if entry.credential.is_refresh_token() { "token" } else { "credential" };
+        content[section]["project_id"] = serde_json::Value::String(project_id.to_string());
         
         std::fs::write(path, serde_json::to_string_pretty(&content).unwrap())
             .map_err(|e| format!("写入文件失败: {}", e))?;
@@ -755,6 +1569,10 @@ impl TokenManager {
     async fn save_refreshed_token(&self, account_id: &str, token_response: &crate::modules::oauth::TokenResponse) -> Result<(), String> {
         let entry = self.tokens.get(account_id)
             .ok_or("账号不存在")?;
+        // 服务账号令牌仅缓存在内存中，账号文件不保存 OAuth token
+        if !entry.credential.is_refresh_token() {
+            return Ok(());
+        }
         
         let path = &entry.account_path;
         
@@ -779,9 +1597,51 @@ impl TokenManager {
         self.tokens.len()
     }
 
+    /// 当前可分配新请求的账号数 (排除排空中、限流及达到并发/RPM 上限的账号)
+    pub fn available_count(&self) -> usize {
+        self.tokens
+            .iter()
+            .filter(|e| !self.draining.contains(e.key()) && !self.is_token_rate_limited(e.value()))
+            .count()
+    }
+
+    /// 当前账号池中出现的订阅等级 (去重排序，未知等级记为 "UNKNOWN")
+    pub fn subscription_tiers(&self) -> Vec<String> {
+        let tiers: std::collections::BTreeSet<String> = self
+            .tokens
+            .iter()
+            .map(|t| t.subscription_tier.clone().unwrap_or_else(|| "UNKNOWN".to_string()))
+            .collect();
+        tiers.into_iter().collect()
+    }
+
+    /// 是否至少有一个健康账号 (未排空、未处于限流或失败熔断锁定) 能服务指定协议下要求 `required_tier` 等级的模型
+    /// 未知订阅等级的账号视为可服务 (缺少配额信息时不隐藏模型)
+    pub fn can_serve_model(&self, required_tier: Option<&str>, protocol: AccountProtocol) -> bool {
+        let required = required_tier.and_then(crate::proxy::config::subscription_tier_rank);
+        self.tokens.iter().any(|entry| {
+            let token = entry.value();
+            if self.draining.contains(&token.account_id)
+                || self.is_rate_limited(&token.account_id)
+                || self.is_rate_limited(&token.email)
+                || !token.serves_protocol(protocol)
+            {
+                return false;
+            }
+            let Some(required) = required else {
+                return true;
+            };
+            token
+                .subscription_tier
+                .as_deref()
+                .and_then(crate::proxy::config::subscription_tier_rank)
+                .is_none_or(|rank| rank >= required)
+        })
+    }
+
     /// 通过 email 获取指定账号的 Token（用于预热等需要指定账号的场景）
     /// 此方法会自动刷新过期的 token
-    pub async fn get_token_by_email(&self, email: &str) -> Result<(String, String, String), String> {
+    pub async fn get_token_by_email(&self, email: &str) -> Result<(UpstreamAuth, String, String), String> {
         // 查找账号信息
         let token_info = {
             let mut found = None;
@@ -790,12 +1650,13 @@ impl TokenManager {
                 if token.email == email {
                     found = Some((
                         token.account_id.clone(),
-                        token.access_token.clone(),
+                        token.upstream_auth(),
                         token.refresh_token.clone(),
                         token.timestamp,
                         token.expires_in,
                         chrono::Utc::now().timestamp(),
                         token.project_id.clone(),
+                        token.credential.clone(),
                     ));
                     break;
                 }
@@ -811,6 +1672,7 @@ impl TokenManager {
             expires_in,
             now,
             project_id_opt,
+            credential,
         ) = match token_info {
             Some(info) => info,
             None => return Err(format!("未找到账号: {}", email)),
@@ -818,15 +1680,15 @@ impl TokenManager {
 
         let project_id = project_id_opt.unwrap_or_else(|| "bamboo-precept-lgxtn".to_string());
         
-        // 检查是否过期 (提前5分钟)
-        if now < timestamp + expires_in - 300 {
+        // 检查是否过期 (提前5分钟)；API Key 无需刷新
+        if matches!(credential, Credential::ApiKey { .. }) || now < timestamp + expires_in - 300 {
             return Ok((current_access_token, project_id, email.to_string()));
         }
 
         tracing::info!("[Warmup] Token for {} is expiring, refreshing...", email);
 
-        // 调用 OAuth 刷新 token
-        match crate::modules::oauth::refresh_access_token(&refresh_token).await {
+        // 按凭据类型刷新 token
+        match fetch_access_token(&credential, &refresh_token).await {
             Ok(token_response) => {
                 tracing::info!("[Warmup] Token refresh successful for {}", email);
                 let new_now = chrono::Utc::now().timestamp();
@@ -841,11 +1703,18 @@ impl TokenManager {
                 // 保存到磁盘
                 let _ = self.save_refreshed_token(&account_id, &token_response).await;
 
-                Ok((token_response.access_token, project_id, email.to_string()))
+                Ok((UpstreamAuth::Bearer(token_response.access_token), project_id, email.to_string()))
             }
             Err(e) => Err(format!("[Warmup] Token refresh failed for {}: {}", email, e)),
         }
     }
+
+    /// 获取账号级上游代理 (未设置时返回 None，调用方使用全局代理)
+    /// 每个上游请求都会调用，经邮箱索引按 account_id 直接查找，不遍历账号池
+    pub fn upstream_proxy_for(&self, email: &str) -> Option<String> {
+        let account_id = self.email_index.get(email)?.value().clone();
+        self.tokens.get(&account_id)?.upstream_proxy.clone()
+    }
     
     // ===== 限流管理方法 =====
     
@@ -857,6 +1726,7 @@ impl TokenManager {
         retry_after_header: Option<&str>,
         error_body: &str,
     ) {
+        let was_limited = self.is_rate_limited(account_id);
         self.rate_limit_tracker.parse_from_error(
             account_id,
             status,
@@ -864,6 +1734,7 @@ impl TokenManager {
             error_body,
             None,
         );
+        self.notify_cooldown(account_id, status, was_limited);
     }
     
     /// 检查账号是否在限流中
@@ -877,6 +1748,24 @@ impl TokenManager {
         self.rate_limit_tracker.get_reset_seconds(account_id)
     }
     
+    /// 所有账号均处于限流时，返回最早解除限流的剩余秒数 (用于向客户端回传 Retry-After)
+    /// 只要还有未限流的账号就返回 None
+    pub fn min_rate_limit_wait(&self) -> Option<u64> {
+        let mut min_wait: Option<u64> = None;
+        for entry in self.tokens.iter() {
+            let token = entry.value();
+            if !self.is_token_rate_limited(token) {
+                return None;
+            }
+            let wait = self
+                .get_rate_limit_reset_seconds(&token.account_id)
+                .max(self.get_rate_limit_reset_seconds(&token.email))
+                .unwrap_or(0);
+            min_wait = Some(min_wait.map_or(wait, |m| m.min(wait)));
+        }
+        min_wait
+    }
+    
     /// 清除过期的限流记录
     #[allow(dead_code)]
     pub fn cleanup_expired_rate_limits(&self) -> usize {
@@ -895,6 +1784,55 @@ impl TokenManager {
     /// 下次失败时从最短的锁定时间开始（智能限流）。
     pub fn mark_account_success(&self, account_id: &str) {
         self.rate_limit_tracker.mark_success(account_id);
+        self.record_canary_success(account_id);
+    }
+
+    /// 本次请求是否允许调度到灰度账号 (按 canary_traffic_percent 抽样)
+    fn admit_canary(&self) -> bool {
+        let percent = self.canary_traffic_percent.load(Ordering::Relaxed);
+        percent >= 100 || (self.random_index(100) as u32) < percent
+    }
+
+    /// 记录灰度账号的一次成功请求，累计达到 canary_promotion_successes 后自动转正并写回账号文件
+    pub fn record_canary_success(&self, id_or_email: &str) {
+        let Some(account_id) = self.find_account_id(id_or_email) else {
+            return;
+        };
+        if !self.tokens.get(&account_id).is_some_and(|t| t.canary) {
+            return;
+        }
+        let successes = {
+            let mut entry = self.canary_successes.entry(account_id.clone()).or_insert(0);
+            *entry += 1;
+            *entry
+        };
+        let threshold = self.canary_promotion_successes.load(Ordering::Relaxed);
+        if threshold == 0 || successes < threshold {
+            return;
+        }
+
+        let path = match self.tokens.get_mut(&account_id) {
+            Some(mut token) => {
+                token.canary = false;
+                token.account_path.clone()
+            }
+            None => return,
+        };
+        self.canary_successes.remove(&account_id);
+        tracing::info!("Canary account {} promoted after {} successful requests", account_id, successes);
+        if let Err(e) = Self::clear_canary_flag(&path) {
+            tracing::warn!("账号 {} 转正后写回文件失败: {}", account_id, e);
+        }
+    }
+
+    fn clear_canary_flag(path: &PathBuf) -> Result<(), String> {
+        let mut content: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(path).map_err(|e| format!("读取文件失败: {}", e))?,
+        )
+        .map_err(|e| format!("解析 JSON 失败: {}", e))?;
+        content["canary"] = serde_json::Value::Bool(false);
+        std::fs::write(path, serde_json::to_string_pretty(&content).unwrap())
+            .map_err(|e| format!("写入文件失败: {}", e))
     }
     
     /// 从账号文件获取配额刷新时间
@@ -971,12 +1909,12 @@ impl TokenManager {
         reason: crate::proxy::rate_limit::RateLimitReason,
         model: Option<String>,
     ) -> bool {
-        // 1. 从 tokens 中获取该账号的 access_token
+        // 1. 从 tokens 中获取该账号的出站凭据
         let access_token = {
-            let mut found_token: Option<String> = None;
+            let mut found_token: Option<UpstreamAuth> = None;
             for entry in self.tokens.iter() {
                 if entry.value().email == email {
-                    found_token = Some(entry.value().access_token.clone());
+                    found_token = Some(entry.value().upstream_auth());
                     break;
                 }
             }
@@ -1041,6 +1979,19 @@ impl TokenManager {
         retry_after_header: Option<&str>,
         error_body: &str,
         model: Option<&str>,  // 🆕 新增模型参数
+    ) {
+        let was_limited = self.is_rate_limited(account_id);
+        self.lock_rate_limited(account_id, status, retry_after_header, error_body, model).await;
+        self.notify_cooldown(account_id, status, was_limited);
+    }
+
+    async fn lock_rate_limited(
+        &self,
+        account_id: &str,
+        status: u16,
+        retry_after_header: Option<&str>,
+        error_body: &str,
+        model: Option<&str>,
     ) {
         if status == 429 {
             let body_len = error_body.len();
@@ -1126,6 +2077,20 @@ impl TokenManager {
     /// 更新调度配置
     pub async fn update_sticky_config(&self, new_config: StickySessionConfig) {
         let mut config = self.sticky_config.write().await;
+        self.default_max_concurrency.store(new_config.max_concurrency_per_account, Ordering::Relaxed);
+        self.default_max_rpm.store(new_config.max_rpm_per_account, Ordering::Relaxed);
+        self.default_daily_cap.store(new_config.daily_request_cap_per_account, Ordering::Relaxed);
+        match crate::proxy::sticky_config::parse_daily_reset_time(&new_config.daily_reset_time) {
+            Ok(reset) => {
+                if let Ok(mut current) = self.daily_reset_time.write() {
+                    *current = reset;
+                }
+            }
+            Err(e) => tracing::warn!("{}", e),
+        }
+        self.session_accounts.configure(new_config.session_ttl_secs, new_config.max_sessions);
+        self.canary_traffic_percent.store(new_config.canary_traffic_percent.min(100), Ordering::Relaxed);
+        self.canary_promotion_successes.store(new_config.canary_promotion_successes, Ordering::Relaxed);
         *config = new_config;
         tracing::debug!("Scheduling configuration updated: {:?}", *config);
     }
@@ -1140,6 +2105,11 @@ impl TokenManager {
     pub fn clear_all_sessions(&self) {
         self.session_accounts.clear();
     }
+
+    /// 当前会话绑定数
+    pub fn session_count(&self) -> usize {
+        self.session_accounts.len()
+    }
 }
 
 fn truncate_reason(reason: &str, max_len: usize) -> String {
@@ -1150,3 +2120,727 @@ fn truncate_reason(reason: &str, max_len: usize) -> String {
     s.push('…');
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_token(id: &str, priority: i32) -> ProxyToken {
+        ProxyToken {
+            account_id: id.to_string(),
+            access_token: format!("at-{}", id),
+            refresh_token: format!("rt-{}", id),
+            expires_in: 3600,
+            timestamp: chrono::Utc::now().timestamp() + 3600,
+            email: format!("{}@example.com", id),
+            account_path: PathBuf::from(format!("/nonexistent/{}.json", id)),
+            project_id: Some(format!("project-{}", id)),
+            subscription_tier: Some("FREE".to_string()),
+            priority,
+            upstream_proxy: None,
+            max_concurrency: None,
+            max_rpm: None,
+            daily_request_cap: None,
+            credential: Credential::RefreshToken,
+            canary: false,
+            supported_protocols: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_account_limit_override_exceeds_global_default() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        manager
+            .update_sticky_config(StickySessionConfig {
+                max_concurrency_per_account: 1,
+                ..Default::default()
+            })
+            .await;
+
+        let mut high = test_token("high", 0);
+        high.max_concurrency = Some(3);
+        let capped = test_token("capped", 0);
+
+        // 账号级上限高于全局默认值
+        let high_slots: Vec<_> = (0..3).map(|_| manager.try_acquire_slot(&high)).collect();
+        assert!(high_slots.iter().all(|s| s.is_some()));
+        assert!(manager.try_acquire_slot(&high).is_none());
+
+        // 其他账号仍受全局默认值限制
+        let capped_slot = manager.try_acquire_slot(&capped);
+        assert!(capped_slot.is_some());
+        assert!(manager.try_acquire_slot(&capped).is_none());
+        assert!(manager.is_token_saturated(&capped));
+
+        // 释放槽位后恢复可用
+        drop(capped_slot);
+        assert!(!manager.is_token_saturated(&capped));
+        assert!(manager.try_acquire_slot(&capped).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_account_rpm_limit() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        let mut token = test_token("rpm", 0);
+        token.max_rpm = Some(2);
+
+        // RPM 按请求计数，释放并发槽位不会归还额度
+        drop(manager.try_acquire_slot(&token));
+        drop(manager.try_acquire_slot(&token));
+        assert!(manager.try_acquire_slot(&token).is_none());
+    }
+
+    async fn rotation_sequence(seed: u64) -> Vec<String> {
+        let manager = TokenManager::with_scheduling_seed(std::env::temp_dir(), Some(seed));
+        for id in ["a", "b", "c", "d", "e"] {
+            let token = test_token(id, 0);
+            manager.tokens.insert(token.account_id.clone(), token);
+        }
+        manager.reset_rotation();
+
+        let mut emails = Vec::new();
+        for _ in 0..8 {
+            let (_, _, email) = manager.get_token("agent", true, None).await.unwrap();
+            emails.push(email);
+        }
+        emails
+    }
+
+    #[tokio::test]
+    async fn test_same_scheduling_seed_reproduces_selection() {
+        let first = rotation_sequence(42).await;
+        let second = rotation_sequence(42).await;
+        assert_eq!(first, second);
+
+        // 相同种子下随机下标序列一致
+        let a = TokenManager::with_scheduling_seed(std::env::temp_dir(), Some(7));
+        let b = TokenManager::with_scheduling_seed(std::env::temp_dir(), Some(7));
+        let seq_a: Vec<usize> = (0..16).map(|_| a.random_index(1000)).collect();
+        let seq_b: Vec<usize> = (0..16).map(|_| b.random_index(1000)).collect();
+        assert_eq!(seq_a, seq_b);
+        assert_eq!(a.random_index(0), 0);
+    }
+
+    #[tokio::test]
+    async fn test_scheduling_state_is_restored_on_new_manager() {
+        let data_dir = std::env::temp_dir().join(format!("ag-scheduling-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let with_tokens = || {
+            let manager = TokenManager::new(data_dir.clone());
+            for id in ["a", "b", "c"] {
+                let token = test_token(id, 0);
+                manager.tokens.insert(token.account_id.clone(), token);
+            }
+            manager
+        };
+
+        let first = with_tokens();
+        first.current_index.store(0, Ordering::SeqCst);
+        let (_, _, email) = first.get_token("agent", false, None).await.unwrap();
+        assert_eq!(email, "a@example.com");
+        first.save_scheduling_state().await.unwrap();
+
+        // 新实例 (模拟重启) 恢复游标、60s 锁定与最近使用时间
+        let second = with_tokens();
+        second.reset_rotation();
+        assert!(second.restore_scheduling_state().await);
+        assert_eq!(second.current_index.load(Ordering::SeqCst), 1);
+        let health = second.account_health();
+        assert!(health.iter().find(|h| h.account_id == "a").unwrap().last_used_at.is_some());
+        assert!(health.iter().find(|h| h.account_id == "b").unwrap().last_used_at.is_none());
+        let (_, _, email) = second.get_token("agent", false, None).await.unwrap();
+        assert_eq!(email, "a@example.com");
+        // 强制轮换时从恢复的游标继续，而不是回到第一个账号
+        let (_, _, email) = second.get_token("agent", true, None).await.unwrap();
+        assert_eq!(email, "b@example.com");
+
+        // 损坏的状态文件被删除，调度状态保持初始值
+        std::fs::write(data_dir.join(SCHEDULING_STATE_FILE), "{not json").unwrap();
+        let third = with_tokens();
+        assert!(!third.restore_scheduling_state().await);
+        assert!(!data_dir.join(SCHEDULING_STATE_FILE).exists());
+        assert!(third.last_used_account.lock().await.is_none());
+        assert_eq!(third.current_index.load(Ordering::SeqCst), 0);
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[tokio::test]
+    async fn test_protocol_limited_account_skipped_for_other_protocols() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        let mut anthropic_only = test_token("claude-only", 10);
+        anthropic_only.supported_protocols = vec![AccountProtocol::Anthropic];
+        for token in [anthropic_only, test_token("any", 0)] {
+            manager.tokens.insert(token.account_id.clone(), token);
+        }
+
+        // 优先级更高的账号仅支持 anthropic，OpenAI 路径的请求不会选中它
+        for _ in 0..10 {
+            let email = REQUEST_PROTOCOL
+                .scope(AccountProtocol::Openai, manager.get_token("agent", true, None))
+                .await
+                .unwrap()
+                .2;
+            assert_eq!(email, "any@example.com");
+        }
+        let email = REQUEST_PROTOCOL
+            .scope(AccountProtocol::Anthropic, manager.get_token("agent", true, None))
+            .await
+            .unwrap()
+            .2;
+        assert_eq!(email, "claude-only@example.com");
+
+        // 没有账号支持请求协议时明确报错
+        manager.tokens.remove("any");
+        let err = REQUEST_PROTOCOL
+            .scope(AccountProtocol::Openai, manager.get_token("agent", true, None))
+            .await
+            .unwrap_err();
+        assert!(err.contains("openai"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_request_served_before_earlier_queued_low_priority() {
+        use crate::proxy::common::priority_queue::RequestPriority;
+
+        let manager = Arc::new(TokenManager::new(std::env::temp_dir()));
+        let mut config = StickySessionConfig {
+            max_concurrency_per_account: 1,
+            ..Default::default()
+        };
+        config.priority_queue.enabled = true;
+        manager.update_sticky_config(config).await;
+        let token = test_token("only", 0);
+        manager.tokens.insert(token.account_id.clone(), token.clone());
+
+        // 唯一的槽位被占用
+        let busy = manager.try_acquire_slot(&token).unwrap();
+
+        let spawn_request = |priority: RequestPriority| {
+            let manager = manager.clone();
+            let holder: RequestSlotHolder = Arc::new(std::sync::Mutex::new(None));
+            let hint = RequestPriorityHint { header: Some(priority), api_key: None };
+            let task = tokio::spawn({
+                let holder = holder.clone();
+                async move {
+                    REQUEST_ACCOUNT_SLOT
+                        .scope(holder, REQUEST_PRIORITY.scope(hint, manager.get_token("agent", false, None)))
+                        .await
+                }
+            });
+            (task, holder)
+        };
+        let wait_queue_len = |len: usize| {
+            let manager = manager.clone();
+            async move {
+                for _ in 0..100 {
+                    if manager.slot_queue.len() == len {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                panic!("queue length never reached {}", len);
+            }
+        };
+
+        let (low, low_slot) = spawn_request(RequestPriority::Low);
+        wait_queue_len(1).await;
+        let (high, high_slot) = spawn_request(RequestPriority::High);
+        wait_queue_len(2).await;
+
+        // 槽位释放：后到的高优先级请求先被服务
+        drop(busy);
+        assert!(high.await.unwrap().is_ok());
+        assert!(!low.is_finished());
+        assert_eq!(manager.slot_queue.len(), 1);
+
+        // 高优先级请求结束后轮到低优先级请求
+        drop(high_slot.lock().unwrap().take());
+        assert!(low.await.unwrap().is_ok());
+        assert!(low_slot.lock().unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_released_slot_reaches_waiter_of_matching_protocol() {
+        use crate::proxy::common::priority_queue::RequestPriority;
+
+        let manager = Arc::new(TokenManager::new(std::env::temp_dir()));
+        let mut config = StickySessionConfig {
+            max_concurrency_per_account: 1,
+            ..Default::default()
+        };
+        config.priority_queue.enabled = true;
+        manager.update_sticky_config(config).await;
+        let mut anthropic_only = test_token("claude-only", 0);
+        anthropic_only.supported_protocols = vec![AccountProtocol::Anthropic];
+        let mut openai_only = test_token("openai-only", 0);
+        openai_only.supported_protocols = vec![AccountProtocol::Openai];
+        for token in [&anthropic_only, &openai_only] {
+            manager.tokens.insert(token.account_id.clone(), token.clone());
+        }
+        let _anthropic_busy = manager.try_acquire_slot(&anthropic_only).unwrap();
+        let openai_busy = manager.try_acquire_slot(&openai_only).unwrap();
+
+        let spawn_request = |protocol: AccountProtocol, priority: RequestPriority| {
+            let manager = manager.clone();
+            let holder: RequestSlotHolder = Arc::new(std::sync::Mutex::new(None));
+            let hint = RequestPriorityHint { header: Some(priority), api_key: None };
+            tokio::spawn(async move {
+                REQUEST_ACCOUNT_SLOT
+                    .scope(
+                        holder,
+                        REQUEST_PROTOCOL.scope(protocol, REQUEST_PRIORITY.scope(hint, manager.get_token("agent", false, None))),
+                    )
+                    .await
+            })
+        };
+        // 队首是用不上 openai 账号的 anthropic 请求
+        let anthropic = spawn_request(AccountProtocol::Anthropic, RequestPriority::High);
+        while manager.slot_queue.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let openai = spawn_request(AccountProtocol::Openai, RequestPriority::Normal);
+        while manager.slot_queue.len() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // openai 账号释放槽位：唤醒先到达队首的 anthropic 请求，再由它转交给 openai 请求
+        let released = Instant::now();
+        drop(openai_busy);
+        let email = tokio::time::timeout(Duration::from_secs(5), openai).await.unwrap().unwrap().unwrap().2;
+        assert_eq!(email, "openai-only@example.com");
+        assert!(released.elapsed() < SLOT_RECHECK_INTERVAL, "{:?}", released.elapsed());
+        assert!(!anthropic.is_finished());
+        anthropic.abort();
+    }
+
+    #[tokio::test]
+    async fn test_canary_account_gets_limited_traffic_until_promoted() {
+        let manager = TokenManager::with_scheduling_seed(std::env::temp_dir(), Some(11));
+        let mut canary = test_token("canary", 0);
+        canary.canary = true;
+        for token in [test_token("a", 0), test_token("b", 0), canary] {
+            manager.tokens.insert(token.account_id.clone(), token);
+        }
+        let canary_hits = |emails: &[String]| emails.iter().filter(|e| *e == "canary@example.com").count();
+        async fn pick(manager: &TokenManager, n: usize) -> Vec<String> {
+            let mut emails = Vec::new();
+            for _ in 0..n {
+                emails.push(manager.get_token("agent", true, None).await.unwrap().2);
+            }
+            emails
+        }
+
+        // 比例为 0 时灰度账号不参与调度
+        manager
+            .update_sticky_config(StickySessionConfig {
+                canary_traffic_percent: 0,
+                canary_promotion_successes: 3,
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(canary_hits(&pick(&manager, 30).await), 0);
+
+        // 10% 抽样：灰度账号承接的流量远低于均分 (约 1/3)
+        manager
+            .update_sticky_config(StickySessionConfig {
+                canary_traffic_percent: 10,
+                canary_promotion_successes: 3,
+                ..Default::default()
+            })
+            .await;
+        let hits = canary_hits(&pick(&manager, 300).await);
+        assert!(hits > 0 && hits < 40, "canary received {} of 300 requests", hits);
+
+        // 成功次数达标后自动转正，按正式账号参与轮询
+        manager
+            .update_sticky_config(StickySessionConfig {
+                canary_traffic_percent: 0,
+                canary_promotion_successes: 3,
+                ..Default::default()
+            })
+            .await;
+        manager.record_canary_success("canary@example.com");
+        manager.record_canary_success("canary");
+        assert!(manager.tokens.get("canary").unwrap().canary);
+        manager.record_canary_success("canary@example.com");
+        assert!(!manager.tokens.get("canary").unwrap().canary);
+        assert!(!manager.account_health().iter().any(|h| h.canary));
+        assert_eq!(canary_hits(&pick(&manager, 30).await), 10);
+    }
+
+    #[tokio::test]
+    async fn test_drain_stops_selection_and_waits_for_in_flight() {
+        let manager = Arc::new(TokenManager::new(std::env::temp_dir()));
+        for token in [test_token("a", 0), test_token("b", 0)] {
+            manager.tokens.insert(token.account_id.clone(), token);
+        }
+
+        // 模拟账号 a 上一个进行中的请求
+        let in_flight = manager.try_acquire_slot(&test_token("a", 0)).unwrap();
+        assert_eq!(manager.in_flight("a"), 1);
+
+        // 超时时返回仍在进行中的请求数，并恢复调度
+        assert_eq!(manager.drain_account("a", Duration::from_millis(100)).await, Err(1));
+        assert!(!manager.is_draining("a"));
+
+        let drain = {
+            let manager = manager.clone();
+            tokio::spawn(async move { manager.drain_account("a", Duration::from_secs(5)).await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(manager.is_draining("a"));
+        assert!(!drain.is_finished());
+
+        // 排空中的账号不再被选中
+        for force_rotate in [false, true, true, true] {
+            let (_, _, email) = manager.get_token("agent", force_rotate, None).await.unwrap();
+            assert_eq!(email, "b@example.com");
+        }
+
+        // 进行中的请求结束后排空完成
+        drop(in_flight);
+        assert_eq!(drain.await.unwrap(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_upstream_proxy_looked_up_by_email_after_reload() {
+        use crate::proxy::tests::support;
+
+        let data_dir = support::temp_data_dir("ag-account-proxy");
+        support::write_account(&data_dir, "a", serde_json::json!({ "upstream_proxy": "http://127.0.0.1:8080" }));
+        support::write_account(&data_dir, "b", serde_json::json!({}));
+        let manager = TokenManager::new(data_dir.clone());
+        manager.load_accounts().await.unwrap();
+
+        assert_eq!(manager.upstream_proxy_for("a@example.com").as_deref(), Some("http://127.0.0.1:8080"));
+        assert_eq!(manager.upstream_proxy_for("b@example.com"), None);
+        assert_eq!(manager.upstream_proxy_for("missing@example.com"), None);
+
+        // 单账号重新加载后使用新的代理设置
+        support::write_account(&data_dir, "a", serde_json::json!({ "upstream_proxy": "socks5://127.0.0.1:1080" }));
+        manager.reload_account("a").await.unwrap();
+        assert_eq!(manager.upstream_proxy_for("a@example.com").as_deref(), Some("socks5://127.0.0.1:1080"));
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[tokio::test]
+    async fn test_slot_not_granted_from_snapshot_taken_before_drain() {
+        let manager = Arc::new(TokenManager::new(std::env::temp_dir()));
+        let token = test_token("a", 0);
+        manager.tokens.insert(token.account_id.clone(), token.clone());
+        let _in_flight = manager.try_acquire_slot(&token).unwrap();
+
+        let drain = {
+            let manager = manager.clone();
+            tokio::spawn(async move { manager.drain_account("a", Duration::from_secs(5)).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // 选号快照早于排空开始时，占用槽位的一步仍会拒绝该账号
+        assert!(manager.try_acquire_slot(&token).is_none());
+        assert_eq!(manager.in_flight("a"), 1);
+        drop(_in_flight);
+        assert_eq!(drain.await.unwrap(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_undrain_cancels_drain_and_restores_selection() {
+        let manager = Arc::new(TokenManager::new(std::env::temp_dir()));
+        let token = test_token("a", 0);
+        manager.tokens.insert(token.account_id.clone(), token);
+        let _in_flight = manager.try_acquire_slot(&test_token("a", 0)).unwrap();
+
+        let drain = {
+            let manager = manager.clone();
+            tokio::spawn(async move { manager.drain_account("a", Duration::from_secs(5)).await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(manager.get_token("agent", false, None).await.is_err());
+
+        assert!(manager.undrain_account("a"));
+        assert!(!manager.undrain_account("a"));
+        assert_eq!(drain.await.unwrap(), Err(1));
+        let (_, _, email) = manager.get_token("agent", false, None).await.unwrap();
+        assert_eq!(email, "a@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_daily_capped_account_is_skipped() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        let mut capped = test_token("a", 0);
+        capped.daily_request_cap = Some(2);
+        for token in [capped.clone(), test_token("b", 0)] {
+            manager.tokens.insert(token.account_id.clone(), token);
+        }
+
+        let now = chrono::Local::now().naive_local();
+        assert!(manager.try_record_daily_request_at("a", 0, now));
+        assert_eq!(manager.account_health()[0].remaining_today, Some(1));
+        assert!(manager.try_record_daily_request_at("a", 0, now));
+        assert!(manager.try_acquire_slot(&capped).is_none());
+
+        for force_rotate in [false, true, true] {
+            let (_, _, email) = manager.get_token("agent", force_rotate, None).await.unwrap();
+            assert_eq!(email, "b@example.com");
+        }
+        let health = manager.account_health();
+        assert_eq!(health[0].requests_today, 2);
+        assert_eq!(health[0].remaining_today, Some(0));
+        assert_eq!(health[1].remaining_today, None);
+    }
+
+    #[tokio::test]
+    async fn test_daily_cap_resets_at_configured_time() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        manager
+            .update_sticky_config(StickySessionConfig {
+                daily_request_cap_per_account: 1,
+                daily_reset_time: "04:00".to_string(),
+                ..Default::default()
+            })
+            .await;
+        let token = test_token("a", 0);
+        let at = |d: u32, h: u32, m: u32| {
+            chrono::NaiveDate::from_ymd_opt(2026, 3, d).unwrap().and_hms_opt(h, m, 0).unwrap()
+        };
+
+        assert!(manager.try_record_daily_request_at("a", 0, at(1, 10, 0)));
+        assert!(manager.is_daily_capped_at(&token, at(1, 23, 0)));
+        // 重置时刻之前仍属于前一天
+        assert!(manager.is_daily_capped_at(&token, at(2, 3, 59)));
+        assert!(!manager.is_daily_capped_at(&token, at(2, 4, 0)));
+    }
+
+    #[test]
+    fn test_daily_cap_holds_under_concurrent_acquires() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        let mut token = test_token("a", 0);
+        token.daily_request_cap = Some(5);
+
+        let acquired = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..32 {
+                scope.spawn(|| {
+                    if manager.try_acquire_slot(&token).is_some() {
+                        acquired.fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert_eq!(acquired.load(Ordering::SeqCst), 5);
+        assert_eq!(manager.requests_today_at("a", TokenManager::local_now()), 5);
+    }
+
+    #[tokio::test]
+    async fn test_higher_priority_account_preferred_until_cooldown() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        for token in [test_token("free-a", 0), test_token("paid", 10), test_token("free-b", 0)] {
+            manager.tokens.insert(token.account_id.clone(), token);
+        }
+
+        // 多次轮换仍然命中高优先级账号
+        for force_rotate in [false, true, true] {
+            let (_, _, email) = manager.get_token("agent", force_rotate, None).await.unwrap();
+            assert_eq!(email, "paid@example.com");
+        }
+
+        // 高优先级账号进入冷却后，才降级到低优先级账号
+        manager.mark_rate_limited("paid@example.com", 429, Some("30"), "");
+        let (_, _, email) = manager.get_token("agent", false, None).await.unwrap();
+        assert!(email.starts_with("free-"));
+
+        let (_, _, next) = manager.get_token("agent", true, None).await.unwrap();
+        assert!(next.starts_with("free-"));
+
+        // 冷却解除后重新回到高优先级账号
+        manager.clear_rate_limit("paid@example.com");
+        let (_, _, email) = manager.get_token("agent", true, None).await.unwrap();
+        assert_eq!(email, "paid@example.com");
+    }
+
+    #[test]
+    fn test_rate_limited_accounts_cannot_serve_models() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        let mut paid = test_token("paid", 10);
+        paid.subscription_tier = Some("PRO".to_string());
+        let mut free = test_token("free", 0);
+        free.subscription_tier = Some("FREE".to_string());
+        for token in [paid, free] {
+            manager.tokens.insert(token.account_id.clone(), token);
+        }
+        assert!(manager.can_serve_model(Some("PRO"), AccountProtocol::Openai));
+
+        // 唯一的高等级账号被锁定后，高级模型不再视为可服务
+        manager.mark_rate_limited("paid@example.com", 429, Some("30"), "");
+        assert!(!manager.can_serve_model(Some("PRO"), AccountProtocol::Openai));
+        assert!(manager.can_serve_model(None, AccountProtocol::Openai));
+
+        manager.mark_rate_limited("free@example.com", 503, Some("30"), "");
+        assert!(!manager.can_serve_model(None, AccountProtocol::Openai));
+    }
+
+    #[tokio::test]
+    async fn test_cooldown_transition_delivers_webhook_event() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<serde_json::Value>();
+        let app = axum::Router::new().route(
+            "/hook",
+            axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send(body);
+                    "ok"
+                }
+            }),
+        );
+        let addr = crate::proxy::tests::support::spawn_router(app).await;
+
+        let manager = TokenManager::new(std::env::temp_dir());
+        for token in [test_token("a", 0), test_token("b", 0)] {
+            manager.tokens.insert(token.account_id.clone(), token);
+        }
+        manager.configure_webhook(
+            Some(format!("http://{}/hook", addr)),
+            &crate::proxy::config::ReadinessThresholdConfig { min_healthy_accounts: 2, min_healthy_fraction: None },
+        );
+
+        manager.mark_rate_limited("a", 429, Some("30"), "");
+        let mut events = Vec::new();
+        for _ in 0..2 {
+            events.push(tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap());
+        }
+        events.sort_by_key(|e| e["event"].as_str().unwrap_or_default().to_string());
+
+        let cooldown = &events[0];
+        assert_eq!(cooldown["event"], "account_cooldown");
+        assert_eq!(cooldown["account"], "a");
+        assert_eq!(cooldown["status"], 429);
+        assert!(cooldown["retry_after_secs"].as_u64().unwrap() <= 30);
+        assert!(cooldown["timestamp"].is_string());
+
+        // 可用账号数低于下限 (2) 时同时发送账号池事件
+        let pool = &events[1];
+        assert_eq!(pool["event"], "pool_unhealthy");
+        assert_eq!(pool["available"], 1);
+        assert_eq!(pool["required"], 2);
+
+        // 已在冷却中的账号再次限流不重复通知
+        manager.mark_rate_limited("a", 429, Some("30"), "");
+        assert!(tokio::time::timeout(Duration::from_millis(200), rx.recv()).await.is_err());
+    }
+
+    async fn outgoing_auth_header(token: ProxyToken) -> (String, String) {
+        let manager = TokenManager::new(std::env::temp_dir());
+        manager.tokens.insert(token.account_id.clone(), token);
+        let (auth, _, _) = manager.get_token("agent", false, None).await.unwrap();
+        let (name, value) = auth.header().unwrap();
+        (name.as_str().to_string(), value.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_credential_uses_bearer_access_token() {
+        let (name, value) = outgoing_auth_header(test_token("oauth", 0)).await;
+        assert_eq!(name, "authorization");
+        assert_eq!(value, "Bearer at-oauth");
+    }
+
+    #[tokio::test]
+    async fn test_api_key_credential_uses_goog_api_key_header() {
+        let mut token = test_token("keyed", 0);
+        token.access_token = String::new();
+        // 即使 timestamp 已过期也不会尝试刷新
+        token.timestamp = 0;
+        token.credential = Credential::ApiKey { api_key: "AIza-test".to_string(), project_id: None };
+
+        let (name, value) = outgoing_auth_header(token).await;
+        assert_eq!(name, "x-goog-api-key");
+        assert_eq!(value, "AIza-test");
+    }
+
+    #[tokio::test]
+    async fn test_service_account_credential_fetches_metadata_token() {
+        let app = axum::Router::new().route(
+            "/computeMetadata/v1/instance/service-accounts/:account/token",
+            axum::routing::get(
+                |axum::extract::Path(account): axum::extract::Path<String>, headers: axum::http::HeaderMap| async move {
+                    assert_eq!(headers["metadata-flavor"], "Google");
+                    axum::Json(serde_json::json!({
+                        "access_token": format!("sa-token-{}", account),
+                        "expires_in": 3599,
+                        "token_type": "Bearer"
+                    }))
+                },
+            ),
+        );
+        let addr = crate::proxy::tests::support::spawn_router(app).await;
+
+        let mut token = test_token("sa", 0);
+        token.access_token = String::new();
+        token.timestamp = 0;
+        token.credential = Credential::ServiceAccount {
+            service_account: "default".to_string(),
+            metadata_url: Some(format!("http://{}", addr)),
+            project_id: None,
+        };
+
+        let (name, value) = outgoing_auth_header(token).await;
+        assert_eq!(name, "authorization");
+        assert_eq!(value, "Bearer sa-token-default");
+    }
+
+    #[test]
+    fn test_credential_defaults_to_refresh_token() {
+        let credential: Credential = serde_json::from_value(serde_json::json!({"type": "api_key", "api_key": "k"})).unwrap();
+        assert_eq!(credential, Credential::ApiKey { api_key: "k".to_string(), project_id: None });
+
+        let account: crate::models::Account = serde_json::from_value(serde_json::json!({
+            "id": "a",
+            "email": "a@example.com",
+            "name": null,
+            "token": {
+                "access_token": "at",
+                "refresh_token": "rt",
+                "expires_in": 3600,
+                "expiry_timestamp": 0,
+                "token_type": "Bearer",
+                "email": null
+            },
+            "quota": null,
+            "created_at": 0,
+            "last_used": 0
+        }))
+        .unwrap();
+        assert!(account.credential.is_refresh_token());
+        // 默认凭据不写入账号文件
+        assert!(serde_json::to_value(&account).unwrap().get("credential").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_api_key_account_without_token_lists_and_loads() {
+        let data_dir = crate::proxy::tests::support::temp_data_dir("ag-api-key");
+        let account = serde_json::json!({
+            "id": "keyed",
+            "email": "keyed@example.com",
+            "name": null,
+            "credential": { "type": "api_key", "api_key": "AIza-test", "project_id": "project-keyed" },
+            "quota": null,
+            "created_at": 0,
+            "last_used": 0
+        });
+        let path = data_dir.join("accounts").join("keyed.json");
+        std::fs::write(&path, account.to_string()).unwrap();
+
+        // 账号管理侧可以反序列化 (无 token 字段)
+        let parsed: crate::models::Account = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(parsed.token.is_none());
+        assert!(parsed.oauth_token().is_err());
+
+        // 反代账号池按凭据中的项目出站，使用 x-goog-api-key
+        let manager = TokenManager::new(data_dir);
+        assert_eq!(manager.load_accounts().await.unwrap(), 1);
+        let (auth, project_id, _) = manager.get_token("agent", false, None).await.unwrap();
+        assert_eq!(auth, UpstreamAuth::ApiKey("AIza-test".to_string()));
+        assert_eq!(project_id, "project-keyed");
+    }
+}
@@ -0,0 +1,209 @@
+// 上游错误响应规范化
+// 网关等中间层可能返回 HTML 错误页，直接透传会让客户端以 JSON 解析失败
+
+use axum::{
+    body::Body,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+
+/// 错误片段最大保留字符数
+const MAX_SNIPPET_CHARS: usize = 512;
+
+/// 客户端所使用的协议 (决定错误体格式)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorProtocol {
+    OpenAI,
+    Anthropic,
+    Gemini,
+}
+
+/// 判断 Content-Type 是否为 JSON (含 `application/problem+json` 等变体)
+pub fn is_json_content_type(content_type: Option<&str>) -> bool {
+    match content_type {
+        Some(ct) => {
+            let mime = ct.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+            mime == "application/json" || mime.ends_with("+json")
+        }
+        None => false,
+    }
+}
+
+/// 按字符截断错误文本，避免 UTF-8 边界 panic
+pub fn truncate_snippet(text: &str) -> String {
+    let trimmed = text.trim();
+    if trimmed.chars().count() > MAX_SNIPPET_CHARS {
+        let preview: String = trimmed.chars().take(MAX_SNIPPET_CHARS).collect();
+        format!("{}...", preview)
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// 构建协议对应的错误体
+pub fn build_error_body(protocol: ErrorProtocol, status: u16, message: &str) -> Value {
+    match protocol {
+        ErrorProtocol::OpenAI => json!({
+            "error": {
+                "message": message,
+                "type": "upstream_error",
+                "code": status
+            }
+        }),
+        ErrorProtocol::Anthropic => json!({
+            "type": "error",
+            "error": {
+                "type": "api_error",
+                "message": message
+            }
+        }),
+        ErrorProtocol::Gemini => {
+            let reason = StatusCode::from_u16(status)
+                .ok()
+                .and_then(|s| s.canonical_reason())
+                .unwrap_or("UNKNOWN")
+                .to_ascii_uppercase()
+                .replace(' ', "_");
+            json!({
+                "error": {
+                    "code": status,
+                    "message": message,
+                    "status": reason
+                }
+            })
+        }
+    }
+}
+
+/// 根据上游 Content-Type 生成返回给客户端的错误响应
+/// - JSON: 原样透传
+/// - 非 JSON (HTML 错误页等): 包装为协议对应的 JSON 错误体，保留原始状态码
+pub fn upstream_error_response(
+    protocol: ErrorProtocol,
+    status: StatusCode,
+    content_type: Option<&str>,
+    error_text: String,
+) -> Response {
+    let looks_like_json = content_type.is_none()
+        && serde_json::from_str::<Value>(&error_text).is_ok();
+
+    if is_json_content_type(content_type) || looks_like_json {
+        return Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(error_text))
+            .unwrap_or_else(|_| status.into_response());
+    }
+
+    let snippet = truncate_snippet(&error_text);
+    let content_type = content_type.unwrap_or("unknown");
+    tracing::warn!(
+        "[Upstream] Non-JSON error response (status={}, content-type={}): {}",
+        status.as_u16(),
+        content_type,
+        snippet
+    );
+
+    let message = format!(
+        "Upstream returned a non-JSON error (HTTP {}, content-type: {}): {}",
+        status.as_u16(),
+        content_type,
+        snippet
+    );
+    (status, axum::Json(build_error_body(protocol, status.as_u16(), &message))).into_response()
+}
+
+/// 读取上游错误响应并按 Content-Type 规范化
+pub async fn read_upstream_error(protocol: ErrorProtocol, resp: reqwest::Response) -> Response {
+    let status = resp.status();
+    let content_type = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+    let error_text = resp
+        .text()
+        .await
+        .unwrap_or_else(|_| format!("HTTP {}", status.as_u16()));
+
+    upstream_error_response(protocol, status, content_type.as_deref(), error_text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_json_content_type() {
+        assert!(is_json_content_type(Some("application/json")));
+        assert!(is_json_content_type(Some("application/json; charset=UTF-8")));
+        assert!(is_json_content_type(Some("application/problem+json")));
+        assert!(!is_json_content_type(Some("text/html")));
+        assert!(!is_json_content_type(None));
+    }
+
+    #[test]
+    fn test_truncate_snippet_respects_char_boundary() {
+        let long = "错".repeat(MAX_SNIPPET_CHARS + 10);
+        let snippet = truncate_snippet(&long);
+        assert!(snippet.ends_with("..."));
+        assert_eq!(snippet.chars().count(), MAX_SNIPPET_CHARS + 3);
+    }
+
+    #[tokio::test]
+    async fn test_html_502_upstream_becomes_json_error() {
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::post(|| async {
+                (
+                    StatusCode::BAD_GATEWAY,
+                    [(header::CONTENT_TYPE, "text/html")],
+                    "<html><body><h1>502 Bad Gateway</h1></body></html>",
+                )
+            }),
+        );
+        let addr = crate::proxy::tests::support::spawn_router(app).await;
+
+        let resp = reqwest::Client::builder()
+            .no_proxy()
+            .build()
+            .unwrap()
+            .post(format!("http://{}/", addr))
+            .send()
+            .await
+            .unwrap();
+
+        let out = read_upstream_error(ErrorProtocol::Anthropic, resp).await;
+        assert_eq!(out.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            out.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+
+        let bytes = axum::body::to_bytes(out.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "api_error");
+        let message = body["error"]["message"].as_str().unwrap();
+        assert!(message.contains("HTTP 502"));
+        assert!(message.contains("text/html"));
+        assert!(message.contains("502 Bad Gateway"));
+    }
+
+    #[test]
+    fn test_json_error_passes_through() {
+        let raw = r#"{"error":{"code":400,"message":"bad"}}"#.to_string();
+        let out = upstream_error_response(
+            ErrorProtocol::Gemini,
+            StatusCode::BAD_REQUEST,
+            Some("application/json"),
+            raw,
+        );
+        assert_eq!(out.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            out.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+    }
+}
@@ -3,4 +3,5 @@
 
 pub mod client;
 pub mod retry;
+pub mod errors;
 pub mod models;
@@ -66,9 +66,94 @@ pub fn parse_retry_delay(error_text: &str) -> Option<u64> {
     None
 }
 
+/// 账号无权访问所请求模型 (上游 404) 时的执行决策
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelUnavailableDecision {
+    /// 换号重试同一模型
+    RotateAccount,
+    /// 切换到下一个候选模型
+    NextCandidate,
+    /// 返回 404
+    Fail,
+}
+
+/// 根据配置与剩余重试次数决定 404 (模型不可用) 的处理方式
+pub fn model_unavailable_decision(
+    action: crate::proxy::config::ModelUnavailableAction,
+    attempt: usize,
+    max_attempts: usize,
+    is_last_model: bool,
+) -> ModelUnavailableDecision {
+    use crate::proxy::config::ModelUnavailableAction;
+
+    let advance = if is_last_model {
+        ModelUnavailableDecision::Fail
+    } else {
+        ModelUnavailableDecision::NextCandidate
+    };
+    match action {
+        ModelUnavailableAction::Fail => ModelUnavailableDecision::Fail,
+        ModelUnavailableAction::RotateAccount if attempt + 1 < max_attempts => {
+            ModelUnavailableDecision::RotateAccount
+        }
+        ModelUnavailableAction::RotateAccount | ModelUnavailableAction::NextCandidate => advance,
+    }
+}
+
+/// 思维链变体不可用时的同账号基础模型重试
+/// 变体 (如 `-thinking`) 在某账号 404 时记录该账号，下一次尝试改用基础模型且不换号；
+/// 每个候选只回退一次，并为此额外保留一次尝试机会
+#[derive(Debug)]
+pub struct VariantFallback<A> {
+    base_model: Option<String>,
+    pinned: Option<A>,
+    used: bool,
+}
+
+impl<A> VariantFallback<A> {
+    pub fn new(enabled: bool, model: &str) -> Self {
+        Self {
+            base_model: if enabled {
+                crate::proxy::common::model_mapping::variant_base_model(model)
+            } else {
+                None
+            },
+            pinned: None,
+            used: false,
+        }
+    }
+
+    /// 为同账号重试额外保留的尝试次数
+    pub fn extra_attempts(&self) -> usize {
+        usize::from(self.base_model.is_some())
+    }
+
+    /// 是否有待执行的同账号重试
+    pub fn is_pending(&self) -> bool {
+        self.pinned.is_some()
+    }
+
+    /// 变体模型 404 时调用：尚未回退过则记录账号并返回 true
+    pub fn on_unavailable(&mut self, account: A) -> bool {
+        if self.used || self.base_model.is_none() {
+            return false;
+        }
+        self.used = true;
+        self.pinned = Some(account);
+        true
+    }
+
+    /// 取出待重试的账号及要改用的基础模型
+    pub fn take_pinned(&mut self) -> Option<(A, String)> {
+        let account = self.pinned.take()?;
+        Some((account, self.base_model.clone()?))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::proxy::config::ModelUnavailableAction;
 
     #[test]
     fn test_parse_duration_ms() {
@@ -91,4 +176,20 @@ mod tests {
 
         assert_eq!(parse_retry_delay(error_json), Some(1204));
     }
+
+    #[test]
+    fn test_model_unavailable_decision() {
+        use ModelUnavailableDecision::*;
+        let rotate = ModelUnavailableAction::RotateAccount;
+        assert_eq!(model_unavailable_decision(rotate, 0, 3, true), RotateAccount);
+        // 账号耗尽后再切换候选
+        assert_eq!(model_unavailable_decision(rotate, 2, 3, false), NextCandidate);
+        assert_eq!(model_unavailable_decision(rotate, 2, 3, true), Fail);
+
+        let next = ModelUnavailableAction::NextCandidate;
+        assert_eq!(model_unavailable_decision(next, 0, 3, false), NextCandidate);
+        assert_eq!(model_unavailable_decision(next, 0, 3, true), Fail);
+
+        assert_eq!(model_unavailable_decision(ModelUnavailableAction::Fail, 0, 3, false), Fail);
+    }
 }
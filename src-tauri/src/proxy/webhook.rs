@@ -0,0 +1,236 @@
+// 账号健康 Webhook
+// 账号进入冷却、被禁用，以及账号池健康状态 (可用账号数相对 readiness_threshold) 变化时，
+// 向 `webhook_url` POST JSON 事件；投递在后台任务中进行，不阻塞请求处理，
+// 失败按指数退避重试，最终仍失败的事件追加到死信文件 `<data_dir>/webhook_dead_letter.jsonl`
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::proxy::config::ReadinessThresholdConfig;
+
+const DEAD_LETTER_FILE: &str = "webhook_dead_letter.jsonl";
+
+/// 单个事件最多投递次数 (含首次)
+pub const DEFAULT_MAX_DELIVERY_ATTEMPTS: u32 = 4;
+/// 首次重试的等待时间，之后每次翻倍
+const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+/// 单次投递超时
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+const POOL_UNKNOWN: u8 = 0;
+const POOL_HEALTHY: u8 = 1;
+const POOL_UNHEALTHY: u8 = 2;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// 账号被限流，进入冷却
+    AccountCooldown {
+        account: String,
+        status: u16,
+        retry_after_secs: Option<u64>,
+    },
+    /// 账号被禁用 (如 invalid_grant)
+    AccountDisabled { account: String, reason: String },
+    /// 可用账号数低于健康下限
+    PoolUnhealthy { available: usize, total: usize, required: usize },
+    /// 可用账号数恢复到健康下限以上
+    PoolRecovered { available: usize, total: usize, required: usize },
+}
+
+#[derive(Debug, Serialize)]
+struct Envelope<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+}
+
+pub struct WebhookNotifier {
+    url: RwLock<Option<String>>,
+    threshold: RwLock<ReadinessThresholdConfig>,
+    pool_state: AtomicU8,
+    dead_letter_path: PathBuf,
+    max_attempts: u32,
+    retry_base_delay: Duration,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(data_dir: &Path) -> Self {
+        Self::with_retry(data_dir, DEFAULT_MAX_DELIVERY_ATTEMPTS, DEFAULT_RETRY_BASE_DELAY)
+    }
+
+    pub fn with_retry(data_dir: &Path, max_attempts: u32, retry_base_delay: Duration) -> Self {
+        Self {
+            url: RwLock::new(None),
+            threshold: RwLock::new(ReadinessThresholdConfig::default()),
+            pool_state: AtomicU8::new(POOL_UNKNOWN),
+            dead_letter_path: data_dir.join(DEAD_LETTER_FILE),
+            max_attempts: max_attempts.max(1),
+            retry_base_delay,
+            // Webhook 一般指向内网服务，直连不经过系统代理
+            client: reqwest::Client::builder()
+                .no_proxy()
+                .timeout(DELIVERY_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// 更新投递地址与账号池健康下限 (空地址表示关闭)
+    pub fn configure(&self, url: Option<String>, threshold: &ReadinessThresholdConfig) {
+        if let Ok(mut current) = self.url.write() {
+            *current = url.filter(|u| !u.trim().is_empty());
+        }
+        if let Ok(mut current) = self.threshold.write() {
+            *current = threshold.clone();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.url.read().map(|u| u.is_some()).unwrap_or(false)
+    }
+
+    pub fn dead_letter_path(&self) -> &Path {
+        &self.dead_letter_path
+    }
+
+    /// 在后台投递事件；未配置地址或不在 tokio 运行时中时为空操作
+    pub fn notify(&self, event: WebhookEvent) {
+        let Some(url) = self.url.read().ok().and_then(|u| u.clone()) else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let client = self.client.clone();
+        let dead_letter_path = self.dead_letter_path.clone();
+        let max_attempts = self.max_attempts;
+        let base_delay = self.retry_base_delay;
+        runtime.spawn(async move {
+            deliver(&client, &url, &event, max_attempts, base_delay, &dead_letter_path).await;
+        });
+    }
+
+    /// 根据当前可用账号数检测账号池健康状态变化，只在跨越健康下限时发送事件
+    pub fn observe_pool(&self, available: usize, total: usize) {
+        if !self.is_enabled() {
+            return;
+        }
+        let required = self
+            .threshold
+            .read()
+            .map(|t| t.required_healthy(total))
+            .unwrap_or(1);
+        let healthy = available >= required;
+        let next = if healthy { POOL_HEALTHY } else { POOL_UNHEALTHY };
+        let previous = self.pool_state.swap(next, Ordering::SeqCst);
+        if previous == next {
+            return;
+        }
+        // 启动后首次观测为健康时不通知
+        if previous == POOL_UNKNOWN && healthy {
+            return;
+        }
+        self.notify(if healthy {
+            WebhookEvent::PoolRecovered { available, total, required }
+        } else {
+            WebhookEvent::PoolUnhealthy { available, total, required }
+        });
+    }
+}
+
+async fn deliver(
+    client: &reqwest::Client,
+    url: &str,
+    event: &WebhookEvent,
+    max_attempts: u32,
+    base_delay: Duration,
+    dead_letter_path: &Path,
+) {
+    let envelope = Envelope {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        event,
+    };
+    let mut last_error = String::new();
+    for attempt in 0..max_attempts {
+        if attempt > 0 {
+            tokio::time::sleep(base_delay * 2u32.saturating_pow(attempt - 1)).await;
+        }
+        match client.post(url).json(&envelope).send().await {
+            Ok(resp) if resp.status().is_success() => return,
+            Ok(resp) => last_error = format!("HTTP {}", resp.status()),
+            Err(e) => last_error = e.to_string(),
+        }
+        tracing::debug!("[Webhook] Delivery attempt {}/{} failed: {}", attempt + 1, max_attempts, last_error);
+    }
+
+    tracing::warn!("[Webhook] Giving up after {} attempts ({}), writing to dead-letter log", max_attempts, last_error);
+    let record = serde_json::json!({
+        "failed_at": chrono::Utc::now().to_rfc3339(),
+        "error": last_error,
+        "payload": envelope,
+    });
+    let written = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dead_letter_path)
+        .and_then(|mut file| writeln!(file, "{}", record));
+    if let Err(e) = written {
+        tracing::error!("[Webhook] Failed to write dead-letter log {:?}: {}", dead_letter_path, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_undeliverable_event_goes_to_dead_letter_log() {
+        let dir = std::env::temp_dir().join(format!("ag-webhook-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // 无人监听的端口，保证连接被拒绝
+        let url = format!("http://{}/hook", crate::proxy::tests::support::closed_local_addr());
+
+        let notifier = WebhookNotifier::with_retry(&dir, 2, Duration::from_millis(10));
+        notifier.configure(Some(url), &ReadinessThresholdConfig::default());
+        notifier.notify(WebhookEvent::AccountDisabled {
+            account: "a@example.com".to_string(),
+            reason: "invalid_grant".to_string(),
+        });
+
+        let mut content = String::new();
+        for _ in 0..100 {
+            content = std::fs::read_to_string(notifier.dead_letter_path()).unwrap_or_default();
+            if !content.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let record: serde_json::Value = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(record["payload"]["event"], "account_disabled");
+        assert_eq!(record["payload"]["account"], "a@example.com");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_pool_events_only_on_transition() {
+        let dir = std::env::temp_dir();
+        let notifier = WebhookNotifier::new(&dir);
+        // 未配置地址时不跟踪
+        notifier.observe_pool(0, 2);
+        assert_eq!(notifier.pool_state.load(Ordering::SeqCst), POOL_UNKNOWN);
+
+        // 无运行时，notify 为空操作，只验证状态机
+        notifier.configure(Some("http://127.0.0.1:9/hook".to_string()), &ReadinessThresholdConfig::default());
+        notifier.observe_pool(2, 2);
+        assert_eq!(notifier.pool_state.load(Ordering::SeqCst), POOL_HEALTHY);
+        notifier.observe_pool(0, 2);
+        assert_eq!(notifier.pool_state.load(Ordering::SeqCst), POOL_UNHEALTHY);
+    }
+}
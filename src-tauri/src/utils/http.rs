@@ -24,10 +24,10 @@ pub fn create_client_with_proxy(
             match Proxy::all(&config.url) {
                 Ok(proxy) => {
                     builder = builder.proxy(proxy);
-                    tracing::info!("HTTP 客户端已启用上游代理: {}", config.url);
+                    tracing::info!("HTTP 客户端已启用上游代理: {}", crate::proxy::upstream::client::redact_proxy_url(&config.url));
                 }
                 Err(e) => {
-                    tracing::error!("无效的代理地址: {}, 错误: {}", config.url, e);
+                    tracing::error!("无效的代理地址: {}, 错误: {}", crate::proxy::upstream::client::redact_proxy_url(&config.url), e);
                 }
             }
         }
@@ -21,13 +21,20 @@ interface ProxyRequestLog {
     response_body?: string;
     input_tokens?: number;
     output_tokens?: number;
+    estimated_cost?: number;
     account_email?: string;
+    experiment_arm?: string;
 }
 
 interface ProxyStats {
     total_requests: number;
     success_count: number;
     error_count: number;
+    default_fallback_count?: number;
+    deprecated_route_count?: number;
+    total_cost?: number;
+    cost_by_model?: Record<string, number>;
+    monitoring_enabled?: boolean;
 }
 
 interface ProxyMonitorProps {
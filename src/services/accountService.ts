@@ -111,6 +111,21 @@ export async function toggleProxyStatus(accountId: string, enable: boolean, reas
     return await invoke('toggle_proxy_status', { accountId, enable, reason });
 }
 
+export interface DrainResult {
+    account_id: string;
+    drained: boolean;
+    in_flight: number;
+}
+
+/** 排空账号 (删除前调用)：停止分配新请求并等待进行中的请求结束，超时后恢复调度 */
+export async function drainProxyAccount(accountId: string, timeoutSecs?: number): Promise<DrainResult> {
+    return await invoke('drain_proxy_account', { accountId, timeoutSecs });
+}
+
+export async function undrainProxyAccount(accountId: string): Promise<boolean> {
+    return await invoke('undrain_proxy_account', { accountId });
+}
+
 /**
  * 重新排序账号列表
  * @param accountIds 按新顺序排列的账号ID数组
@@ -1,8 +1,14 @@
+export type Credential =
+    | { type: 'refresh_token' }
+    | { type: 'api_key'; api_key: string; project_id?: string }
+    | { type: 'service_account'; service_account: string; metadata_url?: string; project_id?: string };
+
 export interface Account {
     id: string;
     email: string;
     name?: string;
-    token: TokenData;
+    token?: TokenData; // 仅 refresh_token 账号持有
+    credential?: Credential;
     device_profile?: DeviceProfile;
     device_history?: DeviceProfileVersion[];
     quota?: QuotaData;
@@ -12,10 +18,19 @@ export interface Account {
     proxy_disabled?: boolean;
     proxy_disabled_reason?: string;
     proxy_disabled_at?: number;
+    priority?: number;
+    upstream_proxy?: string;
+    max_concurrency?: number;
+    max_rpm?: number;
+    daily_request_cap?: number;
+    canary?: boolean; // 灰度账号，仅承接少量流量，成功若干次后自动转正
+    supported_protocols?: AccountProtocol[]; // 可服务的客户端协议，缺省为全部
     created_at: number;
     last_used: number;
 }
 
+export type AccountProtocol = 'openai' | 'anthropic' | 'gemini';
+
 export interface TokenData {
     access_token: string;
     refresh_token: string;
@@ -12,22 +12,193 @@ export interface ProxyConfig {
     auto_start: boolean;
     anthropic_mapping?: Record<string, string>;
     openai_mapping?: Record<string, string>;
+    openai_family_rules?: OpenAIFamilyRule[];
     custom_mapping?: Record<string, string>;
     request_timeout: number;
+    connect_timeout?: number;
+    idle_read_timeout?: number;
+    allow_header_overrides?: boolean;
+    max_request_timeout_secs?: number;
     model_strategies?: Record<string, ModelStrategy>;
+    default_fallback_policy?: ModelFallbackPolicy;
+    strategies_dir?: string;
+    strategy_experiments?: Record<string, StrategyExperiment>;
+    model_output_limits?: Record<string, ModelOutputLimit>;
+    reasoning_effort_budgets?: Record<string, ReasoningEffortBudgets>;
+    model_transforms?: Record<string, ModelTransform>;
+    model_system_prompts?: Record<string, string>;
+    model_prices?: Record<string, ModelPrice>;
+    schema_inline_max_bytes?: number;
+    disable_family_mapping?: boolean;
+    force_family_mapping?: boolean;
+    advertise_all_aliases?: boolean;
+    advertise_servable_only?: boolean;
+    model_min_tiers?: Record<string, string>; // 模型 (支持 * 通配) -> FREE/PRO/ULTRA
+    model_canonicalization?: ModelCanonicalizationConfig;
+    auto_model?: AutoModelConfig;
+    deprecated_models?: DeprecatedModel[];
+    auto_replace_deprecated?: boolean;
     enable_logging: boolean;
+    monitor_mode?: 'full' | 'counters_only';
     upstream_proxy: UpstreamProxyConfig;
     zai?: ZaiConfig;
     scheduling?: StickySessionConfig;
+    streaming?: StreamingConfig;
+    context_cache?: ContextCacheConfig;
+    dedupe_in_flight?: boolean;
+    readiness_requires_upstream?: boolean;
+    readiness_threshold?: ReadinessThresholdConfig;
+    scheduling_seed?: number;
+    otlp_endpoint?: string;
+    header_forwarding?: HeaderForwardingConfig;
+    hedge_delay_ms?: number;
+    model_unavailable_action?: ModelUnavailableAction;
+    strip_variant_suffix_on_unavailable?: boolean;
+    default_model_per_protocol?: DefaultModelPerProtocol;
+    allowed_client_models?: string[];
+    truncation_warning_header?: boolean;
+    enabled_endpoints?: EnabledEndpointsConfig;
+    response_compression?: boolean;
+    public_version_endpoint?: boolean;
+    tool_limit?: ToolLimitConfig;
+    remote_media?: RemoteMediaConfig;
+    schema_retry?: SchemaRetryConfig;
+    safety_fallback?: SafetyFallbackConfig;
+    auto_trim_history?: AutoTrimHistoryConfig;
+    logprobs?: LogprobsConfig;
+    thinking_passthrough_prefixes?: string[];
+    family_overrides_builtin?: boolean;
+    max_total_attempts?: number;
+    webhook_url?: string; // 单请求上游调用总数上限，0 = 不限制
+    expose_debug_headers?: boolean;
+    maintenance_mode?: MaintenanceModeConfig;
+    reasoning_output?: ReasoningOutputMode;
+}
+
+export type ReasoningOutputMode = 'passthrough' | 'strip' | 'separate';
+
+export type ModelUnavailableAction = 'fail' | 'rotate_account' | 'next_candidate';
+
+export interface StreamingConfig {
+    keepalive_interval_secs?: number;
+    max_stream_bytes?: number;
+    max_stream_duration_secs?: number;
+    non_streaming_models?: string[];
+    non_streaming_action?: NonStreamingAction;
+}
+
+export type NonStreamingAction = 'emulate' | 'reject';
+
+export interface ModelCanonicalizationConfig {
+    enabled: boolean;
+    strip_prefixes?: string[];
+    strip_suffixes?: string[];
+    strip_at_version?: boolean;
+}
+
+export type ToolPruneStrategy = 'error' | 'truncate_tail' | 'drop_unused';
+
+export interface ReadinessThresholdConfig {
+    min_healthy_accounts?: number;
+    min_healthy_fraction?: number;
+}
+
+export interface ToolLimitConfig {
+    max_tools?: number;
+    strategy?: ToolPruneStrategy;
+}
+
+export interface RemoteMediaConfig {
+    enabled?: boolean; // 默认关闭：URL 原样交给上游
+    allowed_hosts?: string[]; // 精确匹配或 *.example.com，为空时允许任意公网主机
+}
+
+export interface SchemaRetryConfig {
+    enabled?: boolean;
+    error_signatures?: string[]; // 子串匹配，不区分大小写
+}
+
+export interface SafetyFallbackConfig {
+    enabled?: boolean;
+    block_reasons?: string[]; // 默认 SAFETY / RECITATION
+}
+
+export interface AutoTrimHistoryConfig {
+    enabled?: boolean;
+    context_windows?: Record<string, number>; // 上游模型 (支持 * 通配) -> 上下文窗口 token 数
+    keep_recent_messages?: number;
+}
+
+export interface MaintenanceModeConfig {
+    enabled: boolean;
+    message?: string;
+}
+
+export type LogprobsUnsupportedAction = 'strip' | 'error';
+
+export interface LogprobsConfig {
+    supported_models?: string[]; // 支持 * 通配
+    unsupported_action?: LogprobsUnsupportedAction;
+}
+
+export interface AutoModelConfig {
+    aliases?: string[];
+    target?: string; // 模型 ID 或 strategy:<id>
+}
+
+// 客户端省略 model 字段时按协议使用的默认模型
+export interface DefaultModelPerProtocol {
+    openai?: string;
+    anthropic?: string;
+    gemini?: string;
+}
+
+export interface DeprecatedModel {
+    model: string;
+    replacement?: string;
+}
+
+export interface HeaderForwardingConfig {
+    allow: string[];
+    deny: string[];
+}
+
+export interface EnabledEndpointsConfig {
+    openai_chat: boolean;
+    openai_completions: boolean;
+    openai_images: boolean;
+    audio_transcriptions: boolean;
+    anthropic_messages: boolean;
+    gemini: boolean;
+    mcp: boolean;
+}
+
+export interface ContextCacheConfig {
+    enabled: boolean;
+    ttl_secs?: number;
+    min_prefix_bytes?: number;
+    max_prefix_bytes?: number;
+    max_entries?: number;
+    warm?: ContextCacheWarmConfig;
+}
+
+export interface ContextCacheWarmConfig {
+    enabled: boolean;
+    interval_secs?: number;
+    refresh_before_secs?: number;
+    max_idle_secs?: number;
+    models?: string[];
 }
 
 export type ModelPriority = 'accuracy_first' | 'capacity_first';
 export type ModelStickiness = 'strong' | 'weak';
+export type SelectionMode = 'ordered' | 'adaptive_success';
 
 export interface ModelFallbackPolicy {
     model_priority?: ModelPriority;
     stickiness?: ModelStickiness;
     max_model_hops?: number;
+    selection_mode?: SelectionMode;
 }
 
 export interface ModelStrategy {
@@ -35,11 +206,61 @@ export interface ModelStrategy {
     policy?: ModelFallbackPolicy;
 }
 
+export interface StrategyExperiment {
+    arm_a: string;
+    arm_b: string;
+    a_percent: number;
+}
+
+export interface ReasoningEffortBudgets {
+    efforts?: Record<string, number>;
+    default_budget: number;
+}
+
+export interface ModelOutputLimit {
+    default_max_tokens?: number;
+    max_tokens_cap?: number;
+}
+
+export type JsonTransformOp =
+    | { op: 'set'; path: string; value: unknown }
+    | { op: 'remove'; path: string }
+    | { op: 'default'; path: string; value: unknown };
+
+export interface ModelTransform {
+    request?: JsonTransformOp[];
+    response?: JsonTransformOp[];
+}
+
+export interface ModelPrice {
+    input_per_1k?: number;
+    output_per_1k?: number;
+}
+
 export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
 
 export interface StickySessionConfig {
     mode: SchedulingMode;
     max_wait_seconds: number;
+    max_concurrency_per_account?: number;
+    max_rpm_per_account?: number;
+    daily_request_cap_per_account?: number;
+    daily_reset_time?: string;
+    session_ttl_secs?: number;
+    max_sessions?: number;
+    canary_traffic_percent?: number; // 0-100
+    canary_promotion_successes?: number; // 0 = 不自动转正
+    priority_queue?: PriorityQueueConfig;
+}
+
+export type RequestPriority = 'high' | 'normal' | 'low';
+
+export interface PriorityQueueConfig {
+    enabled: boolean;
+    max_queue_len?: number;
+    max_wait_secs?: number;
+    default_priority?: RequestPriority;
+    api_key_priorities?: Record<string, RequestPriority>;
 }
 
 export type ZaiDispatchMode = 'off' | 'exclusive' | 'pooled' | 'fallback';
@@ -97,3 +318,8 @@ export interface AppConfig {
     proxy: ProxyConfig;
 }
 
+export interface OpenAIFamilyRule {
+    pattern: string;
+    mapping_key: string;
+    exclude?: string[]; // 模型名包含任一片段时不匹配
+}